@@ -0,0 +1,67 @@
+//! Measures the parse throughput of the Python files of the directories
+//! (or files) given as arguments, like the standard library:
+//!
+//! ```sh
+//! cargo run --release --example throughput -- /usr/lib/python3.7
+//! ```
+//!
+//! Run it with and without a feature (like `--features smallvec`) to
+//! measure its effect.
+
+extern crate python_parser;
+
+use std::env::args_os;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use python_parser::parse_module;
+
+/// Number of times each file is parsed.
+const ROUNDS: u32 = 5;
+
+/// Pushes the content of the `.py` files of `path` to `files`.
+fn read_files(path: &Path, files: &mut Vec<String>) -> io::Result<()> {
+    if path.is_dir() {
+        for entry in fs::read_dir(path)? {
+            read_files(&entry?.path(), files)?;
+        }
+    } else if path.extension() == Some("py".as_ref()) {
+        // Skip the files which are not UTF-8
+        if let Ok(content) = fs::read_to_string(path) {
+            files.push(content);
+        }
+    }
+    Ok(())
+}
+
+fn seconds(duration: Duration) -> f64 {
+    duration.as_secs() as f64 + f64::from(duration.subsec_nanos()) * 1e-9
+}
+
+fn main() -> io::Result<()> {
+    let mut files = Vec::new();
+    for path in args_os().skip(1) {
+        read_files(Path::new(&path), &mut files)?;
+    }
+    let bytes: usize = files.iter().map(String::len).sum();
+    let mut parsed = 0;
+    let start = Instant::now();
+    for _ in 0..ROUNDS {
+        parsed = files
+            .iter()
+            .filter(|content| parse_module(content).is_ok())
+            .count();
+    }
+    let elapsed = seconds(start.elapsed()) / f64::from(ROUNDS);
+    println!(
+        "{} files ({} parsed), {:.1} MB in {:.3}s: {:.2} MB/s",
+        files.len(),
+        parsed,
+        bytes as f64 / 1e6,
+        elapsed,
+        bytes as f64 / 1e6 / elapsed
+    );
+    Ok(())
+}
@@ -0,0 +1,602 @@
+//! Helpers for annotations written as strings: forward references like
+//! `x: "List[Node]"`, and all the annotations of modules with
+//! `from __future__ import annotations` (as returned by
+//! `typing.get_type_hints`), and a normalizer of the syntax of
+//! annotations for a version of Python.
+
+use std::char;
+use std::collections::BTreeSet;
+use std::mem;
+
+use ast::*;
+use errors::ParseError;
+use parse_expression;
+use span::Span;
+use tokenizer::line_breaks;
+
+/// Parses the content of a string annotation, like `typing.ForwardRef`
+/// does with `compile(content, '<string>', 'eval')`, except whitespace
+/// before the expression is allowed (like the line break and indentation
+/// of multi-line strings).
+///
+/// The offsets and lines of errors are in `content`.
+///
+/// ```
+/// use python_parser::annotations::parse_annotation_string;
+/// use python_parser::ast::*;
+/// assert_eq!(parse_annotation_string(" Node"), Ok(Expression::Name("Node".to_string())));
+/// assert_eq!(parse_annotation_string("List[Node").unwrap_err().offset, 4);
+/// ```
+pub fn parse_annotation_string(content: &str) -> Result<Expression, ParseError> {
+    let indent = content.len() - content.trim_start().len();
+    parse_expression(&content[indent..]).map_err(|e| ParseError {
+        offset: e.offset + indent,
+        line: e.line + line_breaks(&content[..indent]),
+        code: e.code,
+    })
+}
+
+/// Returns the content of a string literal (with its prefix and quotes),
+/// and the offset in the literal of each byte of the content (plus the
+/// end of the content), or the offset of the error if it is not a
+/// string literal, or is a bytes literal or an f-string.
+fn decode_literal(literal: &str) -> Result<(String, Vec<usize>), usize> {
+    let prefix_len = literal.find(&['\'', '"'][..]).ok_or(literal.len())?;
+    let prefix = literal[..prefix_len].to_lowercase();
+    if !["", "r", "u"].contains(&&prefix[..]) {
+        return Err(0);
+    }
+    let quote = &literal[prefix_len..prefix_len + 1];
+    let triple_quote = quote.repeat(3);
+    let quotes =
+        if literal[prefix_len..].starts_with(&triple_quote) && literal.len() >= prefix_len + 6 {
+            &triple_quote[..]
+        } else {
+            quote
+        };
+    let start = prefix_len + quotes.len();
+    if literal.len() < start + quotes.len() || !literal.ends_with(quotes) {
+        return Err(literal.len());
+    }
+    let body = &literal[start..literal.len() - quotes.len()];
+
+    let mut content = String::with_capacity(body.len());
+    let mut offsets = Vec::with_capacity(body.len() + 1);
+    let mut push = |content: &mut String, c: char, offset: usize| {
+        content.push(c);
+        offsets.extend((0..c.len_utf8()).map(|_| start + offset));
+    };
+    let mut chars = body.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            // Line breaks are read as `\n`
+            '\r' => {
+                if let Some(&(_, '\n')) = chars.peek() {
+                    chars.next();
+                }
+                push(&mut content, '\n', i);
+            }
+            '\\' if prefix != "r" => {
+                let (_, escape) = chars.next().ok_or(start + i)?;
+                let mut hex = |len: usize| -> Result<char, usize> {
+                    let digits = body.get(i + 2..i + 2 + len).ok_or(start + i)?;
+                    let code = u32::from_str_radix(digits, 16).map_err(|_| start + i)?;
+                    for _ in 0..len {
+                        chars.next();
+                    }
+                    char::from_u32(code).ok_or(start + i)
+                };
+                let decoded = match escape {
+                    '\n' => continue,
+                    '\r' => {
+                        if let Some(&(_, '\n')) = chars.peek() {
+                            chars.next();
+                        }
+                        continue;
+                    }
+                    '\\' | '\'' | '"' => escape,
+                    'a' => '\x07',
+                    'b' => '\x08',
+                    'f' => '\x0c',
+                    'n' => '\n',
+                    'r' => '\r',
+                    't' => '\t',
+                    'v' => '\x0b',
+                    'x' => hex(2)?,
+                    'u' => hex(4)?,
+                    'U' => hex(8)?,
+                    '0'..='7' => {
+                        let mut code = escape.to_digit(8).unwrap_or(0);
+                        for _ in 0..2 {
+                            match chars.peek().and_then(|&(_, c)| c.to_digit(8)) {
+                                Some(digit) => {
+                                    code = code * 8 + digit;
+                                    chars.next();
+                                }
+                                None => break,
+                            }
+                        }
+                        char::from_u32(code).ok_or(start + i)?
+                    }
+                    // Named escapes (`\N{...}`) are not supported
+                    'N' => return Err(start + i),
+                    _ => {
+                        // Unknown escapes are kept
+                        push(&mut content, '\\', i);
+                        push(&mut content, escape, i + 1);
+                        continue;
+                    }
+                };
+                push(&mut content, decoded, i);
+            }
+            _ => push(&mut content, c, i),
+        }
+    }
+    offsets.push(start + body.len());
+    Ok((content, offsets))
+}
+
+/// Parses a string annotation, given the code and the span of the
+/// string literal of the annotation (eg. from the tokenizer). The
+/// offsets and lines of errors are mapped back to `code`: an error
+/// inside the string is reported at the character of the literal that
+/// produced it, even when escape sequences are used.
+///
+/// The literal must be a single string (not bytes, an f-string, or
+/// several concatenated strings).
+///
+/// ```
+/// use python_parser::annotations::parse_annotation_literal;
+/// use python_parser::span::Span;
+/// let code = "def f(x: 'Dict[str,\\nint'):\n    pass\n";
+/// let error = parse_annotation_literal(code, Span::new(9, 25)).unwrap_err();
+/// assert_eq!((error.offset, error.line), (14, 1));
+/// ```
+pub fn parse_annotation_literal(code: &str, span: Span) -> Result<Expression, ParseError> {
+    let error_at = |offset: usize| ParseError {
+        offset,
+        line: 1 + line_breaks(code.get(..offset).unwrap_or(code)),
+        code: None,
+    };
+    let literal = code
+        .get(span.start..span.end)
+        .ok_or_else(|| error_at(span.start))?;
+    let (content, offsets) =
+        decode_literal(literal).map_err(|offset| error_at(span.start + offset))?;
+    parse_annotation_string(&content).map_err(|e| {
+        let offset = offsets[e.offset.min(content.len())];
+        ParseError {
+            code: e.code,
+            ..error_at(span.start + offset)
+        }
+    })
+}
+
+/// Generic aliases of `typing`, and the builtins replacing them since
+/// Python 3.9 (PEP 585).
+const BUILTIN_GENERICS: [(&str, &str); 6] = [
+    ("List", "list"),
+    ("Dict", "dict"),
+    ("Set", "set"),
+    ("FrozenSet", "frozenset"),
+    ("Tuple", "tuple"),
+    ("Type", "type"),
+];
+
+/// Returns the name of `Name` or `typing.Name`.
+fn typing_name(e: &Expression) -> Option<&str> {
+    match e {
+        Expression::Name(name) => Some(name),
+        Expression::Attribute(module, name) => match **module {
+            Expression::Name(ref module) if module == "typing" || module == "typing_extensions" => {
+                Some(name)
+            }
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Returns the members of `A | B | C`.
+fn union_members(e: Expression, members: &mut Vec<Expression>) {
+    match e {
+        Expression::Bop(Bop::BitOr, left, right) => {
+            union_members(*left, members);
+            union_members(*right, members);
+        }
+        Expression::MultiBop(first, rest) if rest.iter().all(|&(op, _)| op == Bop::BitOr) => {
+            union_members(*first, members);
+            for (_, e) in rest {
+                union_members(e, members);
+            }
+        }
+        e => members.push(e),
+    }
+}
+
+fn is_union(e: &Expression) -> bool {
+    match e {
+        Expression::Bop(Bop::BitOr, _, _) => true,
+        Expression::MultiBop(_, rest) => rest.iter().all(|&(op, _)| op == Bop::BitOr),
+        _ => false,
+    }
+}
+
+/// Returns `A | B | C`, like the parser does.
+fn make_union(mut members: Vec<Expression>) -> Expression {
+    match members.len() {
+        0 => Expression::None,
+        1 => members.remove(0),
+        2 => {
+            let right = members.remove(1);
+            Expression::Bop(Bop::BitOr, Box::new(members.remove(0)), Box::new(right))
+        }
+        _ => {
+            let first = members.remove(0);
+            let rest = members.into_iter().map(|e| (Bop::BitOr, e)).collect();
+            Expression::MultiBop(Box::new(first), rest)
+        }
+    }
+}
+
+fn subscript(name: &str, items: Vec<Expression>) -> Expression {
+    Expression::Subscript(
+        Box::new(Expression::Name(name.to_string())),
+        items.into_iter().map(Subscript::Simple).collect(),
+    )
+}
+
+struct AnnotationNormalizer {
+    target: (u8, u8),
+    /// Names that must be imported from `typing`.
+    imports: BTreeSet<&'static str>,
+}
+
+impl AnnotationNormalizer {
+    fn statements(&mut self, stmts: &mut [Statement]) {
+        for stmt in stmts {
+            match *stmt {
+                Statement::TypeAnnotation(_, ref mut annotation)
+                | Statement::TypedAssignment(_, ref mut annotation, _) => {
+                    self.expression(annotation)
+                }
+                Statement::Compound(ref mut stmt) => self.compound_statement(stmt),
+                _ => (),
+            }
+        }
+    }
+
+    fn compound_statement(&mut self, stmt: &mut CompoundStatement) {
+        match *stmt {
+            CompoundStatement::If(ref mut cond_blocks, ref mut else_block) => {
+                for &mut (_, ref mut block) in cond_blocks {
+                    self.statements(block);
+                }
+                if let Some(ref mut block) = *else_block {
+                    self.statements(block);
+                }
+            }
+            CompoundStatement::For {
+                ref mut for_block,
+                ref mut else_block,
+                ..
+            }
+            | CompoundStatement::While(_, ref mut for_block, ref mut else_block) => {
+                self.statements(for_block);
+                if let Some(ref mut block) = *else_block {
+                    self.statements(block);
+                }
+            }
+            CompoundStatement::With(_, ref mut block) => self.statements(block),
+            CompoundStatement::Funcdef(ref mut funcdef) => {
+                let params = &mut funcdef.parameters;
+                let mut annotations: Vec<_> = params
+                    .posonly_args
+                    .iter_mut()
+                    .chain(params.args.iter_mut())
+                    .chain(params.keyword_args.iter_mut())
+                    .filter_map(|param| param.1.as_mut())
+                    .collect();
+                if let StarParams::Named((_, Some(ref mut annotation))) = params.star_args {
+                    annotations.push(annotation);
+                }
+                if let Some((_, Some(ref mut annotation))) = params.star_kwargs {
+                    annotations.push(annotation);
+                }
+                annotations.extend(funcdef.return_type.as_mut());
+                for annotation in annotations {
+                    self.expression(annotation);
+                }
+                self.statements(&mut funcdef.code);
+            }
+            CompoundStatement::Classdef(ref mut classdef) => self.statements(&mut classdef.code),
+            CompoundStatement::Try(ref mut try_) => {
+                self.statements(&mut try_.try_block);
+                for &mut (_, _, ref mut block) in &mut try_.except_clauses {
+                    self.statements(block);
+                }
+                self.statements(&mut try_.last_except);
+                self.statements(&mut try_.else_block);
+                self.statements(&mut try_.finally_block);
+            }
+        }
+    }
+
+    fn expression(&mut self, e: &mut Expression) {
+        if is_union(e) {
+            let mut members = Vec::new();
+            union_members(mem::replace(e, Expression::None), &mut members);
+            for member in &mut members {
+                self.expression(member);
+            }
+            *e = self.union(members);
+            return;
+        }
+        // Normalizes the types in the expression first
+        match *e {
+            Expression::Subscript(ref value, ref mut subs) => {
+                let types = match typing_name(value) {
+                    Some("Literal") => 0,
+                    Some("Annotated") => 1,
+                    _ => subs.len(),
+                };
+                for sub in subs.iter_mut().take(types) {
+                    if let Subscript::Simple(ref mut e) = *sub {
+                        self.expression(e);
+                    }
+                }
+            }
+            // Like the parameters of `Callable[[int, str], bool]`
+            Expression::ListLiteral(ref mut items) | Expression::TupleLiteral(ref mut items) => {
+                for item in items {
+                    if let SetItem::Unique(ref mut e) = *item {
+                        self.expression(e);
+                    }
+                }
+            }
+            _ => return,
+        }
+
+        *e = match mem::replace(e, Expression::None) {
+            Expression::Subscript(value, subs) => self.subscript(*value, subs),
+            other => other,
+        };
+    }
+
+    /// Returns the union of `members`, with the syntax of the target.
+    fn union(&mut self, mut members: Vec<Expression>) -> Expression {
+        if self.target >= (3, 10) {
+            make_union(members)
+        } else if members.len() == 2 && members.contains(&Expression::None) {
+            self.imports.insert("Optional");
+            members.retain(|m| *m != Expression::None);
+            subscript("Optional", members)
+        } else {
+            self.imports.insert("Union");
+            subscript("Union", members)
+        }
+    }
+
+    fn subscript(&mut self, value: Expression, subs: Vec<Subscript>) -> Expression {
+        let name = typing_name(&value).map(str::to_string);
+        let is_simple = |sub: &Subscript| match sub {
+            // String forward references can't be used with `|`
+            Subscript::Simple(Expression::String(_)) => false,
+            Subscript::Simple(_) => true,
+            _ => false,
+        };
+        match name.as_deref() {
+            Some("Optional") | Some("Union")
+                if self.target >= (3, 10) && !subs.is_empty() && subs.iter().all(is_simple) =>
+            {
+                let optional = name.as_deref() == Some("Optional");
+                let mut members = Vec::new();
+                for sub in subs.into_iter().take(if optional { 1 } else { usize::MAX }) {
+                    if let Subscript::Simple(e) = sub {
+                        union_members(e, &mut members);
+                    }
+                }
+                if optional {
+                    members.push(Expression::None);
+                }
+                make_union(members)
+            }
+            Some(name) if self.target >= (3, 9) => {
+                match BUILTIN_GENERICS.iter().find(|&&(alias, _)| alias == name) {
+                    Some(&(_, builtin)) => {
+                        Expression::Subscript(Box::new(Expression::Name(builtin.to_string())), subs)
+                    }
+                    None => Expression::Subscript(Box::new(value), subs),
+                }
+            }
+            _ if self.target < (3, 9) => {
+                let alias = match value {
+                    Expression::Name(ref name) => BUILTIN_GENERICS
+                        .iter()
+                        .find(|&&(_, builtin)| builtin == name)
+                        .map(|&(alias, _)| alias),
+                    _ => None,
+                };
+                match alias {
+                    Some(alias) => {
+                        self.imports.insert(alias);
+                        Expression::Subscript(Box::new(Expression::Name(alias.to_string())), subs)
+                    }
+                    None => Expression::Subscript(Box::new(value), subs),
+                }
+            }
+            _ => Expression::Subscript(Box::new(value), subs),
+        }
+    }
+}
+
+/// Rewrites an annotation to the syntax of the `target` version of
+/// Python, like pyupgrade does: since Python 3.10, `Optional[X]`
+/// becomes `X | None` and `Union[A, B]` becomes `A | B` (PEP 604), and
+/// since Python 3.9, `List[int]` becomes `list[int]` (PEP 585, likewise
+/// for `Dict`, `Set`, `FrozenSet`, `Tuple` and `Type`). For older
+/// versions, the opposite rewrites are done.
+///
+/// Returns the names that must be imported from `typing` for the
+/// rewritten annotation, when it uses aliases that it did not use.
+/// Annotations written as strings are not changed.
+///
+pub fn normalize_annotation(annotation: &mut Expression, target: (u8, u8)) -> Vec<&'static str> {
+    let mut normalizer = AnnotationNormalizer {
+        target,
+        imports: BTreeSet::new(),
+    };
+    normalizer.expression(annotation);
+    normalizer.imports.into_iter().collect()
+}
+
+/// Rewrites all the annotations of variables and functions in `stmts`
+/// (see `normalize_annotation`), and returns the names that must be
+/// imported from `typing`.
+///
+/// ```
+/// use python_parser::annotations::normalize_annotations;
+/// use python_parser::parse_module;
+///
+/// let old = parse_module("x: Optional[Dict[str, Union[int, str]]]\n").unwrap().body;
+/// let new = parse_module("x: dict[str, int | str] | None\n").unwrap().body;
+/// let mut body = old.clone();
+/// assert_eq!(normalize_annotations(&mut body, (3, 10)), Vec::<&str>::new());
+/// assert_eq!(body, new);
+/// assert_eq!(normalize_annotations(&mut body, (3, 8)), ["Dict", "Optional", "Union"]);
+/// assert_eq!(body, old);
+/// ```
+pub fn normalize_annotations(stmts: &mut [Statement], target: (u8, u8)) -> Vec<&'static str> {
+    let mut normalizer = AnnotationNormalizer {
+        target,
+        imports: BTreeSet::new(),
+    };
+    normalizer.statements(stmts);
+    normalizer.imports.into_iter().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use errors::PyParseError;
+
+    #[test]
+    fn test_parse_annotation_string() {
+        let expected = Expression::Subscript(
+            Box::new(Expression::Name("List".to_string())),
+            vec![Subscript::Simple(Expression::Name("int".to_string()))],
+        );
+        assert_eq!(parse_annotation_string("List[int]"), Ok(expected.clone()));
+        assert_eq!(parse_annotation_string("\tList[\n  int\n]\n"), Ok(expected));
+        let error = parse_annotation_string(" int )").unwrap_err();
+        assert_eq!((error.offset, error.line), (5, 1));
+        assert!(parse_annotation_string("").is_err());
+        assert!(parse_annotation_string("x = 1").is_err());
+    }
+
+    #[test]
+    fn test_decode_literal() {
+        let decoded = |literal| decode_literal(literal).map(|(content, _)| content);
+        assert_eq!(decoded("'int'"), Ok("int".to_string()));
+        assert_eq!(decoded("U'\\x41\\u00e9\\101\\q'"), Ok("AéA\\q".to_string()));
+        assert_eq!(decoded("r'\\x41'"), Ok("\\x41".to_string()));
+        assert_eq!(decoded("'''a\r\n\\\nb'''"), Ok("a\nb".to_string()));
+        assert_eq!(decoded("''"), Ok(String::new()));
+        assert_eq!(decoded("b'int'"), Err(0));
+        assert_eq!(decoded("f'int'"), Err(0));
+        assert_eq!(decoded("'\\N{DASH}'"), Err(1));
+        let (_, offsets) = decode_literal("'\\x41é'").unwrap();
+        assert_eq!(offsets, [1, 5, 5, 7]);
+    }
+
+    #[test]
+    fn test_parse_annotation_literal() {
+        let code = "x: 'List[\\x69nt]'\ny: '''\n  Dict[str, (int]'''\n";
+        assert!(parse_annotation_literal(code, Span::new(3, 17)).is_ok());
+        let error = parse_annotation_literal(code, Span::new(21, 45)).unwrap_err();
+        assert_eq!(
+            (error.offset, error.line, error.code),
+            (41, 3, Some(PyParseError::MismatchedBracket))
+        );
+        let error = parse_annotation_literal(code, Span::new(3, 100)).unwrap_err();
+        assert_eq!((error.offset, error.line), (3, 1));
+        let error = parse_annotation_literal("x: b'int'", Span::new(3, 9)).unwrap_err();
+        assert_eq!((error.offset, error.line), (3, 1));
+    }
+
+    #[test]
+    fn test_normalize_annotation() {
+        let check = |annotation, target, expected, imports: &[&str]| {
+            let mut e = parse_expression(annotation).unwrap();
+            let expected = parse_expression(expected).unwrap();
+            assert_eq!(normalize_annotation(&mut e, target), imports);
+            assert_eq!(e, expected);
+        };
+        check(
+            "typing.Optional[List[int]]",
+            (3, 10),
+            "list[int] | None",
+            &[],
+        );
+        check("Optional[List[int]]", (3, 9), "Optional[list[int]]", &[]);
+        check("Union['A', None]", (3, 10), "Union['A', None]", &[]);
+        check("Literal[Optional]", (3, 10), "Literal[Optional]", &[]);
+        check(
+            "Annotated[Set[int], Union]",
+            (3, 9),
+            "Annotated[set[int], Union]",
+            &[],
+        );
+        check(
+            "Callable[[Type[A]], Tuple[()]]",
+            (3, 9),
+            "Callable[[type[A]], tuple[()]]",
+            &[],
+        );
+        check("a.List[int]", (3, 9), "a.List[int]", &[]);
+        check(
+            "int | str | None",
+            (3, 9),
+            "Union[int, str, None]",
+            &["Union"],
+        );
+        check(
+            "frozenset[int | None]",
+            (3, 8),
+            "FrozenSet[Optional[int]]",
+            &["FrozenSet", "Optional"],
+        );
+        check("list", (3, 8), "list", &[]);
+        check(
+            "Optional[Union[A, B | C]]",
+            (3, 10),
+            "A | B | C | None",
+            &[],
+        );
+        check("(A | B) | C", (3, 8), "Union[A, B, C]", &["Union"]);
+    }
+
+    #[test]
+    fn test_normalize_annotations() {
+        let code = "def f(x: List[int], *args: Set[str], **kwargs: Type[A]) -> Dict[str, int]:
+    y: Tuple[int] = ()
+    class C:
+        z: FrozenSet[int]
+    lambda: List[int]
+";
+        let mut module = ::parse_module(code).unwrap();
+        assert_eq!(
+            normalize_annotations(&mut module.body, (3, 9)),
+            Vec::<&str>::new()
+        );
+        let expected = ::parse_module(
+            "def f(x: list[int], *args: set[str], **kwargs: type[A]) -> dict[str, int]:
+    y: tuple[int] = ()
+    class C:
+        z: frozenset[int]
+    lambda: List[int]
+",
+        );
+        assert_eq!(module.body, expected.unwrap().body);
+    }
+}
@@ -5,6 +5,8 @@ use std::fmt;
 #[cfg(feature = "bigint")]
 use num_bigint::BigUint;
 
+#[cfg(feature = "smallvec")]
+use smallvec;
 #[cfg(feature = "wtf8")]
 use wtf8;
 
@@ -25,6 +27,14 @@ pub type PyStringCodePoint = char;
 
 pub type Name = String;
 
+/// A list which almost always has a single item, like the targets of
+/// an assignment. With the `smallvec` feature, a single item is stored
+/// without an allocation.
+#[cfg(feature = "smallvec")]
+pub type SmallList<T> = smallvec::SmallVec<[T; 1]>;
+#[cfg(not(feature = "smallvec"))]
+pub type SmallList<T> = Vec<T>;
+
 /// Represents whether a function signature has `*`, `*args`, or none of these.
 #[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub enum StarParams<T> {
@@ -65,11 +75,12 @@ pub struct UntypedArgsList {
 /// A function or class decorator.
 #[derive(Clone, Debug, PartialEq)]
 pub struct Decorator {
-    pub name: Vec<Name>,
+    pub name: SmallList<Name>,
     pub args: Option<Vec<Argument>>,
 }
 
-/// An argument to a function call
+/// An argument to a function call or class definition, which keeps
+/// its position among the other arguments
 #[derive(Clone, Debug, PartialEq)]
 pub enum Argument {
     Positional(Expression),
@@ -193,7 +204,7 @@ pub enum ComprehensionChunk {
     },
     For {
         async: bool,
-        item: Vec<Expression>,
+        item: SmallList<Expression>,
         iterator: Expression,
     },
 }
@@ -217,7 +228,11 @@ pub enum SetItem {
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct PyString {
     pub prefix: String,
+    /// The value of the string, empty with `StringDecoding::Raw`.
     pub content: PyStringContent,
+    /// The literal as written in the code, with its prefix and quotes,
+    /// with `StringDecoding::Raw` and `StringDecoding::Both`.
+    pub raw: Option<String>,
 }
 
 /// The big thing: a Python expression.
@@ -244,6 +259,8 @@ pub enum Expression {
     Generator(Box<SetItem>, Vec<ComprehensionChunk>),
     Await(Box<Expression>),
 
+    /// A call, with its arguments in the order of the code (which
+    /// matters for evaluation, eg. of `f(*a, key=b(), *c)`).
     Call(Box<Expression>, Vec<Argument>),
     Subscript(Box<Expression>, Vec<Subscript>),
     /// `foo.bar`
@@ -350,7 +367,7 @@ pub enum Statement {
     Import(Import),
     Expressions(Vec<Expression>),
     // `lhs = rhs1 = rhs2` -> `lhs, vec![rhs1, rhs2]`
-    Assignment(Vec<Expression>, Vec<Vec<Expression>>),
+    Assignment(SmallList<Expression>, Vec<SmallList<Expression>>),
     // `lhs: type` -> `lhs, type`
     TypeAnnotation(Vec<Expression>, Expression),
     // `lhs: type = rhs` -> `lhs, type, rhs`
@@ -359,6 +376,15 @@ pub enum Statement {
     AugmentedAssignment(Vec<Expression>, AugAssignOp, Vec<Expression>),
 
     Compound(Box<CompoundStatement>),
+
+    // Code of a statement that could not be parsed, without its
+    // indentation (see `tolerant::parse_module_tolerant`).
+    Invalid(String),
+
+    // A top-level line of a grammar extension: the name of the
+    // extension and the code of the line (see
+    // `extensions::parse_module_with_extensions`).
+    Extension(String, String),
 }
 
 /// A function definition, including its decorators.
@@ -372,6 +398,18 @@ pub struct Funcdef {
     pub code: Vec<Statement>,
 }
 
+/// A function signature in a type comment, like
+/// `# type: (int, *str) -> bool`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FuncType {
+    pub args: Vec<Expression>,
+    /// Type of `*args`
+    pub star_args: Option<Expression>,
+    /// Type of `**kwargs`
+    pub star_kwargs: Option<Expression>,
+    pub return_type: Expression,
+}
+
 /// A class definition, including its decorators.
 #[derive(Clone, Debug, PartialEq)]
 pub struct Classdef {
@@ -401,7 +439,7 @@ pub enum CompoundStatement {
     If(Vec<(Expression, Vec<Statement>)>, Option<Vec<Statement>>),
     For {
         async: bool,
-        item: Vec<Expression>,
+        item: SmallList<Expression>,
         iterator: Vec<Expression>,
         for_block: Vec<Statement>,
         else_block: Option<Vec<Statement>>,
@@ -412,3 +450,19 @@ pub enum CompoundStatement {
     Classdef(Classdef),
     Try(Try),
 }
+
+/// A whole file, with the information about it that is not part of its
+/// statements.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Module {
+    /// The first line, if it starts with `#!` (without the line break).
+    pub shebang: Option<String>,
+    /// The encoding declared by a `# -*- coding: ... -*-` comment on
+    /// one of the first two lines.
+    pub encoding: Option<String>,
+    /// The comments before the first statement, other than the shebang.
+    pub leading_comments: Vec<String>,
+    /// Whether the file ends with a line break.
+    pub trailing_newline: bool,
+    pub body: Vec<Statement>,
+}
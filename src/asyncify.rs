@@ -0,0 +1,497 @@
+//! Conversion of functions to coroutines (`async def`), for migrations
+//! to `asyncio`.
+//!
+//! The calls of the converted functions in the module are awaited, and
+//! the functions making them are converted too. In the converted
+//! functions, `with` and `for` statements on known asynchronous context
+//! managers and iterators become `async with` and `async for`.
+
+use std::fmt;
+
+use definition::{closing, header_end, split_top_level, top_level_keyword, Bindings};
+use lint::{dotted_name, is_name, is_op, LintContext};
+use span::{Span, Spanned};
+use tokenizer::{tokenize, Token, TokenKind};
+
+/// Qualified names (like `asyncio.Lock`) of the functions and classes
+/// whose results are asynchronous context managers or iterators.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct AsyncApi {
+    pub context_managers: Vec<String>,
+    pub iterators: Vec<String>,
+}
+
+/// The context managers of `asyncio`, `aiohttp` and `aiofiles`.
+impl Default for AsyncApi {
+    fn default() -> AsyncApi {
+        let names = |names: &[&str]| names.iter().map(|name| name.to_string()).collect();
+        AsyncApi {
+            context_managers: names(&[
+                "asyncio.Lock",
+                "asyncio.Semaphore",
+                "asyncio.BoundedSemaphore",
+                "asyncio.Condition",
+                "asyncio.timeout",
+                "aiohttp.ClientSession",
+                "aiofiles.open",
+            ]),
+            iterators: Vec::new(),
+        }
+    }
+}
+
+/// Error returned by `asyncify`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum AsyncifyError {
+    /// No function of the module has this qualified name.
+    NotFound(String),
+}
+
+impl fmt::Display for AsyncifyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            AsyncifyError::NotFound(ref name) => write!(f, "no function named {:?}", name),
+        }
+    }
+}
+
+impl ::std::error::Error for AsyncifyError {}
+
+/// The result of `asyncify`.
+#[derive(Clone, Debug, PartialEq, Eq, Default)]
+pub struct Conversion {
+    pub edits: Vec<(Span, String)>,
+    /// Qualified names of the functions converted to coroutines: the
+    /// selected ones and the ones calling them, in the order of the
+    /// code.
+    pub functions: Vec<String>,
+    /// Calls of the converted functions which cannot be awaited: in the
+    /// module or a class body, in a lambda or generator expression, or
+    /// in a generator.
+    pub unawaited: Vec<Span>,
+}
+
+/// A function defined in the module.
+struct Function {
+    /// Qualified name, like `A.f` for a method.
+    name: String,
+    /// Offset of `def`, or of `async` if it is already a coroutine.
+    keyword: usize,
+    is_async: bool,
+    is_generator: bool,
+    /// The name in the `def`.
+    span: Span,
+    /// The name of its class if it is a method.
+    class: Option<Span>,
+}
+
+/// A compound statement whose body is indented.
+struct Block {
+    depth: usize,
+    name: String,
+    function: Option<usize>,
+    /// The name of the class if it is a class.
+    class: Option<Span>,
+}
+
+/// Returns the logical lines of `code`, without comments and line
+/// breaks, with the index of the innermost function containing each,
+/// and the functions of the code.
+fn functions<'a>(code: &'a str) -> (Vec<(Vec<Token<'a>>, Option<usize>)>, Vec<Function>) {
+    let mut lines = Vec::new();
+    let mut functions: Vec<Function> = Vec::new();
+    let mut blocks: Vec<Block> = Vec::new();
+    let mut depth = 0;
+    let mut line = Vec::new();
+    for token in tokenize(code) {
+        match token.kind {
+            TokenKind::Indent => depth += 1,
+            TokenKind::Dedent => depth -= 1,
+            TokenKind::Comment | TokenKind::Nl | TokenKind::EndMarker => (),
+            TokenKind::Newline => {
+                while blocks.last().map_or(false, |block| block.depth >= depth) {
+                    blocks.pop();
+                }
+                let enclosing = blocks
+                    .iter()
+                    .rev()
+                    .filter_map(|block| block.function)
+                    .next();
+                let is_async = is_name(line.first(), "async");
+                let header = if is_async { &line[1..] } else { &line[..] };
+                let keyword = header.first().map_or("", |t: &Token| t.text);
+                let name = match header.get(1) {
+                    Some(name) if name.kind == TokenKind::Name => Some(name),
+                    _ => None,
+                };
+                let colon = header_end(header).unwrap_or(header.len());
+                let body = header.get(colon + 1..).unwrap_or(&[]);
+                let mut block = None;
+                match (keyword, name) {
+                    ("def", Some(name)) => {
+                        let parent = blocks.last();
+                        let mut qualified: Vec<_> = blocks.iter().map(|b| &b.name[..]).collect();
+                        qualified.push(name.text);
+                        functions.push(Function {
+                            name: qualified.join("."),
+                            keyword: line[0].offset,
+                            is_async,
+                            is_generator: body.iter().any(|t| is_name(Some(t), "yield")),
+                            span: name.span(),
+                            class: parent.and_then(|b| b.class),
+                        });
+                        block = Some(Block {
+                            depth,
+                            name: name.text.to_string(),
+                            function: Some(functions.len() - 1),
+                            class: None,
+                        });
+                    }
+                    ("class", Some(name)) => {
+                        block = Some(Block {
+                            depth,
+                            name: name.text.to_string(),
+                            function: None,
+                            class: Some(name.span()),
+                        });
+                    }
+                    _ => {
+                        if let Some(function) = enclosing {
+                            if line.iter().any(|t| is_name(Some(t), "yield")) {
+                                functions[function].is_generator = true;
+                            }
+                        }
+                    }
+                }
+                if let Some(block) = block.filter(|_| body.is_empty()) {
+                    blocks.push(block);
+                }
+                lines.push((::std::mem::replace(&mut line, Vec::new()), enclosing));
+            }
+            _ => line.push(token),
+        }
+    }
+    (lines, functions)
+}
+
+/// A call of a converted function.
+struct Call {
+    /// From the function (or `self`) to the closing parenthesis.
+    span: Span,
+    /// Whether `await` must be parenthesized, as the call is followed by
+    /// a trailer (like `.x`).
+    parenthesized: bool,
+    in_generator: bool,
+}
+
+/// Whether `line[i]` is in a generator expression.
+fn in_generator(line: &[Token], i: usize) -> bool {
+    let mut depth = 0;
+    for j in (0..i).rev() {
+        match line[j].text {
+            ")" | "]" | "}" if line[j].kind == TokenKind::Op => depth += 1,
+            "(" | "[" | "{" if line[j].kind == TokenKind::Op => {
+                if depth > 0 {
+                    depth -= 1;
+                } else if line[j].text == "("
+                    && top_level_keyword(&line[j + 1..closing(line, j)], "for").is_some()
+                {
+                    return true;
+                }
+            }
+            _ => (),
+        }
+    }
+    false
+}
+
+/// Returns the calls of `function` which are not awaited yet: `f(...)`,
+/// and `self.f(...)`, `cls.f(...)` or `A.f(...)` for a method of `A`.
+fn calls(
+    lines: &[(Vec<Token>, Option<usize>)],
+    bindings: &Bindings,
+    function: &Function,
+) -> Vec<Call> {
+    let name = function.name.rsplit('.').next().unwrap_or("");
+    let mut calls = Vec::new();
+    for &(ref line, _) in lines {
+        for (i, token) in line.iter().enumerate() {
+            if token.text != name || token.span() == function.span || !is_op(line.get(i + 1), "(") {
+                continue;
+            }
+            let start = if i > 0 && is_op(line.get(i - 1), ".") {
+                let object = match line.get(i.wrapping_sub(2)) {
+                    Some(object) if i >= 2 && object.kind == TokenKind::Name => object,
+                    _ => continue,
+                };
+                let is_class = function.class.map_or(false, |class| {
+                    object.text == "self"
+                        || object.text == "cls"
+                        || bindings.resolve(object.offset).map(|b| b.span) == Some(class)
+                });
+                if !is_class || (i >= 3 && is_op(line.get(i - 3), ".")) {
+                    continue;
+                }
+                i - 2
+            } else if bindings.resolve(token.offset).map(|b| b.span) == Some(function.span) {
+                i
+            } else {
+                continue;
+            };
+            if start > 0 && is_name(line.get(start - 1), "await") {
+                continue;
+            }
+            let close = closing(line, i + 1).min(line.len() - 1);
+            let next = line.get(close + 1);
+            calls.push(Call {
+                span: line[start].span().cover(line[close].span()),
+                parenthesized: is_op(next, ".") || is_op(next, "(") || is_op(next, "["),
+                in_generator: in_generator(line, start),
+            });
+        }
+    }
+    calls
+}
+
+/// Whether the expression `tokens` is a dotted name or a call of a
+/// dotted name, whose qualified name is in `names`.
+fn is_known(context: &LintContext, tokens: &[Token], names: &[String]) -> bool {
+    let name = dotted_name(tokens, 0);
+    let is_call =
+        is_op(tokens.get(name.len()), "(") && closing(tokens, name.len()) + 1 == tokens.len();
+    !name.is_empty()
+        && (name.len() == tokens.len() || is_call)
+        && names.contains(&context.qualified_name(name))
+}
+
+/// Converts the functions named `selected` (like `f`, or `A.f` for a
+/// method of `A`) to coroutines, and awaits their calls in the module.
+///
+/// ```
+/// use python_parser::asyncify::{asyncify, AsyncApi};
+/// use python_parser::span::replace_spans;
+///
+/// let code = "import asyncio\n\ndef fetch(url):\n    with asyncio.Lock():\n        return get(url)\n\n\
+///             def main():\n    return fetch('a').text\n";
+/// let conversion = asyncify(code, &["fetch"], &AsyncApi::default()).unwrap();
+/// assert_eq!(conversion.functions, vec!["fetch", "main"]);
+/// let edits: Vec<_> = conversion.edits.iter().map(|&(span, ref text)| (span, &text[..])).collect();
+/// assert_eq!(
+///     replace_spans(code, &edits).unwrap(),
+///     "import asyncio\n\nasync def fetch(url):\n    async with asyncio.Lock():\n        return get(url)\n\n\
+///      async def main():\n    return (await fetch('a')).text\n"
+/// );
+/// ```
+pub fn asyncify(
+    code: &str,
+    selected: &[&str],
+    api: &AsyncApi,
+) -> Result<Conversion, AsyncifyError> {
+    let (lines, functions) = functions(code);
+    let bindings = Bindings::new(code);
+    let mut queue = Vec::new();
+    for &name in selected {
+        match functions.iter().position(|function| function.name == name) {
+            Some(function) => queue.push(function),
+            None => return Err(AsyncifyError::NotFound(name.to_string())),
+        }
+    }
+    let mut conversion = Conversion::default();
+    let mut converted = vec![false; functions.len()];
+    while let Some(function) = queue.pop() {
+        if converted[function] {
+            continue;
+        }
+        converted[function] = true;
+        for call in calls(&lines, &bindings, &functions[function]) {
+            let caller = bindings
+                .function_at(call.span.start)
+                .and_then(|b| functions.iter().position(|f| f.span == b.span));
+            let caller = match caller {
+                Some(caller)
+                    if !call.in_generator
+                        && !functions[caller].is_generator
+                        && !functions[function].is_generator =>
+                {
+                    caller
+                }
+                _ => {
+                    conversion.unawaited.push(call.span);
+                    continue;
+                }
+            };
+            if call.parenthesized {
+                conversion
+                    .edits
+                    .push((Span::at(call.span.start), "(await ".to_string()));
+                conversion
+                    .edits
+                    .push((Span::at(call.span.end), ")".to_string()));
+            } else {
+                conversion
+                    .edits
+                    .push((Span::at(call.span.start), "await ".to_string()));
+            }
+            queue.push(caller);
+        }
+    }
+    for (function, _) in functions.iter().zip(&converted).filter(|&(_, &c)| c) {
+        if !function.is_async {
+            conversion
+                .edits
+                .push((Span::at(function.keyword), "async ".to_string()));
+        }
+        conversion.functions.push(function.name.clone());
+    }
+
+    let context = LintContext::new(code);
+    for &(ref line, function) in &lines {
+        if !function.map_or(false, |function| converted[function]) {
+            continue;
+        }
+        let header = &line[..header_end(line).unwrap_or(line.len())];
+        let is_async = match header.first().map(|t| t.text) {
+            Some("with") => split_top_level(&header[1..], ",").iter().all(|item| {
+                let as_ = top_level_keyword(item, "as").unwrap_or(item.len());
+                is_known(&context, &item[..as_], &api.context_managers)
+            }),
+            Some("for") => match top_level_keyword(header, "in") {
+                Some(in_) => is_known(&context, &header[in_ + 1..], &api.iterators),
+                None => false,
+            },
+            _ => false,
+        };
+        if is_async {
+            conversion
+                .edits
+                .push((Span::at(line[0].offset), "async ".to_string()));
+        }
+    }
+    conversion.edits.sort_by_key(|&(span, _)| span.start);
+    conversion.unawaited.sort_by_key(|span| span.start);
+    Ok(conversion)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use span::replace_spans;
+
+    /// Returns the converted code, and the code of the calls which
+    /// could not be awaited.
+    fn converted<'a>(code: &'a str, selected: &[&str], api: &AsyncApi) -> (String, Vec<&'a str>) {
+        let conversion = asyncify(code, selected, api).unwrap();
+        let edits: Vec<_> = conversion
+            .edits
+            .iter()
+            .map(|&(span, ref text)| (span, &text[..]))
+            .collect();
+        let unawaited = conversion
+            .unawaited
+            .iter()
+            .map(|s| &code[s.start..s.end])
+            .collect();
+        (replace_spans(code, &edits).unwrap(), unawaited)
+    }
+
+    #[test]
+    fn test_asyncify() {
+        let api = AsyncApi::default();
+        let code = "class Client:
+    def get(self, url):
+        return self.session.get(url)
+
+    def get_all(self, urls):
+        return [self.get(url) for url in urls]
+
+    @classmethod
+    def check(cls):
+        x = -cls.get(None)['status']
+        return Client.get(None), (x.get(y) for y in x)
+
+def get(url):
+    pass
+
+def main(client):
+    return client.get('a'), get('b'), lambda: Client.get(None)
+
+def lines(client):
+    yield client.get('a') + Client.get(None)
+
+Client.get(None)
+";
+        let expected = "class Client:
+    async def get(self, url):
+        return self.session.get(url)
+
+    async def get_all(self, urls):
+        return [await self.get(url) for url in urls]
+
+    @classmethod
+    async def check(cls):
+        x = -(await cls.get(None))['status']
+        return await Client.get(None), (x.get(y) for y in x)
+
+def get(url):
+    pass
+
+def main(client):
+    return client.get('a'), get('b'), lambda: Client.get(None)
+
+def lines(client):
+    yield client.get('a') + Client.get(None)
+
+Client.get(None)
+";
+        assert_eq!(
+            converted(code, &["Client.get"], &api),
+            (
+                expected.to_string(),
+                vec!["Client.get(None)", "Client.get(None)", "Client.get(None)"]
+            )
+        );
+        assert_eq!(
+            asyncify(code, &["Client.post"], &api),
+            Err(AsyncifyError::NotFound("Client.post".to_string()))
+        );
+
+        // Generators, generator expressions and already awaited calls
+        let code = "def f():\n    pass\n\ndef g():\n    yield f()\n\ndef h():\n    \
+                    return sum(f() for x in y), await f()\n";
+        assert_eq!(
+            converted(code, &["f", "g"], &api),
+            (
+                "async def f():\n    pass\n\nasync def g():\n    yield f()\n\ndef h():\n    \
+                 return sum(f() for x in y), await f()\n"
+                    .to_string(),
+                vec!["f()", "f()"]
+            )
+        );
+
+        // Context managers and iterators
+        let api = AsyncApi {
+            context_managers: vec!["asyncio.Lock".to_string(), "aiofiles.open".to_string()],
+            iterators: vec!["db.rows".to_string()],
+        };
+        let code = "from aiofiles import open
+import asyncio as aio, db
+
+def f(lock):
+    with aio.Lock(), open(path) as f:
+        for row in db.rows(f):
+            pass
+    with lock, open(path) as f:
+        for row in db.rows(f).x:
+            pass
+    with aio.Lock().x:
+        pass
+with aio.Lock():
+    pass
+";
+        let expected = code
+            .replace("def f", "async def f")
+            .replace("with aio.Lock(), open", "async with aio.Lock(), open")
+            .replace("for row in db.rows(f):", "async for row in db.rows(f):");
+        assert_eq!(converted(code, &["f"], &api), (expected, vec![]));
+    }
+}
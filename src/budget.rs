@@ -0,0 +1,365 @@
+//! Bounds on the work done by the parsers and the memory they use, for
+//! untrusted code.
+//!
+//! Inside `with_budget`, the parsers spend one unit of fuel each time
+//! they start parsing a statement or an operand (again each time they
+//! backtrack over it), and give up with a `PyParseError::FuelExhausted`
+//! error when there is none left, or with `PyParseError::Cancelled` as
+//! soon as the cancellation token is cancelled, possibly from another
+//! thread.
+//!
+//! Code and string literals larger than the limits of the budget are
+//! rejected with `PyParseError::CodeTooLarge` and
+//! `PyParseError::LiteralTooLong` errors, and `validate::validate` and
+//! `tolerant::parse_module_tolerant` stop collecting errors after
+//! `max_diagnostics` of them, with a last `PyParseError::TooManyDiagnostics`
+//! error.
+//!
+//! With or without a budget, expressions nested deeper than
+//! `MAX_NESTING` levels (of brackets, calls or `**` operators) are
+//! rejected with a `PyParseError::NestedTooDeeply` error, like CPython
+//! does. The parsers grow the stack when it runs low, so code nested up
+//! to the limit parses on threads with a small stack too.
+
+use std::cell::{Cell, RefCell};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use nom::{Context, ErrorKind, IResult};
+
+use errors::{ParseError, PyParseError};
+use helpers::{fail_if, StrSpan};
+
+/// A flag shared between threads, to stop parsing from another thread.
+#[derive(Clone, Debug, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    pub fn new() -> CancellationToken {
+        CancellationToken::default()
+    }
+
+    /// Makes the parsers using this token fail as soon as possible.
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed)
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Limits given to `with_budget`.
+#[derive(Clone, Debug, Default)]
+pub struct Budget {
+    /// Number of statements and operands the parsers may start parsing,
+    /// or `None` for no limit.
+    pub fuel: Option<u64>,
+    pub cancellation: Option<CancellationToken>,
+    /// Largest code the parsers accept, in bytes.
+    pub max_code_size: Option<usize>,
+    /// Largest string or bytes literal the parsers accept, in bytes of
+    /// code (including its prefix and quotes).
+    pub max_literal_length: Option<usize>,
+    /// Largest number of errors collected by functions that report all
+    /// the errors of the code.
+    pub max_diagnostics: Option<usize>,
+    /// Deepest nesting of expressions the parsers accept, instead of
+    /// `MAX_NESTING`.
+    pub max_nesting: Option<usize>,
+}
+
+/// Deepest nesting of expressions the parsers accept by default, the
+/// limit of CPython's parser.
+pub const MAX_NESTING: usize = 200;
+
+/// Stack left below which a level of nesting runs on a new stack segment
+/// (debug builds use tens of kilobytes per level).
+const RED_ZONE: usize = 256 * 1024;
+
+/// Size of the new stack segments.
+const STACK_SEGMENT: usize = 4 << 20;
+
+thread_local! {
+    static BUDGET: RefCell<Option<Budget>> = const { RefCell::new(None) };
+    static DEPTH: Cell<usize> = const { Cell::new(0) };
+}
+
+/// Restores the previous budget when dropped, even if the closure
+/// panics.
+struct Guard {
+    previous: Option<Budget>,
+}
+
+impl Drop for Guard {
+    fn drop(&mut self) {
+        let previous = self.previous.take();
+        BUDGET.with(|budget| *budget.borrow_mut() = previous);
+    }
+}
+
+/// Runs `f`, making the parsers it calls on the current thread fail
+/// once they used up `budget`.
+///
+/// ```
+/// use python_parser::budget::{with_budget, Budget};
+/// use python_parser::errors::PyParseError;
+/// use python_parser::parse_module;
+///
+/// let code = "x = 1\n".repeat(1000);
+/// let budget = Budget { fuel: Some(100), ..Budget::default() };
+/// let error = with_budget(budget, || parse_module(&code)).unwrap_err();
+/// assert_eq!(error.code, Some(PyParseError::FuelExhausted));
+/// assert!(parse_module(&code).is_ok());
+/// ```
+pub fn with_budget<F, R>(budget: Budget, f: F) -> R
+where
+    F: FnOnce() -> R,
+{
+    let previous = BUDGET.with(|current| current.borrow_mut().replace(budget));
+    let _guard = Guard { previous };
+    f()
+}
+
+/// Spends a unit of fuel, and returns why parsing must stop, if it must.
+pub(crate) fn spend() -> Option<PyParseError> {
+    BUDGET.with(|budget| {
+        let mut budget = budget.borrow_mut();
+        let budget = budget.as_mut()?;
+        if budget
+            .cancellation
+            .as_ref()
+            .is_some_and(|c| c.is_cancelled())
+        {
+            return Some(PyParseError::Cancelled);
+        }
+        match budget.fuel {
+            Some(0) => Some(PyParseError::FuelExhausted),
+            Some(ref mut fuel) => {
+                *fuel -= 1;
+                None
+            }
+            None => None,
+        }
+    })
+}
+
+/// Parser that spends a unit of fuel, and fails without backtracking
+/// if parsing must stop.
+pub(crate) fn spend_fuel(i: StrSpan) -> IResult<StrSpan, (), u32> {
+    match spend() {
+        None => Ok((i, ())),
+        Some(code) => Err(::nom::Err::Failure(Context::Code(
+            i,
+            ErrorKind::Custom(code.into()),
+        ))),
+    }
+}
+
+/// Returns the value of a limit of the current budget.
+fn limit<F: FnOnce(&Budget) -> Option<usize>>(f: F) -> Option<usize> {
+    BUDGET.with(|budget| budget.borrow().as_ref().and_then(f))
+}
+
+/// Fails with a `PyParseError::CodeTooLarge` error, where the code goes
+/// over the limit, if `code` is larger than allowed.
+pub(crate) fn check_code_size(code: &str) -> Result<(), ParseError> {
+    match limit(|b| b.max_code_size) {
+        Some(max) if code.len() > max => {
+            let mut offset = max;
+            while !code.is_char_boundary(offset) {
+                offset -= 1;
+            }
+            Err(ParseError {
+                offset,
+                line: code[..offset].matches('\n').count() as u32 + 1,
+                code: Some(PyParseError::CodeTooLarge),
+            })
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Parser that fails without backtracking, at `start`, if the literal
+/// from `start` to `end` is longer than allowed.
+pub(crate) fn check_literal_length<'a>(
+    i: StrSpan<'a>,
+    start: StrSpan<'a>,
+    end: StrSpan,
+) -> IResult<StrSpan<'a>, (), u32> {
+    let too_long =
+        limit(|b| b.max_literal_length).is_some_and(|max| end.offset - start.offset > max);
+    fail_if(i, too_long, start, PyParseError::LiteralTooLong)
+}
+
+/// Returns the deepest nesting of expressions allowed.
+pub(crate) fn max_nesting() -> usize {
+    limit(|b| b.max_nesting).unwrap_or(MAX_NESTING)
+}
+
+/// Leaves a level of nesting when dropped, even if the parser panics.
+struct DepthGuard;
+
+impl Drop for DepthGuard {
+    fn drop(&mut self) {
+        DEPTH.with(|depth| depth.set(depth.get() - 1));
+    }
+}
+
+/// Runs `f`, on a new stack segment if the stack is running low.
+pub(crate) fn grow_stack<R, F: FnOnce() -> R>(f: F) -> R {
+    stacker::maybe_grow(RED_ZONE, STACK_SEGMENT, f)
+}
+
+/// Runs `parser` one level of nesting deeper, or fails without
+/// backtracking if it would go over the limit.
+pub(crate) fn nested<'a, O, F>(i: StrSpan<'a>, parser: F) -> IResult<StrSpan<'a>, O, u32>
+where
+    F: FnOnce(StrSpan<'a>) -> IResult<StrSpan<'a>, O, u32>,
+{
+    let level = DEPTH.with(|depth| {
+        let level = depth.get();
+        depth.set(level + 1);
+        level
+    });
+    let _guard = DepthGuard;
+    if level > max_nesting() {
+        return Err(::nom::Err::Failure(Context::Code(
+            i,
+            ErrorKind::Custom(PyParseError::NestedTooDeeply.into()),
+        )));
+    }
+    grow_stack(|| parser(i))
+}
+
+/// Keeps the first errors of `diagnostics` (sorted by position), as
+/// many as allowed, and replaces the code of the first error over the
+/// limit by `PyParseError::TooManyDiagnostics`.
+pub(crate) fn limit_diagnostics<T>(diagnostics: &mut Vec<T>, error: fn(&mut T) -> &mut ParseError) {
+    if let Some(max) = limit(|b| b.max_diagnostics) {
+        if diagnostics.len() > max {
+            diagnostics.truncate(max + 1);
+            error(&mut diagnostics[max]).code = Some(PyParseError::TooManyDiagnostics);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parse_module;
+    use tolerant::parse_module_tolerant;
+    use validate::{validate, ValidationOptions};
+
+    #[test]
+    fn test_fuel() {
+        let code = "def f(x):\n    return [x + 1 for x in y]\n";
+        let parse = |fuel| {
+            let budget = Budget {
+                fuel: Some(fuel),
+                ..Budget::default()
+            };
+            with_budget(budget, || parse_module(code))
+        };
+        assert_eq!(
+            parse(5).unwrap_err().code,
+            Some(PyParseError::FuelExhausted)
+        );
+        assert!(parse(1000).is_ok());
+        // Nested calls do not share their fuel
+        assert!(with_budget(Budget::default(), || parse(1000)).is_ok());
+        BUDGET.with(|budget| assert!(budget.borrow().is_none()));
+    }
+
+    #[test]
+    fn test_cancellation() {
+        let token = CancellationToken::new();
+        let budget = Budget {
+            cancellation: Some(token.clone()),
+            ..Budget::default()
+        };
+        assert!(with_budget(budget.clone(), || parse_module("x = 1\n")).is_ok());
+        token.cancel();
+        assert_eq!(
+            with_budget(budget, || parse_module("x = 1\n"))
+                .unwrap_err()
+                .code,
+            Some(PyParseError::Cancelled)
+        );
+    }
+
+    #[test]
+    fn test_nesting() {
+        let parse = |code: String, budget: Budget| with_budget(budget, || parse_module(&code));
+        let brackets = |depth| format!("x = {}y{}\n", "([".repeat(depth), "])".repeat(depth));
+        assert!(parse(brackets(MAX_NESTING / 2), Budget::default()).is_ok());
+        let error = parse(brackets(MAX_NESTING / 2 + 1), Budget::default()).unwrap_err();
+        assert_eq!(
+            (error.offset, error.code),
+            (5 + MAX_NESTING, Some(PyParseError::NestedTooDeeply))
+        );
+        let calls = format!(
+            "x = {}1{}\n",
+            "f(".repeat(MAX_NESTING),
+            ")".repeat(MAX_NESTING)
+        );
+        assert!(parse(calls, Budget::default()).is_ok());
+        let powers = |depth| format!("x = {}2\n", "2**".repeat(depth));
+        assert!(parse(powers(MAX_NESTING), Budget::default()).is_ok());
+        let error = parse(powers(3000), Budget::default()).unwrap_err();
+        assert_eq!(error.code, Some(PyParseError::NestedTooDeeply));
+
+        let budget = Budget {
+            max_nesting: Some(10),
+            ..Budget::default()
+        };
+        assert!(parse(brackets(5), budget.clone()).is_ok());
+        let error = parse(brackets(6), budget).unwrap_err();
+        assert_eq!(error.code, Some(PyParseError::NestedTooDeeply));
+        DEPTH.with(|depth| assert_eq!(depth.get(), 0));
+    }
+
+    #[test]
+    fn test_limits() {
+        let budget = Budget {
+            max_code_size: Some(12),
+            max_literal_length: Some(5),
+            max_diagnostics: Some(2),
+            ..Budget::default()
+        };
+        let parse = |code| with_budget(budget.clone(), || parse_module(code));
+        assert!(parse("x = 'abc'\n").is_ok());
+        let error = parse("x = 1\ny = 23456\n").unwrap_err();
+        assert_eq!((error.offset, error.line), (12, 2));
+        assert_eq!(error.code, Some(PyParseError::CodeTooLarge));
+        let error = parse("x = b'abcd'\n").unwrap_err();
+        assert_eq!(
+            (error.offset, error.code),
+            (4, Some(PyParseError::LiteralTooLong))
+        );
+        let error = parse("x = r'abcd'\n").unwrap_err();
+        assert_eq!(
+            (error.offset, error.code),
+            (4, Some(PyParseError::LiteralTooLong))
+        );
+
+        let code = "break\ncontinue\nreturn\nyield\n";
+        let module = parse_module(code).unwrap();
+        let diagnostics = with_budget(budget.clone(), || {
+            validate(code, &module.body, &ValidationOptions::default())
+        });
+        let codes: Vec<_> = diagnostics.iter().map(|d| d.error.code).collect();
+        assert_eq!(
+            codes,
+            [
+                Some(PyParseError::BreakOutsideLoop),
+                Some(PyParseError::ContinueOutsideLoop),
+                Some(PyParseError::TooManyDiagnostics)
+            ]
+        );
+        let errors = with_budget(budget, || parse_module_tolerant(")\n)\n)\n)\n").errors);
+        let lines: Vec<_> = errors.iter().map(|e| (e.line, e.code)).collect();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[2], (3, Some(PyParseError::TooManyDiagnostics)));
+    }
+}
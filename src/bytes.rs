@@ -2,12 +2,13 @@ use std::cmp::min;
 
 use nom::anychar;
 
-use helpers::StrSpan;
+use budget::check_literal_length;
+use helpers::{line_break, position, StrSpan};
 
 named!(escapedchar<StrSpan, Option<u8>>,
   preceded!(char!('\\'),
     alt!(
-      char!('\n') => { |_| None }
+      line_break => { |_| None }
     | char!('\\') => { |_| Some(b'\\') }
     | char!('\'') => { |_| Some(b'\'') }
     | char!('"') => { |_| Some(b'"') }
@@ -51,6 +52,7 @@ named_args!(longbytes(quote: char) <StrSpan, Vec<u8>>,
   fold_many0!(
     alt!(
       call!(escapedchar)
+    | line_break => { |_| Some(b'\n') }
     | verify!(tuple!(peek!(take!(3)), anychar), |(s,_):(StrSpan,_)| { s.fragment.0.chars().collect::<Vec<char>>() != vec![quote,quote,quote] }) => { |(_,c)| Some(c as u8) }
     ),
     Vec::new(),
@@ -76,7 +78,9 @@ named_args!(shortrawbytes(quote: char) <StrSpan, Vec<u8>>,
 named_args!(longrawbytes(quote: char) <StrSpan, Vec<u8>>,
   fold_many0!(
     alt!(
-      tuple!(char!('\\'), anychar) => { |(c1,c2)| (c1 as u8, Some(c2 as u8)) }
+      preceded!(char!('\\'), line_break) => { |_| (b'\\', Some(b'\n')) }
+    | tuple!(char!('\\'), anychar) => { |(c1,c2)| (c1 as u8, Some(c2 as u8)) }
+    | line_break => { |_| (b'\n', None) }
     | verify!(tuple!(peek!(take!(3)), none_of!("\\")), |(s,_):(StrSpan,_)| { s.fragment.0.chars().collect::<Vec<char>>() != vec![quote,quote,quote] }) => { |(_,c)| (c as u8, None) }
     ),
     Vec::new(),
@@ -90,6 +94,7 @@ named_args!(longrawbytes(quote: char) <StrSpan, Vec<u8>>,
 
 named!(pub bytes<StrSpan, Vec<u8>>,
   do_parse!(
+    start: position >>
     prefix: alt!(tag!("br")|tag!("Br")|tag!("bR")|tag!("BR")|tag!("rb")|tag!("rB")|tag!("Rb")|tag!("RB")|tag!("b")|tag!("B")|tag!("")) >>
     is_raw: call!(|i, s:StrSpan| Ok((i, s.fragment.0.contains('r') || s.fragment.0.contains('R'))), prefix) >>
     content: switch!(call!(|i| Ok((i, is_raw))),
@@ -105,6 +110,9 @@ named!(pub bytes<StrSpan, Vec<u8>>,
       | delimited!(char!('\''), return_error!(call!(shortrawbytes, '\'')), char!('\''))
       | delimited!(char!('"'), return_error!(call!(shortrawbytes, '"')), char!('"'))
       )
-    ) >> (content)
+    ) >>
+    end: position >>
+    call!(check_literal_length, start, end) >>
+    (content)
   )
 );
@@ -0,0 +1,221 @@
+//! An on-disk cache of parsed modules, so that tools parsing the same
+//! files again and again only parse the ones that changed.
+
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use ast::Module;
+use decoding::string_decoding;
+use errors::ParseError;
+use parse_module;
+use serialize::{deserialize_module, serialize_module};
+use visitors::hash::Fnv;
+
+/// Error returned by `CachedParser::parse_file`.
+#[derive(Debug)]
+pub enum ParseFileError {
+    Io(io::Error),
+    Parse(ParseError),
+}
+
+impl fmt::Display for ParseFileError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ParseFileError::Io(ref e) => write!(f, "could not read code: {}", e),
+            ParseFileError::Parse(ref e) => e.fmt(f),
+        }
+    }
+}
+
+impl ::std::error::Error for ParseFileError {}
+
+/// Number of temporary files created by this process, to name them.
+static TMP_FILES: AtomicUsize = AtomicUsize::new(0);
+
+/// Returns a path to write the entry at `path` to, which no other
+/// thread or process uses.
+fn tmp_path(path: &Path) -> PathBuf {
+    let n = TMP_FILES.fetch_add(1, Ordering::Relaxed);
+    path.with_extension(format!("tmp{}-{}", process::id(), n))
+}
+
+/// Parses modules with `parse_module`, storing the ASTs in a directory
+/// to return them without parsing when given the same code again.
+///
+/// Entries are keyed by a hash of the code and of the version (and
+/// features) of this crate, so they never need to be invalidated; the
+/// directory can be deleted at any time to reclaim space. They store
+/// the code too, which is compared on load, since the hash is short.
+/// The cache is best-effort: if it cannot be read or written, the code
+/// is parsed as usual.
+/// Code with syntax errors is not cached.
+///
+/// ```
+/// use python_parser::cache::CachedParser;
+///
+/// let dir = std::env::temp_dir().join("python-parser-doctest-cache");
+/// let parser = CachedParser::new(&dir);
+/// let module = parser.parse("x = 1\n").unwrap();
+/// assert_eq!(parser.parse("x = 1\n").unwrap(), module); // Not parsed again
+/// # std::fs::remove_dir_all(&dir).unwrap();
+/// ```
+#[derive(Clone, Debug)]
+pub struct CachedParser {
+    dir: PathBuf,
+}
+
+impl CachedParser {
+    /// Returns a parser storing its cache in `dir`, which is created
+    /// when needed.
+    pub fn new<P: Into<PathBuf>>(dir: P) -> CachedParser {
+        CachedParser { dir: dir.into() }
+    }
+
+    /// Returns the path of the cache entry of `code`.
+    fn entry_path(&self, code: &str) -> PathBuf {
+        let mut hasher = Fnv::new();
+        hasher.update(env!("CARGO_PKG_VERSION").as_bytes());
+        hasher.update(&[cfg!(feature = "bigint") as u8, cfg!(feature = "wtf8") as u8]);
+        hasher.update(&[string_decoding() as u8]);
+        hasher.update(code.as_bytes());
+        self.dir.join(format!("{:016x}.ast", hasher.finish()))
+    }
+
+    /// Returns the AST of the entry at `path`, if it is the one of
+    /// `code`: entries start with the length of their code and the code,
+    /// to tell hash collisions apart.
+    fn read_entry(path: &Path, code: &str) -> Option<Module> {
+        let bytes = fs::read(path).ok()?;
+        let end = 8 + code.len();
+        if bytes.len() < end
+            || bytes[..8] != (code.len() as u64).to_le_bytes()
+            || &bytes[8..end] != code.as_bytes()
+        {
+            return None;
+        }
+        deserialize_module(&bytes[end..]).ok()
+    }
+
+    fn write_entry(&self, path: &Path, code: &str, module: &Module) -> io::Result<()> {
+        let mut bytes = (code.len() as u64).to_le_bytes().to_vec();
+        bytes.extend(code.as_bytes());
+        bytes.extend(serialize_module(module));
+        fs::create_dir_all(&self.dir)?;
+        // Write to another file then rename it, so concurrent parsers
+        // never read a partial entry.
+        let tmp_path = tmp_path(path);
+        fs::write(&tmp_path, bytes)?;
+        let res = fs::rename(&tmp_path, path);
+        if res.is_err() {
+            let _ = fs::remove_file(&tmp_path);
+        }
+        res
+    }
+
+    /// Like `parse_module`, but returns the cached AST if `code` was
+    /// already parsed.
+    pub fn parse(&self, code: &str) -> Result<Module, ParseError> {
+        let path = self.entry_path(code);
+        if let Some(module) = CachedParser::read_entry(&path, code) {
+            return Ok(module);
+        }
+        let module = parse_module(code)?;
+        let _ = self.write_entry(&path, code, &module); // The cache is optional
+        Ok(module)
+    }
+
+    /// Reads and parses a file, using the cache if it did not change
+    /// since it was last parsed.
+    pub fn parse_file<P: AsRef<Path>>(&self, path: P) -> Result<Module, ParseFileError> {
+        let code = fs::read_to_string(path).map_err(ParseFileError::Io)?;
+        self.parse(&code).map_err(ParseFileError::Parse)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    /// Returns an empty directory, specific to the test.
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("python-parser-{}-{}", name, process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn test_cache_hit() {
+        let dir = test_dir("cache-hit");
+        let parser = CachedParser::new(&dir);
+        let module = parser.parse("x = 1\n").unwrap();
+        assert_eq!(module, parse_module("x = 1\n").unwrap());
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+
+        // Replace the entry, to check it is used instead of parsing
+        let other = parse_module("y = 2\n").unwrap();
+        let path = parser.entry_path("x = 1\n");
+        parser.write_entry(&path, "x = 1\n", &other).unwrap();
+        assert_eq!(parser.parse("x = 1\n").unwrap(), other);
+
+        assert_eq!(
+            parser.parse("x = 2\n").unwrap(),
+            parse_module("x = 2\n").unwrap()
+        );
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 2);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_invalid_entries() {
+        let dir = test_dir("invalid-entries");
+        let parser = CachedParser::new(&dir);
+        assert!(parser.parse("x = (\n").is_err());
+        assert!(!dir.exists()); // Errors are not cached
+
+        let path = parser.entry_path("x = 1\n");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(&path, b"garbage").unwrap();
+        assert_eq!(
+            parser.parse("x = 1\n").unwrap(),
+            parse_module("x = 1\n").unwrap()
+        );
+        // The entry was overwritten
+        assert_eq!(
+            CachedParser::read_entry(&path, "x = 1\n"),
+            parse_module("x = 1\n").ok()
+        );
+        // Entries of other code are ignored, even with the same length
+        assert_eq!(CachedParser::read_entry(&path, "x = 12\n"), None);
+        assert_eq!(CachedParser::read_entry(&path, "x = 2\n"), None);
+        assert_ne!(tmp_path(&path), tmp_path(&path));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_parse_file() {
+        let dir = test_dir("parse-file");
+        let parser = CachedParser::new(dir.join("cache"));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("foo.py");
+        match parser.parse_file(&path) {
+            Err(ParseFileError::Io(_)) => (),
+            res => panic!("{:?}", res),
+        }
+        fs::write(&path, "import foo\n").unwrap();
+        assert_eq!(
+            parser.parse_file(&path).unwrap(),
+            parse_module("import foo\n").unwrap()
+        );
+        fs::write(&path, "import\n").unwrap();
+        match parser.parse_file(&path) {
+            Err(ParseFileError::Parse(_)) => (),
+            res => panic!("{:?}", res),
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,297 @@
+//! Extraction of the Python code blocks of documents, to check them in
+//! the continuous integration of documentation: the fenced code blocks
+//! (` ```python `) of Markdown, and the `code-block` directives of
+//! reStructuredText.
+//!
+//! Interactive sessions (` ```pycon `, or `>>> ` prompts) are not
+//! extracted; see `doctest` for them.
+
+use std::fmt;
+
+use doctest::indentation;
+use errors::ParseError;
+use tolerant::parse_module_tolerant;
+
+/// Names of Python in the info strings of fences and in directives.
+const PYTHON_NAMES: [&str; 4] = ["python", "py", "python3", "py3"];
+
+/// The language of a document.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Markup {
+    Markdown,
+    ReStructuredText,
+}
+
+impl Markup {
+    /// Returns the language of the files with the extension `ext`
+    /// (without its dot), if it is one of them.
+    pub fn from_extension(ext: &str) -> Option<Markup> {
+        match &ext.to_lowercase()[..] {
+            "md" | "markdown" => Some(Markup::Markdown),
+            "rst" | "rest" => Some(Markup::ReStructuredText),
+            _ => None,
+        }
+    }
+}
+
+/// A block of Python code of a document.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CodeBlock {
+    /// The code, without the indentation of the block, and with each
+    /// line ending with a line break.
+    pub code: String,
+    /// Line of the first line of the code in the document, from 1.
+    pub line: u32,
+}
+
+/// A syntax error in a code block of a document.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BlockDiagnostic {
+    /// Index of the block among the Python blocks of the document.
+    pub block: usize,
+    /// Line of the error in the document, from 1.
+    pub line: u32,
+    /// The error, whose offset and line are in the code of the block.
+    pub error: ParseError,
+}
+
+impl fmt::Display for BlockDiagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.error.code {
+            Some(code) => write!(f, "{} at line {}", code, self.line),
+            None => write!(f, "invalid syntax at line {}", self.line),
+        }
+    }
+}
+
+fn is_python(language: &str) -> bool {
+    PYTHON_NAMES.contains(&&language.to_lowercase()[..])
+}
+
+/// Returns the indentation, character, length and info string of a
+/// line opening or closing a fenced code block.
+fn fence(line: &str) -> Option<(usize, char, usize, &str)> {
+    let indent = indentation(line);
+    let rest = &line[indent..];
+    let c = rest.chars().next()?;
+    if c != '`' && c != '~' {
+        return None;
+    }
+    let len = rest.len() - rest.trim_start_matches(c).len();
+    let info = rest[len..].trim();
+    if len < 3 || (c == '`' && info.contains('`')) {
+        return None;
+    }
+    Some((indent, c, len, info))
+}
+
+/// Returns the language of the info string of a fence, like `python`
+/// for `python title="a.py"` or `{.python}`.
+fn fence_language(info: &str) -> &str {
+    info.split(|c: char| c.is_whitespace() || c == '{' || c == '}' || c == ',')
+        .find(|word| !word.is_empty())
+        .map_or("", |word| word.trim_start_matches('.'))
+}
+
+fn markdown_blocks(text: &str) -> Vec<CodeBlock> {
+    let mut blocks = Vec::new();
+    let mut lines = text.lines().enumerate();
+    while let Some((i, line)) = lines.next() {
+        let (indent, c, len, info) = match fence(line) {
+            Some(fence) => fence,
+            None => continue,
+        };
+        // Unclosed blocks end at the end of the document
+        let mut code = String::new();
+        for (_, line) in &mut lines {
+            match fence(line) {
+                Some((_, closing, closing_len, "")) if closing == c && closing_len >= len => break,
+                _ => (),
+            }
+            code.push_str(&line[indentation(line).min(indent)..]);
+            code.push('\n');
+        }
+        if is_python(fence_language(info)) {
+            blocks.push(CodeBlock {
+                code,
+                line: i as u32 + 2,
+            });
+        }
+    }
+    blocks
+}
+
+/// Returns the indentation and language of a line starting a
+/// `code-block`, `code` or `sourcecode` directive.
+fn directive(line: &str) -> Option<(usize, &str)> {
+    let indent = indentation(line);
+    let rest = line[indent..].trim_end();
+    if !rest.starts_with(".. ") {
+        return None;
+    }
+    let rest = rest[3..].trim_start();
+    ["code-block::", "code::", "sourcecode::"]
+        .iter()
+        .find(|name| rest.starts_with(*name))
+        .map(|name| (indent, rest[name.len()..].trim()))
+}
+
+fn rest_blocks(text: &str) -> Vec<CodeBlock> {
+    let mut blocks = Vec::new();
+    let mut lines = text.lines().enumerate().peekable();
+    while let Some((_, line)) = lines.next() {
+        let (indent, language) = match directive(line) {
+            Some(directive) => directive,
+            None => continue,
+        };
+        // The options of the directive, like `:linenos:`, then the
+        // content, indented
+        let mut content: Vec<(usize, &str)> = Vec::new();
+        while let Some(&(i, line)) = lines.peek() {
+            let is_blank = line.trim().is_empty();
+            if !is_blank && indentation(line) <= indent {
+                break;
+            }
+            lines.next();
+            let is_option = content.is_empty() && line.trim_start().starts_with(':');
+            if !(is_option || (is_blank && content.is_empty())) {
+                content.push((i, line));
+            }
+        }
+        while content
+            .last()
+            .map_or(false, |&(_, line)| line.trim().is_empty())
+        {
+            content.pop();
+        }
+        if !is_python(language) || content.is_empty() {
+            continue;
+        }
+        let common = content
+            .iter()
+            .filter(|&&(_, line)| !line.trim().is_empty())
+            .map(|&(_, line)| indentation(line))
+            .min()
+            .unwrap_or(0);
+        let mut code = String::new();
+        for &(_, line) in &content {
+            code.push_str(line.get(common..).unwrap_or(""));
+            code.push('\n');
+        }
+        blocks.push(CodeBlock {
+            code,
+            line: content[0].0 as u32 + 1,
+        });
+    }
+    blocks
+}
+
+/// Returns the Python code blocks of a document.
+///
+/// ```
+/// use python_parser::code_blocks::{code_blocks, Markup};
+///
+/// let text = "Example:\n\n.. code-block:: python\n   :linenos:\n\n   import os\n   print(os.sep)\n\nThe end.\n";
+/// let blocks = code_blocks(text, Markup::ReStructuredText);
+/// assert_eq!(blocks[0].code, "import os\nprint(os.sep)\n");
+/// assert_eq!(blocks[0].line, 6);
+/// ```
+pub fn code_blocks(text: &str, markup: Markup) -> Vec<CodeBlock> {
+    match markup {
+        Markup::Markdown => markdown_blocks(text),
+        Markup::ReStructuredText => rest_blocks(text),
+    }
+}
+
+/// Parses the Python code blocks of a document, and returns their
+/// syntax errors, with their lines in the document.
+///
+/// ```
+/// use python_parser::code_blocks::{check_code_blocks, Markup};
+///
+/// let text = "# Usage\n\n```python\nfrom a import b\nb(1,\n```\n\n```bash\nls (\n```\n";
+/// let diagnostics = check_code_blocks(text, Markup::Markdown);
+/// assert_eq!(diagnostics.len(), 1);
+/// assert_eq!(diagnostics[0].to_string(), "'(' was never closed at line 5");
+/// ```
+pub fn check_code_blocks(text: &str, markup: Markup) -> Vec<BlockDiagnostic> {
+    let mut diagnostics = Vec::new();
+    for (index, block) in code_blocks(text, markup).into_iter().enumerate() {
+        let errors = parse_module_tolerant(&block.code).errors;
+        diagnostics.extend(errors.into_iter().map(|error| BlockDiagnostic {
+            block: index,
+            line: block.line + error.line - 1,
+            error,
+        }));
+    }
+    diagnostics
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_markdown_blocks() {
+        let text = "\
+Text
+````py title=\"a.py\"
+```
+x = 1
+```
+````
+- item
+
+  ~~~{.python}
+  def f():
+      return 2
+~~~
+```Python
+unclosed";
+        let blocks = code_blocks(text, Markup::Markdown);
+        let blocks: Vec<_> = blocks.iter().map(|b| (&b.code[..], b.line)).collect();
+        assert_eq!(
+            blocks,
+            vec![
+                ("```\nx = 1\n```\n", 3),
+                ("def f():\n    return 2\n", 10),
+                ("unclosed\n", 14),
+            ]
+        );
+        assert_eq!(Markup::from_extension("MD"), Some(Markup::Markdown));
+        assert_eq!(Markup::from_extension("txt"), None);
+    }
+
+    #[test]
+    fn test_rest_blocks() {
+        let text = "\
+Title
+=====
+
+.. code:: bash
+
+   ls
+
+Paragraph
+
+  .. sourcecode:: py
+
+     if x:
+
+         y = (
+  Text
+.. code-block:: python
+";
+        let blocks = code_blocks(text, Markup::ReStructuredText);
+        assert_eq!(
+            blocks,
+            vec![CodeBlock {
+                code: "if x:\n\n    y = (\n".to_string(),
+                line: 12,
+            }]
+        );
+        let diagnostics = check_code_blocks(text, Markup::ReStructuredText);
+        let lines: Vec<_> = diagnostics.iter().map(|d| (d.block, d.line)).collect();
+        assert_eq!(lines, vec![(0, 14)]);
+    }
+}
@@ -0,0 +1,335 @@
+//! Colored output for terminals (with the `termcolor` feature): the
+//! trees of `debug_tree`, and the syntax errors and lint diagnostics
+//! with the line of code they are on, underlined.
+//!
+//! The functions write to any `WriteColor`, so the colors can be
+//! disabled (eg. with `termcolor::ColorChoice::Auto` when the output is
+//! not a terminal) without changing the text.
+//!
+//! Columns are reported in characters, like CPython does, and the tabs
+//! of the lines of code are expanded to `TAB_SIZE` columns, so the
+//! underlines line up with the code.
+
+use std::io;
+
+use termcolor::{Color, ColorSpec, WriteColor};
+
+use errors::ParseError;
+use keywords::{is_keyword, PARSED_VERSION};
+use line_index::LineIndex;
+use lint::{LintDiagnostic, Severity};
+use span::Span;
+use tokenizer::{tokenize, TokenKind};
+
+/// Number of columns between the tab stops of the lines of code.
+pub const TAB_SIZE: u32 = 8;
+
+fn fg(color: Color) -> ColorSpec {
+    let mut spec = ColorSpec::new();
+    spec.set_fg(Some(color));
+    spec
+}
+
+fn bold(color: Color) -> ColorSpec {
+    let mut spec = fg(color);
+    spec.set_bold(true);
+    spec
+}
+
+fn dimmed() -> ColorSpec {
+    let mut spec = ColorSpec::new();
+    spec.set_dimmed(true);
+    spec
+}
+
+fn write_colored<W: WriteColor>(out: &mut W, spec: &ColorSpec, text: &str) -> io::Result<()> {
+    out.set_color(spec)?;
+    out.write_all(text.as_bytes())?;
+    out.reset()
+}
+
+/// Returns the length of the string or bytes literal at the start of
+/// `text`, written by `dump` like `'it\'s'` or `b'\x00'`.
+fn literal_len(text: &str) -> usize {
+    let start = if text.starts_with('b') { 2 } else { 1 };
+    let mut escaped = false;
+    for (i, c) in text[start..].char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            '\'' => return start + i + 1,
+            _ => (),
+        }
+    }
+    text.len()
+}
+
+/// Returns the length of the start of `text` made of characters
+/// matching `f`.
+fn run_len<F: Fn(char) -> bool>(text: &str, f: F) -> usize {
+    text.find(|c| !f(c)).unwrap_or_else(|| text.len())
+}
+
+/// Writes a tree returned by `debug_tree`, with the names of the nodes,
+/// of the fields, the literals and the spans in colors.
+///
+/// ```
+/// extern crate termcolor;
+/// # extern crate python_parser;
+/// use python_parser::color::write_tree;
+/// use python_parser::visitors::dump::debug_tree;
+///
+/// # fn main() {
+/// let tree = debug_tree("x = 'a'\n").unwrap();
+/// let mut out = termcolor::Buffer::ansi();
+/// write_tree(&mut out, &tree).unwrap();
+/// let out = String::from_utf8(out.into_inner()).unwrap();
+/// assert!(out.contains("\x1b[0m\x1b[32m'a'\x1b[0m"));
+///
+/// // Without colors, the tree is unchanged
+/// let mut out = termcolor::Buffer::no_color();
+/// write_tree(&mut out, &tree).unwrap();
+/// assert_eq!(out.into_inner(), tree.into_bytes());
+/// # }
+/// ```
+pub fn write_tree<W: WriteColor>(out: &mut W, tree: &str) -> io::Result<()> {
+    let mut rest = tree;
+    while let Some(c) = rest.chars().next() {
+        let next = rest[c.len_utf8()..].chars().next();
+        let (len, spec) = if "│├└─".contains(c) {
+            (run_len(rest, |c| "│├└─ ".contains(c)), Some(dimmed()))
+        } else if c == '\'' || (c == 'b' && next == Some('\'')) {
+            (literal_len(rest), Some(fg(Color::Green)))
+        } else if c == '[' && next.map_or(false, |c| c.is_ascii_digit()) {
+            // The span of a statement
+            (run_len(rest, |c| c != ']') + 1, Some(dimmed()))
+        } else if c.is_ascii_digit() {
+            (
+                run_len(rest, |c| c.is_alphanumeric() || c == '.'),
+                Some(fg(Color::Magenta)),
+            )
+        } else if c.is_alphabetic() || c == '_' {
+            let len = run_len(rest, |c| c.is_alphanumeric() || c == '_');
+            let spec = match &rest[..len] {
+                "None" | "True" | "False" => Some(fg(Color::Yellow)),
+                _ if rest[len..].starts_with(|c| c == ':' || c == '=') => Some(fg(Color::Blue)),
+                _ if c.is_uppercase() => Some(bold(Color::Cyan)),
+                // `list` and `tuple`
+                _ => None,
+            };
+            (len, spec)
+        } else {
+            (c.len_utf8(), None)
+        };
+        let len = len.min(rest.len());
+        match spec {
+            Some(spec) => write_colored(out, &spec, &rest[..len])?,
+            None => out.write_all(rest[..len].as_bytes())?,
+        }
+        rest = &rest[len..];
+    }
+    Ok(())
+}
+
+/// Writes a line of code, with its keywords, literals and comments in
+/// colors.
+fn write_code<W: WriteColor>(out: &mut W, line: &str) -> io::Result<()> {
+    let mut written = 0;
+    for token in tokenize(line) {
+        let spec = match token.kind {
+            TokenKind::Name if is_keyword(token.text, PARSED_VERSION) => bold(Color::Magenta),
+            TokenKind::String => fg(Color::Green),
+            TokenKind::Number => fg(Color::Cyan),
+            TokenKind::Comment => dimmed(),
+            _ => continue,
+        };
+        let end = (token.offset + token.text.len()).min(line.len());
+        if token.offset < written || end <= token.offset {
+            continue;
+        }
+        out.write_all(line[written..token.offset].as_bytes())?;
+        write_colored(out, &spec, &line[token.offset..end])?;
+        written = end;
+    }
+    out.write_all(line[written..].as_bytes())
+}
+
+/// Returns `line` with its tabs replaced by spaces up to the next tab
+/// stop.
+fn expand_tabs(line: &str) -> String {
+    let mut expanded = String::new();
+    let mut col = 0;
+    for c in line.chars() {
+        if c == '\t' {
+            let next = (col / TAB_SIZE + 1) * TAB_SIZE;
+            expanded.extend((col..next).map(|_| ' '));
+            col = next;
+        } else {
+            expanded.push(c);
+            col += 1;
+        }
+    }
+    expanded
+}
+
+/// Writes `label: message`, the location of `span` in `filename`, and
+/// the line of code where `span` starts, with the span underlined.
+fn write_snippet<W: WriteColor>(
+    out: &mut W,
+    filename: &str,
+    code: &str,
+    span: Span,
+    label: (&str, Color),
+    message: &str,
+) -> io::Result<()> {
+    let (label, color) = label;
+    write_colored(out, &bold(color), label)?;
+    out.set_color(ColorSpec::new().set_bold(true))?;
+    write!(out, ": {}", message)?;
+    out.reset()?;
+    writeln!(out)?;
+
+    let index = LineIndex::new(code);
+    let positions = (
+        index.display_position(span.start, Some(TAB_SIZE)),
+        index.display_position(span.end, Some(TAB_SIZE)),
+        index.display_position(span.start, None),
+    );
+    let (start, end, col) = match positions {
+        (Some(start), Some(end), Some(col)) => (start, end, col.display_col),
+        _ => return writeln!(out, "  --> {}", filename),
+    };
+    let line_span = index.line_span(start.line).unwrap_or(span);
+    let line = expand_tabs(&code[line_span.start..line_span.end]);
+    let number = (start.line + 1).to_string();
+    let margin = " ".repeat(number.len());
+    write_colored(out, &dimmed(), &format!("{}--> ", margin))?;
+    writeln!(out, "{}:{}:{}", filename, start.line + 1, col + 1)?;
+    write_colored(out, &dimmed(), &format!("{} | ", number))?;
+    write_code(out, &line)?;
+    writeln!(out)?;
+
+    let line_end = line.chars().count() as u32;
+    let end_col = if end.line == start.line {
+        end.display_col
+    } else {
+        line_end
+    };
+    let width = end_col.saturating_sub(start.display_col).max(1) as usize;
+    write_colored(out, &dimmed(), &format!("{} | ", margin))?;
+    out.write_all(" ".repeat(start.display_col as usize).as_bytes())?;
+    write_colored(out, &bold(color), &"^".repeat(width))?;
+    writeln!(out)
+}
+
+/// Writes a syntax error of the code of `filename`, with the line of
+/// the error.
+///
+/// ```
+/// extern crate termcolor;
+/// # extern crate python_parser;
+/// use python_parser::color::write_parse_error;
+/// use python_parser::parse_module;
+///
+/// # fn main() {
+/// let code = "x = 1\ndef f(:\n    pass\n";
+/// let error = parse_module(code).unwrap_err();
+/// let mut out = termcolor::Buffer::no_color();
+/// write_parse_error(&mut out, "a.py", code, &error).unwrap();
+/// assert_eq!(
+///     String::from_utf8(out.into_inner()).unwrap(),
+///     "error: '(' was never closed at line 2
+///  --> a.py:2:6
+/// 2 | def f(:
+///   |      ^
+/// "
+/// );
+/// # }
+/// ```
+pub fn write_parse_error<W: WriteColor>(
+    out: &mut W,
+    filename: &str,
+    code: &str,
+    error: &ParseError,
+) -> io::Result<()> {
+    let span = Span::at(error.offset);
+    let message = error.to_string();
+    write_snippet(out, filename, code, span, ("error", Color::Red), &message)
+}
+
+/// Writes a diagnostic of the lint rules on the code of `filename`,
+/// with the line of its span, in a color depending on its severity.
+pub fn write_lint_diagnostic<W: WriteColor>(
+    out: &mut W,
+    filename: &str,
+    code: &str,
+    diagnostic: &LintDiagnostic,
+) -> io::Result<()> {
+    let color = match diagnostic.severity {
+        Severity::High => Color::Red,
+        Severity::Medium => Color::Yellow,
+        Severity::Low => Color::Blue,
+    };
+    let label = format!("{} ({} severity)", diagnostic.rule, diagnostic.severity);
+    write_snippet(
+        out,
+        filename,
+        code,
+        diagnostic.span,
+        (&label, color),
+        &diagnostic.message,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lint::lint;
+    use lint::security::security_rules;
+    use parse_module;
+    use termcolor::Buffer;
+
+    #[test]
+    fn test_write_lint_diagnostic() {
+        let code = "import os\nif x:\n    eval(y)  # unsafe\n";
+        let diagnostics = lint(code, &security_rules());
+        assert_eq!(diagnostics.len(), 1);
+
+        let mut out = Buffer::no_color();
+        write_lint_diagnostic(&mut out, "a.py", code, &diagnostics[0]).unwrap();
+        let out = String::from_utf8(out.into_inner()).unwrap();
+        let mut lines = out.lines().skip(1);
+        assert_eq!(lines.next(), Some(" --> a.py:3:5"));
+        assert_eq!(lines.next(), Some("3 |     eval(y)  # unsafe"));
+        assert_eq!(lines.next(), Some("  |     ^^^^"));
+
+        let mut out = Buffer::ansi();
+        write_lint_diagnostic(&mut out, "a.py", code, &diagnostics[0]).unwrap();
+        let out = String::from_utf8(out.into_inner()).unwrap();
+        // The comment is dimmed, and the span underlined in yellow (eval is of medium severity)
+        assert!(out.contains("\x1b[2m# unsafe\x1b[0m"));
+        assert!(out.contains("\x1b[1m\x1b[33m^^^^\x1b[0m"));
+    }
+
+    #[test]
+    fn test_tabs() {
+        let code = "x = 1\nf(\t1,\t2 3)\n";
+        let error = parse_module(code).unwrap_err();
+        let mut out = Buffer::no_color();
+        write_parse_error(&mut out, "a.py", code, &error).unwrap();
+        let out = String::from_utf8(out.into_inner()).unwrap();
+        let mut lines = out.lines().skip(1);
+        assert_eq!(lines.next(), Some(" --> a.py:2:7"));
+        assert_eq!(lines.next(), Some("2 | f(      1,      2 3)"));
+        assert_eq!(lines.next(), Some("  |                 ^"));
+    }
+
+    #[test]
+    fn test_write_code() {
+        let mut out = Buffer::ansi();
+        write_code(&mut out, "if x: return 'a' + 1").unwrap();
+        let out = String::from_utf8(out.into_inner()).unwrap();
+        assert!(out.starts_with("\x1b[0m\x1b[1m\x1b[35mif\x1b[0m x: "));
+        assert!(out.contains("\x1b[32m'a'\x1b[0m + \x1b[0m\x1b[36m1\x1b[0m"));
+    }
+}
@@ -0,0 +1,379 @@
+//! The syntactic context of a cursor, for completion engines: what
+//! kind of name can be typed there.
+//!
+//! Only the code before the cursor is used, and it is tokenized (which
+//! never fails), so the context is found in incomplete code.
+
+use ast::Expression;
+use definition::header_end;
+use lint::{is_name, is_op};
+use parse_expression;
+use span::{Span, Spanned};
+use tokenizer::{tokenize, Token, TokenKind};
+
+/// What can be typed at the cursor.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ContextKind {
+    /// A keyword starting a statement, or an expression.
+    StatementStart,
+    /// A name in an expression.
+    Expression,
+    /// An attribute of the expression before a dot.
+    Attribute {
+        /// The code of the expression.
+        receiver: Span,
+        /// The expression, if it can be parsed.
+        expression: Option<Expression>,
+    },
+    /// A module in an `import` statement, or after `from`.
+    ImportPath {
+        /// Number of leading dots of a relative import.
+        level: usize,
+        /// The packages before the cursor, like `["os"]` in
+        /// `import os.pa`.
+        path: Vec<String>,
+    },
+    /// A name imported from a module, in `from module import name`.
+    ImportName { level: usize, module: Vec<String> },
+    /// The name of a new parameter of a function or lambda.
+    Parameter,
+    /// Nothing can be completed: in a string, comment or number, or in
+    /// a new name after `as`.
+    NoCompletion,
+}
+
+/// The context of a cursor, found by `completion_context`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CompletionContext {
+    pub kind: ContextKind,
+    /// The part of the name before the cursor, which the completion
+    /// replaces (it is empty if the cursor is not after a name).
+    pub prefix: Span,
+}
+
+impl Spanned for CompletionContext {
+    fn span(&self) -> Span {
+        self.prefix
+    }
+}
+
+/// Returns the index of the bracket opening the one closed at
+/// `tokens[close]`.
+fn opening(tokens: &[Token], close: usize) -> Option<usize> {
+    let mut depth = 0;
+    for i in (0..=close).rev() {
+        if tokens[i].kind != TokenKind::Op {
+            continue;
+        }
+        match tokens[i].text {
+            ")" | "]" | "}" => depth += 1,
+            "(" | "[" | "{" => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i);
+                }
+            }
+            _ => (),
+        }
+    }
+    None
+}
+
+/// Returns the index of the first token of the primary expression
+/// (names, attributes, calls, subscripts and literals) ending at
+/// `tokens[end - 1]`.
+pub(crate) fn primary_start(tokens: &[Token], end: usize) -> usize {
+    let mut start = end;
+    while start > 0 {
+        let token = &tokens[start - 1];
+        match token.kind {
+            TokenKind::Op if [")", "]", "}"].contains(&token.text) => {
+                match opening(tokens, start - 1) {
+                    Some(open) => start = open,
+                    None => break,
+                }
+                // A literal, unless it is called or subscripted
+                let trailer = start > 0
+                    && match tokens[start - 1].kind {
+                        TokenKind::Name | TokenKind::String => true,
+                        TokenKind::Op => [")", "]", "}"].contains(&tokens[start - 1].text),
+                        _ => false,
+                    };
+                if !trailer {
+                    break;
+                }
+            }
+            TokenKind::Name | TokenKind::String | TokenKind::Number => {
+                start -= 1;
+                if start == 0 || !is_op(tokens.get(start - 1), ".") {
+                    break;
+                }
+            }
+            TokenKind::Op if token.text == "." => start -= 1,
+            _ => break,
+        }
+    }
+    start
+}
+
+/// Returns the names of the dotted name `tokens`, and the number of
+/// dots before them.
+fn dotted_path(tokens: &[Token]) -> (usize, Vec<String>) {
+    let level = tokens
+        .iter()
+        .take_while(|t| t.kind == TokenKind::Op)
+        .map(|t| t.text.len())
+        .sum();
+    let path = tokens
+        .iter()
+        .filter(|t| t.kind == TokenKind::Name)
+        .map(|t| t.text.to_string())
+        .collect();
+    (level, path)
+}
+
+/// Returns the context of the tokens of the current statement before
+/// the name being typed.
+fn statement_context(code: &str, tokens: &[Token]) -> ContextKind {
+    let last = match tokens.last() {
+        Some(last) => last,
+        None => return ContextKind::StatementStart,
+    };
+    if is_name(Some(last), "as") {
+        return ContextKind::NoCompletion;
+    }
+    if is_name(tokens.first(), "import") || is_name(tokens.first(), "from") {
+        let import = tokens.iter().position(|t| is_name(Some(t), "import"));
+        return match import {
+            Some(i) if tokens[0].text == "from" => {
+                let (level, module) = dotted_path(&tokens[1..i]);
+                ContextKind::ImportName { level, module }
+            }
+            _ => {
+                // The last of the imported modules
+                let start = tokens
+                    .iter()
+                    .rposition(|t| is_op(Some(t), ","))
+                    .map_or(1, |i| i + 1);
+                let (level, path) = dotted_path(&tokens[start..]);
+                ContextKind::ImportPath { level, path }
+            }
+        };
+    }
+    if is_op(Some(last), ":") && header_end(tokens) == Some(tokens.len() - 1) {
+        // The body of a compound statement on the line of its header
+        let compound = tokens
+            .iter()
+            .find(|t| !is_name(Some(t), "async"))
+            .is_some_and(|t| {
+                t.kind == TokenKind::Name
+                    && [
+                        "def", "class", "if", "elif", "else", "while", "for", "try", "except",
+                        "finally", "with",
+                    ]
+                    .contains(&t.text)
+            });
+        if compound {
+            return ContextKind::StatementStart;
+        }
+    }
+    if is_op(Some(last), ".") {
+        let start = primary_start(tokens, tokens.len() - 1);
+        if start < tokens.len() - 1 {
+            let receiver = tokens[start].span().cover(tokens[tokens.len() - 2].span());
+            return ContextKind::Attribute {
+                receiver,
+                expression: parse_expression(&code[receiver.start..receiver.end]).ok(),
+            };
+        }
+        return ContextKind::NoCompletion;
+    }
+    // The unclosed brackets, and the lambdas before their colon
+    let mut brackets: Vec<usize> = Vec::new();
+    let mut lambdas: Vec<usize> = Vec::new();
+    for (i, token) in tokens.iter().enumerate() {
+        match (token.kind, token.text) {
+            (TokenKind::Op, "(") | (TokenKind::Op, "[") | (TokenKind::Op, "{") => brackets.push(i),
+            (TokenKind::Op, ")") | (TokenKind::Op, "]") | (TokenKind::Op, "}") => {
+                if let Some(open) = brackets.pop() {
+                    lambdas.retain(|&lambda| lambda < open);
+                }
+            }
+            (TokenKind::Name, "lambda") => lambdas.push(i),
+            (TokenKind::Op, ":") => {
+                let depth_start = brackets.last().cloned();
+                if lambdas
+                    .last()
+                    .is_some_and(|&l| depth_start.map_or(true, |b| l > b))
+                {
+                    lambdas.pop();
+                }
+            }
+            _ => (),
+        }
+    }
+    let after_separator = ["(", ",", "*", "**"].iter().any(|op| is_op(Some(last), op))
+        || is_name(Some(last), "lambda");
+    let in_lambda = lambdas
+        .last()
+        .is_some_and(|&l| brackets.last().map_or(true, |&b| l > b));
+    if in_lambda && after_separator {
+        return ContextKind::Parameter;
+    }
+    let def = if is_name(tokens.first(), "async") {
+        1
+    } else {
+        0
+    };
+    let in_parameters = is_name(tokens.get(def), "def") && brackets == [def + 2];
+    if in_parameters && after_separator {
+        return ContextKind::Parameter;
+    }
+    ContextKind::Expression
+}
+
+/// Returns the context of the cursor at `offset` in `code`.
+///
+/// ```
+/// use python_parser::completion::{completion_context, ContextKind};
+/// use python_parser::parse_expression;
+///
+/// let code = "import os\nos.path.jo";
+/// let context = completion_context(code, code.len());
+/// assert_eq!(&code[context.prefix.start..context.prefix.end], "jo");
+/// match context.kind {
+///     ContextKind::Attribute { receiver, expression } => {
+///         assert_eq!(&code[receiver.start..receiver.end], "os.path");
+///         assert_eq!(expression, parse_expression("os.path").ok());
+///     }
+///     kind => panic!("{:?}", kind),
+/// }
+/// ```
+pub fn completion_context(code: &str, offset: usize) -> CompletionContext {
+    let before = &code[..offset];
+    let mut tokens: Vec<_> = tokenize(before)
+        .filter(|t| match t.kind {
+            TokenKind::Nl | TokenKind::Indent | TokenKind::Dedent | TokenKind::EndMarker => false,
+            // The newline added at the end of the code
+            TokenKind::Newline => !t.text.is_empty(),
+            _ => true,
+        })
+        .collect();
+    let mut prefix = Span::at(offset);
+    let last = tokens.last().cloned();
+    if let Some(last) = last.filter(|t| t.span().end == offset) {
+        match last.kind {
+            TokenKind::Name => {
+                prefix = last.span();
+                tokens.pop();
+            }
+            // An unterminated string is an error token
+            TokenKind::String | TokenKind::Comment | TokenKind::Number | TokenKind::Error => {
+                return CompletionContext {
+                    kind: ContextKind::NoCompletion,
+                    prefix,
+                };
+            }
+            _ => (),
+        }
+    }
+    tokens.retain(|t| t.kind != TokenKind::Comment);
+    let start = tokens
+        .iter()
+        .rposition(|t| t.kind == TokenKind::Newline || is_op(Some(t), ";"))
+        .map_or(0, |i| i + 1);
+    CompletionContext {
+        kind: statement_context(code, &tokens[start..]),
+        prefix,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns the context at the `|` in `code`.
+    fn context(code: &str) -> ContextKind {
+        let offset = code.find('|').unwrap();
+        let code = code.replace('|', "");
+        completion_context(&code, offset).kind
+    }
+
+    fn path(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn test_contexts() {
+        assert_eq!(context("x = 1\n|"), ContextKind::StatementStart);
+        assert_eq!(context("def f():\n    re|"), ContextKind::StatementStart);
+        assert_eq!(context("if x: re|"), ContextKind::StatementStart);
+        assert_eq!(context("x = 1; |"), ContextKind::StatementStart);
+        assert_eq!(context("x = f(a, |"), ContextKind::Expression);
+        assert_eq!(context("x = {'a': |"), ContextKind::Expression);
+        assert_eq!(context("x = '|"), ContextKind::NoCompletion);
+        assert_eq!(context("x = 1  # |"), ContextKind::NoCompletion);
+        assert_eq!(context("x = 1|"), ContextKind::NoCompletion);
+        assert_eq!(context("with f() as |"), ContextKind::NoCompletion);
+
+        let attribute = |code: &str, receiver: &str| match context(code) {
+            ContextKind::Attribute {
+                receiver: span,
+                expression,
+            } => {
+                let code = code.replace('|', "");
+                assert_eq!(&code[span.start..span.end], receiver);
+                expression.is_some()
+            }
+            kind => panic!("{:?}", kind),
+        };
+        assert!(attribute("y = f(x)[0].|", "f(x)[0]"));
+        assert!(attribute("y = [1, (a,\n b).c|", "(a,\n b)"));
+        assert!(attribute("y = 'a'.|", "'a'"));
+        assert!(!attribute("y = a.b + (1 +).|", "(1 +)"));
+    }
+
+    #[test]
+    fn test_imports() {
+        assert_eq!(
+            context("import os.pa|"),
+            ContextKind::ImportPath {
+                level: 0,
+                path: path(&["os"]),
+            }
+        );
+        assert_eq!(
+            context("import sys, |"),
+            ContextKind::ImportPath {
+                level: 0,
+                path: vec![],
+            }
+        );
+        assert_eq!(
+            context("from ..a.|"),
+            ContextKind::ImportPath {
+                level: 2,
+                path: path(&["a"]),
+            }
+        );
+        assert_eq!(
+            context("from os.path import (join, |"),
+            ContextKind::ImportName {
+                level: 0,
+                module: path(&["os", "path"]),
+            }
+        );
+        assert_eq!(context("import os as |"), ContextKind::NoCompletion);
+    }
+
+    #[test]
+    fn test_parameters() {
+        assert_eq!(context("def f(a, |"), ContextKind::Parameter);
+        assert_eq!(context("async def f(*|"), ContextKind::Parameter);
+        assert_eq!(context("def f(a=g(|"), ContextKind::Expression);
+        assert_eq!(context("def f(a: |"), ContextKind::Expression);
+        assert_eq!(context("f = lambda x, |"), ContextKind::Parameter);
+        assert_eq!(context("f = lambda x: |"), ContextKind::Expression);
+        assert_eq!(context("f = g(lambda: (|"), ContextKind::Expression);
+    }
+}
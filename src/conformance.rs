@@ -0,0 +1,270 @@
+//! A runner of the parser on all the Python files of a directory (eg. a
+//! checkout of CPython, or a corpus of user code), which reports the
+//! files it rejects, grouped by the construct of the rejected
+//! statements, to find the parts of the grammar which are not
+//! supported yet.
+//!
+//! Some files may be rejected on purpose (eg. the `badsyntax_*.py` tests
+//! of CPython); the report does not tell them apart.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use errors::ParseError;
+use json::Json;
+use keywords::{is_keyword, is_soft_keyword};
+use parse_module;
+use tokenizer::{tokenize, TokenKind};
+
+/// Version of Python whose keywords name the constructs, so that the
+/// statements of versions which are not parsed yet are named too.
+const CONSTRUCT_VERSION: (u8, u8) = (3, 13);
+
+/// A file rejected by the parser.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ConformanceFailure {
+    pub path: PathBuf,
+    pub error: ParseError,
+    /// The construct of the statement of the error (see
+    /// `failure_construct`).
+    pub construct: String,
+}
+
+/// The result of `run_conformance`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ConformanceReport {
+    /// Number of files parsed, including the rejected ones.
+    pub files: usize,
+    /// The rejected files, in the order of their paths.
+    pub failures: Vec<ConformanceFailure>,
+    /// Files which could not be read, or are not valid UTF-8.
+    pub unreadable: Vec<PathBuf>,
+}
+
+impl ConformanceReport {
+    /// Number of files accepted by the parser.
+    pub fn passed(&self) -> usize {
+        self.files - self.failures.len()
+    }
+
+    /// Returns the proportion of the files accepted by the parser, or 1
+    /// if there is none.
+    pub fn coverage(&self) -> f64 {
+        if self.files == 0 {
+            return 1.;
+        }
+        self.passed() as f64 / self.files as f64
+    }
+
+    /// Returns the number of failures of each construct.
+    pub fn by_construct(&self) -> BTreeMap<&str, usize> {
+        let mut constructs = BTreeMap::new();
+        for failure in &self.failures {
+            *constructs.entry(&failure.construct[..]).or_insert(0) += 1;
+        }
+        constructs
+    }
+
+    /// Returns the report as JSON, like
+    /// `{"files":10,"passed":9,"coverage":0.9,"constructs":{"match":1},
+    /// "failures":[{"path":"a.py","line":3,"construct":"match","error":"..."}],
+    /// "unreadable":[]}`.
+    pub fn to_json(&self) -> String {
+        let path = |path: &Path| Json::String(path.to_string_lossy().into_owned());
+        let constructs = self
+            .by_construct()
+            .into_iter()
+            .map(|(construct, count)| (construct.to_string(), Json::Number(count as f64)))
+            .collect();
+        let failures = self
+            .failures
+            .iter()
+            .map(|failure| {
+                Json::Object(vec![
+                    ("path".to_string(), path(&failure.path)),
+                    (
+                        "line".to_string(),
+                        Json::Number(f64::from(failure.error.line)),
+                    ),
+                    (
+                        "construct".to_string(),
+                        Json::String(failure.construct.clone()),
+                    ),
+                    ("error".to_string(), Json::String(failure.error.to_string())),
+                ])
+            })
+            .collect();
+        Json::Object(vec![
+            ("files".to_string(), Json::Number(self.files as f64)),
+            ("passed".to_string(), Json::Number(self.passed() as f64)),
+            ("coverage".to_string(), Json::Number(self.coverage())),
+            ("constructs".to_string(), Json::Object(constructs)),
+            ("failures".to_string(), Json::Array(failures)),
+            (
+                "unreadable".to_string(),
+                Json::Array(self.unreadable.iter().map(|p| path(p)).collect()),
+            ),
+        ])
+        .to_string()
+    }
+}
+
+/// Returns the construct of the logical line of `code` containing
+/// `offset`: its first keyword (eg. `match`, `def` or `async`),
+/// `decorator`, `named expression` if it has a `:=`, `f-string` if it
+/// has one, `assignment`, or `expression`.
+///
+/// ```
+/// use python_parser::conformance::failure_construct;
+///
+/// let code = "x = 1\nmatch x:\n    case 1:\n        pass\n";
+/// assert_eq!(failure_construct(code, 6), "match");
+/// assert_eq!(failure_construct(code, 0), "assignment");
+/// assert_eq!(failure_construct("print(f'{x!r:>{width}}')\n", 10), "f-string");
+/// ```
+pub fn failure_construct(code: &str, offset: usize) -> String {
+    let mut line = Vec::new();
+    for token in tokenize(code) {
+        if token.offset > offset && token.kind == TokenKind::Newline {
+            break;
+        }
+        match token.kind {
+            TokenKind::Newline | TokenKind::Indent | TokenKind::Dedent => line.clear(),
+            TokenKind::Comment | TokenKind::Nl => (),
+            _ => line.push(token),
+        }
+    }
+    match line.first() {
+        Some(first) if first.kind == TokenKind::Op && first.text == "@" => {
+            return "decorator".to_string()
+        }
+        Some(first)
+            if first.kind == TokenKind::Name
+                && (is_keyword(first.text, CONSTRUCT_VERSION)
+                    || is_soft_keyword(first.text, CONSTRUCT_VERSION)) =>
+        {
+            return first.text.to_string()
+        }
+        _ => (),
+    }
+    let has_op = |op: &str| {
+        line.iter()
+            .any(|token| token.kind == TokenKind::Op && token.text == op)
+    };
+    let has_fstring = line.iter().any(|token| {
+        let prefix = token.text.split(|c| c == '\'' || c == '"').next();
+        token.kind == TokenKind::String && prefix.map_or(false, |p| p.contains(&['f', 'F'][..]))
+    });
+    let construct = if has_op(":=") {
+        "named expression"
+    } else if has_fstring {
+        "f-string"
+    } else if has_op("=") {
+        "assignment"
+    } else {
+        "expression"
+    };
+    construct.to_string()
+}
+
+/// Adds the `.py` files of `dir` and of its subdirectories to `paths`.
+fn python_files(dir: &Path, paths: &mut Vec<PathBuf>) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            python_files(&path, paths)?;
+        } else if path.extension().map_or(false, |ext| ext == "py") {
+            paths.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Parses all the `.py` files of `dir` and of its subdirectories, and
+/// returns the report of the files the parser rejected.
+pub fn run_conformance<P: AsRef<Path>>(dir: P) -> io::Result<ConformanceReport> {
+    let mut paths = Vec::new();
+    python_files(dir.as_ref(), &mut paths)?;
+    paths.sort();
+    let mut report = ConformanceReport::default();
+    for path in paths {
+        let code = match fs::read_to_string(&path) {
+            Ok(code) => code,
+            Err(_) => {
+                report.unreadable.push(path);
+                continue;
+            }
+        };
+        report.files += 1;
+        if let Err(error) = parse_module(&code) {
+            report.failures.push(ConformanceFailure {
+                construct: failure_construct(&code, error.offset),
+                path,
+                error,
+            });
+        }
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::process;
+
+    #[test]
+    fn test_failure_construct() {
+        let code =
+            "@dec(\n    x)\ndef f():\n    y = (yield)\n    return [z := 1]\nasync with a: pass\n";
+        let construct = |needle: &str| failure_construct(code, code.find(needle).unwrap());
+        assert_eq!(construct("x)"), "decorator");
+        assert_eq!(construct("def"), "def");
+        assert_eq!(construct("yield"), "assignment");
+        assert_eq!(construct("z :="), "return");
+        assert_eq!(construct("with"), "async");
+        assert_eq!(failure_construct("f(x := 1)\n", 2), "named expression");
+        assert_eq!(failure_construct("f(x)\n", 2), "expression");
+    }
+
+    #[test]
+    fn test_run_conformance() {
+        let dir = env::temp_dir().join(format!("python-parser-conformance-{}", process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("pkg")).unwrap();
+        fs::write(dir.join("a.py"), "x = 1\n").unwrap();
+        fs::write(
+            dir.join("pkg/b.py"),
+            "match x:\n    case 1:\n        pass\n",
+        )
+        .unwrap();
+        fs::write(dir.join("pkg/c.py"), "def f(:\n    pass\n").unwrap();
+        fs::write(dir.join("pkg/d.py"), b"\xff\n").unwrap();
+        fs::write(dir.join("notes.txt"), "x = (\n").unwrap();
+
+        let report = run_conformance(&dir).unwrap();
+        assert_eq!(report.files, 3);
+        assert_eq!(report.passed(), 1);
+        let failures: Vec<_> = report
+            .failures
+            .iter()
+            .map(|f| (f.path.strip_prefix(&dir).unwrap(), &f.construct[..]))
+            .collect();
+        assert_eq!(
+            failures,
+            vec![
+                (Path::new("pkg/b.py"), "match"),
+                (Path::new("pkg/c.py"), "def")
+            ]
+        );
+        assert_eq!(report.unreadable, vec![dir.join("pkg/d.py")]);
+
+        let json = Json::parse(&report.to_json()).unwrap();
+        assert_eq!(json.get("passed"), Some(&Json::Number(1.)));
+        let constructs = json.get("constructs").unwrap();
+        assert_eq!(constructs.get("match"), Some(&Json::Number(1.)));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,103 @@
+//! Choice of what the string literals of the AST hold: their value, as
+//! evaluators want it, the text of the literal, as formatters want it
+//! to keep the prefix, quotes and escapes of the code, or both.
+//!
+//! The policy applies to the `ast::PyString`s built by the parsers
+//! called inside `with_string_decoding`. Bytes literals are always
+//! decoded, as `Expression::Bytes` only holds their value.
+
+use std::cell::Cell;
+
+/// What the `ast::PyString`s built by the parsers hold.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub enum StringDecoding {
+    /// The value of the literal in `content`, and no `raw` text.
+    #[default]
+    Decoded,
+    /// The text of the literal in `raw`, and an empty `content`.
+    Raw,
+    /// Both the value and the text of the literal.
+    Both,
+}
+
+thread_local! {
+    static DECODING: Cell<StringDecoding> = const { Cell::new(StringDecoding::Decoded) };
+}
+
+/// Restores the previous policy when dropped, even if the closure
+/// panics.
+struct Guard {
+    previous: StringDecoding,
+}
+
+impl Drop for Guard {
+    fn drop(&mut self) {
+        DECODING.with(|decoding| decoding.set(self.previous));
+    }
+}
+
+/// Runs `f`, making the parsers it calls on the current thread build
+/// string literals following `policy`.
+///
+/// ```
+/// use python_parser::ast::{Expression, Statement};
+/// use python_parser::decoding::{with_string_decoding, StringDecoding};
+/// use python_parser::parse_module;
+///
+/// let code = "x = r'\\d+' \"\\x41\"\n";
+/// let module = with_string_decoding(StringDecoding::Both, || parse_module(code)).unwrap();
+/// match module.body[0] {
+///     Statement::Assignment(_, ref values) => match values[0][0] {
+///         Expression::String(ref strings) => {
+///             assert_eq!(strings[0].raw.as_ref().unwrap(), "r'\\d+'");
+///             assert_eq!(strings[1].raw.as_ref().unwrap(), "\"\\x41\"");
+///             # #[cfg(not(feature = "wtf8"))]
+///             assert_eq!(strings[1].content, "A");
+///         }
+///         _ => unreachable!(),
+///     },
+///     _ => unreachable!(),
+/// }
+/// ```
+pub fn with_string_decoding<F, R>(policy: StringDecoding, f: F) -> R
+where
+    F: FnOnce() -> R,
+{
+    let previous = DECODING.with(|decoding| decoding.replace(policy));
+    let _guard = Guard { previous };
+    f()
+}
+
+/// Returns the policy of the current thread.
+pub(crate) fn string_decoding() -> StringDecoding {
+    DECODING.with(|decoding| decoding.get())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ast::*;
+    use parse_module;
+    use visitors::printer::format_module;
+
+    #[test]
+    fn test_raw() {
+        let code = "x = f'{a!r}' R\"\"\"\\n\"\"\"\n";
+        let module = with_string_decoding(StringDecoding::Raw, || parse_module(code)).unwrap();
+        match module.body[0] {
+            Statement::Assignment(_, ref values) => match values[0][0] {
+                Expression::String(ref strings) => {
+                    assert_eq!(strings[0].raw, Some("f'{a!r}'".to_string()));
+                    assert_eq!(strings[1].raw, Some("R\"\"\"\\n\"\"\"".to_string()));
+                    assert_eq!(strings[1].content.len(), 0);
+                }
+                _ => panic!("not a string"),
+            },
+            _ => panic!("not an assignment"),
+        }
+        // Formatters keep the literals as written
+        assert!(format_module(&module.body).contains("f'{a!r}' R\"\"\"\\n\"\"\""));
+        assert_eq!(string_decoding(), StringDecoding::Decoded);
+        assert_eq!(parse_module(code).unwrap().body.len(), 1);
+    }
+}
@@ -0,0 +1,809 @@
+//! Go-to-definition: resolving a name used in a module to the code
+//! binding it.
+//!
+//! The AST has no positions, so the scopes and the names they bind are
+//! found from the tokens of the code. Names are resolved like Python
+//! does: a name bound anywhere in a function is local to it, class
+//! bodies are not visible from the functions and comprehensions in
+//! them, and `global` and `nonlocal` declarations are followed.
+//! Attributes are not resolved.
+
+use std::collections::HashMap;
+
+use keywords::{is_keyword, PARSED_VERSION};
+use lint::{is_name, is_op, span_of, top_level};
+use span::{Span, Spanned};
+use tokenizer::{tokenize, Token, TokenKind};
+
+/// Keywords starting a compound statement.
+const COMPOUND: [&str; 11] = [
+    "def", "class", "if", "elif", "else", "while", "for", "try", "except", "finally", "with",
+];
+
+/// How a name is bound.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum BindingKind {
+    /// A parameter of a function or lambda.
+    Parameter,
+    /// An assignment target, a `for` or `with` target, an `except`
+    /// name or an assignment expression.
+    Variable,
+    Function,
+    Class,
+    Import,
+}
+
+/// A name bound in a module, found by `Bindings::new`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Binding<'a> {
+    pub name: &'a str,
+    pub kind: BindingKind,
+    /// The name in the code binding it.
+    pub span: Span,
+    /// The simple statement binding the name, or the header of the
+    /// compound statement.
+    pub statement: Span,
+}
+
+impl<'a> Spanned for Binding<'a> {
+    fn span(&self) -> Span {
+        self.span
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum ScopeKind {
+    Module,
+    Class,
+    /// A function or lambda.
+    Function,
+    Comprehension,
+}
+
+#[derive(Debug)]
+struct Scope<'a> {
+    kind: ScopeKind,
+    /// The code in the scope: from the colon of the header of a
+    /// function or class, or of a lambda.
+    span: Span,
+    parent: Option<usize>,
+    globals: Vec<&'a str>,
+    nonlocals: Vec<&'a str>,
+}
+
+/// Returns the index of the bracket closing the one at `tokens[open]`,
+/// or the length of `tokens` if it is not closed.
+pub(crate) fn closing(tokens: &[Token], open: usize) -> usize {
+    let mut depth = 0;
+    for (i, token) in tokens.iter().enumerate().skip(open) {
+        if token.kind != TokenKind::Op {
+            continue;
+        }
+        match token.text {
+            "(" | "[" | "{" => depth += 1,
+            ")" | "]" | "}" => {
+                depth -= 1;
+                if depth == 0 {
+                    return i;
+                }
+            }
+            _ => (),
+        }
+    }
+    tokens.len()
+}
+
+/// Returns the index of the first keyword `name` outside of brackets.
+pub(crate) fn top_level_keyword(tokens: &[Token], name: &str) -> Option<usize> {
+    let mut depth = 0usize;
+    for (i, token) in tokens.iter().enumerate() {
+        match (token.kind, token.text) {
+            (TokenKind::Op, "(") | (TokenKind::Op, "[") | (TokenKind::Op, "{") => depth += 1,
+            (TokenKind::Op, ")") | (TokenKind::Op, "]") | (TokenKind::Op, "}") => {
+                depth = depth.saturating_sub(1)
+            }
+            (TokenKind::Name, text) if depth == 0 && text == name => return Some(i),
+            _ => (),
+        }
+    }
+    None
+}
+
+/// Returns the index of the colon ending the header of a compound
+/// statement (not the one of a lambda in it).
+pub(crate) fn header_end(tokens: &[Token]) -> Option<usize> {
+    let mut depth = 0usize;
+    let mut lambdas = 0;
+    for (i, token) in tokens.iter().enumerate() {
+        match (token.kind, token.text) {
+            (TokenKind::Op, "(") | (TokenKind::Op, "[") | (TokenKind::Op, "{") => depth += 1,
+            (TokenKind::Op, ")") | (TokenKind::Op, "]") | (TokenKind::Op, "}") => {
+                depth = depth.saturating_sub(1)
+            }
+            (TokenKind::Name, "lambda") if depth == 0 => lambdas += 1,
+            (TokenKind::Op, ":") if depth == 0 => {
+                if lambdas == 0 {
+                    return Some(i);
+                }
+                lambdas -= 1;
+            }
+            _ => (),
+        }
+    }
+    None
+}
+
+/// Splits `tokens` at the operators `op` outside of brackets.
+pub(crate) fn split_top_level<'t, 'a>(tokens: &'t [Token<'a>], op: &str) -> Vec<&'t [Token<'a>]> {
+    let mut parts = Vec::new();
+    let mut rest = tokens;
+    while let Some(i) = top_level(rest, op) {
+        parts.push(&rest[..i]);
+        rest = &rest[i + 1..];
+    }
+    parts.push(rest);
+    parts
+}
+
+fn is_identifier(token: &Token) -> bool {
+    token.kind == TokenKind::Name && !is_keyword(token.text, PARSED_VERSION)
+}
+
+/// The names bound in a module, and its scopes.
+#[derive(Debug)]
+pub struct Bindings<'a> {
+    /// Tokens of the code, without comments, line breaks and
+    /// indentation.
+    tokens: Vec<Token<'a>>,
+    scopes: Vec<Scope<'a>>,
+    /// The bindings, with the index of their scope.
+    bindings: Vec<(usize, Binding<'a>)>,
+    /// Indexes of the bindings of each name.
+    names: HashMap<&'a str, Vec<usize>>,
+}
+
+impl<'a> Bindings<'a> {
+    pub fn new(code: &'a str) -> Bindings<'a> {
+        let mut bindings = Bindings {
+            tokens: Vec::new(),
+            scopes: Vec::new(),
+            bindings: Vec::new(),
+            names: HashMap::new(),
+        };
+        bindings.new_scope(ScopeKind::Module, Span::new(0, code.len()), None);
+        // For each indented block, its scope if it is the body of a
+        // function or class
+        let mut blocks: Vec<Option<usize>> = Vec::new();
+        let mut body: Option<usize> = None;
+        let mut line = Vec::new();
+        for token in tokenize(code) {
+            match token.kind {
+                TokenKind::Comment | TokenKind::Nl | TokenKind::EndMarker => (),
+                TokenKind::Indent => blocks.push(body.take()),
+                TokenKind::Dedent => {
+                    if let Some(Some(scope)) = blocks.pop() {
+                        let end = bindings.tokens.last().map_or(0, |t| t.span().end);
+                        bindings.scopes[scope].span.end = end;
+                    }
+                }
+                TokenKind::Newline => {
+                    let scope = blocks.iter().rev().flatten().next().cloned().unwrap_or(0);
+                    body = bindings.statement(&line, scope);
+                    bindings.tokens.append(&mut line);
+                }
+                _ => line.push(token),
+            }
+        }
+        bindings
+    }
+
+    fn new_scope(&mut self, kind: ScopeKind, span: Span, parent: Option<usize>) -> usize {
+        self.scopes.push(Scope {
+            kind,
+            span,
+            parent,
+            globals: Vec::new(),
+            nonlocals: Vec::new(),
+        });
+        self.scopes.len() - 1
+    }
+
+    fn bind(&mut self, mut scope: usize, token: &Token<'a>, kind: BindingKind, statement: Span) {
+        if self.scopes[scope].globals.contains(&token.text) {
+            scope = 0;
+        } else if self.scopes[scope].nonlocals.contains(&token.text) {
+            scope = self.enclosing_function(scope);
+        }
+        let binding = Binding {
+            name: token.text,
+            kind,
+            span: token.span(),
+            statement,
+        };
+        self.names
+            .entry(token.text)
+            .or_insert_with(Vec::new)
+            .push(self.bindings.len());
+        self.bindings.push((scope, binding));
+    }
+
+    /// Adds the bindings of a logical line in `scope`, and returns the
+    /// scope of its body if it is the header of a function or class
+    /// whose body is on the next lines.
+    fn statement(&mut self, tokens: &[Token<'a>], scope: usize) -> Option<usize> {
+        let tokens = if is_name(tokens.first(), "async") {
+            &tokens[1..]
+        } else {
+            tokens
+        };
+        let first = match tokens.first() {
+            Some(token) if token.kind == TokenKind::Name && COMPOUND.contains(&token.text) => {
+                token.text
+            }
+            Some(_) => {
+                for simple in split_top_level(tokens, ";") {
+                    self.simple_statement(simple, scope);
+                }
+                return None;
+            }
+            None => return None,
+        };
+        let colon = header_end(tokens).unwrap_or(tokens.len());
+        let (header, body) = (&tokens[..colon], tokens.get(colon + 1..).unwrap_or(&[]));
+        let statement = span_of(header);
+        let body_scope = match first {
+            "def" | "class" => {
+                let name = match tokens.get(1) {
+                    Some(name) if is_identifier(name) => name,
+                    _ => return None,
+                };
+                let kind = if first == "def" {
+                    BindingKind::Function
+                } else {
+                    BindingKind::Class
+                };
+                self.bind(scope, name, kind, statement);
+                // The parameters, their defaults and annotations, and the
+                // bases are not in the new scope
+                let start = tokens.get(colon).map_or(statement.end, |t| t.offset);
+                let end = span_of(tokens).end;
+                let body_scope = if first == "def" {
+                    let function =
+                        self.new_scope(ScopeKind::Function, Span::new(start, end), Some(scope));
+                    if is_op(tokens.get(2), "(") {
+                        let params = &header[3..closing(header, 2).min(header.len())];
+                        self.parameters(params, function, statement);
+                    }
+                    function
+                } else {
+                    self.expressions(&header[2..], scope, statement);
+                    self.new_scope(ScopeKind::Class, Span::new(start, end), Some(scope))
+                };
+                if body.is_empty() {
+                    return Some(body_scope);
+                }
+                self.scopes[body_scope].span.end = span_of(body).end;
+                body_scope
+            }
+            "for" => {
+                let in_ = top_level_keyword(header, "in").unwrap_or(header.len());
+                self.targets(&header[1..in_], scope, statement);
+                self.expressions(&header[in_..], scope, statement);
+                scope
+            }
+            "with" | "except" => {
+                for item in split_top_level(&header[1..], ",") {
+                    let as_ = top_level_keyword(item, "as").unwrap_or(item.len());
+                    self.expressions(&item[..as_], scope, statement);
+                    if as_ < item.len() {
+                        self.targets(&item[as_ + 1..], scope, statement);
+                    }
+                }
+                scope
+            }
+            _ => {
+                self.expressions(&header[1..], scope, statement);
+                scope
+            }
+        };
+        self.statement(body, body_scope);
+        None
+    }
+
+    /// Adds the bindings of a statement which is not compound.
+    fn simple_statement(&mut self, tokens: &[Token<'a>], scope: usize) {
+        let statement = span_of(tokens);
+        match tokens.first().map(|t| t.text) {
+            Some("import") => {
+                for name in split_top_level(&tokens[1..], ",") {
+                    // `import a.b` binds `a`, `import a.b as c` binds `c`
+                    let bound = if is_name(name.get(name.len().wrapping_sub(2)), "as") {
+                        name.last()
+                    } else {
+                        name.first()
+                    };
+                    if let Some(bound) = bound.filter(|t| is_identifier(t)) {
+                        self.bind(scope, bound, BindingKind::Import, statement);
+                    }
+                }
+            }
+            Some("from") => {
+                let import = top_level_keyword(tokens, "import").unwrap_or(tokens.len());
+                let names: Vec<_> = tokens[import..]
+                    .iter()
+                    .skip(1)
+                    .filter(|t| !is_op(Some(t), "(") && !is_op(Some(t), ")"))
+                    .cloned()
+                    .collect();
+                for name in names.split(|t| is_op(Some(t), ",")) {
+                    if let Some(bound) = name.last().filter(|t| is_identifier(t)) {
+                        self.bind(scope, bound, BindingKind::Import, statement);
+                    }
+                }
+            }
+            Some("global") | Some("nonlocal") => {
+                let names = tokens[1..].iter().filter(|t| is_identifier(t));
+                let scope = &mut self.scopes[scope];
+                if tokens[0].text == "global" {
+                    scope.globals.extend(names.map(|t| t.text));
+                } else {
+                    scope.nonlocals.extend(names.map(|t| t.text));
+                }
+            }
+            _ => {
+                // Indexes of the `=`, and of the first augmented
+                // assignment operator or annotation, outside of brackets
+                // and lambdas
+                let mut assignments = Vec::new();
+                let mut other = None;
+                let mut depth = 0usize;
+                for (i, token) in tokens.iter().enumerate() {
+                    match (token.kind, token.text) {
+                        (TokenKind::Op, "(") | (TokenKind::Op, "[") | (TokenKind::Op, "{") => {
+                            depth += 1
+                        }
+                        (TokenKind::Op, ")") | (TokenKind::Op, "]") | (TokenKind::Op, "}") => {
+                            depth = depth.saturating_sub(1)
+                        }
+                        (TokenKind::Name, "lambda") if depth == 0 => break,
+                        (TokenKind::Op, "=") if depth == 0 => assignments.push(i),
+                        (TokenKind::Op, op)
+                            if depth == 0
+                                && other.is_none()
+                                && (op == ":"
+                                    || (op.len() > 1
+                                        && op.ends_with('=')
+                                        && !["==", "<=", ">=", "!=", ":="].contains(&op))) =>
+                        {
+                            other = Some(i)
+                        }
+                        _ => (),
+                    }
+                }
+                let mut start = 0;
+                for &end in &assignments {
+                    // The first target may be annotated (`x: int = 1`)
+                    let end = other.filter(|&i| start == 0 && i < end).unwrap_or(end);
+                    self.targets(&tokens[start..end], scope, statement);
+                    start = end + 1;
+                }
+                if let (true, Some(end)) = (assignments.is_empty(), other) {
+                    self.targets(&tokens[..end], scope, statement);
+                }
+                self.expressions(tokens, scope, statement);
+            }
+        }
+    }
+
+    /// Adds the parameters of a function or lambda.
+    fn parameters(&mut self, tokens: &[Token<'a>], scope: usize, statement: Span) {
+        let mut depth = 0usize;
+        for (i, token) in tokens.iter().enumerate() {
+            match (token.kind, token.text) {
+                (TokenKind::Op, "(") | (TokenKind::Op, "[") | (TokenKind::Op, "{") => depth += 1,
+                (TokenKind::Op, ")") | (TokenKind::Op, "]") | (TokenKind::Op, "}") => {
+                    depth = depth.saturating_sub(1)
+                }
+                _ if depth == 0 && is_identifier(token) => {
+                    let previous = if i == 0 { None } else { tokens.get(i - 1) };
+                    if previous.is_none()
+                        || ["(", ",", "*", "**"].iter().any(|op| is_op(previous, op))
+                    {
+                        self.bind(scope, token, BindingKind::Parameter, statement);
+                    }
+                }
+                _ => (),
+            }
+        }
+    }
+
+    /// Adds the names bound by an assignment target (not the ones in
+    /// attributes, subscripts and calls).
+    fn targets(&mut self, tokens: &[Token<'a>], scope: usize, statement: Span) {
+        // Whether each open bracket is in a subscript or call
+        let mut brackets: Vec<bool> = Vec::new();
+        for (i, token) in tokens.iter().enumerate() {
+            let previous = if i == 0 { None } else { tokens.get(i - 1) };
+            match (token.kind, token.text) {
+                (TokenKind::Op, "(") | (TokenKind::Op, "[") | (TokenKind::Op, "{") => {
+                    let trailer = previous.is_some_and(|t| {
+                        is_identifier(t)
+                            || t.kind == TokenKind::String
+                            || [")", "]", "}"].contains(&t.text)
+                    });
+                    let in_trailer = brackets.last() == Some(&true);
+                    brackets.push(trailer || in_trailer);
+                }
+                (TokenKind::Op, ")") | (TokenKind::Op, "]") | (TokenKind::Op, "}") => {
+                    brackets.pop();
+                }
+                _ if is_identifier(token)
+                    && brackets.last() != Some(&true)
+                    && !is_op(previous, ".")
+                    && ![".", "(", "["]
+                        .iter()
+                        .any(|op| is_op(tokens.get(i + 1), op)) =>
+                {
+                    self.bind(scope, token, BindingKind::Variable, statement)
+                }
+                _ => (),
+            }
+        }
+    }
+
+    /// Returns the innermost function enclosing `scope`, or the module.
+    fn enclosing_function(&self, scope: usize) -> usize {
+        let mut parent = self.scopes[scope].parent;
+        while let Some(scope) = parent {
+            if self.scopes[scope].kind == ScopeKind::Function {
+                return scope;
+            }
+            parent = self.scopes[scope].parent;
+        }
+        0
+    }
+
+    /// Returns the scope where the assignment expressions in `scope`
+    /// bind their names (the function of a comprehension).
+    fn assignment_scope(&self, mut scope: usize) -> usize {
+        while self.scopes[scope].kind == ScopeKind::Comprehension {
+            scope = self.scopes[scope].parent.unwrap_or(0);
+        }
+        scope
+    }
+
+    /// Adds the names bound in the expressions of `tokens`: by
+    /// comprehensions, lambdas and assignment expressions.
+    fn expressions(&mut self, tokens: &[Token<'a>], scope: usize, statement: Span) {
+        let mut i = 0;
+        while i < tokens.len() {
+            let token = tokens[i];
+            match (token.kind, token.text) {
+                (TokenKind::Op, "(") | (TokenKind::Op, "[") | (TokenKind::Op, "{") => {
+                    let close = closing(tokens, i);
+                    let inner = &tokens[i + 1..close];
+                    let inner_scope = if top_level_keyword(inner, "for").is_some() {
+                        let span = span_of(&tokens[i..(close + 1).min(tokens.len())]);
+                        self.new_scope(ScopeKind::Comprehension, span, Some(scope))
+                    } else {
+                        scope
+                    };
+                    self.expressions(inner, inner_scope, statement);
+                    i = close + 1;
+                }
+                // A `for` of the comprehension of `scope`
+                (TokenKind::Name, "for") => {
+                    let in_ = i + top_level_keyword(&tokens[i..], "in").unwrap_or(tokens.len() - i);
+                    self.targets(&tokens[i + 1..in_], scope, statement);
+                    i = in_;
+                }
+                (TokenKind::Name, "lambda") => {
+                    let colon = i + top_level(&tokens[i..], ":").unwrap_or(tokens.len() - i);
+                    let end =
+                        colon + top_level(&tokens[colon..], ",").unwrap_or(tokens.len() - colon);
+                    let lambda = self.new_scope(
+                        ScopeKind::Function,
+                        span_of(&tokens[colon..end]),
+                        Some(scope),
+                    );
+                    self.parameters(&tokens[i + 1..colon], lambda, statement);
+                    if colon < end {
+                        self.expressions(&tokens[colon + 1..end], lambda, statement);
+                    }
+                    i = end;
+                }
+                _ if is_identifier(&token) && is_op(tokens.get(i + 1), ":=") => {
+                    let scope = self.assignment_scope(scope);
+                    self.bind(scope, &token, BindingKind::Variable, statement);
+                    i += 1;
+                }
+                _ => i += 1,
+            }
+        }
+    }
+
+    /// Returns the binding of the name at `offset` (or ending at it),
+    /// or `None` if it is not bound in the module (eg. a builtin) or
+    /// is an attribute or a keyword argument.
+    ///
+    /// When a name is bound several times in a scope, the last binding
+    /// before `offset` is returned, or the first one if there is none.
+    pub fn resolve(&self, offset: usize) -> Option<&Binding<'a>> {
+        // The token starting at `offset`, or the one before it
+        let after = self.tokens.partition_point(|t| t.offset <= offset);
+        let index = (after.saturating_sub(2)..after).rev().find(|&i| {
+            let token = &self.tokens[i];
+            is_identifier(token) && offset <= token.span().end
+        })?;
+        let token = &self.tokens[index];
+        let mut bindings = self.bindings_of(token.text);
+        if let Some(&(_, ref binding)) = bindings.find(|&&(_, ref b)| b.span == token.span()) {
+            return Some(binding);
+        }
+        let previous = if index == 0 {
+            None
+        } else {
+            self.tokens.get(index - 1)
+        };
+        let is_keyword_argument = is_op(self.tokens.get(index + 1), "=")
+            && (is_op(previous, "(") || is_op(previous, ","));
+        if is_op(previous, ".") || is_keyword_argument {
+            return None;
+        }
+        self.resolve_name(token.text, offset)
+    }
+
+    /// Returns the innermost scope containing `offset`.
+    pub(crate) fn scope_at(&self, offset: usize) -> usize {
+        self.scopes
+            .iter()
+            .rposition(|s| s.span.start <= offset && offset <= s.span.end)
+            .unwrap_or(0)
+    }
+
+    /// Returns the binding of `name` if it was used at `offset`.
+    pub(crate) fn resolve_name(&self, name: &str, offset: usize) -> Option<&Binding<'a>> {
+        self.lookup(name, self.scope_at(offset), offset)
+    }
+
+    /// Returns the binding of the `def` of the innermost function whose
+    /// body contains `offset` (maybe in a comprehension), or `None` if
+    /// it is in a lambda, or in the module or a class body.
+    pub(crate) fn function_at(&self, offset: usize) -> Option<&Binding<'a>> {
+        let mut scope = self.scope_at(offset);
+        while self.scopes[scope].kind == ScopeKind::Comprehension {
+            scope = self.scopes[scope].parent?;
+        }
+        if self.scopes[scope].kind != ScopeKind::Function {
+            return None;
+        }
+        // The header of the `def` ends before the colon starting the
+        // scope
+        let start = self.scopes[scope].span.start;
+        let colon = self.tokens.iter().position(|t| t.offset == start)?;
+        let header_end = self.tokens.get(colon.checked_sub(1)?)?.span().end;
+        self.bindings
+            .iter()
+            .map(|&(_, ref binding)| binding)
+            .find(|b| b.kind == BindingKind::Function && b.statement.end == header_end)
+    }
+
+    /// Whether `binding` binds a name in a function or lambda (and not in
+    /// a module, class or comprehension).
+    pub(crate) fn is_local(&self, binding: &Binding) -> bool {
+        self.bindings_of(binding.name).any(|&(scope, ref b)| {
+            b.span == binding.span && self.scopes[scope].kind == ScopeKind::Function
+        })
+    }
+
+    /// Returns the names referring to the same variable as the name at
+    /// `offset` (the bindings of the name in its scope, and the names
+    /// resolved to them), in the order of the code, or an empty list if
+    /// it is not bound in the module.
+    ///
+    /// ```
+    /// use python_parser::definition::Bindings;
+    ///
+    /// let code = "x = 1\ndef f(x):\n    return x\nx += f(x)\n";
+    /// let bindings = Bindings::new(code);
+    /// let starts: Vec<_> = bindings.references(0).iter().map(|s| s.start).collect();
+    /// assert_eq!(starts, vec![0, 29, 36]);
+    /// ```
+    pub fn references(&self, offset: usize) -> Vec<Span> {
+        let scope_of = |binding: &Binding| {
+            self.bindings_of(binding.name)
+                .find(|&&(_, ref b)| b.span == binding.span)
+                .map(|&(scope, _)| scope)
+        };
+        let (name, scope) = match self.resolve(offset) {
+            Some(binding) => (binding.name, scope_of(binding)),
+            None => return Vec::new(),
+        };
+        self.tokens
+            .iter()
+            .filter(|t| t.text == name && is_identifier(t))
+            .filter(|t| self.resolve(t.offset).map(&scope_of) == Some(scope))
+            .map(Token::span)
+            .collect()
+    }
+
+    /// Returns the bindings of `name`, with their scope.
+    fn bindings_of<'b>(
+        &'b self,
+        name: &str,
+    ) -> impl Iterator<Item = &'b (usize, Binding<'a>)> + 'b {
+        let indexes = self.names.get(name).map_or(&[][..], |indexes| &indexes[..]);
+        indexes.iter().map(move |&i| &self.bindings[i])
+    }
+
+    fn lookup(&self, name: &str, mut scope: usize, offset: usize) -> Option<&Binding<'a>> {
+        let mut innermost = true;
+        loop {
+            let current = &self.scopes[scope];
+            if scope != 0 && current.globals.contains(&name) {
+                scope = 0;
+                continue;
+            }
+            let visible = innermost || current.kind != ScopeKind::Class;
+            if visible && !current.nonlocals.contains(&name) {
+                let candidates: Vec<_> = self
+                    .bindings_of(name)
+                    .filter(|&&(s, _)| s == scope)
+                    .map(|&(_, ref binding)| binding)
+                    .collect();
+                let before = candidates.iter().rev().find(|b| b.span.start <= offset);
+                if let Some(binding) = before.or_else(|| candidates.first()) {
+                    return Some(binding);
+                }
+            }
+            innermost = false;
+            scope = current.parent?;
+        }
+    }
+}
+
+/// Returns the span of the definition of the name at `offset`: the name
+/// in the parameter, assignment, `def` or `class` statement binding
+/// it, or the whole import statement if it is imported.
+///
+/// ```
+/// use python_parser::definition::definition;
+/// use python_parser::span::Span;
+///
+/// let code = "from os import path\n\ndef f(x):\n    return path.join(x)\n";
+/// let use_of = |name, n| code.match_indices(name).nth(n).unwrap().0;
+/// assert_eq!(definition(code, use_of("x", 1)), Some(Span::new(27, 28)));
+/// assert_eq!(definition(code, use_of("path", 1)), Some(Span::new(0, 19)));
+/// // Attributes are not resolved
+/// assert_eq!(definition(code, use_of("join", 0)), None);
+/// ```
+pub fn definition(code: &str, offset: usize) -> Option<Span> {
+    let bindings = Bindings::new(code);
+    let binding = bindings.resolve(offset)?;
+    Some(match binding.kind {
+        BindingKind::Import => binding.statement,
+        _ => binding.span,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns the offset of the `n`-th token `name` of `code`.
+    fn offset(code: &str, name: &str, n: usize) -> usize {
+        tokenize(code)
+            .filter(|t| t.text == name)
+            .nth(n)
+            .unwrap()
+            .offset
+    }
+
+    /// Returns the line of the start of the definition of the `n`-th
+    /// token `name` of `code`, and its text.
+    fn definition_of<'a>(code: &'a str, name: &str, n: usize) -> Option<(usize, &'a str)> {
+        let span = definition(code, offset(code, name, n))?;
+        let line = code[..span.start].matches('\n').count() + 1;
+        Some((line, &code[span.start..span.end]))
+    }
+
+    #[test]
+    fn test_scopes() {
+        let code = "import os.path as p, sys
+x = 1
+class A:
+    x = 2
+    y = x
+    def f(self, z=x, *args):
+        return x + z + self.x
+    g = lambda x: [x for x in range(x)]
+
+def h():
+    global x
+    x = 3
+    def i():
+        nonlocal y
+        y = 4
+        print(y, sep=x)
+    y = 5
+    return i
+";
+        let import = Some((1, "import os.path as p, sys"));
+        assert_eq!(definition_of(code, "p", 0), import);
+        assert_eq!(definition_of(code, "sys", 0), import);
+        // In a class body, and the defaults of its methods
+        assert_eq!(definition_of(code, "x", 2), Some((4, "x")));
+        assert_eq!(definition_of(code, "x", 3), Some((4, "x")));
+        // Class bodies are not visible in methods
+        assert_eq!(definition_of(code, "x", 4), Some((2, "x")));
+        assert_eq!(definition_of(code, "z", 1), Some((6, "z")));
+        assert_eq!(definition_of(code, "self", 1), Some((6, "self")));
+        assert_eq!(definition_of(code, "x", 5), None);
+        // Lambdas and comprehensions
+        let span = |name, n| {
+            let start = offset(code, name, n);
+            Some(Span::new(start, start + 1))
+        };
+        assert_eq!(definition(code, offset(code, "x", 7)), span("x", 8));
+        assert_eq!(definition(code, offset(code, "x", 9)), span("x", 8));
+        assert_eq!(definition(code, offset(code, "x", 6)), span("x", 6));
+        // `global` and `nonlocal`
+        assert_eq!(definition_of(code, "x", 12), Some((12, "x")));
+        assert_eq!(definition_of(code, "y", 3), Some((15, "y")));
+        assert_eq!(definition_of(code, "sep", 0), None);
+        assert_eq!(definition_of(code, "print", 0), None);
+        assert_eq!(definition_of(code, "i", 1), Some((13, "i")));
+    }
+
+    #[test]
+    fn test_targets() {
+        let code = "a, (b, *c) = d[e] = f.g = h()
+for i, j in k: pass
+with l as (m, n), o: pass
+try: pass
+except E as p: pass
+if (q := 1): pass
+r: int = 1
+t += 1
+";
+        for name in "a b c i j m n p q r t".split(' ') {
+            let start = offset(code, name, 0);
+            assert_eq!(
+                definition(code, start),
+                Some(Span::new(start, start + 1)),
+                "{}",
+                name
+            );
+        }
+        for name in "d e f g h k l o E int".split(' ') {
+            assert_eq!(definition(code, offset(code, name, 0)), None, "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_references() {
+        let code = "x = 1
+class A:
+    x = 2
+    def f(self):
+        global x
+        x = x.x + A.x
+    def g(self, x=x):
+        return [x for x in self], x
+";
+        let bindings = Bindings::new(code);
+        let references = |n| -> Vec<_> {
+            bindings
+                .references(offset(code, "x", n))
+                .into_iter()
+                .map(|span| tokenize(code).position(|t| t.span() == span).unwrap())
+                .map(|i| tokenize(code).take(i).filter(|t| t.text == "x").count())
+                .collect()
+        };
+        assert_eq!(references(0), vec![0, 2, 3, 4]);
+        assert_eq!(references(1), vec![1, 8]);
+        assert_eq!(references(7), vec![7, 11]);
+        assert_eq!(references(9), vec![9, 10]);
+        assert_eq!(references(5), vec![]);
+    }
+}
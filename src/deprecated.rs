@@ -0,0 +1,494 @@
+//! Detection of the usages of deprecated or removed modules and
+//! functions of the standard library, with their replacements.
+//!
+//! Like `validate`, it works on the tokens of the code, because the AST
+//! has no positions. Names are resolved through the imports of the
+//! module (eg. `from asyncio import get_event_loop as loop`), ignoring
+//! scopes and reassignments.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use lint::{dotted_name, is_name, is_op, span_of};
+use span::{Span, Spanned};
+use tokenizer::{tokenize, Token, TokenKind};
+
+/// A deprecated module, function, class or constant.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct DeprecatedApi {
+    /// Qualified name, like `"imp"` or `"asyncio.get_event_loop"`.
+    pub name: String,
+    /// Version of Python that deprecated it.
+    pub since: (u8, u8),
+    /// Version of Python that removed it, if it did.
+    pub removed: Option<(u8, u8)>,
+    /// What to use instead, if there is something.
+    pub replacement: Option<String>,
+}
+
+/// Name, deprecation and removal versions, and replacement of an API.
+type Deprecation = (
+    &'static str,
+    (u8, u8),
+    Option<(u8, u8)>,
+    Option<&'static str>,
+);
+
+/// The deprecated modules and functions of the standard library known
+/// by `DeprecationTable::default()`.
+const BUILTIN_DEPRECATIONS: [Deprecation; 40] = [
+    ("aifc", (3, 11), Some((3, 13)), None),
+    ("asynchat", (3, 6), Some((3, 12)), Some("asyncio")),
+    (
+        "asyncio.coroutine",
+        (3, 8),
+        Some((3, 11)),
+        Some("async def"),
+    ),
+    (
+        "asyncio.get_event_loop",
+        (3, 10),
+        None,
+        Some("asyncio.get_running_loop"),
+    ),
+    ("asyncore", (3, 6), Some((3, 12)), Some("asyncio")),
+    ("audioop", (3, 11), Some((3, 13)), None),
+    (
+        "base64.decodestring",
+        (3, 1),
+        Some((3, 9)),
+        Some("base64.decodebytes"),
+    ),
+    (
+        "base64.encodestring",
+        (3, 1),
+        Some((3, 9)),
+        Some("base64.encodebytes"),
+    ),
+    ("binhex", (3, 9), Some((3, 11)), None),
+    ("cgi", (3, 11), Some((3, 13)), None),
+    ("cgitb", (3, 11), Some((3, 13)), None),
+    (
+        "collections.Callable",
+        (3, 3),
+        Some((3, 10)),
+        Some("collections.abc.Callable"),
+    ),
+    (
+        "collections.Iterable",
+        (3, 3),
+        Some((3, 10)),
+        Some("collections.abc.Iterable"),
+    ),
+    (
+        "collections.Mapping",
+        (3, 3),
+        Some((3, 10)),
+        Some("collections.abc.Mapping"),
+    ),
+    (
+        "collections.MutableMapping",
+        (3, 3),
+        Some((3, 10)),
+        Some("collections.abc.MutableMapping"),
+    ),
+    (
+        "collections.Sequence",
+        (3, 3),
+        Some((3, 10)),
+        Some("collections.abc.Sequence"),
+    ),
+    ("crypt", (3, 11), Some((3, 13)), Some("hashlib")),
+    (
+        "datetime.datetime.utcfromtimestamp",
+        (3, 12),
+        None,
+        Some("datetime.datetime.fromtimestamp"),
+    ),
+    (
+        "datetime.datetime.utcnow",
+        (3, 12),
+        None,
+        Some("datetime.datetime.now"),
+    ),
+    ("distutils", (3, 10), Some((3, 12)), Some("setuptools")),
+    ("formatter", (3, 4), Some((3, 10)), None),
+    ("imghdr", (3, 11), Some((3, 13)), None),
+    ("imp", (3, 4), Some((3, 12)), Some("importlib")),
+    (
+        "inspect.getargspec",
+        (3, 0),
+        Some((3, 11)),
+        Some("inspect.signature"),
+    ),
+    (
+        "locale.getdefaultlocale",
+        (3, 11),
+        None,
+        Some("locale.getlocale"),
+    ),
+    ("mailcap", (3, 11), Some((3, 13)), None),
+    ("nntplib", (3, 11), Some((3, 13)), None),
+    ("parser", (3, 9), Some((3, 10)), Some("ast")),
+    ("pipes", (3, 11), Some((3, 13)), Some("subprocess")),
+    (
+        "pkgutil.find_loader",
+        (3, 12),
+        Some((3, 14)),
+        Some("importlib.util.find_spec"),
+    ),
+    ("platform.linux_distribution", (3, 5), Some((3, 8)), None),
+    ("smtpd", (3, 6), Some((3, 12)), None),
+    ("sndhdr", (3, 11), Some((3, 13)), None),
+    (
+        "ssl.wrap_socket",
+        (3, 7),
+        Some((3, 12)),
+        Some("ssl.SSLContext.wrap_socket"),
+    ),
+    ("symbol", (3, 9), Some((3, 10)), None),
+    (
+        "sys.setcheckinterval",
+        (3, 2),
+        Some((3, 9)),
+        Some("sys.setswitchinterval"),
+    ),
+    ("telnetlib", (3, 11), Some((3, 13)), None),
+    (
+        "time.clock",
+        (3, 3),
+        Some((3, 8)),
+        Some("time.perf_counter"),
+    ),
+    ("unittest.makeSuite", (3, 11), Some((3, 13)), None),
+    (
+        "xml.etree.cElementTree",
+        (3, 3),
+        Some((3, 9)),
+        Some("xml.etree.ElementTree"),
+    ),
+];
+
+/// The deprecated APIs to look for.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DeprecationTable {
+    apis: HashMap<String, DeprecatedApi>,
+}
+
+impl DeprecationTable {
+    /// Returns a table without any API.
+    pub fn empty() -> DeprecationTable {
+        DeprecationTable {
+            apis: HashMap::new(),
+        }
+    }
+
+    /// Adds an API to the table, replacing the one with the same name.
+    pub fn add(&mut self, api: DeprecatedApi) {
+        self.apis.insert(api.name.clone(), api);
+    }
+
+    pub fn get(&self, name: &str) -> Option<&DeprecatedApi> {
+        self.apis.get(name)
+    }
+}
+
+/// The deprecated modules and functions of the standard library.
+impl Default for DeprecationTable {
+    fn default() -> DeprecationTable {
+        let mut table = DeprecationTable::empty();
+        for &(name, since, removed, replacement) in BUILTIN_DEPRECATIONS.iter() {
+            table.add(DeprecatedApi {
+                name: name.to_string(),
+                since,
+                removed,
+                replacement: replacement.map(str::to_string),
+            });
+        }
+        table
+    }
+}
+
+/// A usage of a deprecated API, found by `find_deprecated`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DeprecatedUsage<'a> {
+    pub api: &'a DeprecatedApi,
+    /// The code naming the API, like `imp` in `import imp`, or
+    /// `asyncio.get_event_loop` in `asyncio.get_event_loop()`.
+    pub span: Span,
+    /// Line of the start of the span, starting from 1.
+    pub line: u32,
+}
+
+impl<'a> fmt::Display for DeprecatedUsage<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let api = self.api;
+        write!(
+            f,
+            "'{}' is deprecated since Python {}.{}",
+            api.name, api.since.0, api.since.1
+        )?;
+        if let Some((major, minor)) = api.removed {
+            write!(f, " and removed in Python {}.{}", major, minor)?;
+        }
+        if let Some(ref replacement) = api.replacement {
+            write!(f, ", use '{}' instead", replacement)?;
+        }
+        write!(f, " at line {}", self.line)
+    }
+}
+
+impl<'a> Spanned for DeprecatedUsage<'a> {
+    fn span(&self) -> Span {
+        self.span
+    }
+}
+
+/// Returns the names of a dotted name, without the dots.
+fn names<'a>(dotted: &[Token<'a>]) -> Vec<&'a str> {
+    dotted.iter().step_by(2).map(|t| t.text).collect()
+}
+
+struct Finder<'a, 't> {
+    table: &'t DeprecationTable,
+    target: (u8, u8),
+    /// Qualified names of the names bound by the imports.
+    aliases: HashMap<&'a str, String>,
+    usages: Vec<DeprecatedUsage<'t>>,
+}
+
+impl<'a, 't> Finder<'a, 't> {
+    /// Reports the first deprecated API among `qualified` and its
+    /// prefixes longer than `skip` names, where `dotted` names the end of
+    /// `qualified` (its first name may stand for several, if it is an
+    /// alias).
+    fn check(&mut self, qualified: &[&str], dotted: &[Token], skip: usize) {
+        let first = qualified.len() + 1 - dotted.len().div_ceil(2);
+        for len in first.max(skip + 1)..=qualified.len() {
+            let name = qualified[..len].join(".");
+            let api = match self.table.get(&name) {
+                Some(api) if api.since <= self.target => api,
+                _ => continue,
+            };
+            let tokens = &dotted[..2 * (len - first) + 1];
+            self.usages.push(DeprecatedUsage {
+                api,
+                span: span_of(tokens),
+                line: tokens[0].line,
+            });
+            return;
+        }
+    }
+
+    /// Handles a simple statement (without the tokens of line breaks
+    /// and indentation).
+    fn statement(&mut self, tokens: &[Token<'a>]) {
+        if is_name(tokens.first(), "import") {
+            let mut i = 1;
+            loop {
+                let dotted = dotted_name(tokens, i);
+                if dotted.is_empty() {
+                    break;
+                }
+                let path = names(dotted);
+                self.check(&path, dotted, 0);
+                i += dotted.len();
+                if is_name(tokens.get(i), "as") {
+                    if let Some(alias) = tokens.get(i + 1) {
+                        self.aliases.insert(alias.text, path.join("."));
+                    }
+                    i += 2;
+                } else {
+                    self.aliases.insert(path[0], path[0].to_string());
+                }
+                if !is_op(tokens.get(i), ",") {
+                    break;
+                }
+                i += 1;
+            }
+        } else if is_name(tokens.first(), "from") {
+            // Relative imports are not resolved
+            let dotted = dotted_name(tokens, 1);
+            if dotted.is_empty() || !is_name(tokens.get(1 + dotted.len()), "import") {
+                return;
+            }
+            let module = names(dotted);
+            self.check(&module, dotted, 0);
+            let mut i = 2 + dotted.len();
+            while i < tokens.len() {
+                let token = tokens[i];
+                if token.kind == TokenKind::Name && token.text != "as" {
+                    let mut path = module.clone();
+                    path.push(token.text);
+                    // A deprecated module was already reported
+                    self.check(&path, &tokens[i..=i], module.len());
+                    let alias = if is_name(tokens.get(i + 1), "as") {
+                        i += 2;
+                        tokens.get(i).map_or(token.text, |t| t.text)
+                    } else {
+                        token.text
+                    };
+                    self.aliases.insert(alias, path.join("."));
+                }
+                i += 1;
+            }
+        } else {
+            let mut i = 0;
+            while i < tokens.len() {
+                let token = tokens[i];
+                let is_attribute = i > 0 && is_op(tokens.get(i - 1), ".");
+                let is_definition = i > 0
+                    && (is_name(tokens.get(i - 1), "def") || is_name(tokens.get(i - 1), "class"));
+                let qualified = match self.aliases.get(token.text) {
+                    Some(qualified)
+                        if token.kind == TokenKind::Name && !is_attribute && !is_definition =>
+                    {
+                        qualified.clone()
+                    }
+                    _ => {
+                        i += 1;
+                        continue;
+                    }
+                };
+                let dotted = dotted_name(tokens, i);
+                let mut path: Vec<&str> = qualified.split('.').collect();
+                let imported = path.len();
+                path.extend(names(dotted).into_iter().skip(1));
+                // The import of the name was already reported
+                self.check(&path, dotted, imported);
+                i += dotted.len();
+            }
+        }
+    }
+}
+
+/// Returns the usages of the APIs of `table` deprecated in the `target`
+/// version of Python (or before), in the order of the code: imports of
+/// deprecated modules and functions, and accesses to deprecated
+/// attributes of imported modules (like `asyncio.get_event_loop`).
+///
+/// Each deprecated API is reported where it is named first: a module
+/// where it is imported, and not where it is used.
+///
+/// ```
+/// use python_parser::deprecated::{find_deprecated, DeprecationTable};
+///
+/// let code = "import imp\nimport asyncio as aio\nloop = aio.get_event_loop()\n";
+/// let table = DeprecationTable::default();
+/// let usages = find_deprecated(code, &table, (3, 12));
+/// assert_eq!(usages.len(), 2);
+/// assert_eq!(usages[0].to_string(),
+///     "'imp' is deprecated since Python 3.4 and removed in Python 3.12, \
+///      use 'importlib' instead at line 1");
+/// assert_eq!(&code[usages[1].span.start..usages[1].span.end], "aio.get_event_loop");
+/// assert_eq!(find_deprecated(code, &table, (3, 4)).len(), 1);
+/// ```
+pub fn find_deprecated<'t>(
+    code: &str,
+    table: &'t DeprecationTable,
+    target: (u8, u8),
+) -> Vec<DeprecatedUsage<'t>> {
+    let mut finder = Finder {
+        table,
+        target,
+        aliases: HashMap::new(),
+        usages: Vec::new(),
+    };
+    let mut statement = Vec::new();
+    for token in tokenize(code) {
+        match token.kind {
+            TokenKind::Comment | TokenKind::Nl => (),
+            TokenKind::Newline | TokenKind::Indent | TokenKind::Dedent | TokenKind::EndMarker => {
+                finder.statement(&statement);
+                statement.clear();
+            }
+            TokenKind::Op if token.text == ";" => {
+                finder.statement(&statement);
+                statement.clear();
+            }
+            _ => statement.push(token),
+        }
+    }
+    finder.usages
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns the code, API name and line of the usages.
+    fn found(code: &str, target: (u8, u8)) -> Vec<(&str, String, u32)> {
+        let table = DeprecationTable::default();
+        find_deprecated(code, &table, target)
+            .iter()
+            .map(|u| (&code[u.span.start..u.span.end], u.api.name.clone(), u.line))
+            .collect()
+    }
+
+    fn expected<'a>(usages: &[(&'a str, &str, u32)]) -> Vec<(&'a str, String, u32)> {
+        usages
+            .iter()
+            .map(|&(text, name, line)| (text, name.to_string(), line))
+            .collect()
+    }
+
+    #[test]
+    fn test_imports() {
+        let code = "import os, distutils.core as core, xml.etree.cElementTree
+from collections import (Mapping as M,
+    OrderedDict)
+from imp import reload; from .imp import x
+from time import clock
+";
+        assert_eq!(
+            found(code, (3, 12)),
+            expected(&[
+                ("distutils", "distutils", 1),
+                ("xml.etree.cElementTree", "xml.etree.cElementTree", 1),
+                ("Mapping", "collections.Mapping", 2),
+                ("imp", "imp", 4),
+                ("clock", "time.clock", 5),
+            ])
+        );
+        assert_eq!(found(code, (3, 4)).len(), 4);
+    }
+
+    #[test]
+    fn test_usages() {
+        let code = "import asyncio, datetime as dt, imp
+from datetime import datetime
+asyncio.get_event_loop()
+x.asyncio.get_event_loop()
+dt.datetime.utcnow(datetime.utcnow())
+imp.reload(x)
+def asyncio(): '''asyncio.get_event_loop()''' # asyncio.coroutine
+";
+        assert_eq!(
+            found(code, (3, 12)),
+            expected(&[
+                ("imp", "imp", 1),
+                ("asyncio.get_event_loop", "asyncio.get_event_loop", 3),
+                ("dt.datetime.utcnow", "datetime.datetime.utcnow", 5),
+                ("datetime.utcnow", "datetime.datetime.utcnow", 5),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_custom_table() {
+        let mut table = DeprecationTable::empty();
+        table.add(DeprecatedApi {
+            name: "mylib.old".to_string(),
+            since: (3, 0),
+            removed: None,
+            replacement: Some("mylib.new".to_string()),
+        });
+        let code = "import mylib\nmylib.old()\nimport imp\n";
+        let usages = find_deprecated(code, &table, (3, 7));
+        assert_eq!(usages.len(), 1);
+        assert_eq!(
+            usages[0].to_string(),
+            "'mylib.old' is deprecated since Python 3.0, use 'mylib.new' instead at line 2"
+        );
+    }
+}
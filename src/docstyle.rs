@@ -0,0 +1,703 @@
+//! Parsing of docstrings written with one of the three major
+//! conventions (Google, NumPy and reStructuredText fields) into their
+//! sections, for documentation tools and for lint rules checking them
+//! against the signatures of the functions.
+
+use ast::{Funcdef, StarParams};
+use doctest::{clean_docstring, indentation};
+
+/// A docstring convention.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Convention {
+    /// Sections like `Args:`, with indented entries like
+    /// `x (int): The value.`
+    Google,
+    /// Sections titles underlined with dashes, with entries like
+    /// `x : int` followed by an indented description.
+    Numpy,
+    /// reStructuredText fields, like `:param int x: The value.`
+    Rest,
+}
+
+/// An entry of a section: a parameter, a returned value or an
+/// exception.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Field {
+    /// The name of the parameter (with its stars, like `*args`), of the
+    /// returned value if it is named (otherwise it is empty), or of the
+    /// exception.
+    pub name: String,
+    pub type_name: Option<String>,
+    pub description: String,
+}
+
+/// The sections of a docstring, found by `parse_sections`.
+#[derive(Clone, Debug, PartialEq, Eq, Default)]
+pub struct Sections {
+    /// The convention of the docstring, or `None` if it has no sections.
+    pub convention: Option<Convention>,
+    /// The first paragraph of the docstring.
+    pub summary: String,
+    /// The text between the summary and the first section.
+    pub description: String,
+    pub parameters: Vec<Field>,
+    /// The returned (or yielded) values.
+    pub returns: Vec<Field>,
+    pub raises: Vec<Field>,
+    /// The text of the examples section (not for reST, which has no
+    /// such section).
+    pub examples: String,
+}
+
+/// The kinds of sections.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum SectionKind {
+    Parameters,
+    Returns,
+    Raises,
+    Examples,
+    Other,
+}
+
+/// Returns the kind of a section, given its title.
+fn section_kind(title: &str) -> SectionKind {
+    match &title.to_lowercase()[..] {
+        "args" | "arguments" | "parameters" | "params" | "keyword args" | "keyword arguments"
+        | "other parameters" => SectionKind::Parameters,
+        "returns" | "return" | "yields" | "yield" => SectionKind::Returns,
+        "raises" | "raise" | "exceptions" => SectionKind::Raises,
+        "examples" | "example" => SectionKind::Examples,
+        _ => SectionKind::Other,
+    }
+}
+
+/// Titles of sections of the Google convention, which are not
+/// parameters, returns, raises or examples.
+const OTHER_GOOGLE_SECTIONS: [&str; 9] = [
+    "attributes",
+    "note",
+    "notes",
+    "references",
+    "see also",
+    "todo",
+    "warning",
+    "warnings",
+    "methods",
+];
+
+/// Reference fields of reST (with their synonyms).
+const REST_FIELDS: [&str; 14] = [
+    "param",
+    "parameter",
+    "arg",
+    "argument",
+    "key",
+    "keyword",
+    "type",
+    "returns",
+    "return",
+    "rtype",
+    "yields",
+    "raises",
+    "raise",
+    "except",
+];
+
+/// Returns the title of the Google section started by `line`, if it is
+/// one.
+fn google_title(line: &str) -> Option<&str> {
+    let title = line.strip_suffix(':')?;
+    let known = section_kind(title) != SectionKind::Other
+        || OTHER_GOOGLE_SECTIONS.contains(&&title.to_lowercase()[..]);
+    if indentation(line) == 0 && known {
+        Some(title)
+    } else {
+        None
+    }
+}
+
+/// Whether `lines[i]` is the title of a NumPy section, underlined by
+/// the next line.
+fn is_numpy_title(lines: &[&str], i: usize) -> bool {
+    let underline = match lines.get(i + 1) {
+        Some(line) => line.trim_end(),
+        None => return false,
+    };
+    !lines[i].trim().is_empty()
+        && indentation(lines[i]) == 0
+        && underline.len() >= 3
+        && underline.chars().all(|c| c == '-')
+}
+
+/// Returns the field name and argument of a reST field line, like
+/// `("param", "int x")` for `:param int x: The value.`, and the text
+/// after it.
+fn rest_field(line: &str) -> Option<(&str, &str, &str)> {
+    let rest = line.strip_prefix(':')?;
+    let end = rest.find(':')?;
+    let (head, text) = (&rest[..end], rest[end + 1..].trim());
+    let mut parts = head.splitn(2, ' ');
+    let name = parts.next()?;
+    if !REST_FIELDS.contains(&name) {
+        return None;
+    }
+    Some((name, parts.next().unwrap_or("").trim(), text))
+}
+
+/// Returns the convention of a (cleaned) docstring, or `None` if it
+/// has no sections.
+pub fn detect_convention(docstring: &str) -> Option<Convention> {
+    let lines: Vec<&str> = docstring.lines().collect();
+    if lines
+        .iter()
+        .any(|line| rest_field(line.trim_start()).is_some())
+    {
+        Some(Convention::Rest)
+    } else if (0..lines.len()).any(|i| is_numpy_title(&lines, i)) {
+        Some(Convention::Numpy)
+    } else if lines.iter().any(|line| google_title(line).is_some()) {
+        Some(Convention::Google)
+    } else {
+        None
+    }
+}
+
+/// Returns the lines of `lines` joined, without their common
+/// indentation and the blank lines at their ends.
+fn dedent(lines: &[&str]) -> String {
+    let margin = lines
+        .iter()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| indentation(line))
+        .min()
+        .unwrap_or(0);
+    let lines: Vec<&str> = lines
+        .iter()
+        .map(|line| {
+            if line.len() >= margin {
+                &line[margin..]
+            } else {
+                ""
+            }
+        })
+        .collect();
+    lines.join("\n").trim_matches('\n').to_string()
+}
+
+/// Splits the lines of a section into entries: a line without
+/// indentation, and the more indented lines after it.
+fn entries(lines: &[&str]) -> Vec<(String, String)> {
+    let body = dedent(lines);
+    let body: Vec<&str> = body.lines().collect();
+    let mut entries = Vec::new();
+    let mut start: Option<usize> = None;
+    for i in 0..body.len() + 1 {
+        let is_start = body.get(i).map_or(true, |line| {
+            !line.trim().is_empty() && indentation(line) == 0
+        });
+        if is_start {
+            if let Some(start) = start {
+                entries.push((body[start].trim().to_string(), dedent(&body[start + 1..i])));
+            }
+            start = Some(i);
+        }
+    }
+    entries
+}
+
+/// Returns the index of the first `:` of `text` outside of brackets.
+fn top_level_colon(text: &str) -> Option<usize> {
+    let mut depth = 0usize;
+    for (i, c) in text.char_indices() {
+        match c {
+            '(' | '[' | '{' => depth += 1,
+            ')' | ']' | '}' => depth = depth.saturating_sub(1),
+            ':' if depth == 0 => return Some(i),
+            _ => (),
+        }
+    }
+    None
+}
+
+/// Joins the first line of a description and the next ones.
+fn join(first: &str, rest: String) -> String {
+    match (first.is_empty(), rest.is_empty()) {
+        (true, _) => rest,
+        (false, true) => first.to_string(),
+        (false, false) => format!("{}\n{}", first, rest),
+    }
+}
+
+/// Returns whether `text` looks like a type: no spaces outside of
+/// brackets, except after commas.
+fn is_type(text: &str) -> bool {
+    let mut depth = 0usize;
+    let mut previous = ' ';
+    for c in text.chars() {
+        match c {
+            '(' | '[' | '{' => depth += 1,
+            ')' | ']' | '}' => depth = depth.saturating_sub(1),
+            ' ' if depth == 0 && previous != ',' => return false,
+            _ => (),
+        }
+        previous = c;
+    }
+    !text.is_empty()
+}
+
+/// Adds a Google entry to `sections`.
+fn google_entry(sections: &mut Sections, kind: SectionKind, head: &str, rest: String) {
+    let colon = top_level_colon(head);
+    let (name, text) = match colon {
+        Some(colon) => (head[..colon].trim(), head[colon + 1..].trim()),
+        None => ("", head),
+    };
+    match kind {
+        SectionKind::Parameters => {
+            let (name, type_name) = match (name.find('('), name.ends_with(')')) {
+                (Some(open), true) => (
+                    name[..open].trim(),
+                    Some(name[open + 1..name.len() - 1].to_string()),
+                ),
+                _ => (name, None),
+            };
+            sections.parameters.push(Field {
+                name: name.to_string(),
+                type_name,
+                description: join(text, rest),
+            });
+        }
+        SectionKind::Returns => {
+            let (type_name, text) = if is_type(name) {
+                (Some(name.to_string()), text)
+            } else {
+                (None, head)
+            };
+            sections.returns.push(Field {
+                name: String::new(),
+                type_name,
+                description: join(text, rest),
+            });
+        }
+        SectionKind::Raises => sections.raises.push(Field {
+            name: name.to_string(),
+            type_name: None,
+            description: join(text, rest),
+        }),
+        SectionKind::Examples | SectionKind::Other => (),
+    }
+}
+
+/// Adds a NumPy entry to `sections`.
+fn numpy_entry(sections: &mut Sections, kind: SectionKind, head: &str, rest: String) {
+    let (names, type_name) = match head.find(" :") {
+        Some(colon) => {
+            let type_name = head[colon + 2..].trim();
+            let type_name = if type_name.is_empty() {
+                None
+            } else {
+                Some(type_name.to_string())
+            };
+            (head[..colon].trim(), type_name)
+        }
+        None => (head.trim(), None),
+    };
+    match kind {
+        SectionKind::Parameters => {
+            // `x, y : int` documents both parameters
+            for name in names.split(',').map(str::trim).filter(|n| !n.is_empty()) {
+                sections.parameters.push(Field {
+                    name: name.to_string(),
+                    type_name: type_name.clone(),
+                    description: rest.clone(),
+                });
+            }
+        }
+        // An unnamed returned value only has a type
+        SectionKind::Returns => sections.returns.push(match type_name {
+            Some(type_name) => Field {
+                name: names.to_string(),
+                type_name: Some(type_name),
+                description: rest,
+            },
+            None => Field {
+                name: String::new(),
+                type_name: Some(names.to_string()),
+                description: rest,
+            },
+        }),
+        SectionKind::Raises => sections.raises.push(Field {
+            name: names.to_string(),
+            type_name: None,
+            description: rest,
+        }),
+        SectionKind::Examples | SectionKind::Other => (),
+    }
+}
+
+/// Adds the sections of a docstring with the Google or NumPy
+/// convention to `sections`, and returns the index of the line of the
+/// first section.
+fn parse_titled_sections(lines: &[&str], sections: &mut Sections) -> usize {
+    let numpy = sections.convention == Some(Convention::Numpy);
+    // The titles of the sections, with the index of their first line
+    let titles: Vec<(usize, &str, usize)> = (0..lines.len())
+        .filter_map(|i| {
+            if numpy && is_numpy_title(lines, i) {
+                Some((i, lines[i].trim(), i + 2))
+            } else if !numpy {
+                google_title(lines[i]).map(|title| (i, title, i + 1))
+            } else {
+                None
+            }
+        })
+        .collect();
+    for (n, &(_, title, start)) in titles.iter().enumerate() {
+        let end = titles.get(n + 1).map_or(lines.len(), |&(next, _, _)| next);
+        let kind = section_kind(title);
+        let body = &lines[start.min(end)..end];
+        if kind == SectionKind::Examples {
+            sections.examples = dedent(body);
+            continue;
+        }
+        for (head, rest) in entries(body) {
+            if numpy {
+                numpy_entry(sections, kind, &head, rest);
+            } else {
+                google_entry(sections, kind, &head, rest);
+            }
+        }
+    }
+    titles.first().map_or(lines.len(), |&(i, _, _)| i)
+}
+
+/// Adds the fields of a docstring with the reST convention to
+/// `sections`, and returns the index of the line of the first field.
+fn parse_rest_fields(lines: &[&str], sections: &mut Sections) -> usize {
+    let first = lines
+        .iter()
+        .position(|line| rest_field(line).is_some())
+        .unwrap_or(lines.len());
+    let mut types = Vec::new();
+    let mut return_type = None;
+    for (head, rest) in entries(&lines[first..]) {
+        let (field, argument, text) = match rest_field(&head) {
+            Some(field) => field,
+            None => continue,
+        };
+        let description = join(text, rest);
+        match field {
+            "param" | "parameter" | "arg" | "argument" | "key" | "keyword" => {
+                // `:param int x:` has the type before the name
+                let (type_name, name) = match argument.rfind(' ') {
+                    Some(space) => (Some(argument[..space].to_string()), &argument[space + 1..]),
+                    None => (None, argument),
+                };
+                sections.parameters.push(Field {
+                    name: name.to_string(),
+                    type_name,
+                    description,
+                });
+            }
+            "type" => types.push((argument.to_string(), description)),
+            "returns" | "return" | "yields" => sections.returns.push(Field {
+                name: String::new(),
+                type_name: None,
+                description,
+            }),
+            "rtype" => return_type = Some(description),
+            _ => sections.raises.push(Field {
+                name: argument.to_string(),
+                type_name: None,
+                description,
+            }),
+        }
+    }
+    for (name, type_name) in types {
+        if let Some(parameter) = sections.parameters.iter_mut().find(|p| p.name == name) {
+            parameter.type_name = Some(type_name);
+        }
+    }
+    if let Some(return_type) = return_type {
+        if sections.returns.is_empty() {
+            sections.returns.push(Field {
+                name: String::new(),
+                type_name: None,
+                description: String::new(),
+            });
+        }
+        sections.returns[0].type_name = Some(return_type);
+    }
+    first
+}
+
+/// Returns the sections of a docstring (as written in the code, or
+/// cleaned by `doctest::clean_docstring`).
+///
+/// ```
+/// use python_parser::docstyle::{parse_sections, Convention};
+///
+/// let docstring = "Adds numbers.
+///
+///     Args:
+///         x (int): The first number.
+///         y: The second one,
+///             added to x.
+///
+///     Returns:
+///         int: The sum.
+///     ";
+/// let sections = parse_sections(docstring);
+/// assert_eq!(sections.convention, Some(Convention::Google));
+/// assert_eq!(sections.summary, "Adds numbers.");
+/// assert_eq!(sections.parameters[0].type_name.as_ref().unwrap(), "int");
+/// assert_eq!(sections.parameters[1].description, "The second one,\nadded to x.");
+/// assert_eq!(sections.returns[0].description, "The sum.");
+/// ```
+pub fn parse_sections(docstring: &str) -> Sections {
+    let docstring = clean_docstring(docstring);
+    let lines: Vec<&str> = docstring.lines().collect();
+    let mut sections = Sections {
+        convention: detect_convention(&docstring),
+        ..Sections::default()
+    };
+    let first_section = match sections.convention {
+        Some(Convention::Rest) => parse_rest_fields(&lines, &mut sections),
+        Some(_) => parse_titled_sections(&lines, &mut sections),
+        None => lines.len(),
+    };
+    let head = &lines[..first_section];
+    let summary_end = head
+        .iter()
+        .position(|line| line.trim().is_empty())
+        .unwrap_or(head.len());
+    sections.summary = head[..summary_end].join("\n");
+    sections.description = dedent(&head[summary_end..]);
+    sections
+}
+
+/// A difference between the parameters of a function and the ones
+/// documented in its docstring, found by `check_parameters`.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum ParameterMismatch {
+    /// A parameter of the function which is not documented.
+    Undocumented(String),
+    /// A documented parameter which is not one of the function.
+    Unknown(String),
+}
+
+/// Returns the differences between the parameters of `function` and the
+/// ones documented in `sections`, in the order of the signature, then of
+/// the docstring. `self` and `cls` need not be documented, and nothing
+/// is reported if no parameter is documented.
+///
+/// ```
+/// use python_parser::ast::{CompoundStatement, Statement};
+/// use python_parser::docstyle::{check_parameters, parse_sections, ParameterMismatch};
+/// use python_parser::parse_module;
+///
+/// let module = parse_module("def f(self, x, *args):\n    pass\n").unwrap();
+/// let function = match module.body[0] {
+///     Statement::Compound(ref compound) => match **compound {
+///         CompoundStatement::Funcdef(ref function) => function.clone(),
+///         _ => unreachable!(),
+///     },
+///     _ => unreachable!(),
+/// };
+/// let sections = parse_sections(":param x: X.\n:param y: Y.");
+/// assert_eq!(
+///     check_parameters(&function, &sections),
+///     vec![
+///         ParameterMismatch::Undocumented("*args".to_string()),
+///         ParameterMismatch::Unknown("y".to_string()),
+///     ]
+/// );
+/// ```
+pub fn check_parameters(function: &Funcdef, sections: &Sections) -> Vec<ParameterMismatch> {
+    if sections.parameters.is_empty() {
+        return Vec::new();
+    }
+    let parameters = &function.parameters;
+    let mut names: Vec<String> = parameters
+        .posonly_args
+        .iter()
+        .chain(&parameters.args)
+        .map(|&(ref name, _, _)| name.clone())
+        .collect();
+    if let StarParams::Named((ref name, _)) = parameters.star_args {
+        names.push(format!("*{}", name));
+    }
+    names.extend(
+        parameters
+            .keyword_args
+            .iter()
+            .map(|&(ref name, _, _)| name.clone()),
+    );
+    if let Some((ref name, _)) = parameters.star_kwargs {
+        names.push(format!("**{}", name));
+    }
+    let documented: Vec<&str> = sections
+        .parameters
+        .iter()
+        // NumPy docstrings may escape the stars, like `\\*args`
+        .map(|p| p.name.trim_start_matches('\\'))
+        .collect();
+    // `*args` may be documented as `args` or `*args`
+    let is_documented = |name: &str| {
+        documented
+            .iter()
+            .any(|d| d.trim_start_matches('*') == name.trim_start_matches('*'))
+    };
+    let mut mismatches: Vec<_> = names
+        .iter()
+        .filter(|name| *name != "self" && *name != "cls" && !is_documented(name))
+        .map(|name| ParameterMismatch::Undocumented(name.clone()))
+        .collect();
+    mismatches.extend(
+        documented
+            .iter()
+            .filter(|d| {
+                !names
+                    .iter()
+                    .any(|n| n.trim_start_matches('*') == d.trim_start_matches('*'))
+            })
+            .map(|d| ParameterMismatch::Unknown(d.to_string())),
+    );
+    mismatches
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn field(name: &str, type_name: Option<&str>, description: &str) -> Field {
+        Field {
+            name: name.to_string(),
+            type_name: type_name.map(str::to_string),
+            description: description.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_google() {
+        let sections = parse_sections(
+            "Reads a file.
+
+            Long description,
+            on two lines.
+
+            Args:
+                path (str): Path of
+                    the file.
+                mode (Dict[str, int], optional): The mode.
+                **kwargs: Other options.
+
+            Returns:
+                The contents.
+
+            Raises:
+                IOError: If it cannot be read.
+
+            Examples:
+                >>> read('x')
+                'contents'
+            ",
+        );
+        assert_eq!(sections.convention, Some(Convention::Google));
+        assert_eq!(sections.summary, "Reads a file.");
+        assert_eq!(sections.description, "Long description,\non two lines.");
+        assert_eq!(
+            sections.parameters,
+            vec![
+                field("path", Some("str"), "Path of\nthe file."),
+                field("mode", Some("Dict[str, int], optional"), "The mode."),
+                field("**kwargs", None, "Other options."),
+            ]
+        );
+        assert_eq!(sections.returns, vec![field("", None, "The contents.")]);
+        assert_eq!(
+            sections.raises,
+            vec![field("IOError", None, "If it cannot be read.")]
+        );
+        assert_eq!(sections.examples, ">>> read('x')\n'contents'");
+    }
+
+    #[test]
+    fn test_numpy() {
+        let sections = parse_sections(
+            "Reads a file.
+
+            Parameters
+            ----------
+            path, alt : str
+                Paths of the file.
+            mode
+                The mode.
+
+            Returns
+            -------
+            str
+                The contents.
+            size : int
+
+            Raises
+            ------
+            IOError
+            ",
+        );
+        assert_eq!(sections.convention, Some(Convention::Numpy));
+        assert_eq!(sections.description, "");
+        assert_eq!(
+            sections.parameters,
+            vec![
+                field("path", Some("str"), "Paths of the file."),
+                field("alt", Some("str"), "Paths of the file."),
+                field("mode", None, "The mode."),
+            ]
+        );
+        assert_eq!(
+            sections.returns,
+            vec![
+                field("", Some("str"), "The contents."),
+                field("size", Some("int"), ""),
+            ]
+        );
+        assert_eq!(sections.raises, vec![field("IOError", None, "")]);
+    }
+
+    #[test]
+    fn test_rest() {
+        let sections = parse_sections(
+            "Reads a file.
+
+            :param str path: Path of
+                the file.
+            :param mode: The mode.
+            :type mode: int
+            :returns: The contents.
+            :rtype: str
+            :raises IOError: If it cannot be read.
+            ",
+        );
+        assert_eq!(sections.convention, Some(Convention::Rest));
+        assert_eq!(
+            sections.parameters,
+            vec![
+                field("path", Some("str"), "Path of\nthe file."),
+                field("mode", Some("int"), "The mode."),
+            ]
+        );
+        assert_eq!(
+            sections.returns,
+            vec![field("", Some("str"), "The contents.")]
+        );
+        assert_eq!(
+            sections.raises,
+            vec![field("IOError", None, "If it cannot be read.")]
+        );
+
+        let sections = parse_sections("Does nothing.\n\nReally.");
+        assert_eq!(sections.convention, None);
+        assert_eq!(sections.description, "Really.");
+    }
+}
@@ -0,0 +1,374 @@
+//! Extraction of docstrings, and of the examples of doctests (`>>> `
+//! lines and their expected output) they contain, for tools running or
+//! checking doctests.
+
+use ast::*;
+use errors::ParseError;
+use interactive::parse_command;
+
+/// The docstring of a module, class or function.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Docstring {
+    /// Dotted name of the class or function in the module (eg.
+    /// `"Class.method"`), or `""` for the module.
+    pub name: String,
+    pub text: String,
+}
+
+/// An example of a doctest.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Example {
+    /// Code of the example, without its prompts and indentation, and
+    /// ending with a line break.
+    pub source: String,
+    /// The statements of the source, parsed like a command typed in the
+    /// interactive interpreter (see `interactive::parse_command`).
+    pub statements: Result<Vec<Statement>, ParseError>,
+    /// Expected output, with each line ending with a line break, or an
+    /// empty string if the example has no output.
+    pub want: String,
+    /// Line of the `>>>` prompt in the docstring, from 0 (like doctest's
+    /// `Example.lineno`).
+    pub line: usize,
+    /// Indentation of the prompt.
+    pub indent: usize,
+}
+
+/// The docstring of a module, class or function, with its examples.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Doctest {
+    pub docstring: Docstring,
+    pub examples: Vec<Example>,
+}
+
+#[cfg(feature = "wtf8")]
+fn content_to_string(content: &PyStringContent) -> String {
+    content.to_string_lossy().into_owned()
+}
+
+#[cfg(not(feature = "wtf8"))]
+fn content_to_string(content: &PyStringContent) -> String {
+    content.clone()
+}
+
+/// Returns the docstring of a module, class or function, given its
+/// body: the string literal of its first statement.
+pub fn docstring(body: &[Statement]) -> Option<String> {
+    match body.first() {
+        Some(Statement::Assignment(lhs, rhs)) if rhs.is_empty() => match lhs[..] {
+            [Expression::String(ref strings)] => {
+                if strings.iter().any(|s| s.prefix.contains(&['f', 'F'][..])) {
+                    return None; // f-strings are not docstrings
+                }
+                Some(
+                    strings
+                        .iter()
+                        .map(|s| content_to_string(&s.content))
+                        .collect(),
+                )
+            }
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Adds the docstrings of the classes and functions of `body` (a module
+/// or class body) to `docstrings`, with `prefix` before their names.
+fn add_docstrings(body: &[Statement], prefix: &str, docstrings: &mut Vec<Docstring>) {
+    for stmt in body {
+        let compound = match *stmt {
+            Statement::Compound(ref compound) => compound,
+            _ => continue,
+        };
+        let (name, code, is_class) = match **compound {
+            CompoundStatement::Funcdef(ref funcdef) => (&funcdef.name, &funcdef.code, false),
+            CompoundStatement::Classdef(ref classdef) => (&classdef.name, &classdef.code, true),
+            _ => continue,
+        };
+        let name = format!("{}{}", prefix, name);
+        if let Some(text) = docstring(code) {
+            docstrings.push(Docstring {
+                name: name.clone(),
+                text,
+            });
+        }
+        // Like doctest, only methods of classes are searched, not
+        // nested functions
+        if is_class {
+            add_docstrings(code, &format!("{}.", name), docstrings);
+        }
+    }
+}
+
+/// Returns the docstrings of a module, given its body, and of its
+/// classes, methods and functions, in the order of the code.
+pub fn docstrings(body: &[Statement]) -> Vec<Docstring> {
+    let mut docstrings = Vec::new();
+    if let Some(text) = docstring(body) {
+        docstrings.push(Docstring {
+            name: String::new(),
+            text,
+        });
+    }
+    add_docstrings(body, "", &mut docstrings);
+    docstrings
+}
+
+/// Returns a docstring without its indentation and its blank lines at
+/// the start and end, like Python's `inspect.cleandoc`: the first line
+/// is stripped, and the common indentation of the other lines is
+/// removed.
+///
+/// ```
+/// use python_parser::doctest::clean_docstring;
+/// let docstring = "  Adds numbers.\n\n    Example:\n        add(1, 2)\n    ";
+/// assert_eq!(clean_docstring(docstring), "Adds numbers.\n\nExample:\n    add(1, 2)");
+/// ```
+pub fn clean_docstring(docstring: &str) -> String {
+    let docstring = expand_tabs(docstring);
+    let mut lines: Vec<&str> = docstring.lines().collect();
+    let margin = lines
+        .iter()
+        .skip(1)
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| indentation(line))
+        .min()
+        .unwrap_or(0);
+    for (i, line) in lines.iter_mut().enumerate() {
+        *line = if i == 0 {
+            line.trim_start()
+        } else if indentation(line) >= margin {
+            &line[margin..]
+        } else {
+            line.trim_start()
+        };
+    }
+    while lines.last().is_some_and(|line| line.trim().is_empty()) {
+        lines.pop();
+    }
+    let start = lines
+        .iter()
+        .position(|line| !line.trim().is_empty())
+        .unwrap_or(lines.len());
+    lines[start..].join("\n")
+}
+
+/// Returns the number of spaces at the start of `line`.
+pub(crate) fn indentation(line: &str) -> usize {
+    line.len() - line.trim_start_matches(' ').len()
+}
+
+/// Returns the code after the prompt `prompt` (`>>>` or `...`) at
+/// `indent`, if `line` has it.
+fn after_prompt<'a>(line: &'a str, indent: usize, prompt: &str) -> Option<&'a str> {
+    if indentation(line) < indent {
+        return None;
+    }
+    let rest = line[indent..].strip_prefix(prompt)?;
+    // The prompt must be followed by a space, unless the line ends
+    match rest.strip_prefix(' ') {
+        Some(code) => Some(code),
+        None if rest.trim_end().is_empty() => Some(""),
+        None => None,
+    }
+}
+
+/// Returns the examples of a docstring, like doctest's `DocTestParser`:
+/// each example starts with a line whose code is after a `>>> ` prompt,
+/// continued by the lines with a `... ` prompt, and followed by the
+/// lines of its expected output, until a blank line or the next prompt.
+/// Tabs are expanded, like doctest does.
+///
+/// ```
+/// use python_parser::doctest::examples;
+/// let docstring = "Adds numbers.
+///
+///     >>> add(1,
+///     ...     2)
+///     3
+///     >>> x = 1
+/// ";
+/// let examples = examples(docstring);
+/// assert_eq!(examples[0].source, "add(1,\n    2)\n");
+/// assert_eq!(examples[0].want, "3\n");
+/// assert_eq!(examples[1].line, 5);
+/// assert!(examples[1].statements.is_ok());
+/// ```
+pub fn examples(docstring: &str) -> Vec<Example> {
+    let docstring = expand_tabs(docstring);
+    let lines: Vec<&str> = docstring.lines().collect();
+    let mut examples = Vec::new();
+    let mut i = 0;
+    while i < lines.len() {
+        let indent = indentation(lines[i]);
+        let first = match after_prompt(lines[i], indent, ">>>") {
+            Some(first) => first,
+            None => {
+                i += 1;
+                continue;
+            }
+        };
+        let line = i;
+        let mut source = format!("{}\n", first);
+        i += 1;
+        while let Some(code) = lines.get(i).and_then(|l| after_prompt(l, indent, "...")) {
+            source.push_str(code);
+            source.push('\n');
+            i += 1;
+        }
+        let mut want = String::new();
+        while let Some(&text) = lines.get(i) {
+            if text.trim().is_empty() || text.trim_start().starts_with(">>>") {
+                break;
+            }
+            want.push_str(&text[indentation(text).min(indent)..]);
+            want.push('\n');
+            i += 1;
+        }
+        let statements = match parse_command(&source) {
+            Ok(Some(statements)) => Ok(statements),
+            Ok(None) => Err(ParseError {
+                offset: source.len(),
+                line: source.lines().count() as u32,
+                code: None,
+            }),
+            Err(e) => Err(e),
+        };
+        examples.push(Example {
+            source,
+            statements,
+            want,
+            line,
+            indent,
+        });
+    }
+    examples
+}
+
+/// Replaces tabs by spaces, up to the next multiple of 8 columns.
+fn expand_tabs(text: &str) -> String {
+    let mut expanded = String::with_capacity(text.len());
+    let mut column = 0;
+    for c in text.chars() {
+        match c {
+            '\t' => {
+                let spaces = 8 - column % 8;
+                expanded.extend((0..spaces).map(|_| ' '));
+                column += spaces;
+            }
+            '\n' | '\r' => {
+                expanded.push(c);
+                column = 0;
+            }
+            _ => {
+                expanded.push(c);
+                column += 1;
+            }
+        }
+    }
+    expanded
+}
+
+/// Returns the doctests of a module, given its body: its docstrings
+/// that have examples (see `docstrings` and `examples`).
+pub fn doctests(body: &[Statement]) -> Vec<Doctest> {
+    docstrings(body)
+        .into_iter()
+        .filter_map(|docstring| {
+            let examples = examples(&docstring.text);
+            if examples.is_empty() {
+                None
+            } else {
+                Some(Doctest {
+                    docstring,
+                    examples,
+                })
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parse_module;
+
+    #[test]
+    fn test_docstrings() {
+        let code = "'''Module.'''
+class C:
+    'Class' ' C.'
+    def f(self):
+        '''Method.'''
+        def g():
+            '''Nested.'''
+def h():
+    f'''Not a docstring.'''
+def i():
+    pass
+    '''Not a docstring.'''
+";
+        let module = parse_module(code).unwrap();
+        let docstrings: Vec<_> = docstrings(&module.body)
+            .into_iter()
+            .map(|d| (d.name, d.text))
+            .collect();
+        let expected = [("", "Module."), ("C", "Class C."), ("C.f", "Method.")];
+        let expected: Vec<_> = expected
+            .iter()
+            .map(|&(name, text)| (name.to_string(), text.to_string()))
+            .collect();
+        assert_eq!(docstrings, expected);
+    }
+
+    #[test]
+    fn test_examples() {
+        let docstring = "
+  >>> for i in range(2):
+  ...     print(i)
+  0
+  1
+
+  >>> if x:
+  >>>x
+  >>> 1 +
+  \t>>> raise ValueError
+  Traceback (most recent call last):
+  ValueError
+";
+        let examples = examples(docstring);
+        let summary: Vec<_> = examples
+            .iter()
+            .map(|e| (e.line, e.indent, &e.source[..], &e.want[..]))
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                (1, 2, "for i in range(2):\n    print(i)\n", "0\n1\n"),
+                (6, 2, "if x:\n", ""),
+                (8, 2, "1 +\n", ""),
+                (
+                    9,
+                    8,
+                    "raise ValueError\n",
+                    "Traceback (most recent call last):\nValueError\n"
+                ),
+            ]
+        );
+        assert_eq!(examples[0].statements.as_ref().map(|s| s.len()), Ok(1));
+        // Incomplete and invalid code
+        assert_eq!(examples[1].statements.as_ref().unwrap_err().offset, 6);
+        assert!(examples[2].statements.is_err());
+        assert!(examples[3].statements.is_ok());
+    }
+
+    #[test]
+    fn test_doctests() {
+        let code = "def f():\n    '''\n    >>> f()\n    '''\ndef g():\n    '''No example.'''\n";
+        let doctests = doctests(&parse_module(code).unwrap().body);
+        assert_eq!(doctests.len(), 1);
+        assert_eq!(doctests[0].docstring.name, "f");
+        assert_eq!(doctests[0].examples[0].source, "f()\n");
+    }
+}
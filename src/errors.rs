@@ -1,9 +1,91 @@
+use std::error::Error;
+use std::fmt;
+
+use nom::{Context, ErrorKind};
+
+use helpers::StrSpan;
+use keywords::{is_keyword, COMPOUND_KEYWORDS, PARSED_VERSION};
+use tokenizer::{line_breaks, tokenize, Token, TokenKind};
+
+/// Returns whether `token` can be the last one of an operand.
+fn ends_operand(token: Token) -> bool {
+    match token.kind {
+        TokenKind::Op => token.text == ")" || token.text == "]" || token.text == "}",
+        _ => starts_operand(token),
+    }
+}
+
+/// Returns whether `token` can be the first one of an operand (except
+/// for brackets and operators).
+fn starts_operand(token: Token) -> bool {
+    match token.kind {
+        TokenKind::Number | TokenKind::String => true,
+        TokenKind::Name => {
+            !is_keyword(token.text, PARSED_VERSION)
+                || ["False", "None", "True"].contains(&token.text)
+        }
+        _ => false,
+    }
+}
+
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
 #[repr(u32)]
 pub enum PyParseError {
     UnexpectedIndent,
     ExpectedIndent,
     DisabledFeature,
+    MultipleStatements,
+    DuplicateKeywordArgument,
+    NonDefaultAfterDefault,
+    NoParameterAfterBareStar,
+    DuplicateParameter,
+    ReturnOutsideFunction,
+    YieldOutsideFunction,
+    YieldInComprehension,
+    BreakOutsideLoop,
+    ContinueOutsideLoop,
+    AwaitOutsideFunction,
+    AwaitOutsideAsyncFunction,
+    AsyncForOutsideAsyncFunction,
+    AsyncComprehensionOutsideAsyncFunction,
+    YieldFromInAsyncFunction,
+    NonlocalAtModuleLevel,
+    NonlocalWithoutBinding,
+    NonlocalAndGlobal,
+    ParameterAndGlobal,
+    ParameterAndNonlocal,
+    UsedBeforeGlobal,
+    UsedBeforeNonlocal,
+    AssignedBeforeGlobal,
+    AssignedBeforeNonlocal,
+    AnnotatedGlobal,
+    AnnotatedNonlocal,
+    FstringExpectingBrace,
+    FstringSingleBrace,
+    FstringEmptyExpression,
+    FstringInvalidConversion,
+    FstringBackslash,
+    FstringComment,
+    FstringNestedTooDeeply,
+    UnterminatedString,
+    UnterminatedTripleQuotedString,
+    UnclosedParenthesis,
+    UnclosedBracket,
+    UnclosedBrace,
+    UnmatchedParenthesis,
+    UnmatchedBracket,
+    UnmatchedBrace,
+    MismatchedBracket,
+    MissingColon,
+    AssignmentInCondition,
+    MissingComma,
+    FuelExhausted,
+    Cancelled,
+    CodeTooLarge,
+    LiteralTooLong,
+    TooManyDiagnostics,
+    BytesAndNonBytes,
+    NestedTooDeeply,
 }
 impl From<PyParseError> for u32 {
     fn from(e: PyParseError) -> u32 {
@@ -11,6 +93,383 @@ impl From<PyParseError> for u32 {
     }
 }
 
+impl PyParseError {
+    const ALL: [PyParseError; 55] = [
+        PyParseError::UnexpectedIndent,
+        PyParseError::ExpectedIndent,
+        PyParseError::DisabledFeature,
+        PyParseError::MultipleStatements,
+        PyParseError::DuplicateKeywordArgument,
+        PyParseError::NonDefaultAfterDefault,
+        PyParseError::NoParameterAfterBareStar,
+        PyParseError::DuplicateParameter,
+        PyParseError::ReturnOutsideFunction,
+        PyParseError::YieldOutsideFunction,
+        PyParseError::YieldInComprehension,
+        PyParseError::BreakOutsideLoop,
+        PyParseError::ContinueOutsideLoop,
+        PyParseError::AwaitOutsideFunction,
+        PyParseError::AwaitOutsideAsyncFunction,
+        PyParseError::AsyncForOutsideAsyncFunction,
+        PyParseError::AsyncComprehensionOutsideAsyncFunction,
+        PyParseError::YieldFromInAsyncFunction,
+        PyParseError::NonlocalAtModuleLevel,
+        PyParseError::NonlocalWithoutBinding,
+        PyParseError::NonlocalAndGlobal,
+        PyParseError::ParameterAndGlobal,
+        PyParseError::ParameterAndNonlocal,
+        PyParseError::UsedBeforeGlobal,
+        PyParseError::UsedBeforeNonlocal,
+        PyParseError::AssignedBeforeGlobal,
+        PyParseError::AssignedBeforeNonlocal,
+        PyParseError::AnnotatedGlobal,
+        PyParseError::AnnotatedNonlocal,
+        PyParseError::FstringExpectingBrace,
+        PyParseError::FstringSingleBrace,
+        PyParseError::FstringEmptyExpression,
+        PyParseError::FstringInvalidConversion,
+        PyParseError::FstringBackslash,
+        PyParseError::FstringComment,
+        PyParseError::FstringNestedTooDeeply,
+        PyParseError::UnterminatedString,
+        PyParseError::UnterminatedTripleQuotedString,
+        PyParseError::UnclosedParenthesis,
+        PyParseError::UnclosedBracket,
+        PyParseError::UnclosedBrace,
+        PyParseError::UnmatchedParenthesis,
+        PyParseError::UnmatchedBracket,
+        PyParseError::UnmatchedBrace,
+        PyParseError::MismatchedBracket,
+        PyParseError::MissingColon,
+        PyParseError::AssignmentInCondition,
+        PyParseError::MissingComma,
+        PyParseError::FuelExhausted,
+        PyParseError::Cancelled,
+        PyParseError::CodeTooLarge,
+        PyParseError::LiteralTooLong,
+        PyParseError::TooManyDiagnostics,
+        PyParseError::BytesAndNonBytes,
+        PyParseError::NestedTooDeeply,
+    ];
+
+    /// Returns the error whose code is `code`, if any.
+    pub fn from_code(code: u32) -> Option<PyParseError> {
+        PyParseError::ALL
+            .iter()
+            .cloned()
+            .find(|&e| u32::from(e) == code)
+    }
+}
+
+impl fmt::Display for PyParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            PyParseError::UnexpectedIndent => "unexpected indent",
+            PyParseError::ExpectedIndent => "expected an indented block",
+            PyParseError::DisabledFeature => "syntax disabled by a Cargo feature",
+            PyParseError::MultipleStatements => {
+                "multiple statements found while parsing a single statement"
+            }
+            PyParseError::DuplicateKeywordArgument => "keyword argument repeated",
+            PyParseError::NonDefaultAfterDefault => "non-default argument follows default argument",
+            PyParseError::NoParameterAfterBareStar => "named arguments must follow bare *",
+            PyParseError::DuplicateParameter => "duplicate argument in function definition",
+            PyParseError::ReturnOutsideFunction => "'return' outside function",
+            PyParseError::YieldOutsideFunction => "'yield' outside function",
+            PyParseError::YieldInComprehension => {
+                "'yield' inside comprehension or generator expression"
+            }
+            PyParseError::BreakOutsideLoop => "'break' outside loop",
+            PyParseError::ContinueOutsideLoop => "'continue' not properly in loop",
+            PyParseError::AwaitOutsideFunction => "'await' outside function",
+            PyParseError::AwaitOutsideAsyncFunction => "'await' outside async function",
+            PyParseError::AsyncForOutsideAsyncFunction => "'async for' outside async function",
+            PyParseError::AsyncComprehensionOutsideAsyncFunction => {
+                "asynchronous comprehension outside of an asynchronous function"
+            }
+            PyParseError::YieldFromInAsyncFunction => "'yield from' inside async function",
+            PyParseError::NonlocalAtModuleLevel => {
+                "nonlocal declaration not allowed at module level"
+            }
+            PyParseError::NonlocalWithoutBinding => "no binding for nonlocal found",
+            PyParseError::NonlocalAndGlobal => "name is nonlocal and global",
+            PyParseError::ParameterAndGlobal => "name is parameter and global",
+            PyParseError::ParameterAndNonlocal => "name is parameter and nonlocal",
+            PyParseError::UsedBeforeGlobal => "name is used prior to global declaration",
+            PyParseError::UsedBeforeNonlocal => "name is used prior to nonlocal declaration",
+            PyParseError::AssignedBeforeGlobal => "name is assigned to before global declaration",
+            PyParseError::AssignedBeforeNonlocal => {
+                "name is assigned to before nonlocal declaration"
+            }
+            PyParseError::AnnotatedGlobal => "annotated name can't be global",
+            PyParseError::AnnotatedNonlocal => "annotated name can't be nonlocal",
+            PyParseError::FstringExpectingBrace => "f-string: expecting '}'",
+            PyParseError::FstringSingleBrace => "f-string: single '}' is not allowed",
+            PyParseError::FstringEmptyExpression => "f-string: empty expression not allowed",
+            PyParseError::FstringInvalidConversion => {
+                "f-string: invalid conversion character: expected 's', 'r', or 'a'"
+            }
+            PyParseError::FstringBackslash => "f-string expression part cannot include a backslash",
+            PyParseError::FstringComment => "f-string expression part cannot include '#'",
+            PyParseError::FstringNestedTooDeeply => "f-string: expressions nested too deeply",
+            PyParseError::UnterminatedString => "unterminated string literal",
+            PyParseError::UnterminatedTripleQuotedString => {
+                "unterminated triple-quoted string literal"
+            }
+            PyParseError::UnclosedParenthesis => "'(' was never closed",
+            PyParseError::UnclosedBracket => "'[' was never closed",
+            PyParseError::UnclosedBrace => "'{' was never closed",
+            PyParseError::UnmatchedParenthesis => "unmatched ')'",
+            PyParseError::UnmatchedBracket => "unmatched ']'",
+            PyParseError::UnmatchedBrace => "unmatched '}'",
+            PyParseError::MismatchedBracket => {
+                "closing parenthesis does not match opening parenthesis"
+            }
+            PyParseError::MissingColon => "expected ':'",
+            PyParseError::AssignmentInCondition => {
+                "invalid syntax. Maybe you meant '==' or ':=' instead of '='?"
+            }
+            PyParseError::MissingComma => "invalid syntax. Perhaps you forgot a comma?",
+            PyParseError::FuelExhausted => "parsing budget exhausted",
+            PyParseError::Cancelled => "parsing cancelled",
+            PyParseError::CodeTooLarge => "code too large",
+            PyParseError::LiteralTooLong => "string literal too long",
+            PyParseError::TooManyDiagnostics => "too many errors",
+            PyParseError::BytesAndNonBytes => "cannot mix bytes and nonbytes literals",
+            PyParseError::NestedTooDeeply => "expression nested too deeply",
+        })
+    }
+}
+
+/// Error returned by the functions that parse a complete piece of code,
+/// instead of the raw nom error.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParseError {
+    /// Offset (in bytes) of the error in the code.
+    pub offset: usize,
+    /// Line of the error, starting from 1.
+    pub line: u32,
+    /// Why the code was rejected, if the parser knows it.
+    pub code: Option<PyParseError>,
+}
+
+impl ParseError {
+    pub(crate) fn at(span: StrSpan, code: Option<PyParseError>) -> ParseError {
+        ParseError {
+            offset: span.offset,
+            line: span.line,
+            code,
+        }
+    }
+
+    fn at_token(token: Token, code: PyParseError) -> ParseError {
+        ParseError {
+            offset: token.offset,
+            line: token.line,
+            code: Some(code),
+        }
+    }
+
+    /// Replaces this error on `code` by one at the start of the
+    /// construct that caused it, if it is a syntax error caused by an
+    /// unterminated string, a bracket that is never closed, or a closing
+    /// bracket that does not match, or a missing block at the end of
+    /// the code.
+    ///
+    /// Like in CPython, strings and closing brackets are checked first,
+    /// in the whole code, because the parser usually fails after them.
+    pub(crate) fn locate_unterminated(self, code: &str) -> ParseError {
+        let block_expected = match self.code {
+            None => true,
+            Some(PyParseError::ExpectedIndent) => true,
+            Some(_) => return self,
+        };
+        let mut brackets: Vec<Token> = Vec::new();
+        // First and last tokens of the last logical line
+        let mut line: Option<(Token, Token)> = None;
+        let mut at_line_start = true;
+        for token in tokenize(code) {
+            match token.kind {
+                TokenKind::Comment
+                | TokenKind::Nl
+                | TokenKind::Indent
+                | TokenKind::Dedent
+                | TokenKind::EndMarker => continue,
+                TokenKind::Newline => {
+                    at_line_start = true;
+                    continue;
+                }
+                TokenKind::Error if self.code.is_none() => {
+                    let quotes = token
+                        .text
+                        .trim_start_matches(|c: char| c.is_ascii_alphabetic());
+                    if quotes.starts_with("'''") || quotes.starts_with("\"\"\"") {
+                        return ParseError::at_token(
+                            token,
+                            PyParseError::UnterminatedTripleQuotedString,
+                        );
+                    } else if quotes.starts_with(&['\'', '"'][..]) {
+                        return ParseError::at_token(token, PyParseError::UnterminatedString);
+                    }
+                }
+                TokenKind::Op if self.code.is_none() => {
+                    let (opening, unmatched) = match token.text {
+                        "(" | "[" | "{" => {
+                            brackets.push(token);
+                            ("", PyParseError::UnmatchedParenthesis)
+                        }
+                        ")" => ("(", PyParseError::UnmatchedParenthesis),
+                        "]" => ("[", PyParseError::UnmatchedBracket),
+                        "}" => ("{", PyParseError::UnmatchedBrace),
+                        _ => ("", PyParseError::UnmatchedParenthesis),
+                    };
+                    if !opening.is_empty() {
+                        match brackets.pop() {
+                            Some(open) if open.text == opening => (),
+                            Some(_) => {
+                                return ParseError::at_token(token, PyParseError::MismatchedBracket)
+                            }
+                            None => return ParseError::at_token(token, unmatched),
+                        }
+                    }
+                }
+                _ => (),
+            }
+            line = match line {
+                Some((first, _)) if !at_line_start => Some((first, token)),
+                _ => Some((token, token)),
+            };
+            at_line_start = false;
+        }
+        if let Some(open) = brackets.pop() {
+            let code = match open.text {
+                "(" => PyParseError::UnclosedParenthesis,
+                "[" => PyParseError::UnclosedBracket,
+                _ => PyParseError::UnclosedBrace,
+            };
+            return ParseError::at_token(open, code);
+        }
+        match line {
+            Some((first, last))
+                if block_expected
+                    && last.text == ":"
+                    && (first.text == "@" || COMPOUND_KEYWORDS.contains(&first.text)) =>
+            {
+                ParseError::at_token(first, PyParseError::ExpectedIndent)
+            }
+            _ => self,
+        }
+    }
+    /// Replaces a syntax error on `code` by a more helpful one if the
+    /// code has a common mistake from the line of the error: a missing
+    /// `:` at the end of a block header, a `=` instead of `==` in a
+    /// condition, or a missing comma between items in brackets.
+    pub(crate) fn guess_mistake(self, code: &str) -> ParseError {
+        if self.code.is_some() {
+            return self;
+        }
+        let mut line = Vec::new();
+        for token in tokenize(code) {
+            match token.kind {
+                TokenKind::Comment | TokenKind::Nl | TokenKind::Indent | TokenKind::Dedent => (),
+                TokenKind::Newline | TokenKind::EndMarker => {
+                    if let Some(error) = self.mistake_in_line(&line) {
+                        return error;
+                    }
+                    line.clear()
+                }
+                _ => line.push(token),
+            }
+        }
+        self
+    }
+
+    /// Looks for a common mistake in a logical line (without comments
+    /// and non-logical newlines), if it ends after this error.
+    fn mistake_in_line(&self, line: &[Token]) -> Option<ParseError> {
+        let (first, last) = (*line.first()?, *line.last()?);
+        let end = last.offset + last.text.len();
+        if end < self.offset {
+            return None;
+        }
+        let is_header = first.kind == TokenKind::Name && COMPOUND_KEYWORDS.contains(&first.text);
+        let is_condition = ["if", "elif", "while"].contains(&first.text);
+        let mut has_colon = false;
+        // Whether each open bracket is a parenthesis that only groups
+        // an expression (not a call, or a tuple)
+        let mut brackets: Vec<bool> = Vec::new();
+        let mut previous: Option<Token> = None;
+        for &token in line {
+            if let Some(previous) = previous {
+                let is_concatenation =
+                    previous.kind == TokenKind::String && token.kind == TokenKind::String;
+                if !brackets.is_empty()
+                    && ends_operand(previous)
+                    && starts_operand(token)
+                    && !is_concatenation
+                {
+                    return Some(ParseError::at_token(previous, PyParseError::MissingComma));
+                }
+            }
+            if token.kind == TokenKind::Op {
+                match token.text {
+                    "(" | "[" | "{" => {
+                        let is_call = previous.is_some_and(ends_operand);
+                        brackets.push(token.text == "(" && !is_call)
+                    }
+                    ")" | "]" | "}" => {
+                        brackets.pop();
+                    }
+                    ":" if brackets.is_empty() => has_colon = true,
+                    "=" if is_condition && !has_colon && brackets.last() != Some(&false) => {
+                        return Some(ParseError::at_token(
+                            token,
+                            PyParseError::AssignmentInCondition,
+                        ))
+                    }
+                    _ => (),
+                }
+            }
+            previous = Some(token);
+        }
+        if is_header && !has_colon {
+            return Some(ParseError {
+                offset: end,
+                line: last.line + line_breaks(last.text),
+                code: Some(PyParseError::MissingColon),
+            });
+        }
+        None
+    }
+}
+
+impl<'a> From<::nom::Err<StrSpan<'a>>> for ParseError {
+    fn from(e: ::nom::Err<StrSpan<'a>>) -> ParseError {
+        match e {
+            ::nom::Err::Error(Context::Code(span, kind))
+            | ::nom::Err::Failure(Context::Code(span, kind)) => {
+                let code = match kind {
+                    ErrorKind::Custom(code) => PyParseError::from_code(code),
+                    _ => None,
+                };
+                ParseError::at(span, code)
+            }
+            ::nom::Err::Incomplete(_) => unreachable!("Only complete strings are parsed"),
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.code {
+            Some(code) => write!(f, "{} at line {}", code, self.line),
+            None => write!(f, "invalid syntax at line {}", self.line),
+        }
+    }
+}
+
+impl Error for ParseError {}
+
 #[cfg(test)]
 mod tests {
     use nom;
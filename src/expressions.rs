@@ -1,19 +1,88 @@
+use std::collections::HashSet;
 use std::marker::PhantomData;
 
+use nom::IResult;
+
 use ast::*;
+use budget::{nested, spend_fuel};
 use bytes::bytes;
+use errors::PyParseError;
 use functions::varargslist;
 use helpers::*;
+use memo::memoize;
 use numbers::number;
 use strings::string;
 
 #[derive(Clone, Debug, PartialEq)]
-enum TestlistCompReturn {
+pub(crate) enum TestlistCompReturn {
     Comp(Box<SetItem>, Vec<ComprehensionChunk>), // comprehension
     Lit(Vec<SetItem>),                           // list of litterals (length >= 1)
     Single(SetItem), // a single litteral: either from `[foo]` (list) or `(foo)` (atom, NOT tuple)
 }
 
+// What follows the first item of a dict/set display.
+enum MakerRest<T> {
+    Items(Vec<T>),                 // `, item2, item3`
+    Comp(Vec<ComprehensionChunk>), // `for ...`
+}
+
+/// Fails like CPython if a keyword argument is repeated, or returns the
+/// arguments without their positions.
+fn check_arguments<'a>(
+    i: StrSpan<'a>,
+    args: Vec<(StrSpan<'a>, Argument)>,
+) -> IResult<StrSpan<'a>, Vec<Argument>> {
+    let mut names = HashSet::new();
+    for &(pos, ref arg) in &args {
+        if let Argument::Keyword(ref name, _) = *arg {
+            if !names.insert(name) {
+                return Err(::nom::Err::Failure(::nom::Context::Code(
+                    pos,
+                    ::nom::ErrorKind::Custom(PyParseError::DuplicateKeywordArgument.into()),
+                )));
+            }
+        }
+    }
+    Ok((i, args.into_iter().map(|(_, arg)| arg).collect()))
+}
+
+/// A string or bytes literal.
+enum Literal {
+    String(PyString),
+    Bytes(Vec<u8>),
+}
+
+/// Fails like CPython if string and bytes literals are concatenated, or
+/// returns their concatenation.
+fn concatenate<'a>(
+    i: StrSpan<'a>,
+    literals: Vec<(StrSpan<'a>, Literal)>,
+) -> IResult<StrSpan<'a>, Expression> {
+    let mut strings = Vec::new();
+    let mut bytes = Vec::new();
+    let is_bytes = match literals[0].1 {
+        Literal::String(_) => false,
+        Literal::Bytes(_) => true,
+    };
+    for (pos, literal) in literals {
+        match (literal, is_bytes) {
+            (Literal::String(s), false) => strings.push(s),
+            (Literal::Bytes(b), true) => bytes.extend(b),
+            _ => {
+                return Err(::nom::Err::Failure(::nom::Context::Code(
+                    pos,
+                    ::nom::ErrorKind::Custom(PyParseError::BytesAndNonBytes.into()),
+                )))
+            }
+        }
+    }
+    if is_bytes {
+        Ok((i, Expression::Bytes(bytes)))
+    } else {
+        Ok((i, Expression::String(strings)))
+    }
+}
+
 pub(crate) struct ExpressionParser<ANS: AreNewlinesSpaces> {
     _phantom: PhantomData<ANS>,
 }
@@ -42,23 +111,30 @@ impl<ANS: AreNewlinesSpaces> ExpressionParser<ANS> {
     );
 
     // test: or_test ['if' or_test 'else' test] | lambdef
-    named!(pub test<StrSpan, Box<Expression>>,
+    //
+    // Memoized in `memo::with_memoization`, because call arguments
+    // backtrack over it.
+    pub fn test(i: StrSpan) -> IResult<StrSpan, Box<Expression>, u32> {
+        memoize(("test", ANS::VALUE), i, Self::test_uncached)
+    }
+
+    // Chained conditional expressions are parsed in a loop instead of
+    // recursively, so long `a if b else c if d else ...` chains do not
+    // grow the stack.
+    named!(test_uncached<StrSpan, Box<Expression>>,
       alt!(
         call!(Self::lambdef)
       | do_parse!(
-          left: call!(Self::or_test) >>
-          right: opt!(do_parse!(
+          first: call!(Self::or_test) >>
+          branches: many0!(do_parse!(
             ws_auto!(keyword!("if")) >>
             cond: return_error!(call!(Self::or_test)) >>
             ws_auto!(keyword!("else")) >>
-            right: return_error!(call!(Self::test)) >> (
+            right: return_error!(alt!(call!(Self::lambdef) | call!(Self::or_test))) >> (
               (cond, right)
             )
           )) >> (
-            match right {
-              None => left,
-              Some((cond, right)) => Box::new(Expression::Ternary(left, cond, right)),
-            }
+            make_ternary(first, branches)
           )
         )
       )
@@ -97,6 +173,26 @@ impl<ANS: AreNewlinesSpaces> ExpressionParser<ANS> {
     );
 } // End ExpressionParser
 
+/// Builds `first if cond1 else (right1 if cond2 else (right2 ...))` from
+/// the items of a chain of conditional expressions.
+pub(crate) fn make_ternary(
+    first: Box<Expression>,
+    branches: Vec<(Box<Expression>, Box<Expression>)>,
+) -> Box<Expression> {
+    let mut values = vec![first];
+    let mut conds = Vec::with_capacity(branches.len());
+    for (cond, right) in branches {
+        conds.push(cond);
+        values.push(right);
+    }
+    let mut acc = values.pop().unwrap(); // Can't panic, because it contains at least `first`
+    while let Some(cond) = conds.pop() {
+        let left = values.pop().unwrap(); // Can't panic, because len(values) == len(conds) + 1
+        acc = Box::new(Expression::Ternary(left, cond, acc));
+    }
+    acc
+}
+
 macro_rules! bop {
     ( $name:ident, $child:path, $tag:ident!($($args:tt)*) ) => {
         named!(pub $name<StrSpan, Box<Expression>>,
@@ -111,20 +207,29 @@ macro_rules! bop {
               |(first, mut acc):(_,Vec<_>), (op, f):(_,Box<_>)| { acc.push((op, *f)); (first, acc) }
             ) >> ({
               let (first, rest) = r;
-              match rest.len() {
-                  0 => first,
-                  1 => {
-                      let (op, ref rhs) = rest[0];
-                      Box::new(Expression::Bop(op, first, Box::new(rhs.clone())))
-                  }
-                  _ => Box::new(Expression::MultiBop(first, rest))
-              }
+              make_bop(first, rest)
             })
           )
         );
     }
 }
 
+/// Builds the expression for `first op1 rhs1 op2 rhs2 ...`, where all the
+/// operators have the same precedence.
+pub(crate) fn make_bop(
+    first: Box<Expression>,
+    mut rest: Vec<(Bop, Expression)>,
+) -> Box<Expression> {
+    match rest.len() {
+        0 => first,
+        1 => {
+            let (op, rhs) = rest.pop().unwrap(); // Can't panic, because len == 1
+            Box::new(Expression::Bop(op, first, Box::new(rhs)))
+        }
+        _ => Box::new(Expression::MultiBop(first, rest)),
+    }
+}
+
 impl<ANS: AreNewlinesSpaces> ExpressionParser<ANS> {
     // or_test: and_test ('or' and_test)*
     bop!(
@@ -146,9 +251,11 @@ impl<ANS: AreNewlinesSpaces> ExpressionParser<ANS> {
 
     // not_test: 'not' not_test | comparison
     named!(not_test<StrSpan, Box<Expression>>,
-      alt!(
-        preceded!(tuple!(keyword!("not"), spaces!()), call!(Self::not_test)) => { |e| Box::new(Expression::Uop(Uop::Not, e)) }
-      | call!(Self::comparison)
+      do_parse!(
+        nots: fold_many0!(tuple!(keyword!("not"), spaces!()), 0usize, |nots, _| nots + 1) >>
+        e: call!(Self::comparison) >> (
+          (0..nots).fold(e, |e, _| Box::new(Expression::Uop(Uop::Not, e)))
+        )
       )
     );
 
@@ -238,16 +345,31 @@ impl<ANS: AreNewlinesSpaces> ExpressionParser<ANS> {
 
     // factor: ('+'|'-'|'~') factor | power
     named!(factor<StrSpan, Box<Expression>>,
-      alt!(
-        do_parse!(spaces!() >> char!('+') >> spaces!() >> e: call!(Self::factor) >> (Box::new(Expression::Uop(Uop::Plus, e))))
-      | do_parse!(spaces!() >> char!('-') >> spaces!() >> e: call!(Self::factor) >> (Box::new(Expression::Uop(Uop::Minus, e))))
-      | do_parse!(spaces!() >> char!('~') >> spaces!() >> e: call!(Self::factor) >> (Box::new(Expression::Uop(Uop::Invert, e))))
-      | call!(Self::power)
+      do_parse!(
+        ops: many0!(delimited!(
+          spaces!(),
+          alt!(
+            char!('+') => { |_| Uop::Plus }
+          | char!('-') => { |_| Uop::Minus }
+          | char!('~') => { |_| Uop::Invert }
+          ),
+          spaces!()
+        )) >>
+        e: call!(Self::power) >> (
+          ops.into_iter().rev().fold(e, |e, op| Box::new(Expression::Uop(op, e)))
+        )
       )
     );
 
     // power: atom_expr ['**' factor]
-    named!(power<StrSpan, Box<Expression>>,
+    //
+    // Each level of brackets and each `**` recurse through it, so it
+    // counts the nesting (see `budget::MAX_NESTING`).
+    fn power(i: StrSpan) -> IResult<StrSpan, Box<Expression>, u32> {
+        nested(i, Self::power_unnested)
+    }
+
+    named!(power_unnested<StrSpan, Box<Expression>>,
       do_parse!(
         lhs: call!(Self::atom_expr) >>
         rhs: opt!(do_parse!(spaces!() >> tag!("**") >> spaces!() >> e: call!(Self::factor) >> (e))) >> (
@@ -259,6 +381,17 @@ impl<ANS: AreNewlinesSpaces> ExpressionParser<ANS> {
       )
     );
 } // End ExpressionParser
+pub(crate) fn make_slice(
+    first: Option<Expression>,
+    second: Option<Expression>,
+    third: Option<Option<Expression>>,
+) -> Subscript {
+    match third {
+        None => Subscript::Double(first, second),
+        Some(third) => Subscript::Triple(first, second, third),
+    }
+}
+
 enum Trailer {
     Call(Vec<Argument>),
     Subscript(Vec<Subscript>),
@@ -270,6 +403,7 @@ impl<ANS: AreNewlinesSpaces> ExpressionParser<ANS> {
     // subscriptlist: subscript (',' subscript)* [',']
     named!(atom_expr<StrSpan, Box<Expression>>,
       do_parse!(
+        call!(spend_fuel) >>
         async: map!(opt!(terminated!(tag!("await"), space_sep!())), |o| o.is_some()) >>
         lhs: call!(Self::atom) >>
         trailers: fold_many0!(
@@ -290,6 +424,18 @@ impl<ANS: AreNewlinesSpaces> ExpressionParser<ANS> {
       )
     );
 
+    // STRING+, which must all be strings or all be bytes
+    named!(literals<StrSpan, Expression>,
+      do_parse!(
+        literals: separated_nonempty_list!(spaces!(), tuple!(position, alt!(
+          string => { |s| Literal::String(s) }
+        | bytes => { |b| Literal::Bytes(b) }
+        ))) >>
+        expression: call!(concatenate, literals) >>
+        (expression)
+      )
+    );
+
     // atom: ('(' [yield_expr|testlist_comp] ')' |
     //       '[' [testlist_comp] ']' |
     //       '{' [dictorsetmaker] '}' |
@@ -300,12 +446,7 @@ impl<ANS: AreNewlinesSpaces> ExpressionParser<ANS> {
       | keyword!("None") => { |_| Expression::None }
       | keyword!("True") => { |_| Expression::True }
       | keyword!("False") => { |_| Expression::False }
-      | separated_nonempty_list!(spaces!(), string) => { |s| Expression::String(s) }
-      | separated_nonempty_list!(spaces!(), bytes) => { |v| {
-          let mut v2 = Vec::new();
-          for b in v { v2.extend(b) }
-          Expression::Bytes(v2)
-        }}
+      | call!(Self::literals)
       | number
       | name => { |n| Expression::Name(n) }
       | tuple!(char!('['), ws_comm!(opt!(char!(' '))), char!(']')) => { |_| Expression::ListLiteral(vec![]) }
@@ -380,32 +521,33 @@ impl<ANS: AreNewlinesSpaces> ExpressionParser<ANS> {
     // subscript: test | [test] ':' [test] [sliceop]
     named!(subscript<StrSpan, Subscript>,
       ws_comm!(alt!(
-        preceded!(char!(':'), call!(Self::subscript_trail, None))
+        preceded!(char!(':'), call!(Self::subscript_trail)) => { |(second, third)|
+          make_slice(None, second, third)
+        }
       | do_parse!(
           first: call!(Self::test) >>
-          r: opt!(ws_comm!(preceded!(char!(':'), call!(Self::subscript_trail, Some(*first.clone()))))) >> ( // FIXME: remove this clone
-            r.unwrap_or(Subscript::Simple(*first))
+          r: opt!(ws_comm!(preceded!(char!(':'), call!(Self::subscript_trail)))) >> (
+            match r {
+                Some((second, third)) => make_slice(Some(*first), second, third),
+                None => Subscript::Simple(*first),
+            }
           )
         )
       ))
     );
-    named_args!(subscript_trail(first: Option<Expression>) <StrSpan, Subscript>,
+    // returns the optional second and third parts of a slice
+    named!(subscript_trail<StrSpan, (Option<Expression>, Option<Option<Expression>>)>,
       do_parse!(
         second: opt!(call!(Self::test)) >>
-        third: opt!(preceded!(char!(':'), opt!(call!(Self::test)))) >> ({
-          let second = second.map(|s| *s);
-          match third {
-            None => Subscript::Double(first, second),
-            Some(None) => Subscript::Triple(first, second, None),
-            Some(Some(t)) => Subscript::Triple(first, second, Some(*t)),
-          }
-        })
+        third: opt!(preceded!(char!(':'), opt!(call!(Self::test)))) >> (
+          (second.map(|s| *s), third.map(|t| t.map(|t| *t)))
+        )
       )
     );
 
     // exprlist: (expr|star_expr) (',' (expr|star_expr))* [',']
-    named!(pub exprlist<StrSpan, Vec<Expression>>,
-      separated_nonempty_list!(ws_auto!(char!(',')), map!(alt!(call!(Self::expr)|call!(Self::star_expr)), |e| *e))
+    named!(pub exprlist<StrSpan, SmallList<Expression>>,
+      separated_nonempty_small_list!(ws_auto!(char!(',')), map!(alt!(call!(Self::expr)|call!(Self::star_expr)), |e| *e))
     );
 
     // testlist: test (',' test)* [',']
@@ -429,9 +571,9 @@ impl<ANS: AreNewlinesSpaces> ExpressionParser<ANS> {
     );
 
     // testlist_star_expr: (test|star_expr) (',' (test|star_expr))* [',']
-    named!(pub testlist_star_expr<StrSpan, Vec<Expression>>,
+    named!(pub testlist_star_expr<StrSpan, SmallList<Expression>>,
       do_parse!(
-        list: separated_nonempty_list!(
+        list: separated_nonempty_small_list!(
           ws_auto!(char!(',')),
           map!(alt!(
             call!(Self::test)
@@ -441,7 +583,7 @@ impl<ANS: AreNewlinesSpaces> ExpressionParser<ANS> {
         trailing_comma: opt!(ws_auto!(char!(','))) >> (
           if trailing_comma.is_some() && list.len() < 2 {
               // This prevents "foo, =" from being parsed as "foo ="
-              vec![Expression::TupleLiteral(list.into_iter().map(SetItem::Unique).collect())]
+              small_list![Expression::TupleLiteral(list.into_iter().map(SetItem::Unique).collect())]
           }
           else {
               list
@@ -476,55 +618,54 @@ impl ExpressionParser<NewlinesAreSpaces> {
         )
       | do_parse!(
           key: call!(Self::test) >>
-          r: alt!(
-            do_parse!(
-              char!(':') >>
-              item: map!(call!(Self::test), |value: Box<_>| DictItem::Unique(*key.clone(), *value)) >> // FIXME: do not clone
-              r: call!(Self::dictmaker, item) >>
-              (r)
-            )
-          | call!(Self::setmaker, SetItem::Unique(*key))
-          ) >>
+          value: opt!(ws_comm!(preceded!(char!(':'), call!(Self::test)))) >>
+          r: call!(Self::dict_or_set_rest, *key, value.map(|v| *v)) >>
           (r)
         )
       ))
     );
 
+    fn dict_or_set_rest(
+        i: StrSpan,
+        key: Expression,
+        value: Option<Expression>,
+    ) -> IResult<StrSpan, Box<Expression>, u32> {
+        match value {
+            Some(value) => Self::dictmaker(i, DictItem::Unique(key, value)),
+            None => Self::setmaker(i, SetItem::Unique(key)),
+        }
+    }
+
     named_args!(dictmaker(item1: DictItem) <StrSpan, Box<Expression>>,
-      map!(
-        opt!(alt!(
-          ws_comm!(delimited!(char!(','), separated_list!(char!(','), call!(Self::dictitem)), opt!(ws_comm!(char!(','))))) => { |v: Vec<_>| {
-            let mut v = v;
-            v.insert(0, item1.clone()); // FIXME: do not clone
-            Box::new(Expression::DictLiteral(v))
-          }}
-        | preceded!(peek!(keyword!("for")), return_error!(call!(Self::comp_for))) => { |comp| {
-            Box::new(Expression::DictComp(Box::new(item1.clone()), comp)) // FIXME: do not clone
-          }}
-        )),
-        |rest| {
+      do_parse!(
+        rest: opt!(alt!(
+          ws_comm!(delimited!(char!(','), separated_list!(char!(','), call!(Self::dictitem)), opt!(ws_comm!(char!(','))))) => { |v| MakerRest::Items(v) }
+        | preceded!(peek!(keyword!("for")), return_error!(call!(Self::comp_for))) => { |comp| MakerRest::Comp(comp) }
+        )) >> (
           match rest {
-              Some(r) => r,
+              Some(MakerRest::Items(mut v)) => {
+                  v.insert(0, item1);
+                  Box::new(Expression::DictLiteral(v))
+              }
+              Some(MakerRest::Comp(comp)) => Box::new(Expression::DictComp(Box::new(item1), comp)),
               None => Box::new(Expression::DictLiteral(vec![item1])),
           }
-        }
+        )
       )
     );
 
     named_args!(setmaker(item1: SetItem) <StrSpan, Box<Expression>>,
       do_parse!(
-        rest:opt!(alt!(
-          ws_comm!(delimited!(char!(','), separated_list!(char!(','), call!(Self::setitem)), opt!(ws_comm!(char!(','))))) => { |v: Vec<_>| {
-            let mut v = v;
-            v.insert(0, item1.clone()); // FIXME: do not clone
-            Box::new(Expression::SetLiteral(v))
-          }}
-        | call!(Self::comp_for) => { |comp| {
-            Box::new(Expression::SetComp(Box::new(item1.clone()), comp)) // FIXME: do not clone
-          }}
+        rest: opt!(alt!(
+          ws_comm!(delimited!(char!(','), separated_list!(char!(','), call!(Self::setitem)), opt!(ws_comm!(char!(','))))) => { |v| MakerRest::Items(v) }
+        | call!(Self::comp_for) => { |comp| MakerRest::Comp(comp) }
         )) >> (
           match rest {
-              Some(r) => r,
+              Some(MakerRest::Items(mut v)) => {
+                  v.insert(0, item1);
+                  Box::new(Expression::SetLiteral(v))
+              }
+              Some(MakerRest::Comp(comp)) => Box::new(Expression::SetComp(Box::new(item1), comp)),
               None => Box::new(Expression::SetLiteral(vec![item1])),
           }
         )
@@ -560,34 +701,34 @@ impl<ANS: AreNewlinesSpaces> ExpressionParser<ANS> {
     //             '*' test )
     named!(pub arglist<StrSpan, Vec<Argument>>,
       ws_comm!(do_parse!(
-        args: separated_list!(ws_comm!(char!(',')),
+        args: separated_list!(ws_comm!(char!(',')), tuple!(position,
           alt!(
             preceded!(tag!("**"), call!(Self::test)) => { |kwargs: Box<_>| Argument::Kwargs(*kwargs) }
           | preceded!(char!('*'), call!(Self::test)) => { |args: Box<_>| Argument::Starargs(*args) }
-          | do_parse!(
-              name: call!(Self::test) >>
-              value: preceded!(tag!(":="), call!(Self::test)) >> (
-                Argument::Positional(Expression::Named(name, value))
-              )
-            )
           | do_parse!(
               name: name >> // According to the grammar, this should be a 'test', but cpython actually refuses it (for good reasons)
               value: preceded!(char!('='), call!(Self::test)) >> (
                 Argument::Keyword(name.to_string(), *value)
               )
             )
+            // The first `test` of the remaining forms is parsed once
+            // instead of backtracking over it, which took exponential
+            // time on nested calls
           | do_parse!(
               test1: call!(Self::test) >>
-              next: opt!(ws_comm!(alt!(call!(Self::comp_for)))) >> (
-                match next {
-                    Some(e) => Argument::Positional(Expression::Generator(Box::new(SetItem::Unique(*test1)), e)),
-                    None => Argument::Positional(*test1)
+              value: opt!(ws_comm!(preceded!(tag!(":="), call!(Self::test)))) >>
+              generator: cond!(value.is_none(), opt!(ws_comm!(call!(Self::comp_for)))) >> (
+                match (value, generator) {
+                    (Some(value), _) => Argument::Positional(Expression::Named(test1, value)),
+                    (None, Some(Some(e))) => Argument::Positional(Expression::Generator(Box::new(SetItem::Unique(*test1)), e)),
+                    (None, _) => Argument::Positional(*test1),
                 }
               )
             )
           )
-        ) >>
+        )) >>
         opt!(ws_comm!(char!(','))) >>
+        args: call!(check_arguments, args) >>
         (args)
       ))
     );
@@ -597,23 +738,28 @@ impl<ANS: AreNewlinesSpaces> ExpressionParser<ANS> {
      *********************************************************************/
 
     // comp_iter: comp_for | comp_if
-    named_args!(comp_iter(acc: Vec<ComprehensionChunk>) <StrSpan, Vec<ComprehensionChunk>>,
+    named!(comp_iter<StrSpan, ComprehensionChunk>,
       alt!(
-        call!(Self::comp_for2, acc.clone()) // FIXME: do not clone
-      | call!(Self::comp_if, acc)
+        call!(Self::comp_for_chunk)
+      | call!(Self::comp_if_chunk)
       )
     );
 
-    named_args!(opt_comp_iter(acc: Vec<ComprehensionChunk>) <StrSpan, Vec<ComprehensionChunk>>,
-      return_error!(map!(opt!(call!(Self::comp_iter, acc.clone())), |r| r.unwrap_or(acc))) // FIXME: do not clone
-    );
-
     // sync_comp_for: 'for' exprlist 'in' or_test [comp_iter]
     // comp_for: ['async'] sync_comp_for
     named!(comp_for<StrSpan, Vec<ComprehensionChunk>>,
-      call!(Self::comp_for2, Vec::new())
+      do_parse!(
+        first: call!(Self::comp_for_chunk) >>
+        chunks: fold_many0!(
+          call!(Self::comp_iter),
+          vec![first],
+          |mut acc: Vec<_>, chunk| { acc.push(chunk); acc }
+        ) >> (
+          chunks
+        )
+      )
     );
-    named_args!(comp_for2(acc: Vec<ComprehensionChunk>) <StrSpan, Vec<ComprehensionChunk>>,
+    named!(comp_for_chunk<StrSpan, ComprehensionChunk>,
       do_parse!(
         async: map!(opt!(terminated!(tag!("async"), space_sep!())), |o| o.is_some()) >>
         keyword!("for") >>
@@ -623,22 +769,20 @@ impl<ANS: AreNewlinesSpaces> ExpressionParser<ANS> {
         keyword!("in") >>
         spaces!() >>
         iterator: return_error!(map!(call!(Self::or_test), |e| *e)) >>
-        spaces!() >>
-        r: call!(Self::opt_comp_iter, { let mut acc = acc; acc.push(ComprehensionChunk::For { async, item, iterator }); acc }) >> (
-          r
+        spaces!() >> (
+          ComprehensionChunk::For { async, item, iterator }
         )
       )
     );
 
     // comp_if: 'if' test_nocond [comp_iter]
-    named_args!(comp_if(acc: Vec<ComprehensionChunk>) <StrSpan, Vec<ComprehensionChunk>>,
+    named!(comp_if_chunk<StrSpan, ComprehensionChunk>,
       do_parse!(
         keyword!("if") >>
         spaces!() >>
         cond: return_error!(map!(call!(Self::test_nocond), |e| *e)) >>
-        spaces!() >>
-        r: call!(Self::opt_comp_iter, { let mut acc = acc; acc.push(ComprehensionChunk::If { cond }); acc }) >> (
-          r
+        spaces!() >> (
+          ComprehensionChunk::If { cond }
         )
       )
     );
@@ -650,7 +794,7 @@ impl<ANS: AreNewlinesSpaces> ExpressionParser<ANS> {
         keyword!("yield"),
         ws_auto!(alt!(
           preceded!(ws_auto!(keyword!("from")), call!(Self::test)) => { |e| Expression::YieldFrom(e) }
-        | call!(Self::testlist_star_expr) => { |e| Expression::Yield(e) }
+        | call!(Self::testlist_star_expr) => { |e| Expression::Yield(into_vec(e)) }
         | tag!("") => { |_| Expression::Yield(Vec::new()) }
         ))
       ))
@@ -671,6 +815,7 @@ mod tests {
         PyString {
             prefix: prefix.to_string(),
             content: PyStringContent::from_str(s),
+            raw: None,
         }
     }
 
@@ -679,6 +824,7 @@ mod tests {
         PyString {
             prefix: prefix.to_string(),
             content: s.to_string(),
+            raw: None,
         }
     }
 
@@ -772,6 +918,43 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_fstring() {
+        use errors::ParseError;
+        use errors::PyParseError::*;
+
+        let atom = ExpressionParser::<NewlinesAreNotSpaces>::atom;
+        for code in &[
+            r#"f'{x!r:>{y}} {{}} {x!=y} {x = } {d["a"]:{w}.{p}}'"#,
+            r#"f'\{x}\N{BULLET} {(lambda: 1)()} {x:=^10}'"#,
+            "f'''{x\n}'''",
+            r#"rf'\{x}'"#,
+            r#"'{' '}'"#,
+            r#"b'{'"#,
+        ] {
+            assert!(atom(make_strspan(code)).is_ok(), "{}", code);
+        }
+        for &(code, offset, error) in &[
+            ("f'{'", 3, FstringExpectingBrace),
+            ("f'{x!rr}'", 6, FstringExpectingBrace),
+            ("f'{x:{y}'", 8, FstringExpectingBrace),
+            ("f'a{{b}}c}'", 9, FstringSingleBrace),
+            ("f'{x:}}'", 6, FstringSingleBrace),
+            ("f'{ }'", 4, FstringEmptyExpression),
+            ("f'{x:{}}'", 6, FstringEmptyExpression),
+            ("f'{!r}'", 3, FstringEmptyExpression),
+            ("f'{x!z}'", 5, FstringInvalidConversion),
+            ("f'{x!}'", 5, FstringInvalidConversion),
+            (r#"f'{a["\n"]}'"#, 6, FstringBackslash),
+            (r#"rf'{\n}'"#, 4, FstringBackslash),
+            ("f'{x#}'", 4, FstringComment),
+            ("f'{x:{y:{z}}}'", 8, FstringNestedTooDeeply),
+        ] {
+            let e = ParseError::from(atom(make_strspan(code)).unwrap_err());
+            assert_eq!((e.offset, e.code), (offset, Some(error)), "{}", code);
+        }
+    }
+
     #[test]
     fn test_bytes() {
         let atom = ExpressionParser::<NewlinesAreNotSpaces>::atom;
@@ -783,7 +966,7 @@ mod tests {
             )),
         );
         assert_parse_eq(
-            atom(make_strspan(r#"b"foo" "bar""#)),
+            atom(make_strspan(r#"b"foo" B"bar""#)),
             Ok((
                 make_strspan(""),
                 Box::new(Expression::Bytes(b"foobar".to_vec())),
@@ -826,6 +1009,28 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_bytes_and_strings() {
+        use errors::ParseError;
+        use errors::PyParseError::BytesAndNonBytes;
+
+        let atom = ExpressionParser::<NewlinesAreNotSpaces>::atom;
+        for &(code, offset) in &[
+            (r#"b"foo" "bar""#, 7),
+            (r#"'foo' rb'bar'"#, 6),
+            (r#"f'{x}' b'y'"#, 7),
+            (r#"b'x' 'y' b'z'"#, 5),
+        ] {
+            let e = ParseError::from(atom(make_strspan(code)).unwrap_err());
+            assert_eq!(
+                (e.offset, e.code),
+                (offset, Some(BytesAndNonBytes)),
+                "{}",
+                code
+            );
+        }
+    }
+
     #[test]
     fn test_ternary() {
         let test = ExpressionParser::<NewlinesAreNotSpaces>::test;
@@ -840,6 +1045,42 @@ mod tests {
                 )),
             )),
         );
+        assert_parse_eq(
+            test(make_strspan("foo if bar else baz if qux else quux")),
+            Ok((
+                make_strspan(""),
+                Box::new(Expression::Ternary(
+                    Box::new(Expression::Name("foo".to_string())),
+                    Box::new(Expression::Name("bar".to_string())),
+                    Box::new(Expression::Ternary(
+                        Box::new(Expression::Name("baz".to_string())),
+                        Box::new(Expression::Name("qux".to_string())),
+                        Box::new(Expression::Name("quux".to_string())),
+                    )),
+                )),
+            )),
+        );
+    }
+
+    #[test]
+    fn test_long_chains() {
+        let test = ExpressionParser::<NewlinesAreNotSpaces>::test;
+
+        let code = format!("{}{}foo", "not ".repeat(5000), "-".repeat(5000));
+        let (rest, e) = test(make_strspan(&code)).unwrap();
+        assert_eq!(rest.fragment.0, "");
+        let mut depth = 0;
+        let mut e = &*e;
+        while let Expression::Uop(_, ref inner) = *e {
+            depth += 1;
+            e = inner;
+        }
+        assert_eq!(depth, 10000);
+        assert_eq!(*e, Expression::Name("foo".to_string()));
+
+        let code = format!("{}foo", "foo if bar else ".repeat(5000));
+        let (rest, _) = test(make_strspan(&code)).unwrap();
+        assert_eq!(rest.fragment.0, "");
     }
 
     #[test]
@@ -1364,6 +1605,43 @@ mod tests {
                 )),
             )),
         );
+
+        // Arguments of all kinds stay in the order of the code
+        assert_parse_eq(
+            atom_expr(make_strspan(
+                "foo(bar, *baz, qux1=qux2, *quux, **corge, grault=garply)",
+            )),
+            Ok((
+                make_strspan(""),
+                Box::new(Expression::Call(
+                    Box::new(Expression::Name("foo".to_string())),
+                    vec![
+                        Argument::Positional(Expression::Name("bar".to_string())),
+                        Argument::Starargs(Expression::Name("baz".to_string())),
+                        Argument::Keyword("qux1".to_string(), Expression::Name("qux2".to_string())),
+                        Argument::Starargs(Expression::Name("quux".to_string())),
+                        Argument::Kwargs(Expression::Name("corge".to_string())),
+                        Argument::Keyword(
+                            "grault".to_string(),
+                            Expression::Name("garply".to_string()),
+                        ),
+                    ],
+                )),
+            )),
+        );
+    }
+
+    #[test]
+    fn test_call_duplicate_keyword() {
+        use errors::ParseError;
+
+        let atom_expr = ExpressionParser::<NewlinesAreNotSpaces>::atom_expr;
+        for code in &["foo(bar=1, bar=2)", "foo(**baz, bar=1, qux=2,\n  bar=1)"] {
+            let error = ParseError::from(atom_expr(make_strspan(code)).unwrap_err());
+            assert_eq!(error.code, Some(PyParseError::DuplicateKeywordArgument));
+            assert_eq!(error.offset, code.rfind("bar").unwrap());
+        }
+        assert!(atom_expr(make_strspan("foo(bar=1, **bar, baz=bar)")).is_ok());
     }
 
     #[test]
@@ -1764,6 +2042,37 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_dictcomp() {
+        let atom = ExpressionParser::<NewlinesAreNotSpaces>::atom;
+
+        assert_parse_eq(
+            atom(make_strspan("{foo: bar\n for (foo, bar) in baz if qux}")),
+            Ok((
+                make_strspan(""),
+                Box::new(Expression::DictComp(
+                    Box::new(DictItem::Unique(
+                        Expression::Name("foo".to_string()),
+                        Expression::Name("bar".to_string()),
+                    )),
+                    vec![
+                        ComprehensionChunk::For {
+                            async: false,
+                            item: small_list![Expression::TupleLiteral(vec![
+                                SetItem::Unique(Expression::Name("foo".to_string())),
+                                SetItem::Unique(Expression::Name("bar".to_string())),
+                            ])],
+                            iterator: Expression::Name("baz".to_string()),
+                        },
+                        ComprehensionChunk::If {
+                            cond: Expression::Name("qux".to_string()),
+                        },
+                    ],
+                )),
+            )),
+        );
+    }
+
     #[test]
     fn test_comp_for() {
         let comp_for = ExpressionParser::<NewlinesAreNotSpaces>::comp_for;
@@ -1774,7 +2083,7 @@ mod tests {
                 make_strspan(""),
                 vec![ComprehensionChunk::For {
                     async: false,
-                    item: vec![Expression::Name("bar".to_string())],
+                    item: small_list![Expression::Name("bar".to_string())],
                     iterator: Expression::Name("baz".to_string()),
                 }],
             )),
@@ -1793,7 +2102,7 @@ mod tests {
                     Box::new(SetItem::Unique(Expression::Name("foo".to_string()))),
                     vec![ComprehensionChunk::For {
                         async: false,
-                        item: vec![Expression::Name("bar".to_string())],
+                        item: small_list![Expression::Name("bar".to_string())],
                         iterator: Expression::Name("baz".to_string()),
                     }],
                 )),
@@ -1813,7 +2122,7 @@ mod tests {
                     Box::new(SetItem::Unique(Expression::Name("foo".to_string()))),
                     vec![ComprehensionChunk::For {
                         async: false,
-                        item: vec![Expression::Name("bar".to_string())],
+                        item: small_list![Expression::Name("bar".to_string())],
                         iterator: Expression::Name("baz".to_string()),
                     }],
                 ),
@@ -1834,12 +2143,12 @@ mod tests {
                     vec![
                         ComprehensionChunk::For {
                             async: false,
-                            item: vec![Expression::Name("bar".to_string())],
+                            item: small_list![Expression::Name("bar".to_string())],
                             iterator: Expression::Name("baz".to_string()),
                         },
                         ComprehensionChunk::For {
                             async: false,
-                            item: vec![Expression::Name("qux".to_string())],
+                            item: small_list![Expression::Name("qux".to_string())],
                             iterator: Expression::Name("quux".to_string()),
                         },
                     ],
@@ -1861,7 +2170,7 @@ mod tests {
                     vec![
                         ComprehensionChunk::For {
                             async: false,
-                            item: vec![Expression::Name("bar".to_string())],
+                            item: small_list![Expression::Name("bar".to_string())],
                             iterator: Expression::Name("baz".to_string()),
                         },
                         ComprehensionChunk::If {
@@ -1885,7 +2194,7 @@ mod tests {
                     Box::new(SetItem::Unique(Expression::Name("foo".to_string()))),
                     vec![ComprehensionChunk::For {
                         async: false,
-                        item: vec![Expression::TupleLiteral(vec![
+                        item: small_list![Expression::TupleLiteral(vec![
                             SetItem::Unique(Expression::Name("bar".to_string())),
                             SetItem::Unique(Expression::Name("baz".to_string())),
                         ])],
@@ -1908,7 +2217,7 @@ mod tests {
                     Box::new(SetItem::Unique(Expression::Name("foo".to_string()))),
                     vec![ComprehensionChunk::For {
                         async: false,
-                        item: vec![Expression::Name("bar".to_string())],
+                        item: small_list![Expression::Name("bar".to_string())],
                         iterator: Expression::Name("baz".to_string()),
                     }],
                 )),
@@ -2059,7 +2368,7 @@ mod tests {
             testlist_star_expr(make_strspan("foo,")),
             Ok((
                 make_strspan(""),
-                vec![Expression::TupleLiteral(vec![SetItem::Unique(
+                small_list![Expression::TupleLiteral(vec![SetItem::Unique(
                     Expression::Name("foo".to_string()),
                 )])],
             )),
@@ -0,0 +1,139 @@
+//! Hooks to parse Python with non-standard top-level lines, like the
+//! `%magic` and `!shell` lines of notebooks or the directives of a DSL
+//! embedded in Python.
+//!
+//! Top-level lines that cannot be parsed as a statement are offered to
+//! the extensions, and the first one accepting a line makes it a
+//! `Statement::Extension` node instead of a syntax error.
+
+use nom::Slice;
+
+use ast::{Module, Statement};
+use errors::ParseError;
+use helpers::{make_strspan, StrSpan};
+use statements::statement;
+use stream::skip_blank_lines;
+use {make_module, parse_with};
+
+/// A grammar extension. Extensions are `Send` and `Sync`, like lint
+/// rules, so sets of extensions can be shared between threads.
+pub trait Extension: Send + Sync {
+    /// Name of the extension, in its `Statement::Extension` nodes.
+    fn name(&self) -> &str;
+
+    /// Whether `line` (a top-level line without its line break, which
+    /// is not a Python statement) is a statement of the extension.
+    fn accepts(&self, line: &str) -> bool;
+}
+
+/// Returns the line at the start of `i` without its line break, and
+/// the code after its line break.
+fn split_line<'a>(i: StrSpan<'a>) -> (&'a str, StrSpan<'a>) {
+    let code = i.fragment.0;
+    let end = code.find('\n').map_or(code.len(), |end| end + 1);
+    let line = code[..end].trim_end_matches('\n').trim_end_matches('\r');
+    (line, i.slice(end..))
+}
+
+fn statements(code: &str, extensions: &[Box<dyn Extension>]) -> Result<Vec<Statement>, ParseError> {
+    let mut stmts = Vec::new();
+    let mut i = make_strspan(code);
+    while let Some(next) = skip_blank_lines(i)? {
+        match statement(next, 0) {
+            Ok((rest, new_stmts)) => {
+                stmts.extend(new_stmts);
+                i = rest;
+            }
+            Err(e) => {
+                let (line, rest) = split_line(next);
+                let extension = match extensions.iter().find(|ext| ext.accepts(line)) {
+                    Some(extension) => extension,
+                    None => return Err(e.into()),
+                };
+                stmts.push(Statement::Extension(
+                    extension.name().to_string(),
+                    line.to_string(),
+                ));
+                i = rest;
+            }
+        }
+    }
+    Ok(stmts)
+}
+
+/// Parses a module like `parse_module`, with the top-level lines
+/// accepted by `extensions` as `Statement::Extension` nodes.
+///
+/// ```
+/// use python_parser::ast::Statement;
+/// use python_parser::extensions::{parse_module_with_extensions, Extension};
+///
+/// struct Magics;
+///
+/// impl Extension for Magics {
+///     fn name(&self) -> &str {
+///         "magic"
+///     }
+///     fn accepts(&self, line: &str) -> bool {
+///         line.starts_with('%')
+///     }
+/// }
+///
+/// let extensions: Vec<Box<dyn Extension>> = vec![Box::new(Magics)];
+/// let code = "import numpy\n%timeit numpy.arange(10)\nx = 1\n";
+/// let module = parse_module_with_extensions(code, &extensions).unwrap();
+/// assert_eq!(
+///     module.body[1],
+///     Statement::Extension("magic".to_string(), "%timeit numpy.arange(10)".to_string())
+/// );
+/// assert!(parse_module_with_extensions("!ls\n", &extensions).is_err());
+/// ```
+pub fn parse_module_with_extensions(
+    code: &str,
+    extensions: &[Box<dyn Extension>],
+) -> Result<Module, ParseError> {
+    parse_with(code, |code| {
+        let body = statements(code, extensions)?;
+        Ok(make_module(code, body))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parse_module;
+    use visitors::printer::format_module;
+
+    struct Shell;
+
+    impl Extension for Shell {
+        fn name(&self) -> &str {
+            "shell"
+        }
+        fn accepts(&self, line: &str) -> bool {
+            line.starts_with('!')
+        }
+    }
+
+    #[test]
+    fn test_extensions() {
+        let extensions: Vec<Box<dyn Extension>> = vec![Box::new(Shell)];
+        let code = "!pip install x\r\nif y:\n    z = 1\n\n!ls -l\n";
+        let module = parse_module_with_extensions(code, &extensions).unwrap();
+        assert_eq!(module.body.len(), 3);
+        assert_eq!(
+            module.body[2],
+            Statement::Extension("shell".to_string(), "!ls -l".to_string())
+        );
+        assert_eq!(
+            format_module(&module.body),
+            "!pip install x\nif y:\n    z = 1\n!ls -l\n"
+        );
+
+        // Lines in blocks are not offered to the extensions, and errors
+        // are the ones of `parse_module`
+        let code = "x = 1\nif y:\n    !ls\n";
+        let error = parse_module(code).unwrap_err();
+        assert_eq!(parse_module_with_extensions(code, &extensions), Err(error));
+    }
+}
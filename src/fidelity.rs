@@ -0,0 +1,338 @@
+//! Details of the source which are not in the AST: which expressions
+//! are explicitly parenthesized, and how string literals are quoted, so
+//! that formatters can keep them and linters can report redundant
+//! parentheses.
+//!
+//! Like `selection`, they are found from the tokens of the code.
+
+use keywords::{is_keyword, PARSED_VERSION};
+use lint::{is_name, is_op, top_level};
+use span::{Span, Spanned};
+use tokenizer::{tokenize, Token, TokenKind};
+
+/// Parentheses grouping an expression, found by `parentheses`. The
+/// parentheses of calls, tuples and generator expressions are not
+/// groupings.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Parentheses {
+    pub open: Span,
+    pub close: Span,
+    /// Line of the opening parenthesis, starting from 1.
+    pub line: u32,
+    /// Whether removing the parentheses would not change the code: they
+    /// are around a single name, number or string, around the whole
+    /// contents of other brackets, or around the whole value of a
+    /// statement on a single line (like `return (x + 1)` or
+    /// `if (x):`). A space may be needed instead of them (like in
+    /// `not(x)`).
+    pub redundant: bool,
+}
+
+impl Spanned for Parentheses {
+    fn span(&self) -> Span {
+        self.open.cover(self.close)
+    }
+}
+
+/// Keywords followed by the whole value of their statement, and the
+/// token ending it (empty for the end of the statement).
+const VALUE_KEYWORDS: [(&str, &str); 7] = [
+    ("return", ""),
+    ("del", ""),
+    ("assert", ""),
+    ("if", ":"),
+    ("elif", ":"),
+    ("while", ":"),
+    ("in", ":"),
+];
+
+/// Whether a token ends a statement: a newline, `;`, or the `:` of a
+/// header if `end` is `:`.
+fn ends_statement(token: Option<&Token>, end: &str) -> bool {
+    match token {
+        None => true,
+        Some(token) => {
+            token.kind == TokenKind::Newline
+                || token.text == ";"
+                || (end == ":" && token.text == ":")
+        }
+    }
+}
+
+/// Returns the index of the bracket closing each opening bracket of
+/// `tokens`, if it is closed.
+fn closings(tokens: &[Token]) -> Vec<Option<usize>> {
+    let mut closings = vec![None; tokens.len()];
+    let mut open = Vec::new();
+    for (i, token) in tokens.iter().enumerate() {
+        if token.kind != TokenKind::Op {
+            continue;
+        }
+        match token.text {
+            "(" | "[" | "{" => open.push(i),
+            ")" | "]" | "}" => {
+                if let Some(start) = open.pop() {
+                    closings[start] = Some(i);
+                }
+            }
+            _ => (),
+        }
+    }
+    closings
+}
+
+/// Returns the grouping parentheses of a logical line, given the
+/// closing brackets of its tokens.
+fn line_parentheses(tokens: &[Token], closings: &[Option<usize>], found: &mut Vec<Parentheses>) {
+    let statement_start = |i: usize| {
+        tokens[..i]
+            .iter()
+            .rposition(|t| is_op(Some(t), ";"))
+            .map_or(0, |i| i + 1)
+    };
+    for (open, token) in tokens.iter().enumerate() {
+        let close = match closings[open] {
+            Some(close) if token.text == "(" => close,
+            _ => continue,
+        };
+        let previous = if open == 0 {
+            None
+        } else {
+            tokens.get(open - 1)
+        };
+        let is_trailer = previous.map_or(false, |t| match t.kind {
+            TokenKind::Name => !is_keyword(t.text, PARSED_VERSION),
+            TokenKind::String => true,
+            TokenKind::Op => [")", "]", "}"].contains(&t.text),
+            _ => false,
+        });
+        let contents = &tokens[open + 1..close];
+        let first = &tokens[statement_start(open)];
+        if is_trailer
+            || contents.is_empty()
+            || top_level(contents, ",").is_some()
+            || contents
+                .iter()
+                .any(|t| is_name(Some(t), "for") || is_name(Some(t), "yield"))
+            || first.text == "import"
+            || first.text == "from"
+        {
+            continue;
+        }
+        let next = tokens.get(close + 1);
+        let needed = top_level(contents, ":=").is_some() || is_name(contents.first(), "lambda");
+        let is_atom = contents.len() == 1
+            && match contents[0].kind {
+                TokenKind::Name => {
+                    !is_keyword(contents[0].text, PARSED_VERSION)
+                        || ["True", "False", "None"].contains(&contents[0].text)
+                }
+                TokenKind::String => true,
+                // `(1).real` is not `1.real`
+                TokenKind::Number => !is_op(next, "."),
+                _ => false,
+            };
+        let is_contents = open > 0
+            && closings[open - 1] == Some(close + 1)
+            && ["(", "[", "{"].contains(&tokens[open - 1].text);
+        let is_value = tokens[close].line == token.line
+            && previous.map_or(false, |previous| {
+                let at_start = open >= 1 && statement_start(open) == open - 1;
+                let keyword = VALUE_KEYWORDS.iter().find(|&&(keyword, end)| {
+                    previous.text == keyword
+                        && previous.kind == TokenKind::Name
+                        && (at_start || (keyword == "in" && first.text == "for" && end == ":"))
+                });
+                match keyword {
+                    Some(&(_, end)) => ends_statement(next, end),
+                    None => {
+                        let is_assignment = previous.kind == TokenKind::Op
+                            && previous.text.ends_with('=')
+                            && !["==", "<=", ">=", "!="].contains(&previous.text);
+                        // Not a keyword argument or a default value
+                        let nested = (0..open).any(|i| match closings[i] {
+                            Some(closing) => closing > close,
+                            None => ["(", "[", "{"].contains(&tokens[i].text),
+                        });
+                        is_assignment && !nested && ends_statement(next, "")
+                    }
+                }
+            });
+        found.push(Parentheses {
+            open: token.span(),
+            close: tokens[close].span(),
+            line: token.line,
+            redundant: !needed && (is_atom || is_contents || is_value),
+        });
+    }
+}
+
+/// Returns the parentheses grouping expressions in `code`, in the order
+/// of the code.
+///
+/// ```
+/// use python_parser::fidelity::parentheses;
+///
+/// let code = "x = (a + b) * c\nif (x):\n    print((x), (1, 2))\n";
+/// let found: Vec<_> = parentheses(code)
+///     .iter()
+///     .map(|p| (&code[p.open.start..p.close.end], p.redundant))
+///     .collect();
+/// assert_eq!(found, vec![("(a + b)", false), ("(x)", true), ("(x)", true)]);
+/// ```
+pub fn parentheses(code: &str) -> Vec<Parentheses> {
+    let mut found = Vec::new();
+    let mut line = Vec::new();
+    for token in tokenize(code) {
+        match token.kind {
+            TokenKind::Comment
+            | TokenKind::Nl
+            | TokenKind::Indent
+            | TokenKind::Dedent
+            | TokenKind::EndMarker => (),
+            TokenKind::Newline => {
+                line.push(token);
+                line_parentheses(&line, &closings(&line), &mut found);
+                line.clear();
+            }
+            _ => line.push(token),
+        }
+    }
+    found
+}
+
+/// A string literal, and how it is written.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct StringLiteral<'a> {
+    pub span: Span,
+    /// The prefix of the literal (like `rb` or `f`), as written.
+    pub prefix: &'a str,
+    /// `'` or `"`.
+    pub quote: char,
+    /// Whether the literal is triple-quoted.
+    pub triple: bool,
+}
+
+impl<'a> Spanned for StringLiteral<'a> {
+    fn span(&self) -> Span {
+        self.span
+    }
+}
+
+/// Returns the string literals of `code` (each of the parts of an
+/// implicit concatenation), in the order of the code.
+///
+/// ```
+/// use python_parser::fidelity::string_literals;
+///
+/// let strings = string_literals("x = 'a' rb\"b\" + '''c'''");
+/// let quoting: Vec<_> = strings.iter().map(|s| (s.prefix, s.quote, s.triple)).collect();
+/// assert_eq!(quoting, vec![("", '\'', false), ("rb", '"', false), ("", '\'', true)]);
+/// ```
+pub fn string_literals(code: &str) -> Vec<StringLiteral<'_>> {
+    tokenize(code)
+        .filter(|t| t.kind == TokenKind::String)
+        .filter_map(|t| {
+            let quote_start = t.text.find(|c| c == '\'' || c == '"')?;
+            let quote = t.text[quote_start..].chars().next()?;
+            let triple_quote: String = ::std::iter::repeat(quote).take(3).collect();
+            Some(StringLiteral {
+                span: t.span(),
+                prefix: &t.text[..quote_start],
+                quote,
+                triple: t.text[quote_start..].starts_with(&triple_quote[..]),
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns the code of the grouping parentheses of `code`, and
+    /// whether they are redundant.
+    fn found(code: &str) -> Vec<(&str, bool)> {
+        parentheses(code)
+            .into_iter()
+            .map(|p| (&code[p.open.start..p.close.end], p.redundant))
+            .collect()
+    }
+
+    #[test]
+    fn test_parentheses() {
+        // Calls, definitions, tuples, generators and imports
+        let code = "f(x)\ndef g(a): pass\nclass A(B): pass\nt = (1, 2)\ne = ()\n\
+                    s = sum((x for x in y))\nfrom m import (a)\nx = (yield)\n";
+        assert_eq!(found(code), vec![]);
+        assert_eq!(
+            found("x = (a) + (-1) + (1).real + (1.5) + ('s')"),
+            vec![
+                ("(a)", true),
+                ("(-1)", false),
+                ("(1)", false),
+                ("(1.5)", true),
+                ("('s')", true),
+            ]
+        );
+        assert_eq!(
+            found("f((a + b), [(c or d)], ((e)))"),
+            vec![
+                ("(a + b)", false),
+                ("(c or d)", true),
+                ("((e))", false),
+                ("(e)", true),
+            ]
+        );
+        assert_eq!(found("f((a + b))"), vec![("(a + b)", true)]);
+        // Whole values of statements
+        let code = "x = (a + b)\nx += (a + b)\nx = (a + b) * c\nreturn (a or b)\n\
+                    if (a and b):\n    del (x.y)\nwhile (not a): pass\nfor x in (a + b): pass\n\
+                    y = (a +\n     b)\nassert (a == b); z = (a == b)\n";
+        assert_eq!(
+            found(code),
+            vec![
+                ("(a + b)", true),
+                ("(a + b)", true),
+                ("(a + b)", false),
+                ("(a or b)", true),
+                ("(a and b)", true),
+                ("(x.y)", true),
+                ("(not a)", true),
+                ("(a + b)", true),
+                ("(a +\n     b)", false),
+                ("(a == b)", true),
+                ("(a == b)", true),
+            ]
+        );
+        // Needed parentheses
+        let code = "x = (y := 1)\nif (lambda: 1): pass\nx = a if (b) else c\nx == (a or b)\n";
+        assert_eq!(
+            found(code),
+            vec![
+                ("(y := 1)", false),
+                ("(lambda: 1)", false),
+                ("(b)", true),
+                ("(a or b)", false),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_string_literals() {
+        let code = "x = f'{a!r}' Rb'''b''' \"c\" \"\"\"d\"\"\"  # 'e'";
+        let strings: Vec<_> = string_literals(code)
+            .iter()
+            .map(|s| (&code[s.span.start..s.span.end], s.prefix, s.quote, s.triple))
+            .collect();
+        assert_eq!(
+            strings,
+            vec![
+                ("f'{a!r}'", "f", '\'', false),
+                ("Rb'''b'''", "Rb", '\'', true),
+                ("\"c\"", "", '"', false),
+                ("\"\"\"d\"\"\"", "", '"', true),
+            ]
+        );
+    }
+}
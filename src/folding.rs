@@ -0,0 +1,241 @@
+//! Foldable regions of a module, like the ones of the
+//! `textDocument/foldingRange` request of the Language Server Protocol.
+//!
+//! They are found from the tokens of the code, so incomplete code has
+//! folding ranges too.
+
+use keywords::{is_keyword, PARSED_VERSION};
+use tokenizer::{line_breaks, tokenize, Token, TokenKind};
+
+/// What a `FoldingRange` contains.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum FoldingKind {
+    /// A function or class definition, with its body.
+    Definition,
+    /// A list, tuple, dict or set literal (or an expression in
+    /// parentheses), or a string literal, on several lines.
+    Literal,
+    /// Consecutive import statements.
+    Imports,
+    /// The docstring of a module, class or function.
+    Docstring,
+}
+
+/// A region of the code which can be folded, from the start of
+/// `start_line` to the end of `end_line` (starting from 1).
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct FoldingRange {
+    pub start_line: u32,
+    pub end_line: u32,
+    pub kind: FoldingKind,
+}
+
+/// Line of the end of a token.
+fn end_line(token: &Token) -> u32 {
+    token.line + line_breaks(token.text)
+}
+
+/// Whether a bracket after `previous` starts the arguments of a call or
+/// a subscript, rather than a literal.
+fn is_trailer(previous: Option<&Token>) -> bool {
+    match previous {
+        Some(token) => match token.kind {
+            TokenKind::Name => !is_keyword(token.text, PARSED_VERSION),
+            TokenKind::String => true,
+            TokenKind::Op => [")", "]", "}"].contains(&token.text),
+            _ => false,
+        },
+        None => false,
+    }
+}
+
+/// Returns the folding ranges of `code`, sorted by their start line
+/// (and outer ranges first).
+///
+/// ```
+/// use python_parser::folding::{folding_ranges, FoldingKind, FoldingRange};
+///
+/// let code = "import os\nimport sys\n\ndef f():\n    '''Doc\n    string.'''\n    return [\n        1,\n    ]\n";
+/// let range = |start_line, end_line, kind| FoldingRange { start_line, end_line, kind };
+/// assert_eq!(
+///     folding_ranges(code),
+///     vec![
+///         range(1, 2, FoldingKind::Imports),
+///         range(4, 9, FoldingKind::Definition),
+///         range(5, 6, FoldingKind::Docstring),
+///         range(7, 9, FoldingKind::Literal),
+///     ]
+/// );
+/// ```
+pub fn folding_ranges(code: &str) -> Vec<FoldingRange> {
+    let mut ranges = Vec::new();
+    let mut push = |start_line, end_line, kind| {
+        if end_line > start_line {
+            ranges.push(FoldingRange {
+                start_line,
+                end_line,
+                kind,
+            })
+        }
+    };
+    // For each indented block, the line of its definition, if it is
+    // the body of a function or class
+    let mut blocks: Vec<Option<u32>> = Vec::new();
+    // Lines of the open brackets, and whether they start a literal
+    let mut brackets: Vec<(u32, bool)> = Vec::new();
+    // Start line, end line and depth of the current import statements
+    let mut imports: Option<(u32, u32, usize)> = None;
+    // Line of the current definition, until its body starts
+    let mut definition: Option<u32> = None;
+    // Whether the next logical line may be a docstring
+    let mut docstring_allowed = true;
+    let mut at_line_start = true;
+    let mut line_tokens: Vec<Token> = Vec::new();
+    let mut last_line = 1;
+
+    for token in tokenize(code) {
+        match token.kind {
+            TokenKind::Nl | TokenKind::Comment => continue,
+            TokenKind::Indent => {
+                blocks.push(definition.take());
+                continue;
+            }
+            TokenKind::Dedent => {
+                if let Some(Some(start_line)) = blocks.pop() {
+                    push(start_line, last_line, FoldingKind::Definition);
+                }
+                continue;
+            }
+            TokenKind::Newline => {
+                let first = line_tokens.first().map(|t| t.text);
+                // The first statement of a module or body
+                let is_docstring = docstring_allowed
+                    && line_tokens.len() == 1
+                    && line_tokens[0].kind == TokenKind::String;
+                if is_docstring {
+                    push(line_tokens[0].line, last_line, FoldingKind::Docstring);
+                }
+                docstring_allowed = false;
+                let is_header = match (first, line_tokens.get(1).map(|t| t.text)) {
+                    (Some("def"), _) | (Some("class"), _) | (Some("async"), Some("def")) => {
+                        line_tokens.last().map(|t| t.text) == Some(":")
+                    }
+                    _ => false,
+                };
+                if is_header {
+                    definition = Some(line_tokens[0].line);
+                    docstring_allowed = true;
+                }
+                if first == Some("import") || first == Some("from") {
+                    let start_line = line_tokens[0].line;
+                    imports = match imports {
+                        Some((start, _, depth)) if depth == blocks.len() => {
+                            Some((start, last_line, depth))
+                        }
+                        _ => Some((start_line, last_line, blocks.len())),
+                    };
+                } else if let Some((start, end, _)) = imports.take() {
+                    push(start, end, FoldingKind::Imports);
+                }
+                line_tokens.clear();
+                at_line_start = true;
+                continue;
+            }
+            TokenKind::EndMarker => break,
+            _ => (),
+        }
+        if at_line_start {
+            at_line_start = false;
+            if let Some((start, end, depth)) = imports {
+                // Imports are only consecutive in the same block
+                if depth != blocks.len() {
+                    push(start, end, FoldingKind::Imports);
+                    imports = None;
+                }
+            }
+        }
+        if token.kind == TokenKind::Op {
+            match token.text {
+                "(" | "[" | "{" => {
+                    brackets.push((token.line, !is_trailer(line_tokens.last())));
+                }
+                ")" | "]" | "}" => {
+                    if let Some((start_line, true)) = brackets.pop() {
+                        push(start_line, token.line, FoldingKind::Literal);
+                    }
+                }
+                _ => (),
+            }
+        }
+        if token.kind == TokenKind::String && !(docstring_allowed && line_tokens.is_empty()) {
+            push(token.line, end_line(&token), FoldingKind::Literal);
+        }
+        last_line = end_line(&token);
+        line_tokens.push(token);
+    }
+    if let Some((start, end, _)) = imports {
+        push(start, end, FoldingKind::Imports);
+    }
+    ranges.sort_by_key(|range| (range.start_line, !range.end_line, range.kind));
+    ranges
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn range(start_line: u32, end_line: u32, kind: FoldingKind) -> FoldingRange {
+        FoldingRange {
+            start_line,
+            end_line,
+            kind,
+        }
+    }
+
+    #[test]
+    fn test_folding_ranges() {
+        let code = "'''Module
+docstring.'''
+from os import (
+    path,
+)
+import sys
+x = '''not a
+docstring'''
+
+@decorator
+class A(B):
+    import re
+    import json
+
+    async def f(self,
+                x):
+        y = f(1,
+              2)
+        z = {
+            'a': (1,
+                  2),
+        }
+        # comment
+
+    def g(self): pass
+    def h(self):
+        return 1
+";
+        assert_eq!(
+            folding_ranges(code),
+            vec![
+                range(1, 2, FoldingKind::Docstring),
+                range(3, 6, FoldingKind::Imports),
+                range(3, 5, FoldingKind::Literal),
+                range(7, 8, FoldingKind::Literal),
+                range(11, 27, FoldingKind::Definition),
+                range(12, 13, FoldingKind::Imports),
+                range(15, 22, FoldingKind::Definition),
+                range(19, 22, FoldingKind::Literal),
+                range(20, 21, FoldingKind::Literal),
+                range(26, 27, FoldingKind::Definition),
+            ]
+        );
+    }
+}
@@ -1,8 +1,10 @@
+use std::collections::HashSet;
 use std::marker::PhantomData;
 
 use nom::IResult;
 
 use ast::*;
+use errors::PyParseError;
 use expressions::ExpressionParser;
 use helpers::*;
 use statements::{block, func_body_suite, ImportParser};
@@ -25,7 +27,7 @@ named_args!(decorator(indent: usize) <StrSpan, Decorator>,
 );
 
 // decorators: decorator+
-named_args!(decorators(indent: usize) <StrSpan, Vec<Decorator>>,
+named_args!(pub decorators(indent: usize) <StrSpan, Vec<Decorator>>,
   many0!(call!(decorator, indent))
 );
 
@@ -34,8 +36,8 @@ named_args!(pub decorated(indent: usize) <StrSpan, CompoundStatement>,
   do_parse!(
     decorators: call!(decorators, indent) >>
     s: switch!(peek!(preceded!(indent!(indent), first_word)),
-        "def" => call!(funcdef, indent, decorators.clone()) // FIXME: do not clone
-      | "async" => call!(funcdef, indent, decorators.clone()) // FIXME: do not clone
+        "def" => call!(funcdef, indent, decorators)
+      | "async" => call!(funcdef, indent, decorators)
       | "class" => call!(classdef, indent, decorators)
     ) >> (s)
   )
@@ -48,6 +50,16 @@ named_args!(pub decorated(indent: usize) <StrSpan, CompoundStatement>,
 // async_funcdef: 'async' funcdef
 // funcdef: 'def' NAME parameters ['->' test] ':' [TYPE_COMMENT] func_body_suite
 named_args!(funcdef(indent: usize, decorators: Vec<Decorator>) <StrSpan, CompoundStatement>,
+  do_parse!(
+    def: call!(funcdef_header, indent, decorators) >>
+    code: call!(func_body_suite, indent) >> (
+      CompoundStatement::Funcdef(Funcdef { code, ..def })
+    )
+  )
+);
+
+// Everything in funcdef up to its body; returns a Funcdef with empty code
+named_args!(pub funcdef_header(indent: usize, decorators: Vec<Decorator>) <StrSpan, Funcdef>,
   do_parse!(
     indent!(indent) >>
     async: opt!(tuple!(tag!("async"), space_sep_nonl)) >>
@@ -56,17 +68,26 @@ named_args!(funcdef(indent: usize, decorators: Vec<Decorator>) <StrSpan, Compoun
     name: name >>
     parameters: ws_nonl!(parameters) >>
     return_type: opt!(ws_nonl!(preceded!(tag!("->"), call!(ExpressionParser::<NewlinesAreNotSpaces>::test)))) >>
-    ws_nonl!(char!(':')) >>
-    code: call!(func_body_suite, indent) >> (
-      CompoundStatement::Funcdef(Funcdef {
-          async: async.is_some(), decorators, name, parameters, return_type: return_type.map(|t| *t), code
-      })
+    ws_nonl!(char!(':')) >> (
+      Funcdef {
+          async: async.is_some(), decorators, name, parameters, return_type: return_type.map(|t| *t), code: Vec::new()
+      }
     )
   )
 );
 
 // classdef: 'class' NAME ['(' [arglist] ')'] ':' suite
 named_args!(classdef(indent: usize, decorators: Vec<Decorator>) <StrSpan, CompoundStatement>,
+  do_parse!(
+    def: call!(classdef_header, indent, decorators) >>
+    code: call!(block, indent) >> (
+      CompoundStatement::Classdef(Classdef { code, ..def })
+    )
+  )
+);
+
+// Everything in classdef up to its body; returns a Classdef with empty code
+named_args!(pub classdef_header(indent: usize, decorators: Vec<Decorator>) <StrSpan, Classdef>,
   do_parse!(
     indent!(indent) >>
     tag!("class") >>
@@ -74,11 +95,48 @@ named_args!(classdef(indent: usize, decorators: Vec<Decorator>) <StrSpan, Compou
     name: name >>
     spaces_nonl >>
     arguments: opt!(ws_nonl!(delimited!(char!('('), ws_comm!(call!(ExpressionParser::<NewlinesAreSpaces>::arglist)), char!(')')))) >>
-    ws_nonl!(char!(':')) >>
-    code: call!(block, indent) >> (
-      CompoundStatement::Classdef(Classdef {
-          decorators, name, arguments: arguments.unwrap_or_default(), code
-      })
+    ws_nonl!(char!(':')) >> (
+      Classdef {
+          decorators, name, arguments: arguments.unwrap_or_default(), code: Vec::new()
+      }
+    )
+  )
+);
+
+// func_type: '(' [typelist] ')' '->' test
+named!(pub func_type<StrSpan, FuncType>,
+  do_parse!(
+    char!('(') >>
+    args: ws_comm!(typelist) >>
+    char!(')') >>
+    ws_nonl!(tag!("->")) >>
+    return_type: call!(ExpressionParser::<NewlinesAreNotSpaces>::test) >> (
+      FuncType { return_type: *return_type, ..args }
+    )
+  )
+);
+
+// typelist: (test (',' test)* [',' ['*' test [',' '**' test] | '**' test]]
+//         |  '*' test [',' '**' test] | '**' test)
+//
+// Returns a FuncType whose return_type is `None`.
+named!(typelist<StrSpan, FuncType>,
+  do_parse!(
+    args: separated_list!(ws_comm!(char!(',')), call!(ExpressionParser::<NewlinesAreSpaces>::test)) >>
+    star_args: opt!(preceded!(
+      cond_with_error!(!args.is_empty(), ws_comm!(char!(','))),
+      preceded!(ws_comm!(char!('*')), call!(ExpressionParser::<NewlinesAreSpaces>::test))
+    )) >>
+    star_kwargs: opt!(preceded!(
+      cond_with_error!(!args.is_empty() || star_args.is_some(), ws_comm!(char!(','))),
+      preceded!(ws_comm!(tag!("**")), call!(ExpressionParser::<NewlinesAreSpaces>::test))
+    )) >> (
+      FuncType {
+          args: args.into_iter().map(|e| *e).collect(),
+          star_args: star_args.map(|e| *e),
+          star_kwargs: star_kwargs.map(|e| *e),
+          return_type: Expression::None,
+      }
     )
   )
 );
@@ -93,6 +151,9 @@ trait IsItTyped {
 
     fn fpdef<'a>(input: StrSpan<'a>) -> IResult<StrSpan<'a>, Self::Return, u32>;
 
+    /// Returns the name of a parameter returned by `fpdef`.
+    fn name(param: &Self::Return) -> &Name;
+
     fn fpdef_with_default<'a>(
         i: StrSpan<'a>,
     ) -> IResult<StrSpan<'a>, (Self::Return, Option<Box<Expression>>), u32> {
@@ -129,6 +190,10 @@ impl IsItTyped for Typed {
       ))
     );
 
+    fn name(param: &Self::Return) -> &Name {
+        &param.0
+    }
+
     fn make_list(
         posonly_args: Vec<(Self::Return, Option<Box<Expression>>)>,
         args: Vec<(Self::Return, Option<Box<Expression>>)>,
@@ -170,6 +235,10 @@ impl IsItTyped for Untyped {
       tuple!(name)
     );
 
+    fn name(param: &Self::Return) -> &Name {
+        param
+    }
+
     fn make_list(
         posonly_args: Vec<(Self::Return, Option<Box<Expression>>)>,
         args: Vec<(Self::Return, Option<Box<Expression>>)>,
@@ -255,32 +324,37 @@ struct ParamlistParser<IIT: IsItTyped> {
     phantom: PhantomData<IIT>,
 }
 impl<IIT: IsItTyped> ParamlistParser<IIT> {
+    // vfpdef, with its position
+    named!(located_fpdef<StrSpan, (StrSpan, IIT::Return)>,
+      tuple!(preceded!(spaces_nl, position), call!(IIT::fpdef))
+    );
+
     // arguments = argument (',' argument )*
-    named!(arguments<StrSpan, Vec<(IIT::Return, Option<Box<Expression>>)>>,
+    named!(arguments<StrSpan, Vec<((StrSpan, IIT::Return), Option<Box<Expression>>)>>,
       ws_comm!(separated_nonempty_list!(char!(','), call!(Self::argument)))
     );
 
     // argument = vfpdef ['=' test]
-    named!(argument<StrSpan, (IIT::Return, Option<Box<Expression>>)>,
+    named!(argument<StrSpan, ((StrSpan, IIT::Return), Option<Box<Expression>>)>,
       ws_comm!(tuple!(
-        call!(IIT::fpdef),
+        call!(Self::located_fpdef),
         opt!(ws_comm!(preceded!(char!('='), call!(ExpressionParser::<NewlinesAreSpaces>::test))))
       ))
     );
 
     // kwargs = '**' vfpdef [',']
-    named!(kwargs<StrSpan, IIT::Return>,
-      ws_comm!(delimited!(tag!("**"), call!(IIT::fpdef), opt!(char!(','))))
+    named!(kwargs<StrSpan, (StrSpan, IIT::Return)>,
+      ws_comm!(delimited!(tag!("**"), call!(Self::located_fpdef), opt!(char!(','))))
     );
 
     // args = '*' [vfpdef]
-    named!(args<StrSpan, Option<IIT::Return>>,
-      ws_comm!(preceded!(tag!("*"), opt!(call!(IIT::fpdef))))
+    named!(args<StrSpan, Option<(StrSpan, IIT::Return)>>,
+      ws_comm!(preceded!(tag!("*"), opt!(call!(Self::located_fpdef))))
     );
 
     // kwonly_kwargs = (',' argument )* [',' [kwargs]]
     // returns (vec![kwonly_argument], kwargs)
-    named!(kwonly_kwargs<StrSpan, (Vec<(IIT::Return, Option<Box<Expression>>)>, Option<IIT::Return>)>,
+    named!(kwonly_kwargs<StrSpan, (Vec<((StrSpan, IIT::Return), Option<Box<Expression>>)>, Option<(StrSpan, IIT::Return)>)>,
       do_parse!(
         arguments: ws_comm!(many0!(preceded!(char!(','), call!(Self::argument)))) >>
         kwargs: opt!(ws_comm!(preceded!(char!(','), opt!(Self::kwargs)))) >> (
@@ -291,12 +365,15 @@ impl<IIT: IsItTyped> ParamlistParser<IIT> {
 
     // args_kwonly_kwargs = args kwonly_kwargs | kwargs
     // returns (args, vec![kwonly_argument], kwargs)
-    named!(args_kwonly_kwargs<StrSpan, (Option<Option<IIT::Return>>, Vec<(IIT::Return, Option<Box<Expression>>)>, Option<IIT::Return>)>,
+    named!(args_kwonly_kwargs<StrSpan, (Option<Option<(StrSpan, IIT::Return)>>, Vec<((StrSpan, IIT::Return), Option<Box<Expression>>)>, Option<(StrSpan, IIT::Return)>)>,
       alt!(
         call!(Self::kwargs) => {|kwargs| (None, Vec::new(), Option::Some(kwargs))}
       | do_parse!(
+          star: preceded!(spaces_nl, position) >>
           args: call!(Self::args) >>
-          kwonly_kwargs: call!(Self::kwonly_kwargs) >> ({
+          kwonly_kwargs: call!(Self::kwonly_kwargs) >>
+          // Like CPython, reject a bare star without keyword-only parameters
+          call!(fail_if, args.is_none() && kwonly_kwargs.0.is_empty(), star, PyParseError::NoParameterAfterBareStar) >> ({
             let (arguments, kwargs) = kwonly_kwargs;
             (Option::Some(args), arguments, kwargs)
           })
@@ -306,7 +383,7 @@ impl<IIT: IsItTyped> ParamlistParser<IIT> {
 
     // poskeyword_args_kwonly_kwargs = arguments [',' [args_kwonly_kwargs]]
     // returns (vec![argument], args, vec![kwonly_argument], kwargs)
-    named!(poskeyword_args_kwonly_kwargs<StrSpan, (Vec<(IIT::Return, Option<Box<Expression>>)>, Option<Option<IIT::Return>>, Vec<(IIT::Return, Option<Box<Expression>>)>, Option<IIT::Return>)>,
+    named!(poskeyword_args_kwonly_kwargs<StrSpan, (Vec<((StrSpan, IIT::Return), Option<Box<Expression>>)>, Option<Option<(StrSpan, IIT::Return)>>, Vec<((StrSpan, IIT::Return), Option<Box<Expression>>)>, Option<(StrSpan, IIT::Return)>)>,
       do_parse!(
         arguments: call!(Self::arguments) >>
         rest: opt!(ws_comm!(preceded!(char!(','), opt!(call!(Self::args_kwonly_kwargs))))) >> ({
@@ -322,7 +399,7 @@ impl<IIT: IsItTyped> ParamlistParser<IIT> {
 
     // varargslist_no_posonly = poskeyword_args_kwonly_kwargs | args_kwonly_kwargs
     // returns (vec![argument], args, vec![kwonly_argument], kwargs)
-    named!(varargslist_no_posonly<StrSpan, (Vec<(IIT::Return, Option<Box<Expression>>)>, Option<Option<IIT::Return>>, Vec<(IIT::Return, Option<Box<Expression>>)>, Option<IIT::Return>)>,
+    named!(varargslist_no_posonly<StrSpan, (Vec<((StrSpan, IIT::Return), Option<Box<Expression>>)>, Option<Option<(StrSpan, IIT::Return)>>, Vec<((StrSpan, IIT::Return), Option<Box<Expression>>)>, Option<(StrSpan, IIT::Return)>)>,
       alt!(
         call!(Self::poskeyword_args_kwonly_kwargs)
       | call!(Self::args_kwonly_kwargs) => {|(args, kwonly_arguments, kwargs)|
@@ -333,7 +410,7 @@ impl<IIT: IsItTyped> ParamlistParser<IIT> {
 
     // varargslist = arguments ',' '/' [','[(varargslist_no_posonly)]] | (varargslist_no_posonly)
     // returns (vec![posonly_argument], vec![argument], args, vec![kwonly_argument], kwargs)
-    named!(varargslist<StrSpan, (Vec<(IIT::Return, Option<Box<Expression>>)>, Vec<(IIT::Return, Option<Box<Expression>>)>, Option<Option<IIT::Return>>, Vec<(IIT::Return, Option<Box<Expression>>)>, Option<IIT::Return>)>,
+    named!(varargslist<StrSpan, (Vec<((StrSpan, IIT::Return), Option<Box<Expression>>)>, Vec<((StrSpan, IIT::Return), Option<Box<Expression>>)>, Option<Option<(StrSpan, IIT::Return)>>, Vec<((StrSpan, IIT::Return), Option<Box<Expression>>)>, Option<(StrSpan, IIT::Return)>)>,
       alt!(
         ws_comm!(do_parse!(
           posonly_arguments: ws_comm!(call!(Self::arguments)) >>
@@ -355,14 +432,56 @@ impl<IIT: IsItTyped> ParamlistParser<IIT> {
       )
     );
 
-
-
-    named!(parse<StrSpan, IIT::List>,
-      map!(call!(Self::varargslist), |varargslist| {
+    fn parse(i: StrSpan) -> IResult<StrSpan, IIT::List, u32> {
+        let (i, varargslist) = Self::varargslist(i)?;
         let (posonly_arguments, arguments, args, kwonly_arguments, kwargs) = varargslist;
-        IIT::make_list(posonly_arguments, arguments, args, kwonly_arguments, kwargs)
-      })
-    );
+        // Like CPython, reject positional parameters without a default
+        // value after one with a default value
+        let mut seen_default = false;
+        for &((position, _), ref default) in posonly_arguments.iter().chain(&arguments) {
+            fail_if(
+                i,
+                seen_default && default.is_none(),
+                position,
+                PyParseError::NonDefaultAfterDefault,
+            )?;
+            seen_default |= default.is_some();
+        }
+        // and parameters with the same name
+        let mut names = HashSet::new();
+        let params = posonly_arguments
+            .iter()
+            .chain(&arguments)
+            .map(|(param, _)| param)
+            .chain(args.iter().flatten())
+            .chain(kwonly_arguments.iter().map(|(param, _)| param))
+            .chain(&kwargs);
+        for &(position, ref param) in params {
+            fail_if(
+                i,
+                !names.insert(IIT::name(param)),
+                position,
+                PyParseError::DuplicateParameter,
+            )?;
+        }
+
+        let strip_positions = |arguments: Vec<((_, _), _)>| {
+            arguments
+                .into_iter()
+                .map(|((_, param), default)| (param, default))
+                .collect()
+        };
+        Ok((
+            i,
+            IIT::make_list(
+                strip_positions(posonly_arguments),
+                strip_positions(arguments),
+                args.map(|param| param.map(|(_, param)| param)),
+                strip_positions(kwonly_arguments),
+                kwargs.map(|(_, param)| param),
+            ),
+        ))
+    }
 }
 
 pub(crate) fn typedargslist(i: StrSpan) -> IResult<StrSpan, TypedArgsList, u32> {
@@ -385,7 +504,7 @@ mod tests {
             Ok((
                 make_strspan(""),
                 Decorator {
-                    name: vec!["foo".to_string()],
+                    name: small_list!["foo".to_string()],
                     args: None,
                 },
             )),
@@ -395,7 +514,7 @@ mod tests {
             Ok((
                 make_strspan(""),
                 Decorator {
-                    name: vec!["foo".to_string(), "bar".to_string()],
+                    name: small_list!["foo".to_string(), "bar".to_string()],
                     args: None,
                 },
             )),
@@ -406,7 +525,7 @@ mod tests {
             Ok((
                 make_strspan(""),
                 Decorator {
-                    name: vec!["foo".to_string()],
+                    name: small_list!["foo".to_string()],
                     args: Some(vec![Argument::Positional(Expression::Name(
                         "baz".to_string(),
                     ))]),
@@ -418,7 +537,7 @@ mod tests {
             Ok((
                 make_strspan(""),
                 Decorator {
-                    name: vec!["foo".to_string(), "bar".to_string()],
+                    name: small_list!["foo".to_string(), "bar".to_string()],
                     args: Some(vec![Argument::Positional(Expression::Name(
                         "baz".to_string(),
                     ))]),
@@ -430,7 +549,7 @@ mod tests {
             Ok((
                 make_strspan(""),
                 Decorator {
-                    name: vec!["foo".to_string(), "bar".to_string()],
+                    name: small_list!["foo".to_string(), "bar".to_string()],
                     args: Some(vec![Argument::Positional(Expression::Name(
                         "baz".to_string(),
                     ))]),
@@ -452,7 +571,7 @@ mod tests {
                     parameters: TypedArgsList::default(),
                     return_type: None,
                     code: vec![Statement::Assignment(
-                        vec![Expression::Name("bar".to_string())],
+                        small_list![Expression::Name("bar".to_string())],
                         vec![],
                     )],
                 }),
@@ -470,7 +589,7 @@ mod tests {
                     parameters: TypedArgsList::default(),
                     return_type: None,
                     code: vec![Statement::Assignment(
-                        vec![Expression::Name("bar".to_string())],
+                        small_list![Expression::Name("bar".to_string())],
                         vec![],
                     )],
                 }),
@@ -489,14 +608,14 @@ mod tests {
                 CompoundStatement::Funcdef(Funcdef {
                     async: false,
                     decorators: vec![Decorator {
-                        name: vec!["foo".to_string()],
+                        name: small_list!["foo".to_string()],
                         args: None,
                     }],
                     name: "foo".to_string(),
                     parameters: TypedArgsList::default(),
                     return_type: None,
                     code: vec![Statement::Assignment(
-                        vec![Expression::Name("bar".to_string())],
+                        small_list![Expression::Name("bar".to_string())],
                         vec![],
                     )],
                 }),
@@ -1103,4 +1222,89 @@ mod tests {
             )),
         );
     }
+
+    #[test]
+    fn test_func_type() {
+        let name = |n: &str| Expression::Name(n.to_string());
+        assert_parse_eq(
+            func_type(make_strspan("(int, str) -> bool")),
+            Ok((
+                make_strspan(""),
+                FuncType {
+                    args: vec![name("int"), name("str")],
+                    star_args: None,
+                    star_kwargs: None,
+                    return_type: name("bool"),
+                },
+            )),
+        );
+        assert_parse_eq(
+            func_type(make_strspan("(\n  int,\n  *str, **bytes\n) -> None")),
+            Ok((
+                make_strspan(""),
+                FuncType {
+                    args: vec![name("int")],
+                    star_args: Some(name("str")),
+                    star_kwargs: Some(name("bytes")),
+                    return_type: Expression::None,
+                },
+            )),
+        );
+        assert_parse_eq(
+            func_type(make_strspan("(**bytes) -> None")),
+            Ok((
+                make_strspan(""),
+                FuncType {
+                    args: vec![],
+                    star_args: None,
+                    star_kwargs: Some(name("bytes")),
+                    return_type: Expression::None,
+                },
+            )),
+        );
+        assert!(func_type(make_strspan("(int,) -> bool")).is_err());
+        assert!(func_type(make_strspan("(*int, str) -> bool")).is_err());
+    }
+
+    #[test]
+    fn test_invalid_parameters() {
+        use errors::ParseError;
+
+        let cases = [
+            ("a=1, b", "b", PyParseError::NonDefaultAfterDefault),
+            ("a, b=1, /,\n c", "c", PyParseError::NonDefaultAfterDefault),
+            ("a: int = 1, b", "b", PyParseError::NonDefaultAfterDefault),
+            ("a, *", "*", PyParseError::NoParameterAfterBareStar),
+            ("*, **b", "*,", PyParseError::NoParameterAfterBareStar),
+            ("a, b, a", "a", PyParseError::DuplicateParameter),
+        ];
+        for &(code, at, error_code) in &cases {
+            let error = ParseError::from(typedargslist(make_strspan(code)).unwrap_err());
+            assert_eq!(
+                (error.offset, error.code),
+                (code.rfind(at).unwrap(), Some(error_code))
+            );
+        }
+        let error = ParseError::from(varargslist(make_strspan("a=1, b")).unwrap_err());
+        assert_eq!(error.code, Some(PyParseError::NonDefaultAfterDefault));
+
+        // Duplicates are found across all kinds of parameters
+        for code in &[
+            "a, /, a",
+            "a, *a",
+            "*, a, a",
+            "*a, a",
+            "a, **a",
+            "*, a=1, **a",
+        ] {
+            let error = ParseError::from(varargslist(make_strspan(code)).unwrap_err());
+            assert_eq!(error.code, Some(PyParseError::DuplicateParameter));
+            assert_eq!(error.offset, code.len() - 1);
+        }
+
+        for code in &["a, b=1, /, c=2, *, d, e=1, f", "a=1, *b, c, **d", "*, a,"] {
+            let (rest, _) = typedargslist(make_strspan(code)).unwrap();
+            assert_eq!(rest.fragment.0, "");
+        }
+    }
 }
@@ -6,6 +6,9 @@ use unicode_xid::UnicodeXID;
 use nom::types::CompleteStr;
 use nom::Slice;
 use nom_locate::LocatedSpan;
+
+use ast::SmallList;
+use errors::PyParseError;
 pub(crate) type StrSpan<'a> = LocatedSpan<CompleteStr<'a>>;
 
 /// Like `ws!()`, but does not allow newlines.
@@ -48,8 +51,13 @@ macro_rules! ws_comm (
   )
 );
 
+named_attr!(#[doc = "A line break, which may also be `\\r\\n` or `\\r` like in Python's universal newlines mode."],
+pub line_break<StrSpan, ()>,
+  map!(alt!(tag!("\r\n") | tag!("\r") | tag!("\n")), |_| ())
+);
+
 named!(escaped_newline<StrSpan, ()>,
-  map!(terminated!(char!('\\'), char!('\n')), |_| ())
+  map!(terminated!(char!('\\'), line_break), |_| ())
 );
 
 named!(pub spaces_nl<StrSpan, ()>,
@@ -67,9 +75,15 @@ pub fn spaces_nonl(i: StrSpan) -> Result<(StrSpan, ()), ::nom::Err<StrSpan>> {
         let next_char = it.peek().map(|&(_, c)| c);
         match c {
             ' ' | '\t' | '\x0c' => (),
-            '\\' if next_char.unwrap_or(' ') == '\n' => {
+            '\\' if next_char == Some('\n') => {
                 it.next();
             }
+            '\\' if next_char == Some('\r') => {
+                it.next();
+                if it.peek().map(|&(_, c)| c) == Some('\n') {
+                    it.next();
+                }
+            }
             _ => {
                 if index == 0 {
                     return Ok((i, ()));
@@ -174,8 +188,8 @@ named!(pub newline<StrSpan, ()>,
     many1!(
       tuple!(
         spaces_nonl,
-        opt!(preceded!(char!('#'), many0!(none_of!("\n")))),
-        char!('\n')
+        opt!(preceded!(char!('#'), many0!(none_of!("\r\n")))),
+        line_break
       )
     ),
     |_| ()
@@ -186,6 +200,30 @@ named!(pub semicolon<StrSpan, ()>,
   map!(ws_nonl!(char!(';')), |_| ())
 );
 
+/// Returns the current position, without consuming anything.
+pub fn position(i: StrSpan) -> Result<(StrSpan, StrSpan), ::nom::Err<StrSpan>> {
+    Ok((i, i))
+}
+
+/// Fails with `code` at `span` (unlike `return_error!`, which fails at
+/// the current position) if `invalid`, for checks that are not part of
+/// the grammar.
+pub(crate) fn fail_if<'a>(
+    i: StrSpan<'a>,
+    invalid: bool,
+    span: StrSpan<'a>,
+    code: PyParseError,
+) -> Result<(StrSpan<'a>, ()), ::nom::Err<StrSpan<'a>>> {
+    if invalid {
+        Err(::nom::Err::Failure(::nom::Context::Code(
+            span,
+            ::nom::ErrorKind::Custom(code.into()),
+        )))
+    } else {
+        Ok((i, ()))
+    }
+}
+
 /// Helper to make an instance of `StrSpan`, that can be used as the argument
 /// to other parsers.
 pub fn make_strspan(s: &str) -> StrSpan {
@@ -234,9 +272,7 @@ macro_rules! fold_many1_fixed(
         Err(Err::Error(_))      => Err(Err::Error(
           error_position!($i, nom::ErrorKind::Many1)
         )),
-        Err(Err::Failure(_))      => Err(Err::Failure(
-          error_position!($i, nom::ErrorKind::Many1)
-        )),
+        Err(Err::Failure(e))      => Err(Err::Failure(e)),
         Err(Err::Incomplete(i)) => Err(Err::Incomplete(i)),
         Ok((i1,o1))   => {
           let f = $f;
@@ -288,11 +324,138 @@ macro_rules! fold_many1_fixed(
   );
 );
 
+// Like `separated_nonempty_list!`, but returns a `SmallList`.
+macro_rules! separated_nonempty_small_list(
+  ($i:expr, $sep:ident!( $($args:tt)* ), $submac:ident!( $($args2:tt)* )) => (
+    {
+      use nom::lib::std::result::Result::*;
+      use nom::{Err,ErrorKind};
+      use nom::InputLength;
+
+      let mut res   = ::ast::SmallList::new();
+      let mut input = $i.clone();
+
+      // get the first element
+      let input_ = input.clone();
+      match $submac!(input_, $($args2)*) {
+        Err(e)    => Err(e),
+        Ok((i,o)) => {
+          if i.input_len() == input.input_len() {
+            let e = ErrorKind::SeparatedNonEmptyList;
+            Err(Err::Error(error_position!(input, e)))
+          } else {
+            res.push(o);
+            input = i;
+
+            let ret;
+
+            loop {
+              // get the separator first
+              let input_ = input.clone();
+              match $sep!(input_, $($args)*) {
+                Err(Err::Error(_)) => {
+                  ret = Ok((input, res));
+                  break;
+                }
+                Err(e) => {
+                  ret = Err(e);
+                  break;
+                },
+                Ok((i2,_))     => {
+                  let i2_len = i2.input_len();
+                  if i2_len == input.input_len() {
+                    ret = Ok((input, res));
+                    break;
+                  }
+
+                  // get the element next
+                  match $submac!(i2, $($args2)*) {
+                    Err(Err::Error(_)) => {
+                      ret = Ok((input, res));
+                      break;
+                    },
+                    Err(e) => {
+                      ret = Err(e);
+                      break;
+                    },
+                    Ok((i3,o3))    => {
+                      if i3.input_len() == i2_len {
+                        ret = Ok((input, res));
+                        break;
+                      }
+                      res.push(o3);
+                      input = i3;
+                    }
+                  }
+                }
+              }
+            }
+
+            ret
+          }
+        },
+      }
+    }
+  );
+  ($i:expr, $submac:ident!( $($args:tt)* ), $g:expr) => (
+    separated_nonempty_small_list!($i, $submac!($($args)*), call!($g))
+  );
+);
+
+/// Like `vec!`, for a `SmallList`.
+#[cfg(feature = "smallvec")]
+#[macro_export]
+macro_rules! small_list {
+    ($($item:expr),*) => {{
+        let mut list = $crate::ast::SmallList::new();
+        $( list.push($item); )*
+        list
+    }};
+    ($($item:expr,)*) => (small_list![$($item),*]);
+}
+/// Like `vec!`, for a `SmallList`.
+#[cfg(not(feature = "smallvec"))]
+#[macro_export]
+macro_rules! small_list {
+    ($($item:tt)*) => (vec![$($item)*]);
+}
+
+/// Converts a `SmallList` to a `Vec`, without copying its items if
+/// they are not stored inline.
+#[cfg(feature = "smallvec")]
+pub(crate) fn into_vec<T>(list: SmallList<T>) -> Vec<T> {
+    list.into_vec()
+}
+#[cfg(not(feature = "smallvec"))]
+pub(crate) fn into_vec<T>(list: SmallList<T>) -> Vec<T> {
+    list
+}
+
+/// Converts a `Vec` to a `SmallList`.
+#[cfg(feature = "smallvec")]
+pub(crate) fn from_vec<T>(v: Vec<T>) -> SmallList<T> {
+    SmallList::from_vec(v)
+}
+#[cfg(not(feature = "smallvec"))]
+pub(crate) fn from_vec<T>(v: Vec<T>) -> SmallList<T> {
+    v
+}
+
+// Like in Python, a form feed in the indentation resets its length.
+named!(pub form_feeds<StrSpan, ()>,
+  map!(many0!(tuple!(many0!(char!(' ')), char!('\x0c'))), |_| ())
+);
+
 macro_rules! indent {
     ($i:expr, $nb_spaces:expr) => {{
         use nom::ErrorKind;
         use $crate::errors::PyParseError;
-        count!($i, char!(' '), $nb_spaces).and_then(|(i2, _)| {
+        preceded!(
+            $i,
+            call!($crate::helpers::form_feeds),
+            count!(char!(' '), $nb_spaces)
+        )
+        .and_then(|(i2, _)| {
             return_error!(
                 i2,
                 ErrorKind::Custom(PyParseError::UnexpectedIndent.into()),
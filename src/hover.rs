@@ -0,0 +1,252 @@
+//! Information about the name under a cursor, for the hovers of editors
+//! (the `textDocument/hover` request of the Language Server Protocol):
+//! its kind, its binding (see `definition`), and the annotation and
+//! docstring of its definition.
+
+use ast::Statement;
+use definition::{header_end, Binding, BindingKind, Bindings};
+use doctest::{clean_docstring, docstring};
+use keywords::{is_keyword, PARSED_VERSION};
+use lint::{is_name, is_op, top_level};
+use parse_expression;
+use semantic::{classify_name, SemanticKind};
+use span::{Span, Spanned};
+use tokenizer::{tokenize, Token, TokenKind};
+
+/// The information about a name, found by `hover`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Hover<'a> {
+    /// The name under the cursor.
+    pub span: Span,
+    pub kind: SemanticKind,
+    /// The binding of the name, if it is bound in the module.
+    pub binding: Option<Binding<'a>>,
+    /// The code shown for the name: the header of its function or class
+    /// (like `def f(x: int) -> str`), its import statement, or its kind,
+    /// name and annotation (like `(parameter) x: int`).
+    pub signature: String,
+    /// The annotation of the parameter or variable, or the return
+    /// annotation of the function.
+    pub annotation: Option<&'a str>,
+    /// The docstring of the function or class, cleaned like
+    /// `inspect.cleandoc` does.
+    pub docstring: Option<String>,
+}
+
+impl<'a> Spanned for Hover<'a> {
+    fn span(&self) -> Span {
+        self.span
+    }
+}
+
+impl<'a> Hover<'a> {
+    /// Returns the hover as Markdown: the signature in a Python code
+    /// block, followed by the docstring.
+    pub fn markdown(&self) -> String {
+        let mut markdown = format!("```python\n{}\n```", self.signature);
+        if let Some(ref docstring) = self.docstring {
+            markdown.push_str("\n\n");
+            markdown.push_str(docstring);
+        }
+        markdown
+    }
+}
+
+/// Returns the code from the first to the last token.
+fn text<'a>(code: &'a str, tokens: &[Token]) -> Option<&'a str> {
+    match (tokens.first(), tokens.last()) {
+        (Some(first), Some(last)) => Some(&code[first.offset..last.span().end]),
+        _ => None,
+    }
+}
+
+/// Returns the annotation after the name `tokens[i]`, if it is
+/// followed by a colon, until the end of the parameter or assignment.
+fn annotation<'a>(code: &'a str, tokens: &[Token], i: usize) -> Option<&'a str> {
+    if !is_op(tokens.get(i + 1), ":") {
+        return None;
+    }
+    let rest = &tokens[i + 2..];
+    let end = [",", "=", ")"]
+        .iter()
+        .filter_map(|op| top_level(rest, op))
+        .chain(rest.iter().position(|t| t.kind == TokenKind::Newline))
+        .min()
+        .unwrap_or(rest.len());
+    text(code, &rest[..end])
+}
+
+/// Returns the docstring of the function or class whose header ends
+/// at `tokens[colon]`.
+fn body_docstring(code: &str, tokens: &[Token], colon: usize) -> Option<String> {
+    let start = colon
+        + 1
+        + tokens[colon + 1..]
+            .iter()
+            .take_while(|t| t.kind == TokenKind::Newline || t.kind == TokenKind::Indent)
+            .count();
+    let strings = tokens[start..]
+        .iter()
+        .take_while(|t| t.kind == TokenKind::String)
+        .count();
+    let after = tokens.get(start + strings);
+    let ends_statement = after.map_or(true, |t| t.kind == TokenKind::Newline) || is_op(after, ";");
+    if strings == 0 || !ends_statement {
+        return None;
+    }
+    let expr = parse_expression(text(code, &tokens[start..start + strings])?).ok()?;
+    docstring(&[Statement::Assignment(small_list![expr], Vec::new())]).map(|s| clean_docstring(&s))
+}
+
+/// Returns the information about the name at `offset` (or ending at
+/// it), or `None` if there is no name there.
+///
+/// ```
+/// use python_parser::hover::hover;
+///
+/// let code = "def f(x: int) -> str:\n    '''Formats x.'''\n    return str(x)\n\nf(1)\n";
+/// let info = hover(code, code.rfind('f').unwrap()).unwrap();
+/// assert_eq!(info.signature, "def f(x: int) -> str");
+/// assert_eq!(info.annotation, Some("str"));
+/// assert_eq!(info.markdown(), "```python\ndef f(x: int) -> str\n```\n\nFormats x.");
+/// let info = hover(code, code.rfind('x').unwrap()).unwrap();
+/// assert_eq!(info.signature, "(parameter) x: int");
+/// ```
+pub fn hover(code: &str, offset: usize) -> Option<Hover<'_>> {
+    let tokens: Vec<_> = tokenize(code)
+        .filter(|t| match t.kind {
+            TokenKind::Comment | TokenKind::Nl | TokenKind::Dedent | TokenKind::EndMarker => false,
+            _ => true,
+        })
+        .collect();
+    let is_name_token =
+        |t: &Token| t.kind == TokenKind::Name && !is_keyword(t.text, PARSED_VERSION);
+    let index = tokens
+        .iter()
+        .rposition(|t| is_name_token(t) && t.offset <= offset && offset <= t.span().end)?;
+    let token = &tokens[index];
+    let bindings = Bindings::new(code);
+    let (kind, _) = classify_name(&bindings, &tokens, index);
+    let binding = bindings.resolve(offset).copied();
+    let mut info = Hover {
+        span: token.span(),
+        kind,
+        binding,
+        signature: String::new(),
+        annotation: None,
+        docstring: None,
+    };
+    let binding = match binding {
+        Some(binding) => binding,
+        None => {
+            let label = match kind {
+                SemanticKind::Property | SemanticKind::Method => "attribute",
+                SemanticKind::Parameter => "keyword argument",
+                _ => "builtin",
+            };
+            info.signature = format!("({}) {}", label, token.text);
+            return Some(info);
+        }
+    };
+    let definition = tokens.iter().position(|t| t.span() == binding.span)?;
+    match binding.kind {
+        BindingKind::Function | BindingKind::Class => {
+            let start = definition - 1;
+            let colon = start + header_end(&tokens[start..]).unwrap_or(tokens.len() - start);
+            let header = &tokens[start..colon];
+            info.signature = text(code, header).unwrap_or("").to_string();
+            if definition >= 2 && is_name(tokens.get(definition - 2), "async") {
+                info.signature.insert_str(0, "async ");
+            }
+            if let Some(arrow) = top_level(header, "->") {
+                info.annotation = text(code, &header[arrow + 1..]);
+            }
+            if colon < tokens.len() {
+                info.docstring = body_docstring(code, &tokens, colon);
+            }
+        }
+        BindingKind::Import => {
+            info.signature = code[binding.statement.start..binding.statement.end].to_string();
+        }
+        BindingKind::Parameter | BindingKind::Variable => {
+            // Annotated assignments have a single target, and lambdas no
+            // annotations
+            let statement = &code[binding.statement.start..];
+            let annotated = if binding.kind == BindingKind::Parameter {
+                statement.starts_with("def") || statement.starts_with("async")
+            } else {
+                binding.statement.start == binding.span.start
+            };
+            if annotated {
+                info.annotation = annotation(code, &tokens, definition);
+            }
+            let label = if binding.kind == BindingKind::Parameter {
+                "parameter"
+            } else {
+                "variable"
+            };
+            info.signature = match info.annotation {
+                Some(annotation) => format!("({}) {}: {}", label, binding.name, annotation),
+                None => format!("({}) {}", label, binding.name),
+            };
+        }
+    }
+    Some(info)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns the Markdown of the hover of the `n`-th token `name`.
+    fn markdown(code: &str, name: &str, n: usize) -> Option<String> {
+        let offset = tokenize(code)
+            .filter(|t| t.text == name)
+            .nth(n)
+            .unwrap()
+            .offset;
+        hover(code, offset).map(|info| info.markdown())
+    }
+
+    #[test]
+    fn test_hover() {
+        let code = "from os import path as p
+LIMIT: int = 10
+
+class A(object):
+    '''A class.
+
+    With a docstring.
+    '''
+    async def m(self, x: 'List[int]' = None, *, y) -> bool: return p.x
+    def n(self): f'not {self}'
+
+print(A().m(x=LIMIT))
+";
+        let block = |code: &str| Some(format!("```python\n{}\n```", code));
+        assert_eq!(markdown(code, "p", 1), block("from os import path as p"));
+        assert_eq!(markdown(code, "LIMIT", 1), block("(variable) LIMIT: int"));
+        assert_eq!(
+            markdown(code, "A", 1),
+            Some("```python\nclass A(object)\n```\n\nA class.\n\nWith a docstring.".to_string())
+        );
+        assert_eq!(markdown(code, "x", 0), block("(parameter) x: 'List[int]'"));
+        assert_eq!(markdown(code, "y", 0), block("(parameter) y"));
+        assert_eq!(
+            markdown(code, "m", 0),
+            block("async def m(self, x: 'List[int]' = None, *, y) -> bool")
+        );
+        assert_eq!(markdown(code, "n", 0), block("def n(self)"));
+        assert_eq!(markdown(code, "x", 1), block("(attribute) x"));
+        assert_eq!(markdown(code, "x", 2), block("(keyword argument) x"));
+        assert_eq!(markdown(code, "print", 0), block("(builtin) print"));
+        assert_eq!(markdown(code, "class", 0), None);
+
+        let info = hover(code, code.find("y)").unwrap()).unwrap();
+        assert_eq!(info.kind, SemanticKind::Parameter);
+        assert_eq!(info.annotation, None);
+        let info = hover(code, code.find("m(x").unwrap()).unwrap();
+        assert_eq!(info.kind, SemanticKind::Method);
+        assert_eq!(info.binding, None);
+    }
+}
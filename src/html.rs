@@ -0,0 +1,189 @@
+//! Highlighting of Python code as HTML, for documentation generators.
+//!
+//! The tokens classified by `semantic_tokens` are wrapped in `<span>`s
+//! whose classes are their types and modifiers prefixed by `py-` (like
+//! `py-function py-declaration`), styled by `stylesheet` or by inline
+//! styles. The names of the definitions listed by `outline` have an
+//! `id`, their qualified name (like `A.method`), so they can be linked
+//! to.
+
+use std::collections::{HashMap, HashSet};
+
+use outline::{outline, OutlineSymbol};
+use semantic::{semantic_tokens, SemanticKind, DECLARATION, TOKEN_MODIFIERS, TOKEN_TYPES};
+
+/// Styles of the token types, in the order of `TOKEN_TYPES`.
+const STYLES: [&str; 13] = [
+    "color:#267f99",
+    "color:#267f99",
+    "color:#795e26",
+    "color:#795e26",
+    "color:#001080",
+    "color:#001080",
+    "color:#001080",
+    "color:#af00db",
+    "color:#0000ff",
+    "color:#a31515",
+    "color:#098658",
+    "color:#008000;font-style:italic",
+    "color:#000000",
+];
+
+/// Options of `highlight_html`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct HtmlOptions {
+    /// Whether to add a `style` attribute to each token, for pages
+    /// without the `stylesheet`.
+    pub inline_styles: bool,
+    /// Prefix of the `id`s of the definitions, to avoid collisions
+    /// when several modules are in the same page.
+    pub id_prefix: String,
+}
+
+/// Writes `text` to `html`, escaping it.
+fn escape(text: &str, html: &mut String) {
+    for c in text.chars() {
+        match c {
+            '&' => html.push_str("&amp;"),
+            '<' => html.push_str("&lt;"),
+            '>' => html.push_str("&gt;"),
+            '"' => html.push_str("&quot;"),
+            '\'' => html.push_str("&#39;"),
+            _ => html.push(c),
+        }
+    }
+}
+
+/// Adds the qualified names of `symbols` and of their children to
+/// `anchors`, by the offset of their names.
+fn add_anchors(symbols: &[OutlineSymbol], parent: &str, anchors: &mut HashMap<usize, String>) {
+    for symbol in symbols {
+        let name = if parent.is_empty() {
+            symbol.name.clone()
+        } else {
+            format!("{}.{}", parent, symbol.name)
+        };
+        add_anchors(&symbol.children, &name, anchors);
+        anchors.insert(symbol.name_span.start, name);
+    }
+}
+
+/// Returns the classes of the tokens of a kind and modifiers.
+fn classes(kind: SemanticKind, modifiers: u32) -> String {
+    let mut classes = format!("py-{}", TOKEN_TYPES[kind.index() as usize]);
+    for (i, modifier) in TOKEN_MODIFIERS.iter().enumerate() {
+        if modifiers & (1 << i) != 0 {
+            classes.push_str(" py-");
+            classes.push_str(modifier);
+        }
+    }
+    classes
+}
+
+/// Returns `code` highlighted, in a `<pre class="python"><code>`
+/// element.
+///
+/// The `id`s of the definitions are only added when the code is valid;
+/// code with syntax errors is still highlighted.
+///
+/// ```
+/// use python_parser::html::{highlight_html, HtmlOptions};
+///
+/// let html = highlight_html("def f(x):\n    return x < 1\n", &HtmlOptions::default());
+/// assert_eq!(
+///     html,
+///     concat!(
+///         r#"<pre class="python"><code><span class="py-keyword">def</span> "#,
+///         r#"<span class="py-function py-declaration" id="f">f</span>("#,
+///         r#"<span class="py-parameter py-declaration">x</span>):"#,
+///         "\n    ",
+///         r#"<span class="py-keyword">return</span> <span class="py-parameter">x</span> "#,
+///         r#"<span class="py-operator">&lt;</span> <span class="py-number">1</span>"#,
+///         "\n</code></pre>",
+///     )
+/// );
+/// ```
+pub fn highlight_html(code: &str, options: &HtmlOptions) -> String {
+    let mut anchors = HashMap::new();
+    if let Ok(symbols) = outline(code) {
+        add_anchors(&symbols, "", &mut anchors);
+    }
+    let mut ids = HashSet::new();
+    let mut html = r#"<pre class="python"><code>"#.to_string();
+    let mut written = 0;
+    for token in semantic_tokens(code) {
+        escape(&code[written..token.span.start], &mut html);
+        html.push_str(r#"<span class=""#);
+        html.push_str(&classes(token.kind, token.modifiers));
+        html.push('"');
+        if options.inline_styles {
+            html.push_str(r#" style=""#);
+            html.push_str(STYLES[token.kind.index() as usize]);
+            html.push('"');
+        }
+        if token.modifiers & DECLARATION != 0 {
+            // Only the first binding of a name is its anchor
+            match anchors.get(&token.span.start) {
+                Some(id) if ids.insert(id) => {
+                    html.push_str(r#" id=""#);
+                    escape(&options.id_prefix, &mut html);
+                    escape(id, &mut html);
+                    html.push('"');
+                }
+                _ => (),
+            }
+        }
+        html.push('>');
+        escape(&code[token.span.start..token.span.end], &mut html);
+        html.push_str("</span>");
+        written = token.span.end;
+    }
+    escape(&code[written..], &mut html);
+    html.push_str("</code></pre>");
+    html
+}
+
+/// Returns the CSS styling the classes of `highlight_html`.
+pub fn stylesheet() -> String {
+    let mut css = String::new();
+    for (name, style) in TOKEN_TYPES.iter().zip(STYLES.iter()) {
+        css.push_str(&format!(".py-{} {{ {} }}\n", name, style));
+    }
+    css.push_str(".py-declaration { font-weight: bold }\n");
+    css.push_str(".py-defaultLibrary { font-style: italic }\n");
+    css
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_anchors() {
+        let code = "class A:\n    def m(self):\n        s = '<&>'\nX = 1\nX = 2\n";
+        let options = HtmlOptions {
+            inline_styles: true,
+            id_prefix: "mod-".to_string(),
+        };
+        let html = highlight_html(code, &options);
+        assert!(html.contains(r#"style="color:#267f99" id="mod-A">A</span>"#));
+        assert!(html.contains(r#"style="color:#795e26" id="mod-A.m">m</span>"#));
+        assert!(html.contains(r#"style="color:#a31515">&#39;&lt;&amp;&gt;&#39;</span>"#));
+        assert_eq!(html.matches(r#"id="mod-X""#).count(), 1);
+        // The local variable of the method is not a definition of the
+        // outline
+        assert_eq!(html.matches(" id=").count(), 3);
+
+        // Code with a syntax error is highlighted without anchors
+        let html = highlight_html("def f(:\n", &HtmlOptions::default());
+        assert!(html.contains(r#"<span class="py-keyword">def</span>"#));
+        assert!(!html.contains(" id="));
+    }
+
+    #[test]
+    fn test_stylesheet() {
+        let css = stylesheet();
+        assert!(css.starts_with(".py-namespace { color:#267f99 }\n"));
+        assert!(css.contains(".py-comment { color:#008000;font-style:italic }\n"));
+    }
+}
@@ -0,0 +1,890 @@
+//! The import statements of a module, found from its tokens, and edits
+//! of them: removing the imports which are never used, adding
+//! `__future__` imports, converting relative imports to absolute ones
+//! and expanding `*` imports.
+
+use std::collections::BTreeMap;
+
+use annotations::parse_annotation_literal;
+use definition::{closing, header_end, split_top_level, top_level_keyword, Bindings};
+use inline::removal;
+use keywords::{is_keyword, PARSED_VERSION};
+use lint::{dotted_name, is_name, is_op, top_level};
+use span::{Span, Spanned};
+use suppressions::{is_suppressed, suppressions, SuppressionKind};
+use tokenizer::{tokenize, Token, TokenKind};
+use visitors::dead_stores::{expression_names, Names};
+
+/// A name imported by an import statement, like `os.path as p` in
+/// `import os.path as p` or `c` in `from a.b import c`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ImportedName {
+    /// The module (or the object of a `from` import) as written, like
+    /// `os.path`, or `*`.
+    pub name: Span,
+    pub alias: Option<Span>,
+    /// The name bound by the import: the alias, or the first name of
+    /// the module (like `os` for `import os.path`).
+    pub bound: Span,
+    /// Line of the name, starting from 1.
+    pub line: u32,
+}
+
+impl Spanned for ImportedName {
+    fn span(&self) -> Span {
+        self.name.cover(self.alias.unwrap_or(self.name))
+    }
+}
+
+/// An `import` or `from ... import` statement.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Import {
+    pub span: Span,
+    /// The module of a `from` import as written, with its leading dots
+    /// (like `..models`).
+    pub module: Option<Span>,
+    pub names: Vec<ImportedName>,
+}
+
+impl Spanned for Import {
+    fn span(&self) -> Span {
+        self.span
+    }
+}
+
+/// Returns whether `tokens[i]` is the first token of a simple statement.
+fn at_statement_start(tokens: &[Token], i: usize) -> bool {
+    let previous = if i == 0 { None } else { tokens.get(i - 1) };
+    previous.map_or(true, |t| match t.kind {
+        TokenKind::Newline | TokenKind::Indent | TokenKind::Dedent => true,
+        _ => is_op(Some(t), ";") || is_op(Some(t), ":"),
+    })
+}
+
+/// Returns the index of the token ending the simple statement starting
+/// at `tokens[start]` (its `Newline` or `;`).
+fn statement_end(tokens: &[Token], start: usize) -> usize {
+    start
+        + tokens[start..]
+            .iter()
+            .position(|t| t.kind == TokenKind::Newline || is_op(Some(t), ";"))
+            .unwrap_or(tokens.len() - start)
+}
+
+/// Returns the import statements in `tokens` (without comments and
+/// line breaks which do not end a statement), with the indexes of
+/// their first and last tokens.
+fn import_statements(tokens: &[Token]) -> Vec<(Import, usize, usize)> {
+    let mut imports = Vec::new();
+    for (start, token) in tokens.iter().enumerate() {
+        let is_from = is_name(Some(token), "from");
+        if !at_statement_start(tokens, start) || !(is_from || is_name(Some(token), "import")) {
+            continue;
+        }
+        let end = statement_end(tokens, start);
+        let statement = &tokens[start..end];
+        let (module, items) = if is_from {
+            let import = match top_level_keyword(statement, "import") {
+                Some(import) if import > 1 => import,
+                _ => continue,
+            };
+            let module = statement[1].span().cover(statement[import - 1].span());
+            let mut items = &statement[import + 1..];
+            if is_op(items.first(), "(") {
+                items = &items[1..items.len() - is_op(items.last(), ")") as usize];
+            }
+            (Some(module), items)
+        } else {
+            (None, &statement[1..])
+        };
+        let mut names = Vec::new();
+        for item in split_top_level(items, ",") {
+            let name = match item.first() {
+                Some(star) if is_op(Some(star), "*") => &item[..1],
+                Some(_) => dotted_name(item, 0),
+                None => continue,
+            };
+            if name.is_empty() {
+                continue;
+            }
+            let alias = match item.len().checked_sub(2).map(|i| &item[i..]) {
+                Some([as_, alias]) if is_name(Some(as_), "as") => Some(alias.span()),
+                _ => None,
+            };
+            names.push(ImportedName {
+                name: name[0].span().cover(name[name.len() - 1].span()),
+                alias,
+                bound: alias.unwrap_or_else(|| name[0].span()),
+                line: name[0].line,
+            });
+        }
+        let import = Import {
+            span: token.span().cover(tokens[end - 1].span()),
+            module,
+            names,
+        };
+        imports.push((import, start, end - 1));
+    }
+    imports
+}
+
+/// Returns the import statements of `code`, in the order of the code.
+///
+/// ```
+/// use python_parser::imports::imports;
+///
+/// let code = "import os.path as p, sys\nfrom .models import (User,\n    Group)\n";
+/// let imports = imports(code);
+/// let text = |span: python_parser::span::Span| &code[span.start..span.end];
+/// assert_eq!(text(imports[0].names[0].name), "os.path");
+/// assert_eq!(text(imports[0].names[0].bound), "p");
+/// assert_eq!(imports[1].module.map(text), Some(".models"));
+/// assert_eq!(imports[1].names[1].line, 3);
+/// ```
+pub fn imports(code: &str) -> Vec<Import> {
+    let tokens = statement_tokens(code);
+    import_statements(&tokens)
+        .into_iter()
+        .map(|(import, _, _)| import)
+        .collect()
+}
+
+/// Returns the qualified names of the names bound by the imports of
+/// `code`, like `numpy` for `np` (bound by `import numpy as np`) or
+/// `pathlib.Path` for `Path` (bound by `from pathlib import Path`).
+///
+/// Scopes are ignored: the imports of the whole module are used, and a
+/// name imported several times has its last qualified name. The modules
+/// of relative imports are kept as written (like `.models.User`), and
+/// `*` imports are skipped.
+///
+/// ```
+/// use python_parser::imports::import_aliases;
+///
+/// let aliases = import_aliases("import numpy as np, os.path\nfrom pathlib import Path as P\n");
+/// assert_eq!(aliases["np"], "numpy");
+/// assert_eq!(aliases["os"], "os");
+/// assert_eq!(aliases["P"], "pathlib.Path");
+/// ```
+pub fn import_aliases(code: &str) -> BTreeMap<&str, String> {
+    let text = |span: Span| -> String {
+        code[span.start..span.end]
+            .chars()
+            .filter(|c| !c.is_whitespace())
+            .collect()
+    };
+    let mut aliases = BTreeMap::new();
+    for import in imports(code) {
+        for name in &import.names {
+            let bound = &code[name.bound.start..name.bound.end];
+            let qualified = match import.module {
+                _ if bound == "*" => continue,
+                Some(module) if text(module).ends_with('.') => text(module) + &text(name.name),
+                Some(module) => format!("{}.{}", text(module), text(name.name)),
+                None if name.alias.is_some() => text(name.name),
+                None => bound.to_string(),
+            };
+            aliases.insert(bound, qualified);
+        }
+    }
+    aliases
+}
+
+/// Returns the tokens of `code` without comments and line breaks which
+/// do not end a statement.
+fn statement_tokens(code: &str) -> Vec<Token<'_>> {
+    tokenize(code)
+        .filter(|t| t.kind != TokenKind::Comment && t.kind != TokenKind::Nl)
+        .collect()
+}
+
+/// A name imported and never used.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct UnusedImport {
+    /// The name bound by the import.
+    pub name: String,
+    /// The imported name and its alias.
+    pub span: Span,
+    /// Line of the imported name, starting from 1.
+    pub line: u32,
+}
+
+impl Spanned for UnusedImport {
+    fn span(&self) -> Span {
+        self.span
+    }
+}
+
+/// Returns the annotations of `tokens`: the return annotations of
+/// functions, and the annotations of their parameters and of variables.
+fn annotations<'t, 'a>(tokens: &'t [Token<'a>]) -> Vec<&'t [Token<'a>]> {
+    let mut annotations = Vec::new();
+    for (i, token) in tokens.iter().enumerate() {
+        if is_op(Some(token), "->") {
+            let rest = &tokens[i + 1..];
+            annotations.push(&rest[..header_end(rest).unwrap_or(rest.len())]);
+        } else if is_name(Some(token), "def") && is_op(tokens.get(i + 2), "(") {
+            let close = closing(tokens, i + 2).min(tokens.len());
+            for parameter in split_top_level(&tokens[i + 3..close], ",") {
+                if let Some(colon) = top_level(parameter, ":") {
+                    let end = top_level(parameter, "=").unwrap_or(parameter.len());
+                    annotations.push(&parameter[colon + 1..end.max(colon + 1)]);
+                }
+            }
+        } else if token.kind == TokenKind::Name
+            && !is_keyword(token.text, PARSED_VERSION)
+            && at_statement_start(tokens, i)
+        {
+            // Annotated assignments, like `x: int = 0` or `self.x: int`
+            let statement = &tokens[i..statement_end(tokens, i)];
+            let end = top_level(statement, "=").unwrap_or(statement.len());
+            match top_level(statement, ":") {
+                Some(colon) if colon < end => annotations.push(&statement[colon + 1..end]),
+                _ => (),
+            }
+        }
+    }
+    annotations
+}
+
+/// Returns the names used in the annotations of `tokens` written as
+/// strings, like `List` in `def f() -> 'List[int]'`.
+fn string_annotation_names(code: &str, tokens: &[Token]) -> Names {
+    let mut names = Names::new();
+    for annotation in annotations(tokens) {
+        for token in annotation.iter().filter(|t| t.kind == TokenKind::String) {
+            if let Ok(expr) = parse_annotation_literal(code, token.span()) {
+                names.extend(expression_names(&[expr]));
+            }
+        }
+    }
+    names
+}
+
+/// Returns the unused imports of each import statement of `tokens`.
+fn unused_names(code: &str, tokens: &[Token]) -> Vec<(Import, usize, usize, Vec<bool>)> {
+    let bindings = Bindings::new(code);
+    let annotation_names = string_annotation_names(code, tokens);
+    // The names in `__all__` are exported
+    let mut exported = Vec::new();
+    let mut in_all = false;
+    for (i, token) in tokens.iter().enumerate() {
+        match token.kind {
+            TokenKind::Newline => in_all = false,
+            TokenKind::Name if token.text == "__all__" => {
+                in_all = i == 0 || tokens[i - 1].kind != TokenKind::Op
+            }
+            TokenKind::String if in_all => {
+                exported.push(token.text.trim_matches(|c| c == '\'' || c == '"'))
+            }
+            _ => (),
+        }
+    }
+    let is_unused = |name: &ImportedName| {
+        let text = |span: Span| &code[span.start..span.end];
+        let bound = text(name.bound);
+        // `import a as a` and `from m import a as a` are re-exports
+        let reexported = name
+            .alias
+            .map_or(false, |alias| text(alias) == text(name.name));
+        let used = bindings
+            .references(name.bound.start)
+            .iter()
+            .any(|&r| bindings.resolve(r.start).map(|b| b.span) != Some(r));
+        bound != "*"
+            && !reexported
+            && !used
+            && !annotation_names.contains(bound)
+            && !exported.contains(&bound)
+    };
+    import_statements(tokens)
+        .into_iter()
+        .filter(|&(ref import, _, _)| {
+            import.module.map(|m| &code[m.start..m.end]) != Some("__future__")
+        })
+        .map(|(import, start, end)| {
+            let unused = import.names.iter().map(&is_unused).collect();
+            (import, start, end, unused)
+        })
+        .collect()
+}
+
+/// Returns the names imported by `code` which are never used, in the
+/// order of the code.
+///
+/// Names in `__all__`, `__future__` imports and re-exports with the
+/// same name (like `from m import a as a`) are used, as well as names
+/// in annotations written as strings (like `def f() -> 'List[int]'`).
+/// Names only used in other strings are not.
+///
+/// ```
+/// use python_parser::imports::unused_imports;
+///
+/// let code = "import os, sys\nfrom typing import List as L\nprint(sys.argv)\n";
+/// let names: Vec<_> = unused_imports(code).into_iter().map(|u| u.name).collect();
+/// assert_eq!(names, vec!["os", "L"]);
+/// ```
+pub fn unused_imports(code: &str) -> Vec<UnusedImport> {
+    let tokens = statement_tokens(code);
+    let mut unused_imports = Vec::new();
+    for (import, _, _, unused) in unused_names(code, &tokens) {
+        for (name, _) in import.names.iter().zip(unused).filter(|&(_, u)| u) {
+            unused_imports.push(UnusedImport {
+                name: code[name.bound.start..name.bound.end].to_string(),
+                span: name.span(),
+                line: name.line,
+            });
+        }
+    }
+    unused_imports
+}
+
+/// Returns the edits removing the unused imports of `code` (except the
+/// ones on a line with `# noqa: F401`): the names are removed from their
+/// statement (keeping the comments between them), or the whole
+/// statement if none of its names is used.
+///
+/// ```
+/// use python_parser::imports::remove_unused_imports;
+/// use python_parser::span::replace_spans;
+///
+/// let code = "import os\nfrom typing import (\n    Dict,\n    List,\n)\n\nx: List[int] = []\n";
+/// let edits = remove_unused_imports(code);
+/// let edits: Vec<_> = edits.iter().map(|&(span, ref text)| (span, &text[..])).collect();
+/// assert_eq!(
+///     replace_spans(code, &edits).unwrap(),
+///     "from typing import (\n    List,\n)\n\nx: List[int] = []\n"
+/// );
+/// ```
+pub fn remove_unused_imports(code: &str) -> Vec<(Span, String)> {
+    let tokens = statement_tokens(code);
+    let suppressions = suppressions(code);
+    let comments: Vec<_> = tokenize(code)
+        .filter(|t| t.kind == TokenKind::Comment)
+        .map(|t| t.span())
+        .collect();
+    let mut edits = Vec::new();
+    for (import, start, end, mut unused) in unused_names(code, &tokens) {
+        for (name, unused) in import.names.iter().zip(&mut unused) {
+            *unused = *unused
+                && !is_suppressed(
+                    &suppressions,
+                    SuppressionKind::Noqa,
+                    name.line,
+                    Some("F401"),
+                );
+        }
+        if unused.iter().all(|&u| u) {
+            if !unused.is_empty() {
+                edits.push(removal(code, &tokens, start, end));
+            }
+            continue;
+        }
+        // The names are removed with the comma after them, and the last
+        // ones with the comma before them
+        let last_used = unused.iter().rposition(|&u| !u).unwrap_or(0);
+        let names = &import.names;
+        for (i, name) in names.iter().enumerate().filter(|&(i, _)| unused[i]) {
+            if i < last_used {
+                let span = Span::new(name.span().start, names[i + 1].span().start);
+                removal_keeping_comments(code, &comments, span, &mut edits);
+            }
+        }
+        if last_used + 1 < names.len() {
+            let start = names[last_used].span().end;
+            let mut end = names[names.len() - 1].span().end;
+            // With a comment kept, a trailing comma would be left alone
+            // on its line
+            let rest = code[end..].trim_start();
+            if comments.iter().any(|c| start <= c.start && c.end <= end) && rest.starts_with(',') {
+                end = code.len() - rest.len() + 1;
+            }
+            removal_keeping_comments(code, &comments, Span::new(start, end), &mut edits);
+        }
+    }
+    edits
+}
+
+/// Pushes to `edits` the removal of the code of `span` except its
+/// comments. The whitespace around the removed parts is kept, except
+/// the lines which become blank.
+fn removal_keeping_comments(
+    code: &str,
+    comments: &[Span],
+    span: Span,
+    edits: &mut Vec<(Span, String)>,
+) {
+    let mut parts = Vec::new();
+    let mut start = span.start;
+    for comment in comments
+        .iter()
+        .filter(|c| span.start <= c.start && c.end <= span.end)
+    {
+        parts.push(Span::new(start, comment.start));
+        start = comment.end;
+    }
+    parts.push(Span::new(start, span.end));
+    if parts.len() == 1 {
+        edits.push((span, String::new()));
+        return;
+    }
+    for part in parts {
+        let text = &code[part.start..part.end];
+        let start = part.end - text.trim_start().len();
+        let end = part.start + text.trim_end().len();
+        if start >= end {
+            continue;
+        }
+        let line_start = code[..start].rfind('\n').map_or(0, |i| i + 1);
+        let line_end = code[end..].find('\n').map_or(code.len(), |i| end + i + 1);
+        let blank = |text: &str| text.trim().is_empty();
+        if line_start > span.start && blank(&code[line_start..start]) && blank(&code[end..line_end])
+        {
+            edits.push((Span::new(line_start, line_end), String::new()));
+        } else {
+            edits.push((Span::new(start, end), String::new()));
+        }
+    }
+}
+
+/// Returns the edit adding the `__future__` imports of `features` (like
+/// `annotations`) which `code` does not have yet: to its first
+/// `__future__` import, or in a new statement after its docstring and
+/// the comments before its first statement.
+///
+/// ```
+/// use python_parser::imports::add_future_imports;
+/// use python_parser::span::replace_spans;
+///
+/// let code = "#!/usr/bin/env python\n\"\"\"Docstring.\"\"\"\n\nimport os\n";
+/// let edits = add_future_imports(code, &["annotations"]);
+/// let edits: Vec<_> = edits.iter().map(|&(span, ref text)| (span, &text[..])).collect();
+/// assert_eq!(
+///     replace_spans(code, &edits).unwrap(),
+///     "#!/usr/bin/env python\n\"\"\"Docstring.\"\"\"\n\nfrom __future__ import annotations\nimport os\n"
+/// );
+/// ```
+pub fn add_future_imports(code: &str, features: &[&str]) -> Vec<(Span, String)> {
+    let tokens = statement_tokens(code);
+    let text = |span: Span| &code[span.start..span.end];
+    let future: Vec<_> = import_statements(&tokens)
+        .into_iter()
+        .map(|(import, _, _)| import)
+        .filter(|import| import.module.map(text) == Some("__future__"))
+        .collect();
+    let mut missing: Vec<&str> = Vec::new();
+    for &feature in features {
+        let imported = future
+            .iter()
+            .any(|import| import.names.iter().any(|name| text(name.name) == feature));
+        if !imported && !missing.contains(&feature) {
+            missing.push(feature);
+        }
+    }
+    if missing.is_empty() {
+        return Vec::new();
+    }
+    if let Some(last) = future.first().and_then(|import| import.names.last()) {
+        let names = format!(", {}", missing.join(", "));
+        return vec![(Span::at(last.span().end), names)];
+    }
+    let line_break = tokens
+        .iter()
+        .find(|t| t.kind == TokenKind::Newline && !t.text.is_empty())
+        .map_or("\n", |t| t.text);
+    let statement = format!(
+        "from __future__ import {}{}",
+        missing.join(", "),
+        line_break
+    );
+    // The first statement after the docstring
+    let strings = tokens
+        .iter()
+        .take_while(|t| t.kind == TokenKind::String)
+        .count();
+    let first = match tokens.get(strings) {
+        Some(newline) if strings > 0 && newline.kind == TokenKind::Newline => strings + 1,
+        _ => 0,
+    };
+    match tokens.get(first) {
+        Some(token) if token.kind != TokenKind::EndMarker => {
+            let line_start = code[..token.offset]
+                .rfind(|c| c == '\n' || c == '\r')
+                .map_or(0, |i| i + 1);
+            vec![(Span::at(line_start), statement)]
+        }
+        _ if code.is_empty() || code.ends_with('\n') || code.ends_with('\r') => {
+            vec![(Span::at(code.len()), statement)]
+        }
+        _ => vec![(Span::at(code.len()), format!("{}{}", line_break, statement))],
+    }
+}
+
+/// How the modules of `from` imports are written.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum ImportStyle {
+    /// Like `from pkg.utils import x`.
+    Absolute,
+    /// Like `from ..utils import x`, for the modules of the same
+    /// top-level package.
+    Relative,
+}
+
+/// Returns the edits rewriting the modules of the `from` imports of the
+/// module named `module` in `style`. `is_package` tells whether it is
+/// the `__init__` module of the package, which relative imports are
+/// relative to.
+///
+/// Relative imports going above the top-level package are not changed.
+///
+/// ```
+/// use python_parser::imports::{convert_imports, ImportStyle};
+/// use python_parser::span::replace_spans;
+///
+/// let code = "from ..utils import x\nfrom . import y\n";
+/// let edits = convert_imports(code, "pkg.sub.mod", false, ImportStyle::Absolute);
+/// let edits: Vec<_> = edits.iter().map(|&(span, ref text)| (span, &text[..])).collect();
+/// assert_eq!(
+///     replace_spans(code, &edits).unwrap(),
+///     "from pkg.utils import x\nfrom pkg.sub import y\n"
+/// );
+/// ```
+pub fn convert_imports(
+    code: &str,
+    module: &str,
+    is_package: bool,
+    style: ImportStyle,
+) -> Vec<(Span, String)> {
+    let mut package: Vec<&str> = module.split('.').collect();
+    if !is_package {
+        package.pop();
+    }
+    let mut edits = Vec::new();
+    for import in imports(code) {
+        let span = match import.module {
+            Some(span) => span,
+            None => continue,
+        };
+        let written: String = code[span.start..span.end]
+            .chars()
+            .filter(|c| !c.is_whitespace())
+            .collect();
+        let name = written.trim_start_matches('.');
+        let dots = written.len() - name.len();
+        let path: Vec<&str> = name.split('.').filter(|part| !part.is_empty()).collect();
+        let converted = match style {
+            ImportStyle::Absolute if dots > 0 => {
+                if dots > package.len() {
+                    continue;
+                }
+                let mut absolute = package[..package.len() + 1 - dots].to_vec();
+                absolute.extend(path);
+                absolute.join(".")
+            }
+            ImportStyle::Relative if dots == 0 => {
+                let common = package
+                    .iter()
+                    .zip(&path)
+                    .take_while(|&(a, b)| a == b)
+                    .count();
+                if common == 0 {
+                    continue;
+                }
+                let dots = ".".repeat(package.len() - common + 1);
+                format!("{}{}", dots, path[common..].join("."))
+            }
+            _ => continue,
+        };
+        edits.push((span, converted));
+    }
+    edits
+}
+
+/// Returns the edits replacing `from <module> import *` (with `module`
+/// as written in the import, like `os.path` or `.models`) by an import
+/// of the names of `exports` used in `code`, sorted, given the names
+/// exported by the module (the names in its `__all__`, or its public
+/// names). The import is removed if none of them is used.
+///
+/// ```
+/// use python_parser::imports::expand_wildcard_import;
+/// use python_parser::span::replace_spans;
+///
+/// let code = "from os.path import *\n\nprint(join(dirname(p), 'x'), exists)\n";
+/// let exports = ["basename", "dirname", "exists", "join"];
+/// let edits = expand_wildcard_import(code, "os.path", &exports);
+/// let edits: Vec<_> = edits.iter().map(|&(span, ref text)| (span, &text[..])).collect();
+/// assert_eq!(
+///     replace_spans(code, &edits).unwrap(),
+///     "from os.path import dirname, exists, join\n\nprint(join(dirname(p), 'x'), exists)\n"
+/// );
+/// ```
+pub fn expand_wildcard_import(code: &str, module: &str, exports: &[&str]) -> Vec<(Span, String)> {
+    let tokens = statement_tokens(code);
+    let bindings = Bindings::new(code);
+    // The exported names which are not bound in the module
+    let mut used: Vec<&str> = Vec::new();
+    for (i, token) in tokens.iter().enumerate() {
+        if token.kind != TokenKind::Name || !exports.contains(&token.text) {
+            continue;
+        }
+        let previous = if i == 0 { None } else { tokens.get(i - 1) };
+        let is_keyword_argument =
+            is_op(tokens.get(i + 1), "=") && (is_op(previous, "(") || is_op(previous, ","));
+        if is_op(previous, ".") || is_keyword_argument || bindings.resolve(token.offset).is_some() {
+            continue;
+        }
+        if !used.contains(&token.text) {
+            used.push(token.text);
+        }
+    }
+    used.sort();
+    let is_module = |span: Span| {
+        code[span.start..span.end]
+            .chars()
+            .filter(|c| !c.is_whitespace())
+            .eq(module.chars())
+    };
+    let mut edits = Vec::new();
+    for (import, start, end) in import_statements(&tokens) {
+        let star = match import.names.first() {
+            Some(star)
+                if import.module.map_or(false, &is_module)
+                    && &code[star.name.start..star.name.end] == "*" =>
+            {
+                star
+            }
+            _ => continue,
+        };
+        if used.is_empty() {
+            edits.push(removal(code, &tokens, start, end));
+        } else {
+            edits.push((star.name, used.join(", ")));
+        }
+    }
+    edits
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use span::replace_spans;
+
+    fn without_unused_imports(code: &str) -> String {
+        let edits = remove_unused_imports(code);
+        let edits: Vec<_> = edits
+            .iter()
+            .map(|&(span, ref text)| (span, &text[..]))
+            .collect();
+        replace_spans(code, &edits).unwrap()
+    }
+
+    #[test]
+    fn test_imports() {
+        let code = "import a.b.c as d, e\nif x: from . import (f, g as h,)\n\
+                    from ..i.j import *; import k\nraise l from m\n";
+        let text = |span: Span| &code[span.start..span.end];
+        let found: Vec<_> = imports(code)
+            .iter()
+            .map(|import| {
+                let names: Vec<_> = import
+                    .names
+                    .iter()
+                    .map(|n| (text(n.name), n.alias.map(text), text(n.bound)))
+                    .collect();
+                (import.module.map(text), names)
+            })
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                (None, vec![("a.b.c", Some("d"), "d"), ("e", None, "e")]),
+                (Some("."), vec![("f", None, "f"), ("g", Some("h"), "h")]),
+                (Some("..i.j"), vec![("*", None, "*")]),
+                (None, vec![("k", None, "k")]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_unused_imports() {
+        let code = "from __future__ import annotations
+import os, os.path, sys as system
+from typing import List as List, Dict
+from m import *
+import json  # noqa: F401
+__all__ = ['Dict']
+
+def f():
+    import re
+    return os.sep
+";
+        let unused: Vec<_> = unused_imports(code)
+            .into_iter()
+            .map(|u| (u.name, &code[u.span.start..u.span.end], u.line))
+            .collect();
+        assert_eq!(
+            unused,
+            vec![
+                ("system".to_string(), "sys as system", 2),
+                ("json".to_string(), "json", 5),
+                ("re".to_string(), "re", 9),
+            ]
+        );
+        assert_eq!(
+            without_unused_imports(code),
+            code.replace(", sys as system", "")
+                .replace("    import re\n", "")
+        );
+
+        // Several names, trailing commas, `;` and blocks
+        let code = "from m import (a, b,\n    c, d,)\nimport e, f, g\nimport h; import i\n\
+                    try:\n    import j\nexcept ImportError:\n    pass\nprint(b, d, f, i)\n";
+        assert_eq!(
+            without_unused_imports(code),
+            "from m import (b,\n    d,)\nimport f\nimport i\n\
+             try:\n    pass\nexcept ImportError:\n    pass\nprint(b, d, f, i)\n"
+        );
+
+        // Comments between the names
+        let code = "from a import (b,  # keep\n    c)\nfrom d import (e,  # keep\n    f,\n)\nprint(c, e)\n";
+        assert_eq!(
+            without_unused_imports(code),
+            "from a import (  # keep\n    c)\nfrom d import (e  # keep\n)\nprint(c, e)\n"
+        );
+    }
+
+    #[test]
+    fn test_string_annotations() {
+        let code = "from typing import Dict, List, Optional, Set\nimport os.path\n\n\
+                    def f(x: 'Optional[os.PathLike]' = None) -> 'List[int]':\n\
+                    \x20   y: \"Dict[str, 'int']\" = {'Set': 1}\n";
+        let unused: Vec<_> = unused_imports(code).into_iter().map(|u| u.name).collect();
+        assert_eq!(unused, vec!["Set"]);
+    }
+
+    #[test]
+    fn test_add_future_imports() {
+        let added = |code: &str, features: &[&str]| {
+            let edits = add_future_imports(code, features);
+            let edits: Vec<_> = edits
+                .iter()
+                .map(|&(span, ref text)| (span, &text[..]))
+                .collect();
+            replace_spans(code, &edits).unwrap()
+        };
+        let features = ["annotations", "division", "annotations"];
+        assert_eq!(
+            added(
+                "'''Doc'''  # comment\r\n# comment\r\nimport os\r\n",
+                &features
+            ),
+            "'''Doc'''  # comment\r\n# comment\r\n\
+             from __future__ import annotations, division\r\nimport os\r\n"
+        );
+        assert_eq!(
+            added("x = 1\n", &features),
+            "from __future__ import annotations, division\nx = 1\n"
+        );
+        assert_eq!(
+            added("'a' + 'b'\n", &features),
+            "from __future__ import annotations, division\n'a' + 'b'\n"
+        );
+        assert_eq!(
+            added("'''Doc'''", &features),
+            "'''Doc'''\nfrom __future__ import annotations, division\n"
+        );
+        assert_eq!(
+            added("", &features),
+            "from __future__ import annotations, division\n"
+        );
+        // Merged into the existing imports
+        let code =
+            "from __future__ import (\n    division,\n)\nfrom __future__ import print_function\n";
+        assert_eq!(
+            added(code, &features),
+            code.replace("division,", "division, annotations,")
+        );
+        assert_eq!(added(code, &["division", "print_function"]), code);
+    }
+
+    #[test]
+    fn test_convert_imports() {
+        let converted = |code: &str, module, is_package, style| {
+            let edits = convert_imports(code, module, is_package, style);
+            let edits: Vec<_> = edits
+                .iter()
+                .map(|&(span, ref text)| (span, &text[..]))
+                .collect();
+            replace_spans(code, &edits).unwrap()
+        };
+        let relative = "from . import a\nfrom .mod import b\nfrom .. import c\n\
+                        from ..utils.text import d\nfrom ... import e\nimport pkg.sub\n";
+        let absolute = "from pkg.sub import a\nfrom pkg.sub.mod import b\nfrom pkg import c\n\
+                        from pkg.utils.text import d\nfrom ... import e\nimport pkg.sub\n";
+        let style = ImportStyle::Absolute;
+        assert_eq!(converted(relative, "pkg.sub.mod", false, style), absolute);
+        // In `pkg/sub/__init__.py`
+        assert_eq!(converted(relative, "pkg.sub", true, style), absolute);
+        let style = ImportStyle::Relative;
+        assert_eq!(converted(absolute, "pkg.sub.mod", false, style), relative);
+        assert_eq!(
+            converted("from os import path\n", "pkg.mod", false, style),
+            "from os import path\n"
+        );
+        assert_eq!(
+            converted("from pkg import x\n", "pkg", true, style),
+            "from . import x\n"
+        );
+        assert_eq!(
+            converted(
+                "from ... import x\n",
+                "pkg.mod",
+                false,
+                ImportStyle::Absolute
+            ),
+            "from ... import x\n"
+        );
+    }
+
+    #[test]
+    fn test_import_aliases() {
+        let code = "import a.b.c, d.e as f\nfrom g.h import (i as j, k)\nfrom .l import m\n\
+                    from .. import n\nfrom o import *\nif x: import k\n";
+        let aliases: Vec<_> = import_aliases(code).into_iter().collect();
+        let expected = [
+            ("a", "a"),
+            ("f", "d.e"),
+            ("j", "g.h.i"),
+            ("k", "k"),
+            ("m", ".l.m"),
+            ("n", "..n"),
+        ];
+        let expected: Vec<_> = expected.iter().map(|&(n, q)| (n, q.to_string())).collect();
+        assert_eq!(aliases, expected);
+    }
+
+    #[test]
+    fn test_expand_wildcard_import() {
+        let expanded = |code: &str, module, exports: &[&str]| {
+            let edits = expand_wildcard_import(code, module, exports);
+            let edits: Vec<_> = edits
+                .iter()
+                .map(|&(span, ref text)| (span, &text[..]))
+                .collect();
+            replace_spans(code, &edits).unwrap()
+        };
+        let exports = ["a", "b", "c", "d", "e", "f"];
+        let code = "from .m import *\nfrom n import *\nimport os\n\
+                    def f(x=a, c=1):\n    return b(d=os.e, c=c)\n";
+        assert_eq!(
+            expanded(code, ".m", &exports),
+            code.replace(".m import *", ".m import a, b")
+        );
+        assert_eq!(
+            expanded("import os\nfrom . m import *\nos.a\n", ".m", &exports),
+            "import os\nos.a\n"
+        );
+        assert_eq!(expanded(code, "m", &exports), code);
+    }
+}
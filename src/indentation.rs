@@ -0,0 +1,379 @@
+//! Inference of the indentation style of some code, so that code
+//! generated or reformatted for a file can match its existing style,
+//! and of the indentation of new lines, for the auto-indent of editors.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use definition::header_end;
+use lint::is_op;
+use tokenizer::{tokenize, Token, TokenKind};
+
+/// The indentation added by each level of blocks.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum IndentUnit {
+    /// This number of spaces.
+    Spaces(usize),
+    Tab,
+}
+
+impl IndentUnit {
+    /// Returns the whitespace indenting a block by one level.
+    pub fn text(self) -> String {
+        match self {
+            IndentUnit::Spaces(n) => " ".repeat(n),
+            IndentUnit::Tab => "\t".to_string(),
+        }
+    }
+}
+
+impl fmt::Display for IndentUnit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            IndentUnit::Spaces(1) => write!(f, "1 space"),
+            IndentUnit::Spaces(n) => write!(f, "{} spaces", n),
+            IndentUnit::Tab => write!(f, "tab"),
+        }
+    }
+}
+
+/// A line whose indentation does not follow the style of the code.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Inconsistency {
+    /// A block indented by this unit, which is not the dominant one.
+    OtherUnit { line: u32, unit: IndentUnit },
+    /// A block indented by both tabs and spaces.
+    MixedTabsAndSpaces { line: u32 },
+    /// A block whose indentation does not start with the one of the
+    /// enclosing block (eg. a tab in the enclosing block, and spaces
+    /// in this one).
+    UnrelatedToParent { line: u32 },
+    /// A dedent that does not match any enclosing block.
+    UnmatchedDedent { line: u32 },
+}
+
+impl Inconsistency {
+    /// Returns the line of the inconsistency, starting from 1.
+    pub fn line(&self) -> u32 {
+        match *self {
+            Inconsistency::OtherUnit { line, .. }
+            | Inconsistency::MixedTabsAndSpaces { line }
+            | Inconsistency::UnrelatedToParent { line }
+            | Inconsistency::UnmatchedDedent { line } => line,
+        }
+    }
+}
+
+/// The indentation style of some code, returned by `infer_indentation`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct IndentationStyle {
+    /// The unit indenting most blocks, or `None` if the code has no
+    /// indented block using a single unit.
+    pub unit: Option<IndentUnit>,
+    /// The lines not indented with the dominant unit, or inconsistently
+    /// with their enclosing blocks, in the order of the code.
+    pub inconsistencies: Vec<Inconsistency>,
+}
+
+/// Returns the unit of an indentation increment, or `None` if it mixes
+/// tabs and spaces.
+fn unit_of(increment: &str) -> Option<IndentUnit> {
+    if increment.bytes().all(|c| c == b'\t') {
+        // Tabs are counted per block, several tabs are a single step
+        Some(IndentUnit::Tab)
+    } else if increment.bytes().all(|c| c == b' ') {
+        Some(IndentUnit::Spaces(increment.len()))
+    } else {
+        None
+    }
+}
+
+/// Returns the dominant indentation unit of `code` and the lines not
+/// using it.
+///
+/// Each indented block is compared with its enclosing block; the unit
+/// used by most blocks wins, and ties are broken in favor of the first
+/// one used in the code. Form feeds are ignored.
+///
+/// ```
+/// use python_parser::indentation::{infer_indentation, IndentUnit, Inconsistency};
+/// let style = infer_indentation("if x:\n    if y:\n      z\n    w\n");
+/// assert_eq!(style.unit, Some(IndentUnit::Spaces(4)));
+/// assert_eq!(style.inconsistencies, vec![
+///     Inconsistency::OtherUnit { line: 3, unit: IndentUnit::Spaces(2) },
+/// ]);
+/// ```
+pub fn infer_indentation(code: &str) -> IndentationStyle {
+    // Indentation of the enclosing blocks
+    let mut stack: Vec<&str> = vec![""];
+    // Blocks indented by each unit, in the order of the code
+    let mut blocks: Vec<(u32, IndentUnit)> = Vec::new();
+    let mut inconsistencies = Vec::new();
+    for token in tokenize(code) {
+        match token.kind {
+            TokenKind::Indent => {
+                let indent = token.text.trim_start_matches('\x0c');
+                let parent = *stack.last().unwrap(); // Never empty
+                stack.push(indent);
+                if !indent.starts_with(parent) {
+                    inconsistencies.push(Inconsistency::UnrelatedToParent { line: token.line });
+                    continue;
+                }
+                match unit_of(&indent[parent.len()..]) {
+                    Some(unit) => blocks.push((token.line, unit)),
+                    None => {
+                        inconsistencies.push(Inconsistency::MixedTabsAndSpaces { line: token.line })
+                    }
+                }
+            }
+            TokenKind::Dedent => {
+                stack.pop();
+            }
+            TokenKind::Error if token.text.is_empty() => {
+                inconsistencies.push(Inconsistency::UnmatchedDedent { line: token.line })
+            }
+            _ => (),
+        }
+    }
+
+    let mut counts: HashMap<IndentUnit, usize> = HashMap::new();
+    for &(_, unit) in &blocks {
+        *counts.entry(unit).or_insert(0) += 1;
+    }
+    let mut dominant: Option<IndentUnit> = None;
+    for &(_, unit) in &blocks {
+        match dominant {
+            Some(dominant) if counts[&unit] <= counts[&dominant] => (),
+            _ => dominant = Some(unit),
+        }
+    }
+    if let Some(dominant) = dominant {
+        inconsistencies.extend(
+            blocks
+                .into_iter()
+                .filter(|&(_, unit)| unit != dominant)
+                .map(|(line, unit)| Inconsistency::OtherUnit { line, unit }),
+        );
+        inconsistencies.sort_by_key(Inconsistency::line);
+    }
+    IndentationStyle {
+        unit: dominant,
+        inconsistencies,
+    }
+}
+
+/// Returns the whitespace at the start of the line containing `offset`.
+fn line_indentation(code: &str, offset: usize) -> &str {
+    let start = code[..offset].rfind('\n').map_or(0, |i| i + 1);
+    let line = &code[start..];
+    let end = line
+        .find(|c: char| c != ' ' && c != '\t')
+        .unwrap_or(line.len());
+    &line[..end]
+}
+
+/// Removes one level of `unit` from the end of `indentation`.
+fn dedent(mut indentation: String, unit: IndentUnit) -> String {
+    match unit {
+        IndentUnit::Spaces(n) if indentation.ends_with(' ') => {
+            let spaces = indentation.len() - indentation.trim_end_matches(' ').len();
+            let len = indentation.len() - spaces.min(n);
+            indentation.truncate(len);
+        }
+        _ => {
+            indentation.pop();
+        }
+    }
+    indentation
+}
+
+/// Returns the indentation of a line inserted at the end of `code` (the
+/// code before the cursor), indenting blocks by `unit`:
+///
+/// * one more level after the header of a compound statement (like
+///   `if x:`) or a backslash continuation;
+/// * one less level after `return`, `pass`, `raise`, `break` or
+///   `continue`;
+/// * in an unclosed bracket, aligned with the first item after it, or
+///   one more level than the line of the bracket if it ends that line;
+/// * in an unterminated string, the indentation of the current line;
+/// * otherwise, the indentation of the statement.
+///
+/// ```
+/// use python_parser::indentation::{next_line_indentation, IndentUnit};
+///
+/// let unit = IndentUnit::Spaces(4);
+/// assert_eq!(next_line_indentation("def f(x):", unit), "    ");
+/// assert_eq!(next_line_indentation("def f(x):\n    return x", unit), "");
+/// assert_eq!(next_line_indentation("x = f(a,", unit), "      ");
+/// assert_eq!(next_line_indentation("if x:\n    y = [", unit), "        ");
+/// ```
+pub fn next_line_indentation(code: &str, unit: IndentUnit) -> String {
+    let mut tokens: Vec<Token> = Vec::new();
+    for token in tokenize(code) {
+        match token.kind {
+            TokenKind::Error if token.text.contains(|c| c == '\'' || c == '"') => {
+                return line_indentation(code, code.len()).to_string();
+            }
+            TokenKind::Comment
+            | TokenKind::Nl
+            | TokenKind::Indent
+            | TokenKind::Dedent
+            | TokenKind::EndMarker => (),
+            _ => tokens.push(token),
+        }
+    }
+    // The tokens of the last logical line, without its newline
+    let end = tokens
+        .iter()
+        .rposition(|t| t.kind != TokenKind::Newline)
+        .map_or(0, |i| i + 1);
+    let start = tokens[..end]
+        .iter()
+        .rposition(|t| t.kind == TokenKind::Newline)
+        .map_or(0, |i| i + 1);
+    let line = &tokens[start..end];
+    let first = match line.first() {
+        Some(first) => first,
+        None => return line_indentation(code, code.len()).to_string(),
+    };
+    let indentation = line_indentation(code, first.offset).to_string();
+
+    let mut brackets = Vec::new();
+    for (i, token) in line.iter().enumerate() {
+        if token.kind != TokenKind::Op {
+            continue;
+        }
+        match token.text {
+            "(" | "[" | "{" => brackets.push(i),
+            ")" | "]" | "}" => {
+                brackets.pop();
+            }
+            _ => (),
+        }
+    }
+    if let Some(&open) = brackets.last() {
+        return match line.get(open + 1) {
+            // Aligned with the item, keeping the tabs before it
+            Some(item) if item.line == line[open].line => {
+                let line_start = code[..item.offset].rfind('\n').map_or(0, |i| i + 1);
+                code[line_start..item.offset]
+                    .chars()
+                    .map(|c| if c == '\t' { '\t' } else { ' ' })
+                    .collect()
+            }
+            // Hanging indentation
+            _ => line_indentation(code, line[open].offset).to_string() + &unit.text(),
+        };
+    }
+    if code
+        .trim_end_matches(|c| c == ' ' || c == '\t')
+        .ends_with('\\')
+    {
+        return indentation + &unit.text();
+    }
+    if is_op(line.last(), ":") && header_end(line) == Some(line.len() - 1) {
+        return indentation + &unit.text();
+    }
+    match first.text {
+        "return" | "pass" | "raise" | "break" | "continue" if first.kind == TokenKind::Name => {
+            dedent(indentation, unit)
+        }
+        _ => indentation,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dominant_unit() {
+        assert_eq!(infer_indentation("x = 1\n").unit, None);
+        assert_eq!(
+            infer_indentation("if x:\n  y\n").unit,
+            Some(IndentUnit::Spaces(2))
+        );
+        assert_eq!(
+            infer_indentation("if x:\n\tif y:\n\t\tz\n").unit,
+            Some(IndentUnit::Tab)
+        );
+        // Continuation lines and blank lines are not blocks
+        let style = infer_indentation("x = (1,\n   2)\nif x:\n\n        y\n\n");
+        assert_eq!(style.unit, Some(IndentUnit::Spaces(8)));
+        assert_eq!(style.inconsistencies, vec![]);
+        // Ties go to the first unit
+        let style = infer_indentation("if x:\n   y\nif z:\n  w\n");
+        assert_eq!(style.unit, Some(IndentUnit::Spaces(3)));
+        assert_eq!(
+            style.inconsistencies,
+            vec![Inconsistency::OtherUnit {
+                line: 4,
+                unit: IndentUnit::Spaces(2)
+            }]
+        );
+    }
+
+    #[test]
+    fn test_inconsistencies() {
+        let code =
+            "if a:\n    if b:\n\tc\nif d:\n  \te\nif f:\n\tg\nif h:\n    if i:\n      j\n   k\n";
+        let style = infer_indentation(code);
+        assert_eq!(style.unit, Some(IndentUnit::Spaces(4)));
+        assert_eq!(
+            style.inconsistencies,
+            vec![
+                Inconsistency::UnrelatedToParent { line: 3 },
+                Inconsistency::MixedTabsAndSpaces { line: 5 },
+                Inconsistency::OtherUnit {
+                    line: 7,
+                    unit: IndentUnit::Tab
+                },
+                Inconsistency::OtherUnit {
+                    line: 10,
+                    unit: IndentUnit::Spaces(2)
+                },
+                Inconsistency::UnmatchedDedent { line: 11 },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_unit_text() {
+        assert_eq!(IndentUnit::Spaces(2).text(), "  ");
+        assert_eq!(IndentUnit::Tab.text(), "\t");
+        assert_eq!(IndentUnit::Spaces(4).to_string(), "4 spaces");
+    }
+
+    #[test]
+    fn test_next_line_indentation() {
+        let indent = |code: &str| next_line_indentation(code, IndentUnit::Spaces(4));
+        assert_eq!(indent(""), "");
+        assert_eq!(indent("x = 1"), "");
+        assert_eq!(indent("if x:\n    y = 1"), "    ");
+        assert_eq!(indent("if x:\n    y = 1\n"), "    ");
+        assert_eq!(indent("class A(B):  # comment"), "    ");
+        assert_eq!(indent("if x:\n    for y in z:"), "        ");
+        assert_eq!(indent("if x: y"), "");
+        assert_eq!(indent("f = lambda:"), "");
+        assert_eq!(indent("x: int"), "");
+        assert_eq!(indent("def f():\n    if x:\n        raise E"), "    ");
+        assert_eq!(indent("while x:\n    continue"), "");
+        assert_eq!(indent("return"), "");
+        assert_eq!(indent("returned = 1"), "");
+        assert_eq!(indent("x = f(a, [b,"), "          ");
+        assert_eq!(indent("x = f(a, [b],"), "      ");
+        assert_eq!(indent("x = f(\n    a,"), "    ");
+        assert_eq!(indent("x = f(\n    a, g("), "        ");
+        assert_eq!(indent("x = f(a,\n      b)"), "");
+        assert_eq!(indent("d = {1:"), "     ");
+        assert_eq!(indent("if x and \\"), "    ");
+        assert_eq!(indent("def f():\n    '''Doc\n      more"), "      ");
+        let tab = |code: &str| next_line_indentation(code, IndentUnit::Tab);
+        assert_eq!(tab("if x:\n\tif y:"), "\t\t");
+        assert_eq!(tab("if x:\n\tpass"), "");
+        assert_eq!(tab("if x:\n\tf(a,"), "\t  ");
+        // Partial levels are removed
+        assert_eq!(indent("if x:\n  return"), "");
+    }
+}
@@ -0,0 +1,624 @@
+//! The inline-variable refactoring: replacing the uses of a local
+//! variable assigned once by its value, and removing the assignment.
+//!
+//! Like `rename`, it edits the code in place, and finds the variables
+//! with `definition::Bindings`. A value reading attributes or items of
+//! a variable is not inlined after an assignment to an attribute or item
+//! of that variable, but calls are assumed not to change them.
+
+use std::fmt;
+
+use definition::{BindingKind, Bindings};
+use keywords::{is_keyword, PARSED_VERSION};
+use lint::{is_name, is_op, top_level};
+use span::{Span, Spanned};
+use tokenizer::{tokenize, Token, TokenKind};
+
+/// Error returned by `inline_variable`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum InlineError {
+    /// There is no variable at the offset.
+    NotAVariable,
+    /// The variable is not local to a function, so it may be used by
+    /// other modules or after the function is defined.
+    NotLocal,
+    /// The variable is bound several times, or not by a simple
+    /// assignment (like `x = value` or `x: int = value`).
+    NotSingleAssignment,
+    /// The value has side effects (like a call), and inlining it would
+    /// evaluate it at another time, several times, or not at all; or it
+    /// creates an object (like a list), and the variable is used several
+    /// times.
+    SideEffects(Span),
+    /// A name of the value would refer to another variable at this use,
+    /// an attribute or item it reads is assigned before the use, or the
+    /// use is evaluated before the assignment.
+    ChangedValue(Span),
+    /// The variable is used in a way which cannot be inlined: deleted,
+    /// in its own value, or in an f-string.
+    UnsupportedUse(Span),
+}
+
+impl fmt::Display for InlineError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            InlineError::NotAVariable => write!(f, "no variable to inline"),
+            InlineError::NotLocal => write!(f, "only local variables can be inlined"),
+            InlineError::NotSingleAssignment => {
+                write!(
+                    f,
+                    "the variable is not assigned once by a simple assignment"
+                )
+            }
+            InlineError::SideEffects(_) => {
+                write!(f, "inlining the value would change its side effects")
+            }
+            InlineError::ChangedValue(_) => write!(f, "the value would change at a use"),
+            InlineError::UnsupportedUse(_) => write!(f, "a use of the variable cannot be inlined"),
+        }
+    }
+}
+
+impl ::std::error::Error for InlineError {}
+
+/// Whether `tokens[i]` follows a primary (like `a`, `a.b` or `a[b]`),
+/// so a bracket there opens the arguments of a call or a subscript.
+fn follows_primary(tokens: &[Token], i: usize) -> bool {
+    i > 0
+        && match tokens[i - 1].kind {
+            TokenKind::Name => !is_keyword(tokens[i - 1].text, PARSED_VERSION),
+            TokenKind::String => true,
+            TokenKind::Op => [")", "]", "}"].contains(&tokens[i - 1].text),
+            _ => false,
+        }
+}
+
+/// Whether `tokens[i]` opens the arguments of a call.
+fn is_call(tokens: &[Token], i: usize) -> bool {
+    is_op(tokens.get(i), "(") && follows_primary(tokens, i)
+}
+
+/// Whether `tokens[i]` opens a subscript.
+fn is_subscript(tokens: &[Token], i: usize) -> bool {
+    is_op(tokens.get(i), "[") && follows_primary(tokens, i)
+}
+
+/// Whether evaluating `tokens` creates a new object, whose identity
+/// would differ at each use: if they have a list, dict or set display,
+/// a comprehension or a lambda.
+fn creates_object(tokens: &[Token]) -> bool {
+    (0..tokens.len()).any(|i| {
+        (is_op(tokens.get(i), "[") && !is_subscript(tokens, i))
+            || is_op(tokens.get(i), "{")
+            || is_name(tokens.get(i), "for")
+            || is_name(tokens.get(i), "lambda")
+    })
+}
+
+/// Returns the index after the primary starting at `tokens[i]`, a name
+/// followed by attributes and subscripts.
+fn primary_end(tokens: &[Token], i: usize) -> usize {
+    let mut i = i + 1;
+    loop {
+        if is_op(tokens.get(i), ".") && tokens.get(i + 1).is_some_and(|t| t.kind == TokenKind::Name)
+        {
+            i += 2;
+        } else if is_op(tokens.get(i), "[") {
+            i += match top_level(&tokens[i + 1..], "]") {
+                Some(close) => close + 2,
+                None => return tokens.len(),
+            };
+        } else {
+            return i;
+        }
+    }
+}
+
+/// Returns the names whose attributes or items are read by `value`.
+fn read_bases<'a>(value: &[Token<'a>]) -> Vec<&'a str> {
+    (0..value.len())
+        .filter(|&i| {
+            value[i].kind == TokenKind::Name
+                && !is_keyword(value[i].text, PARSED_VERSION)
+                && !(i > 0 && is_op(value.get(i - 1), "."))
+                && (is_op(value.get(i + 1), ".") || is_subscript(value, i + 1))
+        })
+        .map(|i| value[i].text)
+        .collect()
+}
+
+/// Whether `tokens` assign to an attribute or item of one of `bases`,
+/// or delete it.
+fn stores_to(tokens: &[Token], bases: &[&str]) -> bool {
+    (0..tokens.len()).any(|i| {
+        let token = &tokens[i];
+        if token.kind != TokenKind::Name
+            || !bases.contains(&token.text)
+            || (i > 0 && is_op(tokens.get(i - 1), "."))
+        {
+            return false;
+        }
+        let end = primary_end(tokens, i);
+        if end == i + 1 {
+            return false; // Not an attribute or subscript
+        }
+        let statement_end = tokens[end..]
+            .iter()
+            .position(|t| t.kind == TokenKind::Newline || is_op(Some(t), ";"))
+            .map_or(tokens.len(), |j| end + j);
+        // Targets of `=`, of augmented assignments, or of `del`
+        let is_augmented = tokens.get(end).is_some_and(|t| {
+            t.kind == TokenKind::Op
+                && t.text.len() > 1
+                && t.text.ends_with('=')
+                && !["==", "<=", ">=", "!="].contains(&t.text)
+        });
+        let is_deleted = tokens[..i]
+            .iter()
+            .rev()
+            .take_while(|t| t.kind != TokenKind::Newline && !is_op(Some(t), ";"))
+            .any(|t| is_name(Some(t), "del"));
+        is_augmented || is_deleted || top_level(&tokens[end..statement_end], "=").is_some()
+    })
+}
+
+/// Whether evaluating `tokens` may have side effects: if they have
+/// calls, `await`, `yield` or assignment expressions.
+fn has_side_effects(tokens: &[Token]) -> bool {
+    (0..tokens.len()).any(|i| {
+        is_call(tokens, i)
+            || is_name(tokens.get(i), "await")
+            || is_name(tokens.get(i), "yield")
+            || is_op(tokens.get(i), ":=")
+    })
+}
+
+/// Whether evaluating the start of an expression, `tokens`, may have
+/// side effects before what follows: if it has a call whose arguments
+/// are closed, or an assignment expression.
+fn has_prior_side_effects(tokens: &[Token]) -> bool {
+    let mut calls = Vec::new();
+    for (i, token) in tokens.iter().enumerate() {
+        match (token.kind, token.text) {
+            (TokenKind::Op, "(") | (TokenKind::Op, "[") | (TokenKind::Op, "{") => {
+                calls.push(is_call(tokens, i))
+            }
+            (TokenKind::Op, ")") | (TokenKind::Op, "]") | (TokenKind::Op, "}") => {
+                if calls.pop() == Some(true) {
+                    return true;
+                }
+            }
+            (TokenKind::Op, ":=") => return true,
+            _ => (),
+        }
+    }
+    false
+}
+
+/// Whether the expression of `tokens` is a single name, literal, or
+/// primary (like `a.b[c](d)`), which never needs parentheses.
+fn is_atomic(tokens: &[Token]) -> bool {
+    let mut depth = 0usize;
+    tokens.iter().all(|token| {
+        let top_level = depth == 0;
+        match (token.kind, token.text) {
+            (TokenKind::Op, "(") | (TokenKind::Op, "[") | (TokenKind::Op, "{") => depth += 1,
+            (TokenKind::Op, ")") | (TokenKind::Op, "]") | (TokenKind::Op, "}") => {
+                depth = depth.saturating_sub(1)
+            }
+            _ => (),
+        }
+        !top_level
+            || match token.kind {
+                TokenKind::Name => {
+                    !is_keyword(token.text, PARSED_VERSION)
+                        || ["True", "False", "None"].contains(&token.text)
+                }
+                TokenKind::Number | TokenKind::String => true,
+                TokenKind::Op => ["(", "[", "{", "."].contains(&token.text),
+                _ => false,
+            }
+    })
+}
+
+/// Returns the value of `value` to put instead of the use
+/// `tokens[use_]`, with parentheses if they are needed there.
+fn replacement(code: &str, value: &[Token], tokens: &[Token], use_: usize) -> String {
+    let text = &code[value[0].offset..value[value.len() - 1].span().end];
+    let previous = if use_ == 0 {
+        None
+    } else {
+        tokens.get(use_ - 1)
+    };
+    let next = tokens.get(use_ + 1);
+    let needs_parentheses = if is_atomic(value) {
+        // `1.real` does not parse
+        value.len() == 1 && value[0].kind == TokenKind::Number && is_op(next, ".")
+    } else {
+        let is_whole = previous.is_some_and(|t| {
+            let is_assignment = t.kind == TokenKind::Op
+                && t.text.ends_with('=')
+                && !["==", "<=", ">=", "!="].contains(&t.text);
+            is_assignment
+                || ["(", "[", "{", ","].contains(&t.text)
+                || (t.kind == TokenKind::Name && t.text == "return")
+        }) && next.is_some_and(|t| {
+            t.kind == TokenKind::Newline || [")", "]", "}", ",", ";"].contains(&t.text)
+        });
+        let is_tuple = top_level(value, ",").is_some();
+        let first = &value[0];
+        !is_whole
+            || is_tuple
+            || is_name(Some(first), "lambda")
+            || is_name(Some(first), "yield")
+            || is_op(Some(first), "*")
+    };
+    if needs_parentheses {
+        format!("({})", text)
+    } else {
+        text.to_string()
+    }
+}
+
+/// Returns the edit removing the statement from `tokens[start]` to
+/// `tokens[end]` (a simple statement), replacing it by `pass` if it is
+/// the only one of its block.
+pub(crate) fn removal(code: &str, tokens: &[Token], start: usize, end: usize) -> (Span, String) {
+    let span = tokens[start].span().cover(tokens[end].span());
+    let previous = if start == 0 {
+        None
+    } else {
+        tokens.get(start - 1)
+    };
+    let next = tokens.get(end + 1);
+    if is_op(next, ";") {
+        let after = tokens.get(end + 2).map_or(code.len(), |t| t.offset);
+        return (Span::new(span.start, after), String::new());
+    }
+    if is_op(previous, ";") {
+        return (Span::new(previous.unwrap().offset, span.end), String::new());
+    }
+    let after_newline = tokens.get(end + 2).map(|t| t.kind);
+    let only_in_block = is_op(previous, ":")
+        || (previous.map(|t| t.kind) == Some(TokenKind::Indent)
+            && match after_newline {
+                None | Some(TokenKind::Dedent) | Some(TokenKind::EndMarker) => true,
+                _ => false,
+            });
+    if only_in_block {
+        return (span, "pass".to_string());
+    }
+    // The whole lines of the statement, with its comment
+    let line_start = code[..span.start].rfind('\n').map_or(0, |i| i + 1);
+    let line_end = code[span.end..]
+        .find('\n')
+        .map_or(code.len(), |i| span.end + i + 1);
+    (Span::new(line_start, line_end), String::new())
+}
+
+/// Returns the edits inlining the local variable at `offset` (or ending
+/// at it): replacing its uses by its value, with parentheses if needed,
+/// and removing its assignment.
+///
+/// A value with side effects (calls, `await`, `yield` or assignment
+/// expressions) is only inlined in a single use in the next statement,
+/// if nothing before the use in that statement has side effects.
+///
+/// ```
+/// use python_parser::inline::inline_variable;
+/// use python_parser::span::replace_spans;
+///
+/// let code = "def area(r):\n    r2 = r * r  # squared\n    return 3.14 * r2\n";
+/// let edits = inline_variable(code, code.find("r2").unwrap()).unwrap();
+/// let edits: Vec<_> = edits.iter().map(|&(span, ref text)| (span, &text[..])).collect();
+/// assert_eq!(
+///     replace_spans(code, &edits).unwrap(),
+///     "def area(r):\n    return 3.14 * (r * r)\n"
+/// );
+/// ```
+pub fn inline_variable(code: &str, offset: usize) -> Result<Vec<(Span, String)>, InlineError> {
+    let bindings = Bindings::new(code);
+    match bindings.resolve(offset) {
+        Some(binding) if binding.kind == BindingKind::Variable => (),
+        _ => return Err(InlineError::NotAVariable),
+    }
+    let references = bindings.references(offset);
+    let is_binding = |span: Span| bindings.resolve(span.start).is_some_and(|b| b.span == span);
+    let (assignments, uses): (Vec<Span>, Vec<Span>) =
+        references.into_iter().partition(|&span| is_binding(span));
+    if assignments.len() != 1 {
+        return Err(InlineError::NotSingleAssignment);
+    }
+    let binding = *bindings.resolve(assignments[0].start).unwrap();
+    if !bindings.is_local(&binding) {
+        return Err(InlineError::NotLocal);
+    }
+
+    let tokens: Vec<_> = tokenize(code)
+        .filter(|t| t.kind != TokenKind::Comment && t.kind != TokenKind::Nl)
+        .collect();
+    let index = |offset: usize| tokens.iter().position(|t| t.offset == offset).unwrap();
+    let start = index(binding.statement.start);
+    let end = tokens
+        .iter()
+        .rposition(|t| t.span().end == binding.statement.end)
+        .unwrap();
+    let statement = &tokens[start..end + 1];
+    // `x = value` or `x: annotation = value`
+    let equal = match top_level(statement, "=") {
+        Some(equal) if equal + 1 < statement.len() => equal,
+        _ => return Err(InlineError::NotSingleAssignment),
+    };
+    let is_simple = statement[0].span() == binding.span
+        && (equal == 1 || is_op(statement.get(1), ":"))
+        && top_level(&statement[equal + 1..], "=").is_none();
+    if !is_simple {
+        return Err(InlineError::NotSingleAssignment);
+    }
+    let value = &statement[equal + 1..];
+    let value_span = value[0].span().cover(value[value.len() - 1].span());
+    let side_effects = has_side_effects(value);
+    let bases = read_bases(value);
+
+    // Names bound in f-strings are not found by `Bindings`
+    let in_fstring = tokens.iter().find(|t| {
+        let prefix = t.text.split(|c| c == '\'' || c == '"').next().unwrap_or("");
+        t.kind == TokenKind::String
+            && prefix.contains(|c| c == 'f' || c == 'F')
+            && t.text.contains(binding.name)
+            && bindings.scope_at(t.offset) == bindings.scope_at(binding.span.start)
+    });
+    if let Some(string) = in_fstring {
+        return Err(InlineError::UnsupportedUse(string.span()));
+    }
+    if uses.is_empty() && side_effects {
+        return Err(InlineError::SideEffects(value_span));
+    }
+    // The first statement after the assignment, in the same block
+    let next_statement = tokens[end + 1..]
+        .iter()
+        .position(|t| t.kind != TokenKind::Newline && !is_op(Some(t), ";"))
+        .map(|i| end + 1 + i)
+        .filter(|&i| tokens[i].kind != TokenKind::Indent && tokens[i].kind != TokenKind::Dedent);
+
+    let mut edits = vec![removal(code, &tokens, start, end)];
+    for &use_ in &uses {
+        let i = index(use_.start);
+        if use_.start < binding.statement.start {
+            return Err(InlineError::ChangedValue(use_));
+        }
+        let statement_start = tokens[..i]
+            .iter()
+            .rposition(|t| t.kind == TokenKind::Newline || is_op(Some(t), ";"))
+            .map_or(0, |i| i + 1);
+        let statement_start = statement_start
+            + tokens[statement_start..i]
+                .iter()
+                .take_while(|t| t.kind == TokenKind::Indent || t.kind == TokenKind::Dedent)
+                .count();
+        if use_.end <= binding.statement.end || is_name(tokens.get(statement_start), "del") {
+            return Err(InlineError::UnsupportedUse(use_));
+        }
+        // The names of the value must refer to the same variables
+        for (j, token) in value.iter().enumerate() {
+            let is_attribute = j > 0 && is_op(value.get(j - 1), ".");
+            if token.kind != TokenKind::Name || is_attribute {
+                continue;
+            }
+            let before = bindings.resolve(token.offset);
+            if before.is_some_and(|b| value_span.contains(b.span.start)) {
+                continue; // Bound in the value, like in a comprehension
+            }
+            let after = bindings.resolve_name(token.text, use_.start);
+            if before.map(|b| b.span) != after.map(|b| b.span) {
+                return Err(InlineError::ChangedValue(use_));
+            }
+        }
+        if stores_to(&tokens[end + 1..i], &bases) {
+            return Err(InlineError::ChangedValue(use_));
+        }
+        if use_ != uses[0] && creates_object(value) {
+            return Err(InlineError::SideEffects(use_));
+        }
+        if side_effects {
+            let in_next_statement = uses.len() == 1
+                && next_statement == Some(statement_start)
+                && bindings.scope_at(use_.start) == bindings.scope_at(binding.span.start);
+            let before_use = &tokens[statement_start..i];
+            let conditional = before_use.iter().skip(1).any(|t| {
+                t.kind == TokenKind::Name && ["if", "else", "and", "or"].contains(&t.text)
+            });
+            if !in_next_statement
+                || conditional
+                || is_name(before_use.first(), "while")
+                || has_prior_side_effects(before_use)
+            {
+                return Err(InlineError::SideEffects(use_));
+            }
+        }
+        edits.push((use_, replacement(code, value, &tokens, i)));
+    }
+    Ok(edits)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use span::replace_spans;
+
+    /// Returns `code` with the variable at the `n`-th name `name`
+    /// inlined.
+    fn inlined(code: &str, name: &str, n: usize) -> Result<String, InlineError> {
+        let offset = tokenize(code)
+            .filter(|t| t.text == name)
+            .nth(n)
+            .unwrap()
+            .offset;
+        let edits = inline_variable(code, offset)?;
+        let edits: Vec<_> = edits.iter().map(|&(span, ref t)| (span, &t[..])).collect();
+        Ok(replace_spans(code, &edits).unwrap())
+    }
+
+    #[test]
+    fn test_inline_variable() {
+        let code = "def f(a, b):
+    x = a.b[0]
+    y = a + b
+    z = -1
+    t = a, b
+    print(x, y, y * 2, z.real, x.c, t)
+    g(k=y); return [y, t]
+";
+        let x_inlined = "def f(a, b):
+    y = a + b
+    z = -1
+    t = a, b
+    print(a.b[0], y, y * 2, z.real, a.b[0].c, t)
+    g(k=y); return [y, t]
+";
+        assert_eq!(inlined(code, "x", 0), Ok(x_inlined.to_string()));
+        assert_eq!(
+            inlined(code, "y", 2),
+            Ok(code
+                .replace("    y = a + b\n", "")
+                .replace("x, y, y * 2", "x, a + b, (a + b) * 2")
+                .replace("g(k=y); return [y, t]", "g(k=a + b); return [a + b, t]"))
+        );
+        assert_eq!(
+            inlined(code, "z", 0),
+            Ok(code
+                .replace("    z = -1\n", "")
+                .replace("z.real", "(-1).real"))
+        );
+        assert_eq!(
+            inlined(code, "t", 0),
+            Ok(code
+                .replace("    t = a, b\n", "")
+                .replace("x.c, t)", "x.c, (a, b))")
+                .replace("[y, t]", "[y, (a, b)]"))
+        );
+
+        // Removed statements
+        assert_eq!(
+            inlined("def f():\n    x = 1; y = x\n    if c: z = 2\n", "x", 0),
+            Ok("def f():\n    y = 1\n    if c: z = 2\n".to_string())
+        );
+        assert_eq!(
+            inlined("def f():\n    if c:\n        x = 1\n", "x", 0),
+            Ok("def f():\n    if c:\n        pass\n".to_string())
+        );
+        assert_eq!(
+            inlined("def f():\n    if c: x = 1\n    g()\n", "x", 0),
+            Ok("def f():\n    if c: pass\n    g()\n".to_string())
+        );
+
+        // A single new object, and reads of items which are not assigned
+        assert_eq!(
+            inlined("def f(a):\n    x = [a]\n    return x\n", "x", 0),
+            Ok("def f(a):\n    return [a]\n".to_string())
+        );
+        assert_eq!(
+            inlined(
+                "def f(a, b):\n    x = a[0]\n    b[0] = a[1]\n    return x, x\n",
+                "x",
+                0
+            ),
+            Ok("def f(a, b):\n    b[0] = a[1]\n    return a[0], a[0]\n".to_string())
+        );
+
+        // Side effects
+        let code = "def f(a):\n    x = g(a)\n    h(a.b, x)\n";
+        assert_eq!(
+            inlined(code, "x", 0),
+            Ok("def f(a):\n    h(a.b, g(a))\n".to_string())
+        );
+        let side_effects = |code: &str| match inlined(code, "x", 0) {
+            Err(InlineError::SideEffects(_)) => true,
+            _ => false,
+        };
+        assert!(side_effects("def f():\n    x = g()\n"));
+        assert!(side_effects("def f():\n    x = g()\n    h(x, x)\n"));
+        assert!(side_effects("def f():\n    x = g()\n    h()\n    i(x)\n"));
+        assert!(side_effects("def f():\n    x = g()\n    h(i(), x)\n"));
+        assert!(side_effects("def f():\n    x = g()\n    h(c or x)\n"));
+        assert!(side_effects("def f():\n    x = g()\n    while x: pass\n"));
+        assert!(side_effects("def f():\n    x = g()\n    h(lambda: x)\n"));
+        assert_eq!(
+            inlined("def f():\n    x = await g()\n    return x\n", "x", 0),
+            Ok("def f():\n    return await g()\n".to_string())
+        );
+
+        // Refused
+        let span = |code: &str, name: &str| {
+            let start = code.rfind(name).unwrap();
+            Span::new(start, start + name.len())
+        };
+        let code = "def f(a):\n    x = a\n    a = 2\n    return x\n";
+        assert_eq!(
+            inlined(code, "x", 0),
+            Err(InlineError::ChangedValue(span(code, "x")))
+        );
+        let code = "def f(a):\n    x = a[0].b\n    a[0].b = 5\n    return x\n";
+        assert_eq!(
+            inlined(code, "x", 0),
+            Err(InlineError::ChangedValue(span(code, "x")))
+        );
+        let code = "def f(a):\n    x = a.b\n    del a.b\n    return x\n";
+        assert_eq!(
+            inlined(code, "x", 0),
+            Err(InlineError::ChangedValue(span(code, "x")))
+        );
+        let code = "def f(a):\n    x = a[0]\n    a[1] += 5\n    return x\n";
+        assert_eq!(
+            inlined(code, "x", 0),
+            Err(InlineError::ChangedValue(span(code, "x")))
+        );
+        // Each use would be another object
+        let code = "def f(a):\n    x = []\n    x.append(a)\n    return x\n";
+        assert_eq!(
+            inlined(code, "x", 0),
+            Err(InlineError::SideEffects(span(code, "x")))
+        );
+        for value in &["[1]", "{}", "(y for y in a)", "lambda: a"] {
+            let code = format!("def f(a):\n    x = {}\n    return x is x\n", value);
+            assert_eq!(
+                inlined(&code, "x", 0),
+                Err(InlineError::SideEffects(span(&code, "x")))
+            );
+        }
+        let code = "def f():\n    x = 1\n    del x\n";
+        assert_eq!(
+            inlined(code, "x", 0),
+            Err(InlineError::UnsupportedUse(span(code, "x")))
+        );
+        let code = "def f():\n    x = 1\n    return f'{x}'\n";
+        assert_eq!(
+            inlined(code, "x", 0),
+            Err(InlineError::UnsupportedUse(span(code, "f'{x}'")))
+        );
+        assert_eq!(
+            inlined("x = 1\nprint(x)\n", "x", 0),
+            Err(InlineError::NotLocal)
+        );
+        assert_eq!(
+            inlined("def f():\n    x = 1\n    x += 1\n", "x", 0),
+            Err(InlineError::NotSingleAssignment)
+        );
+        assert_eq!(
+            inlined("def f():\n    x = y = 1\n    return x\n", "x", 0),
+            Err(InlineError::NotSingleAssignment)
+        );
+        assert_eq!(
+            inlined("def f():\n    for x in y: pass\n", "x", 0),
+            Err(InlineError::NotSingleAssignment)
+        );
+        assert_eq!(
+            inlined("def f(x): return x\n", "x", 0),
+            Err(InlineError::NotAVariable)
+        );
+        assert_eq!(
+            inlined("def f(): return g\n", "g", 0),
+            Err(InlineError::NotAVariable)
+        );
+    }
+}
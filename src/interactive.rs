@@ -0,0 +1,169 @@
+//! Parsing of the commands typed in an interactive interpreter, which
+//! needs to tell incomplete commands from invalid ones.
+
+use ast::*;
+use errors::ParseError;
+use keywords::COMPOUND_KEYWORDS;
+use parse_single;
+use tokenizer::{tokenize, Token, TokenKind};
+
+/// Returns whether `token` is a string literal missing the end of its
+/// triple quotes, which is continued by the next lines.
+fn is_unterminated_triple_quoted(token: &Token) -> bool {
+    let quotes = token
+        .text
+        .trim_start_matches(|c: char| c.is_ascii_alphabetic());
+    quotes.starts_with("'''") || quotes.starts_with("\"\"\"")
+}
+
+/// Returns whether more lines are needed to complete `code`.
+fn is_incomplete(code: &str) -> bool {
+    let mut depth = 0usize;
+    let mut level = 0usize;
+    let mut last: Option<Token> = None;
+    // First token of the last logical line, and the indentation level
+    // of that line.
+    let mut line_start: Option<(Token, usize)> = None;
+    let mut at_line_start = true;
+    for token in tokenize(code) {
+        match token.kind {
+            TokenKind::Comment | TokenKind::Nl | TokenKind::EndMarker => continue,
+            TokenKind::Newline => {
+                at_line_start = true;
+                continue;
+            }
+            TokenKind::Indent => {
+                level += 1;
+                continue;
+            }
+            TokenKind::Dedent => {
+                level = level.saturating_sub(1);
+                continue;
+            }
+            TokenKind::Error if is_unterminated_triple_quoted(&token) => return true,
+            TokenKind::Error if token.text == "\\" => return true, // At the end of the code
+            TokenKind::Op => match token.text {
+                "(" | "[" | "{" => depth += 1,
+                ")" | "]" | "}" => depth = depth.saturating_sub(1),
+                _ => (),
+            },
+            _ => (),
+        }
+        if at_line_start {
+            line_start = Some((token, level));
+            at_line_start = false;
+        }
+        last = Some(token);
+    }
+
+    let (last, (first, level)) = match (last, line_start) {
+        (Some(last), Some(line_start)) => (last, line_start),
+        _ => return false, // Only blank lines and comments
+    };
+    if depth > 0 {
+        return true;
+    }
+    let after_last = &code[last.offset + last.text.len()..];
+    if after_last
+        .trim_start_matches(&[' ', '\t', '\x0c'][..])
+        .starts_with('\\')
+    {
+        return true; // Continued by a backslash
+    }
+    let is_compound = first.kind == TokenKind::Name && COMPOUND_KEYWORDS.contains(&first.text);
+    if first.text == "@" || (is_compound && last.text == ":") {
+        return true; // The block or the decorated definition is missing
+    }
+    // The last line of a compound statement must be ended
+    (is_compound || level > 0) && !code.ends_with('\n')
+}
+
+/// Parses a command typed in an interactive interpreter, like Python's
+/// `codeop.compile_command(code, symbol='single')`.
+///
+/// Returns `Ok(None)` if the command is incomplete and the interpreter
+/// should read more lines: after a line opening a block (`if foo:`),
+/// on the unfinished last line of a block, inside brackets or
+/// triple-quoted strings, or after a backslash.
+///
+/// ```
+/// use python_parser::interactive::parse_command;
+/// assert_eq!(parse_command("if foo:").unwrap(), None);
+/// assert_eq!(parse_command("if foo:\n    bar()").unwrap(), None);
+/// assert!(parse_command("if foo:\n    bar()\n").unwrap().is_some());
+/// assert!(parse_command("foo +").is_err());
+/// ```
+pub fn parse_command(code: &str) -> Result<Option<Vec<Statement>>, ParseError> {
+    if is_incomplete(code) {
+        return Ok(None);
+    }
+    let is_blank = code
+        .lines()
+        .map(|line| line.trim_start_matches(&[' ', '\t', '\x0c'][..]))
+        .all(|line| line.is_empty() || line.starts_with('#') || line == "\r");
+    if is_blank {
+        return Ok(Some(Vec::new()));
+    }
+    parse_single(code).map(Some)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_incomplete() {
+        for code in &[
+            "x = (",
+            "x = [\n1,\n",
+            "x = 1 \\",
+            "x = 1 \\\n",
+            "x = \"\"\"foo\nbar",
+            "if x:",
+            "if x:  # comment",
+            "if x:\n  # comment",
+            "if x:\n  pass",
+            "if x:\n  pass\n  ",
+            "if x:\n  pass\nelse:",
+            "def f(): pass",
+            "async def f():",
+            "@foo",
+            "@foo\n",
+        ] {
+            assert_eq!(parse_command(code), Ok(None), "{:?}", code);
+        }
+    }
+
+    #[test]
+    fn test_complete() {
+        for code in &[
+            "",
+            "\n\n",
+            "# comment",
+            "x",
+            "x = (1,\n2)",
+            "x = 1 # comment \\",
+            "if x:\n  pass\n",
+            "if x: pass\n",
+            "@foo\ndef f(): pass\n",
+            "if x:\n  if y:\n    pass\n",
+        ] {
+            assert!(parse_command(code).unwrap().is_some(), "{:?}", code);
+        }
+        assert_eq!(parse_command("x; y").unwrap().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_invalid() {
+        for code in &[
+            "x +",
+            "x = )",
+            "x = \"foo",
+            "lambda:",
+            "if x:\nfoo",
+            "x = 1 if y",
+        ] {
+            assert!(parse_command(code).is_err(), "{:?}", code);
+        }
+    }
+}
@@ -0,0 +1,325 @@
+//! A small JSON reader and writer, for the files of other tools (eg.
+//! notebooks) and machine-readable reports.
+
+use std::char;
+use std::fmt;
+
+/// Nesting limit of arrays and objects, to avoid stack overflows on
+/// untrusted files.
+const MAX_DEPTH: usize = 512;
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    /// Members in the order of the file.
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    /// Parses a JSON document, and returns the offset of the first
+    /// error if it is invalid.
+    pub(crate) fn parse(text: &str) -> Result<Json, usize> {
+        let mut reader = Reader {
+            text: text.as_bytes(),
+            pos: 0,
+            depth: 0,
+        };
+        let value = reader.value()?;
+        reader.spaces();
+        if reader.pos != reader.text.len() {
+            return Err(reader.pos);
+        }
+        Ok(value)
+    }
+
+    /// Returns the value of the member `key` of an object.
+    pub(crate) fn get(&self, key: &str) -> Option<&Json> {
+        match *self {
+            Json::Object(ref members) => members.iter().find(|m| m.0 == key).map(|m| &m.1),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn as_str(&self) -> Option<&str> {
+        match *self {
+            Json::String(ref s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn as_array(&self) -> Option<&[Json]> {
+        match *self {
+            Json::Array(ref items) => Some(items),
+            _ => None,
+        }
+    }
+}
+
+/// Writes `s` as a JSON string, with its quotes.
+fn write_string(f: &mut fmt::Formatter, s: &str) -> fmt::Result {
+    f.write_str("\"")?;
+    for c in s.chars() {
+        match c {
+            '"' => f.write_str("\\\"")?,
+            '\\' => f.write_str("\\\\")?,
+            '\n' => f.write_str("\\n")?,
+            '\r' => f.write_str("\\r")?,
+            '\t' => f.write_str("\\t")?,
+            '\x00'..='\x1f' => write!(f, "\\u{:04x}", c as u32)?,
+            _ => write!(f, "{}", c)?,
+        }
+    }
+    f.write_str("\"")
+}
+
+/// Compact JSON, without spaces.
+impl fmt::Display for Json {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Json::Null => f.write_str("null"),
+            Json::Bool(b) => write!(f, "{}", b),
+            // JSON has no infinities and NaN
+            Json::Number(n) if !n.is_finite() => f.write_str("null"),
+            Json::Number(n) => write!(f, "{}", n),
+            Json::String(ref s) => write_string(f, s),
+            Json::Array(ref items) => {
+                f.write_str("[")?;
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(",")?;
+                    }
+                    item.fmt(f)?;
+                }
+                f.write_str("]")
+            }
+            Json::Object(ref members) => {
+                f.write_str("{")?;
+                for (i, (key, value)) in members.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(",")?;
+                    }
+                    write_string(f, key)?;
+                    f.write_str(":")?;
+                    value.fmt(f)?;
+                }
+                f.write_str("}")
+            }
+        }
+    }
+}
+
+struct Reader<'a> {
+    text: &'a [u8],
+    pos: usize,
+    depth: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn spaces(&mut self) {
+        while let Some(b' ') | Some(b'\t') | Some(b'\n') | Some(b'\r') = self.peek() {
+            self.pos += 1;
+        }
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.text.get(self.pos).cloned()
+    }
+
+    /// Consumes `expected`, or fails at the current position.
+    fn expect(&mut self, expected: &[u8]) -> Result<(), usize> {
+        if self.text[self.pos..].starts_with(expected) {
+            self.pos += expected.len();
+            Ok(())
+        } else {
+            Err(self.pos)
+        }
+    }
+
+    fn value(&mut self) -> Result<Json, usize> {
+        self.spaces();
+        match self.peek() {
+            Some(b'n') => self.expect(b"null").map(|()| Json::Null),
+            Some(b't') => self.expect(b"true").map(|()| Json::Bool(true)),
+            Some(b'f') => self.expect(b"false").map(|()| Json::Bool(false)),
+            Some(b'"') => self.string().map(Json::String),
+            Some(b'[') => self.nested(|r| r.array().map(Json::Array)),
+            Some(b'{') => self.nested(|r| r.object().map(Json::Object)),
+            Some(b'-') | Some(b'0'..=b'9') => self.number(),
+            _ => Err(self.pos),
+        }
+    }
+
+    fn nested<F: FnOnce(&mut Self) -> Result<Json, usize>>(&mut self, f: F) -> Result<Json, usize> {
+        if self.depth == MAX_DEPTH {
+            return Err(self.pos);
+        }
+        self.depth += 1;
+        let value = f(self);
+        self.depth -= 1;
+        value
+    }
+
+    fn array(&mut self) -> Result<Vec<Json>, usize> {
+        self.pos += 1; // [
+        let mut items = Vec::new();
+        self.spaces();
+        if self.peek() == Some(b']') {
+            self.pos += 1;
+            return Ok(items);
+        }
+        loop {
+            items.push(self.value()?);
+            self.spaces();
+            match self.peek() {
+                Some(b',') => self.pos += 1,
+                Some(b']') => {
+                    self.pos += 1;
+                    return Ok(items);
+                }
+                _ => return Err(self.pos),
+            }
+        }
+    }
+
+    fn object(&mut self) -> Result<Vec<(String, Json)>, usize> {
+        self.pos += 1; // {
+        let mut members = Vec::new();
+        self.spaces();
+        if self.peek() == Some(b'}') {
+            self.pos += 1;
+            return Ok(members);
+        }
+        loop {
+            self.spaces();
+            if self.peek() != Some(b'"') {
+                return Err(self.pos);
+            }
+            let key = self.string()?;
+            self.spaces();
+            self.expect(b":")?;
+            members.push((key, self.value()?));
+            self.spaces();
+            match self.peek() {
+                Some(b',') => self.pos += 1,
+                Some(b'}') => {
+                    self.pos += 1;
+                    return Ok(members);
+                }
+                _ => return Err(self.pos),
+            }
+        }
+    }
+
+    fn number(&mut self) -> Result<Json, usize> {
+        let start = self.pos;
+        while let Some(b'-') | Some(b'+') | Some(b'.') | Some(b'e') | Some(b'E')
+        | Some(b'0'..=b'9') = self.peek()
+        {
+            self.pos += 1;
+        }
+        // The characters are ASCII, so this is valid UTF-8
+        let text = String::from_utf8_lossy(&self.text[start..self.pos]);
+        text.parse().map(Json::Number).map_err(|_| start)
+    }
+
+    /// Reads the 4 hexadecimal digits of a `\u` escape.
+    fn hex4(&mut self) -> Result<u32, usize> {
+        let digits = self.text.get(self.pos..self.pos + 4).ok_or(self.pos)?;
+        let digits = ::std::str::from_utf8(digits).map_err(|_| self.pos)?;
+        let value = u32::from_str_radix(digits, 16).map_err(|_| self.pos)?;
+        self.pos += 4;
+        Ok(value)
+    }
+
+    fn string(&mut self) -> Result<String, usize> {
+        self.pos += 1; // "
+        let mut s = Vec::new();
+        loop {
+            let c = self.peek().ok_or(self.pos)?;
+            self.pos += 1;
+            match c {
+                b'"' => break,
+                b'\\' => {
+                    let escape = self.peek().ok_or(self.pos)?;
+                    self.pos += 1;
+                    let c = match escape {
+                        b'"' => '"',
+                        b'\\' => '\\',
+                        b'/' => '/',
+                        b'b' => '\x08',
+                        b'f' => '\x0c',
+                        b'n' => '\n',
+                        b'r' => '\r',
+                        b't' => '\t',
+                        b'u' => {
+                            let start = self.pos - 2;
+                            let mut code = self.hex4()?;
+                            if (0xd800..0xdc00).contains(&code)
+                                && self.text[self.pos..].starts_with(b"\\u")
+                            {
+                                self.pos += 2;
+                                let low = self.hex4()?;
+                                if !(0xdc00..0xe000).contains(&low) {
+                                    return Err(start);
+                                }
+                                code = 0x10000 + ((code - 0xd800) << 10) + (low - 0xdc00);
+                            }
+                            char::from_u32(code).ok_or(start)?
+                        }
+                        _ => return Err(self.pos - 1),
+                    };
+                    let mut buf = [0; 4];
+                    s.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+                }
+                0..=0x1f => return Err(self.pos - 1),
+                _ => s.push(c),
+            }
+        }
+        // The input is a `str`, and escapes are encoded as UTF-8
+        String::from_utf8(s).map_err(|_| self.pos)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let json = Json::parse(
+            r#" {"a": [1, -2.5e1, true, null], "b": "x\n\u00e9\ud83d\ude00\"", "c": {}} "#,
+        );
+        assert_eq!(
+            json,
+            Ok(Json::Object(vec![
+                (
+                    "a".to_string(),
+                    Json::Array(vec![
+                        Json::Number(1.),
+                        Json::Number(-25.),
+                        Json::Bool(true),
+                        Json::Null
+                    ])
+                ),
+                ("b".to_string(), Json::String("x\né😀\"".to_string())),
+                ("c".to_string(), Json::Object(vec![])),
+            ]))
+        );
+        assert_eq!(Json::parse("[1, 2"), Err(5));
+        assert_eq!(Json::parse("[1] 2"), Err(4));
+        assert_eq!(Json::parse("\"\\x\""), Err(2));
+        assert_eq!(Json::parse(&"[".repeat(10000)), Err(MAX_DEPTH));
+    }
+
+    #[test]
+    fn test_display() {
+        let text = r#"{"a":[1,-2.5,true,null],"b":"x\n\"\\\u0001é","c":{}}"#;
+        let json = Json::parse(text).unwrap();
+        assert_eq!(json.to_string(), text);
+        assert_eq!(Json::Number(1e300 * 1e300).to_string(), "null");
+    }
+}
@@ -0,0 +1,142 @@
+//! Classification of identifiers as keywords, depending on the Python
+//! version.
+//!
+//! Versions are `(major, minor)` pairs, like the first two items of
+//! `sys.version_info`.
+
+/// The version of the syntax parsed by this crate.
+pub const PARSED_VERSION: (u8, u8) = (3, 7);
+
+/// A keyword, with the first version it is a keyword in, and the last
+/// one (if it is not anymore).
+type Entry = (&'static str, (u8, u8), Option<(u8, u8)>);
+
+const KEYWORDS: [Entry; 38] = [
+    ("False", (3, 0), None),
+    ("None", (3, 0), None),
+    ("True", (3, 0), None),
+    ("__peg_parser__", (3, 9), Some((3, 9))),
+    ("and", (2, 0), None),
+    ("as", (2, 6), None),
+    ("assert", (2, 0), None),
+    ("async", (3, 7), None),
+    ("await", (3, 7), None),
+    ("break", (2, 0), None),
+    ("class", (2, 0), None),
+    ("continue", (2, 0), None),
+    ("def", (2, 0), None),
+    ("del", (2, 0), None),
+    ("elif", (2, 0), None),
+    ("else", (2, 0), None),
+    ("except", (2, 0), None),
+    ("exec", (2, 0), Some((2, 7))),
+    ("finally", (2, 0), None),
+    ("for", (2, 0), None),
+    ("from", (2, 0), None),
+    ("global", (2, 0), None),
+    ("if", (2, 0), None),
+    ("import", (2, 0), None),
+    ("in", (2, 0), None),
+    ("is", (2, 0), None),
+    ("lambda", (2, 0), None),
+    ("nonlocal", (3, 0), None),
+    ("not", (2, 0), None),
+    ("or", (2, 0), None),
+    ("pass", (2, 0), None),
+    ("print", (2, 0), Some((2, 7))),
+    ("raise", (2, 0), None),
+    ("return", (2, 0), None),
+    ("try", (2, 0), None),
+    ("while", (2, 0), None),
+    ("with", (2, 6), None),
+    ("yield", (2, 0), None),
+];
+
+/// Keywords starting a statement that has a block.
+pub(crate) const COMPOUND_KEYWORDS: [&str; 12] = [
+    "if", "elif", "else", "while", "for", "try", "except", "finally", "with", "def", "class",
+    "async",
+];
+
+/// Soft keywords, which are keywords only in some contexts.
+const SOFT_KEYWORDS: [Entry; 6] = [
+    ("_", (3, 10), None),
+    ("async", (3, 5), Some((3, 6))),
+    ("await", (3, 5), Some((3, 6))),
+    ("case", (3, 10), None),
+    ("match", (3, 10), None),
+    ("type", (3, 12), None),
+];
+
+fn lookup(table: &[Entry], name: &str, version: (u8, u8)) -> bool {
+    table.iter().any(|&(keyword, first, last)| {
+        keyword == name
+            && first <= version
+            && match last {
+                Some(last) => version <= last,
+                None => true,
+            }
+    })
+}
+
+/// Returns whether `name` is a keyword in the given version of Python,
+/// so it can't be used as an identifier.
+///
+/// ```
+/// use python_parser::keywords::is_keyword;
+/// assert!(is_keyword("nonlocal", (3, 7)));
+/// assert!(!is_keyword("async", (3, 6)));
+/// assert!(is_keyword("async", (3, 7)));
+/// assert!(is_keyword("print", (2, 7)) && !is_keyword("print", (3, 0)));
+/// assert!(!is_keyword("match", (3, 10))); // Only a soft keyword
+/// ```
+pub fn is_keyword(name: &str, version: (u8, u8)) -> bool {
+    lookup(&KEYWORDS, name, version)
+}
+
+/// Returns whether `name` is a soft keyword in the given version of
+/// Python: a keyword only in some contexts, which can still be used as
+/// an identifier.
+///
+/// Before they became keywords, `async` and `await` are soft keywords
+/// in Python 3.5 and 3.6.
+///
+/// ```
+/// use python_parser::keywords::is_soft_keyword;
+/// assert!(is_soft_keyword("match", (3, 10)));
+/// assert!(!is_soft_keyword("match", (3, 9)));
+/// assert!(is_soft_keyword("type", (3, 12)));
+/// assert!(!is_soft_keyword("if", (3, 12)));
+/// ```
+pub fn is_soft_keyword(name: &str, version: (u8, u8)) -> bool {
+    lookup(&SOFT_KEYWORDS, name, version)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_keywords() {
+        // keyword.kwlist of Python 3.7
+        let kwlist = [
+            "False", "None", "True", "and", "as", "assert", "async", "await", "break", "class",
+            "continue", "def", "del", "elif", "else", "except", "finally", "for", "from", "global",
+            "if", "import", "in", "is", "lambda", "nonlocal", "not", "or", "pass", "raise",
+            "return", "try", "while", "with", "yield",
+        ];
+        let keywords: Vec<_> = KEYWORDS
+            .iter()
+            .map(|&(name, _, _)| name)
+            .filter(|name| is_keyword(name, PARSED_VERSION))
+            .collect();
+        assert_eq!(keywords, kwlist.to_vec());
+
+        assert!(is_keyword("__peg_parser__", (3, 9)));
+        assert!(!is_keyword("__peg_parser__", (3, 10)));
+        assert!(!is_keyword("with", (2, 5)) && is_keyword("with", (2, 6)));
+        assert!(!is_keyword("_", (3, 10)) && is_soft_keyword("_", (3, 10)));
+        assert!(is_soft_keyword("async", (3, 6)) && !is_soft_keyword("async", (3, 7)));
+        assert!(!is_keyword("foo", (3, 7)) && !is_soft_keyword("foo", (3, 12)));
+    }
+}
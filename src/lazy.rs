@@ -0,0 +1,324 @@
+//! Parsing of the structure of a module (imports, signatures, ...)
+//! without parsing the bodies of its functions and classes.
+//!
+//! Bodies are only located, using their indentation, and can be parsed
+//! later with `LazyBody::parse`.
+
+use nom::{Context, ErrorKind, IResult, Slice};
+
+use ast::*;
+use errors::{ParseError, PyParseError};
+use functions::{classdef_header, decorators, funcdef_header};
+use helpers::*;
+use memo::new_input;
+use span::{Span, Spanned};
+use statements::{block, statement};
+use stream::{skip_blank_lines, LineScanner};
+
+/// The body of a function or a class, which has not been parsed yet.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LazyBody<'a> {
+    code: StrSpan<'a>,
+    /// Indentation of the definition.
+    indent: usize,
+    /// Indentation of the body, or `None` if it is on the same line as
+    /// the definition.
+    body_indent: Option<usize>,
+}
+
+impl<'a> LazyBody<'a> {
+    /// The source code of the body, starting right after the colon of
+    /// the definition.
+    pub fn code(&self) -> &'a str {
+        self.code.fragment.0
+    }
+
+    /// Offset (in bytes) of the body in the code of the module.
+    pub fn offset(&self) -> usize {
+        self.code.offset
+    }
+
+    /// Line of the start of the body, starting from 1.
+    pub fn line(&self) -> u32 {
+        self.code.line
+    }
+
+    /// Parses the body.
+    pub fn parse(&self) -> Result<Vec<Statement>, ParseError> {
+        let (rest, stmts) = block(self.code, self.indent)?;
+        match skip_blank_lines(rest)? {
+            None => Ok(stmts),
+            Some(rest) => Err(ParseError::at(rest, None)),
+        }
+    }
+
+    /// Parses the body, without parsing the bodies of the functions
+    /// and classes it defines.
+    pub fn parse_lazy(&self) -> Result<Vec<LazyStatement<'a>>, ParseError> {
+        Ok(self
+            .parse_lazy_spanned()?
+            .into_iter()
+            .map(|(_, stmt)| stmt)
+            .collect())
+    }
+
+    /// Like `parse_lazy`, with the span of each statement (see
+    /// `lazy_statements_spanned`). The statements of a body on the same
+    /// line as the definition have the span of the body.
+    pub(crate) fn parse_lazy_spanned(&self) -> Result<Vec<(Span, LazyStatement<'a>)>, ParseError> {
+        match self.body_indent {
+            None => {
+                let span = self.span();
+                Ok(self
+                    .parse()?
+                    .into_iter()
+                    .map(|stmt| (span, LazyStatement::Statement(stmt)))
+                    .collect())
+            }
+            Some(body_indent) => lazy_statements_spanned(self.code, body_indent),
+        }
+    }
+}
+
+/// A statement whose function or class body may not be parsed yet.
+#[derive(Clone, Debug, PartialEq)]
+pub enum LazyStatement<'a> {
+    /// Any statement other than a function or a class definition.
+    Statement(Statement),
+    /// A function definition, whose `code` is empty.
+    Funcdef(Box<Funcdef>, LazyBody<'a>),
+    /// A class definition, whose `code` is empty.
+    Classdef(Box<Classdef>, LazyBody<'a>),
+}
+
+/// Parses a module, without parsing the bodies of the functions and
+/// classes it defines.
+///
+/// This is much faster than `file_input` for tools that only need the
+/// structure of the module, but syntax errors in the bodies are only
+/// found when parsing them.
+pub fn parse_lazy(code: &str) -> Result<Vec<LazyStatement<'_>>, ParseError> {
+    new_input();
+    lazy_statements(make_strspan(code), 0)
+}
+
+fn lazy_statements(i: StrSpan, indent: usize) -> Result<Vec<LazyStatement>, ParseError> {
+    Ok(lazy_statements_spanned(i, indent)?
+        .into_iter()
+        .map(|(_, stmt)| stmt)
+        .collect())
+}
+
+/// Like `lazy_statements`, with the span of each statement, from its
+/// indentation (or its first decorator) to the end of its last line.
+/// Statements separated by `;` have the span of their line.
+pub(crate) fn lazy_statements_spanned(
+    i: StrSpan,
+    indent: usize,
+) -> Result<Vec<(Span, LazyStatement)>, ParseError> {
+    let mut stmts = Vec::new();
+    let mut i = i;
+    while let Some(next) = skip_blank_lines(i)? {
+        let (rest, new_stmts) = lazy_statement(next, indent)?;
+        let span = trim_span(next.fragment.0, next.offset, rest.offset);
+        stmts.extend(new_stmts.into_iter().map(|stmt| (span, stmt)));
+        i = rest;
+    }
+    Ok(stmts)
+}
+
+/// Returns the span of the code from `start` to `end`, where `code`
+/// starts at `start`, without its indentation and the blank lines and
+/// comments that follow it.
+fn trim_span(code: &str, start: usize, end: usize) -> Span {
+    let text = &code[..end - start];
+    let mut text = text.trim_start();
+    let start = end - text.len();
+    loop {
+        text = text.trim_end();
+        match text.rfind(&['\n', '\r'][..]) {
+            Some(pos) if text[pos + 1..].trim_start().starts_with('#') => text = &text[..pos],
+            _ => break,
+        }
+    }
+    Span::new(start, start + text.len())
+}
+
+named_args!(lazy_statement(indent: usize) <StrSpan, Vec<LazyStatement>>,
+  alt!(
+    call!(lazy_definition, indent) => { |s| vec![s] }
+  | call!(statement, indent) => { |stmts: Vec<_>| stmts.into_iter().map(LazyStatement::Statement).collect() }
+  )
+);
+
+named_args!(lazy_definition(indent: usize) <StrSpan, LazyStatement>,
+  do_parse!(
+    decorators: call!(decorators, indent) >>
+    s: switch!(peek!(preceded!(indent!(indent), first_word)),
+        "def" => call!(lazy_funcdef, indent, decorators)
+      | "async" => call!(lazy_funcdef, indent, decorators)
+      | "class" => call!(lazy_classdef, indent, decorators)
+    ) >> (s)
+  )
+);
+
+named_args!(lazy_funcdef(indent: usize, decorators: Vec<Decorator>) <StrSpan, LazyStatement>,
+  do_parse!(
+    def: call!(funcdef_header, indent, decorators) >>
+    body: call!(skip_block, indent) >> (
+      LazyStatement::Funcdef(Box::new(def), body)
+    )
+  )
+);
+
+named_args!(lazy_classdef(indent: usize, decorators: Vec<Decorator>) <StrSpan, LazyStatement>,
+  do_parse!(
+    def: call!(classdef_header, indent, decorators) >>
+    body: call!(skip_block, indent) >> (
+      LazyStatement::Classdef(Box::new(def), body)
+    )
+  )
+);
+
+/// Returns whether a line contains only whitespaces and comments.
+fn is_blank(line: &str) -> bool {
+    let line = line.trim_start_matches(&[' ', '\t', '\x0c'][..]);
+    line.is_empty() || line.starts_with('#') || line.starts_with('\n') || line.starts_with('\r')
+}
+
+/// Finds the end of a block the same way `block` would, but only
+/// looking at the indentation of its lines.
+fn skip_block(i: StrSpan, indent: usize) -> IResult<StrSpan, LazyBody, u32> {
+    let code = i.fragment.0;
+    let mut scanner = LineScanner::default();
+    let mut lines = code.split('\n');
+    let first_line = lines.next().unwrap();
+    let mut line_start = first_line.len() + 1;
+    scanner.scan_line(first_line);
+    let mut end = first_line.len();
+
+    let body_indent = if !is_blank(first_line) {
+        // Body on the same line as the definition
+        for line in lines {
+            if scanner.at_line_start() {
+                break;
+            }
+            scanner.scan_line(line);
+            end = line_start + line.len();
+            line_start = end + 1;
+        }
+        None
+    } else {
+        let mut body_indent = None;
+        for line in lines {
+            if scanner.at_line_start() && !is_blank(line) {
+                let line_indent = line.len() - line.trim_start_matches(' ').len();
+                match body_indent {
+                    None if line_indent > indent => body_indent = Some(line_indent),
+                    None => break,
+                    Some(body_indent) if line_indent < body_indent => break,
+                    Some(_) => (),
+                }
+            }
+            scanner.scan_line(line);
+            if body_indent.is_some() {
+                end = line_start + line.len();
+            }
+            line_start += line.len() + 1;
+        }
+        if body_indent.is_none() {
+            let pos = ::std::cmp::min(end + 1, code.len());
+            return Err(::nom::Err::Failure(Context::Code(
+                i.slice(pos..),
+                ErrorKind::Custom(PyParseError::ExpectedIndent.into()),
+            )));
+        }
+        body_indent
+    };
+
+    let end = ::std::cmp::min(end, code.len());
+    let body = LazyBody {
+        code: i.slice(..end),
+        indent,
+        body_indent,
+    };
+    Ok((i.slice(end..), body))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use file_input;
+
+    #[test]
+    fn test_lazy() {
+        let code = "import foo\n\n@bar\ndef baz(x) -> int:\n    '''doc\nstring'''\n    return (x,\n1)\n  # comment\nclass A(B): pass\nclass C:\n\n    def f(self):\n        if x:\n            return y\n\n    # comment\n    x = 1\nx = 2\n";
+        let stmts = parse_lazy(code).unwrap();
+        let expected = file_input(make_strspan(code)).unwrap().1;
+        assert_eq!(stmts.len(), expected.len());
+
+        let mut parsed = Vec::new();
+        for stmt in stmts {
+            parsed.push(match stmt {
+                LazyStatement::Statement(s) => s,
+                LazyStatement::Funcdef(def, body) => {
+                    assert_eq!(def.code, vec![]);
+                    Statement::Compound(Box::new(CompoundStatement::Funcdef(Funcdef {
+                        code: body.parse().unwrap(),
+                        ..*def
+                    })))
+                }
+                LazyStatement::Classdef(def, body) => {
+                    assert_eq!(def.code, vec![]);
+                    let code = body.parse().unwrap();
+                    assert_eq!(body.parse_lazy().unwrap().len(), code.len());
+                    Statement::Compound(Box::new(CompoundStatement::Classdef(Classdef {
+                        code,
+                        ..*def
+                    })))
+                }
+            });
+        }
+        assert_eq!(parsed, expected);
+    }
+
+    #[test]
+    fn test_lazy_body_positions() {
+        let code = "def f():\n    foo(\n)\nclass C:\n    def g(self): pass\n";
+        let stmts = parse_lazy(code).unwrap();
+        match stmts[0] {
+            LazyStatement::Funcdef(_, ref body) => {
+                assert_eq!(body.code(), "\n    foo(\n)");
+                assert_eq!((body.offset(), body.line()), (8, 1));
+            }
+            ref s => panic!("Unexpected statement: {:?}", s),
+        }
+        let methods = match stmts[1] {
+            LazyStatement::Classdef(_, ref body) => body.parse_lazy().unwrap(),
+            ref s => panic!("Unexpected statement: {:?}", s),
+        };
+        match methods[0] {
+            LazyStatement::Funcdef(ref def, ref body) => {
+                assert_eq!(def.name, "g");
+                assert_eq!(body.code(), "pass");
+                assert_eq!(body.line(), 5);
+            }
+            ref s => panic!("Unexpected statement: {:?}", s),
+        }
+    }
+
+    #[test]
+    fn test_lazy_errors() {
+        // Errors in bodies are only found when parsing them
+        let stmts = parse_lazy("def f():\n    1 +\n").unwrap();
+        match stmts[0] {
+            LazyStatement::Funcdef(_, ref body) => assert!(body.parse().is_err()),
+            ref s => panic!("Unexpected statement: {:?}", s),
+        }
+
+        let e = parse_lazy("x = 1\ndef f():\nfoo\n").unwrap_err();
+        assert_eq!(e.code, Some(PyParseError::ExpectedIndent));
+        assert_eq!(e.line, 3);
+        assert!(parse_lazy("def f():\n    pass\n  foo\n").is_err());
+    }
+}
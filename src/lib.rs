@@ -19,6 +19,29 @@
 //! Note that without the `wtf8` feature, some valid string
 //! literals will be badly parsed (missing characters).
 //!
+//! # Line terminators
+//!
+//! Like Python, lines may end with `\n`, `\r\n` or `\r`, and a form feed
+//! resets the indentation. Line breaks in triple-quoted strings are read
+//! as `\n`.
+//!
+//! Line numbers of errors are counted the same way by the `parse_*`
+//! functions; the other parsers (eg. `file_input`) only count `\n`s.
+//!
+//! # Errors
+//!
+//! When the code has an unterminated string, a bracket that is never
+//! closed or does not match, or ends with a block header without its
+//! block, the `parse_*` functions report the error at the start of that
+//! construct (like CPython's `'(' was never closed`) instead of where
+//! the parser gave up. Likewise, a block header without its `:`, a `=`
+//! instead of `==` in a condition, or items in brackets without a comma
+//! between them are reported with an error suggesting the fix.
+//!
+//! For code being edited, `tolerant::parse_module_tolerant` never fails:
+//! it returns all the errors along with a module where the statements
+//! that could not be parsed are `Statement::Invalid`.
+//!
 //! # Python version support
 //!
 //! Currently supports Python 3.7's syntax (and Python 3.8 up to
@@ -27,6 +50,8 @@
 //! # Example
 //!
 //! ```
+//! # #[macro_use] extern crate python_parser;
+//! # fn main() {
 //! use python_parser::ast::*;
 //! let code = "print(2 + 3, fd=sys.stderr)";
 //! let ast = python_parser::file_input(python_parser::make_strspan(code))
@@ -35,7 +60,7 @@
 //! assert_eq!(ast,
 //!     vec![
 //!         Statement::Assignment(
-//!             vec![
+//!             small_list![
 //!                 Expression::Call(
 //!                     Box::new(Expression::Name("print".to_string())),
 //!                     vec![
@@ -60,6 +85,7 @@
 //!         )
 //!     ]
 //! );
+//! # }
 //! ```
 
 #![recursion_limit = "128"]
@@ -72,6 +98,7 @@ extern crate nom_locate;
 #[macro_use]
 extern crate pretty_assertions;
 
+extern crate stacker;
 extern crate unicode_xid;
 
 #[cfg(feature = "unicode-names")]
@@ -85,24 +112,93 @@ extern crate num_traits;
 #[cfg(feature = "wtf8")]
 extern crate wtf8;
 
+#[cfg(feature = "termcolor")]
+extern crate termcolor;
+
+#[cfg(feature = "smallvec")]
+extern crate smallvec;
+
 #[macro_use]
 mod helpers;
 #[macro_use]
 mod expressions;
 #[macro_use]
 mod statements;
+pub mod annotations;
 pub mod ast;
+pub mod asyncify;
+pub mod budget;
 mod bytes;
+pub mod cache;
+pub mod code_blocks;
+#[cfg(feature = "termcolor")]
+pub mod color;
+pub mod completion;
+pub mod conformance;
+pub mod decoding;
+pub mod definition;
+pub mod deprecated;
+pub mod docstyle;
+pub mod doctest;
 pub mod errors;
+pub mod extensions;
+pub mod fidelity;
+pub mod folding;
 mod functions;
+pub mod hover;
+pub mod html;
+pub mod imports;
+pub mod indentation;
+pub mod inline;
+pub mod interactive;
+mod json;
+pub mod keywords;
+pub mod lazy;
+pub mod line_index;
+pub mod lint;
+pub mod members;
+pub mod memo;
+pub mod metrics;
+pub mod notebook;
 mod numbers;
+pub mod outline;
+pub mod parsed;
+pub mod project;
+pub mod query;
+#[cfg(feature = "recursive-descent")]
+pub mod recursive_descent;
+pub mod reformat;
+pub mod rename;
+pub mod reparse;
+pub mod roundtrip;
+pub mod selection;
+pub mod semantic;
+pub mod serialize;
+pub mod server;
+pub mod session;
+pub mod signature;
+pub mod span;
+pub mod stats;
+pub mod stream;
 mod strings;
+pub mod stubs;
+pub mod suppressions;
+pub mod tokenizer;
+pub mod tolerant;
+pub mod validate;
 pub mod visitors;
+pub mod watch;
+
+use std::borrow::Cow;
 
 use ast::*;
+use budget::check_code_size;
+use errors::{ParseError, PyParseError};
 use expressions::*;
 use helpers::*;
+use memo::new_input;
 use statements::*;
+use stream::skip_blank_lines;
 
 pub use helpers::make_strspan;
 
@@ -135,6 +231,205 @@ pub eval_input <StrSpan, Vec<Expression>>,
   terminated!(ws_nonl!(call!(ExpressionParser::<NewlinesAreNotSpaces>::testlist)), many0!(newline))
 );
 
+/// Replaces lone `\r`s with `\n`s, so lines are counted like Python's
+/// universal newlines. The length, hence the offsets, are unchanged.
+fn universal_newlines<'a>(code: &'a str) -> Cow<'a, str> {
+    if !code.contains('\r') {
+        return Cow::Borrowed(code);
+    }
+    let mut bytes = code.as_bytes().to_vec();
+    for i in 0..bytes.len() {
+        if bytes[i] == b'\r' && bytes.get(i + 1) != Some(&b'\n') {
+            bytes[i] = b'\n';
+        }
+    }
+    // Replacing an ASCII byte with another keeps the string valid UTF-8
+    Cow::Owned(String::from_utf8(bytes).unwrap_or_else(|_| code.to_string()))
+}
+
+/// Runs `parser` on `code` with universal newlines, and reports errors
+/// caused by unterminated constructs where they start, and common
+/// mistakes with a more helpful error.
+fn parse_with<T, F>(code: &str, parser: F) -> Result<T, ParseError>
+where
+    F: FnOnce(&str) -> Result<T, ParseError>,
+{
+    let code = universal_newlines(code);
+    check_code_size(&code)?;
+    new_input();
+    parser(&code).map_err(|e| e.locate_unterminated(&code).guess_mistake(&code))
+}
+
+/// Skips the blank lines and comments before the code of eval-like
+/// inputs, which must not be indented.
+fn skip_to_first_line(i: StrSpan) -> Result<StrSpan, ParseError> {
+    let i = skip_blank_lines(i)?.unwrap_or(i);
+    // Form feeds reset the indentation
+    let line = i.fragment.0.trim_start_matches('\x0c');
+    if line.starts_with(&[' ', '\t'][..]) {
+        return Err(ParseError::at(i, Some(PyParseError::UnexpectedIndent)));
+    }
+    Ok(i)
+}
+
+/// Parses a single expression, accepting the same code as
+/// `compile(code, '<string>', 'eval')`.
+///
+/// Unlike `eval_input`, the whole code must be parsed, and a sequence
+/// of expressions (`a, b`) is returned as a tuple.
+pub fn parse_expression(code: &str) -> Result<Expression, ParseError> {
+    parse_with(code, |code| {
+        let i = skip_to_first_line(make_strspan(code))?;
+        let (rest, mut exprs) =
+            ExpressionParser::<NewlinesAreNotSpaces>::possibly_empty_testlist(i)?;
+        if let Some(rest) = skip_blank_lines(rest)? {
+            return Err(ParseError::at(rest, None));
+        }
+        match exprs.len() {
+            0 => Err(ParseError::at(rest, None)),
+            1 => Ok(exprs.remove(0)), // The remove can't panic, because len == 1
+            _ => Ok(Expression::TupleLiteral(
+                exprs.into_iter().map(SetItem::Unique).collect(),
+            )),
+        }
+    })
+}
+
+/// Parses a single statement, possibly compound, accepting the same
+/// code as `compile(code, '<string>', 'single')`.
+///
+/// Unlike `parse_single_input`, the whole code must be parsed, and
+/// blank lines and comments around the statement are allowed.
+pub fn parse_single(code: &str) -> Result<Vec<Statement>, ParseError> {
+    parse_with(code, |code| {
+        let i = make_strspan(code);
+        let i = match skip_blank_lines(i)? {
+            Some(i) => i,
+            None => return Err(ParseError::at(i, None)),
+        };
+        let (rest, stmts) = statement(i, 0)?;
+        match skip_blank_lines(rest)? {
+            None => Ok(stmts),
+            Some(rest) => {
+                // Like Python, report errors in the next statement (such
+                // as an unexpected indent) rather than its presence.
+                statement(rest, 0)?;
+                Err(ParseError::at(rest, Some(PyParseError::MultipleStatements)))
+            }
+        }
+    })
+}
+
+/// Parses a function signature in a type comment, like
+/// `compile(code, '<string>', 'func_type')`.
+///
+/// ```
+/// use python_parser::ast::*;
+/// let sig = python_parser::parse_func_type("(int, *str) -> bool").unwrap();
+/// assert_eq!(sig.args, vec![Expression::Name("int".to_string())]);
+/// assert_eq!(sig.star_args, Some(Expression::Name("str".to_string())));
+/// assert_eq!(sig.return_type, Expression::Name("bool".to_string()));
+/// ```
+pub fn parse_func_type(code: &str) -> Result<FuncType, ParseError> {
+    parse_with(code, |code| {
+        let i = skip_to_first_line(make_strspan(code))?;
+        let (rest, sig) = functions::func_type(i)?;
+        match skip_blank_lines(rest)? {
+            None => Ok(sig),
+            Some(rest) => Err(ParseError::at(rest, None)),
+        }
+    })
+}
+
+/// Returns the expressions of the last statement, if it is an
+/// expression statement, like `foo`, `foo, bar` or `yield foo`.
+///
+/// The REPL prints the value of such a statement (as a tuple if there
+/// are several expressions).
+///
+/// ```
+/// use python_parser::{last_expression, parse_single};
+/// let stmts = parse_single("x = 1; x + 1").unwrap();
+/// assert_eq!(last_expression(&stmts).map(|e| e.len()), Some(1));
+/// let stmts = parse_single("x = 1").unwrap();
+/// assert_eq!(last_expression(&stmts), None);
+/// ```
+pub fn last_expression(stmts: &[Statement]) -> Option<&[Expression]> {
+    match stmts.last() {
+        Some(Statement::Assignment(ref lhs, ref rhs)) if rhs.is_empty() => Some(lhs),
+        Some(Statement::Expressions(ref exprs)) => Some(exprs),
+        _ => None,
+    }
+}
+
+/// Returns the encoding declared by `line`, if it is a comment like
+/// `# -*- coding: utf-8 -*-` (see PEP 263).
+fn encoding_declaration(line: &str) -> Option<String> {
+    let comment = line.trim_start_matches(&[' ', '\t', '\x0c'][..]);
+    if !comment.starts_with('#') {
+        return None;
+    }
+    let mut rest = comment;
+    while let Some(pos) = rest.find("coding") {
+        rest = &rest[pos + "coding".len()..];
+        if rest.starts_with(':') || rest.starts_with('=') {
+            let name: String = rest[1..]
+                .trim_start_matches(&[' ', '\t'][..])
+                .chars()
+                .take_while(|&c| c.is_alphanumeric() || c == '_' || c == '-' || c == '.')
+                .collect();
+            if !name.is_empty() {
+                return Some(name);
+            }
+        }
+    }
+    None
+}
+
+/// Builds the `Module` of `code`, whose statements are `body`.
+fn make_module(code: &str, body: Vec<Statement>) -> Module {
+    let mut lines = code.lines().map(|line| line.trim_end_matches('\r'));
+    let mut shebang = None;
+    let mut encoding = None;
+    let mut leading_comments = Vec::new();
+    for (lineno, line) in (&mut lines).enumerate() {
+        if lineno == 0 && line.starts_with("#!") {
+            shebang = Some(line.to_string());
+            continue;
+        }
+        let trimmed = line.trim_start_matches(&[' ', '\t', '\x0c'][..]);
+        if !trimmed.is_empty() && !trimmed.starts_with('#') {
+            break;
+        }
+        if lineno < 2 && encoding.is_none() {
+            encoding = encoding_declaration(line);
+        }
+        if !trimmed.is_empty() {
+            leading_comments.push(trimmed.trim_end().to_string());
+        }
+    }
+
+    Module {
+        shebang,
+        encoding,
+        leading_comments,
+        trailing_newline: code.ends_with('\n'),
+        body,
+    }
+}
+
+/// Parses a whole file, along with its shebang, encoding declaration
+/// and leading comments.
+pub fn parse_module(code: &str) -> Result<Module, ParseError> {
+    parse_with(code, |code| {
+        let (rest, body) = file_input(make_strspan(code))?;
+        if let Some(rest) = skip_blank_lines(rest)? {
+            return Err(ParseError::at(rest, None));
+        }
+        Ok(make_module(code, body))
+    })
+}
+
 // encoding_decl: NAME
 // TODO
 
@@ -184,4 +479,200 @@ mod tests {
             )),
         );
     }
+
+    #[test]
+    fn test_parse_expression() {
+        let name = |n: &str| Expression::Name(n.to_string());
+        assert_eq!(parse_expression("foo"), Ok(name("foo")));
+        assert_eq!(
+            parse_expression("\n# comment\n(foo) # comment\n\n"),
+            Ok(name("foo"))
+        );
+        assert_eq!(
+            parse_expression("foo,"),
+            Ok(Expression::TupleLiteral(vec![SetItem::Unique(name("foo"))]))
+        );
+        assert_eq!(
+            parse_expression("foo, \\\n bar"),
+            Ok(Expression::TupleLiteral(vec![
+                SetItem::Unique(name("foo")),
+                SetItem::Unique(name("bar")),
+            ]))
+        );
+
+        assert_eq!(
+            parse_expression(" foo").unwrap_err().code,
+            Some(PyParseError::UnexpectedIndent)
+        );
+        for code in &[
+            "",
+            "# comment",
+            "foo = bar",
+            "foo bar",
+            "*foo",
+            "yield foo",
+            "foo\nbar",
+        ] {
+            assert!(parse_expression(code).is_err(), "{:?}", code);
+        }
+        assert_eq!(parse_expression("foo\nbar").unwrap_err().line, 2);
+    }
+
+    #[test]
+    fn test_parse_single() {
+        assert_eq!(
+            parse_single("# comment\ndel foo; del bar # comment\n\n"),
+            Ok(vec![
+                Statement::Del(vec![Expression::Name("foo".to_string())]),
+                Statement::Del(vec![Expression::Name("bar".to_string())]),
+            ])
+        );
+        assert_eq!(
+            parse_single("if foo:\n  pass\n  # comment\n")
+                .unwrap()
+                .len(),
+            1
+        );
+        assert_eq!(parse_single("if foo:\n  pass").unwrap().len(), 1);
+
+        let e = parse_single("foo\n\nbar\n").unwrap_err();
+        assert_eq!(
+            (e.line, e.code),
+            (3, Some(PyParseError::MultipleStatements))
+        );
+        let e = parse_single("if foo:\n  pass\n bar\n").unwrap_err();
+        assert_eq!((e.line, e.code), (3, Some(PyParseError::UnexpectedIndent)));
+        assert!(parse_single("").is_err());
+        assert!(parse_single("# comment\n").is_err());
+    }
+
+    #[test]
+    fn test_last_expression() {
+        let name = |n: &str| Expression::Name(n.to_string());
+        let stmts = parse_single("foo; bar, baz\n").unwrap();
+        assert_eq!(
+            last_expression(&stmts),
+            Some(&[name("bar"), name("baz")][..])
+        );
+        let stmts = parse_single("yield foo").unwrap();
+        assert_eq!(
+            last_expression(&stmts),
+            Some(&[Expression::Yield(vec![name("foo")])][..])
+        );
+        for code in &[
+            "foo; bar = baz",
+            "foo: int",
+            "foo += 1",
+            "if foo:\n  bar\n",
+            "pass",
+        ] {
+            let stmts = parse_single(code).unwrap();
+            assert_eq!(last_expression(&stmts), None, "{:?}", code);
+        }
+        assert_eq!(last_expression(&[]), None);
+    }
+
+    #[test]
+    fn test_parse_module() {
+        let module = parse_module(
+            "#!/usr/bin/env python3\n# -*- coding: latin-1 -*-\n\n# Foo\n\nfoo = 1\n# Bar\nbar = 2",
+        )
+        .unwrap();
+        assert_eq!(module.shebang, Some("#!/usr/bin/env python3".to_string()));
+        assert_eq!(module.encoding, Some("latin-1".to_string()));
+        assert_eq!(
+            module.leading_comments,
+            vec!["# -*- coding: latin-1 -*-".to_string(), "# Foo".to_string()]
+        );
+        assert!(!module.trailing_newline);
+        assert_eq!(module.body.len(), 2);
+
+        let module = parse_module("# foo\n# vim: set fileencoding=utf-8 :\n").unwrap();
+        assert_eq!(module.shebang, None);
+        assert_eq!(module.encoding, Some("utf-8".to_string()));
+        assert!(module.trailing_newline);
+        assert_eq!(module.body, vec![]);
+
+        // Only the first two lines can declare the encoding
+        let module = parse_module("foo = 1\n# coding: latin-1\n").unwrap();
+        assert_eq!(module.encoding, None);
+        let module = parse_module("\n\n# coding: latin-1\nfoo = 1\n").unwrap();
+        assert_eq!(module.encoding, None);
+        assert_eq!(
+            module.leading_comments,
+            vec!["# coding: latin-1".to_string()]
+        );
+
+        assert_eq!(parse_module("foo = 1\nbar = (\n").unwrap_err().line, 2);
+    }
+
+    #[test]
+    fn test_line_terminators() {
+        let expected = parse_module("# foo\nif x:\n    y = '''a\nb'''\n").unwrap();
+        for code in &[
+            "# foo\r\nif x:\r\n    y = '''a\r\nb'''\r\n",
+            "# foo\rif x:\r    y = '''a\rb'''\r",
+            "# foo\nif x:\n\x0c    y = \\\r'''a\rb'''\r\n",
+        ] {
+            assert_eq!(parse_module(code).unwrap(), expected, "{:?}", code);
+        }
+        // A form feed resets the indentation
+        assert!(parse_module("if x:\n  \x0c    y\n").is_ok());
+        assert!(parse_module("  \x0cx = 1\n").is_ok());
+        assert!(parse_module("\x0c  x = 1\n").is_err());
+
+        assert_eq!(parse_module("x = 1\ry = (\r").unwrap_err().line, 2);
+        assert_eq!(parse_single("\r\rx = (").unwrap_err().line, 3);
+    }
+
+    #[test]
+    fn test_unterminated() {
+        use errors::PyParseError::*;
+        for &(code, offset, error) in &[
+            ("x = (1,\n2\n", 4, UnclosedParenthesis),
+            ("x = (1,\n[2,\n", 8, UnclosedBracket),
+            ("f(\nif x:\n", 1, UnclosedParenthesis),
+            ("x = {1: 2\ny = 3\n", 4, UnclosedBrace),
+            ("x = f(a, [b,\n  c)\n", 16, MismatchedBracket),
+            ("x = 1\n)\n", 6, UnmatchedParenthesis),
+            ("x = (1,)]\n", 8, UnmatchedBracket),
+            ("x = 'abc\ny = (1\n", 4, UnterminatedString),
+            ("x = rb'''abc\ny = 1\n", 4, UnterminatedTripleQuotedString),
+            ("if x:\n    # comment\n", 0, ExpectedIndent),
+            ("class A:\n    @f\n    def f(self):\n", 20, ExpectedIndent),
+            ("if x:\npass\n", 6, ExpectedIndent),
+        ] {
+            let e = parse_module(code).unwrap_err();
+            assert_eq!((e.offset, e.code), (offset, Some(error)), "{:?}", code);
+        }
+        assert_eq!(
+            parse_expression("(1,\n").unwrap_err().code,
+            Some(UnclosedParenthesis)
+        );
+        assert_eq!(
+            parse_single("if x:\n").unwrap_err().code,
+            Some(ExpectedIndent)
+        );
+        assert_eq!(parse_module("x = = (1)\n").unwrap_err().code, None);
+    }
+
+    #[test]
+    fn test_mistakes() {
+        use errors::PyParseError::*;
+        for &(code, offset, error) in &[
+            ("if x\n    pass\n", 4, MissingColon),
+            ("def f()\n    pass\n", 7, MissingColon),
+            ("while x:\n    if y == 1\n        pass\n", 22, MissingColon),
+            ("if x = 1:\n    pass\n", 5, AssignmentInCondition),
+            ("while (x = 1):\n    pass\n", 9, AssignmentInCondition),
+            ("f(a b)\n", 2, MissingComma),
+            ("x = [1 2]\n", 5, MissingComma),
+            ("f(a,\n  b c=1)\n", 7, MissingComma),
+        ] {
+            let e = parse_module(code).unwrap_err();
+            assert_eq!((e.offset, e.code), (offset, Some(error)), "{:?}", code);
+        }
+        assert_eq!(parse_module("if x: y = = 1\n").unwrap_err().code, None);
+        assert!(parse_module("f('a' 'b')\nf(a for a in b)\nif f(a=1): pass\n").is_ok());
+    }
 }
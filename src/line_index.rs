@@ -0,0 +1,344 @@
+//! Conversion between byte offsets and line/column positions, in the
+//! encodings used by editors and the Language Server Protocol.
+
+use span::Span;
+
+/// Unit of the columns of a `LineCol`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Encoding {
+    /// Bytes of UTF-8.
+    Utf8,
+    /// UTF-16 code units, the default of the Language Server Protocol.
+    Utf16,
+    /// Unicode code points.
+    Utf32,
+}
+
+impl Encoding {
+    /// Number of units of a character encoded in `len` UTF-8 bytes.
+    fn units(self, len: usize) -> usize {
+        match self {
+            Encoding::Utf8 => len,
+            Encoding::Utf16 if len == 4 => 2,
+            Encoding::Utf16 | Encoding::Utf32 => 1,
+        }
+    }
+}
+
+/// A position in the code. Both fields start from 0, like in the
+/// Language Server Protocol (but unlike `ParseError::line`).
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct LineCol {
+    pub line: u32,
+    pub col: u32,
+}
+
+/// A position with its column both in bytes and as displayed by
+/// editors, with the tabs expanded. All fields start from 0.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct DisplayPosition {
+    pub line: u32,
+    pub byte_col: u32,
+    pub display_col: u32,
+}
+
+/// Index of the lines of some code, to convert offsets to positions and
+/// back, without keeping a copy of the code.
+///
+/// Lines end with `\n`, `\r\n` or `\r`.
+///
+/// ```
+/// use python_parser::line_index::{Encoding, LineCol, LineIndex};
+///
+/// let index = LineIndex::new("x = 1\ny = '😀' + z\n");
+/// let z = LineCol { line: 1, col: 11 };
+/// assert_eq!(index.line_col(19, Encoding::Utf16), Some(z));
+/// assert_eq!(index.offset(z, Encoding::Utf16), Some(19));
+/// assert_eq!(index.line_col(19, Encoding::Utf8), Some(LineCol { line: 1, col: 13 }));
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LineIndex {
+    /// Offset of the start of each line.
+    line_starts: Vec<usize>,
+    /// Offset of the end of each line, before its line break.
+    line_ends: Vec<usize>,
+    /// Offset and length of the characters that are not ASCII.
+    wide_chars: Vec<(usize, usize)>,
+    /// Offset of the tabs.
+    tabs: Vec<usize>,
+    len: usize,
+}
+
+impl LineIndex {
+    pub fn new(code: &str) -> LineIndex {
+        let mut line_starts = vec![0];
+        let mut line_ends = Vec::new();
+        let mut wide_chars = Vec::new();
+        let mut tabs = Vec::new();
+        let mut chars = code.char_indices().peekable();
+        while let Some((offset, c)) = chars.next() {
+            match c {
+                '\n' => {
+                    line_ends.push(offset);
+                    line_starts.push(offset + 1);
+                }
+                '\r' => {
+                    line_ends.push(offset);
+                    if let Some(&(_, '\n')) = chars.peek() {
+                        chars.next();
+                        line_starts.push(offset + 2);
+                    } else {
+                        line_starts.push(offset + 1);
+                    }
+                }
+                '\t' => tabs.push(offset),
+                _ if !c.is_ascii() => wide_chars.push((offset, c.len_utf8())),
+                _ => (),
+            }
+        }
+        line_ends.push(code.len());
+        LineIndex {
+            line_starts,
+            line_ends,
+            wide_chars,
+            tabs,
+            len: code.len(),
+        }
+    }
+
+    /// Number of lines; code ending with a line break ends with an
+    /// empty line.
+    pub fn line_count(&self) -> usize {
+        self.line_starts.len()
+    }
+
+    /// Returns the span of a line (starting from 0), without its line
+    /// break.
+    pub fn line_span(&self, line: u32) -> Option<Span> {
+        let line = line as usize;
+        Some(Span::new(
+            *self.line_starts.get(line)?,
+            *self.line_ends.get(line)?,
+        ))
+    }
+
+    /// Returns the non-ASCII characters between `start` and `end`.
+    fn wide_chars_in(&self, start: usize, end: usize) -> &[(usize, usize)] {
+        let first = match self.wide_chars.binary_search(&(start, 0)) {
+            Ok(i) | Err(i) => i,
+        };
+        let last = match self.wide_chars.binary_search(&(end, 0)) {
+            Ok(i) | Err(i) => i,
+        };
+        &self.wide_chars[first..last]
+    }
+
+    /// Returns the position of a byte offset, or `None` if it is after
+    /// the end of the code or inside a character (the end of the code
+    /// itself has a position).
+    /// The position of a line break is at the end of its line.
+    pub fn line_col(&self, offset: usize, encoding: Encoding) -> Option<LineCol> {
+        if offset > self.len {
+            return None;
+        }
+        let line = match self.line_starts.binary_search(&offset) {
+            Ok(line) => line,
+            Err(next_line) => next_line - 1, // Can't underflow, there is a line at 0
+        };
+        let start = self.line_starts[line];
+        let mut col = offset - start;
+        for &(char_offset, len) in self.wide_chars_in(start, offset) {
+            if char_offset + len > offset {
+                return None; // Inside the character
+            }
+            col = col - len + encoding.units(len);
+        }
+        if offset > self.line_ends[line] {
+            return None; // Between \r and \n
+        }
+        Some(LineCol {
+            line: line as u32,
+            col: col as u32,
+        })
+    }
+
+    /// Returns the byte offset of a position, or `None` if its line
+    /// does not exist.
+    ///
+    /// Like in the Language Server Protocol, columns after the end of
+    /// the line are the end of the line; columns inside a character
+    /// (possible in UTF-16) are the start of the character.
+    pub fn offset(&self, pos: LineCol, encoding: Encoding) -> Option<usize> {
+        let span = self.line_span(pos.line)?;
+        let col = pos.col as usize;
+        // Offset and column of the start of the ASCII characters after
+        // the current wide character
+        let mut offset = span.start;
+        let mut units = 0;
+        for &(char_offset, len) in self.wide_chars_in(span.start, span.end) {
+            if col <= units + char_offset - offset {
+                break;
+            }
+            units += char_offset - offset;
+            let char_units = encoding.units(len);
+            if col < units + char_units {
+                return Some(char_offset);
+            }
+            units += char_units;
+            offset = char_offset + len;
+        }
+        Some((offset + col - units).min(span.end))
+    }
+
+    /// Returns the number of characters between `start` and `end`.
+    fn char_count(&self, start: usize, end: usize) -> usize {
+        let wide_chars = self.wide_chars_in(start, end);
+        end - start - wide_chars.iter().map(|&(_, len)| len - 1).sum::<usize>()
+    }
+
+    /// Returns the position of a byte offset, with its display column:
+    /// tabs move to the next multiple of `tab_size` columns (like in
+    /// editors), or count as one column with `None` (like in CPython's
+    /// errors), and the other characters count as one column.
+    ///
+    /// ```
+    /// use python_parser::line_index::{DisplayPosition, LineIndex};
+    ///
+    /// let index = LineIndex::new("if x:\n\ty = 'é'\t+ z\n");
+    /// let z = index.display_position(18, Some(8)).unwrap();
+    /// assert_eq!(z, DisplayPosition { line: 1, byte_col: 12, display_col: 18 });
+    /// assert_eq!(index.display_position(18, None).unwrap().display_col, 11);
+    /// ```
+    pub fn display_position(
+        &self,
+        offset: usize,
+        tab_size: Option<u32>,
+    ) -> Option<DisplayPosition> {
+        let pos = self.line_col(offset, Encoding::Utf8)?;
+        let start = self.line_starts[pos.line as usize];
+        let mut col = 0;
+        let mut previous = start;
+        if let Some(tab_size) = tab_size {
+            let tab_size = tab_size.max(1) as usize;
+            let first = match self.tabs.binary_search(&start) {
+                Ok(i) | Err(i) => i,
+            };
+            for &tab in self.tabs[first..].iter().take_while(|&&tab| tab < offset) {
+                col += self.char_count(previous, tab);
+                col = (col / tab_size + 1) * tab_size;
+                previous = tab + 1;
+            }
+        }
+        col += self.char_count(previous, offset);
+        Some(DisplayPosition {
+            line: pos.line,
+            byte_col: pos.col,
+            display_col: col as u32,
+        })
+    }
+
+    /// Converts a span to the positions of its start and end.
+    pub fn span_line_cols(&self, span: Span, encoding: Encoding) -> Option<(LineCol, LineCol)> {
+        Some((
+            self.line_col(span.start, encoding)?,
+            self.line_col(span.end, encoding)?,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lines() {
+        let index = LineIndex::new("a\nbc\r\nd\re");
+        assert_eq!(index.line_count(), 4);
+        assert_eq!(index.line_span(1), Some(Span::new(2, 4)));
+        assert_eq!(index.line_span(3), Some(Span::new(8, 9)));
+        assert_eq!(index.line_span(4), None);
+        let positions = [
+            (0, 0),
+            (0, 1),
+            (1, 0),
+            (1, 1),
+            (1, 2),
+            (9, 9), // Between \r and \n
+            (2, 0),
+            (2, 1),
+            (3, 0),
+            (3, 1),
+        ];
+        for (offset, &(line, col)) in positions.iter().enumerate() {
+            let pos = LineCol {
+                line: line as u32,
+                col: col as u32,
+            };
+            if line == 9 {
+                assert_eq!(index.line_col(offset, Encoding::Utf8), None);
+                continue;
+            }
+            assert_eq!(index.line_col(offset, Encoding::Utf8), Some(pos));
+            assert_eq!(index.offset(pos, Encoding::Utf16), Some(offset));
+        }
+        assert_eq!(index.line_col(10, Encoding::Utf8), None);
+        assert_eq!(LineIndex::new("").line_count(), 1);
+        assert_eq!(LineIndex::new("a\n").line_count(), 2);
+    }
+
+    #[test]
+    fn test_display_position() {
+        let index = LineIndex::new("\t\tx\n  \ty\tz\n😀\t\n");
+        let cols = |offset| {
+            let pos = index.display_position(offset, Some(4)).unwrap();
+            (pos.line, pos.byte_col, pos.display_col)
+        };
+        assert_eq!(cols(1), (0, 1, 4));
+        assert_eq!(cols(2), (0, 2, 8));
+        assert_eq!(cols(6), (1, 2, 2)); // At a tab
+        assert_eq!(cols(7), (1, 3, 4));
+        assert_eq!(cols(9), (1, 5, 8));
+        assert_eq!(cols(16), (2, 5, 4)); // After an emoji and a tab
+        assert_eq!(index.display_position(9, None).unwrap().display_col, 5);
+        assert_eq!(index.display_position(9, Some(0)).unwrap().display_col, 5);
+        assert_eq!(index.display_position(12, Some(4)), None); // Inside 😀
+    }
+
+    #[test]
+    fn test_encodings() {
+        // é is 2 bytes, 😀 is 4 bytes and 2 UTF-16 units
+        let index = LineIndex::new("x\né😀y\n");
+        let expected = [
+            (Encoding::Utf8, [0, 2, 6, 7]),
+            (Encoding::Utf16, [0, 1, 3, 4]),
+            (Encoding::Utf32, [0, 1, 2, 3]),
+        ];
+        for &(encoding, cols) in &expected {
+            for (&offset, &col) in [2, 4, 8, 9].iter().zip(cols.iter()) {
+                let pos = LineCol { line: 1, col };
+                assert_eq!(index.line_col(offset, encoding), Some(pos));
+                assert_eq!(index.offset(pos, encoding), Some(offset));
+            }
+        }
+        assert_eq!(index.line_col(3, Encoding::Utf16), None); // Inside é
+        assert_eq!(index.line_col(5, Encoding::Utf16), None); // Inside 😀
+                                                              // Inside 😀, rounded down
+        assert_eq!(
+            index.offset(LineCol { line: 1, col: 2 }, Encoding::Utf16),
+            Some(4)
+        );
+        // After the end of the line
+        assert_eq!(
+            index.offset(LineCol { line: 1, col: 10 }, Encoding::Utf16),
+            Some(9)
+        );
+        assert_eq!(
+            index.offset(LineCol { line: 3, col: 0 }, Encoding::Utf16),
+            None
+        );
+        assert_eq!(
+            index.span_line_cols(Span::new(1, 8), Encoding::Utf16),
+            Some((LineCol { line: 0, col: 1 }, LineCol { line: 1, col: 3 }))
+        );
+    }
+}
@@ -0,0 +1,324 @@
+//! A framework for lint rules working on the tokens of the code (the
+//! AST has no positions), with the names bound by imports resolved.
+//!
+//! Diagnostics on a line with a `# noqa` comment for their rule (see
+//! `suppressions`) are not reported.
+
+pub mod security;
+pub mod style;
+
+use std::collections::HashMap;
+use std::fmt;
+
+use imports::import_aliases;
+use span::{Span, Spanned};
+use suppressions::{is_suppressed, suppressions, SuppressionKind};
+use tokenizer::{tokenize, Token, TokenKind};
+
+/// How serious a problem found by a lint rule is.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Severity {
+    Low,
+    Medium,
+    High,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            Severity::Low => "low",
+            Severity::Medium => "medium",
+            Severity::High => "high",
+        })
+    }
+}
+
+/// A problem found by a lint rule.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LintDiagnostic {
+    /// Code of the rule, like `"B307"`.
+    pub rule: &'static str,
+    pub severity: Severity,
+    pub message: String,
+    pub span: Span,
+    /// Line of the start of the span, starting from 1.
+    pub line: u32,
+}
+
+impl fmt::Display for LintDiagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} ({} severity): {} at line {}",
+            self.rule, self.severity, self.message, self.line
+        )
+    }
+}
+
+impl Spanned for LintDiagnostic {
+    fn span(&self) -> Span {
+        self.span
+    }
+}
+
+/// A lint rule. Rules are `Send` and `Sync`, so sets of rules can be
+/// shared between threads.
+pub trait Rule: Send + Sync {
+    /// Code of the rule, used in diagnostics and `# noqa` comments.
+    fn code(&self) -> &'static str;
+
+    /// Adds the problems found in the code to `diagnostics`.
+    fn check(&self, context: &LintContext, diagnostics: &mut Vec<LintDiagnostic>);
+}
+
+/// A call in the code, found by `LintContext::calls`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Call<'t, 'a: 't> {
+    /// Qualified name of the called function (through the imports), or
+    /// its name if it is not imported (eg. a builtin).
+    pub name: String,
+    /// Tokens of the dotted name of the function.
+    pub callee: &'t [Token<'a>],
+    /// Tokens between the parentheses of the call.
+    pub arguments: &'t [Token<'a>],
+}
+
+impl<'t, 'a> Call<'t, 'a> {
+    /// Returns the tokens of the value of the keyword argument `name`.
+    pub fn keyword(&self, name: &str) -> Option<&'t [Token<'a>]> {
+        let args = self.arguments;
+        let mut depth = 0;
+        for (i, token) in args.iter().enumerate() {
+            match (token.kind, token.text) {
+                (TokenKind::Op, "(") | (TokenKind::Op, "[") | (TokenKind::Op, "{") => depth += 1,
+                (TokenKind::Op, ")") | (TokenKind::Op, "]") | (TokenKind::Op, "}") => depth -= 1,
+                (TokenKind::Name, text)
+                    if depth == 0
+                        && text == name
+                        && is_op(args.get(i + 1), "=")
+                        && (i == 0 || is_op(args.get(i - 1), ",")) =>
+                {
+                    let value = &args[i + 2..];
+                    let end = top_level(value, ",").unwrap_or(value.len());
+                    return Some(&value[..end]);
+                }
+                _ => (),
+            }
+        }
+        None
+    }
+
+    pub fn span(&self) -> Span {
+        span_of(self.callee)
+    }
+}
+
+/// Whether `token` is the operator `op`.
+pub fn is_op(token: Option<&Token>, op: &str) -> bool {
+    token.is_some_and(|t| t.kind == TokenKind::Op && t.text == op)
+}
+
+/// Whether `token` is the name (or keyword) `name`.
+pub fn is_name(token: Option<&Token>, name: &str) -> bool {
+    token.is_some_and(|t| t.kind == TokenKind::Name && t.text == name)
+}
+
+/// Returns the index of the first operator `op` outside of brackets
+/// (which may be a closing bracket, not opened in `tokens`).
+pub fn top_level(tokens: &[Token], op: &str) -> Option<usize> {
+    let mut depth = 0usize;
+    for (i, token) in tokens.iter().enumerate() {
+        if token.kind != TokenKind::Op {
+            continue;
+        }
+        match token.text {
+            "(" | "[" | "{" => depth += 1,
+            ")" | "]" | "}" if depth > 0 => depth -= 1,
+            text if depth == 0 && text == op => return Some(i),
+            _ => (),
+        }
+    }
+    None
+}
+
+/// Returns the span from the first to the last token.
+pub fn span_of(tokens: &[Token]) -> Span {
+    match (tokens.first(), tokens.last()) {
+        (Some(first), Some(last)) => first.span().cover(last.span()),
+        _ => Span::default(),
+    }
+}
+
+/// Returns the tokens of the dotted name starting at `tokens[i]`.
+pub(crate) fn dotted_name<'t, 'a>(tokens: &'t [Token<'a>], i: usize) -> &'t [Token<'a>] {
+    let mut end = i;
+    if tokens.get(i).is_some_and(|t| t.kind == TokenKind::Name) {
+        end += 1;
+        while is_op(tokens.get(end), ".")
+            && tokens
+                .get(end + 1)
+                .is_some_and(|t| t.kind == TokenKind::Name)
+        {
+            end += 2;
+        }
+    }
+    &tokens[i..end]
+}
+
+/// The code being linted.
+pub struct LintContext<'a> {
+    pub code: &'a str,
+    /// Tokens of the simple statements of the code (split at line
+    /// breaks and `;`), without comments, line breaks and indentation.
+    pub statements: Vec<Vec<Token<'a>>>,
+    /// Qualified names of the names bound by imports, in the whole
+    /// module (ignoring scopes).
+    imports: HashMap<&'a str, String>,
+}
+
+impl<'a> LintContext<'a> {
+    pub fn new(code: &'a str) -> LintContext<'a> {
+        let mut statements = Vec::new();
+        let mut statement = Vec::new();
+        for token in tokenize(code) {
+            match token.kind {
+                TokenKind::Comment | TokenKind::Nl => (),
+                TokenKind::Newline
+                | TokenKind::Indent
+                | TokenKind::Dedent
+                | TokenKind::EndMarker => {
+                    if !statement.is_empty() {
+                        statements.push(statement);
+                        statement = Vec::new();
+                    }
+                }
+                TokenKind::Op if token.text == ";" => {
+                    statements.push(statement);
+                    statement = Vec::new();
+                }
+                _ => statement.push(token),
+            }
+        }
+        LintContext {
+            code,
+            statements,
+            // Relative imports are not resolved
+            imports: import_aliases(code)
+                .into_iter()
+                .filter(|&(_, ref name)| !name.starts_with('.'))
+                .collect(),
+        }
+    }
+
+    /// Returns the qualified name of the dotted name `tokens` (through
+    /// the imports), or the name itself if its first name is not
+    /// imported.
+    pub fn qualified_name(&self, tokens: &[Token]) -> String {
+        let mut names = tokens.iter().step_by(2).map(|t| t.text);
+        let first = names.next().unwrap_or("");
+        let mut qualified = self
+            .imports
+            .get(first)
+            .cloned()
+            .unwrap_or_else(|| first.to_string());
+        for name in names {
+            qualified.push('.');
+            qualified.push_str(name);
+        }
+        qualified
+    }
+
+    /// Returns the calls of functions with a dotted name, like `f(x)`
+    /// or `os.path.join(a, b)`, in all the statements except imports.
+    pub fn calls(&self) -> Vec<Call<'_, 'a>> {
+        let mut calls = Vec::new();
+        for tokens in &self.statements {
+            if is_name(tokens.first(), "import") || is_name(tokens.first(), "from") {
+                continue;
+            }
+            let mut i = 0;
+            while i < tokens.len() {
+                let after_dot = i > 0 && is_op(tokens.get(i - 1), ".");
+                let definition = i > 0
+                    && (is_name(tokens.get(i - 1), "def") || is_name(tokens.get(i - 1), "class"));
+                let callee = dotted_name(tokens, i);
+                if callee.is_empty() || after_dot || definition {
+                    i += 1;
+                    continue;
+                }
+                let open = i + callee.len();
+                if is_op(tokens.get(open), "(") {
+                    let arguments = &tokens[open + 1..];
+                    let close = top_level(arguments, ")").unwrap_or(arguments.len());
+                    calls.push(Call {
+                        name: self.qualified_name(callee),
+                        callee,
+                        arguments: &arguments[..close],
+                    });
+                }
+                // Calls in the arguments are found next
+                i = open;
+            }
+        }
+        calls
+    }
+}
+
+/// Runs the rules on some code, and returns their diagnostics in the
+/// order of the code, except the ones suppressed by `# noqa`.
+///
+/// ```
+/// use python_parser::lint::{lint, security::security_rules};
+///
+/// let code = "import subprocess\nsubprocess.run(cmd, shell=True)\neval(x)  # noqa: B307\n";
+/// let rules = security_rules();
+/// let diagnostics = lint(code, &rules);
+/// assert_eq!(diagnostics.len(), 1);
+/// assert_eq!(diagnostics[0].to_string(),
+///     "B602 (high severity): subprocess call with shell=True at line 2");
+/// ```
+pub fn lint(code: &str, rules: &[Box<dyn Rule>]) -> Vec<LintDiagnostic> {
+    let context = LintContext::new(code);
+    let mut diagnostics = Vec::new();
+    for rule in rules {
+        rule.check(&context, &mut diagnostics);
+    }
+    let suppressions = suppressions(code);
+    diagnostics
+        .retain(|d| !is_suppressed(&suppressions, SuppressionKind::Noqa, d.line, Some(d.rule)));
+    diagnostics.sort_by_key(|d| (d.span.start, d.rule));
+    diagnostics
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_calls() {
+        let code = "import os.path as p, sys\nfrom a.b import (c as d,\n  e)\n\
+                    p.join(sys.exit(1), d(f=e()))\ndef g(x): x.y()\n";
+        let context = LintContext::new(code);
+        let calls: Vec<_> = context
+            .calls()
+            .iter()
+            .map(|c| (c.name.clone(), c.arguments.len()))
+            .collect();
+        let expected = [
+            ("os.path.join", 15),
+            ("sys.exit", 1),
+            ("a.b.c", 5),
+            ("a.b.e", 0),
+            ("x.y", 0),
+        ];
+        let expected: Vec<_> = expected
+            .iter()
+            .map(|&(n, len)| (n.to_string(), len))
+            .collect();
+        assert_eq!(calls, expected);
+        let call = &context.calls()[2];
+        let value: Vec<_> = call.keyword("f").unwrap().iter().map(|t| t.text).collect();
+        assert_eq!(value, ["e", "(", ")"]);
+        assert_eq!(call.keyword("e"), None);
+    }
+}
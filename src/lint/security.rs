@@ -0,0 +1,309 @@
+//! Lint rules finding code that is often a security issue, like the
+//! ones of bandit (whose codes they use).
+
+use lint::{is_name, is_op, span_of, top_level, LintContext, LintDiagnostic, Rule, Severity};
+use span::Spanned;
+use tokenizer::{Token, TokenKind};
+
+/// Functions deserializing data in a way that may run arbitrary code.
+const UNSAFE_DESERIALIZERS: [&str; 9] = [
+    "pickle.load",
+    "pickle.loads",
+    "pickle.Unpickler",
+    "cPickle.load",
+    "cPickle.loads",
+    "dill.load",
+    "dill.loads",
+    "marshal.load",
+    "marshal.loads",
+];
+
+/// Functions of `subprocess` running a command.
+const SUBPROCESS_FUNCTIONS: [&str; 7] = [
+    "subprocess.Popen",
+    "subprocess.call",
+    "subprocess.check_call",
+    "subprocess.check_output",
+    "subprocess.run",
+    "subprocess.getoutput",
+    "subprocess.getstatusoutput",
+];
+
+/// Parts of the names of variables and arguments holding passwords.
+const PASSWORD_NAMES: [&str; 6] = ["password", "passwd", "passphrase", "pwd", "secret", "token"];
+
+/// Calls of builtins: `eval` (B307) or `exec` (B102).
+pub struct BuiltinCall {
+    code: &'static str,
+    name: &'static str,
+}
+
+impl Rule for BuiltinCall {
+    fn code(&self) -> &'static str {
+        self.code
+    }
+
+    fn check(&self, context: &LintContext, diagnostics: &mut Vec<LintDiagnostic>) {
+        for call in context.calls() {
+            if call.name == self.name {
+                diagnostics.push(LintDiagnostic {
+                    rule: self.code,
+                    severity: Severity::Medium,
+                    message: format!("use of {}(), which may run arbitrary code", self.name),
+                    span: call.span(),
+                    line: call.callee[0].line,
+                });
+            }
+        }
+    }
+}
+
+/// Deserialization with `pickle` and similar modules (B301).
+pub struct Deserialization;
+
+impl Rule for Deserialization {
+    fn code(&self) -> &'static str {
+        "B301"
+    }
+
+    fn check(&self, context: &LintContext, diagnostics: &mut Vec<LintDiagnostic>) {
+        for call in context.calls() {
+            if UNSAFE_DESERIALIZERS.contains(&&call.name[..]) {
+                diagnostics.push(LintDiagnostic {
+                    rule: self.code(),
+                    severity: Severity::Medium,
+                    message: format!(
+                        "{} may run arbitrary code when deserializing untrusted data",
+                        call.name
+                    ),
+                    span: call.span(),
+                    line: call.callee[0].line,
+                });
+            }
+        }
+    }
+}
+
+/// Calls of `subprocess` functions with `shell=True` (B602).
+pub struct SubprocessShell;
+
+impl Rule for SubprocessShell {
+    fn code(&self) -> &'static str {
+        "B602"
+    }
+
+    fn check(&self, context: &LintContext, diagnostics: &mut Vec<LintDiagnostic>) {
+        for call in context.calls() {
+            if !SUBPROCESS_FUNCTIONS.contains(&&call.name[..]) {
+                continue;
+            }
+            if let Some(&[value]) = call.keyword("shell") {
+                if value.kind == TokenKind::Name && value.text == "True" {
+                    diagnostics.push(LintDiagnostic {
+                        rule: self.code(),
+                        severity: Severity::High,
+                        message: "subprocess call with shell=True".to_string(),
+                        span: span_of(call.callee).cover(value.span()),
+                        line: call.callee[0].line,
+                    });
+                }
+            }
+        }
+    }
+}
+
+/// Whether a name is the one of a variable or argument holding a
+/// password.
+fn is_password_name(name: &str) -> bool {
+    let name = name.to_lowercase();
+    PASSWORD_NAMES.iter().any(|&part| name.contains(part))
+}
+
+/// Whether `token` is a non-empty string literal (not an f-string).
+fn is_string_literal(token: &Token) -> bool {
+    if token.kind != TokenKind::String {
+        return false;
+    }
+    let prefix_len = token.text.find(&['\'', '"'][..]).unwrap_or(0);
+    let prefix = &token.text[..prefix_len];
+    let quotes = token.text[prefix_len..].len()
+        - token.text[prefix_len..]
+            .trim_matches(&['\'', '"'][..])
+            .len();
+    !prefix.contains(&['f', 'F', 'b', 'B'][..]) && token.text.len() > prefix_len + quotes
+}
+
+/// Passwords in string literals assigned to variables (B105) or given
+/// as keyword arguments (B106).
+pub struct HardcodedPassword;
+
+impl HardcodedPassword {
+    fn report(&self, rule: &'static str, target: &Token, value: &Token) -> LintDiagnostic {
+        LintDiagnostic {
+            rule,
+            severity: Severity::Low,
+            message: format!("possible hardcoded password in '{}'", target.text),
+            span: target.span().cover(value.span()),
+            line: target.line,
+        }
+    }
+}
+
+impl Rule for HardcodedPassword {
+    fn code(&self) -> &'static str {
+        "B105"
+    }
+
+    fn check(&self, context: &LintContext, diagnostics: &mut Vec<LintDiagnostic>) {
+        // `password = "..."`, `self.password = "..."` or
+        // `config["password"] = "..."`
+        for tokens in &context.statements {
+            let equal = match top_level(tokens, "=") {
+                Some(equal) if equal > 0 && tokens.len() == equal + 2 => equal,
+                _ => continue,
+            };
+            let value = &tokens[equal + 1];
+            let mut target = &tokens[equal - 1];
+            if is_op(Some(target), "]") && equal >= 2 {
+                target = &tokens[equal - 2];
+            }
+            let name = match target.kind {
+                TokenKind::Name => target.text,
+                TokenKind::String => target.text.trim_matches(&['\'', '"'][..]),
+                _ => continue,
+            };
+            if is_password_name(name) && is_string_literal(value) {
+                diagnostics.push(self.report("B105", target, value));
+            }
+        }
+        // `connect(password="...")`
+        for call in context.calls() {
+            let args = call.arguments;
+            for (i, token) in args.iter().enumerate() {
+                let is_keyword = token.kind == TokenKind::Name
+                    && is_op(args.get(i + 1), "=")
+                    && (i == 0 || is_op(args.get(i - 1), ","));
+                if !is_keyword || !is_password_name(token.text) {
+                    continue;
+                }
+                if let Some(&[value]) = call.keyword(token.text) {
+                    if is_string_literal(&value) {
+                        diagnostics.push(self.report("B106", token, &value));
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// `assert` statements, which are removed when Python runs with `-O`
+/// (B101).
+pub struct AssertUsed;
+
+impl Rule for AssertUsed {
+    fn code(&self) -> &'static str {
+        "B101"
+    }
+
+    fn check(&self, context: &LintContext, diagnostics: &mut Vec<LintDiagnostic>) {
+        for tokens in &context.statements {
+            for token in tokens.iter().filter(|t| is_name(Some(t), "assert")) {
+                diagnostics.push(LintDiagnostic {
+                    rule: self.code(),
+                    severity: Severity::Low,
+                    message: "use of assert, which is removed in optimized code".to_string(),
+                    span: token.span(),
+                    line: token.line,
+                });
+            }
+        }
+    }
+}
+
+/// Returns all the security rules.
+pub fn security_rules() -> Vec<Box<dyn Rule>> {
+    vec![
+        Box::new(BuiltinCall {
+            code: "B307",
+            name: "eval",
+        }),
+        Box::new(BuiltinCall {
+            code: "B102",
+            name: "exec",
+        }),
+        Box::new(Deserialization),
+        Box::new(SubprocessShell),
+        Box::new(HardcodedPassword),
+        Box::new(AssertUsed),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lint::lint;
+
+    fn found(code: &str) -> Vec<(&'static str, Severity, &str, u32)> {
+        lint(code, &security_rules())
+            .iter()
+            .map(|d| (d.rule, d.severity, &code[d.span.start..d.span.end], d.line))
+            .collect()
+    }
+
+    #[test]
+    fn test_calls() {
+        let code = "import pickle as p, subprocess
+from subprocess import run
+eval(x); self.eval(x); exec(compile(x))
+p.loads(data)
+subprocess.Popen(cmd, shell=True)
+run(cmd, shell=False); run(cmd, shell=True, check=True)
+def eval(x): pass
+";
+        assert_eq!(
+            found(code),
+            [
+                ("B307", Severity::Medium, "eval", 3),
+                ("B102", Severity::Medium, "exec", 3),
+                ("B301", Severity::Medium, "p.loads", 4),
+                (
+                    "B602",
+                    Severity::High,
+                    "subprocess.Popen(cmd, shell=True",
+                    5
+                ),
+                ("B602", Severity::High, "run(cmd, shell=True", 6),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_passwords() {
+        let code = "password = 'hunter2'
+self.db_password = \"x\"
+config['secret_key'] = 'x'
+token = ''
+password = get_password()
+password = f'{x}'
+connect(user='me', password='x')
+connect(password=password)
+";
+        assert_eq!(
+            found(code),
+            [
+                ("B105", Severity::Low, "password = 'hunter2'", 1),
+                ("B105", Severity::Low, "db_password = \"x\"", 2),
+                ("B105", Severity::Low, "'secret_key'] = 'x'", 3),
+                ("B106", Severity::Low, "password='x'", 7),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_assert() {
+        assert_eq!(
+            found("assert user.is_admin\nx = 1  # assert\nassert x  # noqa: B101\n"),
+            [("B101", Severity::Low, "assert", 1)]
+        );
+    }
+}
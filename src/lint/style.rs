@@ -0,0 +1,85 @@
+//! Lint rules about the style of the code, like the ones of pylint and
+//! pyflakes (whose codes they use).
+
+use fidelity::parentheses;
+use imports::unused_imports;
+use lint::{LintContext, LintDiagnostic, Rule, Severity};
+use span::Spanned;
+
+/// Parentheses which can be removed without changing the code, like in
+/// `return (x)` or `if (a and b):` (C0325).
+pub struct RedundantParentheses;
+
+impl Rule for RedundantParentheses {
+    fn code(&self) -> &'static str {
+        "C0325"
+    }
+
+    fn check(&self, context: &LintContext, diagnostics: &mut Vec<LintDiagnostic>) {
+        for group in parentheses(context.code)
+            .into_iter()
+            .filter(|p| p.redundant)
+        {
+            diagnostics.push(LintDiagnostic {
+                rule: self.code(),
+                severity: Severity::Low,
+                message: "redundant parentheses".to_string(),
+                span: group.span(),
+                line: group.line,
+            });
+        }
+    }
+}
+
+/// Names imported and never used (F401), which
+/// `imports::remove_unused_imports` removes.
+pub struct UnusedImport;
+
+impl Rule for UnusedImport {
+    fn code(&self) -> &'static str {
+        "F401"
+    }
+
+    fn check(&self, context: &LintContext, diagnostics: &mut Vec<LintDiagnostic>) {
+        for unused in unused_imports(context.code) {
+            diagnostics.push(LintDiagnostic {
+                rule: self.code(),
+                severity: Severity::Low,
+                message: format!("`{}` imported but unused", unused.name),
+                span: unused.span,
+                line: unused.line,
+            });
+        }
+    }
+}
+
+/// Returns all the style rules.
+pub fn style_rules() -> Vec<Box<dyn Rule>> {
+    vec![Box::new(RedundantParentheses), Box::new(UnusedImport)]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lint::lint;
+
+    #[test]
+    fn test_redundant_parentheses() {
+        let code = "if (x):\n    return (x + 1) * 2\nwhile (x): pass  # noqa: C0325\n";
+        let found: Vec<_> = lint(code, &style_rules())
+            .iter()
+            .map(|d| (d.rule, &code[d.span.start..d.span.end], d.line))
+            .collect();
+        assert_eq!(found, [("C0325", "(x)", 1)]);
+    }
+
+    #[test]
+    fn test_unused_import() {
+        let code = "import os, sys  # noqa: F401\nfrom m import (a,\n    b)\nprint(a)\n";
+        let found: Vec<_> = lint(code, &style_rules())
+            .iter()
+            .map(|d| (d.rule, d.message.clone(), d.line))
+            .collect();
+        assert_eq!(found, [("F401", "`b` imported but unused".to_string(), 3)]);
+    }
+}
@@ -1,26 +1,163 @@
 extern crate python_parser;
+#[cfg(feature = "termcolor")]
+extern crate termcolor;
 
 use std::env::args_os;
-use std::fs::File;
-use std::io::Read;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::exit;
+use std::time::Duration;
 
+#[cfg(feature = "termcolor")]
+use python_parser::color::{write_parse_error, write_tree};
+use python_parser::query::{select, Filter};
+use python_parser::server::Server;
+use python_parser::visitors::dump::debug_tree;
 use python_parser::visitors::printer::format_module;
-use python_parser::{file_input, make_strspan};
+use python_parser::watch::{FileChange, FileWatcher};
+use python_parser::{file_input, make_strspan, parse_module};
+
+/// Prints the first line of each node of `content` selected by `filter`.
+fn print_selected(filename: &str, content: &str, filter: &Filter) {
+    let module = match parse_module(content) {
+        Ok(module) => module,
+        Err(e) => {
+            eprintln!("{}: {}", filename, e);
+            return;
+        }
+    };
+    select(&module.body, filter, |node, _| {
+        println!("{}: {}", filename, node.first_line())
+    });
+}
+
+/// Prints the code of `content` formatted, or what could not be parsed.
+fn print_formatted(filename: &str, content: &str) {
+    let (rest, ast) = match file_input(make_strspan(content)) {
+        Ok(res) => res,
+        Err(e) => {
+            eprintln!("{}: {:?}", filename, e);
+            return;
+        }
+    };
+    //println!("{:?}", ast);
+    let output = format_module(&ast);
+    if rest.fragment.0.len() > 0 {
+        println!("\nUnparsed: {:?}\n\n", rest.fragment.0)
+    }
+    println!("{}", output);
+}
+
+/// Prints the AST of `content` as a tree, in colors if `color`.
+#[cfg(feature = "termcolor")]
+fn print_tree(filename: &str, content: &str, color: bool) {
+    use termcolor::{ColorChoice, StandardStream};
+
+    let choice = if color {
+        ColorChoice::Always
+    } else {
+        ColorChoice::Never
+    };
+    let result = match debug_tree(content) {
+        Ok(tree) => write_tree(&mut StandardStream::stdout(choice).lock(), &tree),
+        Err(e) => write_parse_error(
+            &mut StandardStream::stderr(choice).lock(),
+            filename,
+            content,
+            &e,
+        ),
+    };
+    result.expect("Could not write the tree");
+}
+
+/// Prints the AST of `content` as a tree.
+#[cfg(not(feature = "termcolor"))]
+fn print_tree(filename: &str, content: &str, _color: bool) {
+    match debug_tree(content) {
+        Ok(tree) => print!("{}", tree),
+        Err(e) => eprintln!("{}: {}", filename, e),
+    }
+}
+
+/// How the files are printed.
+#[derive(Copy, Clone)]
+struct Output {
+    tree: bool,
+    color: bool,
+}
+
+fn process_file(path: &Path, filter: Option<&Filter>, output: Output) {
+    let filename = path.to_string_lossy();
+    let content = match fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) => {
+            eprintln!("{}: {}", filename, e);
+            return;
+        }
+    };
+    match filter {
+        Some(filter) => print_selected(&filename, &content, filter),
+        None if output.tree => print_tree(&filename, &content, output.color),
+        None => print_formatted(&filename, &content),
+    }
+}
 
 fn main() {
     let mut iter = args_os();
     iter.next();
-    for filename in iter {
-        let mut file = File::open(filename).expect("Could not open file");
-        let mut content = String::new();
-        file.read_to_string(&mut content)
-            .expect("Could not read file");
-        let (rest, ast) = file_input(make_strspan(&content)).unwrap();
-        //println!("{:?}", ast);
-        let output = format_module(&ast);
-        if rest.fragment.0.len() > 0 {
-            println!("\nUnparsed: {:?}\n\n", rest.fragment.0)
+    let mut filter = None;
+    let mut watch = false;
+    let mut output = Output {
+        tree: false,
+        color: false,
+    };
+    let mut paths = Vec::new();
+    while let Some(arg) = iter.next() {
+        if arg == "--select" {
+            let arg = iter.next().expect("--select needs a filter");
+            match Filter::parse(&arg.to_string_lossy()) {
+                Ok(f) => filter = Some(f),
+                Err(e) => {
+                    eprintln!("{}", e);
+                    exit(2);
+                }
+            }
+        } else if arg == "--tree" {
+            output.tree = true;
+        } else if arg == "--color" {
+            if cfg!(not(feature = "termcolor")) {
+                eprintln!("--color needs the termcolor feature");
+                exit(2);
+            }
+            output.color = true;
+        } else if arg == "--watch" {
+            watch = true;
+        } else if arg == "--server" {
+            let stdin = io::stdin();
+            let stdout = io::stdout();
+            Server::new()
+                .serve(stdin.lock(), stdout.lock())
+                .expect("Could not read requests");
+            return;
+        } else {
+            paths.push(PathBuf::from(arg));
+        }
+    }
+    if !watch {
+        for path in paths {
+            process_file(&path, filter.as_ref(), output);
+        }
+        return;
+    }
+    // Process the files again each time they change
+    let mut watcher = FileWatcher::new(paths);
+    loop {
+        for change in watcher.wait(Duration::from_millis(200)) {
+            match change {
+                FileChange::Modified(path) => process_file(&path, filter.as_ref(), output),
+                FileChange::Removed(path) => eprintln!("{}: removed", path.display()),
+            }
         }
-        println!("{}", output);
     }
 }
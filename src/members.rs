@@ -0,0 +1,628 @@
+//! Reordering of the members of classes to follow a convention (like
+//! dunder methods first, then properties, then the other methods),
+//! keeping the comments and formatting of the code.
+//!
+//! Members are moved with their decorators and the comments above them.
+//! The other statements of a class body (like its docstring, `pass` or
+//! an `if`) are never moved, and members are not moved across them.
+
+use keywords::{is_keyword, PARSED_VERSION};
+use line_index::LineIndex;
+use lint::{is_name, is_op};
+use span::{replace_spans, Span};
+use tokenizer::{line_breaks, tokenize, Token, TokenKind};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum MemberKind {
+    /// Assignments and annotations of class attributes (like `x = 1` or
+    /// `x: int`).
+    Attribute,
+    NestedClass,
+    /// Methods named like `__init__`.
+    Dunder,
+    /// Methods decorated with `property` or `cached_property`, along
+    /// with their setters and deleters.
+    Property,
+    ClassMethod,
+    StaticMethod,
+    /// Methods named like `_name`.
+    Private,
+    /// The other methods.
+    Public,
+}
+
+/// The order of the members of classes.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct MemberOrder {
+    /// Kinds of members, in their order. Members of the kinds which are
+    /// not in it go last.
+    pub kinds: Vec<MemberKind>,
+    /// Whether members of the same kind are sorted by name (except for
+    /// `__new__` and `__init__`, which go first). Otherwise they keep
+    /// their order.
+    pub by_name: bool,
+}
+
+impl Default for MemberOrder {
+    fn default() -> MemberOrder {
+        MemberOrder {
+            kinds: vec![
+                MemberKind::Attribute,
+                MemberKind::NestedClass,
+                MemberKind::Dunder,
+                MemberKind::Property,
+                MemberKind::ClassMethod,
+                MemberKind::StaticMethod,
+                MemberKind::Public,
+                MemberKind::Private,
+            ],
+            by_name: false,
+        }
+    }
+}
+
+/// A statement of a class body.
+struct Member<'a> {
+    /// `None` for statements which are not moved.
+    kind: Option<MemberKind>,
+    name: &'a str,
+    /// From its first comment or decorator to the end of its last line,
+    /// without the line break.
+    span: Span,
+    /// Names bound by the member, and names evaluated when the class is
+    /// created.
+    binds: Vec<&'a str>,
+    uses: Vec<&'a str>,
+}
+
+/// Returns the kind of a method decorated by `decorators`.
+fn method_kind(name: &str, decorators: &[String]) -> MemberKind {
+    let is_decorated = |names: &[&str]| {
+        decorators.iter().any(|decorator| {
+            names
+                .iter()
+                .any(|name| decorator == name || decorator.ends_with(&format!(".{}", name)[..]))
+        })
+    };
+    if is_decorated(&["property", "cached_property", "setter", "getter", "deleter"]) {
+        MemberKind::Property
+    } else if is_decorated(&["classmethod"]) {
+        MemberKind::ClassMethod
+    } else if is_decorated(&["staticmethod"]) {
+        MemberKind::StaticMethod
+    } else if name.len() > 4 && name.starts_with("__") && name.ends_with("__") {
+        MemberKind::Dunder
+    } else if name.starts_with('_') {
+        MemberKind::Private
+    } else {
+        MemberKind::Public
+    }
+}
+
+/// Returns the names evaluated by `tokens` (not attributes, keywords or
+/// keyword arguments).
+fn used_names<'a>(tokens: &[Token<'a>]) -> Vec<&'a str> {
+    tokens
+        .iter()
+        .enumerate()
+        .filter(|&(i, t)| {
+            t.kind == TokenKind::Name
+                && !is_keyword(t.text, PARSED_VERSION)
+                && (i == 0 || !is_op(tokens.get(i - 1), "."))
+                && !(is_op(tokens.get(i + 1), "=") && i > 0 && tokens[i - 1].text != "=")
+        })
+        .map(|(_, t)| t.text)
+        .collect()
+}
+
+/// Sets the kind, name and names of a member from the tokens of its
+/// decorators and first logical line.
+fn classify<'a>(member: &mut Member<'a>, lines: &[Vec<Token<'a>>]) {
+    let (statement, decorators) = match lines.split_last() {
+        Some(split) => split,
+        None => return,
+    };
+    let first = match statement.iter().position(|t| t.text != "async") {
+        Some(first) => first,
+        None => return,
+    };
+    if decorators.iter().any(|line| !is_op(line.first(), "@")) {
+        return;
+    }
+    for decorator in decorators {
+        member.uses.extend(used_names(&decorator[1..]));
+    }
+    let mut depth = 0;
+    let mut assignments = Vec::new();
+    for (i, token) in statement.iter().enumerate() {
+        match token.text {
+            "(" | "[" | "{" if token.kind == TokenKind::Op => depth += 1,
+            ")" | "]" | "}" if token.kind == TokenKind::Op => depth -= 1,
+            ";" if token.kind == TokenKind::Op && depth == 0 => return,
+            "=" if token.kind == TokenKind::Op && depth == 0 => assignments.push(i),
+            _ => (),
+        }
+    }
+    let keyword = &statement[first];
+    let name = match statement.get(first + 1) {
+        Some(name) if name.kind == TokenKind::Name => name.text,
+        _ => "",
+    };
+    if is_name(Some(keyword), "def") && !name.is_empty() {
+        let decorators: Vec<String> = decorators
+            .iter()
+            .map(|line| {
+                line[1..]
+                    .iter()
+                    .take_while(|t| t.text != "(")
+                    .map(|t| t.text)
+                    .collect()
+            })
+            .collect();
+        member.kind = Some(method_kind(name, &decorators));
+        member.name = name;
+        member.binds.push(name);
+        // The defaults and annotations of the parameters, without the
+        // parameters themselves
+        let header = &statement[first + 2..];
+        let mut depth = 0;
+        for (i, token) in header.iter().enumerate() {
+            match token.text {
+                "(" | "[" | "{" => depth += 1,
+                ")" | "]" | "}" => depth -= 1,
+                _ => (),
+            }
+            let previous = if i == 0 { None } else { header.get(i - 1) };
+            let is_parameter =
+                depth == 1 && previous.map_or(false, |t| ["(", ",", "*", "**"].contains(&t.text));
+            if !is_parameter {
+                member.uses.extend(used_names(&header[i..=i]));
+            }
+        }
+    } else if is_name(Some(keyword), "class") && first == 0 && !name.is_empty() {
+        member.kind = Some(MemberKind::NestedClass);
+        member.name = name;
+        member.binds.push(name);
+        member.uses.extend(used_names(&statement[2..]));
+    } else if first == 0 && decorators.is_empty() {
+        let annotated = keyword.kind == TokenKind::Name && is_op(statement.get(1), ":");
+        let targets_end = if annotated {
+            1
+        } else {
+            match assignments.last() {
+                Some(&end) => end,
+                None => return,
+            }
+        };
+        let targets = &statement[..targets_end];
+        let is_target = |t: &Token| {
+            (t.kind == TokenKind::Name && !is_keyword(t.text, PARSED_VERSION))
+                || is_op(Some(t), ",")
+                || is_op(Some(t), "=")
+        };
+        if !targets.iter().all(is_target) {
+            return;
+        }
+        member.kind = Some(MemberKind::Attribute);
+        member.name = keyword.text;
+        member.binds = used_names(targets);
+        member.uses = used_names(&statement[targets_end..]);
+    }
+}
+
+/// Returns the statements of the class body starting at the `Indent`
+/// token `tokens[body]`.
+fn class_members<'a>(
+    code: &'a str,
+    index: &LineIndex,
+    tokens: &[Token<'a>],
+    body: usize,
+) -> Vec<Member<'a>> {
+    let line_start = |line: u32| index.line_span(line - 1).map_or(0, |span| span.start);
+    let column = tokens[body].text.len();
+    // The first and last lines of each member, and its body-level
+    // logical lines
+    let mut members: Vec<(u32, u32, Vec<Vec<Token<'a>>>)> = Vec::new();
+    let mut comment_line = None;
+    let mut depth = 0;
+    let mut in_line = false;
+    for token in &tokens[body + 1..] {
+        match token.kind {
+            TokenKind::Indent => depth += 1,
+            TokenKind::Dedent if depth == 0 => break,
+            TokenKind::Dedent => depth -= 1,
+            TokenKind::EndMarker => break,
+            TokenKind::Nl => (),
+            TokenKind::Newline => in_line = false,
+            TokenKind::Comment if in_line => (),
+            TokenKind::Comment if comment_line.is_some() => (),
+            TokenKind::Comment => {
+                if token.offset - line_start(token.line) > column {
+                    if let Some(member) = members.last_mut() {
+                        member.1 = token.line;
+                    }
+                } else {
+                    comment_line = Some(token.line);
+                }
+            }
+            _ => {
+                let is_new_line = !in_line;
+                in_line = true;
+                if is_new_line && depth > 0 {
+                    // The comments were in the body of the last member
+                    // (like a comment at column 0 in a method)
+                    comment_line = None;
+                }
+                if is_new_line && depth == 0 {
+                    let is_decorated = members.last().map_or(false, |member| {
+                        member
+                            .2
+                            .last()
+                            .map_or(false, |line| is_op(line.first(), "@"))
+                    });
+                    match members.last_mut() {
+                        Some(ref mut member) if is_decorated => member.2.push(Vec::new()),
+                        _ => {
+                            let first = comment_line.unwrap_or(token.line);
+                            members.push((first, token.line, vec![Vec::new()]))
+                        }
+                    }
+                    comment_line = None;
+                }
+                if let Some(member) = members.last_mut() {
+                    member.1 = token.line + line_breaks(token.text);
+                    if depth == 0 {
+                        if let Some(line) = member.2.last_mut() {
+                            line.push(*token);
+                        }
+                    }
+                }
+            }
+        }
+    }
+    members
+        .into_iter()
+        .map(|(first, last, lines)| {
+            let end = index
+                .line_span(last - 1)
+                .map_or(code.len(), |span| span.end);
+            let mut member = Member {
+                kind: None,
+                name: "",
+                span: Span::new(line_start(first), end),
+                binds: Vec::new(),
+                uses: Vec::new(),
+            };
+            classify(&mut member, &lines);
+            member
+        })
+        .collect()
+}
+
+/// Merges the consecutive methods with the same name (like the getter
+/// and setter of a property, or overloads).
+fn merge_methods(members: Vec<Member>) -> Vec<Member> {
+    let mut merged: Vec<Member> = Vec::new();
+    for member in members {
+        let is_method = |m: &Member| match m.kind {
+            Some(MemberKind::Attribute) | Some(MemberKind::NestedClass) | None => false,
+            Some(_) => true,
+        };
+        match merged.last_mut() {
+            Some(ref mut last)
+                if is_method(last) && is_method(&member) && last.name == member.name =>
+            {
+                last.span = last.span.cover(member.span);
+                last.uses.extend(member.uses);
+            }
+            _ => merged.push(member),
+        }
+    }
+    merged
+}
+
+/// Returns the new order of a run of members which can be moved, or
+/// `None` if moving them would change what the class body evaluates.
+fn sorted(members: &[Member], order: &MemberOrder) -> Option<Vec<usize>> {
+    let mut sorted: Vec<usize> = (0..members.len()).collect();
+    sorted.sort_by_key(|&i| {
+        let member = &members[i];
+        let kind = member.kind.expect("members have a kind");
+        let rank = order
+            .kinds
+            .iter()
+            .position(|&k| k == kind)
+            .unwrap_or_else(|| order.kinds.len());
+        if order.by_name {
+            let constructor = ["__new__", "__init__"]
+                .iter()
+                .position(|&name| name == member.name)
+                .unwrap_or(2);
+            (rank, constructor, member.name, i)
+        } else {
+            (rank, 0, "", i)
+        }
+    });
+    let position = |i: usize| sorted.iter().position(|&j| j == i);
+    let shares = |names: &[&str], others: &[&str]| names.iter().any(|n| others.contains(n));
+    for (i, first) in members.iter().enumerate() {
+        for (j, second) in members.iter().enumerate().skip(i + 1) {
+            let depends = shares(&first.binds, &second.uses)
+                || shares(&first.uses, &second.binds)
+                || shares(&first.binds, &second.binds);
+            if depends && position(j) < position(i) {
+                return None;
+            }
+        }
+    }
+    Some(sorted)
+}
+
+/// Returns the edits reordering the members of the classes whose
+/// keyword is at the indentation depth `class_depth`.
+fn sort_depth(code: &str, class_depth: usize, order: &MemberOrder) -> Vec<(Span, String)> {
+    let index = LineIndex::new(code);
+    let tokens: Vec<_> = tokenize(code).collect();
+    let mut edits = Vec::new();
+    let mut depth = 0;
+    let mut at_line_start = true;
+    for (i, token) in tokens.iter().enumerate() {
+        match token.kind {
+            TokenKind::Indent => depth += 1,
+            TokenKind::Dedent => depth -= 1,
+            TokenKind::Newline => at_line_start = true,
+            TokenKind::Comment | TokenKind::Nl => (),
+            _ => {
+                let is_class =
+                    at_line_start && depth == class_depth && is_name(Some(token), "class");
+                at_line_start = false;
+                if !is_class {
+                    continue;
+                }
+                let body = tokens[i..]
+                    .iter()
+                    .position(|t| t.kind == TokenKind::Newline)
+                    .and_then(|newline| {
+                        tokens[i + newline + 1..]
+                            .iter()
+                            .position(|t| t.kind != TokenKind::Comment && t.kind != TokenKind::Nl)
+                            .map(|body| i + newline + 1 + body)
+                    });
+                let body = match body {
+                    Some(body) if tokens[body].kind == TokenKind::Indent => body,
+                    _ => continue,
+                };
+                let members = merge_methods(class_members(code, &index, &tokens, body));
+                for run in members.split(|member| member.kind.is_none()) {
+                    let sorted = match sorted(run, order) {
+                        Some(sorted) => sorted,
+                        None => continue,
+                    };
+                    for (member, &new) in run.iter().zip(&sorted) {
+                        let new = &run[new];
+                        if new.span != member.span {
+                            edits.push((
+                                member.span,
+                                code[new.span.start..new.span.end].to_string(),
+                            ));
+                        }
+                    }
+                }
+            }
+        }
+    }
+    edits
+}
+
+/// Returns `code` with the members of its classes reordered following
+/// `order`. Members are not moved if it would change the names their
+/// decorators, default values or values refer to when the class is
+/// created.
+///
+/// Returns `None` if the members of a class cannot be told apart, which
+/// should not happen on valid code.
+///
+/// ```
+/// use python_parser::members::{sort_members, MemberOrder};
+///
+/// let code = "class A:
+///     def f(self):
+///         pass
+///
+///     def __init__(self):  # Creates an A
+///         pass
+///
+///     @property
+///     def x(self):
+///         return 1
+/// ";
+/// assert_eq!(
+///     sort_members(code, &MemberOrder::default()).unwrap(),
+///     "class A:
+///     def __init__(self):  # Creates an A
+///         pass
+///
+///     @property
+///     def x(self):
+///         return 1
+///
+///     def f(self):
+///         pass
+/// "
+/// );
+/// ```
+pub fn sort_members(code: &str, order: &MemberOrder) -> Option<String> {
+    let max_depth = tokenize(code)
+        .scan(0, |depth, token| {
+            match token.kind {
+                TokenKind::Indent => *depth += 1,
+                TokenKind::Dedent => *depth -= 1,
+                _ => (),
+            }
+            Some(*depth)
+        })
+        .max()
+        .unwrap_or(0);
+    let mut code = code.to_string();
+    // Nested classes first, so that the spans of the members of a class
+    // do not change while they are reordered
+    for depth in (0..=max_depth).rev() {
+        let edits = sort_depth(&code, depth, order);
+        if edits.is_empty() {
+            continue;
+        }
+        let edits: Vec<_> = edits
+            .iter()
+            .map(|&(span, ref text)| (span, &text[..]))
+            .collect();
+        code = replace_spans(&code, &edits)?;
+    }
+    Some(code)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sort_members() {
+        let order = MemberOrder::default();
+        let code = "class A(Base):
+    '''Docstring'''
+
+    def public(self):  # trailing
+        pass
+        # end of public
+
+    @staticmethod
+    def static():
+        pass
+
+    def _private(self):
+        pass
+
+    @classmethod
+    def create(cls):
+        return cls()
+
+    # Comment of x
+    @property
+    def x(self):
+        return 1
+
+    @x.setter
+    def x(self, value):
+        pass
+
+    def __eq__(self, other):
+        return True
+
+    y: int = 1
+
+    class Meta:
+        def b(self): pass
+        def __init__(self): pass
+";
+        let expected = "class A(Base):
+    '''Docstring'''
+
+    y: int = 1
+
+    class Meta:
+        def __init__(self): pass
+        def b(self): pass
+
+    def __eq__(self, other):
+        return True
+
+    # Comment of x
+    @property
+    def x(self):
+        return 1
+
+    @x.setter
+    def x(self, value):
+        pass
+
+    @classmethod
+    def create(cls):
+        return cls()
+
+    @staticmethod
+    def static():
+        pass
+
+    def public(self):  # trailing
+        pass
+        # end of public
+
+    def _private(self):
+        pass
+";
+        assert_eq!(sort_members(code, &order).unwrap(), expected);
+        assert_eq!(sort_members(expected, &order).unwrap(), expected);
+
+        let by_name = MemberOrder {
+            kinds: vec![MemberKind::Public, MemberKind::Dunder],
+            by_name: true,
+        };
+        let code = "class A:\n    def b(self): pass\n    def __eq__(self): pass\n    \
+                    def a(self): pass\n    def __init__(self): pass\n    x = 1\n";
+        assert_eq!(
+            sort_members(code, &by_name).unwrap(),
+            "class A:\n    def a(self): pass\n    def b(self): pass\n    \
+             def __init__(self): pass\n    def __eq__(self): pass\n    x = 1\n"
+        );
+
+        // Members are not moved across other statements, or if what
+        // the class body evaluates would change
+        let unchanged = [
+            "class A:\n    def f(self): pass\n    if x:\n        y = 1\n    def __eq__(self): pass\n",
+            "class A:\n    def f(self): pass\n    g = f\n",
+            "class A:\n    def f(self, x=f): pass\n    f = 1\n",
+            "class A:\n    def f(self): pass\n    @f\n    def __eq__(self): pass\n",
+            "class A: x = 1\n",
+        ];
+        for code in &unchanged {
+            assert_eq!(&sort_members(code, &order).unwrap(), code);
+        }
+        assert_eq!(
+            sort_members("class A:\n    def f(self, g=1): pass\n    g = 2\n", &order).unwrap(),
+            "class A:\n    g = 2\n    def f(self, g=1): pass\n"
+        );
+    }
+
+    #[test]
+    fn test_comments_in_bodies() {
+        // Comments less indented than the body of a method are still in
+        // the method if it goes on after them
+        let code = "class A:
+    def f(self):
+        x = 1
+# disabled:
+#        x = 2
+        return x
+
+    # About __init__
+        # indented
+    def __init__(self):
+        pass
+";
+        let expected = "class A:
+    # About __init__
+        # indented
+    def __init__(self):
+        pass
+
+    def f(self):
+        x = 1
+# disabled:
+#        x = 2
+        return x
+";
+        assert_eq!(
+            sort_members(code, &MemberOrder::default()).unwrap(),
+            expected
+        );
+    }
+}
@@ -0,0 +1,233 @@
+//! Optional memoization of the failures of some rules (packrat parsing).
+//!
+//! The parsers backtrack when an alternative fails, which can make
+//! them re-parse the same expression many times; on crafted inputs,
+//! this can take exponential time.
+//! Inside `with_memoization`, the failures of these rules are cached
+//! by position, so each failing attempt is parsed at most once.
+//!
+//! Successes are not cached: the AST is made of boxes, so returning a
+//! cached node would deep-copy it, which takes quadratic time on nested
+//! code. The grammar does not parse a rule twice at the same position
+//! when it succeeds, instead.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::mem;
+
+use nom::types::CompleteStr;
+use nom::{Context, ErrorKind, IResult};
+use nom_locate::LocatedSpan;
+
+use ast::Expression;
+use helpers::StrSpan;
+
+/// A memoized rule, and whether newlines are spaces for it.
+type Rule = (&'static str, bool);
+
+/// Position of the input, identified by the address and length of the
+/// remaining code and by its offset.
+type Position = (usize, usize, usize);
+
+/// Offset, line and kind of the error.
+type Entry = (usize, u32, ErrorKind<u32>);
+
+/// The failures of the rules, by position.
+pub(crate) type Table = HashMap<(Rule, Position), Entry>;
+
+thread_local! {
+    static MEMO: RefCell<Option<Table>> = const { RefCell::new(None) };
+}
+
+/// Restores the previous state of the memoization table when dropped,
+/// even if the closure panics.
+struct Guard {
+    was_enabled: bool,
+}
+
+impl Drop for Guard {
+    fn drop(&mut self) {
+        if !self.was_enabled {
+            MEMO.with(|memo| *memo.borrow_mut() = None);
+        }
+    }
+}
+
+/// Runs `f`, memoizing the rules the parsers backtrack on, so failing
+/// alternatives are not parsed again, at the cost of some memory.
+///
+/// The table is specific to the current thread and dropped when `f`
+/// returns. It is emptied by the parsing functions (like
+/// `parse_module`), as positions of different inputs (such as
+/// successive contents of a buffer) may be the same; when calling
+/// parsers like `file_input` directly, use one scope per input.
+///
+/// ```
+/// use python_parser::{file_input, make_strspan};
+/// use python_parser::memo::with_memoization;
+///
+/// let code = format!("{}x{}\n", "f(".repeat(30), ")".repeat(30));
+/// let (rest, ast) = with_memoization(|| file_input(make_strspan(&code))).unwrap();
+/// assert_eq!(rest.fragment.0, "");
+/// assert_eq!(ast.len(), 1);
+/// ```
+pub fn with_memoization<F, R>(f: F) -> R
+where
+    F: FnOnce() -> R,
+{
+    let was_enabled = MEMO.with(|memo| {
+        let mut memo = memo.borrow_mut();
+        let was_enabled = memo.is_some();
+        if !was_enabled {
+            *memo = Some(HashMap::new());
+        }
+        was_enabled
+    });
+    let _guard = Guard { was_enabled };
+    f()
+}
+
+/// Gives the table back to its owner when dropped, emptied but with
+/// its memory.
+struct TableGuard<'a> {
+    table: &'a mut Table,
+    previous: Option<Table>,
+}
+
+impl<'a> Drop for TableGuard<'a> {
+    fn drop(&mut self) {
+        let previous = self.previous.take();
+        if let Some(table) = MEMO.with(|memo| mem::replace(&mut *memo.borrow_mut(), previous)) {
+            *self.table = table;
+        }
+        self.table.clear();
+    }
+}
+
+/// Like `with_memoization`, but with `table` as the table, so that
+/// consecutive calls reuse its memory.
+pub(crate) fn with_table<F, R>(table: &mut Table, f: F) -> R
+where
+    F: FnOnce() -> R,
+{
+    table.clear();
+    let previous = MEMO.with(|memo| memo.borrow_mut().replace(mem::take(table)));
+    let _guard = TableGuard { table, previous };
+    f()
+}
+
+/// Empties the table, if enabled, before the parsers run on a new
+/// input. Entry points of the parsers must call it.
+pub(crate) fn new_input() {
+    MEMO.with(|memo| {
+        if let Some(ref mut table) = *memo.borrow_mut() {
+            table.clear();
+        }
+    });
+}
+
+/// Returns the part of `i` starting at `offset`, which must not be
+/// before `i`.
+fn span_at(i: StrSpan, offset: usize, line: u32) -> StrSpan {
+    LocatedSpan {
+        offset,
+        line,
+        fragment: CompleteStr(&i.fragment.0[offset - i.offset..]),
+    }
+}
+
+/// Runs `parser` on `i`, or returns its error from the table if it
+/// already failed there.
+pub(crate) fn memoize<'a, F>(
+    rule: Rule,
+    i: StrSpan<'a>,
+    parser: F,
+) -> IResult<StrSpan<'a>, Box<Expression>, u32>
+where
+    F: FnOnce(StrSpan<'a>) -> IResult<StrSpan<'a>, Box<Expression>, u32>,
+{
+    let key = (
+        rule,
+        (i.fragment.0.as_ptr() as usize, i.fragment.0.len(), i.offset),
+    );
+    let cached = MEMO.with(|memo| memo.borrow().as_ref().map(|table| table.get(&key).cloned()));
+    match cached {
+        None => parser(i), // Memoization is disabled
+        Some(Some((offset, line, kind))) => Err(::nom::Err::Error(Context::Code(
+            span_at(i, offset, line),
+            kind,
+        ))),
+        Some(None) => {
+            let res = parser(i);
+            if let Err(::nom::Err::Error(Context::Code(span, ref kind))) = res {
+                // Failures are not backtracked over, so they are not stored
+                if span.offset >= i.offset {
+                    let entry = (span.offset, span.line, kind.clone());
+                    MEMO.with(|memo| {
+                        if let Some(ref mut table) = *memo.borrow_mut() {
+                            table.insert(key, entry);
+                        }
+                    });
+                }
+            }
+            res
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use helpers::make_strspan;
+    use {file_input, parse_module};
+
+    #[test]
+    fn test_nested_calls() {
+        let code = format!("{}x{}\n", "f(g, ".repeat(20), ")".repeat(20));
+        let (rest, ast) = with_memoization(|| file_input(make_strspan(&code))).unwrap();
+        assert_eq!(rest.fragment.0, "");
+        assert_eq!(ast.len(), 1);
+    }
+
+    #[test]
+    fn test_same_result() {
+        let code = "f(a, b=c, *d)(x for x in y)\nif f(x):\n  g(lambda: h(i), j)\nk = (l(m), n)\n";
+        let expected = file_input(make_strspan(code));
+        assert_eq!(
+            with_memoization(|| file_input(make_strspan(code))),
+            expected
+        );
+        let code = "f(a, b c)\n";
+        let expected = file_input(make_strspan(code));
+        assert_eq!(
+            with_memoization(|| file_input(make_strspan(code))),
+            expected
+        );
+    }
+
+    #[test]
+    fn test_new_input() {
+        // `test` fails at offset 2 of the first code, and must not fail
+        // there in the second one, which has the same address and length
+        let mut code = String::new();
+        with_memoization(|| {
+            for line in &["f(),1\n", "f(1)\n\n"] {
+                code.clear();
+                code.push_str(line);
+                let expected = parse_module(line);
+                assert!(expected.is_ok());
+                assert_eq!(parse_module(&code), expected);
+            }
+        });
+    }
+
+    #[test]
+    fn test_scope() {
+        with_memoization(|| {
+            with_memoization(|| ());
+            // Still enabled after the inner scope
+            MEMO.with(|memo| assert!(memo.borrow().is_some()));
+        });
+        MEMO.with(|memo| assert!(memo.borrow().is_none()));
+    }
+}
@@ -0,0 +1,219 @@
+//! Halstead's metrics, computed like radon: the operators are the
+//! arithmetic, bitwise, boolean and comparison operators (including the
+//! ones of augmented assignments), and the operands are the expressions
+//! they apply to.
+
+use std::collections::HashSet;
+
+use ast::*;
+use visitors::walk::{walk_expression, walk_statement, Visitor};
+
+/// Numbers of operators and operands of some code, from which the
+/// other metrics are derived.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Halstead {
+    /// η1, the number of distinct operators.
+    pub distinct_operators: usize,
+    /// η2, the number of distinct operands.
+    pub distinct_operands: usize,
+    /// N1, the number of operators.
+    pub operators: usize,
+    /// N2, the number of operands.
+    pub operands: usize,
+}
+
+/// `x * log2(x)`, which is 0 for `x = 0`.
+fn x_log2(x: usize) -> f64 {
+    if x == 0 {
+        0.
+    } else {
+        x as f64 * (x as f64).log2()
+    }
+}
+
+impl Halstead {
+    /// η = η1 + η2
+    pub fn vocabulary(&self) -> usize {
+        self.distinct_operators + self.distinct_operands
+    }
+
+    /// N = N1 + N2
+    pub fn length(&self) -> usize {
+        self.operators + self.operands
+    }
+
+    /// η1 log2(η1) + η2 log2(η2)
+    pub fn calculated_length(&self) -> f64 {
+        x_log2(self.distinct_operators) + x_log2(self.distinct_operands)
+    }
+
+    /// V = N log2(η)
+    pub fn volume(&self) -> f64 {
+        match self.vocabulary() {
+            0 => 0.,
+            vocabulary => self.length() as f64 * (vocabulary as f64).log2(),
+        }
+    }
+
+    /// D = η1 / 2 × N2 / η2
+    pub fn difficulty(&self) -> f64 {
+        match self.distinct_operands {
+            0 => 0.,
+            distinct => {
+                self.distinct_operators as f64 / 2. * self.operands as f64 / distinct as f64
+            }
+        }
+    }
+
+    /// E = D × V
+    pub fn effort(&self) -> f64 {
+        self.difficulty() * self.volume()
+    }
+
+    /// T = E / 18, in seconds.
+    pub fn time(&self) -> f64 {
+        self.effort() / 18.
+    }
+
+    /// B = V / 3000, the estimated number of bugs.
+    pub fn bugs(&self) -> f64 {
+        self.volume() / 3000.
+    }
+}
+
+/// Collects the operators and operands of some code.
+struct HalsteadCounter {
+    /// Whether to skip the nested functions and classes.
+    skip_definitions: bool,
+    operators: HashSet<String>,
+    /// Operands, by their debug representation.
+    operands: HashSet<String>,
+    halstead: Halstead,
+}
+
+impl HalsteadCounter {
+    fn new(skip_definitions: bool) -> HalsteadCounter {
+        HalsteadCounter {
+            skip_definitions,
+            operators: HashSet::new(),
+            operands: HashSet::new(),
+            halstead: Halstead::default(),
+        }
+    }
+
+    fn add_operator(&mut self, operator: String) {
+        self.halstead.operators += 1;
+        self.operators.insert(operator);
+    }
+
+    fn add_operand(&mut self, operand: &Expression) {
+        self.halstead.operands += 1;
+        self.operands.insert(format!("{:?}", operand));
+    }
+
+    fn finish(self) -> Halstead {
+        Halstead {
+            distinct_operators: self.operators.len(),
+            distinct_operands: self.operands.len(),
+            ..self.halstead
+        }
+    }
+}
+
+impl Visitor for HalsteadCounter {
+    fn visit_statement(&mut self, stmt: &Statement) {
+        match *stmt {
+            Statement::AugmentedAssignment(ref lhs, op, ref rhs) => {
+                // Like in radon, `x += 1` has the operator of `x + 1`
+                self.add_operator(op.to_string().trim_end_matches('=').to_string());
+                for operand in lhs.iter().chain(rhs) {
+                    self.add_operand(operand);
+                }
+            }
+            Statement::Compound(ref compound) if self.skip_definitions => match **compound {
+                CompoundStatement::Funcdef(_) | CompoundStatement::Classdef(_) => return,
+                _ => (),
+            },
+            _ => (),
+        }
+        walk_statement(self, stmt)
+    }
+
+    fn visit_expression(&mut self, expr: &Expression) {
+        match *expr {
+            Expression::Bop(op, ref left, ref right) => {
+                self.add_operator(op.to_string());
+                self.add_operand(left);
+                self.add_operand(right);
+            }
+            Expression::MultiBop(ref first, ref rest) => {
+                self.add_operand(first);
+                for (op, operand) in rest {
+                    self.add_operator(op.to_string());
+                    self.add_operand(operand);
+                }
+            }
+            Expression::Uop(op, ref operand) => {
+                self.add_operator(format!("unary {}", op.to_string().trim_end()));
+                self.add_operand(operand);
+            }
+            _ => (),
+        }
+        walk_expression(self, expr)
+    }
+}
+
+/// Returns the Halstead metrics of a function body, without its nested
+/// functions and classes.
+pub(crate) fn body_halstead(stmts: &[Statement]) -> Halstead {
+    let mut counter = HalsteadCounter::new(true);
+    for stmt in stmts {
+        counter.visit_statement(stmt);
+    }
+    counter.finish()
+}
+
+/// Returns the Halstead metrics of a module, including its functions
+/// and classes. The ones of each function are in `FunctionMetrics`.
+///
+/// ```
+/// use python_parser::metrics::halstead::module_halstead;
+/// use python_parser::parse_module;
+///
+/// let module = parse_module("x = a + b * 2\nif not x > a:\n    x += 2\n").unwrap();
+/// let halstead = module_halstead(&module.body);
+/// assert_eq!(halstead.distinct_operators, 4);
+/// assert_eq!(halstead.operators, 5);
+/// assert_eq!((halstead.distinct_operands, halstead.operands), (6, 9));
+/// ```
+pub fn module_halstead(stmts: &[Statement]) -> Halstead {
+    let mut counter = HalsteadCounter::new(false);
+    for stmt in stmts {
+        counter.visit_statement(stmt);
+    }
+    counter.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_derived() {
+        let halstead = Halstead {
+            distinct_operators: 4,
+            distinct_operands: 8,
+            operators: 10,
+            operands: 16,
+        };
+        assert_eq!(halstead.vocabulary(), 12);
+        assert_eq!(halstead.length(), 26);
+        assert_eq!(halstead.calculated_length(), 32.);
+        assert!((halstead.volume() - 93.209).abs() < 1e-3);
+        assert_eq!(halstead.difficulty(), 4.);
+        assert!((halstead.effort() - 372.836).abs() < 1e-3);
+        assert!((halstead.time() - 20.713).abs() < 1e-3);
+        assert_eq!(Halstead::default().volume(), 0.);
+        assert_eq!(Halstead::default().difficulty(), 0.);
+    }
+}
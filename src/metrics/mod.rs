@@ -0,0 +1,363 @@
+//! Metrics of the functions of a module: cyclomatic complexity, length,
+//! number of arguments, nesting of their blocks and Halstead's metrics.
+//!
+//! Lines are found from the tokens of the code, since the AST has no
+//! positions.
+
+pub mod halstead;
+pub mod sloc;
+pub mod thresholds;
+
+use ast::*;
+use metrics::halstead::{body_halstead, Halstead};
+use tokenizer::{tokenize, TokenKind};
+use visitors::walk::{walk_expression, walk_statement, Visitor};
+
+/// Metrics of a function (or method), found by `function_metrics`.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct FunctionMetrics {
+    /// Name of the function, prefixed with the names of the classes and
+    /// functions it is defined in, like `"C.method"` or `"outer.inner"`.
+    pub name: String,
+    /// Line of the `def` keyword, starting from 1.
+    pub line: u32,
+    /// Last line of the body, not including the comments and blank
+    /// lines after it.
+    pub end_line: u32,
+    /// McCabe's cyclomatic complexity: 1 plus the number of branches
+    /// (`if` and `elif`, loops, `except` clauses, `and` and `or`
+    /// operators, conditional expressions, and the `for` and `if` of
+    /// comprehensions). Nested functions and classes are not counted.
+    pub complexity: usize,
+    /// Number of parameters, including `*args` and `**kwargs`.
+    pub arguments: usize,
+    /// Largest number of nested blocks in the body, eg. 2 for a loop in
+    /// an `if`. Nested functions and classes are not counted.
+    pub nesting: usize,
+    /// Number of statements in the body, including the ones of blocks
+    /// but not of nested functions and classes.
+    pub statements: usize,
+    /// Operators and operands of the body, without the ones of nested
+    /// functions and classes.
+    pub halstead: Halstead,
+}
+
+impl FunctionMetrics {
+    /// Number of lines from the `def` keyword to the end of the body.
+    pub fn length(&self) -> usize {
+        (self.end_line + 1).saturating_sub(self.line) as usize
+    }
+}
+
+/// Counts the branches of a function body.
+struct ComplexityCounter {
+    complexity: usize,
+}
+
+impl Visitor for ComplexityCounter {
+    fn visit_statement(&mut self, stmt: &Statement) {
+        if let Statement::Compound(ref compound) = *stmt {
+            match **compound {
+                CompoundStatement::Funcdef(_) | CompoundStatement::Classdef(_) => return,
+                CompoundStatement::If(ref cond_blocks, _) => self.complexity += cond_blocks.len(),
+                CompoundStatement::For { .. } | CompoundStatement::While(..) => {
+                    self.complexity += 1
+                }
+                CompoundStatement::Try(ref try_) => {
+                    self.complexity += try_.except_clauses.len();
+                    if !try_.last_except.is_empty() {
+                        self.complexity += 1;
+                    }
+                }
+                CompoundStatement::With(..) => (),
+            }
+        }
+        walk_statement(self, stmt)
+    }
+
+    fn visit_expression(&mut self, expr: &Expression) {
+        match *expr {
+            Expression::Bop(Bop::And, _, _) | Expression::Bop(Bop::Or, _, _) => {
+                self.complexity += 1
+            }
+            Expression::MultiBop(_, ref rest) => {
+                self.complexity += rest
+                    .iter()
+                    .filter(|(bop, _)| *bop == Bop::And || *bop == Bop::Or)
+                    .count()
+            }
+            Expression::Ternary(..) => self.complexity += 1,
+            Expression::DictComp(_, ref chunks)
+            | Expression::SetComp(_, ref chunks)
+            | Expression::ListComp(_, ref chunks)
+            | Expression::Generator(_, ref chunks) => self.complexity += chunks.len(),
+            _ => (),
+        }
+        walk_expression(self, expr)
+    }
+}
+
+/// Returns the blocks of a compound statement, except the ones of
+/// functions and classes.
+fn blocks(stmt: &CompoundStatement) -> Vec<&[Statement]> {
+    match *stmt {
+        CompoundStatement::If(ref cond_blocks, ref else_block) => cond_blocks
+            .iter()
+            .map(|(_, block)| &block[..])
+            .chain(else_block.as_ref().map(|block| &block[..]))
+            .collect(),
+        CompoundStatement::For {
+            ref for_block,
+            ref else_block,
+            ..
+        } => Some(&for_block[..])
+            .into_iter()
+            .chain(else_block.as_ref().map(|block| &block[..]))
+            .collect(),
+        CompoundStatement::While(_, ref block, ref else_block) => Some(&block[..])
+            .into_iter()
+            .chain(else_block.as_ref().map(|block| &block[..]))
+            .collect(),
+        CompoundStatement::With(_, ref block) => vec![block],
+        CompoundStatement::Try(ref try_) => Some(&try_.try_block[..])
+            .into_iter()
+            .chain(try_.except_clauses.iter().map(|(_, _, block)| &block[..]))
+            .chain(vec![
+                &try_.last_except[..],
+                &try_.else_block,
+                &try_.finally_block,
+            ])
+            .filter(|block| !block.is_empty())
+            .collect(),
+        CompoundStatement::Funcdef(_) | CompoundStatement::Classdef(_) => Vec::new(),
+    }
+}
+
+/// Returns the number of statements of a block and its largest number
+/// of nested blocks.
+fn count_statements(stmts: &[Statement]) -> (usize, usize) {
+    let mut statements = 0;
+    let mut nesting = 0;
+    for stmt in stmts {
+        statements += 1;
+        if let Statement::Compound(ref compound) = *stmt {
+            for block in blocks(compound) {
+                let (block_statements, block_nesting) = count_statements(block);
+                statements += block_statements;
+                nesting = nesting.max(block_nesting + 1);
+            }
+        }
+    }
+    (statements, nesting)
+}
+
+fn count_params(params: &TypedArgsList) -> usize {
+    let star_args = match params.star_args {
+        StarParams::Named(_) => 1,
+        StarParams::No | StarParams::Anonymous => 0,
+    };
+    params.posonly_args.len()
+        + params.args.len()
+        + star_args
+        + params.keyword_args.len()
+        + params.star_kwargs.iter().count()
+}
+
+/// Adds the metrics of the functions of `stmts` to `metrics`, in the
+/// order of the code, with the lines left to 0.
+fn collect_functions(stmts: &[Statement], prefix: &str, metrics: &mut Vec<FunctionMetrics>) {
+    for stmt in stmts {
+        let compound = match *stmt {
+            Statement::Compound(ref compound) => compound,
+            _ => continue,
+        };
+        match **compound {
+            CompoundStatement::Funcdef(ref funcdef) => {
+                let mut counter = ComplexityCounter { complexity: 1 };
+                for stmt in &funcdef.code {
+                    counter.visit_statement(stmt);
+                }
+                let (statements, nesting) = count_statements(&funcdef.code);
+                let name = format!("{}{}", prefix, funcdef.name);
+                metrics.push(FunctionMetrics {
+                    name: name.clone(),
+                    line: 0,
+                    end_line: 0,
+                    complexity: counter.complexity,
+                    arguments: count_params(&funcdef.parameters),
+                    nesting,
+                    statements,
+                    halstead: body_halstead(&funcdef.code),
+                });
+                collect_functions(&funcdef.code, &format!("{}.", name), metrics);
+            }
+            CompoundStatement::Classdef(ref classdef) => {
+                let prefix = format!("{}{}.", prefix, classdef.name);
+                collect_functions(&classdef.code, &prefix, metrics);
+            }
+            ref compound => {
+                for block in blocks(compound) {
+                    collect_functions(block, prefix, metrics);
+                }
+            }
+        }
+    }
+}
+
+/// Returns the first and last lines of the functions of some code, in
+/// the order of their `def` keywords.
+fn function_lines(code: &str) -> Vec<(u32, u32)> {
+    let mut lines: Vec<(u32, u32)> = Vec::new();
+    // Indexes in `lines` of the functions whose body is not finished,
+    // with the indentation depth of their `def`
+    let mut open: Vec<(usize, usize)> = Vec::new();
+    // Function whose header is not finished, and function whose header
+    // is finished but may be followed by an indented body
+    let mut header = None;
+    let mut body = None;
+    let mut depth = 0;
+    let mut last_line = 1;
+    for token in tokenize(code) {
+        match token.kind {
+            TokenKind::Indent | TokenKind::Comment | TokenKind::Nl => (),
+            _ => {
+                // The body is on the line of the header, like in
+                // `def f(): pass`
+                if body.take().is_some() {
+                    if let Some((i, _)) = open.pop() {
+                        lines[i] = (lines[i].0, last_line);
+                    }
+                }
+            }
+        }
+        match token.kind {
+            TokenKind::Name if token.text == "def" => {
+                open.push((lines.len(), depth));
+                header = Some(lines.len());
+                lines.push((token.line, token.line));
+            }
+            TokenKind::Newline => {
+                last_line = token.line;
+                body = header.take();
+            }
+            TokenKind::Indent => {
+                depth += 1;
+                body = None;
+            }
+            TokenKind::Dedent => {
+                depth -= 1;
+                while open.last().is_some_and(|&(_, d)| d >= depth) {
+                    let (i, _) = open.pop().unwrap_or_default();
+                    lines[i] = (lines[i].0, last_line);
+                }
+            }
+            TokenKind::EndMarker => {
+                for (i, _) in open.drain(..) {
+                    lines[i] = (lines[i].0, last_line);
+                }
+            }
+            _ => (),
+        }
+    }
+    lines
+}
+
+/// Returns the metrics of the functions and methods of some code, whose
+/// statements are `stmts`, in the order of the code.
+///
+/// ```
+/// use python_parser::metrics::function_metrics;
+/// use python_parser::parse_module;
+///
+/// let code = "class C:\n    def f(self, x):\n        if x and self.y:\n            return 1\n        return 2\n";
+/// let module = parse_module(code).unwrap();
+/// let metrics = function_metrics(code, &module.body);
+/// assert_eq!(metrics[0].name, "C.f");
+/// assert_eq!((metrics[0].line, metrics[0].end_line), (2, 5));
+/// assert_eq!(metrics[0].complexity, 3);
+/// assert_eq!(metrics[0].arguments, 2);
+/// ```
+pub fn function_metrics(code: &str, stmts: &[Statement]) -> Vec<FunctionMetrics> {
+    let mut metrics = Vec::new();
+    collect_functions(stmts, "", &mut metrics);
+    for (function, (line, end_line)) in metrics.iter_mut().zip(function_lines(code)) {
+        function.line = line;
+        function.end_line = end_line;
+    }
+    metrics
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parse_module;
+
+    fn metrics(code: &str) -> Vec<FunctionMetrics> {
+        function_metrics(code, &parse_module(code).unwrap().body)
+    }
+
+    #[test]
+    fn test_lines() {
+        let code = "@decorator
+def f(a,
+      b): pass
+class C:
+    def g(self):
+        x = 1
+
+        def h(): return 2
+        # comment
+    # comment
+
+    async def i(*args, **kwargs):
+        return [
+            1]
+def j(): pass
+";
+        let summary: Vec<_> = metrics(code)
+            .into_iter()
+            .map(|m| (m.name, m.line, m.end_line, m.arguments))
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                ("f".to_string(), 2, 3, 2),
+                ("C.g".to_string(), 5, 8, 1),
+                ("C.g.h".to_string(), 8, 8, 0),
+                ("C.i".to_string(), 12, 14, 2),
+                ("j".to_string(), 15, 15, 0),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_complexity() {
+        let code = "def f(x, *, y=1):
+    if x or y and z:
+        for a in x:
+            while a:
+                a = [b for b in a if b]
+    elif y:
+        pass
+    else:
+        try:
+            return 1 if x else 2
+        except E:
+            pass
+        except:
+            pass
+    def g():
+        if x: pass
+    with x:
+        pass
+";
+        let m = &metrics(code)[0];
+        assert_eq!(m.complexity, 12);
+        assert_eq!(m.arguments, 2);
+        assert_eq!(m.nesting, 3);
+        assert_eq!(m.statements, 12);
+        assert_eq!(m.length(), 18);
+        assert_eq!(m.halstead.operators, 2);
+        assert_eq!(m.halstead.distinct_operands, 4);
+    }
+}
@@ -0,0 +1,290 @@
+//! Size of the code of a module and of its functions, in lines of
+//! several kinds and in statements.
+
+use ast::*;
+use doctest::docstring;
+use metrics::{blocks, function_metrics};
+use tokenizer::{line_breaks, tokenize, Token, TokenKind};
+use visitors::walk::{walk_statement, Visitor};
+
+/// Size of a part of the code.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct CodeSize {
+    /// Number of lines.
+    pub physical: usize,
+    /// Number of logical lines, which may span several physical lines
+    /// (eg. in brackets) and have several statements (separated by
+    /// `;`).
+    pub logical: usize,
+    /// Number of lines with code, other than docstrings.
+    pub source: usize,
+    /// Number of lines with a comment (alone or after code).
+    pub comments: usize,
+    /// Number of lines with only whitespace, outside of strings.
+    pub blank: usize,
+    /// Number of lines of docstrings of the module, classes and
+    /// functions.
+    pub docstrings: usize,
+    /// Number of statements, including the ones of blocks.
+    pub statements: usize,
+}
+
+/// Size of a function, in `SizeReport`.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct FunctionSize {
+    /// Name of the function (see `FunctionMetrics::name`).
+    pub name: String,
+    /// Lines from the `def` keyword to the end of the body, including
+    /// the ones of nested functions, and statements of the body except
+    /// the ones of nested functions and classes.
+    pub size: CodeSize,
+}
+
+/// Size of a module and of its functions, returned by `size_report`.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct SizeReport {
+    pub module: CodeSize,
+    /// Functions and methods in the order of the code.
+    pub functions: Vec<FunctionSize>,
+}
+
+/// What a physical line has.
+#[derive(Copy, Clone, Debug, Default)]
+struct LineKind {
+    code: bool,
+    comment: bool,
+    docstring: bool,
+    /// Whether the line has a token, possibly a string starting on a
+    /// previous line.
+    covered: bool,
+    /// Whether a logical line ends on this line.
+    logical_end: bool,
+}
+
+struct StatementCounter {
+    statements: usize,
+}
+
+impl Visitor for StatementCounter {
+    fn visit_statement(&mut self, stmt: &Statement) {
+        self.statements += 1;
+        walk_statement(self, stmt)
+    }
+}
+
+/// Adds whether the bodies of the classes and functions of `stmts`
+/// start with a docstring to `has_docstring`, in the order of the code.
+fn collect_docstrings(stmts: &[Statement], has_docstring: &mut Vec<bool>) {
+    for stmt in stmts {
+        let compound = match *stmt {
+            Statement::Compound(ref compound) => compound,
+            _ => continue,
+        };
+        match **compound {
+            CompoundStatement::Funcdef(Funcdef { ref code, .. })
+            | CompoundStatement::Classdef(Classdef { ref code, .. }) => {
+                has_docstring.push(docstring(code).is_some());
+                collect_docstrings(code, has_docstring);
+            }
+            ref compound => {
+                for block in blocks(compound) {
+                    collect_docstrings(block, has_docstring);
+                }
+            }
+        }
+    }
+}
+
+/// Whether the tokens of a simple statement start a class or function
+/// definition, whose body starts after the first `:` outside brackets.
+fn is_definition(statement: &[Token]) -> bool {
+    let keyword = match statement.first() {
+        Some(token) if token.text == "async" => statement.get(1),
+        first => first,
+    };
+    keyword.is_some_and(|t| t.kind == TokenKind::Name && (t.text == "def" || t.text == "class"))
+}
+
+/// Classifies the lines of some code, whose statements are `stmts`.
+fn line_kinds(code: &str, stmts: &[Statement]) -> Vec<LineKind> {
+    // Lines start from 1, and there is a last line after the last line
+    // break
+    let mut lines = vec![LineKind::default(); line_breaks(code) as usize + 2];
+    let mut has_docstring = vec![docstring(stmts).is_some()];
+    collect_docstrings(stmts, &mut has_docstring);
+    let mut has_docstring = has_docstring.into_iter();
+
+    let mut statement: Vec<Token> = Vec::new();
+    let mut depth = 0usize;
+    // Whether the current statement is the first one of the module, or
+    // of the body of a class or function
+    let mut first_of_body = true;
+    let mut finish = |statement: &mut Vec<Token>, first_of_body: &mut bool| {
+        if statement.is_empty() {
+            return;
+        }
+        let docstring = *first_of_body && has_docstring.next().unwrap_or(false);
+        for token in statement.drain(..) {
+            let end = token.line + line_breaks(token.text);
+            for line in &mut lines[token.line as usize..=end as usize] {
+                if docstring {
+                    line.docstring = true;
+                } else {
+                    line.code = true;
+                }
+            }
+        }
+        *first_of_body = false;
+    };
+    for token in tokenize(code) {
+        match token.kind {
+            TokenKind::Newline | TokenKind::EndMarker => finish(&mut statement, &mut first_of_body),
+            TokenKind::Nl | TokenKind::Indent | TokenKind::Dedent | TokenKind::Comment => (),
+            TokenKind::Op if token.text == ";" => finish(&mut statement, &mut first_of_body),
+            TokenKind::Op if token.text == ":" && depth == 0 && is_definition(&statement) => {
+                statement.push(token);
+                finish(&mut statement, &mut first_of_body);
+                first_of_body = true;
+            }
+            _ => {
+                match token.text {
+                    "(" | "[" | "{" if token.kind == TokenKind::Op => depth += 1,
+                    ")" | "]" | "}" if token.kind == TokenKind::Op => {
+                        depth = depth.saturating_sub(1)
+                    }
+                    _ => (),
+                }
+                statement.push(token);
+            }
+        }
+    }
+    // Comments, blank lines and ends of logical lines
+    for token in tokenize(code) {
+        match token.kind {
+            TokenKind::Comment => lines[token.line as usize].comment = true,
+            TokenKind::Newline => lines[token.line as usize].logical_end = true,
+            TokenKind::Nl | TokenKind::Indent | TokenKind::Dedent | TokenKind::EndMarker => {
+                continue
+            }
+            _ => (),
+        }
+        if token.kind != TokenKind::Newline {
+            let end = token.line + line_breaks(token.text);
+            for line in &mut lines[token.line as usize..=end as usize] {
+                line.covered = true;
+            }
+        }
+    }
+    // The last line is only counted if it is not empty
+    if code.is_empty() || code.ends_with(&['\n', '\r'][..]) {
+        lines.pop();
+    }
+    lines
+}
+
+/// Counts the kinds of the lines from `first` to `last`.
+fn count_lines(lines: &[LineKind], first: usize, last: usize) -> CodeSize {
+    let lines = lines.get(first..=last.min(lines.len() - 1)).unwrap_or(&[]);
+    CodeSize {
+        physical: lines.len(),
+        logical: lines.iter().filter(|l| l.logical_end).count(),
+        source: lines.iter().filter(|l| l.code).count(),
+        comments: lines.iter().filter(|l| l.comment).count(),
+        blank: lines.iter().filter(|l| !l.covered).count(),
+        docstrings: lines.iter().filter(|l| l.docstring).count(),
+        statements: 0,
+    }
+}
+
+/// Returns the size of some code, whose statements are `stmts`, and of
+/// its functions and methods.
+///
+/// ```
+/// use python_parser::metrics::sloc::size_report;
+/// use python_parser::parse_module;
+///
+/// let code = "\"\"\"Module.\"\"\"\n\n# Comment\ndef f(x):\n    '''Doc\n    string.'''\n    return (x +\n            1)  # Add\n";
+/// let module = parse_module(code).unwrap();
+/// let report = size_report(code, &module.body);
+/// assert_eq!(report.module.physical, 8);
+/// assert_eq!(report.module.logical, 4);
+/// assert_eq!(report.module.source, 3);
+/// assert_eq!(report.module.comments, 2);
+/// assert_eq!(report.module.blank, 1);
+/// assert_eq!(report.module.docstrings, 3);
+/// assert_eq!(report.functions[0].size.physical, 5);
+/// ```
+pub fn size_report(code: &str, stmts: &[Statement]) -> SizeReport {
+    let lines = line_kinds(code, stmts);
+    let mut counter = StatementCounter { statements: 0 };
+    for stmt in stmts {
+        counter.visit_statement(stmt);
+    }
+    let module = CodeSize {
+        statements: counter.statements,
+        ..count_lines(&lines, 1, lines.len())
+    };
+    let functions = function_metrics(code, stmts)
+        .into_iter()
+        .map(|function| FunctionSize {
+            size: CodeSize {
+                statements: function.statements,
+                ..count_lines(&lines, function.line as usize, function.end_line as usize)
+            },
+            name: function.name,
+        })
+        .collect();
+    SizeReport { module, functions }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parse_module;
+
+    #[test]
+    fn test_docstrings() {
+        let code = "class C:
+    'Doc'; x = 1
+    def f(self): '''Doc
+    string'''
+    def g(self):
+        f'not {a} docstring'
+if x:
+    def h():
+
+        \"Doc\"
+        return '''
+        s'''
+";
+        let report = size_report(code, &parse_module(code).unwrap().body);
+        assert_eq!(
+            report.module,
+            CodeSize {
+                physical: 12,
+                logical: 9,
+                source: 9,
+                comments: 0,
+                blank: 1,
+                docstrings: 4,
+                statements: 11,
+            }
+        );
+        let functions: Vec<_> = report
+            .functions
+            .iter()
+            .map(|f| {
+                (
+                    &f.name[..],
+                    f.size.physical,
+                    f.size.docstrings,
+                    f.size.statements,
+                )
+            })
+            .collect();
+        assert_eq!(
+            functions,
+            [("C.f", 2, 2, 1), ("C.g", 2, 0, 1), ("h", 5, 1, 2)]
+        );
+    }
+}
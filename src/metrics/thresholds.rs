@@ -0,0 +1,246 @@
+//! A checker of the functions exceeding thresholds on their metrics,
+//! with a JSON report for continuous integration.
+
+use std::fmt;
+
+use json::Json;
+use metrics::FunctionMetrics;
+
+/// Largest values allowed for the metrics of functions, or `None` to
+/// not check a metric.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Thresholds {
+    pub max_complexity: Option<usize>,
+    pub max_length: Option<usize>,
+    pub max_arguments: Option<usize>,
+    pub max_nesting: Option<usize>,
+    pub max_statements: Option<usize>,
+}
+
+/// The defaults of mccabe (`--max-complexity=10`) and pylint, without
+/// a maximal length.
+impl Default for Thresholds {
+    fn default() -> Thresholds {
+        Thresholds {
+            max_complexity: Some(10),
+            max_length: None,
+            max_arguments: Some(5),
+            max_nesting: Some(5),
+            max_statements: Some(50),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Metric {
+    Complexity,
+    Length,
+    Arguments,
+    Nesting,
+    Statements,
+}
+
+impl Metric {
+    /// Name of the metric in reports, like `"complexity"`.
+    pub fn name(self) -> &'static str {
+        match self {
+            Metric::Complexity => "complexity",
+            Metric::Length => "length",
+            Metric::Arguments => "arguments",
+            Metric::Nesting => "nesting",
+            Metric::Statements => "statements",
+        }
+    }
+}
+
+impl fmt::Display for Metric {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// A metric of a function exceeding its threshold.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Violation {
+    /// Name of the function (see `FunctionMetrics::name`).
+    pub function: String,
+    /// Line of the function.
+    pub line: u32,
+    pub metric: Metric,
+    pub value: usize,
+    pub threshold: usize,
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "function '{}' has a {} of {} (more than {}) at line {}",
+            self.function, self.metric, self.value, self.threshold, self.line
+        )
+    }
+}
+
+/// Returns the metrics of `metrics` exceeding the thresholds, in the
+/// order of the functions.
+///
+/// ```
+/// use python_parser::metrics::function_metrics;
+/// use python_parser::metrics::thresholds::{check_thresholds, Thresholds};
+/// use python_parser::parse_module;
+///
+/// let code = "def f(a, b, c):\n    return a or b or c\n";
+/// let module = parse_module(code).unwrap();
+/// let thresholds = Thresholds {
+///     max_complexity: Some(2),
+///     ..Thresholds::default()
+/// };
+/// let violations = check_thresholds(&function_metrics(code, &module.body), &thresholds);
+/// assert_eq!(violations[0].to_string(),
+///     "function 'f' has a complexity of 3 (more than 2) at line 1");
+/// ```
+pub fn check_thresholds(metrics: &[FunctionMetrics], thresholds: &Thresholds) -> Vec<Violation> {
+    let mut violations = Vec::new();
+    for function in metrics {
+        let checks = [
+            (
+                Metric::Complexity,
+                function.complexity,
+                thresholds.max_complexity,
+            ),
+            (Metric::Length, function.length(), thresholds.max_length),
+            (
+                Metric::Arguments,
+                function.arguments,
+                thresholds.max_arguments,
+            ),
+            (Metric::Nesting, function.nesting, thresholds.max_nesting),
+            (
+                Metric::Statements,
+                function.statements,
+                thresholds.max_statements,
+            ),
+        ];
+        for &(metric, value, threshold) in &checks {
+            match threshold {
+                Some(threshold) if value > threshold => violations.push(Violation {
+                    function: function.name.clone(),
+                    line: function.line,
+                    metric,
+                    value,
+                    threshold,
+                }),
+                _ => (),
+            }
+        }
+    }
+    violations
+}
+
+fn number(n: usize) -> Json {
+    Json::Number(n as f64)
+}
+
+/// Returns a JSON report of the metrics of the functions and of the
+/// violations of the thresholds, like
+/// `{"passed":false,"functions":[{"name":"f","line":1,...}],"violations":[...]}`,
+/// where `passed` is whether there is no violation.
+pub fn json_report(metrics: &[FunctionMetrics], violations: &[Violation]) -> String {
+    let functions = metrics
+        .iter()
+        .map(|function| {
+            Json::Object(vec![
+                ("name".to_string(), Json::String(function.name.clone())),
+                ("line".to_string(), number(function.line as usize)),
+                ("end_line".to_string(), number(function.end_line as usize)),
+                ("complexity".to_string(), number(function.complexity)),
+                ("length".to_string(), number(function.length())),
+                ("arguments".to_string(), number(function.arguments)),
+                ("nesting".to_string(), number(function.nesting)),
+                ("statements".to_string(), number(function.statements)),
+                (
+                    "volume".to_string(),
+                    Json::Number(function.halstead.volume()),
+                ),
+                (
+                    "difficulty".to_string(),
+                    Json::Number(function.halstead.difficulty()),
+                ),
+                (
+                    "effort".to_string(),
+                    Json::Number(function.halstead.effort()),
+                ),
+            ])
+        })
+        .collect();
+    let violations_json = violations
+        .iter()
+        .map(|violation| {
+            Json::Object(vec![
+                (
+                    "function".to_string(),
+                    Json::String(violation.function.clone()),
+                ),
+                ("line".to_string(), number(violation.line as usize)),
+                (
+                    "metric".to_string(),
+                    Json::String(violation.metric.name().to_string()),
+                ),
+                ("value".to_string(), number(violation.value)),
+                ("threshold".to_string(), number(violation.threshold)),
+            ])
+        })
+        .collect();
+    Json::Object(vec![
+        ("passed".to_string(), Json::Bool(violations.is_empty())),
+        ("functions".to_string(), Json::Array(functions)),
+        ("violations".to_string(), Json::Array(violations_json)),
+    ])
+    .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use metrics::function_metrics;
+    use parse_module;
+
+    #[test]
+    fn test_report() {
+        let code = "def f(a, b, c, d, e, f):\n    if a:\n        if b:\n            pass\n\
+                    def g(): pass\n";
+        let metrics = function_metrics(code, &parse_module(code).unwrap().body);
+        let thresholds = Thresholds {
+            max_nesting: Some(1),
+            max_length: Some(3),
+            ..Thresholds::default()
+        };
+        let violations = check_thresholds(&metrics, &thresholds);
+        let summary: Vec<_> = violations
+            .iter()
+            .map(|v| (&v.function[..], v.metric, v.value, v.threshold))
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                ("f", Metric::Length, 4, 3),
+                ("f", Metric::Arguments, 6, 5),
+                ("f", Metric::Nesting, 2, 1),
+            ]
+        );
+        let report = Json::parse(&json_report(&metrics, &violations)).unwrap();
+        assert_eq!(report.get("passed"), Some(&Json::Bool(false)));
+        let functions = report.get("functions").and_then(Json::as_array).unwrap();
+        assert_eq!(functions[1].get("name").and_then(Json::as_str), Some("g"));
+        assert_eq!(functions[1].get("line"), Some(&Json::Number(5.)));
+        let violations = report.get("violations").and_then(Json::as_array).unwrap();
+        assert_eq!(violations.len(), 3);
+        assert_eq!(
+            violations[2].to_string(),
+            r#"{"function":"f","line":1,"metric":"nesting","value":2,"threshold":1}"#
+        );
+
+        let report = json_report(&metrics[1..], &check_thresholds(&metrics[1..], &thresholds));
+        assert!(report.starts_with(r#"{"passed":true,"#));
+    }
+}
@@ -0,0 +1,282 @@
+//! Parsing of the code cells of Jupyter notebooks (`.ipynb` files), to
+//! build linters of notebooks.
+//!
+//! IPython's syntax extensions (magics like `%time f()` or `%%bash`,
+//! shell commands like `!ls` or `files = !ls`, and help like `f?`) are
+//! masked before parsing, see `mask_magics`.
+
+use std::fmt;
+
+use ast::Module;
+use errors::ParseError;
+use json::Json;
+use stream::LineScanner;
+use tolerant::parse_module_tolerant;
+
+/// Cell magics whose body is Python code.
+const PYTHON_CELL_MAGICS: [&str; 6] = ["time", "timeit", "capture", "prun", "debug", "python"];
+
+/// Error returned when a notebook cannot be read.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum NotebookError {
+    /// The file is not valid JSON; this is the offset of the error.
+    InvalidJson(usize),
+    /// The file is valid JSON, but not a notebook of a version that can
+    /// be read (nbformat 4).
+    InvalidNotebook(&'static str),
+}
+
+impl fmt::Display for NotebookError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            NotebookError::InvalidJson(offset) => write!(f, "invalid JSON at offset {}", offset),
+            NotebookError::InvalidNotebook(reason) => write!(f, "invalid notebook: {}", reason),
+        }
+    }
+}
+
+impl ::std::error::Error for NotebookError {}
+
+/// A code cell of a notebook.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Cell {
+    /// Index of the cell among all the cells of the notebook (including
+    /// Markdown cells), from 0.
+    pub index: usize,
+    pub source: String,
+}
+
+/// A code cell, parsed.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ParsedCell {
+    pub index: usize,
+    /// The code of the cell, where the statements that could not be
+    /// parsed are `Statement::Invalid` (see
+    /// `tolerant::parse_module_tolerant`).
+    pub module: Module,
+}
+
+/// A syntax error in a cell.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct NotebookDiagnostic {
+    /// Index of the cell (see `Cell::index`).
+    pub cell: usize,
+    /// The error, whose offset and line are in the source of the cell.
+    pub error: ParseError,
+}
+
+impl fmt::Display for NotebookDiagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "cell {}: {}", self.cell, self.error)
+    }
+}
+
+/// A notebook parsed by `parse_notebook`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ParsedNotebook {
+    pub cells: Vec<ParsedCell>,
+    /// The syntax errors of all the cells, in the order of the cells.
+    pub diagnostics: Vec<NotebookDiagnostic>,
+}
+
+/// Returns the code cells of a notebook, given the JSON of its file.
+pub fn code_cells(notebook: &str) -> Result<Vec<Cell>, NotebookError> {
+    let json = Json::parse(notebook).map_err(NotebookError::InvalidJson)?;
+    match json.get("nbformat") {
+        Some(&Json::Number(version)) if version >= 4. => (),
+        _ => return Err(NotebookError::InvalidNotebook("nbformat 4 is required")),
+    }
+    let cells = json
+        .get("cells")
+        .and_then(Json::as_array)
+        .ok_or(NotebookError::InvalidNotebook("no cells"))?;
+    let mut code_cells = Vec::new();
+    for (index, cell) in cells.iter().enumerate() {
+        if cell.get("cell_type").and_then(Json::as_str) != Some("code") {
+            continue;
+        }
+        // The source is either a string or a list of lines
+        let source = match cell.get("source") {
+            Some(Json::String(source)) => source.clone(),
+            Some(Json::Array(lines)) => lines
+                .iter()
+                .map(Json::as_str)
+                .collect::<Option<String>>()
+                .ok_or(NotebookError::InvalidNotebook("invalid source of a cell"))?,
+            _ => return Err(NotebookError::InvalidNotebook("invalid source of a cell")),
+        };
+        code_cells.push(Cell { index, source });
+    }
+    Ok(code_cells)
+}
+
+/// Whether the rest of a line after its indentation is IPython syntax.
+fn is_magic(line: &str) -> bool {
+    let line = line.trim_end();
+    line.starts_with(&['%', '!', '?'][..]) || (line.ends_with('?') && !line.contains('#'))
+}
+
+/// Returns the length of the assignment target of `x = %magic` or
+/// `x = !command`, including the `=`.
+fn magic_assignment(line: &str) -> Option<usize> {
+    let equal = line.find('=')?;
+    let target = line[..equal].trim_end();
+    let is_name = !target.is_empty()
+        && target
+            .chars()
+            .all(|c| c == '_' || c == '.' || c == ',' || c == ' ' || c.is_alphanumeric());
+    let value = line[equal + 1..].trim_start();
+    if is_name && value.starts_with(&['%', '!'][..]) {
+        Some(equal + 1)
+    } else {
+        None
+    }
+}
+
+/// Replaces `text` (without its line break) by `replacement`, padded
+/// with spaces to keep the offsets of the rest of the code.
+fn mask(masked: &mut String, text: &str, replacement: &str) {
+    masked.push_str(replacement);
+    for _ in replacement.len()..text.len() {
+        masked.push(' ');
+    }
+}
+
+/// Replaces IPython's syntax extensions by Python code, keeping the
+/// lines of the code (and their offsets, when the extensions are not
+/// too short): magics, shell commands and help become `pass`, their
+/// values in assignments (`x = !ls`) become `None`, and the code of
+/// cells starting with a cell magic whose body is not Python (eg.
+/// `%%bash`) is removed.
+///
+/// ```
+/// use python_parser::notebook::mask_magics;
+/// assert_eq!(mask_magics("%matplotlib inline\nfiles = !ls\nif x:\n    f?\n"),
+///            "pass              \nfiles = None\nif x:\n    pass\n");
+/// ```
+pub fn mask_magics(code: &str) -> String {
+    let mut masked = String::with_capacity(code.len());
+    let first = code.trim_start();
+    if let Some(magic) = first.strip_prefix("%%") {
+        let name = magic.split(|c: char| !c.is_alphanumeric()).next();
+        if !name.is_some_and(|name| PYTHON_CELL_MAGICS.contains(&name)) {
+            return code
+                .split_inclusive('\n')
+                .map(|line| &line[line.trim_end().len()..])
+                .collect();
+        }
+    }
+    let mut scanner = LineScanner::default();
+    for line in code.split_inclusive('\n') {
+        let text = line.trim_end_matches(&['\r', '\n'][..]);
+        let trimmed = text.trim_start();
+        let indent = &text[..text.len() - trimmed.len()];
+        if scanner.at_line_start() && is_magic(trimmed) {
+            masked.push_str(indent);
+            mask(&mut masked, trimmed, "pass");
+        } else if let Some(len) = magic_assignment(trimmed).filter(|_| scanner.at_line_start()) {
+            masked.push_str(&text[..indent.len() + len]);
+            mask(&mut masked, &trimmed[len..], " None");
+        } else {
+            masked.push_str(text);
+            scanner.scan_line(if scanner.at_line_start() {
+                trimmed
+            } else {
+                text
+            });
+        }
+        masked.push_str(&line[text.len()..]);
+    }
+    masked
+}
+
+/// Parses the code cells of a notebook, given the JSON of its file, and
+/// returns them with their syntax errors. Like in Jupyter, each cell is
+/// parsed on its own, and IPython's syntax is allowed (see
+/// `mask_magics`).
+///
+/// ```
+/// use python_parser::notebook::parse_notebook;
+/// let notebook = r##"{"nbformat": 4, "nbformat_minor": 5, "metadata": {}, "cells": [
+///     {"cell_type": "markdown", "metadata": {}, "source": ["# Title"]},
+///     {"cell_type": "code", "metadata": {}, "outputs": [], "execution_count": null,
+///      "source": ["%load_ext autoreload\n", "x = (1,\n"]}
+/// ]}"##;
+/// let parsed = parse_notebook(notebook).unwrap();
+/// assert_eq!(parsed.cells[0].index, 1);
+/// assert_eq!(parsed.diagnostics[0].to_string(), "cell 1: '(' was never closed at line 2");
+/// ```
+pub fn parse_notebook(notebook: &str) -> Result<ParsedNotebook, NotebookError> {
+    let mut parsed = ParsedNotebook {
+        cells: Vec::new(),
+        diagnostics: Vec::new(),
+    };
+    for cell in code_cells(notebook)? {
+        let tolerant = parse_module_tolerant(&mask_magics(&cell.source));
+        parsed
+            .diagnostics
+            .extend(tolerant.errors.into_iter().map(|error| NotebookDiagnostic {
+                cell: cell.index,
+                error,
+            }));
+        parsed.cells.push(ParsedCell {
+            index: cell.index,
+            module: tolerant.module,
+        });
+    }
+    Ok(parsed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ast::*;
+
+    #[test]
+    fn test_mask_magics() {
+        assert_eq!(mask_magics("!ls\nx = f(\n  %d)\n"), "pass\nx = f(\n  %d)\n");
+        assert_eq!(mask_magics("a, b = %who_ls\n"), "a, b = None   \n");
+        assert_eq!(mask_magics("x = 1 # why?\n"), "x = 1 # why?\n");
+        assert_eq!(mask_magics("s = '''\n%s\n'''\n"), "s = '''\n%s\n'''\n");
+        assert_eq!(mask_magics("%%bash\nls\r\n"), "\n\r\n");
+        assert_eq!(mask_magics("%%time\nx = 1\n"), "pass  \nx = 1\n");
+    }
+
+    #[test]
+    fn test_parse_notebook() {
+        let notebook = r#"{
+ "nbformat": 4,
+ "cells": [
+  {"cell_type": "code", "source": "import os\n%ls\n"},
+  {"cell_type": "raw", "source": "def"},
+  {"cell_type": "code", "source": ["def f()\n", "    pass\n", "y = 1"]}
+ ]
+}"#;
+        let parsed = parse_notebook(notebook).unwrap();
+        let indices: Vec<_> = parsed.cells.iter().map(|c| c.index).collect();
+        assert_eq!(indices, [0, 2]);
+        assert_eq!(parsed.cells[0].module.body.len(), 2);
+        assert_eq!(parsed.cells[1].module.body[1].clone(), {
+            let y = Expression::Name("y".to_string());
+            Statement::Assignment(
+                small_list![y],
+                vec![small_list![Expression::Int(1u32.into())]],
+            )
+        });
+        let diagnostics: Vec<_> = parsed
+            .diagnostics
+            .iter()
+            .map(|d| (d.cell, d.error.line))
+            .collect();
+        assert_eq!(diagnostics, [(2, 1)]);
+
+        assert_eq!(
+            parse_notebook("{\"nbformat\": 3, \"worksheets\": []}"),
+            Err(NotebookError::InvalidNotebook("nbformat 4 is required"))
+        );
+        assert_eq!(
+            parse_notebook("{\"nbformat\": 4"),
+            Err(NotebookError::InvalidJson(14))
+        );
+    }
+}
@@ -0,0 +1,377 @@
+//! The outline of a module: its classes, functions, methods and
+//! variables, with their positions, as shown by the symbol panes of
+//! editors.
+//!
+//! The outline is computed from a lazy parse (see `lazy`), so the
+//! bodies of functions are not parsed. Only the definitions at the top
+//! level of the module and of its classes are included, not the ones in
+//! the blocks of `if`, `try`, etc. statements.
+
+use ast::*;
+use errors::ParseError;
+use helpers::make_strspan;
+use json::Json;
+use lazy::{lazy_statements_spanned, LazyStatement};
+use line_index::{Encoding, LineCol, LineIndex};
+use memo::new_input;
+use span::{Span, Spanned};
+use tokenizer::{tokenize, TokenKind};
+use visitors::target_names;
+
+/// The kind of an `OutlineSymbol`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum SymbolKind {
+    Class,
+    Function,
+    /// A function defined in a class body.
+    Method,
+    Variable,
+    /// A variable whose name is in upper case, like `MAX_SIZE`.
+    Constant,
+}
+
+impl SymbolKind {
+    /// The number of the kind in the Language Server Protocol.
+    pub fn lsp_kind(self) -> u32 {
+        match self {
+            SymbolKind::Class => 5,
+            SymbolKind::Method => 6,
+            SymbolKind::Function => 12,
+            SymbolKind::Variable => 13,
+            SymbolKind::Constant => 14,
+        }
+    }
+}
+
+/// A class, function, method or variable of a module, found by
+/// `outline`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OutlineSymbol {
+    pub name: String,
+    pub kind: SymbolKind,
+    /// The whole statement: the definition with its decorators and
+    /// body, or the assignment.
+    pub span: Span,
+    /// The name in the statement.
+    pub name_span: Span,
+    /// The methods, nested classes and class variables of a class.
+    pub children: Vec<OutlineSymbol>,
+}
+
+impl Spanned for OutlineSymbol {
+    fn span(&self) -> Span {
+        self.span
+    }
+}
+
+fn is_constant(name: &str) -> bool {
+    name.chars().any(char::is_alphabetic) && !name.chars().any(char::is_lowercase)
+}
+
+/// Returns the span of the name of the `def` or `class` statement in
+/// `span`, or of the first occurrence of `name` if `after_keyword` is
+/// false.
+fn name_span(code: &str, span: Span, name: &str, after_keyword: bool) -> Span {
+    let mut after = !after_keyword;
+    for token in tokenize(&code[span.start..span.end]) {
+        if token.kind != TokenKind::Name {
+            continue;
+        }
+        if after && token.text == name {
+            return Span::new(
+                span.start + token.offset,
+                span.start + token.offset + name.len(),
+            );
+        }
+        if token.text == "def" || token.text == "class" {
+            after = true;
+        }
+    }
+    span
+}
+
+/// Returns the names of the variables assigned by `stmt`.
+fn assigned_names(stmt: &Statement) -> Vec<&str> {
+    let mut names = Vec::new();
+    match *stmt {
+        Statement::Assignment(ref lhs, ref rhs) if !rhs.is_empty() => {
+            for target in lhs.iter().chain(rhs[..rhs.len() - 1].iter().flatten()) {
+                target_names(target, &mut names);
+            }
+        }
+        Statement::TypeAnnotation(ref lhs, _) | Statement::TypedAssignment(ref lhs, _, _) => {
+            for target in lhs {
+                target_names(target, &mut names);
+            }
+        }
+        _ => (),
+    }
+    names
+}
+
+/// Returns the symbols of `stmts`, a module or class body (if
+/// `in_class`).
+fn symbols(
+    code: &str,
+    stmts: Vec<(Span, LazyStatement)>,
+    in_class: bool,
+) -> Result<Vec<OutlineSymbol>, ParseError> {
+    let mut result = Vec::new();
+    for (span, stmt) in stmts {
+        match stmt {
+            LazyStatement::Funcdef(funcdef, _) => result.push(OutlineSymbol {
+                name_span: name_span(code, span, &funcdef.name, true),
+                name: funcdef.name,
+                kind: if in_class {
+                    SymbolKind::Method
+                } else {
+                    SymbolKind::Function
+                },
+                span,
+                children: Vec::new(),
+            }),
+            LazyStatement::Classdef(classdef, body) => {
+                let children = symbols(code, body.parse_lazy_spanned()?, true)?;
+                result.push(OutlineSymbol {
+                    name_span: name_span(code, span, &classdef.name, true),
+                    name: classdef.name,
+                    kind: SymbolKind::Class,
+                    span,
+                    children,
+                })
+            }
+            LazyStatement::Statement(ref stmt) => {
+                for name in assigned_names(stmt) {
+                    result.push(OutlineSymbol {
+                        name: name.to_string(),
+                        kind: if is_constant(name) {
+                            SymbolKind::Constant
+                        } else {
+                            SymbolKind::Variable
+                        },
+                        span,
+                        name_span: name_span(code, span, name, false),
+                        children: Vec::new(),
+                    })
+                }
+            }
+        }
+    }
+    Ok(result)
+}
+
+/// Returns the outline of a module, in the order of the code.
+///
+/// ```
+/// use python_parser::outline::{outline, SymbolKind};
+/// use python_parser::span::Span;
+///
+/// let code = "MAX = 10\n\nclass A:\n    @property\n    def f(self):\n        return MAX\n";
+/// let symbols = outline(code).unwrap();
+/// assert_eq!(symbols[0].kind, SymbolKind::Constant);
+/// let class = &symbols[1];
+/// assert_eq!((&class.name[..], class.kind), ("A", SymbolKind::Class));
+/// let method = &class.children[0];
+/// assert_eq!((&method.name[..], method.kind), ("f", SymbolKind::Method));
+/// assert_eq!(&code[method.span.start..method.span.end], "@property\n    def f(self):\n        return MAX");
+/// assert_eq!(method.name_span, Span::new(41, 42));
+/// ```
+pub fn outline(code: &str) -> Result<Vec<OutlineSymbol>, ParseError> {
+    new_input();
+    let stmts = lazy_statements_spanned(make_strspan(code), 0)?;
+    symbols(code, stmts, false)
+}
+
+fn lsp_position(position: LineCol) -> Json {
+    Json::Object(vec![
+        ("line".to_string(), Json::Number(f64::from(position.line))),
+        (
+            "character".to_string(),
+            Json::Number(f64::from(position.col)),
+        ),
+    ])
+}
+
+pub(crate) fn lsp_range(index: &LineIndex, span: Span, encoding: Encoding) -> Json {
+    let (start, end) = index
+        .span_line_cols(span, encoding)
+        .unwrap_or((LineCol { line: 0, col: 0 }, LineCol { line: 0, col: 0 }));
+    Json::Object(vec![
+        ("start".to_string(), lsp_position(start)),
+        ("end".to_string(), lsp_position(end)),
+    ])
+}
+
+pub(crate) fn document_symbol(
+    symbol: &OutlineSymbol,
+    index: &LineIndex,
+    encoding: Encoding,
+) -> Json {
+    let children = symbol
+        .children
+        .iter()
+        .map(|child| document_symbol(child, index, encoding))
+        .collect();
+    Json::Object(vec![
+        ("name".to_string(), Json::String(symbol.name.clone())),
+        (
+            "kind".to_string(),
+            Json::Number(f64::from(symbol.kind.lsp_kind())),
+        ),
+        ("range".to_string(), lsp_range(index, symbol.span, encoding)),
+        (
+            "selectionRange".to_string(),
+            lsp_range(index, symbol.name_span, encoding),
+        ),
+        ("children".to_string(), Json::Array(children)),
+    ])
+}
+
+/// Returns the symbols as the JSON array of `DocumentSymbol` of the
+/// Language Server Protocol, the result of the
+/// `textDocument/documentSymbol` request. `index` is the index of the
+/// code of the symbols, and `encoding` the unit of the columns
+/// negotiated with the client.
+///
+/// ```
+/// use python_parser::line_index::{Encoding, LineIndex};
+/// use python_parser::outline::{document_symbols_json, outline};
+///
+/// let code = "class A:\n    x = 1\n";
+/// let json = document_symbols_json(&outline(code).unwrap(), &LineIndex::new(code), Encoding::Utf16);
+/// assert_eq!(
+///     json,
+///     concat!(
+///         r#"[{"name":"A","kind":5,"#,
+///         r#""range":{"start":{"line":0,"character":0},"end":{"line":1,"character":9}},"#,
+///         r#""selectionRange":{"start":{"line":0,"character":6},"end":{"line":0,"character":7}},"#,
+///         r#""children":[{"name":"x","kind":13,"#,
+///         r#""range":{"start":{"line":1,"character":4},"end":{"line":1,"character":9}},"#,
+///         r#""selectionRange":{"start":{"line":1,"character":4},"end":{"line":1,"character":5}},"#,
+///         r#""children":[]}]}]"#,
+///     )
+/// );
+/// ```
+pub fn document_symbols_json(
+    symbols: &[OutlineSymbol],
+    index: &LineIndex,
+    encoding: Encoding,
+) -> String {
+    let symbols = symbols
+        .iter()
+        .map(|symbol| document_symbol(symbol, index, encoding))
+        .collect();
+    Json::Array(symbols).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns the symbols as `(depth, name, kind, text of the span,
+    /// text of the name span)`.
+    fn flatten<'a>(
+        code: &'a str,
+        symbols: &[OutlineSymbol],
+        depth: usize,
+        result: &mut Vec<(usize, String, SymbolKind, &'a str, &'a str)>,
+    ) {
+        for symbol in symbols {
+            result.push((
+                depth,
+                symbol.name.clone(),
+                symbol.kind,
+                &code[symbol.span.start..symbol.span.end],
+                &code[symbol.name_span.start..symbol.name_span.end],
+            ));
+            flatten(code, &symbol.children, depth + 1, result);
+        }
+    }
+
+    #[test]
+    fn test_outline() {
+        let code = "# comment
+import os
+x, (y, *z) = f()  # comment
+T: int
+
+async def g(a): return a
+class A(B):
+    '''Doc.'''
+    a = b = 1
+
+    class Meta: pass
+    def def_(self):
+        def inner(): pass
+        # comment
+
+# comment
+if x:
+    def h(): pass
+";
+        let mut symbols = Vec::new();
+        flatten(code, &outline(code).unwrap(), 0, &mut symbols);
+        let class = "class A(B):
+    '''Doc.'''
+    a = b = 1
+
+    class Meta: pass
+    def def_(self):
+        def inner(): pass";
+        let method = "def def_(self):
+        def inner(): pass";
+        let expected = vec![
+            (0, "x", SymbolKind::Variable, "x, (y, *z) = f()", "x"),
+            (0, "y", SymbolKind::Variable, "x, (y, *z) = f()", "y"),
+            (0, "z", SymbolKind::Variable, "x, (y, *z) = f()", "z"),
+            (0, "T", SymbolKind::Constant, "T: int", "T"),
+            (
+                0,
+                "g",
+                SymbolKind::Function,
+                "async def g(a): return a",
+                "g",
+            ),
+            (0, "A", SymbolKind::Class, class, "A"),
+            (1, "a", SymbolKind::Variable, "a = b = 1", "a"),
+            (1, "b", SymbolKind::Variable, "a = b = 1", "b"),
+            (1, "Meta", SymbolKind::Class, "class Meta: pass", "Meta"),
+            (1, "def_", SymbolKind::Method, method, "def_"),
+        ];
+        let expected: Vec<_> = expected
+            .into_iter()
+            .map(|(depth, name, kind, span, name_span)| {
+                (depth, name.to_string(), kind, span, name_span)
+            })
+            .collect();
+        assert_eq!(symbols, expected);
+    }
+
+    #[test]
+    fn test_document_symbols_json() {
+        let code = "def f():\n    pass\n\nclass \u{e9}t\u{e9}:\n    def g(self): pass\n";
+        let json = document_symbols_json(
+            &outline(code).unwrap(),
+            &LineIndex::new(code),
+            Encoding::Utf8,
+        );
+        let json = Json::parse(&json).unwrap();
+        let symbols = json.as_array().unwrap();
+        assert_eq!(symbols.len(), 2);
+        assert_eq!(symbols[0].get("kind"), Some(&Json::Number(12.)));
+        let class = &symbols[1];
+        assert_eq!(
+            class.get("name").and_then(Json::as_str),
+            Some("\u{e9}t\u{e9}")
+        );
+        let end = class
+            .get("selectionRange")
+            .and_then(|range| range.get("end"))
+            .and_then(|end| end.get("character"));
+        // In bytes of UTF-8
+        assert_eq!(end, Some(&Json::Number(11.)));
+        let methods = class.get("children").and_then(Json::as_array).unwrap();
+        assert_eq!(methods[0].get("name").and_then(Json::as_str), Some("g"));
+        assert_eq!(methods[0].get("kind"), Some(&Json::Number(6.)));
+    }
+}
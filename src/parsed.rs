@@ -0,0 +1,182 @@
+//! A parsed module along with what tools need besides its AST: its
+//! tokens (comments included), its line index and its syntax errors,
+//! so they do not have to tokenize or index the code again.
+//!
+//! All the types of the AST and of the analyses are `Send` and `Sync`,
+//! so `parse_shared` returns an `Arc<ParsedModule>` that the threads of
+//! a server can share instead of cloning the tree.
+
+use std::sync::Arc;
+
+use ast::Module;
+use errors::ParseError;
+use line_index::LineIndex;
+use span::{Span, Spanned};
+use tokenizer::{tokenize, Token, TokenKind};
+use tolerant::{parse_module_tolerant, TolerantModule};
+
+/// The result of parsing some code.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ParsedModule {
+    pub code: String,
+    /// The module, where each statement that could not be parsed is a
+    /// `Statement::Invalid`.
+    pub module: Module,
+    pub line_index: LineIndex,
+    /// The syntax errors, in the order of the code.
+    pub diagnostics: Vec<ParseError>,
+    /// Kind, span and line of the tokens.
+    tokens: Vec<(TokenKind, Span, u32)>,
+}
+
+impl ParsedModule {
+    /// Parses `code`, which may have syntax errors (see
+    /// `tolerant::parse_module_tolerant`).
+    ///
+    /// ```
+    /// use python_parser::parsed::ParsedModule;
+    /// use python_parser::tokenizer::TokenKind;
+    ///
+    /// let parsed = ParsedModule::parse("x = 1  # one\ndef f(:\n");
+    /// assert_eq!(parsed.module.body.len(), 2);
+    /// assert_eq!(parsed.diagnostics[0].line, 2);
+    /// let comment = parsed.tokens().find(|t| t.kind == TokenKind::Comment).unwrap();
+    /// assert_eq!(comment.text, "# one");
+    /// ```
+    pub fn parse(code: &str) -> ParsedModule {
+        let TolerantModule { module, errors } = parse_module_tolerant(code);
+        let tokens = tokenize(code)
+            .map(|token| (token.kind, token.span(), token.line))
+            .collect();
+        ParsedModule {
+            code: code.to_string(),
+            module,
+            line_index: LineIndex::new(code),
+            diagnostics: errors,
+            tokens,
+        }
+    }
+
+    /// Whether the code has no syntax errors.
+    pub fn is_valid(&self) -> bool {
+        self.diagnostics.is_empty()
+    }
+
+    /// Returns the tokens of the code, like `tokenizer::tokenize`.
+    pub fn tokens<'a>(&'a self) -> impl Iterator<Item = Token<'a>> + 'a {
+        self.tokens.iter().map(move |&(kind, span, line)| Token {
+            kind,
+            text: &self.code[span.start..span.end],
+            offset: span.start,
+            line,
+        })
+    }
+}
+
+/// Parses `code`, and returns a handle to the result that can be sent
+/// to other threads.
+///
+/// ```
+/// use std::thread;
+/// use python_parser::parsed::parse_shared;
+///
+/// let parsed = parse_shared("def f():\n    pass\n");
+/// let threads: Vec<_> = (0..4)
+///     .map(|_| {
+///         let parsed = parsed.clone();
+///         thread::spawn(move || parsed.module.body.len())
+///     })
+///     .collect();
+/// for thread in threads {
+///     assert_eq!(thread.join().unwrap(), 1);
+/// }
+/// ```
+pub fn parse_shared(code: &str) -> Arc<ParsedModule> {
+    Arc::new(ParsedModule::parse(code))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ast::*;
+    use budget::Budget;
+    use cache::CachedParser;
+    use code_blocks::BlockDiagnostic;
+    use definition::Bindings;
+    use deprecated::DeprecationTable;
+    use doctest::Doctest;
+    use extensions::Extension;
+    use folding::FoldingRange;
+    use hover::Hover;
+    use imports::UnusedImport;
+    use lazy::LazyStatement;
+    use lint::{LintContext, LintDiagnostic, Rule};
+    use metrics::FunctionMetrics;
+    use notebook::ParsedNotebook;
+    use outline::OutlineSymbol;
+    use project::Project;
+    use query::Filter;
+    use selection::Node;
+    use semantic::SemanticToken;
+    use server::Server;
+    use session::Parser;
+    use std::fs::File;
+    use stream::{StatementStream, Statements};
+    use tokenizer::Tokenizer;
+    use tolerant::TolerantModule;
+    use validate::Diagnostic;
+    use watch::FileWatcher;
+
+    fn assert_send_sync<T: Send + Sync>() {}
+
+    #[test]
+    fn test_parse() {
+        let code = "if x:\n    y = f(1, # a\n      2)\n\tz = 1\n";
+        let parsed = ParsedModule::parse(code);
+        assert_eq!(
+            parsed.tokens().collect::<Vec<_>>(),
+            tokenize(code).collect::<Vec<_>>()
+        );
+        assert!(!parsed.is_valid());
+        assert_eq!(parsed.module, parse_module_tolerant(code).module);
+        assert_eq!(parsed.line_index.line_count(), 5);
+        assert!(ParsedModule::parse("x = 1\n").is_valid());
+    }
+
+    #[test]
+    fn test_send_sync() {
+        assert_send_sync::<Arc<ParsedModule>>();
+        assert_send_sync::<Statement>();
+        assert_send_sync::<Expression>();
+        assert_send_sync::<ParseError>();
+        assert_send_sync::<Budget>();
+        assert_send_sync::<CachedParser>();
+        assert_send_sync::<BlockDiagnostic>();
+        assert_send_sync::<Bindings>();
+        assert_send_sync::<DeprecationTable>();
+        assert_send_sync::<Doctest>();
+        assert_send_sync::<FoldingRange>();
+        assert_send_sync::<Hover>();
+        assert_send_sync::<UnusedImport>();
+        assert_send_sync::<LazyStatement>();
+        assert_send_sync::<Box<dyn Rule>>();
+        assert_send_sync::<Box<dyn Extension>>();
+        assert_send_sync::<LintDiagnostic>();
+        assert_send_sync::<LintContext>();
+        assert_send_sync::<FunctionMetrics>();
+        assert_send_sync::<ParsedNotebook>();
+        assert_send_sync::<OutlineSymbol>();
+        assert_send_sync::<Project>();
+        assert_send_sync::<Filter>();
+        assert_send_sync::<Node>();
+        assert_send_sync::<SemanticToken>();
+        assert_send_sync::<Server>();
+        assert_send_sync::<Parser>();
+        assert_send_sync::<Statements>();
+        assert_send_sync::<StatementStream<File>>();
+        assert_send_sync::<Tokenizer>();
+        assert_send_sync::<TolerantModule>();
+        assert_send_sync::<Diagnostic>();
+        assert_send_sync::<FileWatcher>();
+    }
+}
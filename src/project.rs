@@ -0,0 +1,791 @@
+//! A set of modules parsed together, to follow imports from one module
+//! to the others, like a Python path made of the files of a project.
+//!
+//! Imports are resolved like type checkers do: a stub (`.pyi` file)
+//! hides the module of the same name (which is still available with
+//! `Project::implementation`), and directories of modules without an
+//! `__init__` module are namespace packages (PEP 420).
+
+use std::collections::btree_map;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::io;
+use std::ops::Bound;
+use std::path::{Path, PathBuf};
+
+use ast::*;
+use cache::ParseFileError;
+use errors::ParseError;
+use parse_module;
+use visitors::target_names;
+
+/// Number of imports followed by `Project::find_symbol` before giving
+/// up, which stops import cycles.
+const MAX_IMPORT_DEPTH: usize = 64;
+
+/// A module of a `Project`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ProjectModule {
+    pub module: Module,
+    /// Whether the module is the `__init__` module of a package, which
+    /// relative imports of the module are relative to.
+    pub is_package: bool,
+    /// Whether the module is a stub (`.pyi` file).
+    pub is_stub: bool,
+    /// The file the module was read from, if any.
+    pub path: Option<PathBuf>,
+}
+
+/// What a name of a module refers to, found by `Project::find_symbol`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Symbol<'a> {
+    /// A module or namespace package of the project, eg. for names bound
+    /// by `import x`.
+    Module(&'a str),
+    /// The last statement of `module` binding the name, at the top level
+    /// of the module or in a block of a top-level `if`, `try`, etc.
+    /// This is an import if it imports a module outside the project.
+    Definition {
+        module: &'a str,
+        statement: &'a Statement,
+    },
+}
+
+/// Parsed modules, keyed by their dotted name (eg. `"pkg.module"`).
+///
+/// ```
+/// use python_parser::project::{Project, Symbol};
+///
+/// let mut project = Project::new();
+/// project.add_module("pkg", "from .utils import helper\n", true).unwrap();
+/// project.add_module("pkg.utils", "def helper(): pass\n", false).unwrap();
+/// project.add_module("main", "import pkg\nfrom pkg import helper\n", false).unwrap();
+/// assert_eq!(project.imports("main"), ["pkg"]);
+/// match project.find_symbol("main", "helper") {
+///     Some(Symbol::Definition { module, .. }) => assert_eq!(module, "pkg.utils"),
+///     _ => panic!("helper not found"),
+/// }
+/// ```
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Project {
+    modules: BTreeMap<String, ProjectModule>,
+    /// Modules hidden by a stub of the same name.
+    implementations: BTreeMap<String, ProjectModule>,
+}
+
+impl Project {
+    pub fn new() -> Project {
+        Project::default()
+    }
+
+    /// Parses `code` as the module `name`, replacing the previous
+    /// version of the module. If the code has a syntax error, the
+    /// previous version (if any) is kept.
+    pub fn add_module(
+        &mut self,
+        name: &str,
+        code: &str,
+        is_package: bool,
+    ) -> Result<(), ParseError> {
+        let module = parse_module(code)?;
+        self.insert(
+            name.to_string(),
+            ProjectModule {
+                module,
+                is_package,
+                is_stub: false,
+                path: None,
+            },
+        );
+        Ok(())
+    }
+
+    /// Like `add_module`, for the stub of the module `name`.
+    pub fn add_stub(&mut self, name: &str, code: &str, is_package: bool) -> Result<(), ParseError> {
+        let module = parse_module(code)?;
+        self.insert(
+            name.to_string(),
+            ProjectModule {
+                module,
+                is_package,
+                is_stub: true,
+                path: None,
+            },
+        );
+        Ok(())
+    }
+
+    /// Adds `module`, replacing the previous version of the stub or
+    /// implementation of `name`.
+    fn insert(&mut self, name: String, module: ProjectModule) {
+        let is_stub = module.is_stub;
+        let hidden = self.modules.get(&name).is_some_and(|m| m.is_stub);
+        if hidden && !is_stub {
+            self.implementations.insert(name, module);
+            return;
+        }
+        if let Some(previous) = self.modules.insert(name.clone(), module) {
+            if is_stub && !previous.is_stub {
+                self.implementations.insert(name, previous);
+            }
+        }
+    }
+
+    /// Reads and parses the file at `path`, as the module named after its
+    /// path relative to `root` (eg. `pkg/utils.py` is `pkg.utils`,
+    /// `pkg/__init__.py` is the package `pkg`, and `pkg/utils.pyi` or
+    /// `pkg-stubs/utils.pyi` is the stub of `pkg.utils`), and returns
+    /// that name.
+    pub fn add_file<P: AsRef<Path>>(
+        &mut self,
+        root: &Path,
+        path: P,
+    ) -> Result<String, ParseFileError> {
+        let path = path.as_ref();
+        let (name, is_package, is_stub) = module_name(root, path).ok_or_else(|| {
+            ParseFileError::Io(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "{} is not a Python module of {}",
+                    path.display(),
+                    root.display()
+                ),
+            ))
+        })?;
+        let code = fs::read_to_string(path).map_err(ParseFileError::Io)?;
+        let module = parse_module(&code).map_err(ParseFileError::Parse)?;
+        self.insert(
+            name.clone(),
+            ProjectModule {
+                module,
+                is_package,
+                is_stub,
+                path: Some(path.to_path_buf()),
+            },
+        );
+        Ok(name)
+    }
+
+    /// Reads and parses again the file of the module `name` (and of the
+    /// module its stub hides, if any), after it changed. If the code has
+    /// a syntax error, the previous version is kept.
+    pub fn reparse(&mut self, name: &str) -> Result<(), ParseFileError> {
+        let entries = self
+            .modules
+            .get_mut(name)
+            .into_iter()
+            .chain(self.implementations.get_mut(name))
+            .filter_map(|m| {
+                let path = m.path.clone()?;
+                Some((m, path))
+            })
+            .collect::<Vec<_>>();
+        if entries.is_empty() {
+            return Err(ParseFileError::Io(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("no module {} read from a file", name),
+            )));
+        }
+        for (entry, path) in entries {
+            let code = fs::read_to_string(path).map_err(ParseFileError::Io)?;
+            entry.module = parse_module(&code).map_err(ParseFileError::Parse)?;
+        }
+        Ok(())
+    }
+
+    /// Removes the module `name` and its stub from the project (eg. when
+    /// its files were deleted), and returns the module imports resolved
+    /// to.
+    pub fn remove_module(&mut self, name: &str) -> Option<ProjectModule> {
+        self.implementations.remove(name);
+        self.modules.remove(name)
+    }
+
+    /// Removes the module read from the file at `path` (eg. when it was
+    /// deleted), and returns it. When that is a stub, imports resolve to
+    /// the module it hid again.
+    pub fn remove_file<P: AsRef<Path>>(&mut self, path: P) -> Option<ProjectModule> {
+        let path = Some(path.as_ref());
+        let from_path = |modules: &BTreeMap<String, ProjectModule>| {
+            modules
+                .iter()
+                .find(|&(_, m)| m.path.as_deref() == path)
+                .map(|(name, _)| name.clone())
+        };
+        if let Some(name) = from_path(&self.implementations) {
+            return self.implementations.remove(&name);
+        }
+        let name = from_path(&self.modules)?;
+        let removed = self.modules.remove(&name);
+        if let Some(implementation) = self.implementations.remove(&name) {
+            self.modules.insert(name, implementation);
+        }
+        removed
+    }
+
+    /// Returns the module imports of `name` resolve to: its stub, if it
+    /// has one.
+    pub fn module(&self, name: &str) -> Option<&ProjectModule> {
+        self.modules.get(name)
+    }
+
+    /// Returns the module `name` that CPython would import, which is
+    /// not a stub.
+    pub fn implementation(&self, name: &str) -> Option<&ProjectModule> {
+        self.implementations
+            .get(name)
+            .or_else(|| self.modules.get(name).filter(|m| !m.is_stub))
+    }
+
+    /// Like `implementation`, to modify the module.
+    pub fn implementation_mut(&mut self, name: &str) -> Option<&mut ProjectModule> {
+        if self.implementations.contains_key(name) {
+            self.implementations.get_mut(name)
+        } else {
+            self.modules.get_mut(name).filter(|m| !m.is_stub)
+        }
+    }
+
+    /// Whether `name` is a namespace package: a package without an
+    /// `__init__` module, that only exists because it has modules.
+    pub fn is_namespace_package(&self, name: &str) -> bool {
+        !self.modules.contains_key(name) && self.namespace_package(name).is_some()
+    }
+
+    /// Returns the name of the namespace package `name` as stored in
+    /// the project, if it has modules.
+    fn namespace_package(&self, name: &str) -> Option<&str> {
+        let prefix = format!("{}.", name);
+        self.modules
+            .range::<str, _>((Bound::Excluded(&prefix[..]), Bound::Unbounded))
+            .next()
+            .filter(|&(key, _)| key.starts_with(&prefix))
+            .map(|(key, _)| &key[..name.len()])
+    }
+
+    /// Returns the modules, sorted by name.
+    pub fn modules(&self) -> btree_map::Iter<'_, String, ProjectModule> {
+        self.modules.iter()
+    }
+
+    /// Returns the absolute name of the module or namespace package
+    /// imported by the module `importer` with `from <leading_dots><path>
+    /// import ...` (or `import <path>`, with no leading dots), if it is
+    /// in the project.
+    pub fn resolve(&self, importer: &str, leading_dots: usize, path: &[Name]) -> Option<&str> {
+        let name = self.absolute_name(importer, leading_dots, path)?;
+        match self.modules.get_key_value(&name) {
+            Some((name, _)) => Some(name),
+            None => self.namespace_package(&name),
+        }
+    }
+
+    /// Returns the name of the module imported by `importer`, whether it
+    /// is in the project or not.
+    fn absolute_name(&self, importer: &str, leading_dots: usize, path: &[Name]) -> Option<String> {
+        let mut parts: Vec<&str> = Vec::new();
+        if leading_dots > 0 {
+            let module = self.modules.get(importer)?;
+            parts = importer.split('.').collect();
+            // The package of the importer is the first level
+            let levels = leading_dots - if module.is_package { 1 } else { 0 };
+            if levels >= parts.len() {
+                return None;
+            }
+            let new_len = parts.len() - levels;
+            parts.truncate(new_len);
+        }
+        parts.extend(path.iter().map(|s| &s[..]));
+        if parts.is_empty() {
+            return None;
+        }
+        Some(parts.join("."))
+    }
+
+    /// Returns the modules of the project imported by the module `name`,
+    /// sorted by name: the modules of `import` statements and their
+    /// parent packages, and the modules (or packages) that `from ...
+    /// import` statements import from, at the top level of the module
+    /// or in the blocks of top-level `if`, `try`, etc.
+    pub fn imports(&self, name: &str) -> Vec<&str> {
+        let mut imported = BTreeSet::new();
+        if let Some(module) = self.modules.get(name) {
+            let mut imports = Vec::new();
+            collect_imports(&module.module.body, &mut imports);
+            for import in imports {
+                match *import {
+                    Import::ImportFrom {
+                        leading_dots,
+                        ref path,
+                        ref names,
+                    } => {
+                        imported.extend(self.resolve(name, leading_dots, path));
+                        for (imported_name, _) in names {
+                            let mut path = path.clone();
+                            path.push(imported_name.clone());
+                            imported.extend(self.resolve(name, leading_dots, &path));
+                        }
+                    }
+                    Import::ImportStarFrom {
+                        leading_dots,
+                        ref path,
+                    } => imported.extend(self.resolve(name, leading_dots, path)),
+                    Import::Import { ref names } => {
+                        for (path, _) in names {
+                            for len in 1..=path.len() {
+                                imported.extend(self.resolve(name, 0, &path[..len]));
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        imported.remove(name);
+        imported.into_iter().collect()
+    }
+
+    /// Returns the names bound at the top level of the module `name`
+    /// (including in the blocks of top-level `if`, `try`, etc.), sorted.
+    pub fn symbols(&self, name: &str) -> Vec<&str> {
+        let mut names = BTreeSet::new();
+        if let Some(module) = self.modules.get(name) {
+            let mut bindings = Vec::new();
+            collect_bindings(&module.module.body, &mut bindings);
+            names.extend(bindings.into_iter().map(|(name, _)| name));
+        }
+        names.into_iter().collect()
+    }
+
+    /// Finds what `symbol` refers to in the module `name`, following the
+    /// imports of modules of the project (including `*` imports).
+    pub fn find_symbol(&self, name: &str, symbol: &str) -> Option<Symbol<'_>> {
+        self.find_symbol_at_depth(name, symbol, 0)
+    }
+
+    fn find_symbol_at_depth(&self, name: &str, symbol: &str, depth: usize) -> Option<Symbol<'_>> {
+        if depth > MAX_IMPORT_DEPTH {
+            return None;
+        }
+        let (name, module) = self.modules.get_key_value(name)?;
+        let mut bindings = Vec::new();
+        collect_bindings(&module.module.body, &mut bindings);
+        let statement = match bindings.iter().rev().find(|&&(bound, _)| bound == symbol) {
+            Some(&(_, statement)) => statement,
+            None => {
+                // Names imported with `from x import *`
+                let mut imports = Vec::new();
+                collect_imports(&module.module.body, &mut imports);
+                return imports.into_iter().rev().find_map(|import| match *import {
+                    Import::ImportStarFrom {
+                        leading_dots,
+                        ref path,
+                    } => {
+                        let target = self.resolve(name, leading_dots, path)?;
+                        self.find_symbol_at_depth(target, symbol, depth + 1)
+                    }
+                    _ => None,
+                });
+            }
+        };
+        let definition = Symbol::Definition {
+            module: name,
+            statement,
+        };
+        let import = match *statement {
+            Statement::Import(ref import) => import,
+            _ => return Some(definition),
+        };
+        let found = match *import {
+            Import::ImportFrom {
+                leading_dots,
+                ref path,
+                ref names,
+            } => {
+                let imported = match names
+                    .iter()
+                    .rev()
+                    .find(|entry| entry.1.as_ref().unwrap_or(&entry.0) == symbol)
+                {
+                    Some(entry) => &entry.0,
+                    None => return Some(definition),
+                };
+                let mut module_path = path.clone();
+                module_path.push(imported.clone());
+                if let Some(module) = self.resolve(name, leading_dots, &module_path) {
+                    return Some(Symbol::Module(module));
+                }
+                match self.resolve(name, leading_dots, path) {
+                    // A name that the module of the project may not define
+                    Some(base) => return self.find_symbol_at_depth(base, imported, depth + 1),
+                    None => None,
+                }
+            }
+            Import::Import { ref names } => names
+                .iter()
+                .rev()
+                .find_map(|(path, alias)| match *alias {
+                    Some(ref alias) if alias == symbol => Some(&path[..]),
+                    None if path.first().is_some_and(|first| first == symbol) => Some(&path[..1]),
+                    _ => None,
+                })
+                .and_then(|path| self.resolve(name, 0, path))
+                .map(Symbol::Module),
+            Import::ImportStarFrom { .. } => None,
+        };
+        Some(found.unwrap_or(definition))
+    }
+}
+
+/// Returns the name of the module of the file at `path`, relative to
+/// `root`, whether it is a package, and whether it is a stub.
+fn module_name(root: &Path, path: &Path) -> Option<(String, bool, bool)> {
+    let relative = path.strip_prefix(root).ok()?;
+    let mut parts = relative
+        .iter()
+        .map(|part| part.to_str())
+        .collect::<Option<Vec<_>>>()?;
+    let last = parts.pop()?;
+    let (stem, is_stub) = match (last.strip_suffix(".pyi"), last.strip_suffix(".py")) {
+        (Some(stem), _) => (stem, true),
+        (None, Some(stem)) => (stem, false),
+        (None, None) => return None,
+    };
+    // Stub-only packages (PEP 561)
+    if let Some(package) = parts.first_mut().filter(|_| is_stub) {
+        *package = package.strip_suffix("-stubs").unwrap_or(package);
+    }
+    let is_package = stem == "__init__";
+    if !is_package {
+        parts.push(stem);
+    }
+    if parts.is_empty() {
+        return None;
+    }
+    Some((parts.join("."), is_package, is_stub))
+}
+
+/// Calls `f` on each block that runs in the scope of `stmts` (the blocks
+/// of `if`, `for`, `try`, etc., but not of functions and classes).
+fn nested_blocks<'a, F: FnMut(&'a [Statement])>(stmt: &'a CompoundStatement, mut f: F) {
+    match *stmt {
+        CompoundStatement::If(ref cond_blocks, ref else_block) => {
+            for (_, block) in cond_blocks {
+                f(block);
+            }
+            if let Some(ref block) = *else_block {
+                f(block);
+            }
+        }
+        CompoundStatement::For {
+            ref for_block,
+            ref else_block,
+            ..
+        } => {
+            f(for_block);
+            if let Some(ref block) = *else_block {
+                f(block);
+            }
+        }
+        CompoundStatement::While(_, ref block, ref else_block) => {
+            f(block);
+            if let Some(ref block) = *else_block {
+                f(block);
+            }
+        }
+        CompoundStatement::With(_, ref block) => f(block),
+        CompoundStatement::Try(ref try_) => {
+            f(&try_.try_block);
+            for (_, _, block) in &try_.except_clauses {
+                f(block);
+            }
+            f(&try_.last_except);
+            f(&try_.else_block);
+            f(&try_.finally_block);
+        }
+        CompoundStatement::Funcdef(_) | CompoundStatement::Classdef(_) => (),
+    }
+}
+
+/// Adds the import statements of `stmts` to `imports`, in the order of
+/// the code.
+fn collect_imports<'a>(stmts: &'a [Statement], imports: &mut Vec<&'a Import>) {
+    for stmt in stmts {
+        match *stmt {
+            Statement::Import(ref import) => imports.push(import),
+            Statement::Compound(ref stmt) => {
+                nested_blocks(stmt, |block| collect_imports(block, imports))
+            }
+            _ => (),
+        }
+    }
+}
+
+/// Adds the names bound by `stmts` in their scope to `bindings`, with
+/// the statement binding them, in the order of the code.
+fn collect_bindings<'a>(stmts: &'a [Statement], bindings: &mut Vec<(&'a str, &'a Statement)>) {
+    for stmt in stmts {
+        let mut names = Vec::new();
+        match *stmt {
+            Statement::Assignment(ref lhs, ref rhs) => {
+                // All the expressions but the last are targets
+                let targets = Some(lhs).into_iter().chain(rhs).take(rhs.len());
+                for exprs in targets {
+                    for e in exprs {
+                        target_names(e, &mut names);
+                    }
+                }
+            }
+            Statement::TypeAnnotation(ref lhs, _)
+            | Statement::TypedAssignment(ref lhs, _, _)
+            | Statement::AugmentedAssignment(ref lhs, _, _) => {
+                for e in lhs {
+                    target_names(e, &mut names);
+                }
+            }
+            Statement::Import(Import::ImportFrom {
+                names: ref imported,
+                ..
+            }) => {
+                for (name, alias) in imported {
+                    names.push(alias.as_ref().unwrap_or(name));
+                }
+            }
+            Statement::Import(Import::Import {
+                names: ref imported,
+            }) => {
+                for (path, alias) in imported {
+                    match *alias {
+                        Some(ref alias) => names.push(alias),
+                        None => names.extend(path.first().map(|s| &s[..])),
+                    }
+                }
+            }
+            Statement::Compound(ref compound) => {
+                match **compound {
+                    CompoundStatement::Funcdef(ref funcdef) => names.push(&funcdef.name),
+                    CompoundStatement::Classdef(ref classdef) => names.push(&classdef.name),
+                    CompoundStatement::For { ref item, .. } => {
+                        for e in item {
+                            target_names(e, &mut names);
+                        }
+                    }
+                    CompoundStatement::With(ref contexts, _) => {
+                        for (_, target) in contexts {
+                            if let Some(ref target) = *target {
+                                target_names(target, &mut names);
+                            }
+                        }
+                    }
+                    _ => (),
+                }
+                bindings.extend(names.drain(..).map(|name| (name, stmt)));
+                nested_blocks(compound, |block| collect_bindings(block, bindings));
+            }
+            _ => (),
+        }
+        bindings.extend(names.into_iter().map(|name| (name, stmt)));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::process;
+
+    fn project() -> Project {
+        let mut project = Project::new();
+        let modules = [
+            ("pkg", "from .sub.mod import f as g\nfrom . import sub\n", true),
+            ("pkg.sub", "from .mod import *\n", true),
+            ("pkg.sub.mod", "import os\ndef f(): pass\nif x:\n    h = 1\nelse:\n    h = 2\n", false),
+            ("main", "import pkg.sub\nimport pkg as p\nfrom pkg import g, sub, missing\nfrom os import path\n", false),
+        ];
+        for &(name, code, is_package) in &modules {
+            project.add_module(name, code, is_package).unwrap();
+        }
+        project
+    }
+
+    #[test]
+    fn test_resolve() {
+        let project = project();
+        let path = |s: &str| s.split('.').map(|s| s.to_string()).collect::<Vec<_>>();
+        assert_eq!(
+            project.resolve("main", 0, &path("pkg.sub")),
+            Some("pkg.sub")
+        );
+        assert_eq!(project.resolve("pkg", 1, &path("sub")), Some("pkg.sub"));
+        assert_eq!(project.resolve("pkg.sub.mod", 1, &[]), Some("pkg.sub"));
+        assert_eq!(project.resolve("pkg.sub.mod", 2, &[]), Some("pkg"));
+        assert_eq!(project.resolve("pkg.sub", 2, &[]), Some("pkg"));
+        assert_eq!(project.resolve("pkg", 2, &[]), None);
+        assert_eq!(project.resolve("main", 1, &[]), None);
+        assert_eq!(project.resolve("main", 0, &path("os")), None);
+        assert_eq!(project.imports("main"), ["pkg", "pkg.sub"]);
+        assert_eq!(project.imports("pkg"), ["pkg.sub", "pkg.sub.mod"]);
+        assert_eq!(project.symbols("pkg.sub.mod"), ["f", "h", "os"]);
+    }
+
+    #[test]
+    fn test_find_symbol() {
+        let project = project();
+        let definition = |name, symbol| match project.find_symbol(name, symbol) {
+            Some(Symbol::Definition { module, statement }) => Some((module, statement.clone())),
+            _ => None,
+        };
+        let f = &project.module("pkg.sub.mod").unwrap().module.body[1];
+        assert_eq!(definition("main", "g"), Some(("pkg.sub.mod", f.clone())));
+        assert_eq!(definition("pkg.sub", "f"), Some(("pkg.sub.mod", f.clone())));
+        assert_eq!(
+            project.find_symbol("main", "sub"),
+            Some(Symbol::Module("pkg.sub"))
+        );
+        assert_eq!(
+            project.find_symbol("main", "p"),
+            Some(Symbol::Module("pkg"))
+        );
+        assert_eq!(
+            project.find_symbol("main", "pkg"),
+            Some(Symbol::Module("pkg"))
+        );
+        // Imports from outside the project
+        let import = &project.module("main").unwrap().module.body[3];
+        assert_eq!(definition("main", "path"), Some(("main", import.clone())));
+        assert_eq!(project.find_symbol("main", "missing"), None);
+        assert_eq!(project.find_symbol("main", "f"), None);
+        // The last binding
+        let h = &project.module("pkg.sub.mod").unwrap().module.body[2];
+        let h = match *h {
+            Statement::Compound(ref c) => match **c {
+                CompoundStatement::If(_, Some(ref block)) => block[0].clone(),
+                _ => unreachable!(),
+            },
+            _ => unreachable!(),
+        };
+        assert_eq!(definition("pkg.sub", "h"), Some(("pkg.sub.mod", h)));
+    }
+
+    #[test]
+    fn test_cycle() {
+        let mut project = Project::new();
+        project.add_module("a", "from b import x\n", false).unwrap();
+        project.add_module("b", "from a import x\n", false).unwrap();
+        assert_eq!(project.find_symbol("a", "x"), None);
+    }
+
+    #[test]
+    fn test_files() {
+        let root = env::temp_dir().join(format!("python-parser-project-{}", process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("pkg")).unwrap();
+        fs::write(root.join("pkg/__init__.py"), "x = 1\n").unwrap();
+        fs::write(root.join("pkg/mod.py"), "from . import x\n").unwrap();
+        let mut project = Project::new();
+        assert_eq!(
+            project
+                .add_file(&root, root.join("pkg/__init__.py"))
+                .unwrap(),
+            "pkg"
+        );
+        assert_eq!(
+            project.add_file(&root, root.join("pkg/mod.py")).unwrap(),
+            "pkg.mod"
+        );
+        assert!(project
+            .add_file(&root, env::temp_dir().join("x.py"))
+            .is_err());
+        assert_eq!(project.symbols("pkg"), ["x"]);
+
+        fs::write(root.join("pkg/__init__.py"), "y = 1\n").unwrap();
+        assert_eq!(project.symbols("pkg"), ["x"]);
+        project.reparse("pkg").unwrap();
+        assert_eq!(project.symbols("pkg"), ["y"]);
+        assert_eq!(project.find_symbol("pkg.mod", "x"), None);
+
+        // Syntax errors keep the previous version
+        fs::write(root.join("pkg/__init__.py"), "y = (\n").unwrap();
+        assert!(project.reparse("pkg").is_err());
+        assert_eq!(project.symbols("pkg"), ["y"]);
+
+        assert!(project.remove_module("pkg").is_some());
+        assert!(project.reparse("pkg").is_err());
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_namespace_packages() {
+        let mut project = Project::new();
+        project
+            .add_module("ns.sub.a", "from . import b\n", false)
+            .unwrap();
+        project.add_module("ns.sub.b", "x = 1\n", false).unwrap();
+        project.add_module("nsx", "import ns.sub\n", false).unwrap();
+        assert!(project.is_namespace_package("ns"));
+        assert!(project.is_namespace_package("ns.sub"));
+        assert!(!project.is_namespace_package("ns.sub.a"));
+        assert!(!project.is_namespace_package("n"));
+        assert_eq!(project.imports("nsx"), ["ns", "ns.sub"]);
+        assert_eq!(project.imports("ns.sub.a"), ["ns.sub", "ns.sub.b"]);
+        assert_eq!(project.find_symbol("nsx", "ns"), Some(Symbol::Module("ns")));
+        // A regular package takes precedence
+        project.add_module("ns", "", true).unwrap();
+        assert!(!project.is_namespace_package("ns"));
+    }
+
+    #[test]
+    fn test_stubs() {
+        let mut project = Project::new();
+        project
+            .add_module("m", "def f(x): return x\n", false)
+            .unwrap();
+        project
+            .add_stub("m", "def f(x: int) -> int: ...\n", false)
+            .unwrap();
+        project
+            .add_module("m", "def f(x): return x\ndef g(): pass\n", false)
+            .unwrap();
+        assert!(project.module("m").unwrap().is_stub);
+        assert_eq!(project.symbols("m"), ["f"]);
+        let implementation = project.implementation("m").unwrap();
+        assert!(!implementation.is_stub);
+        assert_eq!(implementation.module.body.len(), 2);
+        assert!(project.remove_module("m").unwrap().is_stub);
+        assert_eq!(project.implementation("m"), None);
+    }
+
+    #[test]
+    fn test_stub_files() {
+        let root = env::temp_dir().join(format!("python-parser-stubs-{}", process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("lib-stubs")).unwrap();
+        fs::write(root.join("lib-stubs/__init__.pyi"), "x: int\n").unwrap();
+        fs::write(root.join("mod.py"), "y = 1\n").unwrap();
+        fs::write(root.join("mod.pyi"), "z: int\n").unwrap();
+        let mut project = Project::new();
+        let mut add = |path: &str| project.add_file(&root, root.join(path)).unwrap();
+        assert_eq!(add("lib-stubs/__init__.pyi"), "lib");
+        assert_eq!(add("mod.pyi"), "mod");
+        assert_eq!(add("mod.py"), "mod");
+        assert_eq!(project.symbols("lib"), ["x"]);
+        assert_eq!(project.symbols("mod"), ["z"]);
+
+        fs::write(root.join("mod.py"), "w = 1\n").unwrap();
+        project.reparse("mod").unwrap();
+        let names = |m: &ProjectModule| {
+            let mut bindings = Vec::new();
+            collect_bindings(&m.module.body, &mut bindings);
+            bindings
+                .into_iter()
+                .map(|(name, _)| name.to_string())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(names(project.implementation("mod").unwrap()), ["w"]);
+
+        // Without the stub, imports resolve to the module
+        assert!(project.remove_file(root.join("mod.pyi")).unwrap().is_stub);
+        assert_eq!(project.symbols("mod"), ["w"]);
+        assert_eq!(project.remove_file(root.join("mod.pyi")), None);
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
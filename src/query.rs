@@ -0,0 +1,544 @@
+//! Selection of the statements and expressions of a module with a small
+//! filter language, like `kind=call and func.name='print' and
+//! in_function`.
+//!
+//! A filter is made of tests combined with `and`, `or`, `not` and
+//! parentheses. A test is either `path=value` or `path!=value`, where
+//! the value is a quoted string or a bare word, or a path alone, which
+//! is true if the path has a value other than `false`.
+//!
+//! A path is a property of the node, possibly after fields leading to
+//! other nodes, like `func.name`. The fields are:
+//!
+//! * `func`: the function of a call;
+//! * `value`: the value of an attribute or subscript.
+//!
+//! The properties are:
+//!
+//! * `kind`: the name of the variant of the node (see `stats`), compared
+//!   ignoring case, like `call`, `attribute` or `funcdef`;
+//! * `name`: the name of a `Name` expression, of the attribute of an
+//!   `Attribute` expression, or of a `def` or `class` statement;
+//! * `in_function`, `in_class` and `in_loop`: whether the node is in a
+//!   `def`, `class`, or `for` or `while` statement (including its
+//!   header), which are `true` or `false`.
+//!
+//! Filters are checked when they are parsed, so unknown paths are
+//! reported before looking at any code.
+
+use std::error::Error;
+use std::fmt;
+
+use ast::*;
+use stats::{expression_kind, statement_kind};
+use visitors::printer::format_module;
+use visitors::walk::{walk_expression, walk_statement, Visitor};
+
+/// A statement or an expression.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Node<'a> {
+    Statement(&'a Statement),
+    Expression(&'a Expression),
+}
+
+impl<'a> Node<'a> {
+    /// Returns the first line of the code of the node, as printed by
+    /// `printer`, like `def f(x):` for a function.
+    pub fn first_line(&self) -> String {
+        let stmt = match *self {
+            Node::Statement(stmt) => stmt.clone(),
+            Node::Expression(expr) => Statement::Expressions(vec![expr.clone()]),
+        };
+        let code = format_module(&[stmt]);
+        let line = code.lines().find(|line| !line.is_empty()).unwrap_or("");
+        line.to_string()
+    }
+}
+
+/// The statements enclosing a node.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Context {
+    pub in_function: bool,
+    pub in_class: bool,
+    pub in_loop: bool,
+}
+
+/// Error returned by `Filter::parse`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FilterError {
+    /// Offset of the invalid part of the filter.
+    pub offset: usize,
+    pub message: String,
+}
+
+impl fmt::Display for FilterError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "invalid filter at offset {}: {}",
+            self.offset, self.message
+        )
+    }
+}
+
+impl Error for FilterError {}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Field {
+    Func,
+    Value,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Property {
+    Kind,
+    Name,
+    InFunction,
+    InClass,
+    InLoop,
+}
+
+/// Where a path leads: a property, or a node if it ends with a field.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Path {
+    fields: Vec<Field>,
+    property: Option<Property>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Condition {
+    Not(Box<Condition>),
+    And(Box<Condition>, Box<Condition>),
+    Or(Box<Condition>, Box<Condition>),
+    Exists(Path),
+    /// `path=value`, or `path!=value` if the boolean is false.
+    Compare(Path, String, bool),
+}
+
+/// A parsed filter.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Filter {
+    condition: Condition,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Token {
+    Word(String),
+    Quoted(String),
+    Equal,
+    NotEqual,
+    Open,
+    Close,
+}
+
+fn tokens(filter: &str) -> Result<Vec<(usize, Token)>, FilterError> {
+    let mut tokens = Vec::new();
+    let mut chars = filter.char_indices().peekable();
+    while let Some((offset, c)) = chars.next() {
+        let token = match c {
+            _ if c.is_whitespace() => continue,
+            '(' => Token::Open,
+            ')' => Token::Close,
+            '=' => Token::Equal,
+            '!' if chars.peek().map(|&(_, c)| c) == Some('=') => {
+                chars.next();
+                Token::NotEqual
+            }
+            '\'' | '"' => {
+                let mut value = String::new();
+                loop {
+                    match chars.next() {
+                        Some((_, end)) if end == c => break,
+                        Some((_, other)) => value.push(other),
+                        None => {
+                            return Err(FilterError {
+                                offset,
+                                message: "unterminated string".to_string(),
+                            })
+                        }
+                    }
+                }
+                Token::Quoted(value)
+            }
+            _ if c.is_alphanumeric() || c == '_' => {
+                let mut word = c.to_string();
+                while let Some(&(_, c)) = chars.peek() {
+                    if !(c.is_alphanumeric() || c == '_' || c == '.') {
+                        break;
+                    }
+                    word.push(c);
+                    chars.next();
+                }
+                Token::Word(word)
+            }
+            _ => {
+                return Err(FilterError {
+                    offset,
+                    message: format!("unexpected `{}`", c),
+                })
+            }
+        };
+        tokens.push((offset, token));
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<(usize, Token)>,
+    pos: usize,
+    /// Length of the filter, the offset of errors at its end.
+    len: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|&(_, ref token)| token)
+    }
+
+    fn offset(&self) -> usize {
+        self.tokens
+            .get(self.pos)
+            .map_or(self.len, |&(offset, _)| offset)
+    }
+
+    fn error(&self, message: &str) -> FilterError {
+        FilterError {
+            offset: self.offset(),
+            message: message.to_string(),
+        }
+    }
+
+    fn is_keyword(&self, keyword: &str) -> bool {
+        self.peek() == Some(&Token::Word(keyword.to_string()))
+    }
+
+    fn or(&mut self) -> Result<Condition, FilterError> {
+        let mut condition = self.and()?;
+        while self.is_keyword("or") {
+            self.pos += 1;
+            condition = Condition::Or(Box::new(condition), Box::new(self.and()?));
+        }
+        Ok(condition)
+    }
+
+    fn and(&mut self) -> Result<Condition, FilterError> {
+        let mut condition = self.not()?;
+        while self.is_keyword("and") {
+            self.pos += 1;
+            condition = Condition::And(Box::new(condition), Box::new(self.not()?));
+        }
+        Ok(condition)
+    }
+
+    fn not(&mut self) -> Result<Condition, FilterError> {
+        if self.is_keyword("not") {
+            self.pos += 1;
+            return Ok(Condition::Not(Box::new(self.not()?)));
+        }
+        self.test()
+    }
+
+    fn test(&mut self) -> Result<Condition, FilterError> {
+        let offset = self.offset();
+        let path = match self.peek().cloned() {
+            Some(Token::Open) => {
+                self.pos += 1;
+                let condition = self.or()?;
+                if self.peek() != Some(&Token::Close) {
+                    return Err(self.error("expected `)`"));
+                }
+                self.pos += 1;
+                return Ok(condition);
+            }
+            Some(Token::Word(ref word)) if !["and", "or", "not"].contains(&&word[..]) => {
+                self.pos += 1;
+                path(word).map_err(|message| FilterError { offset, message })?
+            }
+            _ => return Err(self.error("expected a path")),
+        };
+        let equal = match self.peek() {
+            Some(&Token::Equal) => true,
+            Some(&Token::NotEqual) => false,
+            _ => return Ok(Condition::Exists(path)),
+        };
+        self.pos += 1;
+        match self.peek().cloned() {
+            Some(Token::Word(value)) | Some(Token::Quoted(value)) => {
+                self.pos += 1;
+                Ok(Condition::Compare(path, value, equal))
+            }
+            _ => Err(self.error("expected a value")),
+        }
+    }
+}
+
+fn path(word: &str) -> Result<Path, String> {
+    let mut parts: Vec<_> = word.split('.').collect();
+    let property = match parts.last().cloned() {
+        Some("kind") => Some(Property::Kind),
+        Some("name") => Some(Property::Name),
+        Some("in_function") => Some(Property::InFunction),
+        Some("in_class") => Some(Property::InClass),
+        Some("in_loop") => Some(Property::InLoop),
+        _ => None,
+    };
+    if property.is_some() {
+        parts.pop();
+    }
+    let fields = parts
+        .into_iter()
+        .map(|part| match part {
+            "func" => Ok(Field::Func),
+            "value" => Ok(Field::Value),
+            _ => Err(format!("unknown field `{}`", part)),
+        })
+        .collect::<Result<_, _>>()?;
+    Ok(Path { fields, property })
+}
+
+/// Value of a property.
+enum Value<'a> {
+    Str(&'a str),
+    Bool(bool),
+}
+
+fn field<'a>(node: Node<'a>, field: Field) -> Option<Node<'a>> {
+    match (node, field) {
+        (Node::Expression(&Expression::Call(ref func, _)), Field::Func) => {
+            Some(Node::Expression(func))
+        }
+        (Node::Expression(&Expression::Attribute(ref value, _)), Field::Value)
+        | (Node::Expression(&Expression::Subscript(ref value, _)), Field::Value) => {
+            Some(Node::Expression(value))
+        }
+        _ => None,
+    }
+}
+
+fn property<'a>(node: Node<'a>, property: Property, context: &Context) -> Option<Value<'a>> {
+    match property {
+        Property::Kind => Some(Value::Str(match node {
+            Node::Statement(stmt) => statement_kind(stmt),
+            Node::Expression(expr) => expression_kind(expr),
+        })),
+        Property::Name => match node {
+            Node::Expression(&Expression::Name(ref name))
+            | Node::Expression(&Expression::Attribute(_, ref name)) => Some(Value::Str(name)),
+            Node::Statement(&Statement::Compound(ref stmt)) => match **stmt {
+                CompoundStatement::Funcdef(ref funcdef) => Some(Value::Str(&funcdef.name)),
+                CompoundStatement::Classdef(ref classdef) => Some(Value::Str(&classdef.name)),
+                _ => None,
+            },
+            _ => None,
+        },
+        Property::InFunction => Some(Value::Bool(context.in_function)),
+        Property::InClass => Some(Value::Bool(context.in_class)),
+        Property::InLoop => Some(Value::Bool(context.in_loop)),
+    }
+}
+
+impl Filter {
+    /// Parses a filter.
+    ///
+    /// ```
+    /// use python_parser::query::Filter;
+    ///
+    /// assert!(Filter::parse("kind=call and not (func.name='print' or in_loop)").is_ok());
+    /// let error = Filter::parse("kind=call and args.name=x").unwrap_err();
+    /// assert_eq!(error.offset, 14);
+    /// assert_eq!(error.message, "unknown field `args`");
+    /// ```
+    pub fn parse(filter: &str) -> Result<Filter, FilterError> {
+        let mut parser = Parser {
+            tokens: tokens(filter)?,
+            pos: 0,
+            len: filter.len(),
+        };
+        let condition = parser.or()?;
+        if parser.pos != parser.tokens.len() {
+            return Err(parser.error("expected `and` or `or`"));
+        }
+        Ok(Filter { condition })
+    }
+
+    /// Returns whether a node, enclosed in `context`, is selected.
+    pub fn matches(&self, node: Node, context: &Context) -> bool {
+        matches(&self.condition, node, context)
+    }
+}
+
+fn matches(condition: &Condition, node: Node, context: &Context) -> bool {
+    let value = |path: &Path| -> Option<Option<Value>> {
+        let mut node = node;
+        for &f in &path.fields {
+            node = field(node, f)?;
+        }
+        Some(path.property.and_then(|p| property(node, p, context)))
+    };
+    match *condition {
+        Condition::Not(ref condition) => !matches(condition, node, context),
+        Condition::And(ref left, ref right) => {
+            matches(left, node, context) && matches(right, node, context)
+        }
+        Condition::Or(ref left, ref right) => {
+            matches(left, node, context) || matches(right, node, context)
+        }
+        Condition::Exists(ref path) => match value(path) {
+            Some(None) => path.property.is_none(),
+            Some(Some(Value::Bool(value))) => value,
+            Some(Some(Value::Str(_))) => true,
+            None => false,
+        },
+        Condition::Compare(ref path, ref expected, equal) => {
+            let found = match value(path) {
+                Some(Some(Value::Str(value))) if path.property == Some(Property::Kind) => {
+                    value.eq_ignore_ascii_case(expected)
+                }
+                Some(Some(Value::Str(value))) => value == expected,
+                Some(Some(Value::Bool(value))) => expected == if value { "true" } else { "false" },
+                _ => false,
+            };
+            found == equal
+        }
+    }
+}
+
+struct Selector<'f, F> {
+    filter: &'f Filter,
+    context: Context,
+    callback: F,
+}
+
+impl<'f, F: FnMut(Node, &Context)> Visitor for Selector<'f, F> {
+    fn visit_statement(&mut self, stmt: &Statement) {
+        if self.filter.matches(Node::Statement(stmt), &self.context) {
+            (self.callback)(Node::Statement(stmt), &self.context);
+        }
+        let context = self.context;
+        if let Statement::Compound(ref compound) = *stmt {
+            match **compound {
+                CompoundStatement::Funcdef(_) => self.context.in_function = true,
+                CompoundStatement::Classdef(_) => self.context.in_class = true,
+                CompoundStatement::For { .. } | CompoundStatement::While(..) => {
+                    self.context.in_loop = true
+                }
+                _ => (),
+            }
+        }
+        walk_statement(self, stmt);
+        self.context = context;
+    }
+
+    fn visit_expression(&mut self, expr: &Expression) {
+        if self.filter.matches(Node::Expression(expr), &self.context) {
+            (self.callback)(Node::Expression(expr), &self.context);
+        }
+        walk_expression(self, expr);
+    }
+}
+
+/// Calls `callback` on the statements and expressions of `stmts`
+/// selected by `filter`, in the order of the code.
+///
+/// ```
+/// use python_parser::parse_module;
+/// use python_parser::query::{select, Filter, Node};
+///
+/// let module = parse_module("print(1)\ndef f():\n    print(2)\n    log(3)\n").unwrap();
+/// let filter = Filter::parse("kind=call and func.name='print' and in_function").unwrap();
+/// let mut calls = Vec::new();
+/// select(&module.body, &filter, |node, _| {
+///     if let Node::Expression(expr) = node {
+///         calls.push(expr.clone());
+///     }
+/// });
+/// assert_eq!(calls.len(), 1);
+/// ```
+pub fn select<F: FnMut(Node, &Context)>(stmts: &[Statement], filter: &Filter, callback: F) {
+    let mut selector = Selector {
+        filter,
+        context: Context::default(),
+        callback,
+    };
+    for stmt in stmts {
+        selector.visit_statement(stmt);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parse_module;
+
+    fn selected(code: &str, filter: &str) -> Vec<String> {
+        let module = parse_module(code).unwrap();
+        let filter = Filter::parse(filter).unwrap();
+        let mut selected = Vec::new();
+        select(&module.body, &filter, |node, _| {
+            selected.push(node.first_line())
+        });
+        selected
+    }
+
+    #[test]
+    fn test_select() {
+        let code = "import os
+print(os.path.join(a, b))
+class A:
+    def f(self):
+        for x in self.items:
+            print(x)
+        return os.getcwd()
+";
+        assert_eq!(
+            selected(code, "kind=call and func.name='print' and in_function"),
+            vec!["print(x)"]
+        );
+        assert_eq!(
+            selected(code, "kind=Attribute and value.name=os"),
+            vec!["os.path", "os.getcwd"]
+        );
+        assert_eq!(
+            selected(code, "func.value and not in_class"),
+            vec!["os.path.join(a, b)"]
+        );
+        assert_eq!(
+            selected(code, "(kind=funcdef or kind=classdef) and name != A"),
+            vec!["def f(self):"]
+        );
+        assert_eq!(
+            selected(code, "in_loop=true and kind=name"),
+            vec!["x", "self", "print", "x"]
+        );
+        assert_eq!(selected(code, "func.func"), Vec::<String>::new());
+    }
+
+    #[test]
+    fn test_parse() {
+        let error = |filter| Filter::parse(filter).unwrap_err();
+        assert_eq!(
+            error("kind = 'call"),
+            FilterError {
+                offset: 7,
+                message: "unterminated string".to_string()
+            }
+        );
+        assert_eq!(error("kind=call and").offset, 13);
+        assert_eq!(error("kind=call name").message, "expected `and` or `or`");
+        assert_eq!(error("(kind=call").message, "expected `)`");
+        assert_eq!(error("kind=").message, "expected a value");
+        assert_eq!(error("kind > 1").message, "unexpected `>`");
+        assert_eq!(error("foo").message, "unknown field `foo`");
+        assert_eq!(
+            Filter::parse("not not in_loop"),
+            Ok(Filter {
+                condition: Condition::Not(Box::new(Condition::Not(Box::new(Condition::Exists(
+                    Path {
+                        fields: vec![],
+                        property: Some(Property::InLoop),
+                    }
+                )))))
+            })
+        );
+    }
+}
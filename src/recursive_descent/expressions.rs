@@ -0,0 +1,782 @@
+use std::collections::HashSet;
+
+use super::{PResult, Parser};
+
+use ast::*;
+use bytes::bytes;
+use errors::PyParseError;
+use expressions::{make_bop, make_slice, make_ternary, TestlistCompReturn};
+use helpers::into_vec;
+use keywords::{is_keyword, PARSED_VERSION};
+use numbers::number;
+use strings::string;
+use tokenizer::{Token, TokenKind};
+
+/// Binary operators with the same precedence, from the lowest to the
+/// highest precedence.
+#[derive(Clone, Copy)]
+enum BopLevel {
+    Or,
+    And,
+    Comparison,
+    BitOr,
+    BitXor,
+    BitAnd,
+    Shift,
+    Arith,
+    Term,
+}
+
+impl<'a> Parser<'a> {
+    /// Returns whether the current token can start an expression.
+    pub(super) fn starts_expression(&self) -> bool {
+        let token = self.peek();
+        match token.kind {
+            TokenKind::Number | TokenKind::String => true,
+            TokenKind::Name => match token.text {
+                "None" | "True" | "False" | "not" | "lambda" | "await" => true,
+                name => !is_keyword(name, PARSED_VERSION),
+            },
+            TokenKind::Op => matches!(token.text, "(" | "[" | "{" | "-" | "+" | "~" | "*" | "..."),
+            _ => false,
+        }
+    }
+
+    /// Returns whether the current token starts a `comp_for`.
+    fn is_comp_for(&self) -> bool {
+        self.is_keyword("for")
+            || (self.is_keyword("async") && {
+                let next = self.peek_nth(1);
+                next.kind == TokenKind::Name && next.text == "for"
+            })
+    }
+
+    // namedexpr_test: test [':=' test]
+    pub(super) fn namedexpr_test(&mut self) -> PResult<Box<Expression>> {
+        let left = self.test()?;
+        if self.eat_op(":=") {
+            let right = self.test()?;
+            Ok(Box::new(Expression::Named(left, right)))
+        } else {
+            Ok(left)
+        }
+    }
+
+    // test: or_test ['if' or_test 'else' test] | lambdef
+    pub(super) fn test(&mut self) -> PResult<Box<Expression>> {
+        if self.is_keyword("lambda") {
+            return self.lambdef(false);
+        }
+        let first = self.or_test()?;
+        let mut branches = Vec::new();
+        while self.eat_keyword("if") {
+            let cond = self.or_test()?;
+            self.expect_keyword("else")?;
+            let right = if self.is_keyword("lambda") {
+                self.lambdef(false)?
+            } else {
+                self.or_test()?
+            };
+            branches.push((cond, right));
+        }
+        Ok(make_ternary(first, branches))
+    }
+
+    // test_nocond: or_test | lambdef_nocond
+    fn test_nocond(&mut self) -> PResult<Box<Expression>> {
+        if self.is_keyword("lambda") {
+            self.lambdef(true)
+        } else {
+            self.or_test()
+        }
+    }
+
+    // lambdef: 'lambda' [varargslist] ':' test
+    // lambdef_nocond: 'lambda' [varargslist] ':' test_nocond
+    fn lambdef(&mut self, nocond: bool) -> PResult<Box<Expression>> {
+        self.expect_keyword("lambda")?;
+        let args = self.parameters(false, ":")?;
+        self.expect_op(":")?;
+        let code = if nocond {
+            self.test_nocond()?
+        } else {
+            self.test()?
+        };
+        let args = UntypedArgsList {
+            posonly_args: args
+                .posonly_args
+                .into_iter()
+                .map(|(n, _, d)| (n, d))
+                .collect(),
+            args: args.args.into_iter().map(|(n, _, d)| (n, d)).collect(),
+            star_args: match args.star_args {
+                StarParams::No => StarParams::No,
+                StarParams::Anonymous => StarParams::Anonymous,
+                StarParams::Named((name, _)) => StarParams::Named(name),
+            },
+            keyword_args: args
+                .keyword_args
+                .into_iter()
+                .map(|(n, _, d)| (n, d))
+                .collect(),
+            star_kwargs: args.star_kwargs.map(|(name, _)| name),
+        };
+        Ok(Box::new(Expression::Lambdef(args, code)))
+    }
+
+    /// Parses the parameters of a function definition (`typed`) or of a
+    /// lambda, up to `closing`.
+    pub(super) fn parameters(&mut self, typed: bool, closing: &str) -> PResult<TypedArgsList> {
+        let mut list = TypedArgsList::default();
+        let mut seen_slash = false;
+        let mut seen_star = None;
+        let mut seen_default = false;
+        let mut names = HashSet::new();
+        while !self.is_op(closing) {
+            if list.star_kwargs.is_some() {
+                return self.error();
+            }
+            if self.is_op("/") {
+                if seen_slash || seen_star.is_some() || list.args.is_empty() {
+                    return self.error();
+                }
+                self.advance();
+                seen_slash = true;
+                list.posonly_args = ::std::mem::take(&mut list.args);
+            } else if self.is_op("**") {
+                self.check_bare_star(seen_star, &list)?;
+                self.advance();
+                let name = self.parameter_name(&mut names)?;
+                let annotation = self.annotation(typed)?;
+                list.star_kwargs = Some((name, annotation));
+            } else if self.is_op("*") {
+                if seen_star.is_some() {
+                    return self.error();
+                }
+                seen_star = Some(self.advance());
+                list.star_args = if self.is_op(",") || self.is_op(closing) {
+                    StarParams::Anonymous
+                } else {
+                    let name = self.parameter_name(&mut names)?;
+                    StarParams::Named((name, self.annotation(typed)?))
+                };
+            } else {
+                let token = self.peek();
+                let name = self.parameter_name(&mut names)?;
+                let annotation = self.annotation(typed)?;
+                let default = if self.eat_op("=") {
+                    Some(*self.test()?)
+                } else {
+                    None
+                };
+                if seen_star.is_some() {
+                    list.keyword_args.push((name, annotation, default));
+                } else if seen_default && default.is_none() {
+                    return self.error_at(token, PyParseError::NonDefaultAfterDefault);
+                } else {
+                    seen_default |= default.is_some();
+                    list.args.push((name, annotation, default));
+                }
+            }
+            if !self.eat_op(",") {
+                break;
+            }
+        }
+        if list.star_kwargs.is_none() {
+            self.check_bare_star(seen_star, &list)?;
+        }
+        Ok(list)
+    }
+
+    /// Parses the name of a parameter, which must not be in `names`
+    /// (like in CPython).
+    fn parameter_name(&mut self, names: &mut HashSet<&'a str>) -> PResult<Name> {
+        let token = self.peek();
+        let name = self.name()?;
+        if names.insert(token.text) {
+            Ok(name)
+        } else {
+            self.error_at(token, PyParseError::DuplicateParameter)
+        }
+    }
+
+    /// Fails like CPython if there is a bare star without keyword-only
+    /// parameters.
+    fn check_bare_star(&self, star: Option<Token<'a>>, list: &TypedArgsList) -> PResult<()> {
+        match star {
+            Some(star)
+                if list.star_args == StarParams::Anonymous && list.keyword_args.is_empty() =>
+            {
+                self.error_at(star, PyParseError::NoParameterAfterBareStar)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn annotation(&mut self, typed: bool) -> PResult<Option<Expression>> {
+        if typed && self.eat_op(":") {
+            Ok(Some(*self.test()?))
+        } else {
+            Ok(None)
+        }
+    }
+
+    // or_test: and_test ('or' and_test)*
+    pub(super) fn or_test(&mut self) -> PResult<Box<Expression>> {
+        self.bop(BopLevel::Or)
+    }
+
+    fn bop(&mut self, level: BopLevel) -> PResult<Box<Expression>> {
+        let first = self.bop_operand(level)?;
+        let mut rest = Vec::new();
+        while let Some(op) = self.bop_operator(level) {
+            rest.push((op, *self.bop_operand(level)?));
+        }
+        Ok(make_bop(first, rest))
+    }
+
+    fn bop_operand(&mut self, level: BopLevel) -> PResult<Box<Expression>> {
+        match level {
+            BopLevel::Or => self.bop(BopLevel::And),
+            BopLevel::And => self.not_test(),
+            BopLevel::Comparison => self.expr(),
+            BopLevel::BitOr => self.bop(BopLevel::BitXor),
+            BopLevel::BitXor => self.bop(BopLevel::BitAnd),
+            BopLevel::BitAnd => self.bop(BopLevel::Shift),
+            BopLevel::Shift => self.bop(BopLevel::Arith),
+            BopLevel::Arith => self.bop(BopLevel::Term),
+            BopLevel::Term => self.factor(),
+        }
+    }
+
+    /// Consumes the next operator if it is one of `level`.
+    fn bop_operator(&mut self, level: BopLevel) -> Option<Bop> {
+        let token = self.peek();
+        let op = match (level, token.kind, token.text) {
+            (BopLevel::Or, TokenKind::Name, "or") => Bop::Or,
+            (BopLevel::And, TokenKind::Name, "and") => Bop::And,
+            (BopLevel::Comparison, TokenKind::Op, text) => match text {
+                "<" => Bop::Lt,
+                ">" => Bop::Gt,
+                "==" => Bop::Eq,
+                "<=" => Bop::Leq,
+                ">=" => Bop::Geq,
+                "!=" => Bop::Neq,
+                _ => return None,
+            },
+            (BopLevel::Comparison, TokenKind::Name, "in") => Bop::In,
+            (BopLevel::Comparison, TokenKind::Name, "not") => {
+                let next = self.peek_nth(1);
+                if next.kind != TokenKind::Name || next.text != "in" {
+                    return None;
+                }
+                self.advance();
+                Bop::NotIn
+            }
+            (BopLevel::Comparison, TokenKind::Name, "is") => {
+                let next = self.peek_nth(1);
+                if next.kind == TokenKind::Name && next.text == "not" {
+                    self.advance();
+                    Bop::IsNot
+                } else {
+                    Bop::Is
+                }
+            }
+            (BopLevel::BitOr, TokenKind::Op, "|") => Bop::BitOr,
+            (BopLevel::BitXor, TokenKind::Op, "^") => Bop::BitXor,
+            (BopLevel::BitAnd, TokenKind::Op, "&") => Bop::BitAnd,
+            (BopLevel::Shift, TokenKind::Op, "<<") => Bop::Lshift,
+            (BopLevel::Shift, TokenKind::Op, ">>") => Bop::Rshift,
+            (BopLevel::Arith, TokenKind::Op, "+") => Bop::Add,
+            (BopLevel::Arith, TokenKind::Op, "-") => Bop::Sub,
+            (BopLevel::Term, TokenKind::Op, "*") => Bop::Mult,
+            (BopLevel::Term, TokenKind::Op, "@") => Bop::Matmult,
+            (BopLevel::Term, TokenKind::Op, "/") => Bop::Div,
+            (BopLevel::Term, TokenKind::Op, "%") => Bop::Mod,
+            (BopLevel::Term, TokenKind::Op, "//") => Bop::Floordiv,
+            _ => return None,
+        };
+        self.advance();
+        Some(op)
+    }
+
+    // not_test: 'not' not_test | comparison
+    fn not_test(&mut self) -> PResult<Box<Expression>> {
+        let mut nots = 0;
+        while self.eat_keyword("not") {
+            nots += 1;
+        }
+        let e = self.bop(BopLevel::Comparison)?;
+        Ok((0..nots).fold(e, |e, _| Box::new(Expression::Uop(Uop::Not, e))))
+    }
+
+    // expr: xor_expr ('|' xor_expr)*
+    pub(super) fn expr(&mut self) -> PResult<Box<Expression>> {
+        self.bop(BopLevel::BitOr)
+    }
+
+    // star_expr: '*' expr
+    fn expr_or_star_expr(&mut self) -> PResult<Box<Expression>> {
+        if self.eat_op("*") {
+            Ok(Box::new(Expression::Star(self.expr()?)))
+        } else {
+            self.expr()
+        }
+    }
+
+    fn test_or_star_expr(&mut self) -> PResult<Box<Expression>> {
+        if self.eat_op("*") {
+            Ok(Box::new(Expression::Star(self.expr()?)))
+        } else {
+            self.test()
+        }
+    }
+
+    // factor: ('+'|'-'|'~') factor | power
+    fn factor(&mut self) -> PResult<Box<Expression>> {
+        let mut ops = Vec::new();
+        loop {
+            let op = match (self.peek().kind, self.peek().text) {
+                (TokenKind::Op, "+") => Uop::Plus,
+                (TokenKind::Op, "-") => Uop::Minus,
+                (TokenKind::Op, "~") => Uop::Invert,
+                _ => break,
+            };
+            self.advance();
+            ops.push(op);
+        }
+        let e = self.power()?;
+        Ok(ops
+            .into_iter()
+            .rev()
+            .fold(e, |e, op| Box::new(Expression::Uop(op, e))))
+    }
+
+    // power: atom_expr ['**' factor]
+    fn power(&mut self) -> PResult<Box<Expression>> {
+        self.nested(|p| {
+            let lhs = p.atom_expr()?;
+            if p.eat_op("**") {
+                let rhs = p.factor()?;
+                Ok(Box::new(Expression::Bop(Bop::Power, lhs, rhs)))
+            } else {
+                Ok(lhs)
+            }
+        })
+    }
+
+    // atom_expr: ['await'] atom trailer*
+    // trailer: '(' [arglist] ')' | '[' subscriptlist ']' | '.' NAME
+    fn atom_expr(&mut self) -> PResult<Box<Expression>> {
+        let is_await = self.eat_keyword("await");
+        let mut e = self.atom()?;
+        loop {
+            if self.eat_op("(") {
+                let args = self.arglist()?;
+                self.expect_op(")")?;
+                e = Box::new(Expression::Call(e, args));
+            } else if self.eat_op("[") {
+                let subscripts = self.subscriptlist()?;
+                self.expect_op("]")?;
+                e = Box::new(Expression::Subscript(e, subscripts));
+            } else if self.eat_op(".") {
+                e = Box::new(Expression::Attribute(e, self.name()?));
+            } else {
+                break;
+            }
+        }
+        if is_await {
+            Ok(Box::new(Expression::Await(e)))
+        } else {
+            Ok(e)
+        }
+    }
+
+    // atom: ('(' [yield_expr|testlist_comp] ')' |
+    //       '[' [testlist_comp] ']' |
+    //       '{' [dictorsetmaker] '}' |
+    //       NAME | NUMBER | STRING+ | '...' | 'None' | 'True' | 'False')
+    fn atom(&mut self) -> PResult<Box<Expression>> {
+        self.spend_fuel()?;
+        let token = self.peek();
+        let e = match (token.kind, token.text) {
+            (TokenKind::Op, "(") => {
+                self.advance();
+                let e = if self.is_op(")") {
+                    Expression::TupleLiteral(Vec::new())
+                } else if self.is_keyword("yield") {
+                    self.yield_expr()?
+                } else {
+                    match self.testlist_comp()? {
+                        TestlistCompReturn::Comp(e, comp) => Expression::Generator(e, comp),
+                        TestlistCompReturn::Lit(v) => Expression::TupleLiteral(v),
+                        TestlistCompReturn::Single(SetItem::Unique(e)) => e,
+                        TestlistCompReturn::Single(SetItem::Star(_)) => return self.error(),
+                    }
+                };
+                self.expect_op(")")?;
+                e
+            }
+            (TokenKind::Op, "[") => {
+                self.advance();
+                let e = if self.is_op("]") {
+                    Expression::ListLiteral(Vec::new())
+                } else {
+                    match self.testlist_comp()? {
+                        TestlistCompReturn::Comp(e, comp) => Expression::ListComp(e, comp),
+                        TestlistCompReturn::Lit(v) => Expression::ListLiteral(v),
+                        TestlistCompReturn::Single(e) => Expression::ListLiteral(vec![e]),
+                    }
+                };
+                self.expect_op("]")?;
+                e
+            }
+            (TokenKind::Op, "{") => {
+                self.advance();
+                let e = if self.is_op("}") {
+                    Expression::DictLiteral(Vec::new())
+                } else {
+                    self.dictorsetmaker()?
+                };
+                self.expect_op("}")?;
+                e
+            }
+            (TokenKind::Op, "...") => {
+                self.advance();
+                Expression::Ellipsis
+            }
+            (TokenKind::Name, "None") => {
+                self.advance();
+                Expression::None
+            }
+            (TokenKind::Name, "True") => {
+                self.advance();
+                Expression::True
+            }
+            (TokenKind::Name, "False") => {
+                self.advance();
+                Expression::False
+            }
+            (TokenKind::Name, _) => Expression::Name(self.name()?),
+            (TokenKind::Number, _) => {
+                self.advance();
+                self.literal(token, number)?
+            }
+            (TokenKind::String, _) => self.strings()?,
+            _ => return self.error(),
+        };
+        Ok(Box::new(e))
+    }
+
+    // STRING+
+    fn strings(&mut self) -> PResult<Expression> {
+        let is_bytes = |text: &str| {
+            text.chars()
+                .take_while(|&c| c != '\'' && c != '"')
+                .any(|c| c == 'b' || c == 'B')
+        };
+        if is_bytes(self.peek().text) {
+            let mut content = Vec::new();
+            while self.is_kind(TokenKind::String) && is_bytes(self.peek().text) {
+                let token = self.advance();
+                content.extend(self.literal(token, bytes)?);
+            }
+            if self.is_kind(TokenKind::String) {
+                return self.error_code(PyParseError::BytesAndNonBytes);
+            }
+            Ok(Expression::Bytes(content))
+        } else {
+            let mut strings = Vec::new();
+            while self.is_kind(TokenKind::String) && !is_bytes(self.peek().text) {
+                let token = self.advance();
+                strings.push(self.literal(token, string)?);
+            }
+            if self.is_kind(TokenKind::String) {
+                return self.error_code(PyParseError::BytesAndNonBytes);
+            }
+            Ok(Expression::String(strings))
+        }
+    }
+
+    // testlist_comp: (namedexpr_test|star_expr) ( comp_for | (',' (namedexpr_test|star_expr))* [','] )
+    fn testlist_comp(&mut self) -> PResult<TestlistCompReturn> {
+        let first = self.testlist_comp_item()?;
+        if self.is_comp_for() {
+            return Ok(TestlistCompReturn::Comp(Box::new(first), self.comp_for()?));
+        }
+        if !self.eat_op(",") {
+            return Ok(TestlistCompReturn::Single(first));
+        }
+        let mut items = vec![first];
+        while self.starts_expression() {
+            items.push(self.testlist_comp_item()?);
+            if !self.eat_op(",") {
+                break;
+            }
+        }
+        Ok(TestlistCompReturn::Lit(items))
+    }
+
+    fn testlist_comp_item(&mut self) -> PResult<SetItem> {
+        if self.eat_op("*") {
+            Ok(SetItem::Star(*self.expr()?))
+        } else {
+            Ok(SetItem::Unique(*self.namedexpr_test()?))
+        }
+    }
+
+    // subscriptlist: subscript (',' subscript)* [',']
+    fn subscriptlist(&mut self) -> PResult<Vec<Subscript>> {
+        let mut subscripts = vec![self.subscript()?];
+        let mut trailing_comma = false;
+        while self.eat_op(",") {
+            trailing_comma = self.is_op("]");
+            if trailing_comma {
+                break;
+            }
+            subscripts.push(self.subscript()?);
+        }
+        match (trailing_comma, subscripts.len()) {
+            (true, 1) => match subscripts.pop() {
+                // `foo[bar,]` is indexed by a tuple
+                Some(Subscript::Simple(e)) => {
+                    Ok(vec![Subscript::Simple(Expression::TupleLiteral(vec![
+                        SetItem::Unique(e),
+                    ]))])
+                }
+                _ => self.error(),
+            },
+            _ => Ok(subscripts),
+        }
+    }
+
+    // subscript: test | [test] ':' [test] [sliceop]
+    // sliceop: ':' [test]
+    fn subscript(&mut self) -> PResult<Subscript> {
+        let first = if self.is_op(":") {
+            None
+        } else {
+            let e = self.test()?;
+            if !self.is_op(":") {
+                return Ok(Subscript::Simple(*e));
+            }
+            Some(*e)
+        };
+        self.expect_op(":")?;
+        let second = self.opt_test()?;
+        let third = if self.eat_op(":") {
+            Some(self.opt_test()?)
+        } else {
+            None
+        };
+        Ok(make_slice(first, second, third))
+    }
+
+    fn opt_test(&mut self) -> PResult<Option<Expression>> {
+        if self.starts_expression() {
+            Ok(Some(*self.test()?))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Parses a comma-separated list of items, with an optional trailing
+    /// comma. Returns the items, and whether there is a trailing comma.
+    fn comma_list<F>(&mut self, item: F) -> PResult<(SmallList<Expression>, bool)>
+    where
+        F: Fn(&mut Self) -> PResult<Box<Expression>>,
+    {
+        let mut items = small_list![*item(self)?];
+        while self.eat_op(",") {
+            if !self.starts_expression() {
+                return Ok((items, true));
+            }
+            items.push(*item(self)?);
+        }
+        Ok((items, false))
+    }
+
+    /// Turns `foo,` into a tuple, so it is not confused with `foo`.
+    fn tuple_if_trailing_comma(
+        (items, trailing_comma): (SmallList<Expression>, bool),
+    ) -> SmallList<Expression> {
+        if trailing_comma && items.len() < 2 {
+            small_list![Expression::TupleLiteral(
+                items.into_iter().map(SetItem::Unique).collect(),
+            )]
+        } else {
+            items
+        }
+    }
+
+    // exprlist: (expr|star_expr) (',' (expr|star_expr))* [',']
+    pub(super) fn exprlist(&mut self) -> PResult<SmallList<Expression>> {
+        let list = self.comma_list(Self::expr_or_star_expr)?;
+        Ok(Self::tuple_if_trailing_comma(list))
+    }
+
+    /// Like `exprlist`, but ignores the trailing comma.
+    pub(super) fn exprlist_no_tuple(&mut self) -> PResult<Vec<Expression>> {
+        Ok(into_vec(self.comma_list(Self::expr_or_star_expr)?.0))
+    }
+
+    // testlist: test (',' test)* [',']
+    pub(super) fn testlist(&mut self) -> PResult<Vec<Expression>> {
+        let list = self.comma_list(Self::test)?;
+        Ok(into_vec(Self::tuple_if_trailing_comma(list)))
+    }
+
+    // testlist_star_expr: (test|star_expr) (',' (test|star_expr))* [',']
+    pub(super) fn testlist_star_expr(&mut self) -> PResult<SmallList<Expression>> {
+        let list = self.comma_list(Self::test_or_star_expr)?;
+        Ok(Self::tuple_if_trailing_comma(list))
+    }
+
+    // dictorsetmaker: ( ((test ':' test | '**' expr)
+    //                    (comp_for | (',' (test ':' test | '**' expr))* [','])) |
+    //                   ((test | star_expr)
+    //                    (comp_for | (',' (test | star_expr))* [','])) )
+    fn dictorsetmaker(&mut self) -> PResult<Expression> {
+        if self.eat_op("**") {
+            let item = DictItem::Star(*self.expr()?);
+            return self.dictmaker(item);
+        }
+        if self.eat_op("*") {
+            let item = SetItem::Star(*self.expr()?);
+            return self.setmaker(item);
+        }
+        let key = self.test()?;
+        if self.eat_op(":") {
+            let value = self.test()?;
+            self.dictmaker(DictItem::Unique(*key, *value))
+        } else {
+            self.setmaker(SetItem::Unique(*key))
+        }
+    }
+
+    fn dictmaker(&mut self, item1: DictItem) -> PResult<Expression> {
+        if self.is_comp_for() {
+            return Ok(Expression::DictComp(Box::new(item1), self.comp_for()?));
+        }
+        let mut items = vec![item1];
+        while self.eat_op(",") && !self.is_op("}") {
+            if self.eat_op("**") {
+                items.push(DictItem::Star(*self.expr()?));
+            } else {
+                let key = self.test()?;
+                self.expect_op(":")?;
+                items.push(DictItem::Unique(*key, *self.test()?));
+            }
+        }
+        Ok(Expression::DictLiteral(items))
+    }
+
+    fn setmaker(&mut self, item1: SetItem) -> PResult<Expression> {
+        if self.is_comp_for() {
+            return Ok(Expression::SetComp(Box::new(item1), self.comp_for()?));
+        }
+        let mut items = vec![item1];
+        while self.eat_op(",") && !self.is_op("}") {
+            if self.eat_op("*") {
+                items.push(SetItem::Star(*self.expr()?));
+            } else {
+                items.push(SetItem::Unique(*self.test()?));
+            }
+        }
+        Ok(Expression::SetLiteral(items))
+    }
+
+    // arglist: argument (',' argument)*  [',']
+    pub(super) fn arglist(&mut self) -> PResult<Vec<Argument>> {
+        let mut args = Vec::new();
+        let mut names = HashSet::new();
+        while !self.is_op(")") {
+            let next = self.peek_nth(1);
+            if self.is_kind(TokenKind::Name)
+                && next.kind == TokenKind::Op
+                && next.text == "="
+                && !names.insert(self.peek().text)
+            {
+                return self.error_code(PyParseError::DuplicateKeywordArgument);
+            }
+            args.push(self.argument()?);
+            if !self.eat_op(",") {
+                break;
+            }
+        }
+        Ok(args)
+    }
+
+    // argument: ( test [comp_for] |
+    //             test ':=' test |
+    //             test '=' test |
+    //             '**' test |
+    //             '*' test )
+    fn argument(&mut self) -> PResult<Argument> {
+        if self.eat_op("**") {
+            return Ok(Argument::Kwargs(*self.test()?));
+        }
+        if self.eat_op("*") {
+            return Ok(Argument::Starargs(*self.test()?));
+        }
+        let next = self.peek_nth(1);
+        if self.is_kind(TokenKind::Name) && next.kind == TokenKind::Op && next.text == "=" {
+            let name = self.name()?;
+            self.advance();
+            return Ok(Argument::Keyword(name, *self.test()?));
+        }
+        let e = self.test()?;
+        if self.eat_op(":=") {
+            Ok(Argument::Positional(Expression::Named(e, self.test()?)))
+        } else if self.is_comp_for() {
+            let comp = self.comp_for()?;
+            Ok(Argument::Positional(Expression::Generator(
+                Box::new(SetItem::Unique(*e)),
+                comp,
+            )))
+        } else {
+            Ok(Argument::Positional(*e))
+        }
+    }
+
+    // comp_iter: comp_for | comp_if
+    // comp_for: ['async'] 'for' exprlist 'in' or_test [comp_iter]
+    // comp_if: 'if' test_nocond [comp_iter]
+    fn comp_for(&mut self) -> PResult<Vec<ComprehensionChunk>> {
+        let mut chunks = Vec::new();
+        loop {
+            if self.is_comp_for() {
+                let async = self.eat_keyword("async");
+                self.expect_keyword("for")?;
+                let item = self.exprlist()?;
+                self.expect_keyword("in")?;
+                let iterator = *self.or_test()?;
+                chunks.push(ComprehensionChunk::For {
+                    async,
+                    item,
+                    iterator,
+                });
+            } else if !chunks.is_empty() && self.eat_keyword("if") {
+                let cond = *self.test_nocond()?;
+                chunks.push(ComprehensionChunk::If { cond });
+            } else if chunks.is_empty() {
+                return self.error();
+            } else {
+                return Ok(chunks);
+            }
+        }
+    }
+
+    // yield_expr: 'yield' [yield_arg]
+    // yield_arg: 'from' test | testlist_star_expr
+    pub(super) fn yield_expr(&mut self) -> PResult<Expression> {
+        self.expect_keyword("yield")?;
+        if self.eat_keyword("from") {
+            Ok(Expression::YieldFrom(self.test()?))
+        } else if self.starts_expression() {
+            Ok(Expression::Yield(into_vec(self.testlist_star_expr()?)))
+        } else {
+            Ok(Expression::Yield(Vec::new()))
+        }
+    }
+}
@@ -0,0 +1,377 @@
+//! A hand-written recursive-descent parser, enabled by the
+//! `recursive-descent` feature.
+//!
+//! It works on the output of the `tokenizer` instead of backtracking
+//! over the code like the nom-based parsers, which makes it more than
+//! twice as fast, and it returns the same AST.
+//!
+//! On code the nom-based parsers accept, both return the same AST,
+//! except where they deviate from Python's grammar (for instance,
+//! `return` alone is parsed by them as an expression statement).
+
+mod expressions;
+mod statements;
+
+use std::mem;
+
+use nom::types::CompleteStr;
+use nom::IResult;
+use nom_locate::LocatedSpan;
+
+use ast::*;
+use budget;
+use errors::{ParseError, PyParseError};
+use helpers::StrSpan;
+use keywords::{is_keyword, PARSED_VERSION};
+use tokenizer::{tokenize, Token, TokenKind};
+
+type PResult<T> = Result<T, ParseError>;
+
+struct Parser<'a> {
+    /// Tokens of the code, without comments and non-logical newlines.
+    /// Always ends with an `EndMarker`.
+    tokens: Vec<Token<'a>>,
+    pos: usize,
+    /// Nesting of the expression being parsed.
+    depth: usize,
+}
+
+/// The memory of the tokens of a parse, kept for the following ones
+/// (see `session::Parser`). It has no tokens between parses.
+#[derive(Debug, Default)]
+pub(crate) struct TokenBuffer(pub(crate) Vec<Token<'static>>);
+
+/// Returns the memory of `tokens`, without its tokens, for tokens of
+/// another lifetime.
+fn recycle<'a, 'b>(mut tokens: Vec<Token<'a>>) -> Vec<Token<'b>> {
+    tokens.clear();
+    // Reuses the allocation, as the types have the same layout
+    tokens.into_iter().map(|_| unreachable!()).collect()
+}
+
+impl<'a> Parser<'a> {
+    fn new(code: &'a str, buffer: &mut TokenBuffer) -> Parser<'a> {
+        let mut tokens = recycle(mem::take(&mut buffer.0));
+        tokens.extend(
+            tokenize(code).filter(|t| t.kind != TokenKind::Comment && t.kind != TokenKind::Nl),
+        );
+        Parser {
+            tokens,
+            pos: 0,
+            depth: 0,
+        }
+    }
+
+    /// Gives the memory of the tokens back to `buffer`.
+    fn release(self, buffer: &mut TokenBuffer) {
+        buffer.0 = recycle(self.tokens);
+    }
+
+    fn peek(&self) -> Token<'a> {
+        self.tokens[self.pos]
+    }
+
+    fn peek_nth(&self, n: usize) -> Token<'a> {
+        self.tokens[::std::cmp::min(self.pos + n, self.tokens.len() - 1)]
+    }
+
+    fn advance(&mut self) -> Token<'a> {
+        let token = self.tokens[self.pos];
+        if token.kind != TokenKind::EndMarker {
+            self.pos += 1;
+        }
+        token
+    }
+
+    /// Spends fuel of the current `budget`, failing at the next token
+    /// if parsing must stop.
+    fn spend_fuel(&self) -> PResult<()> {
+        match budget::spend() {
+            Some(code) => self.error_code(code),
+            None => Ok(()),
+        }
+    }
+
+    /// Runs `f` one level of nesting deeper, failing at the next token
+    /// if it would go over the limit of `budget::max_nesting`.
+    fn nested<T, F: FnOnce(&mut Self) -> PResult<T>>(&mut self, f: F) -> PResult<T> {
+        if self.depth > budget::max_nesting() {
+            return self.error_code(PyParseError::NestedTooDeeply);
+        }
+        self.depth += 1;
+        let res = budget::grow_stack(|| f(self));
+        self.depth -= 1;
+        res
+    }
+
+    fn error<T>(&self) -> PResult<T> {
+        let token = self.peek();
+        Err(ParseError {
+            offset: token.offset,
+            line: token.line,
+            code: None,
+        })
+    }
+
+    fn error_code<T>(&self, code: PyParseError) -> PResult<T> {
+        self.error_at(self.peek(), code)
+    }
+
+    fn error_at<T>(&self, token: Token<'a>, code: PyParseError) -> PResult<T> {
+        Err(ParseError {
+            offset: token.offset,
+            line: token.line,
+            code: Some(code),
+        })
+    }
+
+    fn is_kind(&self, kind: TokenKind) -> bool {
+        self.peek().kind == kind
+    }
+
+    fn is_op(&self, op: &str) -> bool {
+        let token = self.peek();
+        token.kind == TokenKind::Op && token.text == op
+    }
+
+    fn is_keyword(&self, keyword: &str) -> bool {
+        let token = self.peek();
+        token.kind == TokenKind::Name && token.text == keyword
+    }
+
+    fn eat_op(&mut self, op: &str) -> bool {
+        let found = self.is_op(op);
+        if found {
+            self.advance();
+        }
+        found
+    }
+
+    fn eat_keyword(&mut self, keyword: &str) -> bool {
+        let found = self.is_keyword(keyword);
+        if found {
+            self.advance();
+        }
+        found
+    }
+
+    fn expect_op(&mut self, op: &str) -> PResult<()> {
+        if self.eat_op(op) {
+            Ok(())
+        } else {
+            self.error()
+        }
+    }
+
+    fn expect_keyword(&mut self, keyword: &str) -> PResult<()> {
+        if self.eat_keyword(keyword) {
+            Ok(())
+        } else {
+            self.error()
+        }
+    }
+
+    fn expect_kind(&mut self, kind: TokenKind) -> PResult<()> {
+        if self.is_kind(kind) {
+            self.advance();
+            Ok(())
+        } else {
+            self.error()
+        }
+    }
+
+    // NAME
+    fn name(&mut self) -> PResult<Name> {
+        let token = self.peek();
+        if token.kind == TokenKind::Name && !is_keyword(token.text, PARSED_VERSION) {
+            self.advance();
+            Ok(token.text.to_string())
+        } else {
+            self.error()
+        }
+    }
+
+    /// Runs one of the nom parsers for literals on a token, which it
+    /// must consume entirely.
+    fn literal<T, F>(&self, token: Token<'a>, parser: F) -> PResult<T>
+    where
+        F: Fn(StrSpan<'a>) -> IResult<StrSpan<'a>, T, u32>,
+    {
+        let span = LocatedSpan {
+            offset: token.offset,
+            line: token.line,
+            fragment: CompleteStr(token.text),
+        };
+        match parser(span) {
+            Ok((rest, value)) => {
+                if rest.fragment.0.is_empty() {
+                    Ok(value)
+                } else {
+                    Err(ParseError::at(rest, None))
+                }
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+/// Parses a module or sequence of commands, like `file_input`.
+pub fn file_input(code: &str) -> Result<Vec<Statement>, ParseError> {
+    budget::check_code_size(code)?;
+    file_input_in(code, &mut TokenBuffer::default())
+        .map_err(|e| e.locate_unterminated(code).guess_mistake(code))
+}
+
+/// Parses the input of eval(), like `eval_input`.
+pub fn eval_input(code: &str) -> Result<Vec<Expression>, ParseError> {
+    budget::check_code_size(code)?;
+    Parser::new(code, &mut TokenBuffer::default())
+        .eval_input()
+        .map_err(|e| e.locate_unterminated(code).guess_mistake(code))
+}
+
+/// Like `file_input`, with the tokens in `buffer`, and without checking
+/// the size of the code nor improving the errors.
+fn file_input_in(code: &str, buffer: &mut TokenBuffer) -> Result<Vec<Statement>, ParseError> {
+    let mut parser = Parser::new(code, buffer);
+    let stmts = parser.file_input();
+    parser.release(buffer);
+    stmts
+}
+
+/// Parses a whole file, like `parse_module`, with the tokens in
+/// `buffer`.
+pub(crate) fn parse_module_in(code: &str, buffer: &mut TokenBuffer) -> Result<Module, ParseError> {
+    ::parse_with(code, |code| {
+        file_input_in(code, buffer).map(|body| ::make_module(code, body))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use budget::{with_budget, Budget};
+    use helpers::make_strspan;
+
+    fn assert_same_ast(code: &str) {
+        let (rest, expected) = ::file_input(make_strspan(code)).unwrap();
+        assert_eq!(rest.fragment.0, "");
+        assert_eq!(file_input(code), Ok(expected), "{}", code);
+    }
+
+    #[test]
+    fn test_expressions() {
+        for code in &[
+            "a + b * c - d ** -e ** f\n",
+            "a < b <= c != d in e not in f is g is not h\n",
+            "not not a and b or c and not d or e\n",
+            "a if b else c if d else lambda x, *y, z=1, **w: x\n",
+            "x = [i for i in y if i if not i for j in i]\n",
+            "f(a, b=c, *d, **e)(g for g in h)[i:j, ::k, l][m]\n",
+            "x = {a: b, **c}, {a, *b}, {a: b for a in b}, {a for a in b}, {}\n",
+            "x = (), (a,), (a), [], [a], [a, *b], (a for b in c)\n",
+            "x = 'a' \"b\" '''c''' r'd', b'e' B'f', 1, 1.5, 1j, 0x1_f, ...\n",
+            "(yield)\n",
+            "x = yield a, b\n",
+            "y = yield from z\n",
+            "await x + await f()\n",
+            "lambda: (yield)\n",
+            "a.b.c[d](e, f).g\n",
+            "x = (\n  1, # comment\n  2,\n)\n",
+            "(x := 1), f(y := 2)\n",
+            "~a | b ^ c & d << e >> f // g % h @ i / j\n",
+        ] {
+            assert_same_ast(code);
+        }
+    }
+
+    #[test]
+    fn test_statements() {
+        for code in &[
+            "del a, b\npass\nglobal a, b\nnonlocal c\nassert a, b\nraise\nraise a from b\n",
+            "import a.b as c, d\nfrom . import (a, b as c,)\nfrom ...a.b import *\n",
+            "a = b = c, d\na, b = c\na: int\na: int = 1\na += 1\n*a, b = c\na, = b\n",
+            "if a: pass\nelif b:\n    pass\nelse:\n  pass\n",
+            "while a:\n    break\nelse:\n    continue\n",
+            "for a, b in c, d:\n    pass\nelse:\n    pass\n",
+            "try:\n    pass\nexcept A as b:\n    pass\nexcept:\n    pass\nelse:\n    pass\nfinally:\n    pass\n",
+            "with a as b, c:\n    pass\n",
+            "@a.b(c)\n@d\nasync def f(a, /, b: int = 1, *c, d, **e) -> int:\n    return a\n",
+            "class A(B, metaclass=C):\n    def f(self): return self\n\n    x = 1\n",
+            "def f(*, a): pass\ndef g(a, b=1, /, c=2): pass\n",
+            "if a:\n    if b:\n        pass\n    # comment\n\n  # comment\nx = 1; y = 2;\n",
+        ] {
+            assert_same_ast(code);
+        }
+    }
+
+    #[test]
+    fn test_errors() {
+        let e = file_input("x = 1\nif x:\nfoo\n").unwrap_err();
+        assert_eq!((e.line, e.code), (3, Some(PyParseError::ExpectedIndent)));
+        let e = file_input("x = 1\n  y = 2\n").unwrap_err();
+        assert_eq!((e.line, e.code), (2, Some(PyParseError::UnexpectedIndent)));
+        let e = file_input("x = (1,\n2 3)\n").unwrap_err();
+        assert_eq!(
+            (e.line, e.offset, e.code),
+            (2, 8, Some(PyParseError::MissingComma))
+        );
+        assert!(file_input("x = 'a\n").is_err());
+        assert!(file_input("return\nreturn = 1\n").is_err());
+        assert!(file_input("a[1,]b\n").is_err());
+        let e = file_input("f(x=1,\n  x=2)\n").unwrap_err();
+        assert_eq!(
+            (e.line, e.offset, e.code),
+            (2, 9, Some(PyParseError::DuplicateKeywordArgument))
+        );
+        let e = file_input("def f(a=1, b): pass\n").unwrap_err();
+        assert_eq!(
+            (e.offset, e.code),
+            (11, Some(PyParseError::NonDefaultAfterDefault))
+        );
+        let e = file_input("f = lambda a, *: a\n").unwrap_err();
+        assert_eq!(
+            (e.offset, e.code),
+            (14, Some(PyParseError::NoParameterAfterBareStar))
+        );
+        let e = file_input("def f(a, *, b, **a): pass\n").unwrap_err();
+        assert_eq!(
+            (e.offset, e.code),
+            (17, Some(PyParseError::DuplicateParameter))
+        );
+        let e = file_input("x = ('a'\n  b'b')\n").unwrap_err();
+        assert_eq!(
+            (e.line, e.offset, e.code),
+            (2, 11, Some(PyParseError::BytesAndNonBytes))
+        );
+        let budget = Budget {
+            max_nesting: Some(3),
+            ..Budget::default()
+        };
+        assert!(with_budget(budget.clone(), || file_input("x = (((y)))\n")).is_ok());
+        let e = with_budget(budget, || file_input("x = ((((y))))\n")).unwrap_err();
+        assert_eq!((e.offset, e.code), (8, Some(PyParseError::NestedTooDeeply)));
+    }
+
+    #[test]
+    fn test_deviations() {
+        // Cases where the nom-based parsers do not follow Python's grammar
+        assert_eq!(file_input("return\n"), Ok(vec![Statement::Return(vec![])]));
+        assert_eq!(
+            file_input("await(x)\n"),
+            Ok(vec![Statement::Assignment(
+                small_list![Expression::Await(Box::new(Expression::Name(
+                    "x".to_string()
+                )))],
+                vec![]
+            )])
+        );
+    }
+
+    #[test]
+    fn test_eval_input() {
+        let expected = ::eval_input(make_strspan("a, b + 1\n\n")).unwrap().1;
+        assert_eq!(eval_input("a, b + 1\n\n"), Ok(expected));
+        assert!(eval_input("a = 1").is_err());
+    }
+}
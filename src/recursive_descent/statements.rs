@@ -0,0 +1,512 @@
+use super::{PResult, Parser};
+
+use ast::*;
+use errors::PyParseError;
+use helpers::into_vec;
+use tokenizer::TokenKind;
+
+impl<'a> Parser<'a> {
+    // file_input: (NEWLINE | stmt)* ENDMARKER
+    pub(super) fn file_input(&mut self) -> PResult<Vec<Statement>> {
+        let mut stmts = Vec::new();
+        loop {
+            match self.peek().kind {
+                TokenKind::EndMarker => return Ok(stmts),
+                TokenKind::Newline => {
+                    self.advance();
+                }
+                _ => stmts.extend(self.statement()?),
+            }
+        }
+    }
+
+    // eval_input: testlist NEWLINE* ENDMARKER
+    pub(super) fn eval_input(&mut self) -> PResult<Vec<Expression>> {
+        let e = self.testlist()?;
+        while self.is_kind(TokenKind::Newline) {
+            self.advance();
+        }
+        self.expect_kind(TokenKind::EndMarker)?;
+        Ok(e)
+    }
+
+    // stmt: simple_stmt | compound_stmt
+    fn statement(&mut self) -> PResult<Vec<Statement>> {
+        self.spend_fuel()?;
+        let token = self.peek();
+        let compound = match (token.kind, token.text) {
+            (TokenKind::Indent, _) => return self.error_code(PyParseError::UnexpectedIndent),
+            (TokenKind::Op, "@") => self.decorated()?,
+            (TokenKind::Name, "if") => self.if_stmt()?,
+            (TokenKind::Name, "while") => self.while_stmt()?,
+            (TokenKind::Name, "for") => self.for_stmt(false)?,
+            (TokenKind::Name, "try") => self.try_stmt()?,
+            (TokenKind::Name, "with") => self.with_stmt()?,
+            (TokenKind::Name, "def") => self.funcdef(Vec::new())?,
+            (TokenKind::Name, "class") => self.classdef(Vec::new())?,
+            (TokenKind::Name, "async") => self.async_stmt(Vec::new())?,
+            _ => return self.simple_stmt(),
+        };
+        Ok(vec![Statement::Compound(Box::new(compound))])
+    }
+
+    // simple_stmt: small_stmt (';' small_stmt)* [';'] NEWLINE
+    fn simple_stmt(&mut self) -> PResult<Vec<Statement>> {
+        let mut stmts = vec![self.small_stmt()?];
+        while self.eat_op(";") && !self.is_kind(TokenKind::Newline) {
+            stmts.push(self.small_stmt()?);
+        }
+        self.expect_kind(TokenKind::Newline)?;
+        Ok(stmts)
+    }
+
+    // small_stmt: (expr_stmt | del_stmt | pass_stmt | flow_stmt |
+    //             import_stmt | global_stmt | nonlocal_stmt | assert_stmt)
+    fn small_stmt(&mut self) -> PResult<Statement> {
+        let token = self.peek();
+        if token.kind != TokenKind::Name {
+            return self.expr_stmt();
+        }
+        match token.text {
+            "del" => {
+                self.advance();
+                Ok(Statement::Del(self.exprlist_no_tuple()?))
+            }
+            "pass" => {
+                self.advance();
+                Ok(Statement::Pass)
+            }
+            "break" => {
+                self.advance();
+                Ok(Statement::Break)
+            }
+            "continue" => {
+                self.advance();
+                Ok(Statement::Continue)
+            }
+            "return" => {
+                self.advance();
+                if self.starts_expression() {
+                    Ok(Statement::Return(into_vec(self.testlist_star_expr()?)))
+                } else {
+                    Ok(Statement::Return(Vec::new()))
+                }
+            }
+            "raise" => self.raise_stmt(),
+            "global" => {
+                self.advance();
+                Ok(Statement::Global(self.names()?))
+            }
+            "nonlocal" => {
+                self.advance();
+                Ok(Statement::Nonlocal(self.names()?))
+            }
+            "assert" => {
+                self.advance();
+                let assertion = self.test()?;
+                let msg = if self.eat_op(",") {
+                    Some(*self.test()?)
+                } else {
+                    None
+                };
+                Ok(Statement::Assert(*assertion, msg))
+            }
+            "import" => self.import_name(),
+            "from" => self.import_from(),
+            "yield" => Ok(Statement::Expressions(vec![self.yield_expr()?])),
+            _ => self.expr_stmt(),
+        }
+    }
+
+    // expr_stmt: testlist_star_expr (annassign | augassign (yield_expr|testlist) |
+    //                      [('=' (yield_expr|testlist_star_expr))+ [TYPE_COMMENT]] )
+    // annassign: ':' test ['=' (yield_expr|testlist)]
+    fn expr_stmt(&mut self) -> PResult<Statement> {
+        let lhs = self.testlist_star_expr()?;
+        if self.eat_op(":") {
+            let typed = *self.test()?;
+            if self.eat_op("=") {
+                let rhs = self.yield_expr_or_testlist()?;
+                Ok(Statement::TypedAssignment(into_vec(lhs), typed, rhs))
+            } else {
+                Ok(Statement::TypeAnnotation(into_vec(lhs), typed))
+            }
+        } else if let Some(op) = self.augassign() {
+            let rhs = self.yield_expr_or_testlist()?;
+            Ok(Statement::AugmentedAssignment(into_vec(lhs), op, rhs))
+        } else {
+            let mut rhs = Vec::new();
+            while self.eat_op("=") {
+                if self.is_keyword("yield") {
+                    rhs.push(small_list![self.yield_expr()?]);
+                } else {
+                    rhs.push(self.testlist_star_expr()?);
+                }
+            }
+            Ok(Statement::Assignment(lhs, rhs))
+        }
+    }
+
+    fn yield_expr_or_testlist(&mut self) -> PResult<Vec<Expression>> {
+        if self.is_keyword("yield") {
+            Ok(vec![self.yield_expr()?])
+        } else {
+            self.testlist()
+        }
+    }
+
+    // augassign: ('+=' | '-=' | '*=' | '@=' | '/=' | '%=' | '&=' | '|=' | '^=' |
+    //            '<<=' | '>>=' | '**=' | '//=')
+    fn augassign(&mut self) -> Option<AugAssignOp> {
+        if !self.is_kind(TokenKind::Op) {
+            return None;
+        }
+        let op = match self.peek().text {
+            "+=" => AugAssignOp::Add,
+            "-=" => AugAssignOp::Sub,
+            "*=" => AugAssignOp::Mult,
+            "@=" => AugAssignOp::MatMult,
+            "/=" => AugAssignOp::Div,
+            "%=" => AugAssignOp::Mod,
+            "&=" => AugAssignOp::BitAnd,
+            "|=" => AugAssignOp::BitOr,
+            "^=" => AugAssignOp::BitXor,
+            "<<=" => AugAssignOp::Lshift,
+            ">>=" => AugAssignOp::Rshift,
+            "**=" => AugAssignOp::Power,
+            "//=" => AugAssignOp::Floordiv,
+            _ => return None,
+        };
+        self.advance();
+        Some(op)
+    }
+
+    // raise_stmt: 'raise' [test ['from' test]]
+    fn raise_stmt(&mut self) -> PResult<Statement> {
+        self.expect_keyword("raise")?;
+        if !self.starts_expression() {
+            return Ok(Statement::Raise);
+        }
+        let exc = *self.test()?;
+        if self.eat_keyword("from") {
+            Ok(Statement::RaiseExcFrom(exc, *self.test()?))
+        } else {
+            Ok(Statement::RaiseExc(exc))
+        }
+    }
+
+    // NAME (',' NAME)*
+    fn names(&mut self) -> PResult<Vec<Name>> {
+        let mut names = vec![self.name()?];
+        while self.eat_op(",") {
+            names.push(self.name()?);
+        }
+        Ok(names)
+    }
+
+    // import_name: 'import' dotted_as_names
+    // dotted_as_names: dotted_as_name (',' dotted_as_name)*
+    // dotted_as_name: dotted_name ['as' NAME]
+    fn import_name(&mut self) -> PResult<Statement> {
+        self.expect_keyword("import")?;
+        let mut names = Vec::new();
+        loop {
+            let path = into_vec(self.dotted_name()?);
+            let alias = if self.eat_keyword("as") {
+                Some(self.name()?)
+            } else {
+                None
+            };
+            names.push((path, alias));
+            if !self.eat_op(",") {
+                return Ok(Statement::Import(Import::Import { names }));
+            }
+        }
+    }
+
+    // dotted_name: NAME ('.' NAME)*
+    fn dotted_name(&mut self) -> PResult<SmallList<Name>> {
+        let mut names = small_list![self.name()?];
+        while self.eat_op(".") {
+            names.push(self.name()?);
+        }
+        Ok(names)
+    }
+
+    // import_from: ('from' (('.' | '...')* dotted_name | ('.' | '...')+)
+    //               'import' ('*' | '(' import_as_names ')' | import_as_names))
+    fn import_from(&mut self) -> PResult<Statement> {
+        self.expect_keyword("from")?;
+        let mut leading_dots = 0;
+        loop {
+            if self.eat_op(".") {
+                leading_dots += 1;
+            } else if self.eat_op("...") {
+                leading_dots += 3;
+            } else {
+                break;
+            }
+        }
+        let path = if leading_dots > 0 && self.is_keyword("import") {
+            Vec::new()
+        } else {
+            into_vec(self.dotted_name()?)
+        };
+        self.expect_keyword("import")?;
+        if self.eat_op("*") {
+            return Ok(Statement::Import(Import::ImportStarFrom {
+                leading_dots,
+                path,
+            }));
+        }
+        let names = if self.eat_op("(") {
+            let names = self.import_as_names(true)?;
+            self.expect_op(")")?;
+            names
+        } else {
+            self.import_as_names(false)?
+        };
+        Ok(Statement::Import(Import::ImportFrom {
+            leading_dots,
+            path,
+            names,
+        }))
+    }
+
+    // import_as_names: import_as_name (',' import_as_name)* [',']
+    // import_as_name: NAME ['as' NAME]
+    fn import_as_names(&mut self, parenthesized: bool) -> PResult<Vec<(Name, Option<Name>)>> {
+        let mut names = Vec::new();
+        loop {
+            let name = self.name()?;
+            let alias = if self.eat_keyword("as") {
+                Some(self.name()?)
+            } else {
+                None
+            };
+            names.push((name, alias));
+            if !self.eat_op(",") || (parenthesized && self.is_op(")")) {
+                return Ok(names);
+            }
+        }
+    }
+
+    // suite: simple_stmt | NEWLINE INDENT stmt+ DEDENT
+    fn block(&mut self) -> PResult<Vec<Statement>> {
+        self.expect_op(":")?;
+        if !self.is_kind(TokenKind::Newline) {
+            return self.simple_stmt();
+        }
+        self.advance();
+        if !self.is_kind(TokenKind::Indent) {
+            return self.error_code(PyParseError::ExpectedIndent);
+        }
+        self.advance();
+        let mut stmts = Vec::new();
+        while !self.is_kind(TokenKind::Dedent) {
+            stmts.extend(self.statement()?);
+        }
+        self.advance();
+        Ok(stmts)
+    }
+
+    fn else_block(&mut self) -> PResult<Option<Vec<Statement>>> {
+        if self.eat_keyword("else") {
+            Ok(Some(self.block()?))
+        } else {
+            Ok(None)
+        }
+    }
+
+    // if_stmt: 'if' namedexpr_test ':' suite ('elif' namedexpr_test ':' suite)* ['else' ':' suite]
+    fn if_stmt(&mut self) -> PResult<CompoundStatement> {
+        self.expect_keyword("if")?;
+        let mut blocks = Vec::new();
+        loop {
+            let cond = *self.namedexpr_test()?;
+            blocks.push((cond, self.block()?));
+            if !self.eat_keyword("elif") {
+                break;
+            }
+        }
+        Ok(CompoundStatement::If(blocks, self.else_block()?))
+    }
+
+    // while_stmt: 'while' namedexpr_test ':' suite ['else' ':' suite]
+    fn while_stmt(&mut self) -> PResult<CompoundStatement> {
+        self.expect_keyword("while")?;
+        let cond = *self.namedexpr_test()?;
+        let block = self.block()?;
+        Ok(CompoundStatement::While(cond, block, self.else_block()?))
+    }
+
+    // for_stmt: 'for' exprlist 'in' testlist ':' [TYPE_COMMENT] suite ['else' ':' suite]
+    fn for_stmt(&mut self, async: bool) -> PResult<CompoundStatement> {
+        self.expect_keyword("for")?;
+        let item = self.exprlist()?;
+        self.expect_keyword("in")?;
+        let iterator = self.testlist()?;
+        let for_block = self.block()?;
+        Ok(CompoundStatement::For {
+            async,
+            item,
+            iterator,
+            for_block,
+            else_block: self.else_block()?,
+        })
+    }
+
+    // try_stmt: ('try' ':' suite
+    //            ((except_clause ':' suite)+
+    //             ['else' ':' suite]
+    //             ['finally' ':' suite] |
+    //             'finally' ':' suite))
+    // except_clause: 'except' [test ['as' NAME]]
+    fn try_stmt(&mut self) -> PResult<CompoundStatement> {
+        self.expect_keyword("try")?;
+        let try_block = self.block()?;
+        let mut except_clauses = Vec::new();
+        let mut last_except = None;
+        while self.is_keyword("except") {
+            if last_except.is_some() {
+                // `except:` must be the last one
+                return self.error();
+            }
+            self.advance();
+            if self.is_op(":") {
+                last_except = Some(self.block()?);
+            } else {
+                let catch_what = *self.test()?;
+                let catch_as = if self.eat_keyword("as") {
+                    Some(self.name()?)
+                } else {
+                    None
+                };
+                except_clauses.push((catch_what, catch_as, self.block()?));
+            }
+        }
+        let has_except = last_except.is_some() || !except_clauses.is_empty();
+        let else_block = if has_except { self.else_block()? } else { None };
+        let finally_block = if self.eat_keyword("finally") {
+            Some(self.block()?)
+        } else if !has_except {
+            return self.error();
+        } else {
+            None
+        };
+        Ok(CompoundStatement::Try(Try {
+            try_block,
+            except_clauses,
+            last_except: last_except.unwrap_or_default(),
+            else_block: else_block.unwrap_or_default(),
+            finally_block: finally_block.unwrap_or_default(),
+        }))
+    }
+
+    // with_stmt: 'with' with_item (',' with_item)*  ':' [TYPE_COMMENT] suite
+    // with_item: test ['as' expr]
+    fn with_stmt(&mut self) -> PResult<CompoundStatement> {
+        self.expect_keyword("with")?;
+        let mut contexts = Vec::new();
+        loop {
+            let context = *self.test()?;
+            let as_ = if self.eat_keyword("as") {
+                Some(*self.expr()?)
+            } else {
+                None
+            };
+            contexts.push((context, as_));
+            if !self.eat_op(",") {
+                break;
+            }
+        }
+        Ok(CompoundStatement::With(contexts, self.block()?))
+    }
+
+    // async_stmt: 'async' (funcdef | with_stmt | for_stmt)
+    fn async_stmt(&mut self, decorators: Vec<Decorator>) -> PResult<CompoundStatement> {
+        let next = self.peek_nth(1);
+        match (next.kind, next.text) {
+            (TokenKind::Name, "def") => self.funcdef(decorators),
+            (TokenKind::Name, "for") if decorators.is_empty() => {
+                self.advance();
+                self.for_stmt(true)
+            }
+            // The AST can't represent `async with` yet.
+            _ => self.error(),
+        }
+    }
+
+    // decorated: decorators (classdef | funcdef | async_funcdef)
+    // decorator: '@' dotted_name [ '(' [arglist] ')' ] NEWLINE
+    fn decorated(&mut self) -> PResult<CompoundStatement> {
+        let mut decorators = Vec::new();
+        while self.eat_op("@") {
+            let name = self.dotted_name()?;
+            let args = if self.eat_op("(") {
+                let args = self.arglist()?;
+                self.expect_op(")")?;
+                Some(args)
+            } else {
+                None
+            };
+            self.expect_kind(TokenKind::Newline)?;
+            decorators.push(Decorator { name, args });
+        }
+        if self.is_keyword("def") {
+            self.funcdef(decorators)
+        } else if self.is_keyword("class") {
+            self.classdef(decorators)
+        } else if self.is_keyword("async") {
+            self.async_stmt(decorators)
+        } else {
+            self.error()
+        }
+    }
+
+    // async_funcdef: 'async' funcdef
+    // funcdef: 'def' NAME parameters ['->' test] ':' [TYPE_COMMENT] func_body_suite
+    // parameters: '(' [typedargslist] ')'
+    fn funcdef(&mut self, decorators: Vec<Decorator>) -> PResult<CompoundStatement> {
+        let async = self.eat_keyword("async");
+        self.expect_keyword("def")?;
+        let name = self.name()?;
+        self.expect_op("(")?;
+        let parameters = self.parameters(true, ")")?;
+        self.expect_op(")")?;
+        let return_type = if self.eat_op("->") {
+            Some(*self.test()?)
+        } else {
+            None
+        };
+        let code = self.block()?;
+        Ok(CompoundStatement::Funcdef(Funcdef {
+            async,
+            decorators,
+            name,
+            parameters,
+            return_type,
+            code,
+        }))
+    }
+
+    // classdef: 'class' NAME ['(' [arglist] ')'] ':' suite
+    fn classdef(&mut self, decorators: Vec<Decorator>) -> PResult<CompoundStatement> {
+        self.expect_keyword("class")?;
+        let name = self.name()?;
+        let arguments = if self.eat_op("(") {
+            let arguments = self.arglist()?;
+            self.expect_op(")")?;
+            arguments
+        } else {
+            Vec::new()
+        };
+        let code = self.block()?;
+        Ok(CompoundStatement::Classdef(Classdef {
+            decorators,
+            name,
+            arguments,
+            code,
+        }))
+    }
+}
@@ -0,0 +1,343 @@
+//! Reformatting of the changed lines of a file only (like `darker` does
+//! with `black`), so that formatting can be adopted progressively
+//! without rewriting the whole history of a file.
+//!
+//! The statements touching the changed lines are printed again with
+//! `visitors::printer`. A compound statement is printed as a whole only
+//! if all of its lines changed; otherwise only its changed inner
+//! statements are. As the printer drops comments, statements containing
+//! comments are left unchanged, as well as the ones which cannot be
+//! parsed on their own (like an `else:` clause).
+
+use parse_module;
+use span::{replace_spans, Span, Spanned};
+use tokenizer::{line_breaks, tokenize, TokenKind};
+use visitors::printer::format_module;
+
+/// A logical line of the code.
+struct Line {
+    /// Offset of its first token.
+    start: usize,
+    /// Offset of the end of its last token.
+    end: usize,
+    first_line: u32,
+    last_line: u32,
+    depth: usize,
+    /// Whether it starts with `elif`, `else`, `except` or `finally`.
+    clause: bool,
+    decorator: bool,
+}
+
+/// Returns the logical lines of `code`, the lines of its comments, and
+/// the spans of its strings on several lines.
+fn logical_lines(code: &str) -> (Vec<Line>, Vec<u32>, Vec<Span>) {
+    let mut lines: Vec<Line> = Vec::new();
+    let mut comments = Vec::new();
+    let mut multiline_strings = Vec::new();
+    let mut depth = 0;
+    let mut current: Option<Line> = None;
+    for token in tokenize(code) {
+        match token.kind {
+            TokenKind::Indent => depth += 1,
+            TokenKind::Dedent => depth -= 1,
+            TokenKind::Comment => comments.push(token.line),
+            TokenKind::Nl | TokenKind::EndMarker => (),
+            TokenKind::Newline => {
+                if let Some(line) = current.take() {
+                    lines.push(line);
+                }
+            }
+            _ => {
+                if token.kind == TokenKind::String && line_breaks(token.text) > 0 {
+                    multiline_strings.push(token.span());
+                }
+                let end_line = token.line + line_breaks(token.text);
+                match current {
+                    Some(ref mut line) => {
+                        line.end = token.span().end;
+                        line.last_line = end_line;
+                    }
+                    None => {
+                        let keyword = token.kind == TokenKind::Name;
+                        current = Some(Line {
+                            start: token.offset,
+                            end: token.span().end,
+                            first_line: token.line,
+                            last_line: end_line,
+                            depth,
+                            clause: keyword
+                                && ["elif", "else", "except", "finally"].contains(&token.text),
+                            decorator: token.kind == TokenKind::Op && token.text == "@",
+                        })
+                    }
+                }
+            }
+        }
+    }
+    (lines, comments, multiline_strings)
+}
+
+/// Returns the index after the last logical line of the statement
+/// starting at `lines[start]`: its decorators, header, block and the
+/// clauses following it.
+fn statement_end(lines: &[Line], start: usize) -> usize {
+    let depth = lines[start].depth;
+    let mut i = start;
+    while lines[i].decorator && i + 1 < lines.len() && lines[i + 1].depth == depth {
+        i += 1;
+    }
+    i += 1;
+    loop {
+        while i < lines.len() && lines[i].depth > depth {
+            i += 1;
+        }
+        if i < lines.len() && lines[i].depth == depth && lines[i].clause {
+            i += 1;
+        } else {
+            return i;
+        }
+    }
+}
+
+/// Whether a line from `first` to `last` (included) is in `changes`.
+fn intersects(changes: &[(u32, u32)], first: u32, last: u32) -> bool {
+    changes
+        .iter()
+        .any(|&(start, end)| start <= last && first <= end)
+}
+
+/// Whether all lines from `first` to `last` (included) are in `changes`.
+fn covers(changes: &[(u32, u32)], first: u32, last: u32) -> bool {
+    (first..last + 1).all(|line| intersects(changes, line, line))
+}
+
+/// Returns the replacement of the statement made of `lines`, printed
+/// again and indented like them, or `None` if it is left unchanged.
+fn format_statement(
+    code: &str,
+    lines: &[Line],
+    comments: &[u32],
+    multiline_strings: &[Span],
+) -> Option<(Span, String)> {
+    let (first, last) = (&lines[0], &lines[lines.len() - 1]);
+    let line_start = code[..first.start].rfind('\n').map_or(0, |i| i + 1);
+    let span = Span::new(line_start, last.end);
+    let inside = |s: &Span| span.start <= s.start && s.end <= span.end;
+    // Dedenting the statement would change its strings on several lines
+    let commented = comments
+        .iter()
+        .any(|&line| first.first_line <= line && line <= last.last_line);
+    if commented || (first.depth > 0 && multiline_strings.iter().any(inside)) {
+        return None;
+    }
+    let indentation = &code[line_start..first.start];
+    let text: Vec<&str> = code[span.start..span.end]
+        .split('\n')
+        .map(|line| {
+            line.strip_prefix(indentation)
+                .unwrap_or_else(|| line.trim_start())
+        })
+        .collect();
+    let module = parse_module(&text.join("\n")).ok()?;
+    // Without the blank lines around definitions
+    let formatted: Vec<String> = format_module(&module.body)
+        .trim_matches('\n')
+        .lines()
+        .map(|line| {
+            if line.is_empty() {
+                String::new()
+            } else {
+                format!("{}{}", indentation, line)
+            }
+        })
+        .collect();
+    let formatted = formatted.join("\n");
+    if formatted == code[span.start..span.end] {
+        None
+    } else {
+        Some((span, formatted))
+    }
+}
+
+/// Adds the replacements of the statements of `lines` touching
+/// `changes` to `edits`.
+fn format_changes(
+    code: &str,
+    lines: &[Line],
+    changes: &[(u32, u32)],
+    comments: &[u32],
+    multiline_strings: &[Span],
+    edits: &mut Vec<(Span, String)>,
+) {
+    let mut i = 0;
+    while i < lines.len() {
+        let end = statement_end(lines, i);
+        let (first, last) = (lines[i].first_line, lines[end - 1].last_line);
+        if intersects(changes, first, last) {
+            let whole = end == i + 1 || covers(changes, first, last);
+            let edit = if whole {
+                format_statement(code, &lines[i..end], comments, multiline_strings)
+            } else {
+                None
+            };
+            match edit {
+                Some(edit) => edits.push(edit),
+                None => format_changes(
+                    code,
+                    &lines[i + 1..end],
+                    changes,
+                    comments,
+                    multiline_strings,
+                    edits,
+                ),
+            }
+        }
+        i = end;
+    }
+}
+
+/// Reformats the statements of `code` touching the lines in `changes`
+/// (pairs of first and last lines, starting from 1), and leaves the
+/// rest of the code as it is.
+///
+/// ```
+/// use python_parser::reformat::reformat_changes;
+///
+/// let code = "x=[1 ,2]\ndef f( a ):\n    y=a+1\n    return   y\n";
+/// assert_eq!(
+///     reformat_changes(code, &[(3, 3)]),
+///     "x=[1 ,2]\ndef f( a ):\n    y = a+1\n    return   y\n"
+/// );
+/// assert_eq!(
+///     reformat_changes(code, &[(2, 4)]),
+///     "x=[1 ,2]\ndef f(a):\n    y = a+1\n    return y\n"
+/// );
+/// ```
+pub fn reformat_changes(code: &str, changes: &[(u32, u32)]) -> String {
+    let (lines, comments, multiline_strings) = logical_lines(code);
+    let mut edits = Vec::new();
+    format_changes(
+        code,
+        &lines,
+        changes,
+        &comments,
+        &multiline_strings,
+        &mut edits,
+    );
+    let edits: Vec<_> = edits
+        .iter()
+        .map(|&(span, ref text)| (span, &text[..]))
+        .collect();
+    replace_spans(code, &edits).expect("statements do not overlap")
+}
+
+/// Returns the lines added or modified by a unified diff (like the
+/// output of `git diff`) of a single file, as pairs of first and last
+/// lines in the new version of the file, starting from 1.
+///
+/// ```
+/// use python_parser::reformat::changed_lines;
+///
+/// let diff = "--- a/m.py\n+++ b/m.py\n@@ -1,3 +1,4 @@\n x = 1\n-y = 2\n+y = 3\n+z = 4\n w = 5\n";
+/// assert_eq!(changed_lines(diff), vec![(2, 3)]);
+/// ```
+pub fn changed_lines(diff: &str) -> Vec<(u32, u32)> {
+    let mut changes: Vec<(u32, u32)> = Vec::new();
+    // Number of the next line of the new file, in a hunk
+    let mut line: Option<u32> = None;
+    for diff_line in diff.lines() {
+        if diff_line.starts_with("@@") {
+            line = diff_line
+                .split_whitespace()
+                .find(|part| part.starts_with('+'))
+                .and_then(|part| part[1..].split(',').next())
+                .and_then(|start| start.parse().ok());
+            continue;
+        }
+        let number = match line {
+            Some(number) => number,
+            None => continue,
+        };
+        if diff_line.starts_with('+') {
+            match changes.last_mut() {
+                Some(&mut (_, ref mut end)) if *end + 1 == number => *end = number,
+                _ => changes.push((number, number)),
+            }
+            line = Some(number + 1);
+        } else if diff_line.starts_with(' ') || diff_line.is_empty() {
+            line = Some(number + 1);
+        } else if !diff_line.starts_with('-') && !diff_line.starts_with('\\') {
+            // The end of the hunks of the file
+            line = None;
+        }
+    }
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reformat_changes() {
+        let code = "import os,sys
+@dec( 1 )
+def f(a):
+    if a :
+        b=1
+    else:
+        b=2 # two
+    return b
+x=f( 1 )
+";
+        let reformat = |changes| reformat_changes(code, changes);
+        assert_eq!(reformat(&[]), code);
+        assert_eq!(reformat(&[(10, 12)]), code);
+        assert_eq!(
+            reformat(&[(1, 1), (9, 9)]),
+            code.replace("os,sys", "os, sys")
+                .replace("x=f( 1 )", "x = f(1)")
+        );
+        assert_eq!(reformat(&[(5, 5)]), code.replace("b=1", "b = 1"));
+        // Headers and statements with comments are left unchanged
+        assert_eq!(reformat(&[(4, 4), (6, 7)]), code);
+        let expected = code.replace("b=1", "b = 1").replace("x=f( 1 )", "x = f(1)");
+        assert_eq!(reformat(&[(2, 9)]), expected);
+        // Compound statements whose lines all changed
+        assert_eq!(
+            reformat_changes("if a :\n  b=1\nelse:\n  b=2\n", &[(1, 4)]),
+            "if a:\n    b = 1\nelse:\n    b = 2\n"
+        );
+        // Indented strings on several lines cannot be dedented
+        let code = "if a:\n    x = '''\n1'''\ny=[\n  '''\n  2''']\n";
+        assert_eq!(reformat_changes(code, &[(2, 3)]), code);
+        assert_eq!(
+            reformat_changes(code, &[(1, 6)]),
+            "if a:\n    x = \"\\n1\"\ny = [\"\\n  2\"]\n"
+        );
+    }
+
+    #[test]
+    fn test_changed_lines() {
+        let diff = "diff --git a/m.py b/m.py
+index 1234567..89abcde 100644
+--- a/m.py
++++ b/m.py
+@@ -1,2 +1,3 @@
++import os
+ x = 1
+ y = 2
+@@ -10,3 +11,2 @@ def f():
+     a = 1
+-    b = 2
+-    c = 3
++    b = 3
+\\ No newline at end of file
+@@ -20 +20,0 @@
+-z = 1
+";
+        assert_eq!(changed_lines(diff), vec![(1, 1), (12, 12)]);
+        assert_eq!(changed_lines("@@ -1 +1 @@\n-x\n+y\n+z\n"), vec![(1, 2)]);
+        assert_eq!(changed_lines(""), vec![]);
+    }
+}
@@ -0,0 +1,255 @@
+//! Renaming of a variable, function or class, by editing its names in
+//! the code, so that the rest of the code keeps its formatting and
+//! comments.
+//!
+//! The names are found by `definition::Bindings`, so attributes (like
+//! `self.x`), keyword arguments in calls and names in strings (like
+//! `__all__`) are not renamed.
+
+use std::fmt;
+
+use definition::{BindingKind, Bindings};
+use keywords::{is_keyword, PARSED_VERSION};
+use lint::{is_name, is_op};
+use span::{replace_spans, Span, Spanned};
+use tokenizer::{tokenize, TokenKind};
+
+/// Error returned by `rename`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RenameError {
+    /// There is no name bound in the module at the offset.
+    NotBound,
+    /// The new name is not an identifier, or is a keyword.
+    InvalidName(String),
+    /// The name is bound by an import of a dotted module (like
+    /// `import os.path`), which cannot be renamed.
+    DottedImport(Span),
+    /// After the renaming, this name would refer to another variable
+    /// (or another variable would be referred to by the new name).
+    Conflict(Span),
+}
+
+impl fmt::Display for RenameError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            RenameError::NotBound => write!(f, "no variable to rename"),
+            RenameError::InvalidName(ref name) => write!(f, "invalid name: {:?}", name),
+            RenameError::DottedImport(_) => write!(f, "cannot rename an import of a submodule"),
+            RenameError::Conflict(_) => write!(f, "the new name conflicts with another variable"),
+        }
+    }
+}
+
+impl ::std::error::Error for RenameError {}
+
+/// Returns the offset in the edited code of the code at `offset`,
+/// which is not in an edited span.
+fn shift(edits: &[(Span, String)], offset: usize) -> usize {
+    edits
+        .iter()
+        .filter(|&&(span, _)| span.end <= offset)
+        .fold(offset, |offset, &(span, ref text)| {
+            offset + text.len() - span.len()
+        })
+}
+
+/// Returns the edits renaming the variable at `offset` (or ending at
+/// it) to `new_name`. Imported names are renamed with an alias (like
+/// `import os as new_name`).
+///
+/// ```
+/// use python_parser::rename::rename;
+/// use python_parser::span::replace_spans;
+///
+/// let code = "import json\n\ndef load(path):  # Reads it\n    return json.load(path)\n";
+/// let edits = rename(code, code.find("json").unwrap(), "js").unwrap();
+/// let edits: Vec<_> = edits.iter().map(|&(span, ref text)| (span, &text[..])).collect();
+/// assert_eq!(
+///     replace_spans(code, &edits).unwrap(),
+///     "import json as js\n\ndef load(path):  # Reads it\n    return js.load(path)\n"
+/// );
+/// ```
+pub fn rename(
+    code: &str,
+    offset: usize,
+    new_name: &str,
+) -> Result<Vec<(Span, String)>, RenameError> {
+    let mut name_tokens = tokenize(new_name).filter(|t| t.kind != TokenKind::EndMarker);
+    let valid = match (name_tokens.next(), name_tokens.next()) {
+        (Some(name), Some(newline)) => {
+            name.kind == TokenKind::Name
+                && name.text == new_name
+                && newline.text.is_empty()
+                && !is_keyword(new_name, PARSED_VERSION)
+        }
+        _ => false,
+    };
+    if !valid {
+        return Err(RenameError::InvalidName(new_name.to_string()));
+    }
+    let bindings = Bindings::new(code);
+    let references = bindings.references(offset);
+    if references.is_empty() {
+        return Err(RenameError::NotBound);
+    }
+    let tokens: Vec<_> = tokenize(code)
+        .filter(|t| match t.kind {
+            TokenKind::Comment | TokenKind::Nl | TokenKind::Indent | TokenKind::Dedent => false,
+            _ => true,
+        })
+        .collect();
+    let mut edits = Vec::new();
+    // The spans of the new names in the edited code, once shifted
+    let mut renamed = Vec::new();
+    for &span in &references {
+        let is_import = bindings
+            .resolve(span.start)
+            .map_or(false, |b| b.span == span && b.kind == BindingKind::Import);
+        let index = tokens.iter().position(|t| t.span() == span);
+        let (previous, next) = match index {
+            Some(i) if i > 0 => (tokens.get(i - 1), tokens.get(i + 1)),
+            Some(i) => (None, tokens.get(i + 1)),
+            None => (None, None),
+        };
+        if is_import && !is_name(previous, "as") {
+            if is_op(next, ".") {
+                return Err(RenameError::DottedImport(span));
+            }
+            let alias = format!("{} as {}", &code[span.start..span.end], new_name);
+            renamed.push((span.start, alias.len() - new_name.len()));
+            edits.push((span, alias));
+        } else {
+            renamed.push((span.start, 0));
+            edits.push((span, new_name.to_string()));
+        }
+    }
+
+    // Checks that the renamed names, and the ones already named like
+    // the new name, refer to the same variables as before
+    let new_code = replace_spans(
+        code,
+        &edits
+            .iter()
+            .map(|&(span, ref text)| (span, &text[..]))
+            .collect::<Vec<_>>(),
+    )
+    .expect("names do not overlap");
+    let new_bindings = Bindings::new(&new_code);
+    let new_span = |start: usize, skipped: usize| {
+        let start = shift(&edits, start) + skipped;
+        Span::new(start, start + new_name.len())
+    };
+    let expected: Vec<_> = renamed
+        .iter()
+        .map(|&(start, skipped)| new_span(start, skipped))
+        .collect();
+    if new_bindings.references(expected[0].start) != expected {
+        return Err(RenameError::Conflict(references[0]));
+    }
+    for token in tokens.iter().filter(|t| t.text == new_name) {
+        let before = bindings
+            .resolve(token.offset)
+            .map(|b| shift(&edits, b.span.start));
+        let after = new_bindings
+            .resolve(shift(&edits, token.offset))
+            .map(|b| b.span.start);
+        if before != after {
+            return Err(RenameError::Conflict(token.span()));
+        }
+    }
+    Ok(edits)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns `code` with the `n`-th name `name` renamed to `new_name`.
+    fn renamed(code: &str, name: &str, n: usize, new_name: &str) -> Result<String, RenameError> {
+        let offset = tokenize(code)
+            .filter(|t| t.text == name)
+            .nth(n)
+            .unwrap()
+            .offset;
+        let edits = rename(code, offset, new_name)?;
+        let edits: Vec<_> = edits.iter().map(|&(span, ref t)| (span, &t[..])).collect();
+        Ok(replace_spans(code, &edits).unwrap())
+    }
+
+    #[test]
+    fn test_rename() {
+        let code = "from os import path
+import sys as system, re
+
+def f(x, y=1):
+    global sys_name
+    sys_name = system.platform  # x
+    return  x+path.x+re.x
+sys_name = f(x=1)
+";
+        assert_eq!(
+            renamed(code, "x", 0, "value"),
+            Ok(code
+                .replace("f(x, y", "f(value, y")
+                .replace(" x+", " value+"))
+        );
+        assert_eq!(
+            renamed(code, "path", 1, "p"),
+            Ok(code
+                .replace("import path", "import path as p")
+                .replace("+path", "+p"))
+        );
+        assert_eq!(
+            renamed(code, "system", 1, "s"),
+            Ok(code.replace("system", "s"))
+        );
+        assert_eq!(
+            renamed(code, "re", 0, "regex"),
+            Ok(code
+                .replace(", re", ", re as regex")
+                .replace("+re", "+regex"))
+        );
+        assert_eq!(
+            renamed(code, "sys_name", 0, "name"),
+            Ok(code.replace("sys_name", "name"))
+        );
+        assert_eq!(
+            renamed(code, "f", 1, "g"),
+            Ok(code.replace("def f", "def g").replace("= f(", "= g("))
+        );
+
+        assert_eq!(renamed(code, "os", 0, "o"), Err(RenameError::NotBound));
+        assert_eq!(renamed(code, "x", 2, "o"), Err(RenameError::NotBound));
+        assert_eq!(renamed(code, "x", 4, "o"), Err(RenameError::NotBound));
+        for name in &["2x", "a b", "class", "a.b", ""] {
+            assert_eq!(
+                renamed(code, "y", 0, name),
+                Err(RenameError::InvalidName(name.to_string()))
+            );
+        }
+        let span = |start| Span::new(start, start + 1);
+        assert_eq!(
+            renamed("import a.b\na.b\n", "a", 0, "c"),
+            Err(RenameError::DottedImport(span(7)))
+        );
+        // The new name is bound in the same scope, or would capture
+        // uses of another variable, or be hidden by a local variable
+        let code = "a = 1\nb = 2\ndef f():\n    c = 3\n    return a + b\n";
+        assert_eq!(
+            renamed(code, "a", 0, "b"),
+            Err(RenameError::Conflict(span(0)))
+        );
+        assert_eq!(
+            renamed(code, "c", 0, "b"),
+            Err(RenameError::Conflict(span(25)))
+        );
+        assert_eq!(
+            renamed(code, "a", 0, "c"),
+            Err(RenameError::Conflict(span(0)))
+        );
+        assert_eq!(
+            renamed(code, "c", 0, "d"),
+            Ok(code.replace("c = 3", "d = 3"))
+        );
+    }
+}
@@ -0,0 +1,113 @@
+//! Replacement of a statement of a parsed module by new code, which is
+//! the only code parsed, for tools applying many edits to a module.
+
+use std::error::Error;
+use std::fmt;
+
+use ast::{Module, Statement};
+use errors::ParseError;
+use helpers::make_strspan;
+use parse_with;
+use statements::statement;
+use stream::skip_blank_lines;
+
+/// Error returned by `reparse_statement`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ReparseError {
+    /// The module has no statement at this index.
+    NoStatement(usize),
+    /// The new code has a syntax error, whose offset and line are in
+    /// the new code.
+    Syntax(ParseError),
+}
+
+impl fmt::Display for ReparseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ReparseError::NoStatement(index) => write!(f, "no statement at index {}", index),
+            ReparseError::Syntax(ref e) => e.fmt(f),
+        }
+    }
+}
+
+impl Error for ReparseError {}
+
+/// Parses the statements of `code`, indented like its first line.
+fn indented_statements(code: &str) -> Result<Vec<Statement>, ParseError> {
+    let mut stmts = Vec::new();
+    let mut i = make_strspan(code);
+    let mut indent = None;
+    while let Some(next) = skip_blank_lines(i)? {
+        let line = next.fragment.0.trim_start_matches('\x0c');
+        let indent = *indent.get_or_insert_with(|| line.len() - line.trim_start_matches(' ').len());
+        let (rest, new_stmts) = statement(next, indent)?;
+        stmts.extend(new_stmts);
+        i = rest;
+    }
+    Ok(stmts)
+}
+
+/// Replaces the top-level statement at `index` of `module` with the
+/// statements of `new_text`, which may be indented (eg. when moved out
+/// of a block) as long as its statements are indented like its first
+/// line. Without statements, `new_text` removes the statement.
+///
+/// The module is unchanged if `new_text` has a syntax error.
+///
+/// ```
+/// use python_parser::parse_module;
+/// use python_parser::reparse::reparse_statement;
+///
+/// let mut module = parse_module("import os\nx = 1\nprint(x)\n").unwrap();
+/// reparse_statement(&mut module, 1, "    if y:\n        x = 2\n    z = 3\n").unwrap();
+/// assert_eq!(module, parse_module("import os\nif y:\n    x = 2\nz = 3\nprint(x)\n").unwrap());
+/// assert!(reparse_statement(&mut module, 0, "import (\n").is_err());
+/// assert_eq!(module.body.len(), 4);
+/// ```
+pub fn reparse_statement(
+    module: &mut Module,
+    index: usize,
+    new_text: &str,
+) -> Result<(), ReparseError> {
+    if index >= module.body.len() {
+        return Err(ReparseError::NoStatement(index));
+    }
+    let stmts = parse_with(new_text, indented_statements).map_err(ReparseError::Syntax)?;
+    module.body.splice(index..index + 1, stmts);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use errors::PyParseError;
+    use parse_module;
+
+    #[test]
+    fn test_reparse_statement() {
+        let code = "def f():\n    return 1\n\nclass A:\n    pass\n";
+        let mut module = parse_module(code).unwrap();
+        reparse_statement(&mut module, 0, "def f(): return 2").unwrap();
+        reparse_statement(&mut module, 1, "\n# Removed\n").unwrap();
+        assert_eq!(module, parse_module("def f(): return 2\n").unwrap());
+
+        let error = reparse_statement(&mut module, 0, "  x = 1\n y = 2\n").unwrap_err();
+        match error {
+            ReparseError::Syntax(ref e) => assert_eq!(e.line, 2),
+            ref e => panic!("Unexpected error: {:?}", e),
+        }
+        assert_eq!(
+            reparse_statement(&mut module, 1, "x = 1\n"),
+            Err(ReparseError::NoStatement(1))
+        );
+        // Errors are the ones of `parse_module`
+        let new_text = "def g():\nreturn 1\n";
+        let error = parse_module(new_text).unwrap_err();
+        assert_eq!(error.code, Some(PyParseError::ExpectedIndent));
+        assert_eq!(
+            reparse_statement(&mut module, 0, new_text),
+            Err(ReparseError::Syntax(error))
+        );
+        assert_eq!(module, parse_module("def f(): return 2\n").unwrap());
+    }
+}
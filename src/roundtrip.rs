@@ -0,0 +1,236 @@
+//! A check that printing the AST of some code (see `printer`) gives code
+//! with the same AST, to validate the printer on a codebase before
+//! using it to format it.
+
+use ast::Module;
+use errors::ParseError;
+use parse_module;
+use visitors::normalize::{ast_eq, Normalize};
+use visitors::printer::format_file;
+
+/// Where the ASTs of some code and of its printed code differ, found by
+/// `check_roundtrip`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Divergence {
+    /// Path of the first node which differs, from the module, like
+    /// `body[1].Compound.Funcdef.code[0].Return[0].Bop.Call`, made of
+    /// the names of fields, variants and structures, and of indices in
+    /// lists.
+    pub path: String,
+    /// The first line of the debug representation (`{:#?}`) of the
+    /// normalized AST of the code which differs, like `"b"` or `]` (when the other AST
+    /// has more nodes there), without its trailing comma.
+    pub original: String,
+    /// The same line of the AST of the printed code.
+    pub reparsed: String,
+}
+
+/// The result of `check_roundtrip`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum RoundTripReport {
+    /// The printed code has the same AST as the code.
+    Identical { printed: String },
+    /// The code could not be parsed.
+    InvalidSource(ParseError),
+    /// The printed code could not be parsed.
+    InvalidOutput { printed: String, error: ParseError },
+    /// The printed code has another AST.
+    Diverged {
+        printed: String,
+        divergence: Divergence,
+    },
+}
+
+impl RoundTripReport {
+    /// Returns whether the code is printed correctly.
+    pub fn is_identical(&self) -> bool {
+        match *self {
+            RoundTripReport::Identical { .. } => true,
+            _ => false,
+        }
+    }
+}
+
+/// A node opened by a line of a debug representation.
+struct Frame<'a> {
+    label: Option<&'a str>,
+    is_list: bool,
+    /// Index of the node among the children of its parent.
+    index: usize,
+    children: usize,
+}
+
+/// Returns the name of the field, variant or structure of a line of a
+/// debug representation, like `body` for `body: [`.
+fn label(line: &str) -> Option<&str> {
+    let end = line
+        .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+        .unwrap_or_else(|| line.len());
+    if end == 0 {
+        return None;
+    }
+    Some(&line[..end])
+}
+
+/// Returns the path of the node at `line` of a debug representation.
+fn node_path(debug: &str, line: usize) -> String {
+    let mut stack: Vec<Frame> = Vec::new();
+    for (i, text) in debug.lines().enumerate() {
+        let text = text.trim();
+        let is_close = text.starts_with(|c| c == ')' || c == ']' || c == '}');
+        let mut index = 0;
+        if !is_close {
+            if let Some(parent) = stack.last_mut() {
+                index = parent.children;
+                parent.children += 1;
+            }
+        }
+        if i == line {
+            if !is_close {
+                stack.push(Frame {
+                    label: label(text),
+                    is_list: false,
+                    index,
+                    children: 0,
+                });
+            }
+            break;
+        }
+        if is_close {
+            stack.pop();
+        } else if text.ends_with(|c| c == '(' || c == '[' || c == '{') {
+            stack.push(Frame {
+                label: label(text),
+                is_list: text.ends_with('['),
+                index,
+                children: 0,
+            });
+        }
+    }
+    let mut path = String::new();
+    let mut last = None;
+    // The module is the root of the paths
+    for (i, frame) in stack.iter().enumerate().skip(1) {
+        if stack[i - 1].is_list {
+            path.push_str(&format!("[{}]", frame.index));
+            last = None;
+        }
+        if let Some(label) = frame.label {
+            if frame.label != last {
+                if !path.is_empty() {
+                    path.push('.');
+                }
+                path.push_str(label);
+                last = frame.label;
+            }
+        }
+    }
+    path
+}
+
+/// Returns where the ASTs of two modules differ, if they do once
+/// normalized (see `normalize`): the printer may change the spelling of
+/// a node, like the raw text of a string, without changing its meaning.
+fn divergence(original: &Module, reparsed: &Module) -> Option<Divergence> {
+    if ast_eq(original, reparsed) {
+        return None;
+    }
+    let (mut original, mut reparsed) = (original.clone(), reparsed.clone());
+    original.normalize();
+    reparsed.normalize();
+    let original = format!("{:#?}", original);
+    let reparsed = format!("{:#?}", reparsed);
+    let mut lines = original.lines().zip(reparsed.lines());
+    let line = lines.position(|(a, b)| a != b)?;
+    let text = |debug: &str| {
+        let line = debug.lines().nth(line).unwrap_or("").trim();
+        line.trim_end_matches(',').to_string()
+    };
+    Some(Divergence {
+        path: node_path(&original, line),
+        original: text(&original),
+        reparsed: text(&reparsed),
+    })
+}
+
+/// Parses `code`, prints its AST, parses the printed code, and returns
+/// whether both ASTs are the same, or where they differ.
+///
+/// ```
+/// use python_parser::roundtrip::{check_roundtrip, RoundTripReport};
+///
+/// let report = check_roundtrip("def f(x):\n    return (x+1)  # comment\n");
+/// assert!(report.is_identical());
+/// match check_roundtrip("def f(:\n") {
+///     RoundTripReport::InvalidSource(error) => assert_eq!(error.line, 1),
+///     report => panic!("{:?}", report),
+/// }
+/// ```
+pub fn check_roundtrip(code: &str) -> RoundTripReport {
+    let module = match parse_module(code) {
+        Ok(module) => module,
+        Err(error) => return RoundTripReport::InvalidSource(error),
+    };
+    let printed = format_file(&module);
+    let reparsed = match parse_module(&printed) {
+        Ok(reparsed) => reparsed,
+        Err(error) => return RoundTripReport::InvalidOutput { printed, error },
+    };
+    match divergence(&module, &reparsed) {
+        None => RoundTripReport::Identical { printed },
+        Some(divergence) => RoundTripReport::Diverged {
+            printed,
+            divergence,
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use decoding::{with_string_decoding, StringDecoding};
+
+    #[test]
+    fn test_divergence() {
+        let original = parse_module("x = 1\ndef f(a, b=2):\n    return a + g(b)\n").unwrap();
+        let reparsed = parse_module("x = 1\ndef f(a, b=2):\n    return a + g(c)\n").unwrap();
+        assert_eq!(divergence(&original, &original), None);
+        assert_eq!(
+            divergence(&original, &reparsed),
+            Some(Divergence {
+                path: "body[1].Compound.Funcdef.code[0].Return[0].Bop.Call[0].Positional.Name"
+                    .to_string(),
+                original: "\"b\"".to_string(),
+                reparsed: "\"c\"".to_string(),
+            })
+        );
+
+        // Spellings of the same AST are not divergences
+        let concatenated = parse_module("x = u'a' 'b'\n").unwrap();
+        assert_eq!(
+            divergence(&concatenated, &parse_module("x = 'ab'\n").unwrap()),
+            None
+        );
+        let (raw, escaped) = with_string_decoding(StringDecoding::Both, || {
+            (parse_module("x = r'\\d'\n"), parse_module("x = '\\\\d'\n"))
+        });
+        assert_eq!(divergence(&raw.unwrap(), &escaped.unwrap()), None);
+
+        let longer = parse_module("x = 1\ny = 2\n").unwrap();
+        let divergence = divergence(&parse_module("x = 1\n").unwrap(), &longer).unwrap();
+        assert_eq!(divergence.path, "body");
+        assert_eq!(divergence.reparsed, "Assignment(");
+    }
+
+    #[test]
+    fn test_check_roundtrip() {
+        let code =
+            "#!/usr/bin/env python\n# comment\nimport os\n@dec\nclass A(B):\n    x: int = 1\n";
+        match check_roundtrip(code) {
+            RoundTripReport::Identical { printed } => {
+                assert_eq!(parse_module(&printed), parse_module(code))
+            }
+            report => panic!("{:?}", report),
+        }
+    }
+}
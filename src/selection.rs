@@ -0,0 +1,297 @@
+//! The syntactic structures containing a position in the code, for the
+//! "expand selection" of editors (the `textDocument/selectionRange`
+//! request of the Language Server Protocol).
+//!
+//! The AST has no positions, so the nodes are found from the tokens of
+//! the code: tokens, the items separated by commas in brackets, bracket
+//! groups, statements (logical lines), blocks, compound statements and
+//! the module. This also works on incomplete code.
+
+use span::{Span, Spanned};
+use tokenizer::{tokenize, Token, TokenKind};
+
+/// The kind of a `Node`, from the smallest to the largest.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum NodeKind {
+    Token,
+    /// An item between commas in brackets, like a function argument.
+    Item,
+    /// The code between brackets, without the brackets.
+    BracketContents,
+    /// Brackets and the code between them.
+    Brackets,
+    /// A simple statement, or the header of a compound statement (eg.
+    /// `if x:`).
+    Statement,
+    /// The indented block of a compound statement.
+    Block,
+    /// A compound statement, with its block.
+    CompoundStatement,
+    Module,
+}
+
+/// A syntactic structure of the code, found by `node_at` and
+/// `selection_ranges`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Node {
+    pub kind: NodeKind,
+    pub span: Span,
+}
+
+impl Spanned for Node {
+    fn span(&self) -> Span {
+        self.span
+    }
+}
+
+/// Whether `offset` is in `span`, or at its end (like a cursor right
+/// after a word).
+fn touches(span: Span, offset: usize) -> bool {
+    span.start <= offset && offset <= span.end
+}
+
+/// An indented block being read.
+struct OpenBlock {
+    /// Start of the header of the compound statement.
+    header_start: usize,
+    /// Start of the first statement of the block.
+    start: Option<usize>,
+    end: usize,
+}
+
+/// Adds the nodes of a statement (its tokens, items, brackets, and
+/// itself) containing `offset` to `nodes`.
+fn statement_nodes(tokens: &[Token], offset: usize, nodes: &mut Vec<Node>) {
+    let span = tokens[0].span().cover(tokens[tokens.len() - 1].span());
+    if !touches(span, offset) {
+        return;
+    }
+    nodes.push(Node {
+        kind: NodeKind::Statement,
+        span,
+    });
+    let mut add = |kind, span| {
+        if touches(span, offset) {
+            nodes.push(Node { kind, span });
+        }
+    };
+    // Indexes of the open brackets, and of the commas in them
+    let mut brackets: Vec<(usize, Vec<usize>)> = Vec::new();
+    for (i, token) in tokens.iter().enumerate() {
+        add(NodeKind::Token, token.span());
+        if token.kind != TokenKind::Op {
+            continue;
+        }
+        match token.text {
+            "(" | "[" | "{" => brackets.push((i, Vec::new())),
+            "," => {
+                if let Some(&mut (_, ref mut commas)) = brackets.last_mut() {
+                    commas.push(i);
+                }
+            }
+            ")" | "]" | "}" => {
+                let (open, commas) = match brackets.pop() {
+                    Some(bracket) => bracket,
+                    None => continue,
+                };
+                add(NodeKind::Brackets, tokens[open].span().cover(token.span()));
+                if i > open + 1 {
+                    let contents = tokens[open + 1].span().cover(tokens[i - 1].span());
+                    add(NodeKind::BracketContents, contents);
+                }
+                let mut item_start = open + 1;
+                for end in commas.into_iter().chain(Some(i)) {
+                    if end > item_start {
+                        let item = tokens[item_start].span().cover(tokens[end - 1].span());
+                        add(NodeKind::Item, item);
+                    }
+                    item_start = end + 1;
+                }
+            }
+            _ => (),
+        }
+    }
+}
+
+/// Returns the nodes containing `offset` (or ending at it), from the
+/// innermost to the module. Nodes with the same span as a smaller one
+/// are skipped.
+///
+/// ```
+/// use python_parser::selection::{selection_ranges, NodeKind};
+///
+/// let code = "if x:\n    f(a, b + 1)\n";
+/// let offset = code.find('b').unwrap();
+/// let ranges: Vec<_> = selection_ranges(code, offset)
+///     .into_iter()
+///     .map(|node| (node.kind, &code[node.span.start..node.span.end]))
+///     .collect();
+/// assert_eq!(
+///     ranges,
+///     vec![
+///         (NodeKind::Token, "b"),
+///         (NodeKind::Item, "b + 1"),
+///         (NodeKind::BracketContents, "a, b + 1"),
+///         (NodeKind::Brackets, "(a, b + 1)"),
+///         (NodeKind::Statement, "f(a, b + 1)"),
+///         (NodeKind::CompoundStatement, "if x:\n    f(a, b + 1)"),
+///         (NodeKind::Module, code),
+///     ]
+/// );
+/// ```
+pub fn selection_ranges(code: &str, offset: usize) -> Vec<Node> {
+    let mut nodes = Vec::new();
+    let mut blocks: Vec<OpenBlock> = Vec::new();
+    let mut line: Vec<Token> = Vec::new();
+    // Start of the last statement, if it is the header of a block
+    let mut header: Option<usize> = None;
+    for token in tokenize(code) {
+        match token.kind {
+            TokenKind::Comment => {
+                if touches(token.span(), offset) {
+                    nodes.push(Node {
+                        kind: NodeKind::Token,
+                        span: token.span(),
+                    });
+                }
+            }
+            TokenKind::Nl | TokenKind::EndMarker => (),
+            TokenKind::Indent => blocks.push(OpenBlock {
+                header_start: header.unwrap_or(token.offset),
+                start: None,
+                end: token.offset,
+            }),
+            TokenKind::Dedent => {
+                let block = match blocks.pop() {
+                    Some(block) => block,
+                    None => continue,
+                };
+                if let Some(start) = block.start {
+                    for (kind, start) in vec![
+                        (NodeKind::Block, start),
+                        (NodeKind::CompoundStatement, block.header_start),
+                    ] {
+                        let span = Span::new(start, block.end);
+                        if touches(span, offset) {
+                            nodes.push(Node { kind, span });
+                        }
+                    }
+                }
+            }
+            TokenKind::Newline => {
+                if line.is_empty() {
+                    continue;
+                }
+                statement_nodes(&line, offset, &mut nodes);
+                let (start, end) = (line[0].offset, line[line.len() - 1].span().end);
+                for block in &mut blocks {
+                    block.start = block.start.or(Some(start));
+                    block.end = end;
+                }
+                header = if line[line.len() - 1].text == ":" {
+                    Some(start)
+                } else {
+                    None
+                };
+                line.clear();
+            }
+            _ => line.push(token),
+        }
+    }
+    nodes.push(Node {
+        kind: NodeKind::Module,
+        span: Span::new(0, code.len()),
+    });
+    nodes.sort_by_key(|node| (node.span.len(), node.kind));
+    nodes.dedup_by_key(|node| node.span);
+    // Tokens ending at the offset are only used if none contains it
+    if nodes.len() > 1 && nodes[1].kind == NodeKind::Token && nodes[1].span.start == offset {
+        nodes.remove(0);
+    }
+    nodes
+}
+
+/// Returns the innermost node containing `offset` (or ending at it).
+///
+/// ```
+/// use python_parser::selection::{node_at, NodeKind};
+///
+/// let code = "x = [1, 2]\n";
+/// let node = node_at(code, 5);
+/// assert_eq!((node.kind, &code[node.span.start..node.span.end]), (NodeKind::Token, "1"));
+/// ```
+pub fn node_at(code: &str, offset: usize) -> Node {
+    selection_ranges(code, offset)[0]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ranges(code: &str, offset: usize) -> Vec<(NodeKind, &str)> {
+        selection_ranges(code, offset)
+            .into_iter()
+            .map(|node| (node.kind, &code[node.span.start..node.span.end]))
+            .collect()
+    }
+
+    #[test]
+    fn test_blocks() {
+        let code = "def f(x):
+    # comment
+    if x:
+        return [
+            x,
+        ]
+    return None
+
+y = 1
+";
+        let def = "def f(x):
+    # comment
+    if x:
+        return [
+            x,
+        ]
+    return None";
+        let body = "if x:
+        return [
+            x,
+        ]
+    return None";
+        assert_eq!(
+            ranges(code, code.find("x,").unwrap()),
+            vec![
+                (NodeKind::Token, "x"),
+                (NodeKind::BracketContents, "x,"),
+                (NodeKind::Brackets, "[\n            x,\n        ]"),
+                (NodeKind::Statement, "return [\n            x,\n        ]"),
+                (
+                    NodeKind::CompoundStatement,
+                    "if x:\n        return [\n            x,\n        ]"
+                ),
+                (NodeKind::Block, body),
+                (NodeKind::CompoundStatement, def),
+                (NodeKind::Module, code),
+            ]
+        );
+        assert_eq!(
+            ranges(code, code.find("comment").unwrap()),
+            vec![
+                (NodeKind::Token, "# comment"),
+                (NodeKind::CompoundStatement, def),
+                (NodeKind::Module, code),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_token_boundaries() {
+        let code = "ab.cd";
+        // Between two tokens, the one starting at the offset is used
+        assert_eq!(node_at(code, 2).span, Span::new(2, 3));
+        // At the end of the code, the last token
+        assert_eq!(node_at(code, 5).span, Span::new(3, 5));
+        assert_eq!(node_at("", 0).kind, NodeKind::Module);
+    }
+}
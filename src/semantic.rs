@@ -0,0 +1,497 @@
+//! Classification of the tokens of a module for highlighting, like the
+//! `textDocument/semanticTokens` request of the Language Server
+//! Protocol.
+//!
+//! Names are classified with the scopes of the module (see
+//! `definition`): a use of a parameter is highlighted like the
+//! parameter, a call of a class like the class, etc.
+
+use definition::{BindingKind, Bindings};
+use keywords::{is_keyword, PARSED_VERSION};
+use line_index::{Encoding, LineIndex};
+use lint::is_op;
+use span::{Span, Spanned};
+use tokenizer::{tokenize, Token, TokenKind};
+
+/// Names of the token types, in the order of `SemanticKind`: the
+/// `tokenTypes` of the legend of the server capabilities.
+pub const TOKEN_TYPES: [&str; 13] = [
+    "namespace",
+    "class",
+    "function",
+    "method",
+    "parameter",
+    "variable",
+    "property",
+    "decorator",
+    "keyword",
+    "string",
+    "number",
+    "comment",
+    "operator",
+];
+
+/// Names of the token modifiers, in the order of their bits: the
+/// `tokenModifiers` of the legend of the server capabilities.
+pub const TOKEN_MODIFIERS: [&str; 2] = ["declaration", "defaultLibrary"];
+
+/// Modifier of the names in the code binding them.
+pub const DECLARATION: u32 = 1;
+/// Modifier of the builtins.
+pub const DEFAULT_LIBRARY: u32 = 2;
+
+/// Builtin classes, including the exceptions.
+const BUILTIN_CLASSES: &[&str] = &[
+    "ArithmeticError",
+    "AssertionError",
+    "AttributeError",
+    "BaseException",
+    "BlockingIOError",
+    "BufferError",
+    "BytesWarning",
+    "DeprecationWarning",
+    "EOFError",
+    "Exception",
+    "FileExistsError",
+    "FileNotFoundError",
+    "FloatingPointError",
+    "GeneratorExit",
+    "ImportError",
+    "IndentationError",
+    "IndexError",
+    "KeyError",
+    "KeyboardInterrupt",
+    "LookupError",
+    "MemoryError",
+    "ModuleNotFoundError",
+    "NameError",
+    "NotImplementedError",
+    "OSError",
+    "OverflowError",
+    "PermissionError",
+    "RecursionError",
+    "RuntimeError",
+    "RuntimeWarning",
+    "StopAsyncIteration",
+    "StopIteration",
+    "SyntaxError",
+    "SystemExit",
+    "TimeoutError",
+    "TypeError",
+    "UnicodeDecodeError",
+    "UnicodeEncodeError",
+    "UnicodeError",
+    "UserWarning",
+    "ValueError",
+    "Warning",
+    "ZeroDivisionError",
+    "bool",
+    "bytearray",
+    "bytes",
+    "complex",
+    "dict",
+    "float",
+    "frozenset",
+    "int",
+    "list",
+    "object",
+    "set",
+    "str",
+    "tuple",
+    "type",
+];
+
+/// Builtin functions, and classes used like functions.
+const BUILTIN_FUNCTIONS: &[&str] = &[
+    "__import__",
+    "abs",
+    "aiter",
+    "all",
+    "any",
+    "ascii",
+    "bin",
+    "breakpoint",
+    "callable",
+    "chr",
+    "classmethod",
+    "compile",
+    "delattr",
+    "dir",
+    "divmod",
+    "enumerate",
+    "eval",
+    "exec",
+    "filter",
+    "format",
+    "getattr",
+    "globals",
+    "hasattr",
+    "hash",
+    "help",
+    "hex",
+    "id",
+    "input",
+    "isinstance",
+    "issubclass",
+    "iter",
+    "len",
+    "locals",
+    "map",
+    "max",
+    "memoryview",
+    "min",
+    "next",
+    "oct",
+    "open",
+    "ord",
+    "pow",
+    "print",
+    "property",
+    "range",
+    "repr",
+    "reversed",
+    "round",
+    "setattr",
+    "slice",
+    "sorted",
+    "staticmethod",
+    "sum",
+    "super",
+    "vars",
+    "zip",
+];
+
+/// Builtin constants which are not keywords.
+const BUILTIN_VARIABLES: &[&str] = &[
+    "Ellipsis",
+    "NotImplemented",
+    "__debug__",
+    "__doc__",
+    "__file__",
+    "__name__",
+];
+
+/// The type of a `SemanticToken`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum SemanticKind {
+    /// An imported name.
+    Namespace,
+    Class,
+    Function,
+    /// An attribute which is called.
+    Method,
+    Parameter,
+    Variable,
+    /// An attribute which is not called.
+    Property,
+    /// The `@` and the name of a decorator.
+    Decorator,
+    Keyword,
+    String,
+    Number,
+    Comment,
+    Operator,
+}
+
+impl SemanticKind {
+    /// The index of the kind in `TOKEN_TYPES`.
+    pub fn index(self) -> u32 {
+        self as u32
+    }
+}
+
+/// A token of the code with its classification, found by
+/// `semantic_tokens`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct SemanticToken {
+    pub span: Span,
+    pub kind: SemanticKind,
+    /// Bits of `DECLARATION` and `DEFAULT_LIBRARY`.
+    pub modifiers: u32,
+}
+
+impl Spanned for SemanticToken {
+    fn span(&self) -> Span {
+        self.span
+    }
+}
+
+/// Returns the kind and modifiers of a name which is not bound in the
+/// module.
+fn builtin(name: &str) -> (SemanticKind, u32) {
+    if BUILTIN_CLASSES.contains(&name) {
+        (SemanticKind::Class, DEFAULT_LIBRARY)
+    } else if BUILTIN_FUNCTIONS.contains(&name) {
+        (SemanticKind::Function, DEFAULT_LIBRARY)
+    } else if BUILTIN_VARIABLES.contains(&name) {
+        (SemanticKind::Variable, DEFAULT_LIBRARY)
+    } else {
+        (SemanticKind::Variable, 0)
+    }
+}
+
+/// Returns the kind and modifiers of the name `tokens[i]`, which is
+/// not a keyword.
+pub(crate) fn classify_name(
+    bindings: &Bindings,
+    tokens: &[Token],
+    i: usize,
+) -> (SemanticKind, u32) {
+    let token = &tokens[i];
+    let previous = if i == 0 { None } else { tokens.get(i - 1) };
+    let next = tokens.get(i + 1);
+    if is_op(previous, ".") {
+        return if is_op(next, "(") {
+            (SemanticKind::Method, 0)
+        } else {
+            (SemanticKind::Property, 0)
+        };
+    }
+    let binding = match bindings.resolve(token.offset) {
+        Some(binding) => binding,
+        None if is_op(next, "=") && (is_op(previous, "(") || is_op(previous, ",")) => {
+            // A keyword argument
+            return (SemanticKind::Parameter, 0);
+        }
+        None => return builtin(token.text),
+    };
+    let kind = match binding.kind {
+        BindingKind::Parameter => SemanticKind::Parameter,
+        BindingKind::Variable => SemanticKind::Variable,
+        BindingKind::Function => SemanticKind::Function,
+        BindingKind::Class => SemanticKind::Class,
+        BindingKind::Import => SemanticKind::Namespace,
+    };
+    let modifiers = if binding.span == token.span() {
+        DECLARATION
+    } else {
+        0
+    };
+    (kind, modifiers)
+}
+
+/// Returns the classified tokens of `code`: its names, keywords,
+/// strings, numbers, comments and operators (but not its brackets and
+/// other delimiters), in the order of the code.
+///
+/// ```
+/// use python_parser::semantic::{semantic_tokens, SemanticKind, DECLARATION, DEFAULT_LIBRARY};
+///
+/// let code = "def f(x):\n    return len(x)\n";
+/// let tokens: Vec<_> = semantic_tokens(code)
+///     .into_iter()
+///     .map(|t| (&code[t.span.start..t.span.end], t.kind, t.modifiers))
+///     .collect();
+/// assert_eq!(
+///     tokens,
+///     vec![
+///         ("def", SemanticKind::Keyword, 0),
+///         ("f", SemanticKind::Function, DECLARATION),
+///         ("x", SemanticKind::Parameter, DECLARATION),
+///         ("return", SemanticKind::Keyword, 0),
+///         ("len", SemanticKind::Function, DEFAULT_LIBRARY),
+///         ("x", SemanticKind::Parameter, 0),
+///     ]
+/// );
+/// ```
+pub fn semantic_tokens(code: &str) -> Vec<SemanticToken> {
+    let bindings = Bindings::new(code);
+    let tokens: Vec<_> = tokenize(code)
+        .filter(|t| match t.kind {
+            TokenKind::Nl | TokenKind::Indent | TokenKind::Dedent | TokenKind::EndMarker => false,
+            _ => true,
+        })
+        .collect();
+    let mut result = Vec::new();
+    let mut in_decorator = false;
+    let mut line_start = true;
+    for (i, token) in tokens.iter().enumerate() {
+        let classification = match token.kind {
+            TokenKind::Name if is_keyword(token.text, PARSED_VERSION) => {
+                Some((SemanticKind::Keyword, 0))
+            }
+            TokenKind::Name if in_decorator => Some((SemanticKind::Decorator, 0)),
+            TokenKind::Name => Some(classify_name(&bindings, &tokens, i)),
+            TokenKind::String => Some((SemanticKind::String, 0)),
+            TokenKind::Number => Some((SemanticKind::Number, 0)),
+            TokenKind::Comment => Some((SemanticKind::Comment, 0)),
+            TokenKind::Op if line_start && token.text == "@" => {
+                in_decorator = true;
+                Some((SemanticKind::Decorator, 0))
+            }
+            TokenKind::Op => match token.text {
+                "(" | ")" | "[" | "]" | "{" | "}" | "," | ":" | "." | ";" | "->" | "..." => {
+                    if token.text != "." {
+                        in_decorator = false;
+                    }
+                    None
+                }
+                _ => Some((SemanticKind::Operator, 0)),
+            },
+            _ => None,
+        };
+        if let Some((kind, modifiers)) = classification {
+            result.push(SemanticToken {
+                span: token.span(),
+                kind,
+                modifiers,
+            });
+        }
+        match token.kind {
+            TokenKind::Newline => {
+                line_start = true;
+                in_decorator = false;
+            }
+            TokenKind::Comment => (),
+            _ => line_start = false,
+        }
+    }
+    result
+}
+
+/// Returns the `data` of the `SemanticTokens` of the Language Server
+/// Protocol: 5 numbers per token, its line and start relative to the
+/// previous token, its length, its type and its modifiers. `index` is
+/// the index of the code of the tokens, and `encoding` the unit of the
+/// columns negotiated with the client.
+///
+/// Tokens on several lines (like strings) are split at line breaks.
+///
+/// ```
+/// use python_parser::line_index::{Encoding, LineIndex};
+/// use python_parser::semantic::{encode_semantic_tokens, semantic_tokens};
+///
+/// let code = "x = 1\n";
+/// let data = encode_semantic_tokens(&semantic_tokens(code), &LineIndex::new(code), Encoding::Utf16);
+/// assert_eq!(data, vec![0, 0, 1, 5, 1, 0, 2, 1, 12, 0, 0, 2, 1, 10, 0]);
+/// ```
+pub fn encode_semantic_tokens(
+    tokens: &[SemanticToken],
+    index: &LineIndex,
+    encoding: Encoding,
+) -> Vec<u32> {
+    let mut data = Vec::new();
+    let (mut previous_line, mut previous_col) = (0, 0);
+    for token in tokens {
+        let (first, last) = match index.span_line_cols(token.span, Encoding::Utf8) {
+            Some(positions) => (positions.0.line, positions.1.line),
+            None => continue,
+        };
+        for line in first..=last {
+            let part = match index.line_span(line) {
+                Some(line) => Span::new(
+                    line.start.max(token.span.start),
+                    line.end.min(token.span.end),
+                ),
+                None => break,
+            };
+            let (from, to) = match index.span_line_cols(part, encoding) {
+                Some(positions) => positions,
+                None => break,
+            };
+            if to.col == from.col {
+                continue;
+            }
+            let delta_col = if from.line == previous_line {
+                from.col - previous_col
+            } else {
+                from.col
+            };
+            data.extend_from_slice(&[
+                from.line - previous_line,
+                delta_col,
+                to.col - from.col,
+                token.kind.index(),
+                token.modifiers,
+            ]);
+            previous_line = from.line;
+            previous_col = from.col;
+        }
+    }
+    data
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_semantic_tokens() {
+        let code = "import os
+# comment
+@functools.wraps(f)
+class A(Exception):
+    def m(self, *, key=None):
+        print(self.x, os.sep, sep='')
+        return A(key=self.m())
+";
+        let tokens: Vec<_> = semantic_tokens(code)
+            .into_iter()
+            .filter(|t| t.kind != SemanticKind::Keyword && t.kind != SemanticKind::Operator)
+            .map(|t| (&code[t.span.start..t.span.end], t.kind, t.modifiers))
+            .collect();
+        assert_eq!(
+            tokens,
+            vec![
+                ("os", SemanticKind::Namespace, DECLARATION),
+                ("# comment", SemanticKind::Comment, 0),
+                ("@", SemanticKind::Decorator, 0),
+                ("functools", SemanticKind::Decorator, 0),
+                ("wraps", SemanticKind::Decorator, 0),
+                ("f", SemanticKind::Variable, 0),
+                ("A", SemanticKind::Class, DECLARATION),
+                ("Exception", SemanticKind::Class, DEFAULT_LIBRARY),
+                ("m", SemanticKind::Function, DECLARATION),
+                ("self", SemanticKind::Parameter, DECLARATION),
+                ("key", SemanticKind::Parameter, DECLARATION),
+                ("print", SemanticKind::Function, DEFAULT_LIBRARY),
+                ("self", SemanticKind::Parameter, 0),
+                ("x", SemanticKind::Property, 0),
+                ("os", SemanticKind::Namespace, 0),
+                ("sep", SemanticKind::Property, 0),
+                ("sep", SemanticKind::Parameter, 0),
+                ("''", SemanticKind::String, 0),
+                ("A", SemanticKind::Class, 0),
+                ("key", SemanticKind::Parameter, 0),
+                ("self", SemanticKind::Parameter, 0),
+                ("m", SemanticKind::Method, 0),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_encode_multiline() {
+        let code = "s = '''a\n\u{e9}'''\n";
+        let data = encode_semantic_tokens(
+            &semantic_tokens(code),
+            &LineIndex::new(code),
+            Encoding::Utf16,
+        );
+        let string = SemanticKind::String.index();
+        assert_eq!(
+            data,
+            vec![
+                0,
+                0,
+                1,
+                5,
+                DECLARATION,
+                0,
+                2,
+                1,
+                12,
+                0,
+                0,
+                2,
+                4,
+                string,
+                0,
+                1,
+                0,
+                4,
+                string,
+                0
+            ]
+        );
+    }
+}
@@ -0,0 +1,769 @@
+//! A compact binary encoding of the AST, to store parsed modules and
+//! load them without parsing them again, or pass them to other tools.
+//!
+//! Encoded modules start with `PYAST` and the version of the encoding,
+//! `FORMAT_VERSION`, which does not depend on the version of this crate
+//! nor on its features. The compatibility policy is:
+//!
+//! * the version is bumped whenever the encoding of an existing node
+//!   changes; new nodes get new tags, so the encoding of the others is
+//!   unchanged, but the version is bumped too as older releases cannot
+//!   read them;
+//! * modules encoded with any version since `MIN_FORMAT_VERSION` are
+//!   decoded, the older ones being converted to the current AST;
+//! * modules encoded with a later version are rejected, and
+//!   `format_version` tells which version a tool needs to read them.
+//!
+//! Versions:
+//!
+//! 1. String contents were encoded as code points with the `wtf8`
+//!    feature and as UTF-8 without it, and are decoded as if encoded
+//!    with the features of the current build.
+//! 2. String contents are always encoded as code points.
+//! 3. Strings have the text of their literal (`PyString::raw`).
+//! 4. `Statement::Extension` nodes.
+//!
+//! Integers which do not fit in 64 bits cannot be decoded without the
+//! `bigint` feature, nor strings with lone surrogates without `wtf8`.
+
+use std::error::Error;
+use std::fmt;
+use std::mem;
+
+use ast::*;
+
+/// Start of all encoded modules.
+const MAGIC: &[u8] = b"PYAST";
+
+/// Version of the encoding written by `serialize_module`.
+pub const FORMAT_VERSION: u8 = 4;
+
+/// Oldest version of the encoding read by `deserialize_module`.
+pub const MIN_FORMAT_VERSION: u8 = 1;
+
+/// Error returned when decoding bytes that are not an encoded module.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DecodeError {
+    /// Offset of the first invalid byte.
+    pub offset: usize,
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid encoded AST at byte {}", self.offset)
+    }
+}
+
+impl Error for DecodeError {}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+    /// Version of the encoding being read.
+    version: u8,
+}
+
+impl<'a> Reader<'a> {
+    fn error(&self) -> DecodeError {
+        DecodeError { offset: self.pos }
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], DecodeError> {
+        if len > self.bytes.len() - self.pos {
+            return Err(self.error());
+        }
+        self.pos += len;
+        Ok(&self.bytes[self.pos - len..self.pos])
+    }
+
+    fn byte(&mut self) -> Result<u8, DecodeError> {
+        self.take(1).map(|bytes| bytes[0])
+    }
+}
+
+trait Serialize: Sized {
+    fn encode(&self, w: &mut Vec<u8>);
+    fn decode(r: &mut Reader) -> Result<Self, DecodeError>;
+}
+
+impl Serialize for u8 {
+    fn encode(&self, w: &mut Vec<u8>) {
+        w.push(*self)
+    }
+    fn decode(r: &mut Reader) -> Result<u8, DecodeError> {
+        r.byte()
+    }
+}
+
+// Lengths are encoded in LEB128.
+impl Serialize for usize {
+    fn encode(&self, w: &mut Vec<u8>) {
+        let mut n = *self;
+        while n >= 0x80 {
+            w.push((n & 0x7f) as u8 | 0x80);
+            n >>= 7;
+        }
+        w.push(n as u8);
+    }
+    fn decode(r: &mut Reader) -> Result<usize, DecodeError> {
+        let bits = 8 * mem::size_of::<usize>();
+        let mut n = 0usize;
+        let mut shift = 0;
+        loop {
+            let byte = r.byte()?;
+            let value = (byte & 0x7f) as usize;
+            if shift >= bits || (shift + 7 > bits && value >> (bits - shift) != 0) {
+                return Err(r.error()); // Overflow
+            }
+            n |= value << shift;
+            if byte < 0x80 {
+                return Ok(n);
+            }
+            shift += 7;
+        }
+    }
+}
+
+impl Serialize for bool {
+    fn encode(&self, w: &mut Vec<u8>) {
+        w.push(*self as u8)
+    }
+    fn decode(r: &mut Reader) -> Result<bool, DecodeError> {
+        match r.byte()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(r.error()),
+        }
+    }
+}
+
+impl Serialize for f64 {
+    fn encode(&self, w: &mut Vec<u8>) {
+        w.extend_from_slice(&self.to_bits().to_le_bytes())
+    }
+    fn decode(r: &mut Reader) -> Result<f64, DecodeError> {
+        let mut bytes = [0u8; 8];
+        bytes.copy_from_slice(r.take(8)?);
+        Ok(f64::from_bits(u64::from_le_bytes(bytes)))
+    }
+}
+
+impl Serialize for String {
+    fn encode(&self, w: &mut Vec<u8>) {
+        self.len().encode(w);
+        w.extend_from_slice(self.as_bytes());
+    }
+    fn decode(r: &mut Reader) -> Result<String, DecodeError> {
+        let len = usize::decode(r)?;
+        let start = r.pos;
+        let bytes = r.take(len)?;
+        String::from_utf8(bytes.to_vec()).map_err(|_| DecodeError { offset: start })
+    }
+}
+
+/// String contents are stored as their code points.
+#[cfg(feature = "wtf8")]
+fn encode_content(content: &PyStringContent, w: &mut Vec<u8>) {
+    let code_points: Vec<_> = content.code_points().collect();
+    code_points.len().encode(w);
+    for c in code_points {
+        (c.to_u32() as usize).encode(w);
+    }
+}
+
+/// String contents are stored as their code points.
+#[cfg(not(feature = "wtf8"))]
+fn encode_content(content: &PyStringContent, w: &mut Vec<u8>) {
+    content.chars().count().encode(w);
+    for c in content.chars() {
+        (c as usize).encode(w);
+    }
+}
+
+fn decode_code_points(r: &mut Reader) -> Result<PyStringContent, DecodeError> {
+    let len = usize::decode(r)?;
+    let mut content = PyStringContent::new();
+    for _ in 0..len {
+        let n = usize::decode(r)?;
+        match PyStringCodePoint::from_u32(n as u32) {
+            Some(c) if n <= 0x10_ffff => content.push(c),
+            _ => return Err(r.error()),
+        }
+    }
+    Ok(content)
+}
+
+#[cfg(feature = "wtf8")]
+fn decode_content(r: &mut Reader) -> Result<PyStringContent, DecodeError> {
+    decode_code_points(r)
+}
+
+#[cfg(not(feature = "wtf8"))]
+fn decode_content(r: &mut Reader) -> Result<PyStringContent, DecodeError> {
+    if r.version == 1 {
+        return String::decode(r);
+    }
+    decode_code_points(r)
+}
+
+/// Integers are stored as their little-endian bytes.
+#[cfg(feature = "bigint")]
+impl Serialize for IntegerType {
+    fn encode(&self, w: &mut Vec<u8>) {
+        let bytes = self.to_bytes_le();
+        bytes.len().encode(w);
+        w.extend_from_slice(&bytes);
+    }
+    fn decode(r: &mut Reader) -> Result<IntegerType, DecodeError> {
+        let len = usize::decode(r)?;
+        Ok(IntegerType::from_bytes_le(r.take(len)?))
+    }
+}
+
+/// Integers are stored as their little-endian bytes.
+#[cfg(not(feature = "bigint"))]
+impl Serialize for IntegerType {
+    fn encode(&self, w: &mut Vec<u8>) {
+        let bytes = self.to_le_bytes();
+        let len = bytes.iter().rposition(|&b| b != 0).map_or(0, |i| i + 1);
+        len.encode(w);
+        w.extend_from_slice(&bytes[..len]);
+    }
+    fn decode(r: &mut Reader) -> Result<IntegerType, DecodeError> {
+        let len = usize::decode(r)?;
+        if len > 8 {
+            return Err(r.error());
+        }
+        let bytes = r.take(len)?;
+        Ok(bytes
+            .iter()
+            .rev()
+            .fold(0, |n, &byte| (n << 8) | IntegerType::from(byte)))
+    }
+}
+
+impl<T: Serialize> Serialize for Box<T> {
+    fn encode(&self, w: &mut Vec<u8>) {
+        (**self).encode(w)
+    }
+    fn decode(r: &mut Reader) -> Result<Box<T>, DecodeError> {
+        T::decode(r).map(Box::new)
+    }
+}
+
+impl<T: Serialize> Serialize for Option<T> {
+    fn encode(&self, w: &mut Vec<u8>) {
+        match *self {
+            None => w.push(0),
+            Some(ref x) => {
+                w.push(1);
+                x.encode(w);
+            }
+        }
+    }
+    fn decode(r: &mut Reader) -> Result<Option<T>, DecodeError> {
+        match r.byte()? {
+            0 => Ok(None),
+            1 => T::decode(r).map(Some),
+            _ => Err(r.error()),
+        }
+    }
+}
+
+impl<T: Serialize> Serialize for Vec<T> {
+    fn encode(&self, w: &mut Vec<u8>) {
+        self.len().encode(w);
+        for x in self {
+            x.encode(w);
+        }
+    }
+    fn decode(r: &mut Reader) -> Result<Vec<T>, DecodeError> {
+        let len = usize::decode(r)?;
+        // Do not trust the length for the allocation, each item takes
+        // at least one byte.
+        let mut v = Vec::with_capacity(len.min(r.bytes.len() - r.pos));
+        for _ in 0..len {
+            v.push(T::decode(r)?);
+        }
+        Ok(v)
+    }
+}
+
+// Encoded like a `Vec`.
+#[cfg(feature = "smallvec")]
+impl<T: Serialize> Serialize for SmallList<T> {
+    fn encode(&self, w: &mut Vec<u8>) {
+        self.len().encode(w);
+        for x in self {
+            x.encode(w);
+        }
+    }
+    fn decode(r: &mut Reader) -> Result<SmallList<T>, DecodeError> {
+        let len = usize::decode(r)?;
+        let mut v = SmallList::new();
+        for _ in 0..len {
+            v.push(T::decode(r)?);
+        }
+        Ok(v)
+    }
+}
+
+impl<A: Serialize, B: Serialize> Serialize for (A, B) {
+    fn encode(&self, w: &mut Vec<u8>) {
+        self.0.encode(w);
+        self.1.encode(w);
+    }
+    fn decode(r: &mut Reader) -> Result<(A, B), DecodeError> {
+        let a = A::decode(r)?;
+        Ok((a, B::decode(r)?))
+    }
+}
+
+impl<A: Serialize, B: Serialize, C: Serialize> Serialize for (A, B, C) {
+    fn encode(&self, w: &mut Vec<u8>) {
+        self.0.encode(w);
+        self.1.encode(w);
+        self.2.encode(w);
+    }
+    fn decode(r: &mut Reader) -> Result<(A, B, C), DecodeError> {
+        let a = A::decode(r)?;
+        let b = B::decode(r)?;
+        Ok((a, b, C::decode(r)?))
+    }
+}
+
+/// Implements `Serialize` for a structure, by encoding its fields in
+/// order.
+macro_rules! serialize_struct {
+    ( $name:ident { $($field:ident),* $(,)* } ) => {
+        impl Serialize for $name {
+            fn encode(&self, w: &mut Vec<u8>) {
+                $( self.$field.encode(w); )*
+            }
+            fn decode(r: &mut Reader) -> Result<$name, DecodeError> {
+                Ok($name { $( $field: Serialize::decode(r)?, )* })
+            }
+        }
+    };
+}
+
+/// Implements `Serialize` for an enum, by encoding the tag of the
+/// variant, then its fields in order. Fields of tuple variants are
+/// named arbitrarily.
+macro_rules! serialize_enum {
+    ( $name:ident $(<$param:ident>)* {
+        $( $tag:tt => $variant:ident $( ( $($field:ident),* ) )* $( { $($sfield:ident),* } )* ),* $(,)*
+    } ) => {
+        impl$(<$param: Serialize>)* Serialize for $name$(<$param>)* {
+            fn encode(&self, w: &mut Vec<u8>) {
+                match *self {
+                    $(
+                        $name::$variant $( ( $(ref $field),* ) )* $( { $(ref $sfield),* } )* => {
+                            w.push($tag);
+                            $( $( $field.encode(w); )* )*
+                            $( $( $sfield.encode(w); )* )*
+                        }
+                    )*
+                }
+            }
+            fn decode(r: &mut Reader) -> Result<Self, DecodeError> {
+                Ok(match r.byte()? {
+                    $(
+                        $tag => $name::$variant
+                            $( ( $( { let $field = Serialize::decode(r)?; $field } ),* ) )*
+                            $( { $( $sfield: Serialize::decode(r)? ),* } )*,
+                    )*
+                    _ => return Err(DecodeError { offset: r.pos - 1 }),
+                })
+            }
+        }
+    };
+}
+
+serialize_enum!(StarParams<T> {
+    0 => No,
+    1 => Anonymous,
+    2 => Named(a),
+});
+
+serialize_struct!(TypedArgsList {
+    posonly_args,
+    args,
+    star_args,
+    keyword_args,
+    star_kwargs,
+});
+
+serialize_struct!(UntypedArgsList {
+    posonly_args,
+    args,
+    star_args,
+    keyword_args,
+    star_kwargs,
+});
+
+serialize_struct!(Decorator { name, args });
+
+serialize_enum!(Argument {
+    0 => Positional(a),
+    1 => Starargs(a),
+    2 => Keyword(a, b),
+    3 => Kwargs(a),
+});
+
+serialize_enum!(Subscript {
+    0 => Simple(a),
+    1 => Double(a, b),
+    2 => Triple(a, b, c),
+});
+
+serialize_enum!(Uop {
+    0 => Plus,
+    1 => Minus,
+    2 => Invert,
+    3 => Not,
+});
+
+serialize_enum!(Bop {
+    0 => Add,
+    1 => Sub,
+    2 => Mult,
+    3 => Matmult,
+    4 => Mod,
+    5 => Floordiv,
+    6 => Div,
+    7 => Power,
+    8 => Lshift,
+    9 => Rshift,
+    10 => BitAnd,
+    11 => BitXor,
+    12 => BitOr,
+    13 => Lt,
+    14 => Gt,
+    15 => Eq,
+    16 => Leq,
+    17 => Geq,
+    18 => Neq,
+    19 => In,
+    20 => NotIn,
+    21 => Is,
+    22 => IsNot,
+    23 => And,
+    24 => Or,
+});
+
+serialize_enum!(ComprehensionChunk {
+    0 => If { cond },
+    1 => For { async, item, iterator },
+});
+
+serialize_enum!(DictItem {
+    0 => Star(a),
+    1 => Unique(a, b),
+});
+
+serialize_enum!(SetItem {
+    0 => Star(a),
+    1 => Unique(a),
+});
+
+impl Serialize for PyString {
+    fn encode(&self, w: &mut Vec<u8>) {
+        self.prefix.encode(w);
+        encode_content(&self.content, w);
+        self.raw.encode(w);
+    }
+    fn decode(r: &mut Reader) -> Result<PyString, DecodeError> {
+        let prefix = String::decode(r)?;
+        let content = decode_content(r)?;
+        let raw = if r.version >= 3 {
+            Option::decode(r)?
+        } else {
+            None
+        };
+        Ok(PyString {
+            prefix,
+            content,
+            raw,
+        })
+    }
+}
+
+serialize_enum!(Expression {
+    0 => Ellipsis,
+    1 => None,
+    2 => True,
+    3 => False,
+    4 => Name(a),
+    5 => Int(a),
+    6 => ImaginaryInt(a),
+    7 => Float(a),
+    8 => ImaginaryFloat(a),
+    9 => String(a),
+    10 => Bytes(a),
+    11 => DictLiteral(a),
+    12 => SetLiteral(a),
+    13 => ListLiteral(a),
+    14 => TupleLiteral(a),
+    15 => DictComp(a, b),
+    16 => SetComp(a, b),
+    17 => ListComp(a, b),
+    18 => Generator(a, b),
+    19 => Await(a),
+    20 => Call(a, b),
+    21 => Subscript(a, b),
+    22 => Attribute(a, b),
+    23 => Uop(a, b),
+    24 => Bop(a, b, c),
+    25 => MultiBop(a, b),
+    26 => Ternary(a, b, c),
+    27 => Yield(a),
+    28 => YieldFrom(a),
+    29 => Star(a),
+    30 => Lambdef(a, b),
+    31 => Named(a, b),
+});
+
+serialize_enum!(Import {
+    0 => ImportFrom { leading_dots, path, names },
+    1 => ImportStarFrom { leading_dots, path },
+    2 => Import { names },
+});
+
+serialize_enum!(AugAssignOp {
+    0 => Add,
+    1 => Sub,
+    2 => Mult,
+    3 => MatMult,
+    4 => Div,
+    5 => Mod,
+    6 => BitAnd,
+    7 => BitOr,
+    8 => BitXor,
+    9 => Lshift,
+    10 => Rshift,
+    11 => Power,
+    12 => Floordiv,
+});
+
+serialize_enum!(Statement {
+    0 => Pass,
+    1 => Del(a),
+    2 => Break,
+    3 => Continue,
+    4 => Return(a),
+    5 => RaiseExcFrom(a, b),
+    6 => RaiseExc(a),
+    7 => Raise,
+    8 => Global(a),
+    9 => Nonlocal(a),
+    10 => Assert(a, b),
+    11 => Import(a),
+    12 => Expressions(a),
+    13 => Assignment(a, b),
+    14 => TypeAnnotation(a, b),
+    15 => TypedAssignment(a, b, c),
+    16 => AugmentedAssignment(a, b, c),
+    17 => Compound(a),
+    18 => Invalid(a),
+    19 => Extension(a, b),
+});
+
+serialize_struct!(Funcdef {
+    async,
+    decorators,
+    name,
+    parameters,
+    return_type,
+    code,
+});
+
+serialize_struct!(Classdef {
+    decorators,
+    name,
+    arguments,
+    code,
+});
+
+serialize_struct!(Try {
+    try_block,
+    except_clauses,
+    last_except,
+    else_block,
+    finally_block,
+});
+
+serialize_enum!(CompoundStatement {
+    0 => If(a, b),
+    1 => For { async, item, iterator, for_block, else_block },
+    2 => While(a, b, c),
+    3 => With(a, b),
+    4 => Funcdef(a),
+    5 => Classdef(a),
+    6 => Try(a),
+});
+
+serialize_struct!(Module {
+    shebang,
+    encoding,
+    leading_comments,
+    trailing_newline,
+    body,
+});
+
+/// Encodes a module, so that `deserialize_module` can return it later.
+pub fn serialize_module(module: &Module) -> Vec<u8> {
+    let mut w = MAGIC.to_vec();
+    w.push(FORMAT_VERSION);
+    module.encode(&mut w);
+    w
+}
+
+/// Returns the version of the encoding of a module encoded by
+/// `serialize_module`, or `None` if `bytes` is not one.
+///
+/// ```
+/// use python_parser::parse_module;
+/// use python_parser::serialize::{format_version, serialize_module, FORMAT_VERSION};
+///
+/// let bytes = serialize_module(&parse_module("x = 1\n").unwrap());
+/// assert_eq!(format_version(&bytes), Some(FORMAT_VERSION));
+/// assert_eq!(format_version(b"x = 1\n"), None);
+/// ```
+pub fn format_version(bytes: &[u8]) -> Option<u8> {
+    if bytes.starts_with(MAGIC) {
+        bytes.get(MAGIC.len()).cloned()
+    } else {
+        None
+    }
+}
+
+/// Decodes a module encoded by `serialize_module`, by this release or
+/// an earlier one.
+///
+/// ```
+/// use python_parser::parse_module;
+/// use python_parser::serialize::{deserialize_module, serialize_module};
+///
+/// let module = parse_module("#!/usr/bin/env python\nprint(1 + 2)\n").unwrap();
+/// let bytes = serialize_module(&module);
+/// assert_eq!(deserialize_module(&bytes), Ok(module));
+/// assert!(deserialize_module(&bytes[..bytes.len() - 1]).is_err());
+/// ```
+pub fn deserialize_module(bytes: &[u8]) -> Result<Module, DecodeError> {
+    let version = match format_version(bytes) {
+        Some(version) if version >= MIN_FORMAT_VERSION && version <= FORMAT_VERSION => version,
+        _ => return Err(DecodeError { offset: 0 }),
+    };
+    let mut r = Reader {
+        bytes,
+        pos: MAGIC.len() + 1,
+        version,
+    };
+    let module = Module::decode(&mut r)?;
+    if r.pos != bytes.len() {
+        return Err(r.error());
+    }
+    Ok(module)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parse_module;
+
+    fn roundtrip(code: &str) {
+        let module = parse_module(code).unwrap();
+        let bytes = serialize_module(&module);
+        assert_eq!(deserialize_module(&bytes), Ok(module), "{:?}", code);
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        roundtrip("");
+        roundtrip("# comment\nx = 1\n");
+        roundtrip("from ..a.b import c as d, e\nimport f.g as h\nfrom . import *\n");
+        roundtrip("x = [1, 2.5, 3j, 4.5j, 'foo', b'bar', u'\\u1234', ..., None, True]\n");
+        roundtrip("x = 12345678901234567 + 0xffffffffffffffff\n");
+        roundtrip("y = {a: b, **c}, {d, *e}, (f,), [g for h in i if j]\n");
+        roundtrip("z = f(a, *b, c=d, **e)[f:g, ::h, i] if not j else -k.l\n");
+        roundtrip("a < b <= c; d @= e; f: g = h; i: j\n");
+        roundtrip("@dec(x)\nasync def f(a, b=1, *c: int, d, e=2, **f) -> g:\n    await h\n    yield from i\n    return lambda j, *k, l=3, **m: (n := j)\n");
+        roundtrip("class A(B, metaclass=C):\n    global d\n    nonlocal e\n    del f\n");
+        roundtrip(
+            "for a in b:\n    break\nelse:\n    continue\nwhile c:\n    pass\nelse:\n    raise\n",
+        );
+        roundtrip("if a:\n    pass\nelif b:\n    pass\nelse:\n    pass\nwith c as d, e:\n    assert f, g\n");
+        roundtrip("try:\n    raise a from b\nexcept c as d:\n    raise e\nexcept:\n    pass\nelse:\n    pass\nfinally:\n    pass\n");
+        roundtrip("async def f():\n    async for a in b:\n        pass\n    return {c async for c in d}\n");
+    }
+
+    #[test]
+    fn test_invalid() {
+        let bytes = serialize_module(&parse_module("x = f(1)\n").unwrap());
+        assert!(deserialize_module(b"").is_err());
+        assert!(deserialize_module(b"PYAST").is_err());
+        let mut other_version = bytes.clone();
+        other_version[MAGIC.len()] += 1;
+        assert_eq!(
+            deserialize_module(&other_version),
+            Err(DecodeError { offset: 0 })
+        );
+        for len in 0..bytes.len() {
+            assert!(deserialize_module(&bytes[..len]).is_err());
+        }
+        let mut extra = bytes.clone();
+        extra.push(0);
+        assert!(deserialize_module(&extra).is_err());
+    }
+
+    #[test]
+    fn test_versions() {
+        let module = parse_module("'\\xe9'\n").unwrap();
+        let header = b"PYAST\x04\x00\x00\x00\x01\x01\x0d\x01\x09\x01\x00";
+        let mut expected = header.to_vec();
+        expected.extend_from_slice(b"\x01\xe9\x01\x00\x00");
+        // The same with all features
+        assert_eq!(serialize_module(&module), expected);
+        assert_eq!(format_version(&expected), Some(4));
+
+        let mut version_3 = expected.clone();
+        version_3[MAGIC.len()] = 3;
+        assert_eq!(deserialize_module(&version_3).as_ref(), Ok(&module));
+
+        let mut version_2 = header.to_vec();
+        version_2[MAGIC.len()] = 2;
+        version_2.extend_from_slice(b"\x01\xe9\x01\x00");
+        assert_eq!(deserialize_module(&version_2).as_ref(), Ok(&module));
+
+        let mut version_1 = header.to_vec();
+        version_1[MAGIC.len()] = 1;
+        if cfg!(feature = "wtf8") {
+            version_1.extend_from_slice(b"\x01\xe9\x01\x00");
+        } else {
+            version_1.extend_from_slice("\x02\u{e9}\x00".as_bytes());
+        }
+        assert_eq!(deserialize_module(&version_1), Ok(module));
+    }
+
+    #[test]
+    fn test_usize() {
+        for &n in &[0, 1, 127, 128, 300, 1 << 40, usize::MAX] {
+            let mut w = Vec::new();
+            n.encode(&mut w);
+            let mut r = Reader {
+                bytes: &w,
+                pos: 0,
+                version: FORMAT_VERSION,
+            };
+            assert_eq!(usize::decode(&mut r), Ok(n));
+            assert_eq!(r.pos, w.len());
+        }
+        let mut r = Reader {
+            bytes: &[0xff; 11],
+            pos: 0,
+            version: FORMAT_VERSION,
+        };
+        assert!(usize::decode(&mut r).is_err());
+    }
+}
@@ -0,0 +1,353 @@
+//! A JSON-RPC 2.0 server, so that editors and other tools can send code
+//! to a single long-running process (eg. the command line tool with
+//! `--server`) instead of starting one for each file.
+//!
+//! Requests and responses are JSON objects, one per line. The methods
+//! take the code either as a `code` string or as the `path` of a file:
+//!
+//! * `parse`: the number of top-level statements and the syntax errors;
+//! * `format`: the code printed again from its AST;
+//! * `diagnostics`: the syntax errors and the problems found by the
+//!   lint rules;
+//! * `outline`: the `DocumentSymbol`s of the Language Server Protocol;
+//! * `query`: the first line of the nodes selected by a `filter` (see
+//!   `query`);
+//! * `exit`: stops the server.
+//!
+//! Positions are in UTF-16 code units, like in the Language Server
+//! Protocol. Parsed modules are cached, so requests on the same code
+//! are only parsed once.
+
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::io::{BufRead, Write};
+
+use ast::*;
+use errors::ParseError;
+use json::Json;
+use line_index::{Encoding, LineIndex};
+use lint::security::security_rules;
+use lint::style::style_rules;
+use lint::{lint, Rule};
+use outline::{document_symbol, lsp_range, outline};
+use parse_module;
+use query::{select, Filter};
+use span::Span;
+use tolerant::parse_module_tolerant;
+use visitors::printer::format_file;
+
+/// Number of parsed modules kept by the server.
+const CACHE_SIZE: usize = 64;
+
+// Error codes of JSON-RPC.
+const PARSE_ERROR: i32 = -32700;
+const INVALID_REQUEST: i32 = -32600;
+const METHOD_NOT_FOUND: i32 = -32601;
+const INVALID_PARAMS: i32 = -32602;
+/// Error code of requests on code with a syntax error.
+const SYNTAX_ERROR: i32 = -32000;
+
+struct RpcError {
+    code: i32,
+    message: String,
+}
+
+impl RpcError {
+    fn new(code: i32, message: &str) -> RpcError {
+        RpcError {
+            code,
+            message: message.to_string(),
+        }
+    }
+}
+
+impl From<ParseError> for RpcError {
+    fn from(e: ParseError) -> RpcError {
+        RpcError {
+            code: SYNTAX_ERROR,
+            message: e.to_string(),
+        }
+    }
+}
+
+fn object(members: Vec<(&str, Json)>) -> Json {
+    Json::Object(
+        members
+            .into_iter()
+            .map(|(key, value)| (key.to_string(), value))
+            .collect(),
+    )
+}
+
+fn diagnostic(index: &LineIndex, span: Span, rule: &str, severity: &str, message: String) -> Json {
+    object(vec![
+        ("range", lsp_range(index, span, Encoding::Utf16)),
+        ("rule", Json::String(rule.to_string())),
+        ("severity", Json::String(severity.to_string())),
+        ("message", Json::String(message)),
+    ])
+}
+
+fn syntax_errors(errors: &[ParseError], index: &LineIndex) -> Vec<Json> {
+    errors
+        .iter()
+        .map(|e| {
+            let span = Span::new(e.offset, e.offset);
+            diagnostic(index, span, "E999", "high", e.to_string())
+        })
+        .collect()
+}
+
+/// The state of the server between requests.
+pub struct Server {
+    /// Parsed modules, by code.
+    cache: HashMap<String, Module>,
+    rules: Vec<Box<dyn Rule>>,
+    running: bool,
+}
+
+impl Default for Server {
+    fn default() -> Server {
+        Server::new()
+    }
+}
+
+impl Server {
+    /// Returns a server using the security and style lint rules.
+    pub fn new() -> Server {
+        let mut rules = security_rules();
+        rules.extend(style_rules());
+        Server {
+            cache: HashMap::new(),
+            rules,
+            running: true,
+        }
+    }
+
+    /// Returns the parsed module of `code`, from the cache if possible.
+    fn module(&mut self, code: &str) -> Result<&Module, ParseError> {
+        if !self.cache.contains_key(code) {
+            let module = parse_module(code)?;
+            if self.cache.len() >= CACHE_SIZE {
+                self.cache.clear();
+            }
+            self.cache.insert(code.to_string(), module);
+        }
+        Ok(&self.cache[code])
+    }
+
+    fn call(&mut self, method: &str, params: Option<&Json>) -> Result<Json, RpcError> {
+        if method == "exit" {
+            self.running = false;
+            return Ok(Json::Null);
+        }
+        let params = params.ok_or_else(|| RpcError::new(INVALID_PARAMS, "missing params"))?;
+        let code = match (params.get("code"), params.get("path")) {
+            (Some(code), _) => code
+                .as_str()
+                .ok_or_else(|| RpcError::new(INVALID_PARAMS, "code is not a string"))?
+                .to_string(),
+            (None, Some(path)) => {
+                let path = path
+                    .as_str()
+                    .ok_or_else(|| RpcError::new(INVALID_PARAMS, "path is not a string"))?;
+                fs::read_to_string(path).map_err(|e| {
+                    RpcError::new(INVALID_PARAMS, &format!("cannot read {}: {}", path, e))
+                })?
+            }
+            (None, None) => return Err(RpcError::new(INVALID_PARAMS, "missing code or path")),
+        };
+        let index = LineIndex::new(&code);
+        match method {
+            "parse" => {
+                let module = parse_module_tolerant(&code);
+                let errors = syntax_errors(&module.errors, &index);
+                Ok(object(vec![
+                    ("statements", Json::Number(module.module.body.len() as f64)),
+                    ("errors", Json::Array(errors)),
+                ]))
+            }
+            "format" => Ok(object(vec![(
+                "code",
+                Json::String(format_file(self.module(&code)?)),
+            )])),
+            "diagnostics" => {
+                let errors = parse_module_tolerant(&code).errors;
+                let mut diagnostics = syntax_errors(&errors, &index);
+                for d in lint(&code, &self.rules) {
+                    let severity = d.severity.to_string();
+                    diagnostics.push(diagnostic(&index, d.span, d.rule, &severity, d.message));
+                }
+                Ok(Json::Array(diagnostics))
+            }
+            "outline" => {
+                let symbols = outline(&code)?
+                    .iter()
+                    .map(|symbol| document_symbol(symbol, &index, Encoding::Utf16))
+                    .collect();
+                Ok(Json::Array(symbols))
+            }
+            "query" => {
+                let filter = params
+                    .get("filter")
+                    .and_then(Json::as_str)
+                    .ok_or_else(|| RpcError::new(INVALID_PARAMS, "missing filter"))?;
+                let filter = Filter::parse(filter)
+                    .map_err(|e| RpcError::new(INVALID_PARAMS, &e.to_string()))?;
+                let mut nodes = Vec::new();
+                select(&self.module(&code)?.body, &filter, |node, _| {
+                    nodes.push(Json::String(node.first_line()))
+                });
+                Ok(Json::Array(nodes))
+            }
+            _ => Err(RpcError::new(METHOD_NOT_FOUND, "method not found")),
+        }
+    }
+
+    /// Handles a request, and returns the response, or `None` for
+    /// notifications (requests without an `id`).
+    ///
+    /// ```
+    /// use python_parser::server::Server;
+    ///
+    /// let mut server = Server::new();
+    /// let response = server.handle(r#"{"jsonrpc": "2.0", "id": 1, "method": "format", "params": {"code": "x=( 1 )\n"}}"#);
+    /// assert_eq!(response.unwrap(), r#"{"jsonrpc":"2.0","id":1,"result":{"code":"x = 1\n"}}"#);
+    /// ```
+    pub fn handle(&mut self, request: &str) -> Option<String> {
+        let (id, result) = match Json::parse(request) {
+            Err(_) => (Json::Null, Err(RpcError::new(PARSE_ERROR, "parse error"))),
+            Ok(request) => {
+                let id = request.get("id").cloned();
+                let result = match request.get("method").and_then(Json::as_str) {
+                    Some(method) => self.call(method, request.get("params")),
+                    None => Err(RpcError::new(INVALID_REQUEST, "missing method")),
+                };
+                (id?, result)
+            }
+        };
+        let response = match result {
+            Ok(result) => ("result", result),
+            Err(e) => (
+                "error",
+                object(vec![
+                    ("code", Json::Number(f64::from(e.code))),
+                    ("message", Json::String(e.message)),
+                ]),
+            ),
+        };
+        Some(
+            object(vec![
+                ("jsonrpc", Json::String("2.0".to_string())),
+                ("id", id),
+                response,
+            ])
+            .to_string(),
+        )
+    }
+
+    /// Handles the requests of `input`, one per line, and writes the
+    /// responses to `output`, until the end of `input` or an `exit`
+    /// request.
+    pub fn serve<R: BufRead, W: Write>(&mut self, input: R, mut output: W) -> io::Result<()> {
+        for line in input.lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            if let Some(response) = self.handle(&line) {
+                writeln!(output, "{}", response)?;
+                output.flush()?;
+            }
+            if !self.running {
+                break;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn result(server: &mut Server, method: &str, params: &str) -> Json {
+        let request = format!(
+            r#"{{"jsonrpc":"2.0","id":"a","method":"{}","params":{}}}"#,
+            method, params
+        );
+        let response = Json::parse(&server.handle(&request).unwrap()).unwrap();
+        assert_eq!(response.get("id"), Some(&Json::String("a".to_string())));
+        response
+            .get("result")
+            .or_else(|| response.get("error"))
+            .unwrap()
+            .clone()
+    }
+
+    #[test]
+    fn test_methods() {
+        let mut server = Server::new();
+        let code = r#"{"code":"import os\ndef f():\n    print(os.sep)\n"}"#;
+        let parse = result(&mut server, "parse", code);
+        assert_eq!(parse.get("statements"), Some(&Json::Number(2.)));
+        assert_eq!(parse.get("errors"), Some(&Json::Array(vec![])));
+
+        let outline = result(&mut server, "outline", code);
+        let symbols = outline.as_array().unwrap();
+        assert_eq!(symbols[0].get("name").and_then(Json::as_str), Some("f"));
+
+        let query = r#"{"code":"import os\ndef f():\n    print(os.sep)\n","filter":"kind=call"}"#;
+        let query = result(&mut server, "query", query);
+        assert_eq!(
+            query,
+            Json::Array(vec![Json::String("print(os.sep)".to_string())])
+        );
+        assert_eq!(server.cache.len(), 1);
+
+        let diagnostics = result(
+            &mut server,
+            "diagnostics",
+            r#"{"code":"import os\nx = (\n"}"#,
+        );
+        let diagnostics = diagnostics.as_array().unwrap();
+        let rules: Vec<_> = diagnostics
+            .iter()
+            .map(|d| d.get("rule").and_then(Json::as_str).unwrap())
+            .collect();
+        assert_eq!(rules, vec!["E999", "F401"]);
+
+        let error = result(&mut server, "format", r#"{"code":"x = (\n"}"#);
+        assert_eq!(error.get("code"), Some(&Json::Number(-32000.)));
+        let error = result(&mut server, "format", r#"{"path":"/nonexistent.py"}"#);
+        assert_eq!(error.get("code"), Some(&Json::Number(-32602.)));
+        let error = result(&mut server, "reformat", code);
+        assert_eq!(error.get("code"), Some(&Json::Number(-32601.)));
+    }
+
+    #[test]
+    fn test_serve() {
+        let input = concat!(
+            "not json\n",
+            "\n",
+            r#"{"jsonrpc":"2.0","method":"parse","params":{"code":""}}"#,
+            "\n",
+            r#"{"jsonrpc":"2.0","id":2,"method":"exit"}"#,
+            "\n",
+            r#"{"jsonrpc":"2.0","id":3,"method":"parse","params":{"code":""}}"#,
+            "\n",
+        );
+        let mut output = Vec::new();
+        Server::new().serve(input.as_bytes(), &mut output).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            concat!(
+                r#"{"jsonrpc":"2.0","id":null,"error":{"code":-32700,"message":"parse error"}}"#,
+                "\n",
+                r#"{"jsonrpc":"2.0","id":2,"result":null}"#,
+                "\n",
+            )
+        );
+    }
+}
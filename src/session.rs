@@ -0,0 +1,138 @@
+//! A parser reusing its buffers between parses, for tools parsing many
+//! files.
+//!
+//! The settings of a session are the ones of `budget::with_budget`,
+//! `decoding::with_string_decoding` and `memo::with_memoization`, which
+//! it sets on the current thread for each parse, as the parsers read
+//! them from there. The memory of the memoization table (see `memo`),
+//! and with the `recursive-descent` feature, of the tokens, is reused by
+//! the following parses instead of being allocated again for each file.
+//! Names are not interned and the AST is not allocated in an arena.
+
+use ast::Module;
+use budget::{with_budget, Budget};
+use decoding::{with_string_decoding, StringDecoding};
+use errors::ParseError;
+use memo::{with_table, Table};
+use parse_module;
+#[cfg(feature = "recursive-descent")]
+use recursive_descent::{parse_module_in, TokenBuffer};
+
+/// A parsing session, holding the settings of its parses and the
+/// buffers they reuse.
+///
+/// ```
+/// use python_parser::decoding::StringDecoding;
+/// use python_parser::session::Parser;
+///
+/// let mut parser = Parser::new();
+/// parser.memoize = true;
+/// parser.decoding = StringDecoding::Both;
+/// for code in &["x = 'a'\n", "def f(x):\n    return g(h(x))\n"] {
+///     assert_eq!(parser.parse(code).unwrap().body.len(), 1);
+/// }
+/// ```
+#[derive(Debug, Default)]
+pub struct Parser {
+    /// Limits of each parse; the fuel is not shared between parses.
+    pub budget: Option<Budget>,
+    pub decoding: StringDecoding,
+    /// Whether to memoize the rules, like `memo::with_memoization`.
+    pub memoize: bool,
+    /// Whether to parse with `recursive_descent` (which does not
+    /// memoize) instead of the nom-based parsers.
+    #[cfg(feature = "recursive-descent")]
+    pub recursive_descent: bool,
+    table: Table,
+    #[cfg(feature = "recursive-descent")]
+    tokens: TokenBuffer,
+}
+
+impl Parser {
+    pub fn new() -> Parser {
+        Parser::default()
+    }
+
+    /// Parses a module, like `parse_module`, with the settings of the
+    /// session.
+    pub fn parse(&mut self, code: &str) -> Result<Module, ParseError> {
+        let budget = self.budget.clone();
+        #[cfg(feature = "recursive-descent")]
+        let tokens = if self.recursive_descent {
+            Some(&mut self.tokens)
+        } else {
+            None
+        };
+        let parse_code = move || {
+            #[cfg(feature = "recursive-descent")]
+            {
+                if let Some(tokens) = tokens {
+                    return parse_module_in(code, tokens);
+                }
+            }
+            parse_module(code)
+        };
+        let parse = || match budget {
+            Some(budget) => with_budget(budget, parse_code),
+            None => parse_code(),
+        };
+        let table = &mut self.table;
+        let memoize = self.memoize;
+        with_string_decoding(self.decoding, || {
+            if memoize {
+                with_table(table, parse)
+            } else {
+                parse()
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use errors::PyParseError;
+
+    #[test]
+    fn test_reuse() {
+        let mut parser = Parser {
+            budget: Some(Budget {
+                fuel: Some(50),
+                ..Budget::default()
+            }),
+            memoize: true,
+            ..Parser::default()
+        };
+        let code = "f(g(), y)\n";
+        let expected = parse_module(code).unwrap();
+        assert_eq!(parser.parse(code), Ok(expected.clone()));
+        assert!(parser.table.is_empty());
+        let capacity = parser.table.capacity();
+        assert!(capacity > 0);
+        // The fuel is given again to each parse
+        assert_eq!(parser.parse(code), Ok(expected));
+        assert_eq!(parser.table.capacity(), capacity);
+        let error = parser.parse(&"x = 1\n".repeat(100)).unwrap_err();
+        assert_eq!(error.code, Some(PyParseError::FuelExhausted));
+        // The session's settings do not leak out of it
+        assert!(parse_module(&"x = 1\n".repeat(100)).is_ok());
+    }
+
+    #[cfg(feature = "recursive-descent")]
+    #[test]
+    fn test_recursive_descent() {
+        let mut parser = Parser {
+            recursive_descent: true,
+            ..Parser::default()
+        };
+        let code = "#!/usr/bin/env python\ndef f(x):\r\n    return g(h(x))\n";
+        assert_eq!(parser.parse(code), parse_module(code));
+        let capacity = parser.tokens.0.capacity();
+        assert!(capacity > 0);
+        assert!(parser.tokens.0.is_empty());
+        let error = parser.parse("x = (1,\n2 3)\n").unwrap_err();
+        assert_eq!(error.code, Some(PyParseError::MissingComma));
+        assert_eq!(parser.parse("x = 1\n"), parse_module("x = 1\n"));
+        assert_eq!(parser.tokens.0.capacity(), capacity);
+    }
+}
@@ -0,0 +1,172 @@
+//! The call around a cursor, for the signature help of editors (the
+//! `textDocument/signatureHelp` request of the Language Server
+//! Protocol): which function is called, and which of its arguments is
+//! being typed.
+//!
+//! Like `completion`, it works on the tokens of the code, so the call
+//! may be incomplete.
+
+use ast::Expression;
+use completion::primary_start;
+use keywords::{is_keyword, PARSED_VERSION};
+use lint::is_op;
+use parse_expression;
+use span::{Span, Spanned};
+use tokenizer::{tokenize, TokenKind};
+
+/// A call containing a cursor, found by `call_context`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CallContext {
+    /// The code of the called expression, like `os.path.join`.
+    pub callee: Span,
+    /// The called expression, if it can be parsed.
+    pub function: Option<Expression>,
+    /// The opening parenthesis of the arguments.
+    pub open: Span,
+    /// Index of the argument containing the cursor, counting the
+    /// arguments before it (positional or not), from 0.
+    pub argument: usize,
+    /// The name of the argument containing the cursor, if it is a
+    /// keyword argument (like `sep` in `print(x, sep=|`, or in
+    /// `print(x, se|p='')`).
+    pub keyword: Option<String>,
+}
+
+impl Spanned for CallContext {
+    fn span(&self) -> Span {
+        self.callee.cover(self.open)
+    }
+}
+
+/// Returns the innermost call whose arguments contain `offset`, or
+/// `None` if the cursor is not in the arguments of a call.
+///
+/// ```
+/// use python_parser::signature::call_context;
+///
+/// let code = "print(f(x), end='')";
+/// let call = call_context(code, code.find("''").unwrap()).unwrap();
+/// assert_eq!(&code[call.callee.start..call.callee.end], "print");
+/// assert_eq!(call.argument, 1);
+/// assert_eq!(call.keyword.as_ref().map(|k| &k[..]), Some("end"));
+/// ```
+pub fn call_context(code: &str, offset: usize) -> Option<CallContext> {
+    let tokens: Vec<_> = tokenize(code)
+        .filter(|t| match t.kind {
+            TokenKind::Comment
+            | TokenKind::Nl
+            | TokenKind::Indent
+            | TokenKind::Dedent
+            | TokenKind::EndMarker => false,
+            _ => true,
+        })
+        .collect();
+    let before = tokens.iter().take_while(|t| t.offset < offset).count();
+    // Cursors in strings or comments are still in the call
+    let line_start = tokens[..before]
+        .iter()
+        .rposition(|t| t.kind == TokenKind::Newline || is_op(Some(t), ";"))
+        .map_or(0, |i| i + 1);
+    // The unclosed brackets before the cursor, with the number of
+    // commas in them and the index of the last one
+    let mut brackets: Vec<(usize, usize, Option<usize>)> = Vec::new();
+    for (i, token) in tokens.iter().enumerate().take(before).skip(line_start) {
+        if token.kind != TokenKind::Op {
+            continue;
+        }
+        match token.text {
+            "(" | "[" | "{" => brackets.push((i, 0, None)),
+            ")" | "]" | "}" => {
+                brackets.pop();
+            }
+            "," => {
+                if let Some(bracket) = brackets.last_mut() {
+                    bracket.1 += 1;
+                    bracket.2 = Some(i);
+                }
+            }
+            _ => (),
+        }
+    }
+    // The innermost parenthesis of a call (and not of a definition)
+    let (open, argument, last_comma) = brackets.into_iter().rev().find(|&(open, _, _)| {
+        let previous = if open == 0 {
+            None
+        } else {
+            tokens.get(open - 1)
+        };
+        let is_definition =
+            open >= 2 && (tokens[open - 2].text == "def" || tokens[open - 2].text == "class");
+        tokens[open].text == "("
+            && !is_definition
+            && previous.is_some_and(|t| match t.kind {
+                TokenKind::Name => !is_keyword(t.text, PARSED_VERSION),
+                TokenKind::String => true,
+                TokenKind::Op => [")", "]", "}"].contains(&t.text),
+                _ => false,
+            })
+    })?;
+    let argument_start = last_comma.map_or(open + 1, |comma| comma + 1);
+    let keyword = match (tokens.get(argument_start), tokens.get(argument_start + 1)) {
+        (Some(name), Some(equal))
+            if name.kind == TokenKind::Name && is_op(Some(equal), "=") && name.offset <= offset =>
+        {
+            Some(name.text.to_string())
+        }
+        _ => None,
+    };
+    let start = primary_start(&tokens, open);
+    let callee = tokens[start].span().cover(tokens[open - 1].span());
+    Some(CallContext {
+        callee,
+        function: parse_expression(&code[callee.start..callee.end]).ok(),
+        open: tokens[open].span(),
+        argument,
+        keyword,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns the callee, argument index and keyword at the `|` in
+    /// `code`.
+    fn context(code: &str) -> Option<(String, usize, Option<String>)> {
+        let offset = code.find('|').unwrap();
+        let code = code.replace('|', "");
+        call_context(&code, offset).map(|call| {
+            (
+                code[call.callee.start..call.callee.end].to_string(),
+                call.argument,
+                call.keyword,
+            )
+        })
+    }
+
+    fn call(
+        callee: &str,
+        argument: usize,
+        keyword: Option<&str>,
+    ) -> Option<(String, usize, Option<String>)> {
+        Some((callee.to_string(), argument, keyword.map(str::to_string)))
+    }
+
+    #[test]
+    fn test_call_context() {
+        assert_eq!(context("f(|"), call("f", 0, None));
+        assert_eq!(context("f(|)"), call("f", 0, None));
+        assert_eq!(context("f(a, (b, c), [d, |"), call("f", 2, None));
+        assert_eq!(context("x = a.b[0](1, g(2|), 3)"), call("g", 0, None));
+        assert_eq!(context("x = a.b[0](1, g(2)|, 3)"), call("a.b[0]", 1, None));
+        assert_eq!(context("f(a,\n  key=[1, |"), call("f", 1, Some("key")));
+        assert_eq!(context("f(a, ke|y=1)"), call("f", 1, Some("key")));
+        assert_eq!(context("f(a, '|, b')"), call("f", 1, None));
+        assert_eq!(context("f(a)|"), None);
+        assert_eq!(context("f(a)\n|"), None);
+        assert_eq!(context("def f(a, |"), None);
+        assert_eq!(context("class A(B, |"), None);
+        assert_eq!(context("x = (1, |"), None);
+        assert_eq!(context("if (|"), None);
+    }
+}
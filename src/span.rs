@@ -0,0 +1,193 @@
+//! Byte ranges of the code, and helpers to get or replace their text.
+//!
+//! These helpers check the spans instead of panicking, so an outdated
+//! span (eg. computed on another version of the code) is not a crash.
+
+use errors::ParseError;
+use lazy::LazyBody;
+use tokenizer::Token;
+
+/// A range of bytes of the code, from `start` (included) to `end`
+/// (excluded).
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    /// Returns the span from `start` to `end`, which are swapped if
+    /// `end` is before `start`.
+    pub fn new(start: usize, end: usize) -> Span {
+        if end < start {
+            Span {
+                start: end,
+                end: start,
+            }
+        } else {
+            Span { start, end }
+        }
+    }
+
+    /// Returns the empty span at `offset`.
+    pub fn at(offset: usize) -> Span {
+        Span::new(offset, offset)
+    }
+
+    pub fn len(&self) -> usize {
+        self.end - self.start
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+
+    /// Returns whether the byte at `offset` is in the span.
+    pub fn contains(&self, offset: usize) -> bool {
+        self.start <= offset && offset < self.end
+    }
+
+    /// Returns the smallest span containing both spans.
+    pub fn cover(&self, other: Span) -> Span {
+        Span::new(self.start.min(other.start), self.end.max(other.end))
+    }
+}
+
+/// Something with a known position in the code.
+pub trait Spanned {
+    fn span(&self) -> Span;
+}
+
+impl Spanned for Span {
+    fn span(&self) -> Span {
+        *self
+    }
+}
+
+impl<'a> Spanned for Token<'a> {
+    fn span(&self) -> Span {
+        Span::new(self.offset, self.offset + self.text.len())
+    }
+}
+
+impl<'a> Spanned for LazyBody<'a> {
+    fn span(&self) -> Span {
+        Span::new(self.offset(), self.offset() + self.code().len())
+    }
+}
+
+/// The empty span where the error was found.
+impl Spanned for ParseError {
+    fn span(&self) -> Span {
+        Span::at(self.offset)
+    }
+}
+
+/// Returns the code of `node`, or `None` if its span is not in `src`
+/// or does not start and end on character boundaries.
+///
+/// ```
+/// use python_parser::span::source_for;
+/// use python_parser::tokenizer::tokenize;
+///
+/// let src = "x = foo(bar)";
+/// let token = tokenize(src).nth(2).unwrap();
+/// assert_eq!(source_for(&token, src), Some("foo"));
+/// assert_eq!(source_for(&token, "x ="), None);
+/// ```
+pub fn source_for<'a, T: Spanned + ?Sized>(node: &T, src: &'a str) -> Option<&'a str> {
+    let span = node.span();
+    src.get(span.start..span.end)
+}
+
+/// Returns `src` with the code of `span` replaced by `text`, or `None`
+/// if the span is not in `src` or does not start and end on character
+/// boundaries.
+///
+/// ```
+/// use python_parser::span::{replace_span, Span};
+/// assert_eq!(replace_span("x = foo(bar)", Span::new(4, 7), "baz.qux"),
+///            Some("x = baz.qux(bar)".to_string()));
+/// ```
+pub fn replace_span(src: &str, span: Span, text: &str) -> Option<String> {
+    replace_spans(src, &[(span, text)])
+}
+
+/// Like `replace_span`, but makes several replacements, all relative to
+/// the original code.
+///
+/// Returns `None` if a span is not in `src` or two spans overlap (an
+/// empty span can only be next to another span, where it inserts its
+/// text before the other span's if it comes first in `edits`).
+pub fn replace_spans(src: &str, edits: &[(Span, &str)]) -> Option<String> {
+    let mut edits: Vec<_> = edits.iter().collect();
+    // Stable, so insertions at the same offset keep their order
+    edits.sort_by_key(|&&(span, _)| (span.start, span.end));
+    let mut s = String::with_capacity(src.len());
+    let mut pos = 0;
+    for &&(span, text) in &edits {
+        if span.start < pos {
+            return None; // Overlapping spans
+        }
+        s.push_str(src.get(pos..span.start)?);
+        src.get(span.start..span.end)?; // Checks the end of the span
+        s.push_str(text);
+        pos = span.end;
+    }
+    s.push_str(src.get(pos..)?);
+    Some(s)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parse_module;
+
+    #[test]
+    fn test_span() {
+        let span = Span::new(5, 2);
+        assert_eq!(span, Span { start: 2, end: 5 });
+        assert_eq!(span.len(), 3);
+        assert!(!span.is_empty() && Span::at(4).is_empty());
+        assert!(span.contains(2) && span.contains(4) && !span.contains(5));
+        assert_eq!(span.cover(Span::new(7, 8)), Span::new(2, 8));
+        assert_eq!(span.cover(Span::at(3)), span);
+    }
+
+    #[test]
+    fn test_source_for() {
+        let src = "x = 'é'\ny = (";
+        let error = parse_module(src).unwrap_err();
+        assert_eq!(source_for(&error, src), Some(""));
+        assert_eq!(source_for(&Span::new(4, 8), src), Some("'é'"));
+        assert_eq!(source_for(&Span::new(4, 6), src), None); // In 'é'
+        assert_eq!(source_for(&Span::new(10, 20), src), None);
+    }
+
+    #[test]
+    fn test_replace_spans() {
+        let src = "f(a, b)";
+        assert_eq!(
+            replace_spans(src, &[(Span::new(5, 6), "c"), (Span::new(0, 1), "g")]),
+            Some("g(a, c)".to_string())
+        );
+        assert_eq!(
+            replace_spans(
+                src,
+                &[
+                    (Span::at(2), "x, "),
+                    (Span::at(2), "y, "),
+                    (Span::new(2, 3), "z")
+                ]
+            ),
+            Some("f(x, y, z, b)".to_string())
+        );
+        assert_eq!(replace_span(src, Span::new(0, 7), ""), Some("".to_string()));
+        assert_eq!(
+            replace_spans(src, &[(Span::new(0, 3), ""), (Span::new(2, 4), "")]),
+            None
+        );
+        assert_eq!(replace_span(src, Span::new(6, 8), ""), None);
+        assert_eq!(replace_span("é", Span::new(1, 2), ""), None);
+    }
+}
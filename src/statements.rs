@@ -1,6 +1,7 @@
 use std::marker::PhantomData;
 
 use ast::*;
+use budget::spend_fuel;
 use errors::PyParseError;
 use expressions::ExpressionParser;
 use functions::decorated;
@@ -16,10 +17,10 @@ macro_rules! call_test {
 
 // stmt: simple_stmt | compound_stmt
 named_args!(pub statement(indent: usize) <StrSpan, Vec<Statement>>,
-  alt!(
+  preceded!(call!(spend_fuel), alt!(
     call!(compound_stmt, indent) => { |stmt| vec![Statement::Compound(Box::new(stmt))] }
   | preceded!(indent!(indent), call!(simple_stmt))
-  )
+  ))
 );
 
 // simple_stmt: small_stmt (';' small_stmt)* [';'] NEWLINE
@@ -72,8 +73,8 @@ named!(expr_stmt<StrSpan, Statement>,
         | call!(ExpressionParser::<NewlinesAreNotSpaces>::testlist)
         )))) >> (
           match rhs {
-              None => Statement::TypeAnnotation(lhs.clone(), *typed),
-              Some(rhs) => Statement::TypedAssignment(lhs.clone(), *typed, rhs),
+              None => Statement::TypeAnnotation(into_vec(lhs.clone()), *typed),
+              Some(rhs) => Statement::TypedAssignment(into_vec(lhs.clone()), *typed, rhs),
           }
         )
       )
@@ -85,14 +86,14 @@ named!(expr_stmt<StrSpan, Statement>,
           call!(ExpressionParser::<NewlinesAreNotSpaces>::yield_expr) => { |e| vec![e] }
         | call!(ExpressionParser::<NewlinesAreNotSpaces>::testlist)
         ) >> (
-          Statement::AugmentedAssignment(lhs.clone(), op, rhs)
+          Statement::AugmentedAssignment(into_vec(lhs.clone()), op, rhs)
         )
       )
 
     | // Case 3: "foo", "foo = bar", "foo = bar = baz", ...
       do_parse!(
         rhs: many0!(ws_nonl!(preceded!(char!('='), alt!(
-          call!(ExpressionParser::<NewlinesAreNotSpaces>::yield_expr) => { |e| vec![e] }
+          call!(ExpressionParser::<NewlinesAreNotSpaces>::yield_expr) => { |e| small_list![e] }
         | call!(ExpressionParser::<NewlinesAreNotSpaces>::testlist_star_expr)
         )))) >> (
           Statement::Assignment(lhs, rhs)
@@ -129,7 +130,7 @@ named!(augassign<StrSpan, AugAssignOp>,
 
 // del_stmt: 'del' exprlist
 named!(del_stmt<StrSpan, Statement>,
-  map!(delimited!(tuple!(keyword!("del"), spaces_nonl), ExpressionParser::<NewlinesAreNotSpaces>::exprlist, opt!(char!(','))), |v:Vec<_>| Statement::Del(v))
+  map!(delimited!(tuple!(keyword!("del"), spaces_nonl), ExpressionParser::<NewlinesAreNotSpaces>::exprlist, opt!(char!(','))), |v| Statement::Del(into_vec(v)))
   // TODO: check it's one of the allowed form of del expression
 );
 
@@ -149,8 +150,8 @@ named!(flow_stmt<StrSpan, Statement>,
   | keyword!("continue") => { |_| Statement::Continue }
   | preceded!(
       tuple!(keyword!("return"), spaces_nonl),
-      ws_nonl!(call!(ExpressionParser::<NewlinesAreNotSpaces>::testlist_star_expr))
-    ) => { |e| Statement::Return(e) }
+      opt!(ws_nonl!(call!(ExpressionParser::<NewlinesAreNotSpaces>::testlist_star_expr)))
+    ) => { |e: Option<_>| Statement::Return(e.map(into_vec).unwrap_or_default()) }
   | raise_stmt
   | call!(ExpressionParser::<NewlinesAreNotSpaces>::yield_expr)
     => { |e| Statement::Expressions(vec![e]) }
@@ -233,10 +234,10 @@ named!(import_from<StrSpan, Import>,
       do_parse!(
         leading_dots: ws_nonl!(map!(many0!(char!('.')), |dots| dots.len())) >>
         from_name: opt!(call!(ImportParser::<NewlinesAreNotSpaces>::dotted_name)) >> (
-          (leading_dots, from_name.unwrap_or(Vec::new()))
+          (leading_dots, from_name.map(into_vec).unwrap_or_default())
         )
       )
-    | call!(ImportParser::<NewlinesAreNotSpaces>::dotted_name) => { |n| (0, n) }
+    | call!(ImportParser::<NewlinesAreNotSpaces>::dotted_name) => { |n| (0, into_vec(n)) }
     ) >>
     spaces_nonl >>
     dbg_dmp!(keyword!("import")) >>
@@ -277,7 +278,7 @@ impl<ANS: AreNewlinesSpaces> ImportParser<ANS> {
 
     // dotted_as_name: dotted_name ['as' NAME]
     named!(dotted_as_name<StrSpan, (Vec<Name>, Option<Name>)>,
-      tuple!(call!(Self::dotted_name), opt!(do_parse!(
+      tuple!(map!(call!(Self::dotted_name), into_vec), opt!(do_parse!(
         spaces!() >>
         keyword!("as") >>
         spaces!() >>
@@ -301,8 +302,8 @@ impl<ANS: AreNewlinesSpaces> ImportParser<ANS> {
     );
 
     // dotted_name: NAME ('.' NAME)*
-    named!(pub dotted_name<StrSpan, Vec<Name>>,
-      separated_nonempty_list!(ws_nonl!(char!('.')), name)
+    named!(pub dotted_name<StrSpan, SmallList<Name>>,
+      separated_nonempty_small_list!(ws_nonl!(char!('.')), name)
     );
 } // end ImportParser
 
@@ -320,6 +321,7 @@ named_args!(pub block(indent: usize) <StrSpan, Vec<Statement>>,
           return_error!(
             ::nom::ErrorKind::Custom(PyParseError::ExpectedIndent.into()),
             do_parse!(
+              form_feeds >>
               count!(char!(' '), indent) >>
               new_spaces: many1!(char!(' ')) >> ({
                 indent + new_spaces.len()
@@ -676,6 +678,25 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_return() {
+        assert_parse_eq(
+            statement(make_strspan("return"), 0),
+            Ok((make_strspan(""), vec![Statement::Return(vec![])])),
+        );
+
+        assert_parse_eq(
+            statement(make_strspan("return foo, bar"), 0),
+            Ok((
+                make_strspan(""),
+                vec![Statement::Return(vec![
+                    Expression::Name("foo".to_string()),
+                    Expression::Name("bar".to_string()),
+                ])],
+            )),
+        );
+    }
+
     #[test]
     fn test_assert1() {
         assert_parse_eq(
@@ -964,7 +985,7 @@ mod tests {
                 make_strspan(""),
                 CompoundStatement::For {
                     async: false,
-                    item: vec![Expression::Name("foo".to_string())],
+                    item: small_list![Expression::Name("foo".to_string())],
                     iterator: vec![Expression::Name("bar".to_string())],
                     for_block: vec![Statement::Del(vec![Expression::Name("baz".to_string())])],
                     else_block: None,
@@ -981,7 +1002,7 @@ mod tests {
                 make_strspan(""),
                 CompoundStatement::For {
                     async: false,
-                    item: vec![Expression::Name("foo".to_string())],
+                    item: small_list![Expression::Name("foo".to_string())],
                     iterator: vec![Expression::Ternary(
                         Box::new(Expression::Name("bar".to_string())),
                         Box::new(Expression::Name("baz".to_string())),
@@ -1005,7 +1026,7 @@ mod tests {
                 make_strspan(""),
                 CompoundStatement::For {
                     async: false,
-                    item: vec![Expression::Name("foo".to_string())],
+                    item: small_list![Expression::Name("foo".to_string())],
                     iterator: vec![Expression::Name("bar".to_string())],
                     for_block: vec![Statement::Del(vec![Expression::Name("baz".to_string())])],
                     else_block: Some(vec![Statement::Del(vec![Expression::Name(
@@ -1050,8 +1071,8 @@ mod tests {
             Ok((
                 make_strspan(""),
                 Statement::Assignment(
-                    vec![Expression::Name("foo".to_string())],
-                    vec![vec![Expression::Name("bar".to_string())]],
+                    small_list![Expression::Name("foo".to_string())],
+                    vec![small_list![Expression::Name("bar".to_string())]],
                 ),
             )),
         );
@@ -1061,10 +1082,10 @@ mod tests {
             Ok((
                 make_strspan(""),
                 Statement::Assignment(
-                    vec![Expression::Name("foo".to_string())],
+                    small_list![Expression::Name("foo".to_string())],
                     vec![
-                        vec![Expression::Name("bar".to_string())],
-                        vec![Expression::Name("baz".to_string())],
+                        small_list![Expression::Name("bar".to_string())],
+                        small_list![Expression::Name("baz".to_string())],
                     ],
                 ),
             )),
@@ -1122,11 +1143,11 @@ mod tests {
             Ok((
                 make_strspan(""),
                 Statement::Assignment(
-                    vec![
+                    small_list![
                         Expression::Name("foo".to_string()),
                         Expression::Name("bar".to_string()),
                     ],
-                    vec![vec![
+                    vec![small_list![
                         Expression::Name("baz".to_string()),
                         Expression::Name("qux".to_string()),
                     ]],
@@ -1139,10 +1160,10 @@ mod tests {
             Ok((
                 make_strspan(""),
                 Statement::Assignment(
-                    vec![Expression::Name("foo".to_string())],
+                    small_list![Expression::Name("foo".to_string())],
                     vec![
-                        vec![Expression::Name("bar".to_string())],
-                        vec![Expression::Name("baz".to_string())],
+                        small_list![Expression::Name("bar".to_string())],
+                        small_list![Expression::Name("baz".to_string())],
                     ],
                 ),
             )),
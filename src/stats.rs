@@ -0,0 +1,198 @@
+//! Statistics about parsed code, to find what makes parsing slow and
+//! monitor the performance of the parser.
+
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+
+use ast::*;
+use errors::ParseError;
+use parse_module;
+use tokenizer::tokenize;
+use visitors::walk::{walk_expression, walk_statement, Visitor};
+
+/// Statistics returned by `parse_module_with_stats`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ParseStats {
+    /// Number of tokens of the code (see `tokenizer::tokenize`),
+    /// including comments and line breaks.
+    pub tokens: usize,
+    /// Number of statements and expressions of each kind, by name of
+    /// their variant (eg. `"Call"`, or `"Funcdef"` for compound
+    /// statements).
+    pub nodes: BTreeMap<&'static str, usize>,
+    /// Largest number of nested statements and expressions, eg. 3 for
+    /// `f(x)` (the expression statement, the call, and `f` or `x`).
+    pub max_depth: usize,
+    /// Time spent parsing, without computing the statistics.
+    pub duration: Duration,
+}
+
+impl ParseStats {
+    /// Computes the statistics of `code`, whose statements are `stmts`,
+    /// without the parsing duration.
+    pub fn new(code: &str, stmts: &[Statement]) -> ParseStats {
+        let mut counter = NodeCounter {
+            stats: ParseStats {
+                tokens: tokenize(code).count(),
+                ..ParseStats::default()
+            },
+            depth: 0,
+        };
+        for stmt in stmts {
+            counter.visit_statement(stmt);
+        }
+        counter.stats
+    }
+
+    /// Total number of statements and expressions.
+    pub fn node_count(&self) -> usize {
+        self.nodes.values().sum()
+    }
+}
+
+pub(crate) fn statement_kind(stmt: &Statement) -> &'static str {
+    match *stmt {
+        Statement::Pass => "Pass",
+        Statement::Del(_) => "Del",
+        Statement::Break => "Break",
+        Statement::Continue => "Continue",
+        Statement::Return(_) => "Return",
+        Statement::RaiseExcFrom(_, _) => "RaiseExcFrom",
+        Statement::RaiseExc(_) => "RaiseExc",
+        Statement::Raise => "Raise",
+        Statement::Global(_) => "Global",
+        Statement::Nonlocal(_) => "Nonlocal",
+        Statement::Assert(_, _) => "Assert",
+        Statement::Import(_) => "Import",
+        Statement::Expressions(_) => "Expressions",
+        Statement::Assignment(_, _) => "Assignment",
+        Statement::TypeAnnotation(_, _) => "TypeAnnotation",
+        Statement::TypedAssignment(_, _, _) => "TypedAssignment",
+        Statement::AugmentedAssignment(_, _, _) => "AugmentedAssignment",
+        Statement::Invalid(_) => "Invalid",
+        Statement::Extension(_, _) => "Extension",
+        Statement::Compound(ref stmt) => match **stmt {
+            CompoundStatement::If(_, _) => "If",
+            CompoundStatement::For { .. } => "For",
+            CompoundStatement::While(_, _, _) => "While",
+            CompoundStatement::With(_, _) => "With",
+            CompoundStatement::Funcdef(_) => "Funcdef",
+            CompoundStatement::Classdef(_) => "Classdef",
+            CompoundStatement::Try(_) => "Try",
+        },
+    }
+}
+
+pub(crate) fn expression_kind(expr: &Expression) -> &'static str {
+    match *expr {
+        Expression::Ellipsis => "Ellipsis",
+        Expression::None => "None",
+        Expression::True => "True",
+        Expression::False => "False",
+        Expression::Name(_) => "Name",
+        Expression::Int(_) => "Int",
+        Expression::ImaginaryInt(_) => "ImaginaryInt",
+        Expression::Float(_) => "Float",
+        Expression::ImaginaryFloat(_) => "ImaginaryFloat",
+        Expression::String(_) => "String",
+        Expression::Bytes(_) => "Bytes",
+        Expression::DictLiteral(_) => "DictLiteral",
+        Expression::SetLiteral(_) => "SetLiteral",
+        Expression::ListLiteral(_) => "ListLiteral",
+        Expression::TupleLiteral(_) => "TupleLiteral",
+        Expression::DictComp(_, _) => "DictComp",
+        Expression::SetComp(_, _) => "SetComp",
+        Expression::ListComp(_, _) => "ListComp",
+        Expression::Generator(_, _) => "Generator",
+        Expression::Await(_) => "Await",
+        Expression::Call(_, _) => "Call",
+        Expression::Subscript(_, _) => "Subscript",
+        Expression::Attribute(_, _) => "Attribute",
+        Expression::Uop(_, _) => "Uop",
+        Expression::Bop(_, _, _) => "Bop",
+        Expression::MultiBop(_, _) => "MultiBop",
+        Expression::Ternary(_, _, _) => "Ternary",
+        Expression::Yield(_) => "Yield",
+        Expression::YieldFrom(_) => "YieldFrom",
+        Expression::Star(_) => "Star",
+        Expression::Lambdef(_, _) => "Lambdef",
+        Expression::Named(_, _) => "Named",
+    }
+}
+
+struct NodeCounter {
+    stats: ParseStats,
+    depth: usize,
+}
+
+impl NodeCounter {
+    fn enter(&mut self, kind: &'static str) {
+        *self.stats.nodes.entry(kind).or_insert(0) += 1;
+        self.depth += 1;
+        if self.depth > self.stats.max_depth {
+            self.stats.max_depth = self.depth;
+        }
+    }
+}
+
+impl Visitor for NodeCounter {
+    fn visit_statement(&mut self, stmt: &Statement) {
+        self.enter(statement_kind(stmt));
+        walk_statement(self, stmt);
+        self.depth -= 1;
+    }
+
+    fn visit_expression(&mut self, expr: &Expression) {
+        self.enter(expression_kind(expr));
+        walk_expression(self, expr);
+        self.depth -= 1;
+    }
+}
+
+/// Parses a module like `parse_module`, and returns statistics about it.
+///
+/// ```
+/// use python_parser::stats::parse_module_with_stats;
+/// let (module, stats) = parse_module_with_stats("print(x)\n").unwrap();
+/// assert_eq!(stats.nodes["Call"], 1);
+/// assert_eq!(stats.nodes["Name"], 2);
+/// assert_eq!(stats.max_depth, 3);
+/// ```
+pub fn parse_module_with_stats(code: &str) -> Result<(Module, ParseStats), ParseError> {
+    let start = Instant::now();
+    let module = parse_module(code)?;
+    let duration = start.elapsed();
+    let stats = ParseStats {
+        duration,
+        ..ParseStats::new(code, &module.body)
+    };
+    Ok((module, stats))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stats() {
+        let code = "import os\n\ndef f(x):  # comment\n    return g(x) + 1\n";
+        let (_, stats) = parse_module_with_stats(code).unwrap();
+        let nodes: Vec<_> = stats.nodes.iter().map(|(&k, &v)| (k, v)).collect();
+        assert_eq!(
+            nodes,
+            [
+                ("Bop", 1),
+                ("Call", 1),
+                ("Funcdef", 1),
+                ("Import", 1),
+                ("Int", 1),
+                ("Name", 2),
+                ("Return", 1)
+            ]
+        );
+        assert_eq!(stats.node_count(), 8);
+        // def f(x): return (g(x) + 1)
+        assert_eq!(stats.max_depth, 5);
+        assert_eq!(stats.tokens, 23);
+    }
+}
@@ -0,0 +1,342 @@
+//! Parsing of modules one top-level statement at a time, so huge files
+//! do not have to be held in memory as a single AST.
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::io;
+use std::io::BufRead;
+
+use nom::types::CompleteStr;
+use nom_locate::LocatedSpan;
+
+use ast::*;
+use errors::ParseError;
+use helpers::*;
+use memo::new_input;
+use statements::statement;
+
+/// Skips blank lines and comments, and returns what follows them, or
+/// `None` if there is nothing else.
+pub(crate) fn skip_blank_lines(i: StrSpan) -> Result<Option<StrSpan>, ParseError> {
+    let mut i = i;
+    while let Ok((rest, ())) = newline(i) {
+        i = rest;
+    }
+    let (rest, ()) = spaces_nonl(i)?;
+    let rest = rest.fragment.0;
+    if rest.is_empty() || (rest.starts_with('#') && !rest.contains('\n')) {
+        Ok(None)
+    } else {
+        Ok(Some(i))
+    }
+}
+
+/// Parses the next top-level statement(s) of `i`, after skipping
+/// blank lines and comments.
+///
+/// Returns `Ok(None)` when there is nothing left to parse.
+fn next_statements(i: StrSpan) -> Result<Option<(StrSpan, Vec<Statement>)>, ParseError> {
+    match skip_blank_lines(i)? {
+        None => Ok(None),
+        Some(i) => {
+            let (rest, stmts) = statement(i, 0)?;
+            Ok(Some((rest, stmts)))
+        }
+    }
+}
+
+/// Iterator over the statements of a module, returned by
+/// `parse_statements`.
+pub struct Statements<'a> {
+    rest: StrSpan<'a>,
+    pending: VecDeque<Statement>,
+    done: bool,
+}
+
+impl<'a> Iterator for Statements<'a> {
+    type Item = Result<Statement, ParseError>;
+
+    fn next(&mut self) -> Option<Result<Statement, ParseError>> {
+        loop {
+            if let Some(stmt) = self.pending.pop_front() {
+                return Some(Ok(stmt));
+            }
+            if self.done {
+                return None;
+            }
+            match next_statements(self.rest) {
+                Ok(Some((rest, stmts))) => {
+                    self.rest = rest;
+                    self.pending.extend(stmts);
+                }
+                Ok(None) => self.done = true,
+                Err(e) => {
+                    self.done = true;
+                    return Some(Err(e));
+                }
+            }
+        }
+    }
+}
+
+/// Lazily parses a module: each top-level statement is only parsed
+/// when the iterator reaches it.
+///
+/// Unlike `file_input`, trailing code that cannot be parsed is reported
+/// as an error instead of being silently left over.
+pub fn parse_statements(code: &str) -> Statements<'_> {
+    new_input();
+    Statements {
+        rest: make_strspan(code),
+        pending: VecDeque::new(),
+        done: false,
+    }
+}
+
+/// Error returned by `StatementStream`.
+#[derive(Debug)]
+pub enum StreamError {
+    Io(io::Error),
+    Parse(ParseError),
+}
+
+impl fmt::Display for StreamError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            StreamError::Io(ref e) => write!(f, "could not read code: {}", e),
+            StreamError::Parse(ref e) => e.fmt(f),
+        }
+    }
+}
+
+impl ::std::error::Error for StreamError {}
+
+/// Tracks enough of the lexical state across lines to know whether a
+/// line starting at column 0 begins a new top-level statement.
+#[derive(Default)]
+pub(crate) struct LineScanner {
+    /// Number of open brackets.
+    depth: usize,
+    /// Quote character of the string we are in, and whether it is a
+    /// triple-quoted one.
+    string: Option<(char, bool)>,
+    /// Whether the previous line ended with a backslash.
+    continuation: bool,
+    /// Whether the last top-level line was a decorator.
+    decorator: bool,
+}
+
+impl LineScanner {
+    /// Returns whether the next line starts a new logical line.
+    pub(crate) fn at_line_start(&self) -> bool {
+        self.depth == 0 && self.string.is_none() && !self.continuation
+    }
+
+    /// Returns whether `line` starts a new top-level statement.
+    pub(crate) fn starts_statement(&self, line: &str) -> bool {
+        if !self.at_line_start() || self.decorator {
+            return false;
+        }
+        match line.chars().next() {
+            None | Some(' ') | Some('\t') | Some('\x0c') | Some('\r') | Some('\n') | Some('#') => {
+                false
+            }
+            Some(_) => {
+                let word = line
+                    .split(|c: char| !c.is_alphanumeric() && c != '_')
+                    .next()
+                    .unwrap_or("");
+                !["else", "elif", "except", "finally"].contains(&word)
+            }
+        }
+    }
+
+    pub(crate) fn scan_line(&mut self, line: &str) {
+        if self.at_line_start() {
+            match line.chars().next() {
+                Some('@') => self.decorator = true,
+                Some(c) if !c.is_whitespace() && c != '#' => self.decorator = false,
+                _ => (),
+            }
+        }
+        let mut chars = line.chars().peekable();
+        while let Some(c) = chars.next() {
+            if let Some((quote, triple)) = self.string {
+                match c {
+                    '\\' => {
+                        chars.next();
+                    }
+                    '\n' if !triple => self.string = None, // Unterminated string
+                    c if c == quote && !triple => self.string = None,
+                    c if c == quote && chars.peek() == Some(&quote) => {
+                        chars.next();
+                        if chars.peek() == Some(&quote) {
+                            chars.next();
+                            self.string = None;
+                        }
+                    }
+                    _ => (),
+                }
+                continue;
+            }
+            match c {
+                '#' => break,
+                '(' | '[' | '{' => self.depth += 1,
+                ')' | ']' | '}' => self.depth = self.depth.saturating_sub(1),
+                '\'' | '"' => {
+                    if chars.peek() == Some(&c) {
+                        chars.next();
+                        if chars.peek() == Some(&c) {
+                            chars.next();
+                            self.string = Some((c, true));
+                        }
+                        // else: empty string
+                    } else {
+                        self.string = Some((c, false));
+                    }
+                }
+                _ => (),
+            }
+        }
+        self.continuation =
+            self.string.is_none() && line.trim_end_matches(&['\r', '\n'][..]).ends_with('\\');
+    }
+}
+
+/// Parses a module read from `reader`, yielding its statements as soon
+/// as each top-level statement has been read.
+///
+/// Only the source code of the top-level statement being parsed is kept
+/// in memory.
+pub struct StatementStream<R> {
+    reader: R,
+    /// Code of the top-level statement being read.
+    buffer: String,
+    /// Offset and line of the start of `buffer` in the whole code.
+    buffer_offset: usize,
+    buffer_line: u32,
+    /// First line of the next top-level statement, read ahead of time.
+    next_line: String,
+    scanner: LineScanner,
+    pending: VecDeque<Statement>,
+    done: bool,
+}
+
+impl<R: BufRead> StatementStream<R> {
+    pub fn new(reader: R) -> StatementStream<R> {
+        StatementStream {
+            reader,
+            buffer: String::new(),
+            buffer_offset: 0,
+            buffer_line: 1,
+            next_line: String::new(),
+            scanner: LineScanner::default(),
+            pending: VecDeque::new(),
+            done: false,
+        }
+    }
+
+    /// Reads lines until the buffer contains a complete top-level
+    /// statement (or the end of the code).
+    ///
+    /// Returns `false` if there was nothing left to read.
+    fn fill_buffer(&mut self) -> io::Result<bool> {
+        let mut line = String::new();
+        self.buffer.push_str(&self.next_line);
+        self.next_line.clear();
+        loop {
+            line.clear();
+            if self.reader.read_line(&mut line)? == 0 {
+                return Ok(!self.buffer.is_empty());
+            }
+            if !self.buffer.is_empty() && self.scanner.starts_statement(&line) {
+                self.scanner.scan_line(&line);
+                self.next_line.push_str(&line);
+                return Ok(true);
+            }
+            self.scanner.scan_line(&line);
+            self.buffer.push_str(&line);
+        }
+    }
+
+    fn parse_buffer(&mut self) -> Result<(), ParseError> {
+        let mut i = LocatedSpan {
+            offset: self.buffer_offset,
+            line: self.buffer_line,
+            fragment: CompleteStr(&self.buffer[..]),
+        };
+        while let Some((rest, stmts)) = next_statements(i)? {
+            self.pending.extend(stmts);
+            i = rest;
+        }
+        self.buffer_offset += self.buffer.len();
+        self.buffer_line += self.buffer.matches('\n').count() as u32;
+        self.buffer.clear();
+        Ok(())
+    }
+}
+
+impl<R: BufRead> Iterator for StatementStream<R> {
+    type Item = Result<Statement, StreamError>;
+
+    fn next(&mut self) -> Option<Result<Statement, StreamError>> {
+        loop {
+            if let Some(stmt) = self.pending.pop_front() {
+                return Some(Ok(stmt));
+            }
+            if self.done {
+                return None;
+            }
+            match self.fill_buffer() {
+                Ok(true) => {
+                    if let Err(e) = self.parse_buffer() {
+                        self.done = true;
+                        return Some(Err(StreamError::Parse(e)));
+                    }
+                }
+                Ok(false) => self.done = true,
+                Err(e) => {
+                    self.done = true;
+                    return Some(Err(StreamError::Io(e)));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use file_input;
+
+    const CODE: &str = "import foo\n\n@bar\ndef baz(x):\n    '''doc\nstring'''\n    return (x,\n1)\n# comment\nif x:\n    pass\nelse:\n    del x; del y\n";
+
+    #[test]
+    fn test_parse_statements() {
+        let expected = file_input(make_strspan(CODE)).unwrap().1;
+        let stmts: Result<Vec<_>, _> = parse_statements(CODE).collect();
+        assert_eq!(stmts.unwrap(), expected);
+    }
+
+    #[test]
+    fn test_stream() {
+        let expected = file_input(make_strspan(CODE)).unwrap().1;
+        let stmts: Result<Vec<_>, _> = StatementStream::new(CODE.as_bytes()).collect();
+        assert_eq!(stmts.unwrap(), expected);
+    }
+
+    #[test]
+    fn test_stream_error() {
+        let code = "foo = 1\n\nbar = 2\nif bar:\nbaz\n";
+        let stmts: Vec<_> = StatementStream::new(code.as_bytes()).collect();
+        assert_eq!(stmts.len(), 3);
+        match stmts[2] {
+            Err(StreamError::Parse(ref e)) => assert_eq!(e.line, 5),
+            ref r => panic!("Unexpected result: {:?}", r),
+        }
+
+        let stmts: Vec<_> = parse_statements(code).collect();
+        assert_eq!(stmts.len(), 3);
+        assert_eq!(stmts[2].as_ref().unwrap_err().line, 5);
+    }
+}
@@ -1,16 +1,17 @@
 use nom::anychar;
+use nom::Slice;
 
 #[cfg(feature = "unicode-names")]
 use unicode_names2;
 
-#[cfg(not(feature = "unicode-names"))]
-use errors::PyParseError;
-
 #[cfg(feature = "wtf8")]
 use wtf8;
 
 use ast::*;
-use helpers::StrSpan;
+use budget::check_literal_length;
+use decoding::{string_decoding, StringDecoding};
+use errors::PyParseError;
+use helpers::{fail_if, line_break, position, StrSpan};
 
 #[cfg(feature = "wtf8")]
 fn cp_from_char(c: char) -> wtf8::CodePoint {
@@ -50,7 +51,7 @@ pub fn unicode_escaped_name(
 named!(escapedchar<StrSpan, Option<PyStringCodePoint>>,
   preceded!(char!('\\'),
     alt!(
-      char!('\n') => { |_| None }
+      line_break => { |_| None }
     | char!('\\') => { |_| Some(cp_from_char('\\')) }
     | char!('\'') => { |_| Some(cp_from_char('\'')) }
     | char!('"') => { |_| Some(cp_from_char('"')) }
@@ -104,6 +105,7 @@ named_args!(longstring(quote: char) <StrSpan, PyStringContent>,
   fold_many0!(
     alt!(
       call!(escapedchar)
+    | line_break => { |_| Some(cp_from_char('\n')) }
     | verify!(tuple!(peek!(take!(3)), anychar), |(s,_):(StrSpan,_)| { s.fragment.0.chars().collect::<Vec<char>>() != vec![quote,quote,quote] }) => { |(_,c)| Some(cp_from_char(c)) }
     ),
     PyStringContent::new(),
@@ -129,7 +131,9 @@ named_args!(shortrawstring(quote: char) <StrSpan, PyStringContent>,
 named_args!(longrawstring(quote: char) <StrSpan, PyStringContent>,
   fold_many0!(
     alt!(
-      tuple!(char!('\\'), anychar) => { |(c1,c2)| (cp_from_char(c1), Some(cp_from_char(c2))) }
+      preceded!(char!('\\'), line_break) => { |_| (cp_from_char('\\'), Some(cp_from_char('\n'))) }
+    | tuple!(char!('\\'), anychar) => { |(c1,c2)| (cp_from_char(c1), Some(cp_from_char(c2))) }
+    | line_break => { |_| (cp_from_char('\n'), None) }
     | verify!(tuple!(peek!(take!(3)), none_of!("\\")), |(s,_):(StrSpan,_)| { s.fragment.0.chars().collect::<Vec<char>>() != vec![quote,quote,quote] }) => { |(_,c)| (cp_from_char(c), None) }
     ),
     PyStringContent::new(),
@@ -143,8 +147,10 @@ named_args!(longrawstring(quote: char) <StrSpan, PyStringContent>,
 
 named!(pub string<StrSpan, PyString>,
   do_parse!(
+    literal_start: position >>
     prefix: alt!(tag!("fr")|tag!("Fr")|tag!("fR")|tag!("FR")|tag!("rf")|tag!("rF")|tag!("Rf")|tag!("RF")|tag!("r")|tag!("u")|tag!("R")|tag!("U")|tag!("f")|tag!("F")|tag!("")) >>
     is_raw: call!(|i, s:StrSpan| Ok((i, s.fragment.0.contains('r') || s.fragment.0.contains('R'))), prefix) >>
+    start: position >>
     content: switch!(call!(|i| Ok((i, is_raw))),
       false => alt!(
         delimited!(tag!("'''"), return_error!(call!(longstring, '\'')), tag!("'''"))
@@ -158,6 +164,179 @@ named!(pub string<StrSpan, PyString>,
       | delimited!(char!('\''), return_error!(call!(shortrawstring, '\'')), char!('\''))
       | delimited!(char!('"'), return_error!(call!(shortrawstring, '"')), char!('"'))
       )
-    ) >> (PyString { prefix: prefix.to_string(), content: content })
+    ) >>
+    end: position >>
+    call!(check_literal_length, literal_start, end) >>
+    call!(check_fstring, prefix, start, end) >>
+    (apply_decoding(prefix, content, literal_start, end))
   )
 );
+
+/// Builds the string literal from `start` to `end` following the
+/// decoding policy.
+fn apply_decoding(
+    prefix: StrSpan,
+    content: PyStringContent,
+    start: StrSpan,
+    end: StrSpan,
+) -> PyString {
+    let raw = || start.fragment.0[..end.offset - start.offset].to_string();
+    let (content, raw) = match string_decoding() {
+        StringDecoding::Decoded => (content, None),
+        StringDecoding::Raw => (PyStringContent::new(), Some(raw())),
+        StringDecoding::Both => (content, Some(raw())),
+    };
+    PyString {
+        prefix: prefix.to_string(),
+        content,
+        raw,
+    }
+}
+
+/// Fails if the string literal from `start` to `end` is an f-string
+/// whose replacement fields are invalid.
+fn check_fstring<'a>(
+    i: StrSpan<'a>,
+    prefix: StrSpan,
+    start: StrSpan<'a>,
+    end: StrSpan,
+) -> Result<(StrSpan<'a>, ()), ::nom::Err<StrSpan<'a>>> {
+    if !prefix.fragment.0.contains(&['f', 'F'][..]) {
+        return Ok((i, ()));
+    }
+    let literal = &start.fragment.0[..end.offset - start.offset];
+    let quotes = if literal.starts_with("'''") || literal.starts_with("\"\"\"") {
+        3
+    } else {
+        1
+    };
+    let mut checker = FstringChecker {
+        body: &literal.as_bytes()[quotes..literal.len() - quotes],
+        pos: 0,
+        raw: prefix.fragment.0.contains(&['r', 'R'][..]),
+    };
+    match checker.literal(0) {
+        Ok(()) => Ok((i, ())),
+        Err((pos, code)) => fail_if(i, true, start.slice(quotes + pos..), code),
+    }
+}
+
+/// Checks the replacement fields of the body of an f-string (the code
+/// between its quotes) like CPython does before it parses their
+/// expressions (which this crate does not do yet), to report the same
+/// errors, at the position of the problem in the body.
+struct FstringChecker<'a> {
+    body: &'a [u8],
+    pos: usize,
+    raw: bool,
+}
+
+type FstringResult = Result<(), (usize, PyParseError)>;
+
+impl<'a> FstringChecker<'a> {
+    fn peek(&self, n: usize) -> Option<u8> {
+        self.body.get(self.pos + n).cloned()
+    }
+
+    fn error(&self, code: PyParseError) -> FstringResult {
+        Err((self.pos, code))
+    }
+
+    /// Checks literal text, up to the end of the body or of the format
+    /// specification of a replacement field (nested in `depth` fields).
+    fn literal(&mut self, depth: usize) -> FstringResult {
+        let in_format_spec = depth > 0;
+        while let Some(c) = self.peek(0) {
+            match c {
+                b'\\' if !self.raw && self.peek(1) == Some(b'\\') => self.pos += 2,
+                b'\\' if !self.raw && self.peek(1) == Some(b'N') && self.peek(2) == Some(b'{') => {
+                    // `\N{name}`, whose braces are not a replacement field
+                    while self.peek(0).is_some_and(|c| c != b'}') {
+                        self.pos += 1
+                    }
+                    self.pos += 1
+                }
+                b'{' if !in_format_spec && self.peek(1) == Some(b'{') => self.pos += 2,
+                b'{' => self.replacement_field(depth + 1)?,
+                b'}' if in_format_spec => return Ok(()),
+                b'}' if self.peek(1) == Some(b'}') => self.pos += 2,
+                b'}' => return self.error(PyParseError::FstringSingleBrace),
+                _ => self.pos += 1,
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks a replacement field, starting at its `{`, and nested in
+    /// `depth - 1` other fields.
+    fn replacement_field(&mut self, depth: usize) -> FstringResult {
+        if depth > 2 {
+            return self.error(PyParseError::FstringNestedTooDeeply);
+        }
+        self.pos += 1;
+        let start = self.pos;
+        self.expression()?;
+        if self.body[start..self.pos]
+            .iter()
+            .all(u8::is_ascii_whitespace)
+        {
+            return self.error(PyParseError::FstringEmptyExpression);
+        }
+        if self.peek(0) == Some(b'=') {
+            self.pos += 1;
+            while self.peek(0).is_some_and(|c| c.is_ascii_whitespace()) {
+                self.pos += 1
+            }
+        }
+        if self.peek(0) == Some(b'!') {
+            self.pos += 1;
+            match self.peek(0) {
+                Some(b's') | Some(b'r') | Some(b'a') => self.pos += 1,
+                Some(_) => return self.error(PyParseError::FstringInvalidConversion),
+                None => return self.error(PyParseError::FstringExpectingBrace),
+            }
+        }
+        if self.peek(0) == Some(b':') {
+            self.pos += 1;
+            self.literal(depth)?
+        }
+        if self.peek(0) == Some(b'}') {
+            self.pos += 1;
+            Ok(())
+        } else {
+            self.error(PyParseError::FstringExpectingBrace)
+        }
+    }
+
+    /// Skips the expression of a replacement field, up to the `=`, `!`,
+    /// `:` or `}` that ends it.
+    fn expression(&mut self) -> FstringResult {
+        let mut brackets = 0;
+        while let Some(c) = self.peek(0) {
+            match c {
+                b'\\' => return self.error(PyParseError::FstringBackslash),
+                b'#' => return self.error(PyParseError::FstringComment),
+                b'\'' | b'"' => {
+                    self.pos += 1;
+                    while let Some(c2) = self.peek(0) {
+                        match c2 {
+                            b'\\' => return self.error(PyParseError::FstringBackslash),
+                            _ if c2 == c => break,
+                            _ => self.pos += 1,
+                        }
+                    }
+                }
+                b'(' | b'[' | b'{' => brackets += 1,
+                b')' | b']' if brackets > 0 => brackets -= 1,
+                b'}' if brackets > 0 => brackets -= 1,
+                b'}' => return Ok(()),
+                // Comparison operators
+                b'!' | b'=' | b'<' | b'>' if self.peek(1) == Some(b'=') => self.pos += 1,
+                b'!' | b'=' | b':' if brackets == 0 => return Ok(()),
+                _ => (),
+            }
+            self.pos += 1
+        }
+        self.error(PyParseError::FstringExpectingBrace)
+    }
+}
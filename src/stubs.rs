@@ -0,0 +1,505 @@
+//! Helpers for stubs (`.pyi` files) and the annotations of modules: a
+//! view of the definitions of a module as type checkers see them, with
+//! overloads grouped together and only the branches of `if`s that apply
+//! to a version of Python.
+
+use std::cmp::Ordering;
+
+use ast::*;
+use keywords::PARSED_VERSION;
+
+/// What `definitions` assumes about the environment of the code.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct StubOptions {
+    /// Version `sys.version_info` is compared to, in conditions.
+    pub python_version: (u8, u8),
+    /// Value of `typing.TYPE_CHECKING`: `true` for type checkers.
+    pub type_checking: bool,
+}
+
+impl Default for StubOptions {
+    fn default() -> StubOptions {
+        StubOptions {
+            python_version: PARSED_VERSION,
+            type_checking: true,
+        }
+    }
+}
+
+/// The signature of a function, without its body.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Signature<'a> {
+    pub async: bool,
+    /// Whether the function is decorated with `@overload`.
+    pub overload: bool,
+    /// Dotted names of the decorators, except `overload`.
+    pub decorators: Vec<String>,
+    pub parameters: &'a TypedArgsList,
+    pub return_type: Option<&'a Expression>,
+    /// Whether the function has a body, instead of `...` (or `pass`)
+    /// and an optional docstring.
+    pub has_body: bool,
+}
+
+/// A definition seen by type checkers.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Definition<'a> {
+    /// A function, with a signature for each of its overloads (the
+    /// implementation of overloaded functions is not included), or the
+    /// signature of the function if it is not overloaded.
+    Function {
+        name: &'a str,
+        signatures: Vec<Signature<'a>>,
+    },
+    Class {
+        name: &'a str,
+        /// Dotted names of the decorators.
+        decorators: Vec<String>,
+        /// Base classes and keywords (eg. `metaclass=...`).
+        arguments: &'a [Argument],
+        body: Vec<Definition<'a>>,
+    },
+    /// A variable, with its annotation if it is annotated.
+    Variable {
+        name: &'a str,
+        annotation: Option<&'a Expression>,
+    },
+}
+
+impl<'a> Definition<'a> {
+    pub fn name(&self) -> &'a str {
+        match *self {
+            Definition::Function { name, .. }
+            | Definition::Class { name, .. }
+            | Definition::Variable { name, .. } => name,
+        }
+    }
+}
+
+/// Returns the dotted name of a decorator, eg. `"typing.overload"`.
+fn decorator_name(decorator: &Decorator) -> String {
+    decorator.name.join(".")
+}
+
+/// Whether `block` is the body of a function of a stub: `...` or `pass`,
+/// possibly after a docstring.
+pub fn is_ellipsis_body(block: &[Statement]) -> bool {
+    let block = match block {
+        [Statement::Assignment(exprs, rhs), _] if rhs.is_empty() => match exprs[..] {
+            [Expression::String(_)] => &block[1..],
+            _ => block,
+        },
+        _ => block,
+    };
+    match block {
+        [Statement::Pass] => true,
+        [Statement::Assignment(exprs, rhs)] => {
+            rhs.is_empty() && exprs[..] == [Expression::Ellipsis]
+        }
+        _ => false,
+    }
+}
+
+/// Whether `funcdef` is decorated with `@overload` or `@typing.overload`.
+pub fn is_overload(funcdef: &Funcdef) -> bool {
+    funcdef.decorators.iter().any(is_overload_decorator)
+}
+
+fn is_overload_decorator(decorator: &Decorator) -> bool {
+    decorator.args.is_none() && is_typing_name(&decorator.name, "overload")
+}
+
+/// Whether `path` is `name`, or `name` in the `typing` module.
+fn is_typing_name(path: &[Name], name: &str) -> bool {
+    match path {
+        [last] => last == name,
+        [module, last] => (module == "typing" || module == "typing_extensions") && last == name,
+        _ => false,
+    }
+}
+
+/// Whether `expr` is `TYPE_CHECKING` or `typing.TYPE_CHECKING`.
+pub fn is_type_checking(expr: &Expression) -> bool {
+    match *expr {
+        Expression::Name(ref name) => name == "TYPE_CHECKING",
+        Expression::Attribute(ref module, ref name) => {
+            name == "TYPE_CHECKING" && **module == Expression::Name("typing".to_string())
+        }
+        _ => false,
+    }
+}
+
+/// Whether `expr` is `sys.version_info`.
+fn is_version_info(expr: &Expression) -> bool {
+    match *expr {
+        Expression::Attribute(ref module, ref name) => {
+            name == "version_info" && **module == Expression::Name("sys".to_string())
+        }
+        _ => false,
+    }
+}
+
+/// Returns the value of a small integer literal.
+fn small_int(expr: &Expression) -> Option<u32> {
+    match *expr {
+        Expression::Int(ref n) => n.to_string().parse().ok(),
+        _ => None,
+    }
+}
+
+/// Returns the integers of a tuple of integer literals.
+fn int_tuple(expr: &Expression) -> Option<Vec<u32>> {
+    match *expr {
+        Expression::TupleLiteral(ref items) => items
+            .iter()
+            .map(|item| match *item {
+                SetItem::Unique(ref e) => small_int(e),
+                SetItem::Star(_) => None,
+            })
+            .collect(),
+        _ => None,
+    }
+}
+
+/// Compares `sys.version_info` (or a subscript of it) to a literal, if
+/// `expr` is one of them and `other` is a literal it can be compared to.
+fn compare_version(expr: &Expression, other: &Expression, version: (u8, u8)) -> Option<Ordering> {
+    // `sys.version_info` also has a micro version, a release level and
+    // a serial, so it is greater than the tuples it starts with.
+    let version_info = [u32::from(version.0), u32::from(version.1), 0];
+    if is_version_info(expr) {
+        let tuple = int_tuple(other)?;
+        let len = tuple.len().min(version_info.len());
+        return Some(
+            version_info[..len]
+                .cmp(&tuple[..len])
+                .then(Ordering::Greater),
+        );
+    }
+    let (value, subscript) = match *expr {
+        Expression::Subscript(ref value, ref subscript) => (value, subscript),
+        _ => return None,
+    };
+    if !is_version_info(value) {
+        return None;
+    }
+    match subscript[..] {
+        [Subscript::Simple(ref index)] => {
+            let index = small_int(index)? as usize;
+            Some(version_info.get(index)?.cmp(&small_int(other)?))
+        }
+        [Subscript::Double(None, Some(ref end))] => {
+            let end = (small_int(end)? as usize).min(version_info.len());
+            Some(version_info[..end].cmp(&int_tuple(other)?[..]))
+        }
+        _ => None,
+    }
+}
+
+/// Evaluates a condition on `sys.version_info` or `TYPE_CHECKING` (or
+/// a combination of them with `and`, `or` and `not`), like type
+/// checkers do to pick the branches of `if` statements that apply.
+/// Returns `None` for other conditions.
+pub fn evaluate_condition(cond: &Expression, options: &StubOptions) -> Option<bool> {
+    if is_type_checking(cond) {
+        return Some(options.type_checking);
+    }
+    match *cond {
+        Expression::Uop(Uop::Not, ref e) => evaluate_condition(e, options).map(|b| !b),
+        Expression::Bop(Bop::And, ref left, ref right) => {
+            match (
+                evaluate_condition(left, options),
+                evaluate_condition(right, options),
+            ) {
+                (Some(false), _) | (_, Some(false)) => Some(false),
+                (Some(true), Some(true)) => Some(true),
+                _ => None,
+            }
+        }
+        Expression::Bop(Bop::Or, ref left, ref right) => {
+            match (
+                evaluate_condition(left, options),
+                evaluate_condition(right, options),
+            ) {
+                (Some(true), _) | (_, Some(true)) => Some(true),
+                (Some(false), Some(false)) => Some(false),
+                _ => None,
+            }
+        }
+        Expression::Bop(op, ref left, ref right) => {
+            let version = options.python_version;
+            let (ordering, op) = match compare_version(left, right, version) {
+                Some(ordering) => (ordering, op),
+                // `(3, 8) <= sys.version_info`
+                None => (compare_version(right, left, version)?.reverse(), op),
+            };
+            match op {
+                Bop::Lt => Some(ordering == Ordering::Less),
+                Bop::Leq => Some(ordering != Ordering::Greater),
+                Bop::Gt => Some(ordering == Ordering::Greater),
+                Bop::Geq => Some(ordering != Ordering::Less),
+                Bop::Eq => Some(ordering == Ordering::Equal),
+                Bop::Neq => Some(ordering != Ordering::Equal),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+/// Returns the blocks of an `if` statement that may run: the first one
+/// whose condition is true, or all of those whose condition cannot be
+/// evaluated up to it (see `evaluate_condition`).
+pub fn active_blocks<'a>(
+    cond_blocks: &'a [(Expression, Vec<Statement>)],
+    else_block: &'a Option<Vec<Statement>>,
+    options: &StubOptions,
+) -> Vec<&'a [Statement]> {
+    let mut blocks = Vec::new();
+    for (cond, block) in cond_blocks {
+        match evaluate_condition(cond, options) {
+            Some(true) => {
+                blocks.push(&block[..]);
+                return blocks;
+            }
+            Some(false) => (),
+            None => blocks.push(block),
+        }
+    }
+    if let Some(ref block) = *else_block {
+        blocks.push(block);
+    }
+    blocks
+}
+
+/// Adds the definitions of `stmts` to `defs`.
+fn add_definitions<'a>(
+    stmts: &'a [Statement],
+    options: &StubOptions,
+    defs: &mut Vec<Definition<'a>>,
+) {
+    let mut overloading = false;
+    for stmt in stmts {
+        match *stmt {
+            Statement::Assignment(ref lhs, ref rhs) => {
+                // All the expressions but the last are targets
+                let targets = Some(lhs).into_iter().chain(rhs).take(rhs.len());
+                for e in targets.flatten() {
+                    if let Expression::Name(ref name) = *e {
+                        defs.push(Definition::Variable {
+                            name,
+                            annotation: None,
+                        });
+                    }
+                }
+            }
+            Statement::TypeAnnotation(ref lhs, ref annotation)
+            | Statement::TypedAssignment(ref lhs, ref annotation, _) => {
+                if let [Expression::Name(ref name)] = lhs[..] {
+                    defs.push(Definition::Variable {
+                        name,
+                        annotation: Some(annotation),
+                    });
+                }
+            }
+            Statement::Compound(ref compound) => match **compound {
+                CompoundStatement::Funcdef(ref funcdef) => {
+                    add_function(funcdef, defs, &mut overloading)
+                }
+                CompoundStatement::Classdef(ref classdef) => defs.push(Definition::Class {
+                    name: &classdef.name,
+                    decorators: classdef.decorators.iter().map(decorator_name).collect(),
+                    arguments: &classdef.arguments,
+                    body: definitions(&classdef.code, options),
+                }),
+                CompoundStatement::If(ref cond_blocks, ref else_block) => {
+                    for block in active_blocks(cond_blocks, else_block, options) {
+                        add_definitions(block, options, defs);
+                    }
+                }
+                CompoundStatement::Try(ref try_) => {
+                    add_definitions(&try_.try_block, options, defs);
+                    add_definitions(&try_.else_block, options, defs);
+                }
+                _ => (),
+            },
+            _ => (),
+        }
+    }
+}
+
+/// Adds a function to `defs`, or to the overloads of the function of
+/// the same name before it if `overloading` (which tells whether the
+/// last definition is a group of overloads still waiting for more of
+/// them or for its implementation).
+fn add_function<'a>(funcdef: &'a Funcdef, defs: &mut Vec<Definition<'a>>, overloading: &mut bool) {
+    let signature = Signature {
+        async: funcdef.async,
+        overload: is_overload(funcdef),
+        decorators: funcdef
+            .decorators
+            .iter()
+            .filter(|decorator| !is_overload_decorator(decorator))
+            .map(decorator_name)
+            .collect(),
+        parameters: &funcdef.parameters,
+        return_type: funcdef.return_type.as_ref(),
+        has_body: !is_ellipsis_body(&funcdef.code),
+    };
+    if let Some(&mut Definition::Function {
+        name,
+        ref mut signatures,
+    }) = defs.last_mut()
+    {
+        // Overloads are followed by more overloads, then the
+        // implementation
+        if *overloading && name == funcdef.name {
+            *overloading = signature.overload;
+            if signature.overload {
+                signatures.push(signature);
+            }
+            return;
+        }
+    }
+    *overloading = signature.overload;
+    defs.push(Definition::Function {
+        name: &funcdef.name,
+        signatures: vec![signature],
+    });
+}
+
+/// Returns the definitions of `stmts` (the body of a module or class)
+/// seen by type checkers: its functions, classes and variables,
+/// including those in the blocks of `if` statements that apply (see
+/// `active_blocks`) and of `try` blocks.
+///
+/// ```
+/// use python_parser::parse_module;
+/// use python_parser::stubs::{definitions, Definition, StubOptions};
+///
+/// let code = "import sys
+/// from typing import overload
+/// @overload
+/// def f(x: int) -> int: ...
+/// @overload
+/// def f(x: str) -> str: ...
+/// if sys.version_info >= (3, 8):
+///     def g(): ...
+/// else:
+///     x: int
+/// ";
+/// let module = parse_module(code).unwrap();
+/// let options = StubOptions { python_version: (3, 7), ..StubOptions::default() };
+/// let defs = definitions(&module.body, &options);
+/// let names: Vec<_> = defs.iter().map(|d| d.name()).collect();
+/// assert_eq!(names, ["f", "x"]);
+/// match defs[0] {
+///     Definition::Function { ref signatures, .. } => assert_eq!(signatures.len(), 2),
+///     _ => unreachable!(),
+/// }
+/// ```
+pub fn definitions<'a>(stmts: &'a [Statement], options: &StubOptions) -> Vec<Definition<'a>> {
+    let mut defs = Vec::new();
+    add_definitions(stmts, options, &mut defs);
+    defs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parse_expression;
+    use parse_module;
+
+    #[test]
+    fn test_conditions() {
+        let options = StubOptions {
+            python_version: (3, 7),
+            type_checking: true,
+        };
+        let evaluate = |code| evaluate_condition(&parse_expression(code).unwrap(), &options);
+        assert_eq!(evaluate("sys.version_info >= (3, 7)"), Some(true));
+        assert_eq!(evaluate("sys.version_info >= (3, 8)"), Some(false));
+        assert_eq!(evaluate("sys.version_info < (3,)"), Some(false));
+        assert_eq!(evaluate("sys.version_info > (3, 7)"), Some(true));
+        assert_eq!(evaluate("sys.version_info[0] == 3"), Some(true));
+        assert_eq!(evaluate("sys.version_info[:2] != (3, 7)"), Some(false));
+        assert_eq!(evaluate("(3, 8) <= sys.version_info"), Some(false));
+        assert_eq!(evaluate("not typing.TYPE_CHECKING"), Some(false));
+        assert_eq!(evaluate("TYPE_CHECKING or x"), Some(true));
+        assert_eq!(evaluate("TYPE_CHECKING and x"), None);
+        assert_eq!(evaluate("sys.version_info >= x"), None);
+        assert_eq!(evaluate("sys.platform == 'linux'"), None);
+    }
+
+    #[test]
+    fn test_ellipsis_body() {
+        let body = |code| match parse_module(code).unwrap().body[0] {
+            Statement::Compound(ref compound) => match **compound {
+                CompoundStatement::Funcdef(ref funcdef) => is_ellipsis_body(&funcdef.code),
+                _ => unreachable!(),
+            },
+            _ => unreachable!(),
+        };
+        assert!(body("def f(): ...\n"));
+        assert!(body("def f():\n    '''Doc.'''\n    pass\n"));
+        assert!(!body("def f():\n    '''Doc.'''\n"));
+        assert!(!body("def f(): return ...\n"));
+    }
+
+    #[test]
+    fn test_definitions() {
+        let code = "from typing import TYPE_CHECKING, overload
+if TYPE_CHECKING:
+    from os import PathLike
+    T = int
+else:
+    U = int
+class C(Base, metaclass=M):
+    x: int
+    @typing.overload
+    def f(self) -> None: ...
+    @overload
+    @staticmethod
+    def f(x: int) -> int: ...
+    def f(*args):
+        return 1
+    def f(): ...
+try:
+    y = z = 1
+except ImportError:
+    w = 2
+";
+        let module = parse_module(code).unwrap();
+        let defs = definitions(&module.body, &StubOptions::default());
+        let names: Vec<_> = defs.iter().map(|d| d.name()).collect();
+        assert_eq!(names, ["T", "C", "y", "z"]);
+        let body = match defs[1] {
+            Definition::Class {
+                ref body,
+                arguments,
+                ..
+            } => {
+                assert_eq!(arguments.len(), 2);
+                body
+            }
+            _ => unreachable!(),
+        };
+        let names: Vec<_> = body.iter().map(|d| d.name()).collect();
+        assert_eq!(names, ["x", "f", "f"]);
+        match body[1] {
+            Definition::Function { ref signatures, .. } => {
+                let decorators: Vec<_> = signatures.iter().map(|s| s.decorators.clone()).collect();
+                assert_eq!(decorators, [vec![], vec!["staticmethod".to_string()]]);
+                assert!(signatures.iter().all(|s| s.overload && !s.has_body));
+            }
+            _ => unreachable!(),
+        }
+        match body[2] {
+            Definition::Function { ref signatures, .. } => {
+                assert_eq!(signatures.len(), 1);
+                assert!(!signatures[0].overload);
+            }
+            _ => unreachable!(),
+        }
+    }
+}
@@ -0,0 +1,295 @@
+//! Collection of the comments suppressing the errors of linters and
+//! type checkers: `# noqa` (flake8 and compatible linters) and
+//! `# type: ignore` (PEP 484 type checkers).
+
+use tokenizer::{tokenize, TokenKind};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum SuppressionKind {
+    /// `# noqa` or `# noqa: E501,W291`, or `# flake8: noqa` for the
+    /// whole file.
+    Noqa,
+    /// `# type: ignore` or `# type: ignore[arg-type]`.
+    TypeIgnore,
+}
+
+/// A suppression comment, found by `suppressions`.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Suppression {
+    pub kind: SuppressionKind,
+    /// Codes of the suppressed errors, or none if all the errors are
+    /// suppressed.
+    pub codes: Vec<String>,
+    /// Offset (in bytes) of the comment in the code.
+    pub offset: usize,
+    /// Line of the comment, starting from 1.
+    pub line: u32,
+    /// First and last lines of the statement (the logical line) of the
+    /// comment, which it applies to. A comment on its own line only
+    /// applies to this line.
+    pub first_line: u32,
+    pub last_line: u32,
+    /// Whether the comment applies to the whole file: `# flake8: noqa`,
+    /// or `# type: ignore` before the first statement.
+    pub file_level: bool,
+}
+
+impl Suppression {
+    /// Whether the comment suppresses the error `code` (or an error
+    /// without code, if `None`) at `line`.
+    pub fn suppresses(&self, line: u32, code: Option<&str>) -> bool {
+        let in_lines = self.file_level || (self.first_line <= line && line <= self.last_line);
+        in_lines
+            && (self.codes.is_empty() || code.is_some_and(|c| self.codes.iter().any(|s| s == c)))
+    }
+}
+
+/// Whether one of `suppressions` of the given kind suppresses the error
+/// `code` at `line`.
+pub fn is_suppressed(
+    suppressions: &[Suppression],
+    kind: SuppressionKind,
+    line: u32,
+    code: Option<&str>,
+) -> bool {
+    suppressions
+        .iter()
+        .any(|s| s.kind == kind && s.suppresses(line, code))
+}
+
+/// Returns the codes of `# noqa: E501, W291`, given the text after
+/// `noqa`, or `None` if it is not a suppression (`# noqa-ish`).
+fn noqa_codes(rest: &str) -> Option<Vec<String>> {
+    let rest = match rest.strip_prefix(':') {
+        Some(rest) => rest,
+        None if rest.starts_with(|c: char| c.is_alphanumeric() || c == '-' || c == '_') => {
+            return None
+        }
+        None => return Some(Vec::new()),
+    };
+    // Like flake8, codes are letters followed by digits, separated by
+    // commas or spaces, and the rest is ignored.
+    let mut codes = Vec::new();
+    for word in rest.split(|c: char| c == ',' || c.is_whitespace()) {
+        if word.is_empty() {
+            continue;
+        }
+        let letters = word.trim_start_matches(|c: char| c.is_ascii_uppercase());
+        let is_code = letters.len() < word.len()
+            && !letters.is_empty()
+            && letters.chars().all(|c| c.is_ascii_digit());
+        if !is_code {
+            break;
+        }
+        codes.push(word.to_string());
+    }
+    Some(codes)
+}
+
+/// Returns the kind and codes of the suppression of a comment, and
+/// whether it is `# flake8: noqa`.
+fn parse_comment(comment: &str) -> Option<(SuppressionKind, Vec<String>, bool)> {
+    let text = comment[1..].trim_start();
+    if let Some(rest) = text.strip_prefix("type:") {
+        // Like CPython's tokenizer, `ignore` must not be followed by
+        // an identifier
+        let rest = rest.trim_start().strip_prefix("ignore")?;
+        if rest.starts_with(|c: char| c.is_alphanumeric() || c == '_') {
+            return None;
+        }
+        let codes = match rest.strip_prefix('[') {
+            Some(rest) => rest[..rest.find(']')?]
+                .split(',')
+                .map(str::trim)
+                .filter(|code| !code.is_empty())
+                .map(str::to_string)
+                .collect(),
+            None => Vec::new(),
+        };
+        return Some((SuppressionKind::TypeIgnore, codes, false));
+    }
+    if let Some(rest) = text.strip_prefix("flake8") {
+        let rest = rest
+            .trim_start()
+            .strip_prefix(&[':', '='][..])?
+            .trim_start();
+        if rest.get(..4)?.eq_ignore_ascii_case("noqa") {
+            // flake8 ignores `# flake8: noqa: E501`, which would be a
+            // mistake for `# noqa: E501`
+            let codes = noqa_codes(&rest[4..])?;
+            return if codes.is_empty() {
+                Some((SuppressionKind::Noqa, codes, true))
+            } else {
+                None
+            };
+        }
+    }
+    // `# noqa` may follow another comment, like in `# foo # noqa`
+    comment.match_indices('#').find_map(|(i, _)| {
+        let text = comment[i + 1..].trim_start();
+        if text.get(..4)?.eq_ignore_ascii_case("noqa") {
+            noqa_codes(&text[4..]).map(|codes| (SuppressionKind::Noqa, codes, false))
+        } else {
+            None
+        }
+    })
+}
+
+/// Returns the suppression comments of some code, in the order of the
+/// code. Like flake8, a `# noqa` comment applies to all the lines of its
+/// statement (for statements spanning several lines).
+///
+/// ```
+/// use python_parser::suppressions::{is_suppressed, suppressions, SuppressionKind};
+///
+/// let code = "import os  # noqa: F401\nx = f(  # type: ignore[call-arg]\n    1)\n";
+/// let suppressions = suppressions(code);
+/// assert_eq!(suppressions[0].codes, ["F401"]);
+/// assert!(is_suppressed(&suppressions, SuppressionKind::Noqa, 1, Some("F401")));
+/// assert!(!is_suppressed(&suppressions, SuppressionKind::Noqa, 1, Some("E501")));
+/// assert!(is_suppressed(&suppressions, SuppressionKind::TypeIgnore, 3, Some("call-arg")));
+/// ```
+pub fn suppressions(code: &str) -> Vec<Suppression> {
+    let mut suppressions = Vec::new();
+    // Index in `suppressions` of the first comment of the current
+    // statement, and the first line of the statement
+    let mut pending = 0;
+    let mut statement_line = None;
+    let mut seen_code = false;
+    for token in tokenize(code) {
+        match token.kind {
+            TokenKind::Comment => {
+                if let Some((kind, codes, flake8)) = parse_comment(token.text) {
+                    let first_line = statement_line.unwrap_or(token.line);
+                    suppressions.push(Suppression {
+                        kind,
+                        codes,
+                        offset: token.offset,
+                        line: token.line,
+                        first_line,
+                        last_line: token.line,
+                        file_level: flake8 || (kind == SuppressionKind::TypeIgnore && !seen_code),
+                    });
+                }
+                if statement_line.is_none() {
+                    pending = suppressions.len();
+                }
+            }
+            TokenKind::Newline | TokenKind::EndMarker => {
+                for suppression in &mut suppressions[pending..] {
+                    suppression.last_line = token.line;
+                }
+                pending = suppressions.len();
+                statement_line = None;
+            }
+            TokenKind::Nl | TokenKind::Indent | TokenKind::Dedent => (),
+            _ => {
+                seen_code = true;
+                if statement_line.is_none() {
+                    statement_line = Some(token.line);
+                    pending = suppressions.len();
+                }
+            }
+        }
+    }
+    suppressions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns the fields of the suppressions, with their codes joined.
+    fn summary(code: &str) -> Vec<(SuppressionKind, String, u32, u32, u32, bool)> {
+        suppressions(code)
+            .into_iter()
+            .map(|s| {
+                let codes = s.codes.join(",");
+                (
+                    s.kind,
+                    codes,
+                    s.line,
+                    s.first_line,
+                    s.last_line,
+                    s.file_level,
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_noqa() {
+        use self::SuppressionKind::Noqa;
+        let code = "import os, sys  # noqa:F401,E401 unused
+x = 1  # NOQA
+y = 2  # something # noqa : E501
+z = 3  # noqa-ish
+'''
+# noqa
+'''
+";
+        assert_eq!(
+            summary(code),
+            [
+                (Noqa, "F401,E401".to_string(), 1, 1, 1, false),
+                (Noqa, String::new(), 2, 2, 2, false),
+                (Noqa, String::new(), 3, 3, 3, false),
+            ]
+        );
+        assert_eq!(
+            summary("# flake8: noqa\n"),
+            [(Noqa, String::new(), 1, 1, 1, true)]
+        );
+        assert_eq!(summary("# flake8: noqa: E501\n"), []);
+    }
+
+    #[test]
+    fn test_type_ignore() {
+        use self::SuppressionKind::TypeIgnore;
+        let code = "# type: ignore
+x = (  # comment
+    f(),  # type: ignore[arg-type, misc]
+)
+# type: ignore
+y = 1  # type: ignored
+";
+        assert_eq!(
+            summary(code),
+            [
+                (TypeIgnore, String::new(), 1, 1, 1, true),
+                (TypeIgnore, "arg-type,misc".to_string(), 3, 2, 4, false),
+                (TypeIgnore, String::new(), 5, 5, 5, false),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_suppresses() {
+        let code = "s = '''\n'''  # noqa: E501\n";
+        let suppressions = suppressions(code);
+        assert!(is_suppressed(
+            &suppressions,
+            SuppressionKind::Noqa,
+            1,
+            Some("E501")
+        ));
+        assert!(!is_suppressed(
+            &suppressions,
+            SuppressionKind::Noqa,
+            1,
+            Some("W291")
+        ));
+        assert!(!is_suppressed(
+            &suppressions,
+            SuppressionKind::Noqa,
+            1,
+            None
+        ));
+        assert!(!is_suppressed(
+            &suppressions,
+            SuppressionKind::TypeIgnore,
+            1,
+            None
+        ));
+    }
+}
@@ -0,0 +1,462 @@
+//! A tokenizer for Python code, similar to Python's `tokenize` module.
+//!
+//! It never fails: characters that cannot start a token, unterminated
+//! strings and inconsistent dedents are returned as `TokenKind::Error`
+//! tokens.
+
+use std::collections::VecDeque;
+
+use unicode_xid::UnicodeXID;
+
+/// The kind of a `Token`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum TokenKind {
+    /// An identifier or a keyword.
+    Name,
+    Number,
+    /// A string or bytes literal, including its prefix and quotes.
+    String,
+    /// An operator or a delimiter.
+    Op,
+    Comment,
+    /// The end of a logical line.
+    Newline,
+    /// A newline that does not end a logical line (blank lines, and
+    /// newlines inside brackets).
+    Nl,
+    Indent,
+    Dedent,
+    EndMarker,
+    Error,
+}
+
+/// A token, with its position in the code.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Token<'a> {
+    pub kind: TokenKind,
+    /// The code of the token. Empty for dedents, the end marker, and
+    /// the newline added at the end of code that does not end with one.
+    pub text: &'a str,
+    /// Offset (in bytes) of the token in the code.
+    pub offset: usize,
+    /// Line of the start of the token, starting from 1.
+    pub line: u32,
+}
+
+const OPERATORS: [&str; 47] = [
+    "**=", "//=", ">>=", "<<=", "...", "**", "//", ">>", "<<", "<=", ">=", "==", "!=", "->", "+=",
+    "-=", "*=", "/=", "%=", "&=", "|=", "^=", "@=", ":=", "+", "-", "*", "/", "%", "@", "&", "|",
+    "^", "~", "<", ">", "(", ")", "[", "]", "{", "}", ",", ":", ";", ".", "=",
+];
+
+const STRING_PREFIXES: [&str; 8] = ["r", "u", "f", "b", "br", "rb", "fr", "rf"];
+
+/// Returns the number of line breaks (`\n`, `\r\n` or `\r`) in `s`.
+pub(crate) fn line_breaks(s: &str) -> u32 {
+    (s.matches('\n').count() + s.matches('\r').count() - s.matches("\r\n").count()) as u32
+}
+
+/// Iterator over the tokens of some code, returned by `tokenize`.
+pub struct Tokenizer<'a> {
+    code: &'a str,
+    pos: usize,
+    line: u32,
+    /// Number of open brackets.
+    depth: usize,
+    /// Columns of the enclosing indentation levels.
+    indents: Vec<usize>,
+    at_line_start: bool,
+    /// Whether the current logical line has tokens.
+    line_has_tokens: bool,
+    pending: VecDeque<Token<'a>>,
+    done: bool,
+}
+
+/// Returns the tokens of `code`, ending with a `TokenKind::EndMarker`.
+pub fn tokenize(code: &str) -> Tokenizer<'_> {
+    Tokenizer {
+        code,
+        pos: 0,
+        line: 1,
+        depth: 0,
+        indents: vec![0],
+        at_line_start: true,
+        line_has_tokens: false,
+        pending: VecDeque::new(),
+        done: false,
+    }
+}
+
+impl<'a> Tokenizer<'a> {
+    fn token(&self, kind: TokenKind, start: usize) -> Token<'a> {
+        Token {
+            kind,
+            text: &self.code[start..self.pos],
+            offset: start,
+            line: self.line,
+        }
+    }
+
+    /// Handles the indentation of a new line, if it is not blank.
+    fn indentation(&mut self) {
+        let rest = &self.code[self.pos..];
+        let mut column = 0;
+        let mut len = 0;
+        for c in rest.bytes() {
+            match c {
+                b' ' => column += 1,
+                b'\t' => column = (column / 8 + 1) * 8,
+                b'\x0c' => column = 0,
+                _ => break,
+            }
+            len += 1;
+        }
+        match rest[len..].bytes().next() {
+            None | Some(b'#') | Some(b'\n') | Some(b'\r') => return, // Blank line
+            _ => (),
+        }
+        let start = self.pos;
+        self.pos += len;
+        let current = *self.indents.last().unwrap(); // Never empty
+        if column > current {
+            self.indents.push(column);
+            let token = self.token(TokenKind::Indent, start);
+            self.pending.push_back(token);
+        } else {
+            while column < *self.indents.last().unwrap() {
+                self.indents.pop();
+                let token = self.token(TokenKind::Dedent, self.pos);
+                self.pending.push_back(token);
+            }
+            if column != *self.indents.last().unwrap() {
+                // Does not match any outer indentation level
+                let token = self.token(TokenKind::Error, self.pos);
+                self.pending.push_back(token);
+            }
+        }
+    }
+
+    fn end(&mut self) {
+        self.done = true;
+        if self.line_has_tokens {
+            let token = self.token(TokenKind::Newline, self.pos);
+            self.pending.push_back(token);
+        }
+        while self.indents.len() > 1 {
+            self.indents.pop();
+            let token = self.token(TokenKind::Dedent, self.pos);
+            self.pending.push_back(token);
+        }
+        let token = self.token(TokenKind::EndMarker, self.pos);
+        self.pending.push_back(token);
+    }
+
+    fn name_len(rest: &str) -> usize {
+        let mut chars = rest.char_indices();
+        match chars.next() {
+            Some((_, c)) if c == '_' || UnicodeXID::is_xid_start(c) => (),
+            _ => return 0,
+        }
+        chars
+            .find(|&(_, c)| !UnicodeXID::is_xid_continue(c))
+            .map_or(rest.len(), |(i, _)| i)
+    }
+
+    fn number_len(rest: &str) -> usize {
+        let bytes = rest.as_bytes();
+        let at = |i: usize| bytes.get(i).cloned().unwrap_or(b'\0');
+        let digits = |mut i: usize| {
+            while at(i).is_ascii_digit() || at(i) == b'_' {
+                i += 1;
+            }
+            i
+        };
+        if at(0) == b'0' && b"xXoObB".contains(&at(1)) {
+            let mut i = 2;
+            while at(i).is_ascii_alphanumeric() || at(i) == b'_' {
+                i += 1;
+            }
+            return i;
+        }
+        let mut i = digits(0);
+        if at(i) == b'.' {
+            i = digits(i + 1);
+        }
+        if at(i) == b'e' || at(i) == b'E' {
+            if at(i + 1).is_ascii_digit() {
+                i = digits(i + 1);
+            } else if (at(i + 1) == b'+' || at(i + 1) == b'-') && at(i + 2).is_ascii_digit() {
+                i = digits(i + 2);
+            }
+        }
+        if at(i) == b'j' || at(i) == b'J' {
+            i += 1;
+        }
+        i
+    }
+
+    /// Returns the length of the string starting with a quote at the
+    /// beginning of `rest`, and whether it is terminated.
+    fn string_len(rest: &str) -> (usize, bool) {
+        let bytes = rest.as_bytes();
+        let quote = bytes[0];
+        let triple = bytes.len() >= 3 && bytes[1] == quote && bytes[2] == quote;
+        let mut i = if triple { 3 } else { 1 };
+        while i < bytes.len() {
+            match bytes[i] {
+                b'\\' if bytes[i + 1..].starts_with(b"\r\n") => i += 2,
+                b'\\' => i += 1,
+                b'\n' | b'\r' if !triple => return (i, false),
+                c if c == quote => {
+                    if !triple {
+                        return (i + 1, true);
+                    } else if bytes[i..].starts_with(&[quote, quote, quote]) {
+                        return (i + 3, true);
+                    }
+                }
+                _ => (),
+            }
+            i += 1;
+        }
+        (bytes.len(), false)
+    }
+
+    /// Reads the next token, or returns `None` if it only skipped
+    /// something (whitespaces, line continuation, ...).
+    fn read_token(&mut self) -> Option<Token<'a>> {
+        let rest = &self.code[self.pos..];
+        let start = self.pos;
+        let first = rest.chars().next().unwrap(); // Never called at the end
+        let second = rest[first.len_utf8()..].chars().next();
+        match first {
+            ' ' | '\t' | '\x0c' => {
+                self.pos += 1;
+                return None;
+            }
+            '\n' | '\r' => {
+                self.pos += if rest.starts_with("\r\n") { 2 } else { 1 };
+                let kind = if self.depth == 0 && self.line_has_tokens {
+                    TokenKind::Newline
+                } else {
+                    TokenKind::Nl
+                };
+                let token = self.token(kind, start);
+                self.line += 1;
+                if self.depth == 0 {
+                    self.at_line_start = true;
+                    self.line_has_tokens = false;
+                }
+                return Some(token);
+            }
+            '#' => {
+                self.pos += rest.find(&['\n', '\r'][..]).unwrap_or(rest.len());
+                return Some(self.token(TokenKind::Comment, start));
+            }
+            '\\' if second == Some('\n') || second == Some('\r') => {
+                self.pos += if rest.starts_with("\\\r\n") { 3 } else { 2 };
+                self.line += 1;
+                return None;
+            }
+            _ => (),
+        }
+
+        self.line_has_tokens = true;
+        let (kind, len) = if first.is_ascii_digit()
+            || (first == '.' && second.is_some_and(|c| c.is_ascii_digit()))
+        {
+            (TokenKind::Number, Self::number_len(rest))
+        } else if first == '\'' || first == '"' {
+            match Self::string_len(rest) {
+                (len, true) => (TokenKind::String, len),
+                (len, false) => (TokenKind::Error, len),
+            }
+        } else if let Some(op) = OPERATORS.iter().find(|op| rest.starts_with(*op)) {
+            match *op {
+                "(" | "[" | "{" => self.depth += 1,
+                ")" | "]" | "}" => self.depth = self.depth.saturating_sub(1),
+                _ => (),
+            }
+            (TokenKind::Op, op.len())
+        } else {
+            match Self::name_len(rest) {
+                0 => (TokenKind::Error, first.len_utf8()),
+                len => {
+                    let name = &rest[..len];
+                    let is_prefix = STRING_PREFIXES
+                        .iter()
+                        .any(|prefix| prefix.eq_ignore_ascii_case(name));
+                    match rest[len..].chars().next() {
+                        Some('\'') | Some('"') if is_prefix => {
+                            match Self::string_len(&rest[len..]) {
+                                (string_len, true) => (TokenKind::String, len + string_len),
+                                (string_len, false) => (TokenKind::Error, len + string_len),
+                            }
+                        }
+                        _ => (TokenKind::Name, len),
+                    }
+                }
+            }
+        };
+        self.pos += len;
+        let token = self.token(kind, start);
+        self.line += line_breaks(token.text);
+        Some(token)
+    }
+}
+
+impl<'a> Iterator for Tokenizer<'a> {
+    type Item = Token<'a>;
+
+    fn next(&mut self) -> Option<Token<'a>> {
+        loop {
+            if let Some(token) = self.pending.pop_front() {
+                return Some(token);
+            }
+            if self.done {
+                return None;
+            }
+            if self.at_line_start && self.depth == 0 {
+                self.at_line_start = false;
+                self.indentation();
+                continue;
+            }
+            if self.pos == self.code.len() {
+                self.end();
+                continue;
+            }
+            if let Some(token) = self.read_token() {
+                return Some(token);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kinds_and_texts(code: &str) -> Vec<(TokenKind, &str)> {
+        tokenize(code).map(|t| (t.kind, t.text)).collect()
+    }
+
+    #[test]
+    fn test_simple() {
+        use self::TokenKind::*;
+        assert_eq!(
+            kinds_and_texts("foo = bar(1, 'baz')  # qux"),
+            vec![
+                (Name, "foo"),
+                (Op, "="),
+                (Name, "bar"),
+                (Op, "("),
+                (Number, "1"),
+                (Op, ","),
+                (String, "'baz'"),
+                (Op, ")"),
+                (Comment, "# qux"),
+                (Newline, ""),
+                (EndMarker, ""),
+            ]
+        );
+        assert_eq!(
+            kinds_and_texts("x**=.5e-3j;rb'a\\'b'...\n"),
+            vec![
+                (Name, "x"),
+                (Op, "**="),
+                (Number, ".5e-3j"),
+                (Op, ";"),
+                (String, "rb'a\\'b'"),
+                (Op, "..."),
+                (Newline, "\n"),
+                (EndMarker, ""),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_indentation() {
+        use self::TokenKind::*;
+        let code = "if x:\n    y = (1,\n2)\n\n  # comment\n    '''a\nb'''\nz\n";
+        assert_eq!(
+            kinds_and_texts(code),
+            vec![
+                (Name, "if"),
+                (Name, "x"),
+                (Op, ":"),
+                (Newline, "\n"),
+                (Indent, "    "),
+                (Name, "y"),
+                (Op, "="),
+                (Op, "("),
+                (Number, "1"),
+                (Op, ","),
+                (Nl, "\n"),
+                (Number, "2"),
+                (Op, ")"),
+                (Newline, "\n"),
+                (Nl, "\n"),
+                (Comment, "# comment"),
+                (Nl, "\n"),
+                (String, "'''a\nb'''"),
+                (Newline, "\n"),
+                (Dedent, ""),
+                (Name, "z"),
+                (Newline, "\n"),
+                (EndMarker, ""),
+            ]
+        );
+        let lines: Vec<_> = tokenize(code).map(|t| t.line).collect();
+        assert_eq!(
+            lines,
+            vec![1, 1, 1, 1, 2, 2, 2, 2, 2, 2, 2, 3, 3, 3, 4, 5, 5, 6, 7, 8, 8, 8, 9]
+        );
+    }
+
+    #[test]
+    fn test_line_breaks() {
+        use self::TokenKind::*;
+        let code = "x = '''a\rb\r\nc'''\ry = 'd\\\r\ne'\r\nz\n";
+        assert_eq!(
+            kinds_and_texts(code),
+            vec![
+                (Name, "x"),
+                (Op, "="),
+                (String, "'''a\rb\r\nc'''"),
+                (Newline, "\r"),
+                (Name, "y"),
+                (Op, "="),
+                (String, "'d\\\r\ne'"),
+                (Newline, "\r\n"),
+                (Name, "z"),
+                (Newline, "\n"),
+                (EndMarker, ""),
+            ]
+        );
+        let lines: Vec<_> = tokenize(code).map(|t| t.line).collect();
+        assert_eq!(lines, vec![1, 1, 1, 3, 4, 4, 4, 5, 6, 6, 7]);
+    }
+
+    #[test]
+    fn test_errors() {
+        use self::TokenKind::*;
+        assert_eq!(
+            kinds_and_texts("if x:\n    y\n  z $ 'a\n"),
+            vec![
+                (Name, "if"),
+                (Name, "x"),
+                (Op, ":"),
+                (Newline, "\n"),
+                (Indent, "    "),
+                (Name, "y"),
+                (Newline, "\n"),
+                (Dedent, ""),
+                (Error, ""),
+                (Name, "z"),
+                (Error, "$"),
+                (Error, "'a"),
+                (Newline, "\n"),
+                (EndMarker, ""),
+            ]
+        );
+    }
+}
@@ -0,0 +1,352 @@
+//! Parsing of code that may have syntax errors, for editors: the
+//! statements that cannot be parsed become `Statement::Invalid` nodes,
+//! and the rest of the module is parsed as usual.
+
+use std::ops::Range;
+
+use nom::types::CompleteStr;
+use nom_locate::LocatedSpan;
+
+use ast::*;
+use budget::{check_code_size, limit_diagnostics};
+use errors::ParseError;
+use helpers::make_strspan;
+use memo::new_input;
+use statements::statement;
+use stream::{skip_blank_lines, LineScanner};
+use {make_module, parse_module, universal_newlines};
+
+/// A module parsed by `parse_module_tolerant`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TolerantModule {
+    /// The module, where each statement that could not be parsed is a
+    /// `Statement::Invalid`.
+    pub module: Module,
+    /// The syntax errors, one for each invalid statement, in the order
+    /// of the code.
+    pub errors: Vec<ParseError>,
+}
+
+/// A physical line of the code.
+struct Line {
+    offset: usize,
+    line: u32,
+    /// Offset of the end of the line, after its line break.
+    end: usize,
+    /// Whether the line starts a logical line.
+    logical: bool,
+    /// Whether the line starts a logical line, and only contains spaces
+    /// or a comment.
+    blank: bool,
+    /// Whether the line can start a new statement: it starts a logical
+    /// line, and is not an `else:`-like clause or after a decorator.
+    starts_statement: bool,
+    /// Number of spaces and tabs the line starts with.
+    indent: usize,
+}
+
+fn split_lines(code: &str) -> Vec<Line> {
+    let mut lines = Vec::new();
+    let mut scanner = LineScanner::default();
+    let mut offset = 0;
+    for (lineno, text) in code.split_inclusive('\n').enumerate() {
+        let logical = scanner.at_line_start();
+        let trimmed = text.trim_start_matches(&[' ', '\t', '\x0c'][..]);
+        let indent = text
+            .trim_start_matches('\x0c')
+            .chars()
+            .take_while(|&c| c == ' ' || c == '\t')
+            .count();
+        let blank = logical && (trimmed.trim_end().is_empty() || trimmed.starts_with('#'));
+        lines.push(Line {
+            offset,
+            line: lineno as u32 + 1,
+            end: offset + text.len(),
+            logical,
+            blank,
+            starts_statement: logical && scanner.starts_statement(trimmed),
+            indent,
+        });
+        // Decorators are only detected at the start of a line
+        scanner.scan_line(if logical { trimmed } else { text });
+        offset += text.len();
+    }
+    lines
+}
+
+/// Returns the blocks of a compound statement, in the order of the
+/// code, skipping absent `else:`-like clauses.
+fn blocks(stmt: &mut CompoundStatement) -> Vec<&mut Vec<Statement>> {
+    match *stmt {
+        CompoundStatement::If(ref mut cond_blocks, ref mut else_block) => cond_blocks
+            .iter_mut()
+            .map(|&mut (_, ref mut block)| block)
+            .chain(else_block)
+            .collect(),
+        CompoundStatement::For {
+            ref mut for_block,
+            ref mut else_block,
+            ..
+        } => Some(for_block).into_iter().chain(else_block).collect(),
+        CompoundStatement::While(_, ref mut block, ref mut else_block) => {
+            Some(block).into_iter().chain(else_block).collect()
+        }
+        CompoundStatement::With(_, ref mut block) => vec![block],
+        CompoundStatement::Funcdef(ref mut funcdef) => vec![&mut funcdef.code],
+        CompoundStatement::Classdef(ref mut classdef) => vec![&mut classdef.code],
+        CompoundStatement::Try(ref mut try_) => Some(&mut try_.try_block)
+            .into_iter()
+            .chain(
+                try_.except_clauses
+                    .iter_mut()
+                    .map(|&mut (_, _, ref mut block)| block),
+            )
+            .chain(Some(&mut try_.last_except))
+            .chain(Some(&mut try_.else_block))
+            .chain(Some(&mut try_.finally_block))
+            .filter(|block| !block.is_empty())
+            .collect(),
+    }
+}
+
+struct Recovery<'a> {
+    code: &'a str,
+    lines: Vec<Line>,
+    errors: Vec<ParseError>,
+}
+
+impl<'a> Recovery<'a> {
+    /// Parses the statements of `lines`, whose indentation is `indent`.
+    fn block(&mut self, lines: Range<usize>, indent: usize) -> Vec<Statement> {
+        let mut stmts = Vec::new();
+        let mut start = None;
+        for i in lines.clone() {
+            let line = &self.lines[i];
+            if line.blank {
+                continue;
+            }
+            let starts_statement = line.starts_statement && line.indent <= indent;
+            match start {
+                Some(_) if !starts_statement => continue,
+                Some(start) => stmts.extend(self.statement(start..i, indent)),
+                None => (),
+            }
+            start = Some(i);
+        }
+        if let Some(start) = start {
+            stmts.extend(self.statement(start..lines.end, indent));
+        }
+        stmts
+    }
+
+    /// Parses the lines of a (possibly compound) statement.
+    fn statement(&mut self, lines: Range<usize>, indent: usize) -> Vec<Statement> {
+        let (start, end) = (&self.lines[lines.start], &self.lines[lines.end - 1]);
+        let mut i = LocatedSpan {
+            offset: start.offset,
+            line: start.line,
+            fragment: CompleteStr(&self.code[start.offset..end.end]),
+        };
+        let mut stmts = Vec::new();
+        loop {
+            match skip_blank_lines(i).and_then(|i| match i {
+                None => Ok(None),
+                Some(i) => statement(i, indent).map(Some).map_err(ParseError::from),
+            }) {
+                Ok(Some((rest, new_stmts))) => {
+                    stmts.extend(new_stmts);
+                    i = rest;
+                }
+                Ok(None) => return stmts,
+                Err(e) => {
+                    let nb_errors = self.errors.len();
+                    if let Some(stmt) = self.compound_statement(lines.clone(), indent) {
+                        if self.errors.len() == nb_errors {
+                            self.error(e, &lines);
+                        }
+                        return vec![stmt];
+                    }
+                    self.error(e, &lines);
+                    return vec![Statement::Invalid(self.text(&lines))];
+                }
+            }
+        }
+    }
+
+    /// Parses a compound statement with errors in its blocks, by parsing
+    /// its clauses with `pass` blocks, then each of its blocks.
+    fn compound_statement(&mut self, lines: Range<usize>, indent: usize) -> Option<Statement> {
+        if self.lines[lines.start].indent != indent {
+            return None;
+        }
+        let mut skeleton = String::new();
+        let mut block_lines = Vec::new();
+        let mut in_clause = true;
+        for i in lines.clone() {
+            let line = &self.lines[i];
+            if line.logical {
+                in_clause = !line.blank && line.indent <= indent;
+            }
+            if in_clause {
+                skeleton.push_str(&self.code[line.offset..line.end]);
+            } else if !line.blank {
+                match block_lines.last_mut() {
+                    Some(&mut (_, ref mut last)) if *last == i => *last = i + 1,
+                    _ => {
+                        block_lines.push((i, i + 1));
+                        skeleton.push_str(&" ".repeat(indent + 4));
+                        skeleton.push_str("pass\n");
+                    }
+                }
+            } else if let Some(&mut (_, ref mut last)) = block_lines.last_mut() {
+                if *last == i {
+                    *last = i + 1;
+                }
+            }
+        }
+
+        // The skeleton may be at the address of a previous one
+        new_input();
+        let (rest, mut stmts) = statement(make_strspan(&skeleton), indent).ok()?;
+        if skip_blank_lines(rest).ok()?.is_some() || stmts.len() != 1 {
+            return None;
+        }
+        let mut stmt = match stmts.pop() {
+            Some(Statement::Compound(stmt)) => stmt,
+            _ => return None,
+        };
+        {
+            let blocks = blocks(&mut stmt);
+            if blocks.len() != block_lines.len() {
+                return None;
+            }
+            for (block, (start, end)) in blocks.into_iter().zip(block_lines) {
+                let block_indent = self.lines[start].indent;
+                *block = self.block(start..end, block_indent);
+            }
+        }
+        Some(Statement::Compound(stmt))
+    }
+
+    /// Returns the code of a statement, without its indentation and the
+    /// blank lines after it.
+    fn text(&self, lines: &Range<usize>) -> String {
+        let end = lines
+            .clone()
+            .rev()
+            .find(|&i| !self.lines[i].blank)
+            .map_or(lines.end, |i| i + 1);
+        let (start, end) = (&self.lines[lines.start], &self.lines[end - 1]);
+        self.code[start.offset..end.end].trim().to_string()
+    }
+
+    /// Adds the error of a statement, reported at the start of an
+    /// unterminated construct or with a suggestion if it is in the
+    /// statement.
+    fn error(&mut self, e: ParseError, lines: &Range<usize>) {
+        let (start, end) = (&self.lines[lines.start], &self.lines[lines.end - 1]);
+        let refined = e
+            .clone()
+            .locate_unterminated(self.code)
+            .guess_mistake(self.code);
+        if start.offset <= refined.offset && refined.offset <= end.end {
+            self.errors.push(refined)
+        } else {
+            self.errors.push(e)
+        }
+    }
+}
+
+/// Parses a whole file like `parse_module`, but never fails: each
+/// statement that cannot be parsed is replaced by a `Statement::Invalid`
+/// and its error is returned along with the module.
+///
+/// The blocks of a compound statement whose clauses can be parsed are
+/// parsed separately, so an error in a function only replaces the
+/// statement of the function that has the error.
+///
+/// ```
+/// use python_parser::ast::*;
+/// use python_parser::tolerant::parse_module_tolerant;
+/// let parsed = parse_module_tolerant("x = (1 +\ny = 2\n");
+/// assert_eq!(parsed.module.body[0], Statement::Invalid("x = (1 +\ny = 2".to_string()));
+/// assert_eq!(parsed.errors[0].to_string(), "'(' was never closed at line 1");
+/// ```
+pub fn parse_module_tolerant(code: &str) -> TolerantModule {
+    let code = universal_newlines(code);
+    if let Err(e) = check_code_size(&code) {
+        return TolerantModule {
+            module: make_module("", Vec::new()),
+            errors: vec![e],
+        };
+    }
+    if let Ok(module) = parse_module(&code) {
+        return TolerantModule {
+            module,
+            errors: Vec::new(),
+        };
+    }
+    let lines = split_lines(&code);
+    let nb_lines = lines.len();
+    let mut recovery = Recovery {
+        code: &code,
+        lines,
+        errors: Vec::new(),
+    };
+    let body = recovery.block(0..nb_lines, 0);
+    let mut errors = recovery.errors;
+    limit_diagnostics(&mut errors, |e| e);
+    TolerantModule {
+        module: make_module(&code, body),
+        errors,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use errors::PyParseError;
+    use visitors::printer::format_module;
+
+    #[test]
+    fn test_valid() {
+        let code = "import foo\n\nif foo:\n    bar()\nelse:\n    pass\n";
+        let parsed = parse_module_tolerant(code);
+        assert_eq!(parsed.module, parse_module(code).unwrap());
+        assert_eq!(parsed.errors, Vec::new());
+    }
+
+    #[test]
+    fn test_invalid_statements() {
+        let parsed = parse_module_tolerant("x = 1\nif x\n    pass\ny = [1,\n\n2]\nz = 2 +\n");
+        assert_eq!(
+            &parsed.module.body[1..],
+            &[
+                Statement::Invalid("if x\n    pass".to_string()),
+                Statement::Assignment(
+                    small_list![Expression::Name("y".to_string())],
+                    vec![small_list![Expression::ListLiteral(vec![
+                        SetItem::Unique(Expression::Int(1u32.into())),
+                        SetItem::Unique(Expression::Int(2u32.into())),
+                    ])]]
+                ),
+                Statement::Invalid("z = 2 +".to_string()),
+            ][..]
+        );
+        let errors: Vec<_> = parsed.errors.iter().map(|e| (e.line, e.code)).collect();
+        assert_eq!(errors, [(2, Some(PyParseError::MissingColon)), (7, None)]);
+    }
+
+    #[test]
+    fn test_invalid_blocks() {
+        let code = "@foo\nclass A:\n    def f(self):\n        x = \n\n        return x\n    def g(self):\n        pass\ntry:\n    pass\nexcept:\n  1 +\nelse:\n    pass\n";
+        let parsed = parse_module_tolerant(code);
+        let valid = parse_module(&code.replace("x = ", "x = y").replace("1 +", "z")).unwrap();
+        let expected = format_module(&valid.body)
+            .replace("x = y", "x =")
+            .replace("    z", "    1 +");
+        assert_eq!(format_module(&parsed.module.body), expected);
+        let errors: Vec<_> = parsed.errors.iter().map(|e| e.line).collect();
+        assert_eq!(errors, [4, 12]);
+    }
+}
@@ -0,0 +1,1089 @@
+//! Checks of the code that are not part of Python's grammar, so the
+//! parsers accept code that CPython's compiler still rejects with a
+//! `SyntaxError`, like `return` outside a function.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::mem;
+use std::vec;
+
+use ast::*;
+use budget::limit_diagnostics;
+use errors::{ParseError, PyParseError};
+use span::{Span, Spanned};
+use tokenizer::{tokenize, Token, TokenKind};
+
+/// Keywords that the checks may report, found with the tokenizer
+/// because the AST has no positions.
+const KEYWORDS: [&str; 8] = [
+    "async", "await", "break", "continue", "global", "nonlocal", "return", "yield",
+];
+
+// Flags of the names of a scope, like the ones of CPython's symbol table
+const USE: u8 = 1;
+const LOCAL: u8 = 2;
+const PARAM: u8 = 4;
+const ANNOTATED: u8 = 8;
+const IMPORTED: u8 = 16;
+const GLOBAL: u8 = 32;
+const NONLOCAL: u8 = 64;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+/// A problem found by `validate`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    /// The error CPython would raise.
+    pub error: ParseError,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.severity {
+            Severity::Error => self.error.fmt(f),
+            Severity::Warning => write!(f, "warning: {}", self.error),
+        }
+    }
+}
+
+impl Spanned for Diagnostic {
+    fn span(&self) -> Span {
+        self.error.span()
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ValidationOptions {
+    /// Reports the problems as warnings instead of errors, for tools
+    /// that must handle any code.
+    pub warnings_only: bool,
+    /// Allows `await`, `async for` and asynchronous comprehensions at
+    /// the top level of the module, like asyncio's REPL does (with
+    /// `PyCF_ALLOW_TOP_LEVEL_AWAIT`).
+    pub top_level_await: bool,
+}
+
+/// Where the code being checked is.
+#[derive(Copy, Clone, Debug)]
+struct Context {
+    in_function: bool,
+    in_loop: bool,
+    /// The kind of the innermost comprehension, if the code is in one
+    /// (except its first iterator, which is in the enclosing scope).
+    comprehension: Option<&'static str>,
+    /// Whether `await` and asynchronous comprehensions are allowed: in
+    /// `async def`, generator expressions, and at the top level with
+    /// `top_level_await`.
+    async_allowed: bool,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum ScopeKind {
+    Module,
+    Class,
+    Function,
+    Comprehension,
+}
+
+/// The names of a module, class, function or comprehension.
+#[derive(Debug)]
+struct Scope {
+    kind: ScopeKind,
+    /// Flags of the names used, bound or declared in the scope so far.
+    names: HashMap<Name, u8>,
+    /// Position of the first `global` or `nonlocal` declaration of
+    /// each name.
+    declarations: HashMap<Name, (usize, u32)>,
+    /// `nonlocal` declarations of the nested scopes, which must be
+    /// bound in this scope or an enclosing function.
+    free: Vec<(Name, (usize, u32))>,
+}
+
+impl Scope {
+    fn new(kind: ScopeKind) -> Scope {
+        Scope {
+            kind,
+            names: HashMap::new(),
+            declarations: HashMap::new(),
+            free: Vec::new(),
+        }
+    }
+
+    fn flags(&self, name: &str) -> u8 {
+        self.names.get(name).cloned().unwrap_or(0)
+    }
+}
+
+struct Validator<'a> {
+    options: &'a ValidationOptions,
+    /// Tokens of the reported keywords, in the order of the code.
+    keywords: HashMap<&'static str, vec::IntoIter<Token<'a>>>,
+    /// Position of the end of the code.
+    end: (usize, u32),
+    context: Context,
+    scope: Scope,
+    enclosing_scopes: Vec<Scope>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl<'a> Validator<'a> {
+    /// Returns the position of the next `keyword`, which must be visited
+    /// in the order of the code.
+    fn keyword(&mut self, keyword: &'static str) -> (usize, u32) {
+        match self
+            .keywords
+            .get_mut(keyword)
+            .and_then(|tokens| tokens.next())
+        {
+            Some(token) => (token.offset, token.line),
+            None => self.end, // The AST is not the one of the code
+        }
+    }
+
+    fn report(&mut self, (offset, line): (usize, u32), error: PyParseError) {
+        let severity = if self.options.warnings_only {
+            Severity::Warning
+        } else {
+            Severity::Error
+        };
+        self.diagnostics.push(Diagnostic {
+            severity,
+            error: ParseError {
+                offset,
+                line,
+                code: Some(error),
+            },
+        });
+    }
+
+    /// Finds the next `keyword`, and reports `error` there if `invalid`.
+    fn check(&mut self, keyword: &'static str, invalid: bool, error: PyParseError) {
+        let position = self.keyword(keyword);
+        if invalid {
+            self.report(position, error)
+        }
+    }
+
+    /// Runs `f` in a modified context.
+    fn with_context<F, G>(&mut self, change: F, f: G)
+    where
+        F: FnOnce(&mut Context),
+        G: FnOnce(&mut Self),
+    {
+        let saved = self.context;
+        change(&mut self.context);
+        f(self);
+        self.context = saved;
+    }
+
+    /// Runs `f` in a new scope.
+    fn with_scope<F>(&mut self, kind: ScopeKind, f: F)
+    where
+        F: FnOnce(&mut Self),
+    {
+        let enclosing = mem::replace(&mut self.scope, Scope::new(kind));
+        self.enclosing_scopes.push(enclosing);
+        f(self);
+        let mut scope = match self.enclosing_scopes.pop() {
+            Some(enclosing) => mem::replace(&mut self.scope, enclosing),
+            None => return,
+        };
+        let free = mem::take(&mut scope.free);
+        for (name, position) in free {
+            let flags = scope.flags(&name);
+            if scope.kind != ScopeKind::Function {
+                // Names of classes are not visible from nested functions
+                self.scope.free.push((name, position))
+            } else if flags & GLOBAL != 0 {
+                self.report(position, PyParseError::NonlocalWithoutBinding)
+            } else if flags & (LOCAL | PARAM | IMPORTED | NONLOCAL) == 0 {
+                self.scope.free.push((name, position))
+            }
+        }
+    }
+
+    /// Runs `f` in the scope enclosing the current one.
+    fn in_enclosing_scope<F>(&mut self, f: F)
+    where
+        F: FnOnce(&mut Self),
+    {
+        match self.enclosing_scopes.pop() {
+            Some(enclosing) => {
+                let scope = mem::replace(&mut self.scope, enclosing);
+                f(self);
+                let enclosing = mem::replace(&mut self.scope, scope);
+                self.enclosing_scopes.push(enclosing)
+            }
+            None => f(self),
+        }
+    }
+
+    fn add_flags(&mut self, name: &str, flags: u8) {
+        *self.scope.names.entry(name.to_string()).or_insert(0) |= flags
+    }
+
+    /// Visits `global` or `nonlocal` declarations.
+    fn declare(&mut self, keyword: &'static str, names: &[Name]) {
+        let position = self.keyword(keyword);
+        let (flag, other) = if keyword == "global" {
+            (GLOBAL, NONLOCAL)
+        } else {
+            (NONLOCAL, GLOBAL)
+        };
+        if flag == NONLOCAL && self.scope.kind == ScopeKind::Module {
+            return self.report(position, PyParseError::NonlocalAtModuleLevel);
+        }
+        for name in names {
+            let flags = self.scope.flags(name);
+            let errors = if flags & PARAM != 0 {
+                Some((
+                    PyParseError::ParameterAndGlobal,
+                    PyParseError::ParameterAndNonlocal,
+                ))
+            } else if flags & USE != 0 {
+                Some((
+                    PyParseError::UsedBeforeGlobal,
+                    PyParseError::UsedBeforeNonlocal,
+                ))
+            } else if flags & ANNOTATED != 0 {
+                Some((
+                    PyParseError::AnnotatedGlobal,
+                    PyParseError::AnnotatedNonlocal,
+                ))
+            } else if flags & LOCAL != 0 {
+                Some((
+                    PyParseError::AssignedBeforeGlobal,
+                    PyParseError::AssignedBeforeNonlocal,
+                ))
+            } else {
+                None
+            };
+            if let Some((global_error, nonlocal_error)) = errors {
+                let error = if flag == GLOBAL {
+                    global_error
+                } else {
+                    nonlocal_error
+                };
+                self.report(position, error);
+            } else if flags & other != 0 {
+                let first = self.scope.declarations[name];
+                self.report(first, PyParseError::NonlocalAndGlobal)
+            } else {
+                if flag == NONLOCAL && flags & NONLOCAL == 0 {
+                    if let Some(enclosing) = self.enclosing_scopes.last_mut() {
+                        enclosing.free.push((name.clone(), position))
+                    }
+                }
+                self.add_flags(name, flag);
+                self.scope
+                    .declarations
+                    .entry(name.clone())
+                    .or_insert(position);
+            }
+        }
+    }
+
+    fn statements(&mut self, stmts: &[Statement]) {
+        for stmt in stmts {
+            self.statement(stmt)
+        }
+    }
+
+    fn statement(&mut self, stmt: &Statement) {
+        match *stmt {
+            Statement::Pass
+            | Statement::Raise
+            | Statement::Invalid(_)
+            | Statement::Extension(_, _) => (),
+            Statement::Global(ref names) => self.declare("global", names),
+            Statement::Nonlocal(ref names) => self.declare("nonlocal", names),
+            Statement::Import(ref import) => match *import {
+                Import::ImportFrom { ref names, .. } => {
+                    for (name, alias) in names {
+                        self.add_flags(alias.as_ref().unwrap_or(name), IMPORTED)
+                    }
+                }
+                Import::ImportStarFrom { .. } => (),
+                Import::Import { ref names } => {
+                    for (path, alias) in names {
+                        if let Some(name) = alias.as_ref().or_else(|| path.first()) {
+                            self.add_flags(name, IMPORTED)
+                        }
+                    }
+                }
+            },
+            Statement::Break => {
+                let invalid = !self.context.in_loop;
+                self.check("break", invalid, PyParseError::BreakOutsideLoop)
+            }
+            Statement::Continue => {
+                let invalid = !self.context.in_loop;
+                self.check("continue", invalid, PyParseError::ContinueOutsideLoop)
+            }
+            Statement::Return(ref exprs) => {
+                let invalid = !self.context.in_function;
+                self.check("return", invalid, PyParseError::ReturnOutsideFunction);
+                self.expressions(exprs)
+            }
+            Statement::Del(ref exprs) => self.targets(exprs),
+            Statement::Expressions(ref exprs) => self.expressions(exprs),
+            Statement::RaiseExc(ref e) => self.expression(e),
+            Statement::RaiseExcFrom(ref e1, ref e2) => {
+                self.expression(e1);
+                self.expression(e2)
+            }
+            Statement::Assert(ref e1, ref e2) => {
+                self.expression(e1);
+                if let Some(ref e2) = *e2 {
+                    self.expression(e2)
+                }
+            }
+            Statement::Assignment(ref lhs, ref rhs) => match rhs.split_last() {
+                Some((value, targets)) => {
+                    self.targets(lhs);
+                    for exprs in targets {
+                        self.targets(exprs)
+                    }
+                    self.expressions(value)
+                }
+                None => self.expressions(lhs),
+            },
+            Statement::TypeAnnotation(ref lhs, ref typ) => {
+                self.annotated(lhs);
+                self.expression(typ)
+            }
+            Statement::TypedAssignment(ref lhs, ref typ, ref rhs) => {
+                self.annotated(lhs);
+                self.expression(typ);
+                self.expressions(rhs)
+            }
+            Statement::AugmentedAssignment(ref lhs, _, ref rhs) => {
+                self.targets(lhs);
+                self.expressions(rhs)
+            }
+            Statement::Compound(ref stmt) => self.compound_statement(stmt),
+        }
+    }
+
+    /// Visits the target of an annotated assignment.
+    fn annotated(&mut self, lhs: &[Expression]) {
+        match *lhs {
+            [Expression::Name(ref name)] => self.add_flags(name, ANNOTATED | LOCAL),
+            _ => self.expressions(lhs),
+        }
+    }
+
+    /// Visits a loop's body (the `else` block is not in the loop).
+    fn loop_body(&mut self, stmts: &[Statement]) {
+        self.with_context(|c| c.in_loop = true, |v| v.statements(stmts))
+    }
+
+    fn compound_statement(&mut self, stmt: &CompoundStatement) {
+        match *stmt {
+            CompoundStatement::If(ref branches, ref else_block) => {
+                for (cond, block) in branches {
+                    self.expression(cond);
+                    self.statements(block)
+                }
+                if let Some(ref block) = *else_block {
+                    self.statements(block)
+                }
+            }
+            CompoundStatement::For {
+                async,
+                ref item,
+                ref iterator,
+                ref for_block,
+                ref else_block,
+            } => {
+                if async {
+                    self.check_async("async", PyParseError::AsyncForOutsideAsyncFunction)
+                }
+                self.targets(item);
+                self.expressions(iterator);
+                self.loop_body(for_block);
+                if let Some(ref block) = *else_block {
+                    self.statements(block)
+                }
+            }
+            CompoundStatement::While(ref cond, ref block, ref else_block) => {
+                self.expression(cond);
+                self.loop_body(block);
+                if let Some(ref block) = *else_block {
+                    self.statements(block)
+                }
+            }
+            CompoundStatement::With(ref items, ref block) => {
+                for (e, target) in items {
+                    self.expression(e);
+                    if let Some(ref target) = *target {
+                        self.target(target)
+                    }
+                }
+                self.statements(block)
+            }
+            CompoundStatement::Funcdef(ref funcdef) => self.funcdef(funcdef),
+            CompoundStatement::Classdef(ref classdef) => {
+                self.decorators(&classdef.decorators);
+                self.arguments(&classdef.arguments);
+                self.add_flags(&classdef.name, LOCAL);
+                let code = &classdef.code;
+                self.with_context(
+                    |c| {
+                        c.in_function = false;
+                        c.in_loop = false;
+                        c.async_allowed = false
+                    },
+                    |v| v.with_scope(ScopeKind::Class, |v| v.statements(code)),
+                )
+            }
+            CompoundStatement::Try(ref try_) => {
+                self.statements(&try_.try_block);
+                for (e, name, block) in &try_.except_clauses {
+                    self.expression(e);
+                    if let Some(ref name) = *name {
+                        self.add_flags(name, LOCAL)
+                    }
+                    self.statements(block)
+                }
+                self.statements(&try_.last_except);
+                self.statements(&try_.else_block);
+                self.statements(&try_.finally_block)
+            }
+        }
+    }
+
+    fn decorators(&mut self, decorators: &[Decorator]) {
+        for decorator in decorators {
+            if let Some(ref args) = decorator.args {
+                self.arguments(args)
+            }
+        }
+    }
+
+    fn funcdef(&mut self, funcdef: &Funcdef) {
+        self.decorators(&funcdef.decorators);
+        if funcdef.async {
+            self.keyword("async");
+        }
+        let params = &funcdef.parameters;
+        let (posonly_args, args, keyword_args) =
+            (&params.posonly_args, &params.args, &params.keyword_args);
+        for (_, annotation, default) in posonly_args.iter().chain(args) {
+            self.optional_expression(annotation);
+            self.optional_expression(default)
+        }
+        if let StarParams::Named((_, ref annotation)) = params.star_args {
+            self.optional_expression(annotation)
+        }
+        for (_, annotation, default) in keyword_args {
+            self.optional_expression(annotation);
+            self.optional_expression(default)
+        }
+        if let Some((_, ref annotation)) = params.star_kwargs {
+            self.optional_expression(annotation)
+        }
+        self.optional_expression(&funcdef.return_type);
+        self.add_flags(&funcdef.name, LOCAL);
+        let mut names: Vec<&Name> = posonly_args
+            .iter()
+            .chain(args)
+            .chain(keyword_args)
+            .map(|(name, _, _)| name)
+            .collect();
+        if let StarParams::Named((ref name, _)) = params.star_args {
+            names.push(name)
+        }
+        if let Some((ref name, _)) = params.star_kwargs {
+            names.push(name)
+        }
+        let (code, async) = (&funcdef.code, funcdef.async);
+        self.with_context(
+            |c| {
+                *c = Context {
+                    in_function: true,
+                    in_loop: false,
+                    comprehension: None,
+                    async_allowed: async,
+                }
+            },
+            |v| {
+                v.with_scope(ScopeKind::Function, |v| {
+                    for name in names {
+                        v.add_flags(name, PARAM)
+                    }
+                    v.statements(code)
+                })
+            },
+        )
+    }
+
+    fn arguments(&mut self, args: &[Argument]) {
+        for arg in args {
+            match *arg {
+                Argument::Positional(ref e)
+                | Argument::Starargs(ref e)
+                | Argument::Keyword(_, ref e)
+                | Argument::Kwargs(ref e) => self.expression(e),
+            }
+        }
+    }
+
+    /// Visits an expression assigned to.
+    fn target(&mut self, expr: &Expression) {
+        match *expr {
+            Expression::Name(ref name) => self.add_flags(name, LOCAL),
+            Expression::TupleLiteral(ref items) | Expression::ListLiteral(ref items) => {
+                for item in items {
+                    match *item {
+                        SetItem::Star(ref e) | SetItem::Unique(ref e) => self.target(e),
+                    }
+                }
+            }
+            Expression::Star(ref e) => self.target(e),
+            _ => self.expression(expr),
+        }
+    }
+
+    fn targets(&mut self, exprs: &[Expression]) {
+        for e in exprs {
+            self.target(e)
+        }
+    }
+
+    fn expressions(&mut self, exprs: &[Expression]) {
+        for e in exprs {
+            self.expression(e)
+        }
+    }
+
+    fn optional_expression(&mut self, e: &Option<Expression>) {
+        if let Some(ref e) = *e {
+            self.expression(e)
+        }
+    }
+
+    fn set_item(&mut self, item: &SetItem) {
+        match *item {
+            SetItem::Star(ref e) | SetItem::Unique(ref e) => self.expression(e),
+        }
+    }
+
+    fn dict_item(&mut self, item: &DictItem) {
+        match *item {
+            DictItem::Star(ref e) => self.expression(e),
+            DictItem::Unique(ref key, ref value) => {
+                self.expression(key);
+                self.expression(value)
+            }
+        }
+    }
+
+    /// Visits a comprehension, whose element is visited by `element`.
+    fn comprehension<F>(&mut self, kind: &'static str, element: F, chunks: &[ComprehensionChunk])
+    where
+        F: FnOnce(&mut Self),
+    {
+        let enter = move |c: &mut Context| {
+            c.comprehension = Some(kind);
+            // Generator expressions can be asynchronous anywhere
+            c.async_allowed |= kind == "generator expression"
+        };
+        self.with_scope(ScopeKind::Comprehension, |v| {
+            v.with_context(enter, element);
+            for (i, chunk) in chunks.iter().enumerate() {
+                match *chunk {
+                    ComprehensionChunk::If { ref cond } => {
+                        v.with_context(enter, |v| v.expression(cond))
+                    }
+                    ComprehensionChunk::For {
+                        async,
+                        ref item,
+                        ref iterator,
+                    } => {
+                        v.with_context(enter, |v| {
+                            if async {
+                                v.check_async(
+                                    "async",
+                                    PyParseError::AsyncComprehensionOutsideAsyncFunction,
+                                )
+                            }
+                            v.targets(item)
+                        });
+                        if i == 0 {
+                            // Evaluated in the enclosing scope
+                            v.in_enclosing_scope(|v| v.expression(iterator))
+                        } else {
+                            v.with_context(enter, |v| v.expression(iterator))
+                        }
+                    }
+                }
+            }
+        })
+    }
+
+    /// Finds the next `keyword` of an asynchronous construct, and
+    /// reports `error` there if it is not allowed (or the error of
+    /// asynchronous comprehensions, in a comprehension).
+    fn check_async(&mut self, keyword: &'static str, error: PyParseError) {
+        let context = self.context;
+        let error = match context.comprehension {
+            Some(_) => PyParseError::AsyncComprehensionOutsideAsyncFunction,
+            None => error,
+        };
+        self.check(keyword, !context.async_allowed, error)
+    }
+
+    fn await_(&mut self) {
+        let error = if self.context.in_function {
+            PyParseError::AwaitOutsideAsyncFunction
+        } else {
+            PyParseError::AwaitOutsideFunction
+        };
+        self.check_async("await", error)
+    }
+
+    fn yield_(&mut self, from: bool) {
+        let context = self.context;
+        let (invalid, error) = match context.comprehension {
+            Some(_) => (true, PyParseError::YieldInComprehension),
+            None if !context.in_function => (true, PyParseError::YieldOutsideFunction),
+            None => (
+                from && context.async_allowed,
+                PyParseError::YieldFromInAsyncFunction,
+            ),
+        };
+        self.check("yield", invalid, error)
+    }
+
+    fn expression(&mut self, expr: &Expression) {
+        match *expr {
+            Expression::Ellipsis
+            | Expression::None
+            | Expression::True
+            | Expression::False
+            | Expression::Int(_)
+            | Expression::ImaginaryInt(_)
+            | Expression::Float(_)
+            | Expression::ImaginaryFloat(_)
+            | Expression::String(_)
+            | Expression::Bytes(_) => (),
+            Expression::DictLiteral(ref items) => {
+                for item in items {
+                    self.dict_item(item)
+                }
+            }
+            Expression::SetLiteral(ref items)
+            | Expression::ListLiteral(ref items)
+            | Expression::TupleLiteral(ref items) => {
+                for item in items {
+                    self.set_item(item)
+                }
+            }
+            Expression::DictComp(ref item, ref chunks) => {
+                self.comprehension("dict comprehension", |v| v.dict_item(item), chunks)
+            }
+            Expression::SetComp(ref item, ref chunks) => {
+                self.comprehension("set comprehension", |v| v.set_item(item), chunks)
+            }
+            Expression::ListComp(ref item, ref chunks) => {
+                self.comprehension("list comprehension", |v| v.set_item(item), chunks)
+            }
+            Expression::Generator(ref item, ref chunks) => {
+                self.comprehension("generator expression", |v| v.set_item(item), chunks)
+            }
+            Expression::Name(ref name) => {
+                if name == "await" {
+                    // `await(x)` is parsed as a call
+                    self.await_()
+                } else {
+                    self.add_flags(name, USE)
+                }
+            }
+            Expression::Await(ref e) => {
+                self.await_();
+                self.expression(e)
+            }
+            Expression::Call(ref e, ref args) => {
+                self.expression(e);
+                self.arguments(args)
+            }
+            Expression::Subscript(ref e, ref subscripts) => {
+                self.expression(e);
+                for subscript in subscripts {
+                    match *subscript {
+                        Subscript::Simple(ref e) => self.expression(e),
+                        Subscript::Double(ref e1, ref e2) => {
+                            self.optional_expression(e1);
+                            self.optional_expression(e2)
+                        }
+                        Subscript::Triple(ref e1, ref e2, ref e3) => {
+                            self.optional_expression(e1);
+                            self.optional_expression(e2);
+                            self.optional_expression(e3)
+                        }
+                    }
+                }
+            }
+            Expression::Attribute(ref e, _) | Expression::Uop(_, ref e) => self.expression(e),
+            Expression::Star(ref e) => self.expression(e),
+            Expression::Bop(_, ref e1, ref e2) => {
+                self.expression(e1);
+                self.expression(e2)
+            }
+            Expression::Named(ref target, ref e) => {
+                if let Expression::Name(ref name) = **target {
+                    // Bound in the scope enclosing the comprehensions
+                    let scope = ::std::iter::once(&mut self.scope)
+                        .chain(self.enclosing_scopes.iter_mut().rev())
+                        .find(|scope| scope.kind != ScopeKind::Comprehension);
+                    if let Some(scope) = scope {
+                        *scope.names.entry(name.clone()).or_insert(0) |= LOCAL
+                    }
+                }
+                self.expression(e)
+            }
+            Expression::MultiBop(ref first, ref rest) => {
+                self.expression(first);
+                for (_, e) in rest {
+                    self.expression(e)
+                }
+            }
+            Expression::Ternary(ref e1, ref e2, ref e3) => {
+                self.expression(e1);
+                self.expression(e2);
+                self.expression(e3)
+            }
+            Expression::Yield(ref exprs) => {
+                self.yield_(false);
+                self.expressions(exprs)
+            }
+            Expression::YieldFrom(ref e) => {
+                self.yield_(true);
+                self.expression(e)
+            }
+            Expression::Lambdef(ref params, ref body) => {
+                let defaults = params
+                    .posonly_args
+                    .iter()
+                    .chain(&params.args)
+                    .chain(&params.keyword_args);
+                for (_, default) in defaults.clone() {
+                    self.optional_expression(default)
+                }
+                let mut names: Vec<&Name> = defaults.map(|(name, _)| name).collect();
+                if let StarParams::Named(ref name) = params.star_args {
+                    names.push(name)
+                }
+                if let Some(ref name) = params.star_kwargs {
+                    names.push(name)
+                }
+                self.with_context(
+                    |c| {
+                        *c = Context {
+                            in_function: true,
+                            in_loop: false,
+                            comprehension: None,
+                            async_allowed: false,
+                        }
+                    },
+                    |v| {
+                        v.with_scope(ScopeKind::Function, |v| {
+                            for name in names {
+                                v.add_flags(name, PARAM)
+                            }
+                            v.expression(body)
+                        })
+                    },
+                )
+            }
+        }
+    }
+}
+
+/// Checks the statements parsed from `code`, returning the problems
+/// CPython's compiler would reject the code for, in the order of the
+/// code:
+///
+/// * `return` and `yield` outside a function,
+/// * `yield` inside a comprehension or generator expression,
+/// * `break` and `continue` outside a loop,
+/// * `await`, `async for` and asynchronous comprehensions outside an
+///   `async def` (except generator expressions, which can be
+///   asynchronous anywhere), reported at their `await` or `async`,
+/// * `yield from` inside an `async def`,
+/// * `nonlocal` declarations at the top level or without a binding in
+///   an enclosing function,
+/// * `global` and `nonlocal` declarations of a parameter, or of a name
+///   used or assigned to before in the same scope, or declared both
+///   `global` and `nonlocal`.
+///
+/// `async with` is not supported by the parsers, so it is not checked.
+///
+/// The positions of the problems are found in `code`, so `stmts` must
+/// be the result of parsing it (or the positions are meaningless).
+///
+/// ```
+/// use python_parser::parse_module;
+/// use python_parser::validate::{validate, ValidationOptions};
+///
+/// let code = "def f():\n    return 1\nreturn 2\n";
+/// let module = parse_module(code).unwrap();
+/// let diagnostics = validate(code, &module.body, &ValidationOptions::default());
+/// assert_eq!(diagnostics.len(), 1);
+/// assert_eq!(diagnostics[0].to_string(), "'return' outside function at line 3");
+/// ```
+pub fn validate(code: &str, stmts: &[Statement], options: &ValidationOptions) -> Vec<Diagnostic> {
+    let mut keywords: HashMap<_, Vec<_>> = HashMap::new();
+    let mut end = (0, 1);
+    for token in tokenize(code) {
+        end = (token.offset, token.line);
+        if token.kind == TokenKind::Name {
+            if let Some(&keyword) = KEYWORDS.iter().find(|&&k| k == token.text) {
+                keywords.entry(keyword).or_default().push(token)
+            }
+        }
+    }
+    let mut validator = Validator {
+        options,
+        keywords: keywords
+            .into_iter()
+            .map(|(keyword, tokens)| (keyword, tokens.into_iter()))
+            .collect(),
+        end,
+        context: Context {
+            in_function: false,
+            in_loop: false,
+            comprehension: None,
+            async_allowed: options.top_level_await,
+        },
+        scope: Scope::new(ScopeKind::Module),
+        enclosing_scopes: Vec::new(),
+        diagnostics: Vec::new(),
+    };
+    validator.statements(stmts);
+    for (_, position) in mem::take(&mut validator.scope.free) {
+        validator.report(position, PyParseError::NonlocalWithoutBinding)
+    }
+    let mut diagnostics = validator.diagnostics;
+    diagnostics.sort_by_key(|d| d.error.offset);
+    limit_diagnostics(&mut diagnostics, |d| &mut d.error);
+    diagnostics
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parse_module;
+
+    /// Returns the errors found in `code`, with the line and column
+    /// (starting from 1) of each one.
+    fn errors(code: &str) -> Vec<(PyParseError, u32, usize)> {
+        let module = parse_module(code).unwrap();
+        validate(code, &module.body, &ValidationOptions::default())
+            .into_iter()
+            .map(|d| {
+                assert_eq!(d.severity, Severity::Error);
+                let line_start = code[..d.error.offset].rfind('\n').map_or(0, |i| i + 1);
+                (
+                    d.error.code.unwrap(),
+                    d.error.line,
+                    d.error.offset - line_start + 1,
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_return_yield() {
+        use errors::PyParseError::*;
+        assert_eq!(errors("return\n"), vec![(ReturnOutsideFunction, 1, 1)]);
+        assert_eq!(
+            errors("def f():\n    return (yield)\nclass A:\n    x = yield\n"),
+            vec![(YieldOutsideFunction, 4, 9)]
+        );
+        assert_eq!(errors("x = lambda: (yield)\n"), vec![]);
+        assert_eq!(
+            errors("def f():\n    [x for x in (yield)]\n    [(yield) for x in y]\n"),
+            vec![(YieldInComprehension, 3, 7)]
+        );
+        assert_eq!(
+            errors("def f():\n    (x for x in y if (yield))\n    {x: (yield) for x in y}\n"),
+            vec![(YieldInComprehension, 2, 23), (YieldInComprehension, 3, 10)]
+        );
+        assert_eq!(
+            errors("def f():\n    [lambda: (yield) for x in y]\n"),
+            vec![]
+        );
+    }
+
+    #[test]
+    fn test_break_continue() {
+        use errors::PyParseError::*;
+        assert_eq!(
+            errors("while x:\n    break\nelse:\n    break\n"),
+            vec![(BreakOutsideLoop, 4, 5)]
+        );
+        assert_eq!(
+            errors("for x in y:\n    def f():\n        break\n    class A:\n        continue\n"),
+            vec![(BreakOutsideLoop, 3, 9), (ContinueOutsideLoop, 5, 9)]
+        );
+        assert_eq!(
+            errors("for x in y:\n    try: pass\n    finally: continue\n    if x: break\n"),
+            vec![]
+        );
+    }
+
+    #[test]
+    fn test_async() {
+        use errors::PyParseError::*;
+        assert_eq!(
+            errors("await x\nclass A:\n    await x\ndef f():\n    await x\n"),
+            vec![
+                (AwaitOutsideFunction, 1, 1),
+                (AwaitOutsideFunction, 3, 5),
+                (AwaitOutsideAsyncFunction, 5, 5),
+            ]
+        );
+        assert_eq!(
+            errors(
+                "@d(await x)\nasync def f(a=await x):\n    await(x)\n    async for x in y: pass\n"
+            ),
+            vec![(AwaitOutsideFunction, 1, 4), (AwaitOutsideFunction, 2, 15)]
+        );
+        assert_eq!(
+            errors("async def f():\n    lambda: await x\n    def g():\n        async for x in y: pass\n"),
+            vec![(AwaitOutsideAsyncFunction, 2, 13), (AsyncForOutsideAsyncFunction, 4, 9)]
+        );
+        assert_eq!(
+            errors("async def f():\n    [await x for x in y]\n    yield from x\n"),
+            vec![(YieldFromInAsyncFunction, 3, 5)]
+        );
+    }
+
+    #[test]
+    fn test_async_comprehensions() {
+        use errors::PyParseError::*;
+        assert_eq!(
+            errors("def f():\n    [await x for x in y]\n    {x async for x in y}\n"),
+            vec![
+                (AsyncComprehensionOutsideAsyncFunction, 2, 6),
+                (AsyncComprehensionOutsideAsyncFunction, 3, 8),
+            ]
+        );
+        assert_eq!(
+            errors("(await x for x in y)\n([x async for x in y] for z in w)\n"),
+            vec![]
+        );
+        assert_eq!(
+            errors("def f():\n    (x for x in await y)\n"),
+            vec![(AwaitOutsideAsyncFunction, 2, 17)]
+        );
+    }
+
+    #[test]
+    fn test_top_level_await() {
+        let code = "await x\n[x async for x in y]\nasync for x in y: pass\nclass A:\n    await x\n";
+        let module = parse_module(code).unwrap();
+        let options = ValidationOptions {
+            top_level_await: true,
+            ..ValidationOptions::default()
+        };
+        let diagnostics = validate(code, &module.body, &options);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(
+            diagnostics[0].to_string(),
+            "'await' outside function at line 5"
+        );
+    }
+
+    #[test]
+    fn test_nonlocal() {
+        use errors::PyParseError::*;
+        assert_eq!(errors("nonlocal x\n"), vec![(NonlocalAtModuleLevel, 1, 1)]);
+        assert_eq!(
+            errors("def f():\n    x = 1\n    def g():\n        y = 2\n        nonlocal x, z\n"),
+            vec![(NonlocalWithoutBinding, 5, 9)]
+        );
+        assert_eq!(
+            errors("def f():\n    def g():\n        nonlocal x\n        def h():\n            nonlocal x\n    x = 1\n"),
+            vec![]
+        );
+        assert_eq!(
+            errors("def f():\n    global x\n    def g():\n        nonlocal x\n"),
+            vec![(NonlocalWithoutBinding, 4, 9)]
+        );
+        assert_eq!(
+            errors("def f():\n    x = 1\n    class A:\n        def g():\n            nonlocal x\n"),
+            vec![]
+        );
+        assert_eq!(
+            errors(
+                "def f():\n    class A:\n        x = 1\n        def g():\n            nonlocal x\n"
+            ),
+            vec![(NonlocalWithoutBinding, 5, 13)]
+        );
+        assert_eq!(
+            errors("def f():\n    [x for x in y]\n    def g():\n        nonlocal x\n"),
+            vec![(NonlocalWithoutBinding, 4, 9)]
+        );
+        assert_eq!(
+            errors("def f():\n    [(x := 1) for y in z]\n    import a.b\n    def g():\n        nonlocal x, a\n"),
+            vec![]
+        );
+    }
+
+    #[test]
+    fn test_declaration_conflicts() {
+        use errors::PyParseError::*;
+        assert_eq!(
+            errors("def f(a, *b):\n    pass;  global a\n    nonlocal b\n"),
+            vec![(ParameterAndGlobal, 2, 12), (ParameterAndNonlocal, 3, 5)]
+        );
+        assert_eq!(
+            errors("x = 1\nprint(x); global x\nclass A:\n    y\n    global y\n"),
+            vec![(UsedBeforeGlobal, 2, 11), (UsedBeforeGlobal, 5, 5)]
+        );
+        assert_eq!(
+            errors("def f():\n    x = 1\n    global x\n    y: int\n    global y\n"),
+            vec![(AssignedBeforeGlobal, 3, 5), (AnnotatedGlobal, 5, 5)]
+        );
+        assert_eq!(
+            errors("def f():\n    del x\n    y += 1\n    global x, y\n"),
+            vec![(AssignedBeforeGlobal, 4, 5), (AssignedBeforeGlobal, 4, 5)]
+        );
+        assert_eq!(
+            errors("def f():\n    [x for y in x]\n    lambda: z\n    global x, z\n"),
+            vec![(UsedBeforeGlobal, 4, 5)]
+        );
+        assert_eq!(
+            errors("def f():\n    x = 1\n    def g():\n        global x\n        nonlocal x\n"),
+            vec![(NonlocalAndGlobal, 4, 9)]
+        );
+        assert_eq!(
+            errors("def f():\n    import x.y\n    global x\n    global x\n    x = 1\n"),
+            vec![]
+        );
+    }
+
+    #[test]
+    fn test_warnings_only() {
+        let code = "yield\n";
+        let module = parse_module(code).unwrap();
+        let options = ValidationOptions {
+            warnings_only: true,
+            ..ValidationOptions::default()
+        };
+        let diagnostics = validate(code, &module.body, &options);
+        assert_eq!(diagnostics[0].severity, Severity::Warning);
+        assert_eq!(
+            diagnostics[0].to_string(),
+            "warning: 'yield' outside function at line 1"
+        );
+    }
+}
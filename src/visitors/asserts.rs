@@ -0,0 +1,178 @@
+//! Rewriting of `assert` statements into `if` statements raising an
+//! `AssertionError`, so the checks are still made when Python runs with
+//! `-O` (which removes the `assert` statements):
+//! `assert cond, msg` becomes `if not cond: raise AssertionError(msg)`.
+
+use std::mem;
+
+use ast::*;
+use project::Project;
+use visitors::walk_mut::{walk_statement_mut, VisitorMut};
+
+/// Which modules of a project to rewrite.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct AssertOptions {
+    /// Dotted names of the modules to rewrite, with their submodules.
+    /// All the modules are rewritten if empty.
+    pub modules: Vec<String>,
+    /// Only rewrite test modules (see `is_test_module`).
+    pub only_tests: bool,
+}
+
+impl AssertOptions {
+    /// Whether the module `name` is to be rewritten.
+    pub fn selects(&self, name: &str) -> bool {
+        let selected = self.modules.is_empty()
+            || self.modules.iter().any(|module| {
+                name == module
+                    || (name.starts_with(&module[..]) && name[module.len()..].starts_with('.'))
+            });
+        selected && (!self.only_tests || is_test_module(name))
+    }
+}
+
+/// Whether the module `name` is a test module, for pytest and unittest:
+/// a `conftest` module, a module named like `test_*` or `*_test`, or a
+/// module of a `test` or `tests` package.
+pub fn is_test_module(name: &str) -> bool {
+    let mut parts: Vec<_> = name.split('.').collect();
+    let last = parts.pop().unwrap_or("");
+    last == "conftest"
+        || last.starts_with("test_")
+        || last.ends_with("_test")
+        || parts.iter().any(|&part| part == "test" || part == "tests")
+}
+
+/// Returns the `if` statement equivalent to `assert cond, msg`.
+fn assert_to_raise(cond: Expression, msg: Option<Expression>) -> Statement {
+    let cond = match cond {
+        // `if not not x:` is `if x:`
+        Expression::Uop(Uop::Not, operand) => *operand,
+        cond => Expression::Uop(Uop::Not, Box::new(cond)),
+    };
+    let error = Expression::Name("AssertionError".to_string());
+    let exc = match msg {
+        Some(msg) => Expression::Call(Box::new(error), vec![Argument::Positional(msg)]),
+        None => error,
+    };
+    let raise = Statement::RaiseExc(exc);
+    Statement::Compound(Box::new(CompoundStatement::If(
+        vec![(cond, vec![raise])],
+        None,
+    )))
+}
+
+struct AssertRewriter {
+    rewritten: usize,
+}
+
+impl VisitorMut for AssertRewriter {
+    fn visit_statement(&mut self, stmt: &mut Statement) {
+        if let Statement::Assert(..) = *stmt {
+            if let Statement::Assert(cond, msg) = mem::replace(stmt, Statement::Pass) {
+                *stmt = assert_to_raise(cond, msg);
+                self.rewritten += 1;
+            }
+            return;
+        }
+        walk_statement_mut(self, stmt)
+    }
+}
+
+/// Rewrites the `assert` statements of `stmts` into `if` statements,
+/// in place, and returns their number.
+///
+/// ```
+/// use python_parser::parse_module;
+/// use python_parser::visitors::asserts::rewrite_asserts;
+///
+/// let mut module = parse_module("def f(x):\n    assert x > 0, 'negative'\n").unwrap();
+/// assert_eq!(rewrite_asserts(&mut module.body), 1);
+/// let expected = "def f(x):\n    if not x > 0:\n        raise AssertionError('negative')\n";
+/// assert_eq!(module, parse_module(expected).unwrap());
+/// ```
+pub fn rewrite_asserts(stmts: &mut Vec<Statement>) -> usize {
+    let mut rewriter = AssertRewriter { rewritten: 0 };
+    rewriter.visit_block(stmts);
+    rewriter.rewritten
+}
+
+/// Rewrites the `assert` statements of the modules of `project`
+/// selected by `options` (but not stubs), and returns their number.
+pub fn rewrite_project_asserts(project: &mut Project, options: &AssertOptions) -> usize {
+    let names: Vec<String> = project
+        .modules()
+        .map(|(name, _)| name.clone())
+        .filter(|name| options.selects(name))
+        .collect();
+    let mut rewritten = 0;
+    for name in names {
+        if let Some(module) = project.implementation_mut(&name) {
+            rewritten += rewrite_asserts(&mut module.module.body);
+        }
+    }
+    rewritten
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parse_module;
+
+    #[test]
+    fn test_rewrite() {
+        let mut module = parse_module(
+            "assert x
+class C:
+    def f(self):
+        if self.x:
+            assert not self.y, f'{self}'
+",
+        )
+        .unwrap();
+        assert_eq!(rewrite_asserts(&mut module.body), 2);
+        let expected = "if not x:
+    raise AssertionError
+class C:
+    def f(self):
+        if self.x:
+            if self.y:
+                raise AssertionError(f'{self}')
+";
+        assert_eq!(module, parse_module(expected).unwrap());
+    }
+
+    #[test]
+    fn test_selection() {
+        assert!(is_test_module("tests.helpers"));
+        assert!(is_test_module("pkg.test_utils"));
+        assert!(is_test_module("pkg.utils_test"));
+        assert!(is_test_module("conftest"));
+        assert!(!is_test_module("pkg.testing"));
+
+        let options = AssertOptions {
+            modules: vec!["pkg".to_string()],
+            only_tests: true,
+        };
+        assert!(options.selects("pkg.test_utils"));
+        assert!(!options.selects("pkg.utils"));
+        assert!(!options.selects("pkg2.test_utils"));
+        assert!(!options.selects("test_pkg"));
+        assert!(AssertOptions::default().selects("pkg.utils"));
+
+        let mut project = Project::new();
+        project.add_module("pkg", "assert a\n", true).unwrap();
+        project
+            .add_module("pkg.test_a", "assert a\n", false)
+            .unwrap();
+        project.add_module("test_b", "assert b\n", false).unwrap();
+        assert_eq!(rewrite_project_asserts(&mut project, &options), 1);
+        let module = &project.module("pkg.test_a").unwrap().module;
+        assert_eq!(
+            module.body,
+            parse_module("if not a:\n    raise AssertionError\n")
+                .unwrap()
+                .body
+        );
+    }
+}
@@ -0,0 +1,597 @@
+//! Conversion of the loops accumulating items into a list, set or dict
+//! into comprehensions, and back.
+//!
+//! `loops_to_comprehensions` replaces a loop found by `loops::loops`
+//! with a `ListAccumulation`, `SetAccumulation` or `DictBuilding`
+//! pattern, when it follows the initialization of the accumulator to an
+//! empty `[]`, `set()` or `{}`, and none of its variables escape:
+//!
+//! ```python
+//! result = []
+//! for item in items:
+//!     if item:
+//!         result.append(item * 2)
+//! ```
+//!
+//! becomes `result = [item * 2 for item in items if item]`, and
+//! `comprehensions_to_loops` does the opposite, for assignments of a
+//! list, set or dict comprehension to a name which it does not use.
+//! Since the variables of the loop outlive it, comprehensions are only
+//! expanded when their variables are not used anywhere else in the
+//! function (or the module), nor by the iterables and conditions before
+//! their loop, and the function must not use `locals`, `vars`, `dir`,
+//! `eval`, `exec` nor a `from m import *`.
+//!
+//! The loops run in the scope of the function, while comprehensions
+//! have their own scope, so the conversions are never made in class
+//! bodies (whose variables are not visible in comprehensions). They are
+//! made in the functions nested in classes, though.
+//!
+//! In Python 3.7, a dict comprehension computes the value of an item
+//! before its key, like `dict[key] = value`, but later versions compute
+//! the key first, so dicts are only converted when the key or the value
+//! has no side effect.
+
+use std::collections::HashMap;
+use std::slice;
+
+use ast::*;
+use visitors::dead_stores::{is_pure, ScopeInfo};
+use visitors::loops::{loops, LoopPattern};
+use visitors::target_names;
+use visitors::walk::{walk_expression, walk_statement, Visitor};
+use visitors::walk_mut::{walk_block_mut, walk_statement_mut, VisitorMut};
+
+/// Counts the occurrences of the names used or bound by statements and
+/// expressions.
+#[derive(Default)]
+struct NameCounter {
+    counts: HashMap<String, usize>,
+    /// Whether there is a `from m import *`.
+    star_import: bool,
+}
+
+impl NameCounter {
+    fn add(&mut self, name: &str) {
+        *self.counts.entry(name.to_string()).or_insert(0) += 1;
+    }
+
+    fn count(&self, name: &str) -> usize {
+        self.counts.get(name).cloned().unwrap_or(0)
+    }
+}
+
+impl Visitor for NameCounter {
+    fn visit_statement(&mut self, stmt: &Statement) {
+        match *stmt {
+            Statement::Global(ref names) | Statement::Nonlocal(ref names) => {
+                for name in names {
+                    self.add(name);
+                }
+            }
+            Statement::Import(Import::ImportFrom { ref names, .. }) => {
+                for (name, alias) in names {
+                    self.add(alias.as_ref().unwrap_or(name));
+                }
+            }
+            Statement::Import(Import::ImportStarFrom { .. }) => self.star_import = true,
+            Statement::Import(Import::Import { ref names }) => {
+                for (path, alias) in names {
+                    self.add(alias.as_ref().unwrap_or(&path[0]));
+                }
+            }
+            Statement::Compound(ref compound) => match **compound {
+                CompoundStatement::Funcdef(ref funcdef) => {
+                    self.add(&funcdef.name);
+                    let params = &funcdef.parameters;
+                    for param in params
+                        .posonly_args
+                        .iter()
+                        .chain(&params.args)
+                        .chain(&params.keyword_args)
+                    {
+                        self.add(&param.0);
+                    }
+                    if let StarParams::Named((ref name, _)) = params.star_args {
+                        self.add(name);
+                    }
+                    if let Some((ref name, _)) = params.star_kwargs {
+                        self.add(name);
+                    }
+                }
+                CompoundStatement::Classdef(ref classdef) => self.add(&classdef.name),
+                CompoundStatement::Try(ref try_) => {
+                    for (_, name, _) in &try_.except_clauses {
+                        if let Some(ref name) = *name {
+                            self.add(name);
+                        }
+                    }
+                }
+                _ => (),
+            },
+            _ => (),
+        }
+        walk_statement(self, stmt)
+    }
+
+    fn visit_expression(&mut self, expr: &Expression) {
+        match *expr {
+            Expression::Name(ref name) => self.add(name),
+            Expression::Lambdef(ref params, _) => {
+                for param in params
+                    .posonly_args
+                    .iter()
+                    .chain(&params.args)
+                    .chain(&params.keyword_args)
+                {
+                    self.add(&param.0);
+                }
+                if let StarParams::Named(ref name) = params.star_args {
+                    self.add(name);
+                }
+                if let Some(ref name) = params.star_kwargs {
+                    self.add(name);
+                }
+            }
+            _ => (),
+        }
+        walk_expression(self, expr)
+    }
+}
+
+fn count_names(stmts: &[Statement]) -> NameCounter {
+    let mut counter = NameCounter::default();
+    for stmt in stmts {
+        counter.visit_statement(stmt);
+    }
+    counter
+}
+
+/// The empty value an accumulator is initialized to before the loop,
+/// for its comprehension.
+fn empty_value(comprehension: &Expression) -> Expression {
+    match *comprehension {
+        Expression::ListComp(..) => Expression::ListLiteral(Vec::new()),
+        Expression::SetComp(..) => {
+            Expression::Call(Box::new(Expression::Name("set".to_string())), Vec::new())
+        }
+        Expression::DictComp(..) => Expression::DictLiteral(Vec::new()),
+        _ => unreachable!(),
+    }
+}
+
+fn assignment(name: &str, value: Expression) -> Statement {
+    Statement::Assignment(
+        small_list![Expression::Name(name.to_string())],
+        vec![small_list![value]],
+    )
+}
+
+/// Returns the name assigned by `stmt` and its value, if it is like
+/// `name = value`.
+fn name_assignment(stmt: &Statement) -> Option<(&str, &Expression)> {
+    match *stmt {
+        Statement::Assignment(ref lhs, ref rhs) if rhs.len() == 1 => {
+            match (&lhs[..], &rhs[0][..]) {
+                ([Expression::Name(ref name)], [ref value]) => Some((name, value)),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+/// Loops to be replaced, by address, with the name of their
+/// accumulator and its comprehension.
+type Conversions = HashMap<*const CompoundStatement, (String, Expression)>;
+
+/// Adds the loops of `code`, the body of a function or module, which
+/// can be converted to `conversions`.
+fn find_conversions(code: &[Statement], conversions: &mut Conversions) {
+    for info in loops(code) {
+        if !info.escaping.is_empty() {
+            continue;
+        }
+        let (is_async, item, iterator) = match *info.statement {
+            CompoundStatement::For {
+                async,
+                ref item,
+                ref iterator,
+                ..
+            } => (async, item, iterator),
+            _ => continue,
+        };
+        let iterator = match iterator[..] {
+            [ref iterator] => iterator.clone(),
+            _ => Expression::TupleLiteral(iterator.iter().cloned().map(SetItem::Unique).collect()),
+        };
+        let mut chunks = vec![ComprehensionChunk::For {
+            async: is_async,
+            item: item.clone(),
+            iterator,
+        }];
+        if let Some(cond) = info.pattern.condition() {
+            chunks.push(ComprehensionChunk::If { cond: cond.clone() });
+        }
+        let (accumulator, comprehension) = match info.pattern {
+            LoopPattern::ListAccumulation { list, element, .. } => (
+                list,
+                Expression::ListComp(Box::new(SetItem::Unique(element.clone())), chunks),
+            ),
+            LoopPattern::SetAccumulation { set, element, .. } => (
+                set,
+                Expression::SetComp(Box::new(SetItem::Unique(element.clone())), chunks),
+            ),
+            LoopPattern::DictBuilding {
+                dict, key, value, ..
+            } if is_pure(key) || is_pure(value) => (
+                dict,
+                Expression::DictComp(
+                    Box::new(DictItem::Unique(key.clone(), value.clone())),
+                    chunks,
+                ),
+            ),
+            _ => continue,
+        };
+        conversions.insert(info.statement, (accumulator.to_string(), comprehension));
+    }
+}
+
+struct LoopConverter {
+    conversions: Conversions,
+    converted: usize,
+}
+
+impl VisitorMut for LoopConverter {
+    fn visit_block(&mut self, stmts: &mut Vec<Statement>) {
+        walk_block_mut(self, stmts);
+        let mut i = 0;
+        while i + 1 < stmts.len() {
+            let replacement = match stmts[i + 1] {
+                Statement::Compound(ref compound) => {
+                    let ptr: *const CompoundStatement = &**compound;
+                    match (name_assignment(&stmts[i]), self.conversions.get(&ptr)) {
+                        (Some((name, value)), Some((accumulator, comprehension)))
+                            if name == accumulator && *value == empty_value(comprehension) =>
+                        {
+                            Some(assignment(name, comprehension.clone()))
+                        }
+                        _ => None,
+                    }
+                }
+                _ => None,
+            };
+            if let Some(replacement) = replacement {
+                stmts[i] = replacement;
+                stmts.remove(i + 1);
+                self.converted += 1;
+            }
+            i += 1;
+        }
+    }
+
+    fn visit_statement(&mut self, stmt: &mut Statement) {
+        if let Statement::Compound(ref compound) = *stmt {
+            if let CompoundStatement::Funcdef(ref funcdef) = **compound {
+                find_conversions(&funcdef.code, &mut self.conversions);
+            }
+        }
+        walk_statement_mut(self, stmt)
+    }
+}
+
+/// Replaces the loops building a list, set or dict of `stmts`, the
+/// body of a module, and of its functions with comprehensions, and
+/// returns the number of replaced loops. See the module documentation
+/// for the loops which are replaced.
+///
+/// ```
+/// use python_parser::parse_module;
+/// use python_parser::visitors::comprehensions::loops_to_comprehensions;
+///
+/// let mut module = parse_module("squares = {}\nfor i in range(10):\n    squares[i] = i * i\n").unwrap();
+/// assert_eq!(loops_to_comprehensions(&mut module.body), 1);
+/// assert_eq!(module, parse_module("squares = {i: i * i for i in range(10)}\n").unwrap());
+/// ```
+pub fn loops_to_comprehensions(stmts: &mut Vec<Statement>) -> usize {
+    let mut converter = LoopConverter {
+        conversions: Conversions::new(),
+        converted: 0,
+    };
+    find_conversions(stmts, &mut converter.conversions);
+    converter.visit_block(stmts);
+    converter.converted
+}
+
+/// Returns the loop computing `comprehension`, assigned to `name`, if
+/// it can be expanded.
+fn expand(name: &str, comprehension: &Expression, scope: &NameCounter) -> Option<Statement> {
+    let (accumulation, chunks) = match *comprehension {
+        Expression::ListComp(ref item, ref chunks) | Expression::SetComp(ref item, ref chunks) => {
+            let method = match *comprehension {
+                Expression::ListComp(..) => "append",
+                _ => "add",
+            };
+            let element = match **item {
+                SetItem::Unique(ref element) => element.clone(),
+                SetItem::Star(_) => return None,
+            };
+            let call = Expression::Call(
+                Box::new(Expression::Attribute(
+                    Box::new(Expression::Name(name.to_string())),
+                    method.to_string(),
+                )),
+                vec![Argument::Positional(element)],
+            );
+            (Statement::Assignment(small_list![call], Vec::new()), chunks)
+        }
+        Expression::DictComp(ref item, ref chunks) => match **item {
+            DictItem::Unique(ref key, ref value) if is_pure(key) || is_pure(value) => {
+                let target = Expression::Subscript(
+                    Box::new(Expression::Name(name.to_string())),
+                    vec![Subscript::Simple(key.clone())],
+                );
+                (
+                    Statement::Assignment(small_list![target], vec![small_list![value.clone()]]),
+                    chunks,
+                )
+            }
+            _ => return None,
+        },
+        _ => return None,
+    };
+    let own = count_names(slice::from_ref(&assignment(name, comprehension.clone())));
+    if own.count(name) != 1 {
+        return None;
+    }
+    // The iterables and conditions read so far: the loops bind their
+    // variables in the enclosing scope, so one read before (or by) the
+    // loop binding it, like `y` in `[x for x in y for y in z]`, would
+    // become an unbound local instead of reading the enclosing name.
+    let mut read = NameCounter::default();
+    let mut variables = Vec::new();
+    for chunk in chunks {
+        match *chunk {
+            ComprehensionChunk::For {
+                ref item,
+                ref iterator,
+                ..
+            } => {
+                read.visit_expression(iterator);
+                let start = variables.len();
+                for target in item {
+                    target_names(target, &mut variables);
+                }
+                if variables[start..]
+                    .iter()
+                    .any(|variable| read.count(variable) != 0)
+                {
+                    return None;
+                }
+            }
+            ComprehensionChunk::If { ref cond } => read.visit_expression(cond),
+        }
+    }
+    if variables
+        .iter()
+        .any(|variable| own.count(variable) != scope.count(variable))
+    {
+        return None;
+    }
+    let mut block = vec![accumulation];
+    for chunk in chunks.iter().rev() {
+        let stmt = match *chunk {
+            ComprehensionChunk::If { ref cond } => {
+                CompoundStatement::If(vec![(cond.clone(), block)], None)
+            }
+            ComprehensionChunk::For {
+                async,
+                ref item,
+                ref iterator,
+            } => CompoundStatement::For {
+                async,
+                item: item.clone(),
+                iterator: vec![iterator.clone()],
+                for_block: block,
+                else_block: None,
+            },
+        };
+        block = vec![Statement::Compound(Box::new(stmt))];
+    }
+    block.pop()
+}
+
+struct ComprehensionExpander {
+    /// The names of the enclosing functions (or module), or `None` in
+    /// class bodies and scopes where comprehensions are not expanded.
+    scopes: Vec<Option<NameCounter>>,
+    expanded: usize,
+}
+
+impl ComprehensionExpander {
+    fn push_scope(&mut self, code: &[Statement], stmts: &[Statement]) {
+        let counter = count_names(stmts);
+        if counter.star_import || ScopeInfo::of_block(code).dynamic {
+            self.scopes.push(None);
+        } else {
+            self.scopes.push(Some(counter));
+        }
+    }
+}
+
+impl VisitorMut for ComprehensionExpander {
+    fn visit_block(&mut self, stmts: &mut Vec<Statement>) {
+        walk_block_mut(self, stmts);
+        let scope = match self.scopes.last().and_then(Option::as_ref) {
+            Some(scope) => scope,
+            None => return,
+        };
+        let mut i = 0;
+        while i < stmts.len() {
+            let expanded = match name_assignment(&stmts[i]) {
+                Some((name, comprehension)) => expand(name, comprehension, scope)
+                    .map(|stmt| (assignment(name, empty_value(comprehension)), stmt)),
+                None => None,
+            };
+            if let Some((init, stmt)) = expanded {
+                stmts[i] = init;
+                stmts.insert(i + 1, stmt);
+                self.expanded += 1;
+                i += 1;
+            }
+            i += 1;
+        }
+    }
+
+    fn visit_statement(&mut self, stmt: &mut Statement) {
+        let is_scope = match *stmt {
+            Statement::Compound(ref compound) => match **compound {
+                CompoundStatement::Funcdef(ref funcdef) => {
+                    // The parameters are counted too
+                    self.push_scope(&funcdef.code, slice::from_ref(stmt));
+                    true
+                }
+                CompoundStatement::Classdef(_) => {
+                    self.scopes.push(None);
+                    true
+                }
+                _ => false,
+            },
+            _ => false,
+        };
+        walk_statement_mut(self, stmt);
+        if is_scope {
+            self.scopes.pop();
+        }
+    }
+}
+
+/// Replaces the assignments of a list, set or dict comprehension of
+/// `stmts`, the body of a module, and of its functions with loops, and
+/// returns the number of replaced comprehensions. See the module
+/// documentation for the comprehensions which are replaced.
+///
+/// ```
+/// use python_parser::parse_module;
+/// use python_parser::visitors::comprehensions::comprehensions_to_loops;
+///
+/// let mut module = parse_module("evens = {i for i in range(10) if i % 2 == 0}\n").unwrap();
+/// assert_eq!(comprehensions_to_loops(&mut module.body), 1);
+/// assert_eq!(
+///     module,
+///     parse_module("evens = set()\nfor i in range(10):\n    if i % 2 == 0:\n        evens.add(i)\n")
+///         .unwrap()
+/// );
+/// ```
+pub fn comprehensions_to_loops(stmts: &mut Vec<Statement>) -> usize {
+    let mut expander = ComprehensionExpander {
+        scopes: Vec::new(),
+        expanded: 0,
+    };
+    expander.push_scope(stmts, stmts);
+    expander.visit_block(stmts);
+    expander.expanded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parse_module;
+
+    /// Checks that converting the loops of `code` gives `expected`, and
+    /// that expanding its comprehensions gives back `code`.
+    fn check(code: &str, expected: &str, converted: usize) {
+        let mut module = parse_module(code).unwrap();
+        assert_eq!(loops_to_comprehensions(&mut module.body), converted);
+        assert_eq!(module, parse_module(expected).unwrap());
+        assert_eq!(comprehensions_to_loops(&mut module.body), converted);
+        assert_eq!(module, parse_module(code).unwrap());
+    }
+
+    #[test]
+    fn test_round_trip() {
+        check(
+            "async def f(items):
+    result = []
+    async for item in items:
+        if item:
+            result.append(item * 2)
+    names = set()
+    for (name, _) in items:
+        names.add(name)
+    d = {}
+    for k in items:
+        d[k] = g(k)
+    return result, names, d
+",
+            "async def f(items):
+    result = [item * 2 async for item in items if item]
+    names = {name for (name, _) in items}
+    d = {k: g(k) for k in items}
+    return result, names, d
+",
+            3,
+        );
+    }
+
+    #[test]
+    fn test_kept_loops() {
+        let code = "def f(items):
+    result = []
+    for item in items:
+        result.append(item)
+    print(item)
+    result = []
+    print(result)
+    for x in items:
+        result.append(x)
+    d = {}
+    for k in items:
+        d[g(k)] = h(k)
+    s = []
+    for y in items:
+        s.add(y)
+class C:
+    result = []
+    for item in items:
+        result.append(item)
+";
+        let mut module = parse_module(code).unwrap();
+        assert_eq!(loops_to_comprehensions(&mut module.body), 0);
+        assert_eq!(module, parse_module(code).unwrap());
+    }
+
+    #[test]
+    fn test_kept_comprehensions() {
+        let code = "def f(items):
+    x = [x for x in items]
+    y = [item for item in items]
+    item = 1
+    z = [a for a in items]
+    return locals()
+class C:
+    l = [i for i in range(10)]
+    def g(self):
+        l = [i for i in l]
+        n = {i: j for i in l for j in g(i)}
+        return n
+";
+        let mut module = parse_module(code).unwrap();
+        assert_eq!(comprehensions_to_loops(&mut module.body), 0);
+        assert_eq!(module, parse_module(code).unwrap());
+    }
+
+    #[test]
+    fn test_variables_read_by_iterables() {
+        // The loops would read the locals `y` and `w` before binding them
+        let code = "def f(z):
+    r = [x for x in y for y in z]
+    s = {w for w in w}
+    t = [a for a in z if b for b in a]
+    return r, s, t
+";
+        let mut module = parse_module(code).unwrap();
+        assert_eq!(comprehensions_to_loops(&mut module.body), 0);
+        assert_eq!(module, parse_module(code).unwrap());
+    }
+}
@@ -0,0 +1,785 @@
+//! Removal of the assignments to local variables of functions whose
+//! values are never read, found by a liveness analysis.
+//!
+//! The analysis is conservative. Only assignments like `x = value` are
+//! removed, where `x` is a local variable and `value` has no side
+//! effect: a literal (but not an f-string, nor a dict or set, whose
+//! items may be unhashable), a tuple or list of them, or a lambda. Names
+//! are not, as reading them may raise a `NameError`. In particular,
+//! these are never removed:
+//!
+//! * augmented assignments (`x += 1` may call `__iadd__`), annotated
+//!   assignments, and assignments to several targets or to attributes
+//!   and subscripts,
+//! * assignments to names starting with `_` (like the wildcard `_`),
+//! * assignments to names declared `global` or `nonlocal`, or used in a
+//!   nested function, class or lambda (which may read them later),
+//! * the last bindings of a variable which is not a parameter (the
+//!   variable would no longer be local, so reading it before it is
+//!   assigned would read a global instead of raising an
+//!   `UnboundLocalError`),
+//! * any assignment in a function using `locals`, `vars`, `dir`, `eval`
+//!   or `exec`, which may read all the variables.
+//!
+//! Any statement is assumed to possibly raise an exception, so the
+//! variables read by the `except` and `finally` blocks of a `try` (and
+//! after a `with` block, whose context manager may swallow exceptions)
+//! are live in all of its body.
+
+use std::collections::{HashMap, HashSet};
+use std::slice;
+
+use ast::*;
+use visitors::simplify::bound_names;
+use visitors::target_names;
+use visitors::walk::{walk_expression, walk_statement, Visitor};
+
+/// Functions which may read all the local variables.
+const DYNAMIC_FUNCTIONS: [&str; 5] = ["locals", "vars", "dir", "eval", "exec"];
+
+pub(crate) type Names = HashSet<String>;
+
+/// Collects the names used in statements and expressions.
+struct NameCollector {
+    names: Names,
+}
+
+impl Visitor for NameCollector {
+    fn visit_expression(&mut self, expr: &Expression) {
+        if let Expression::Name(ref name) = *expr {
+            self.names.insert(name.clone());
+        }
+        walk_expression(self, expr)
+    }
+}
+
+pub(crate) fn expression_names(exprs: &[Expression]) -> Names {
+    let mut collector = NameCollector {
+        names: Names::new(),
+    };
+    for expr in exprs {
+        collector.visit_expression(expr);
+    }
+    collector.names
+}
+
+fn statement_names(stmt: &Statement) -> Names {
+    let mut collector = NameCollector {
+        names: Names::new(),
+    };
+    collector.visit_statement(stmt);
+    collector.names
+}
+
+/// Names of a function body which may not be removed, and whether it
+/// uses one of the `DYNAMIC_FUNCTIONS`.
+#[derive(Default)]
+pub(crate) struct ScopeInfo {
+    /// Names declared `global` or `nonlocal`, or used in nested
+    /// functions, classes and lambdas.
+    pub(crate) excluded: Names,
+    pub(crate) dynamic: bool,
+    /// Number of nested functions, classes and lambdas being visited.
+    depth: usize,
+}
+
+impl ScopeInfo {
+    pub(crate) fn of_block(stmts: &[Statement]) -> ScopeInfo {
+        let mut info = ScopeInfo::default();
+        for stmt in stmts {
+            info.visit_statement(stmt);
+        }
+        info
+    }
+}
+
+impl Visitor for ScopeInfo {
+    fn visit_statement(&mut self, stmt: &Statement) {
+        match *stmt {
+            Statement::Global(ref names) | Statement::Nonlocal(ref names) if self.depth == 0 => {
+                self.excluded.extend(names.iter().cloned())
+            }
+            Statement::Compound(ref compound) => match **compound {
+                CompoundStatement::Funcdef(_) | CompoundStatement::Classdef(_) => {
+                    self.depth += 1;
+                    walk_statement(self, stmt);
+                    self.depth -= 1;
+                    return;
+                }
+                _ => (),
+            },
+            _ => (),
+        }
+        walk_statement(self, stmt)
+    }
+
+    fn visit_expression(&mut self, expr: &Expression) {
+        match *expr {
+            Expression::Name(ref name) => {
+                if self.depth > 0 {
+                    self.excluded.insert(name.clone());
+                }
+                if DYNAMIC_FUNCTIONS.contains(&&name[..]) {
+                    self.dynamic = true;
+                }
+            }
+            Expression::Lambdef(..) => {
+                self.depth += 1;
+                walk_expression(self, expr);
+                self.depth -= 1;
+                return;
+            }
+            _ => (),
+        }
+        walk_expression(self, expr)
+    }
+}
+
+/// Whether evaluating `expr` has no side effect (assuming its names are
+/// bound).
+pub(crate) fn is_pure(expr: &Expression) -> bool {
+    match *expr {
+        Expression::Ellipsis
+        | Expression::None
+        | Expression::True
+        | Expression::False
+        | Expression::Name(_)
+        | Expression::Int(_)
+        | Expression::ImaginaryInt(_)
+        | Expression::Float(_)
+        | Expression::ImaginaryFloat(_)
+        | Expression::Bytes(_) => true,
+        Expression::String(ref strings) => {
+            strings.iter().all(|s| !s.prefix.contains(&['f', 'F'][..]))
+        }
+        Expression::TupleLiteral(ref items) | Expression::ListLiteral(ref items) => {
+            items.iter().all(|item| match *item {
+                SetItem::Unique(ref e) => is_pure(e),
+                SetItem::Star(_) => false,
+            })
+        }
+        Expression::Lambdef(ref params, _) => params
+            .posonly_args
+            .iter()
+            .chain(&params.args)
+            .chain(&params.keyword_args)
+            .all(|(_, default)| default.as_ref().is_none_or(is_pure)),
+        _ => false,
+    }
+}
+
+/// Whether the assignment of `expr` may be removed: it has no side
+/// effect, and reads no name.
+fn is_removable_value(expr: &Expression) -> bool {
+    match *expr {
+        Expression::Name(_) => false,
+        Expression::TupleLiteral(ref items) | Expression::ListLiteral(ref items) => {
+            items.iter().all(|item| match *item {
+                SetItem::Unique(ref e) => is_removable_value(e),
+                SetItem::Star(_) => false,
+            })
+        }
+        Expression::Lambdef(ref params, _) => params
+            .posonly_args
+            .iter()
+            .chain(&params.args)
+            .chain(&params.keyword_args)
+            .all(|(_, default)| default.as_ref().is_none_or(is_removable_value)),
+        _ => is_pure(expr),
+    }
+}
+
+/// Counts the bindings of the names of a function body, but not of its
+/// nested functions and classes.
+#[derive(Default)]
+struct Bindings {
+    counts: HashMap<String, usize>,
+}
+
+impl Bindings {
+    fn add(&mut self, names: Vec<&str>) {
+        for name in names {
+            *self.counts.entry(name.to_string()).or_insert(0) += 1;
+        }
+    }
+}
+
+impl Visitor for Bindings {
+    fn visit_statement(&mut self, stmt: &Statement) {
+        let mut names = Vec::new();
+        bound_names(stmt, &mut names);
+        self.add(names);
+        if let Statement::Compound(ref compound) = *stmt {
+            match **compound {
+                CompoundStatement::Funcdef(_) | CompoundStatement::Classdef(_) => return,
+                _ => (),
+            }
+        }
+        walk_statement(self, stmt)
+    }
+
+    fn visit_expression(&mut self, expr: &Expression) {
+        match *expr {
+            Expression::Named(ref target, _) => {
+                let mut names = Vec::new();
+                target_names(target, &mut names);
+                self.add(names);
+            }
+            Expression::Lambdef(..) => return,
+            _ => (),
+        }
+        walk_expression(self, expr)
+    }
+}
+
+/// Variables live where the control flow may jump to.
+#[derive(Clone, Default)]
+struct Jumps {
+    /// After the enclosing loop.
+    break_: Names,
+    /// At the start of the enclosing loop.
+    continue_: Names,
+    /// After `return` (only in `finally` blocks).
+    return_: Names,
+    /// In the handler of an exception raised at any point.
+    exception: Names,
+}
+
+fn union(mut a: Names, b: &Names) -> Names {
+    a.extend(b.iter().cloned());
+    a
+}
+
+/// Backward liveness analysis of a function body.
+struct Liveness<'i> {
+    info: &'i ScopeInfo,
+    /// Assignments found dead, by address, with the name they assign.
+    dead: HashMap<*const Statement, String>,
+}
+
+impl<'i> Liveness<'i> {
+    /// Returns the name assigned by `stmt` and the assigned expressions,
+    /// if it is like `x = value`.
+    fn store(stmt: &Statement) -> Option<(&str, &[Expression])> {
+        match *stmt {
+            Statement::Assignment(ref lhs, ref rhs) if rhs.len() == 1 => match lhs[..] {
+                [Expression::Name(ref name)] => Some((name, &rhs[0])),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// Whether the store of `value` to `name` may be removed when the
+    /// name is not live after it.
+    fn is_removable(&self, name: &str, value: &[Expression]) -> bool {
+        !name.starts_with('_')
+            && !self.info.excluded.contains(name)
+            && value.iter().all(is_removable_value)
+    }
+
+    /// Returns the variables live before `stmts`, given the ones live
+    /// after them. If `mark`, records the dead assignments.
+    fn block(&mut self, stmts: &[Statement], live: Names, jumps: &Jumps, mark: bool) -> Names {
+        let mut live = live;
+        for stmt in stmts.iter().rev() {
+            live = self.statement(stmt, live, jumps, mark);
+            live = union(live, &jumps.exception);
+        }
+        live
+    }
+
+    fn statement(&mut self, stmt: &Statement, live: Names, jumps: &Jumps, mark: bool) -> Names {
+        if let Some((name, value)) = Liveness::store(stmt) {
+            if mark && !live.contains(name) && self.is_removable(name, value) {
+                self.dead.insert(stmt, name.to_string());
+            }
+            let mut live = live;
+            live.remove(name);
+            return union(live, &expression_names(value));
+        }
+        match *stmt {
+            Statement::Return(_) => union(statement_names(stmt), &jumps.return_),
+            Statement::Break => jumps.break_.clone(),
+            Statement::Continue => jumps.continue_.clone(),
+            Statement::Raise | Statement::RaiseExc(_) | Statement::RaiseExcFrom(_, _) => {
+                union(statement_names(stmt), &jumps.exception)
+            }
+            Statement::Compound(ref compound) => self.compound(stmt, compound, live, jumps, mark),
+            _ => union(live, &statement_names(stmt)),
+        }
+    }
+
+    /// Returns the variables live at the start of a loop, whose body
+    /// continues to the start (where `head` are live) and breaks to
+    /// `after`.
+    fn loop_head(
+        &mut self,
+        body: &[Statement],
+        head: Names,
+        after: &Names,
+        jumps: &Jumps,
+        mark: bool,
+    ) -> Names {
+        let mut head = head;
+        loop {
+            let body_jumps = Jumps {
+                break_: after.clone(),
+                continue_: head.clone(),
+                ..jumps.clone()
+            };
+            let new_head = union(self.block(body, head.clone(), &body_jumps, false), &head);
+            if new_head == head {
+                if mark {
+                    self.block(body, head.clone(), &body_jumps, true);
+                }
+                return head;
+            }
+            head = new_head;
+        }
+    }
+
+    fn compound(
+        &mut self,
+        stmt: &Statement,
+        compound: &CompoundStatement,
+        live: Names,
+        jumps: &Jumps,
+        mark: bool,
+    ) -> Names {
+        match *compound {
+            CompoundStatement::If(ref cond_blocks, ref else_block) => {
+                let mut result = match *else_block {
+                    Some(ref block) => self.block(block, live.clone(), jumps, mark),
+                    None => live.clone(),
+                };
+                for (cond, block) in cond_blocks.iter().rev() {
+                    result = union(result, &self.block(block, live.clone(), jumps, mark));
+                    result = union(result, &expression_names(slice::from_ref(cond)));
+                }
+                result
+            }
+            CompoundStatement::While(ref cond, ref block, ref else_block) => {
+                let after_else = match *else_block {
+                    Some(ref block) => self.block(block, live.clone(), jumps, mark),
+                    None => live.clone(),
+                };
+                let head = union(after_else, &expression_names(slice::from_ref(cond)));
+                self.loop_head(block, head, &live, jumps, mark)
+            }
+            CompoundStatement::For {
+                ref item,
+                ref iterator,
+                ref for_block,
+                ref else_block,
+                ..
+            } => {
+                let after_else = match *else_block {
+                    Some(ref block) => self.block(block, live.clone(), jumps, mark),
+                    None => live.clone(),
+                };
+                // The targets are not removable, so they are only uses
+                let head = union(after_else, &expression_names(item));
+                let head = self.loop_head(for_block, head, &live, jumps, mark);
+                union(head, &expression_names(iterator))
+            }
+            CompoundStatement::With(ref contexts, ref block) => {
+                // The context manager may swallow exceptions
+                let body_jumps = Jumps {
+                    exception: union(jumps.exception.clone(), &live),
+                    ..jumps.clone()
+                };
+                let result = self.block(block, live, &body_jumps, mark);
+                let contexts: Vec<_> = contexts
+                    .iter()
+                    .flat_map(|(context, target)| Some(context).into_iter().chain(target))
+                    .cloned()
+                    .collect();
+                union(result, &expression_names(&contexts))
+            }
+            CompoundStatement::Try(ref try_) => {
+                // Jumps out of the `try` go through the `finally` block,
+                // and are then resumed
+                let (after_finally, inner_jumps) = if try_.finally_block.is_empty() {
+                    (live, jumps.clone())
+                } else {
+                    let resumed = union(live, &jumps.exception);
+                    let resumed = union(resumed, &jumps.return_);
+                    let resumed = union(resumed, &jumps.break_);
+                    let resumed = union(resumed, &jumps.continue_);
+                    let finally = self.block(&try_.finally_block, resumed, jumps, mark);
+                    let inner_jumps = Jumps {
+                        break_: union(jumps.break_.clone(), &finally),
+                        continue_: union(jumps.continue_.clone(), &finally),
+                        return_: union(jumps.return_.clone(), &finally),
+                        exception: union(jumps.exception.clone(), &finally),
+                    };
+                    (finally, inner_jumps)
+                };
+                let mut handlers = if try_.last_except.is_empty() {
+                    // Exceptions not caught are propagated
+                    inner_jumps.exception.clone()
+                } else {
+                    self.block(&try_.last_except, after_finally.clone(), &inner_jumps, mark)
+                };
+                for (exc, _, block) in &try_.except_clauses {
+                    let block = self.block(block, after_finally.clone(), &inner_jumps, mark);
+                    handlers = union(handlers, &block);
+                    handlers = union(handlers, &expression_names(slice::from_ref(exc)));
+                }
+                let after_try = self.block(&try_.else_block, after_finally, &inner_jumps, mark);
+                let try_jumps = Jumps {
+                    exception: handlers,
+                    ..inner_jumps
+                };
+                self.block(&try_.try_block, after_try, &try_jumps, mark)
+            }
+            CompoundStatement::Funcdef(_) | CompoundStatement::Classdef(_) => {
+                union(live, &statement_names(stmt))
+            }
+        }
+    }
+}
+
+/// Removes the statements of `dead` from `stmts` and the blocks of
+/// its compound statements (but not nested functions and classes).
+/// Blocks left empty get a `pass` statement.
+fn remove_statements(stmts: &mut Vec<Statement>, dead: &HashSet<*const Statement>) {
+    let was_empty = stmts.is_empty();
+    stmts.retain(|stmt| !dead.contains(&(stmt as *const Statement)));
+    if stmts.is_empty() && !was_empty {
+        stmts.push(Statement::Pass);
+    }
+    for stmt in stmts {
+        let compound = match *stmt {
+            Statement::Compound(ref mut compound) => compound,
+            _ => continue,
+        };
+        match **compound {
+            CompoundStatement::If(ref mut cond_blocks, ref mut else_block) => {
+                for (_, block) in cond_blocks {
+                    remove_statements(block, dead);
+                }
+                if let Some(ref mut block) = *else_block {
+                    remove_statements(block, dead);
+                }
+            }
+            CompoundStatement::For {
+                ref mut for_block,
+                ref mut else_block,
+                ..
+            } => {
+                remove_statements(for_block, dead);
+                if let Some(ref mut block) = *else_block {
+                    remove_statements(block, dead);
+                }
+            }
+            CompoundStatement::While(_, ref mut block, ref mut else_block) => {
+                remove_statements(block, dead);
+                if let Some(ref mut block) = *else_block {
+                    remove_statements(block, dead);
+                }
+            }
+            CompoundStatement::With(_, ref mut block) => remove_statements(block, dead),
+            CompoundStatement::Try(ref mut try_) => {
+                remove_statements(&mut try_.try_block, dead);
+                for (_, _, block) in &mut try_.except_clauses {
+                    remove_statements(block, dead);
+                }
+                remove_statements(&mut try_.last_except, dead);
+                remove_statements(&mut try_.else_block, dead);
+                remove_statements(&mut try_.finally_block, dead);
+            }
+            CompoundStatement::Funcdef(_) | CompoundStatement::Classdef(_) => (),
+        }
+    }
+}
+
+/// Returns the names of the parameters of a function.
+fn parameter_names(params: &TypedArgsList) -> Names {
+    let mut names: Names = params
+        .posonly_args
+        .iter()
+        .chain(&params.args)
+        .chain(&params.keyword_args)
+        .map(|param| param.0.clone())
+        .collect();
+    if let StarParams::Named((ref name, _)) = params.star_args {
+        names.insert(name.clone());
+    }
+    if let Some((ref name, _)) = params.star_kwargs {
+        names.insert(name.clone());
+    }
+    names
+}
+
+/// Removes the dead stores of a function body, until there is none
+/// left (removing one may make others dead), and returns their number.
+fn remove_in_function(funcdef: &mut Funcdef) -> usize {
+    let parameters = parameter_names(&funcdef.parameters);
+    let code = &mut funcdef.code;
+    let mut removed = 0;
+    loop {
+        let info = ScopeInfo::of_block(code);
+        if info.dynamic {
+            return removed;
+        }
+        let mut liveness = Liveness {
+            info: &info,
+            dead: HashMap::new(),
+        };
+        liveness.block(code, Names::new(), &Jumps::default(), true);
+        let mut bindings = Bindings::default();
+        for stmt in code.iter() {
+            bindings.visit_statement(stmt);
+        }
+        let mut dead_counts = HashMap::new();
+        for name in liveness.dead.values() {
+            *dead_counts.entry(name).or_insert(0) += 1;
+        }
+        // The variables which are not parameters keep at least one
+        // binding, to stay local
+        let dead: HashSet<_> = liveness
+            .dead
+            .iter()
+            .filter(|(_, name)| {
+                parameters.contains(*name) || bindings.counts[*name] > dead_counts[name]
+            })
+            .map(|(stmt, _)| *stmt)
+            .collect();
+        if dead.is_empty() {
+            return removed;
+        }
+        removed += dead.len();
+        remove_statements(code, &dead);
+    }
+}
+
+/// Removes the assignments whose values are never read from the
+/// functions of `stmts` (and their nested functions), and returns the
+/// number of removed assignments. See the module documentation for the
+/// ones that are never removed.
+///
+/// ```
+/// use python_parser::parse_module;
+/// use python_parser::visitors::dead_stores::remove_dead_stores;
+///
+/// let mut module = parse_module("def f(a):\n    b = 1\n    b = a\n    return b\n").unwrap();
+/// assert_eq!(remove_dead_stores(&mut module.body), 1);
+/// assert_eq!(module, parse_module("def f(a):\n    b = a\n    return b\n").unwrap());
+/// ```
+pub fn remove_dead_stores(stmts: &mut [Statement]) -> usize {
+    let mut removed = 0;
+    for stmt in stmts {
+        let compound = match *stmt {
+            Statement::Compound(ref mut compound) => compound,
+            _ => continue,
+        };
+        match **compound {
+            CompoundStatement::Funcdef(ref mut funcdef) => {
+                removed += remove_in_function(funcdef);
+                removed += remove_dead_stores(&mut funcdef.code);
+            }
+            CompoundStatement::Classdef(ref mut classdef) => {
+                removed += remove_dead_stores(&mut classdef.code)
+            }
+            CompoundStatement::If(ref mut cond_blocks, ref mut else_block) => {
+                for (_, block) in cond_blocks {
+                    removed += remove_dead_stores(block);
+                }
+                if let Some(ref mut block) = *else_block {
+                    removed += remove_dead_stores(block);
+                }
+            }
+            CompoundStatement::For {
+                ref mut for_block,
+                ref mut else_block,
+                ..
+            } => {
+                removed += remove_dead_stores(for_block);
+                if let Some(ref mut block) = *else_block {
+                    removed += remove_dead_stores(block);
+                }
+            }
+            CompoundStatement::While(_, ref mut block, ref mut else_block) => {
+                removed += remove_dead_stores(block);
+                if let Some(ref mut block) = *else_block {
+                    removed += remove_dead_stores(block);
+                }
+            }
+            CompoundStatement::With(_, ref mut block) => removed += remove_dead_stores(block),
+            CompoundStatement::Try(ref mut try_) => {
+                removed += remove_dead_stores(&mut try_.try_block);
+                for (_, _, block) in &mut try_.except_clauses {
+                    removed += remove_dead_stores(block);
+                }
+                removed += remove_dead_stores(&mut try_.last_except);
+                removed += remove_dead_stores(&mut try_.else_block);
+                removed += remove_dead_stores(&mut try_.finally_block);
+            }
+        }
+    }
+    removed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parse_module;
+
+    /// Checks that removing the dead stores of `code` gives `expected`.
+    fn check(code: &str, expected: &str, removed: usize) {
+        let mut module = parse_module(code).unwrap();
+        assert_eq!(remove_dead_stores(&mut module.body), removed);
+        assert_eq!(module, parse_module(expected).unwrap());
+    }
+
+    #[test]
+    fn test_straight_line() {
+        check(
+            "x = 1
+def f(a):
+    b = 1
+    c = (1, [None, 'x'])
+    if a:
+        c = 2
+        b = 3
+    b = 4
+    c = 5
+    a = 6
+    return b + c
+class C:
+    def g(self):
+        e = lambda: 1
+        e = 2
+        return e
+",
+            "x = 1
+def f(a):
+    if a:
+        pass
+    b = 4
+    c = 5
+    return b + c
+class C:
+    def g(self):
+        e = 2
+        return e
+",
+            6,
+        );
+    }
+
+    #[test]
+    fn test_kept() {
+        let code = "def f(a, b):
+    global g
+    g = 1
+    _ = 1
+    a += 1
+    b: int = 1
+    c = d = 1
+    a.x = 1
+    x = a()
+    x = f'{a}'
+    x = {a: 1}
+    y = 1
+    return lambda: y
+def h(a):
+    a = 1
+    return locals()
+def k():
+    print(x)
+    x = 1
+    y = undefined_name
+    z = 1
+    z = 2
+";
+        check(code, code, 0);
+    }
+
+    #[test]
+    fn test_loops() {
+        check(
+            "def f(items):
+    i = 0
+    x = 0
+    for item in items:
+        print(i)
+        i = 1
+        x = 1
+        x = 2
+        print(x)
+        if item:
+            j = 1
+            continue
+        j = 2
+    while True:
+        k = 1
+        if items:
+            break
+        print(k)
+    return j
+",
+            "def f(items):
+    i = 0
+    for item in items:
+        print(i)
+        i = 1
+        x = 2
+        print(x)
+        if item:
+            j = 1
+            continue
+        j = 2
+    while True:
+        k = 1
+        if items:
+            break
+        print(k)
+    return j
+",
+            2,
+        );
+    }
+
+    #[test]
+    fn test_exceptions() {
+        check(
+            "def f():
+    x = 1
+    try:
+        x = 2
+        g()
+        x = 3
+    except E:
+        return x
+    finally:
+        y = 4
+    z = 5
+    with m:
+        z = 6
+        g()
+        z = 7
+    return z
+",
+            "def f():
+    x = 1
+    try:
+        x = 2
+        g()
+    except E:
+        return x
+    finally:
+        y = 4
+    z = 5
+    with m:
+        z = 6
+        g()
+        z = 7
+    return z
+",
+            1,
+        );
+    }
+}
@@ -0,0 +1,936 @@
+//! A textual dump of the AST, like Python's `ast.dump`, for golden tests:
+//! `f(1)` is
+//! `Assignment(targets=[Call(func=Name('f'), args=[Positional(Int(1))])], values=[])`.
+//!
+//! Nodes are written with the names of their variants, and their fields
+//! as `name=value`, except for nodes of a single field, whose value is
+//! written alone. Nodes without fields are written like `Pass()`, lists
+//! like `[a, b]`, tuples like `(a, b)`, missing optional values as
+//! `None`, and strings like in Python.
+//!
+//! `debug_tree` writes the same nodes as a tree, with the spans of the
+//! statements, to explore how some code is parsed.
+
+use ast::*;
+use errors::ParseError;
+use helpers::make_strspan;
+use lazy::{lazy_statements_spanned, LazyStatement};
+use parse_module;
+use span::Span;
+
+/// A node, or a value of one of its fields.
+enum Value {
+    /// A variant or structure, with its fields. The names of the fields
+    /// are ignored for nodes with a single field.
+    Node(&'static str, Vec<(&'static str, Value)>),
+    List(Vec<Value>),
+    Tuple(Vec<Value>),
+    /// A name, number, string, etc.
+    Leaf(String),
+}
+
+impl Value {
+    /// Returns whether the value is written on a single line even when
+    /// indenting: when it has no non-empty list.
+    fn is_simple(&self) -> bool {
+        match *self {
+            Value::Leaf(_) => true,
+            Value::List(ref items) => items.is_empty(),
+            Value::Node(_, ref fields) => fields.iter().all(|&(_, ref v)| v.is_simple()),
+            Value::Tuple(ref items) => items.iter().all(Value::is_simple),
+        }
+    }
+}
+
+fn node(name: &'static str, fields: Vec<(&'static str, Value)>) -> Value {
+    Value::Node(name, fields)
+}
+
+/// A node with a single field.
+fn wrap(name: &'static str, value: Value) -> Value {
+    Value::Node(name, vec![("", value)])
+}
+
+fn leaf<T: ToString>(value: T) -> Value {
+    Value::Leaf(value.to_string())
+}
+
+fn list<T, F: Fn(&T) -> Value>(items: &[T], f: F) -> Value {
+    Value::List(items.iter().map(f).collect())
+}
+
+fn option<T, F: Fn(&T) -> Value>(value: &Option<T>, f: F) -> Value {
+    match *value {
+        Some(ref value) => f(value),
+        None => leaf("None"),
+    }
+}
+
+fn boolean(value: bool) -> Value {
+    leaf(if value { "True" } else { "False" })
+}
+
+#[cfg(feature = "wtf8")]
+fn code_points(content: &PyStringContent) -> Vec<u32> {
+    content.code_points().map(|c| c.to_u32()).collect()
+}
+
+#[cfg(not(feature = "wtf8"))]
+fn code_points(content: &PyStringContent) -> Vec<u32> {
+    content.chars().map(|c| c as u32).collect()
+}
+
+/// Writes a string like the `repr` of Python, with single quotes.
+fn repr_code_points<I: IntoIterator<Item = u32>>(code_points: I) -> String {
+    let mut s = "'".to_string();
+    for n in code_points {
+        match ::std::char::from_u32(n) {
+            Some('\'') => s.push_str("\\'"),
+            Some('\\') => s.push_str("\\\\"),
+            Some('\n') => s.push_str("\\n"),
+            Some('\r') => s.push_str("\\r"),
+            Some('\t') => s.push_str("\\t"),
+            Some(c) if c < ' ' || c == '\x7f' => s.push_str(&format!("\\x{:02x}", n)),
+            Some(c) => s.push(c),
+            // Lone surrogate
+            None => s.push_str(&format!("\\u{:04x}", n)),
+        }
+    }
+    s.push('\'');
+    s
+}
+
+fn string(s: &str) -> Value {
+    Value::Leaf(repr_code_points(s.chars().map(|c| c as u32)))
+}
+
+fn bytes(b: &[u8]) -> Value {
+    let mut s = "b'".to_string();
+    for &byte in b {
+        match byte {
+            b'\'' => s.push_str("\\'"),
+            b'\\' => s.push_str("\\\\"),
+            b'\n' => s.push_str("\\n"),
+            b'\r' => s.push_str("\\r"),
+            b'\t' => s.push_str("\\t"),
+            0x20..=0x7e => s.push(byte as char),
+            _ => s.push_str(&format!("\\x{:02x}", byte)),
+        }
+    }
+    s.push('\'');
+    Value::Leaf(s)
+}
+
+fn names(names: &[Name]) -> Value {
+    list(names, |name| string(name))
+}
+
+fn star_params<T, F: Fn(&T) -> Value>(params: &StarParams<T>, f: F) -> Value {
+    match *params {
+        StarParams::No => node("No", vec![]),
+        StarParams::Anonymous => node("Anonymous", vec![]),
+        StarParams::Named(ref param) => wrap("Named", f(param)),
+    }
+}
+
+fn typed_param(param: &(Name, Option<Expression>, Option<Expression>)) -> Value {
+    let (ref name, ref annotation, ref default) = *param;
+    Value::Tuple(vec![
+        string(name),
+        option(annotation, expression),
+        option(default, expression),
+    ])
+}
+
+fn typed_args(params: &TypedArgsList) -> Value {
+    node(
+        "TypedArgsList",
+        vec![
+            ("posonly_args", list(&params.posonly_args, typed_param)),
+            ("args", list(&params.args, typed_param)),
+            (
+                "star_args",
+                star_params(&params.star_args, |&(ref name, ref annotation)| {
+                    Value::Tuple(vec![string(name), option(annotation, expression)])
+                }),
+            ),
+            ("keyword_args", list(&params.keyword_args, typed_param)),
+            (
+                "star_kwargs",
+                option(&params.star_kwargs, |&(ref name, ref annotation)| {
+                    Value::Tuple(vec![string(name), option(annotation, expression)])
+                }),
+            ),
+        ],
+    )
+}
+
+fn untyped_args(params: &UntypedArgsList) -> Value {
+    let param = |&(ref name, ref default): &(Name, Option<Expression>)| {
+        Value::Tuple(vec![string(name), option(default, expression)])
+    };
+    node(
+        "UntypedArgsList",
+        vec![
+            ("posonly_args", list(&params.posonly_args, param)),
+            ("args", list(&params.args, param)),
+            ("star_args", star_params(&params.star_args, |n| string(n))),
+            ("keyword_args", list(&params.keyword_args, param)),
+            ("star_kwargs", option(&params.star_kwargs, |n| string(n))),
+        ],
+    )
+}
+
+fn decorator(decorator: &Decorator) -> Value {
+    node(
+        "Decorator",
+        vec![
+            ("name", names(&decorator.name)),
+            ("args", option(&decorator.args, |args| list(args, argument))),
+        ],
+    )
+}
+
+fn argument(arg: &Argument) -> Value {
+    match *arg {
+        Argument::Positional(ref e) => wrap("Positional", expression(e)),
+        Argument::Starargs(ref e) => wrap("Starargs", expression(e)),
+        Argument::Keyword(ref name, ref e) => node(
+            "Keyword",
+            vec![("name", string(name)), ("value", expression(e))],
+        ),
+        Argument::Kwargs(ref e) => wrap("Kwargs", expression(e)),
+    }
+}
+
+fn subscript(sub: &Subscript) -> Value {
+    let bound = |e: &Option<Expression>| option(e, expression);
+    match *sub {
+        Subscript::Simple(ref e) => wrap("Simple", expression(e)),
+        Subscript::Double(ref start, ref end) => {
+            node("Double", vec![("start", bound(start)), ("end", bound(end))])
+        }
+        Subscript::Triple(ref start, ref end, ref step) => node(
+            "Triple",
+            vec![
+                ("start", bound(start)),
+                ("end", bound(end)),
+                ("step", bound(step)),
+            ],
+        ),
+    }
+}
+
+fn dict_item(item: &DictItem) -> Value {
+    match *item {
+        DictItem::Star(ref e) => wrap("Star", expression(e)),
+        DictItem::Unique(ref key, ref value) => node(
+            "Unique",
+            vec![("key", expression(key)), ("value", expression(value))],
+        ),
+    }
+}
+
+fn set_item(item: &SetItem) -> Value {
+    match *item {
+        SetItem::Star(ref e) => wrap("Star", expression(e)),
+        SetItem::Unique(ref e) => wrap("Unique", expression(e)),
+    }
+}
+
+fn comprehension_chunk(chunk: &ComprehensionChunk) -> Value {
+    match *chunk {
+        ComprehensionChunk::If { ref cond } => wrap("If", expression(cond)),
+        ComprehensionChunk::For {
+            async,
+            ref item,
+            ref iterator,
+        } => node(
+            "For",
+            vec![
+                ("async", boolean(async)),
+                ("item", list(item, expression)),
+                ("iterator", expression(iterator)),
+            ],
+        ),
+    }
+}
+
+fn comprehension(name: &'static str, element: Value, chunks: &[ComprehensionChunk]) -> Value {
+    node(
+        name,
+        vec![
+            ("element", element),
+            ("chunks", list(chunks, comprehension_chunk)),
+        ],
+    )
+}
+
+fn expression(expr: &Expression) -> Value {
+    let e = |e: &Expression| expression(e);
+    match *expr {
+        Expression::Ellipsis => node("Ellipsis", vec![]),
+        Expression::None => node("None", vec![]),
+        Expression::True => node("True", vec![]),
+        Expression::False => node("False", vec![]),
+        Expression::Name(ref name) => wrap("Name", string(name)),
+        Expression::Int(ref n) => wrap("Int", leaf(n)),
+        Expression::ImaginaryInt(ref n) => wrap("ImaginaryInt", leaf(n)),
+        Expression::Float(n) => wrap("Float", leaf(format!("{:?}", n))),
+        Expression::ImaginaryFloat(n) => wrap("ImaginaryFloat", leaf(format!("{:?}", n))),
+        Expression::String(ref strings) => wrap(
+            "String",
+            list(strings, |s| {
+                let mut fields = vec![
+                    ("prefix", string(&s.prefix)),
+                    (
+                        "content",
+                        Value::Leaf(repr_code_points(code_points(&s.content))),
+                    ),
+                ];
+                // Only with the `decoding` policies keeping the text
+                if let Some(ref raw) = s.raw {
+                    fields.push(("raw", string(raw)));
+                }
+                node("PyString", fields)
+            }),
+        ),
+        Expression::Bytes(ref b) => wrap("Bytes", bytes(b)),
+        Expression::DictLiteral(ref items) => wrap("DictLiteral", list(items, dict_item)),
+        Expression::SetLiteral(ref items) => wrap("SetLiteral", list(items, set_item)),
+        Expression::ListLiteral(ref items) => wrap("ListLiteral", list(items, set_item)),
+        Expression::TupleLiteral(ref items) => wrap("TupleLiteral", list(items, set_item)),
+        Expression::DictComp(ref item, ref chunks) => {
+            comprehension("DictComp", dict_item(item), chunks)
+        }
+        Expression::SetComp(ref item, ref chunks) => {
+            comprehension("SetComp", set_item(item), chunks)
+        }
+        Expression::ListComp(ref item, ref chunks) => {
+            comprehension("ListComp", set_item(item), chunks)
+        }
+        Expression::Generator(ref item, ref chunks) => {
+            comprehension("Generator", set_item(item), chunks)
+        }
+        Expression::Await(ref value) => wrap("Await", e(value)),
+        Expression::Call(ref func, ref args) => node(
+            "Call",
+            vec![("func", e(func)), ("args", list(args, argument))],
+        ),
+        Expression::Subscript(ref value, ref subs) => node(
+            "Subscript",
+            vec![("value", e(value)), ("subscripts", list(subs, subscript))],
+        ),
+        Expression::Attribute(ref value, ref attr) => node(
+            "Attribute",
+            vec![("value", e(value)), ("attr", string(attr))],
+        ),
+        Expression::Uop(op, ref operand) => node(
+            "Uop",
+            vec![("op", leaf(format!("{:?}", op))), ("operand", e(operand))],
+        ),
+        Expression::Bop(op, ref left, ref right) => node(
+            "Bop",
+            vec![
+                ("op", leaf(format!("{:?}", op))),
+                ("left", e(left)),
+                ("right", e(right)),
+            ],
+        ),
+        Expression::MultiBop(ref left, ref rest) => node(
+            "MultiBop",
+            vec![
+                ("left", e(left)),
+                (
+                    "rest",
+                    list(rest, |&(op, ref right)| {
+                        Value::Tuple(vec![leaf(format!("{:?}", op)), e(right)])
+                    }),
+                ),
+            ],
+        ),
+        Expression::Ternary(ref body, ref test, ref orelse) => node(
+            "Ternary",
+            vec![("body", e(body)), ("test", e(test)), ("orelse", e(orelse))],
+        ),
+        Expression::Yield(ref values) => wrap("Yield", list(values, e)),
+        Expression::YieldFrom(ref value) => wrap("YieldFrom", e(value)),
+        Expression::Star(ref value) => wrap("Star", e(value)),
+        Expression::Lambdef(ref params, ref body) => node(
+            "Lambdef",
+            vec![("parameters", untyped_args(params)), ("body", e(body))],
+        ),
+        Expression::Named(ref target, ref value) => {
+            node("Named", vec![("target", e(target)), ("value", e(value))])
+        }
+    }
+}
+
+fn import(import: &Import) -> Value {
+    match *import {
+        Import::ImportFrom {
+            leading_dots,
+            ref path,
+            ref names,
+        } => node(
+            "ImportFrom",
+            vec![
+                ("leading_dots", leaf(leading_dots)),
+                ("path", self::names(path)),
+                (
+                    "names",
+                    list(names, |&(ref name, ref alias)| {
+                        Value::Tuple(vec![string(name), option(alias, |a| string(a))])
+                    }),
+                ),
+            ],
+        ),
+        Import::ImportStarFrom {
+            leading_dots,
+            ref path,
+        } => node(
+            "ImportStarFrom",
+            vec![
+                ("leading_dots", leaf(leading_dots)),
+                ("path", self::names(path)),
+            ],
+        ),
+        Import::Import { ref names } => wrap(
+            "Import",
+            list(names, |&(ref path, ref alias)| {
+                Value::Tuple(vec![self::names(path), option(alias, |a| string(a))])
+            }),
+        ),
+    }
+}
+
+fn block(stmts: &[Statement]) -> Value {
+    list(stmts, statement)
+}
+
+fn expressions(exprs: &[Expression]) -> Value {
+    list(exprs, expression)
+}
+
+fn compound_statement(stmt: &CompoundStatement) -> Value {
+    let else_block = |b: &Option<Vec<Statement>>| option(b, |b| block(b));
+    match *stmt {
+        CompoundStatement::If(ref branches, ref else_) => node(
+            "If",
+            vec![
+                (
+                    "branches",
+                    list(branches, |&(ref cond, ref body)| {
+                        Value::Tuple(vec![expression(cond), block(body)])
+                    }),
+                ),
+                ("else_block", else_block(else_)),
+            ],
+        ),
+        CompoundStatement::For {
+            async,
+            ref item,
+            ref iterator,
+            ref for_block,
+            else_block: ref else_,
+        } => node(
+            "For",
+            vec![
+                ("async", boolean(async)),
+                ("item", expressions(item)),
+                ("iterator", expressions(iterator)),
+                ("for_block", block(for_block)),
+                ("else_block", else_block(else_)),
+            ],
+        ),
+        CompoundStatement::While(ref cond, ref body, ref else_) => node(
+            "While",
+            vec![
+                ("cond", expression(cond)),
+                ("block", block(body)),
+                ("else_block", else_block(else_)),
+            ],
+        ),
+        CompoundStatement::With(ref items, ref body) => node(
+            "With",
+            vec![
+                (
+                    "items",
+                    list(items, |&(ref context, ref target)| {
+                        Value::Tuple(vec![expression(context), option(target, expression)])
+                    }),
+                ),
+                ("block", block(body)),
+            ],
+        ),
+        CompoundStatement::Funcdef(ref funcdef) => node(
+            "Funcdef",
+            vec![
+                ("async", boolean(funcdef.async)),
+                ("decorators", list(&funcdef.decorators, decorator)),
+                ("name", string(&funcdef.name)),
+                ("parameters", typed_args(&funcdef.parameters)),
+                ("return_type", option(&funcdef.return_type, expression)),
+                ("code", block(&funcdef.code)),
+            ],
+        ),
+        CompoundStatement::Classdef(ref classdef) => node(
+            "Classdef",
+            vec![
+                ("decorators", list(&classdef.decorators, decorator)),
+                ("name", string(&classdef.name)),
+                ("arguments", list(&classdef.arguments, argument)),
+                ("code", block(&classdef.code)),
+            ],
+        ),
+        CompoundStatement::Try(ref try_) => node(
+            "Try",
+            vec![
+                ("try_block", block(&try_.try_block)),
+                (
+                    "except_clauses",
+                    list(&try_.except_clauses, |&(ref exc, ref name, ref body)| {
+                        Value::Tuple(vec![
+                            expression(exc),
+                            option(name, |n| string(n)),
+                            block(body),
+                        ])
+                    }),
+                ),
+                ("last_except", block(&try_.last_except)),
+                ("else_block", block(&try_.else_block)),
+                ("finally_block", block(&try_.finally_block)),
+            ],
+        ),
+    }
+}
+
+fn statement(stmt: &Statement) -> Value {
+    match *stmt {
+        Statement::Pass => node("Pass", vec![]),
+        Statement::Del(ref exprs) => wrap("Del", expressions(exprs)),
+        Statement::Break => node("Break", vec![]),
+        Statement::Continue => node("Continue", vec![]),
+        Statement::Return(ref exprs) => wrap("Return", expressions(exprs)),
+        Statement::RaiseExcFrom(ref exc, ref cause) => node(
+            "RaiseExcFrom",
+            vec![("exc", expression(exc)), ("cause", expression(cause))],
+        ),
+        Statement::RaiseExc(ref exc) => wrap("RaiseExc", expression(exc)),
+        Statement::Raise => node("Raise", vec![]),
+        Statement::Global(ref n) => wrap("Global", names(n)),
+        Statement::Nonlocal(ref n) => wrap("Nonlocal", names(n)),
+        Statement::Assert(ref test, ref msg) => node(
+            "Assert",
+            vec![("test", expression(test)), ("msg", option(msg, expression))],
+        ),
+        Statement::Import(ref i) => import(i),
+        Statement::Expressions(ref exprs) => wrap("Expressions", expressions(exprs)),
+        Statement::Assignment(ref targets, ref values) => node(
+            "Assignment",
+            vec![
+                ("targets", expressions(targets)),
+                ("values", list(values, |v| expressions(v))),
+            ],
+        ),
+        Statement::TypeAnnotation(ref targets, ref annotation) => node(
+            "TypeAnnotation",
+            vec![
+                ("targets", expressions(targets)),
+                ("annotation", expression(annotation)),
+            ],
+        ),
+        Statement::TypedAssignment(ref targets, ref annotation, ref value) => node(
+            "TypedAssignment",
+            vec![
+                ("targets", expressions(targets)),
+                ("annotation", expression(annotation)),
+                ("value", expressions(value)),
+            ],
+        ),
+        Statement::AugmentedAssignment(ref targets, op, ref value) => node(
+            "AugmentedAssignment",
+            vec![
+                ("targets", expressions(targets)),
+                ("op", leaf(format!("{:?}", op))),
+                ("value", expressions(value)),
+            ],
+        ),
+        Statement::Compound(ref stmt) => compound_statement(stmt),
+        Statement::Invalid(ref code) => wrap("Invalid", string(code)),
+        Statement::Extension(ref name, ref code) => node(
+            "Extension",
+            vec![("name", string(name)), ("code", string(code))],
+        ),
+    }
+}
+
+fn push_indent(indent: usize, s: &mut String) {
+    s.push('\n');
+    for _ in 0..indent {
+        s.push(' ');
+    }
+}
+
+/// Writes `value`, indenting its children by `indent` spaces more than
+/// `level` if `indent` is not `None`.
+fn write(value: &Value, indent: Option<usize>, level: usize, s: &mut String) {
+    let (open, close, items): (&str, &str, Vec<(&str, &Value)>) = match *value {
+        Value::Leaf(ref leaf) => return s.push_str(leaf),
+        Value::Node(name, ref fields) => {
+            s.push_str(name);
+            if fields.len() == 1 {
+                // Written alone, without its name, and without a line
+                // break, like `Return([`
+                s.push('(');
+                write(&fields[0].1, indent, level, s);
+                return s.push(')');
+            }
+            let items = fields.iter().map(|&(name, ref v)| (name, v)).collect();
+            ("(", ")", items)
+        }
+        Value::List(ref items) => ("[", "]", items.iter().map(|v| ("", v)).collect()),
+        Value::Tuple(ref items) => ("(", ")", items.iter().map(|v| ("", v)).collect()),
+    };
+    s.push_str(open);
+    let indent = if value.is_simple() { None } else { indent };
+    for (i, &(name, value)) in items.iter().enumerate() {
+        if i > 0 {
+            s.push(',');
+            if indent.is_none() {
+                s.push(' ');
+            }
+        }
+        if let Some(indent) = indent {
+            push_indent(level + indent, s);
+        }
+        if !name.is_empty() {
+            s.push_str(name);
+            s.push('=');
+        }
+        write(value, indent, level + indent.unwrap_or(0), s);
+    }
+    if let Value::Tuple(ref items) = *value {
+        if items.len() == 1 {
+            s.push(',');
+        }
+    }
+    s.push_str(close);
+}
+
+/// Returns a dump of `stmts`, with one statement per line, or with the
+/// fields of the nodes on their own lines, indented by `indent` spaces,
+/// if `indent` is not `None` (nodes whose fields are only names,
+/// numbers, etc. are still on a single line).
+///
+/// The dump only depends on the AST, so it can be compared to the
+/// expected dump of some code in tests.
+///
+/// ```
+/// use python_parser::parse_module;
+/// use python_parser::visitors::dump::dump;
+///
+/// let module = parse_module("print(x, sep='')\nreturn\n").unwrap();
+/// assert_eq!(
+///     dump(&module.body, None),
+///     "Assignment(targets=[Call(func=Name('print'), args=[Positional(Name('x')), \
+///      Keyword(name='sep', value=String([PyString(prefix='', content='')]))])], \
+///      values=[])\nReturn([])"
+/// );
+/// assert_eq!(
+///     dump(&module.body[..1], Some(2)),
+///     "Assignment(
+///   targets=[
+///     Call(
+///       func=Name('print'),
+///       args=[
+///         Positional(Name('x')),
+///         Keyword(
+///           name='sep',
+///           value=String([
+///             PyString(prefix='', content='')]))])],
+///   values=[])"
+/// );
+/// ```
+pub fn dump(stmts: &[Statement], indent: Option<usize>) -> String {
+    let mut s = String::new();
+    for (i, stmt) in stmts.iter().enumerate() {
+        if i > 0 {
+            s.push('\n');
+        }
+        write(&statement(stmt), indent, 0, &mut s);
+    }
+    s
+}
+
+/// Maximum number of characters of the literals of `debug_tree`.
+const MAX_LITERAL: usize = 30;
+
+/// Returns `leaf`, cut after `MAX_LITERAL` characters, keeping its
+/// closing quote.
+fn truncate(leaf: &str) -> String {
+    match leaf.char_indices().nth(MAX_LITERAL) {
+        None => leaf.to_string(),
+        Some((end, _)) => {
+            let quote = if leaf.ends_with('\'') { "'" } else { "" };
+            format!("{}…{}", &leaf[..end], quote)
+        }
+    }
+}
+
+fn truncate_leaves(value: &mut Value) {
+    match *value {
+        Value::Leaf(ref mut leaf) => *leaf = truncate(leaf),
+        Value::Node(_, ref mut fields) => {
+            for &mut (_, ref mut value) in fields {
+                truncate_leaves(value)
+            }
+        }
+        Value::List(ref mut items) | Value::Tuple(ref mut items) => {
+            for item in items {
+                truncate_leaves(item)
+            }
+        }
+    }
+}
+
+/// The span of a statement, with the spans of the statements of its
+/// body if it is a function or class definition.
+struct SpanTree {
+    span: Span,
+    body: Vec<SpanTree>,
+}
+
+fn span_trees(stmts: Vec<(Span, LazyStatement)>) -> Vec<SpanTree> {
+    stmts
+        .into_iter()
+        .map(|(span, stmt)| {
+            let body = match stmt {
+                LazyStatement::Statement(_) => Vec::new(),
+                LazyStatement::Funcdef(_, body) | LazyStatement::Classdef(_, body) => body
+                    .parse_lazy_spanned()
+                    .map(span_trees)
+                    .unwrap_or_default(),
+            };
+            SpanTree { span, body }
+        })
+        .collect()
+}
+
+/// Writes `value` on a line starting with `first` followed by `label`,
+/// and its children on lines starting with `prefix`. `span` is the span
+/// of the value if it is a statement, and `body` the spans of its body
+/// (or of its items if it is a list of statements).
+fn write_tree(
+    value: &Value,
+    first: &str,
+    label: &str,
+    prefix: &str,
+    span: Option<Span>,
+    body: &[SpanTree],
+    s: &mut String,
+) {
+    s.push_str(first);
+    s.push_str(label);
+    let mut children: Vec<(&str, &Value, Option<Span>, &[SpanTree])> = Vec::new();
+    if value.is_simple() {
+        write(value, None, 0, s);
+    } else {
+        match *value {
+            Value::Leaf(_) => unreachable!(),
+            Value::Node(name, ref fields) => {
+                s.push_str(name);
+                match fields[..] {
+                    // Written without its name, like `Return` followed
+                    // by the returned expressions
+                    [(_, Value::List(ref items))] => {
+                        children.extend(items.iter().map(|item| ("", item, None, &[][..])))
+                    }
+                    [(_, ref value)] => children.push(("", value, None, &[])),
+                    _ => {
+                        for &(name, ref value) in fields {
+                            let body = if name == "code" { body } else { &[] };
+                            children.push((name, value, None, body));
+                        }
+                    }
+                }
+            }
+            Value::List(ref items) | Value::Tuple(ref items) => {
+                if label.is_empty() {
+                    s.push_str(match *value {
+                        Value::List(_) => "list",
+                        _ => "tuple",
+                    });
+                }
+                for (i, item) in items.iter().enumerate() {
+                    let tree = body.get(i);
+                    let body = tree.map_or(&[][..], |tree| &tree.body[..]);
+                    children.push(("", item, tree.map(|tree| tree.span), body));
+                }
+            }
+        }
+    }
+    if let Some(span) = span {
+        s.push_str(&format!(" [{}..{}]", span.start, span.end));
+    }
+    s.push('\n');
+    for (i, &(name, value, span, body)) in children.iter().enumerate() {
+        let (connector, indent) = if i + 1 == children.len() {
+            ("└── ", "    ")
+        } else {
+            ("├── ", "│   ")
+        };
+        let label = match (name.is_empty(), value.is_simple(), value) {
+            (true, _, _) => String::new(),
+            // A list of nodes is only written with the name of its field
+            (false, false, &Value::List(_)) => name.to_string(),
+            (false, _, _) => format!("{}: ", name),
+        };
+        write_tree(
+            value,
+            &format!("{}{}", prefix, connector),
+            &label,
+            &format!("{}{}", prefix, indent),
+            span,
+            body,
+            s,
+        );
+    }
+}
+
+/// Parses `code`, and returns its AST as a tree, with the nodes of
+/// `dump`, the byte ranges of the statements (except of the statements
+/// of the blocks of `if`, `for`, etc.), and literals cut after 30
+/// characters.
+///
+/// ```
+/// use python_parser::visitors::dump::debug_tree;
+///
+/// assert_eq!(
+///     debug_tree("def f(x):\n    return x + 1\n").unwrap(),
+///     "Module
+/// └── Funcdef [0..26]
+///     ├── async: False
+///     ├── decorators: []
+///     ├── name: 'f'
+///     ├── parameters: TypedArgsList
+///     │   ├── posonly_args: []
+///     │   ├── args
+///     │   │   └── ('x', None, None)
+///     │   ├── star_args: No()
+///     │   ├── keyword_args: []
+///     │   └── star_kwargs: None
+///     ├── return_type: None
+///     └── code
+///         └── Return [14..26]
+///             └── Bop(op=Add, left=Name('x'), right=Int(1))
+/// "
+/// );
+/// ```
+pub fn debug_tree(code: &str) -> Result<String, ParseError> {
+    let module = parse_module(code)?;
+    let spans = lazy_statements_spanned(make_strspan(code), 0)
+        .map(span_trees)
+        .unwrap_or_default();
+    let mut stmts = Value::List(module.body.iter().map(statement).collect());
+    truncate_leaves(&mut stmts);
+    let mut s = String::new();
+    write_tree(&stmts, "", "Module", "", None, &spans, &mut s);
+    Ok(s)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parse_module;
+
+    fn dumped(code: &str) -> String {
+        dump(&parse_module(code).unwrap().body, None)
+    }
+
+    #[test]
+    fn test_dump() {
+        assert_eq!(
+            dumped("from .a import b as c\nimport d.e\nglobal f\n"),
+            "ImportFrom(leading_dots=1, path=['a'], names=[('b', 'c')])\n\
+             Import([(['d', 'e'], None)])\n\
+             Global(['f'])"
+        );
+        assert_eq!(
+            dumped("x = y = -1.5, b'\\x00\\'', 'it\\'s\\n'\n"),
+            "Assignment(targets=[Name('x')], values=[[Name('y')], \
+             [Uop(op=Minus, operand=Float(1.5)), Bytes(b'\\x00\\''), \
+             String([PyString(prefix='', content='it\\'s\\n')])]])"
+        );
+        assert_eq!(
+            dumped("a < b <= c if d else (e := None)\n"),
+            "Assignment(targets=[Ternary(body=MultiBop(left=Name('a'), \
+             rest=[(Lt, Name('b')), (Leq, Name('c'))]), test=Name('d'), \
+             orelse=Named(target=Name('e'), value=None()))], values=[])"
+        );
+        assert_eq!(
+            dumped("@d\ndef f(a, *, b: int = 1) -> g:\n    pass\n"),
+            "Funcdef(async=False, decorators=[Decorator(name=['d'], args=None)], name='f', \
+             parameters=TypedArgsList(posonly_args=[], args=[('a', None, None)], \
+             star_args=Anonymous(), keyword_args=[('b', Name('int'), Int(1))], \
+             star_kwargs=None), return_type=Name('g'), code=[Pass()])"
+        );
+    }
+
+    #[test]
+    fn test_indent() {
+        let code = "if x:\n    y[1:] = (z,)\nelse:\n    pass\n";
+        let expected = "If(
+    branches=[
+        (
+            Name('x'),
+            [
+                Assignment(
+                    targets=[
+                        Subscript(
+                            value=Name('y'),
+                            subscripts=[
+                                Double(start=Int(1), end=None)])],
+                    values=[
+                        [
+                            TupleLiteral([
+                                Unique(Name('z'))])]])])],
+    else_block=[
+        Pass()])";
+        assert_eq!(dump(&parse_module(code).unwrap().body, Some(4)), expected);
+    }
+
+    #[test]
+    fn test_debug_tree() {
+        let code = "class A:\n    x = 1; y = 'a long string of more than thirty characters'\nif x:\n    pass\n";
+        let expected = "Module
+├── Classdef [0..70]
+│   ├── decorators: []
+│   ├── name: 'A'
+│   ├── arguments: []
+│   └── code
+│       ├── Assignment [13..70]
+│       │   ├── targets
+│       │   │   └── Name('x')
+│       │   └── values
+│       │       └── list
+│       │           └── Int(1)
+│       └── Assignment [13..70]
+│           ├── targets
+│           │   └── Name('y')
+│           └── values
+│               └── list
+│                   └── String
+│                       └── PyString(prefix='', content='a long string of more than th…')
+└── If [71..85]
+    ├── branches
+    │   └── tuple
+    │       ├── Name('x')
+    │       └── list
+    │           └── Pass()
+    └── else_block: None
+";
+        assert_eq!(debug_tree(code).unwrap(), expected);
+        assert!(debug_tree("x = (\n").is_err());
+    }
+}
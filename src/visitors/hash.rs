@@ -0,0 +1,638 @@
+//! Structural hashing of the AST, to fingerprint modules or functions.
+
+use ast::*;
+use visitors::walk::*;
+
+/// FNV-1a hasher, whose result does not depend on the platform.
+pub(crate) struct Fnv(u64);
+
+impl Fnv {
+    pub(crate) fn new() -> Fnv {
+        Fnv(0xcbf2_9ce4_8422_2325)
+    }
+
+    pub(crate) fn update(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 ^= u64::from(byte);
+            self.0 = self.0.wrapping_mul(0x100_0000_01b3);
+        }
+    }
+
+    pub(crate) fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+/// Visitor feeding the nodes it visits to a `Fnv` hasher.
+///
+/// Each node is hashed as a tag, specific to its variant, followed by
+/// its names, operators and literals, the number of its children and
+/// which of its optional children are present; then its children are
+/// walked. The hash thus only depends on the structure of the AST, not
+/// on the features of this crate nor on how the nodes are stored.
+pub struct StableHasher(Fnv);
+
+impl StableHasher {
+    fn tag(&mut self, tag: u8) {
+        self.0.update(&[tag])
+    }
+
+    fn len(&mut self, len: usize) {
+        self.0.update(&(len as u64).to_le_bytes())
+    }
+
+    fn flag(&mut self, flag: bool) {
+        self.tag(flag as u8)
+    }
+
+    fn bytes(&mut self, bytes: &[u8]) {
+        self.len(bytes.len());
+        self.0.update(bytes)
+    }
+
+    fn str(&mut self, s: &str) {
+        self.bytes(s.as_bytes())
+    }
+
+    fn names(&mut self, names: &[Name]) {
+        self.len(names.len());
+        for name in names {
+            self.str(name);
+        }
+    }
+
+    fn optional_name(&mut self, name: &Option<Name>) {
+        self.flag(name.is_some());
+        if let Some(ref name) = *name {
+            self.str(name);
+        }
+    }
+
+    fn optional_block(&mut self, block: &Option<Vec<Statement>>) {
+        self.flag(block.is_some());
+        if let Some(ref block) = *block {
+            self.len(block.len());
+        }
+    }
+
+    /// Integers are hashed as their decimal digits, which are the same
+    /// with and without the `bigint` feature.
+    fn int(&mut self, n: &IntegerType) {
+        self.str(&n.to_string())
+    }
+
+    /// Strings are hashed as their code points, which are the same
+    /// with and without the `wtf8` feature.
+    fn string(&mut self, s: &PyString) {
+        self.str(&s.prefix);
+        #[cfg(feature = "wtf8")]
+        let code_points: Vec<u32> = s.content.code_points().map(|c| c.to_u32()).collect();
+        #[cfg(not(feature = "wtf8"))]
+        let code_points: Vec<u32> = s.content.chars().map(|c| c as u32).collect();
+        self.len(code_points.len());
+        for c in code_points {
+            self.0.update(&c.to_le_bytes());
+        }
+        self.optional_name(&s.raw);
+    }
+
+    fn import(&mut self, import: &Import) {
+        match *import {
+            Import::ImportFrom {
+                leading_dots,
+                ref path,
+                ref names,
+            } => {
+                self.tag(0);
+                self.len(leading_dots);
+                self.names(path);
+                self.len(names.len());
+                for (name, as_name) in names {
+                    self.str(name);
+                    self.optional_name(as_name);
+                }
+            }
+            Import::ImportStarFrom {
+                leading_dots,
+                ref path,
+            } => {
+                self.tag(1);
+                self.len(leading_dots);
+                self.names(path);
+            }
+            Import::Import { ref names } => {
+                self.tag(2);
+                self.len(names.len());
+                for (path, as_name) in names {
+                    self.names(path);
+                    self.optional_name(as_name);
+                }
+            }
+        }
+    }
+
+    fn args(&mut self, args: &[Argument]) {
+        self.len(args.len());
+        for arg in args {
+            match *arg {
+                Argument::Positional(_) => self.tag(0),
+                Argument::Starargs(_) => self.tag(1),
+                Argument::Keyword(ref name, _) => {
+                    self.tag(2);
+                    self.str(name);
+                }
+                Argument::Kwargs(_) => self.tag(3),
+            }
+        }
+    }
+
+    fn decorators(&mut self, decorators: &[Decorator]) {
+        self.len(decorators.len());
+        for decorator in decorators {
+            self.names(&decorator.name);
+            self.flag(decorator.args.is_some());
+            if let Some(ref args) = decorator.args {
+                self.args(args);
+            }
+        }
+    }
+
+    fn typed_params(&mut self, params: &TypedArgsList) {
+        for params in &[&params.posonly_args, &params.args, &params.keyword_args] {
+            self.len(params.len());
+            for (name, typed, default) in params.iter() {
+                self.str(name);
+                self.flag(typed.is_some());
+                self.flag(default.is_some());
+            }
+        }
+        match params.star_args {
+            StarParams::No => self.tag(0),
+            StarParams::Anonymous => self.tag(1),
+            StarParams::Named((ref name, ref typed)) => {
+                self.tag(2);
+                self.str(name);
+                self.flag(typed.is_some());
+            }
+        }
+        self.flag(params.star_kwargs.is_some());
+        if let Some((ref name, ref typed)) = params.star_kwargs {
+            self.str(name);
+            self.flag(typed.is_some());
+        }
+    }
+
+    fn untyped_params(&mut self, params: &UntypedArgsList) {
+        for params in &[&params.posonly_args, &params.args, &params.keyword_args] {
+            self.len(params.len());
+            for (name, default) in params.iter() {
+                self.str(name);
+                self.flag(default.is_some());
+            }
+        }
+        match params.star_args {
+            StarParams::No => self.tag(0),
+            StarParams::Anonymous => self.tag(1),
+            StarParams::Named(ref name) => {
+                self.tag(2);
+                self.str(name);
+            }
+        }
+        self.optional_name(&params.star_kwargs);
+    }
+
+    fn setitem(&mut self, item: &SetItem) {
+        match *item {
+            SetItem::Star(_) => self.tag(0),
+            SetItem::Unique(_) => self.tag(1),
+        }
+    }
+
+    fn dictitem(&mut self, item: &DictItem) {
+        match *item {
+            DictItem::Star(_) => self.tag(0),
+            DictItem::Unique(_, _) => self.tag(1),
+        }
+    }
+
+    fn comp(&mut self, chunks: &[ComprehensionChunk]) {
+        self.len(chunks.len());
+        for chunk in chunks {
+            match *chunk {
+                ComprehensionChunk::If { .. } => self.tag(0),
+                ComprehensionChunk::For {
+                    async, ref item, ..
+                } => {
+                    self.tag(1);
+                    self.flag(async);
+                    self.len(item.len());
+                }
+            }
+        }
+    }
+
+    fn subscripts(&mut self, subs: &[Subscript]) {
+        self.len(subs.len());
+        for sub in subs {
+            match *sub {
+                Subscript::Simple(_) => self.tag(0),
+                Subscript::Double(ref start, ref end) => {
+                    self.tag(1);
+                    self.flag(start.is_some());
+                    self.flag(end.is_some());
+                }
+                Subscript::Triple(ref start, ref end, ref step) => {
+                    self.tag(2);
+                    self.flag(start.is_some());
+                    self.flag(end.is_some());
+                    self.flag(step.is_some());
+                }
+            }
+        }
+    }
+
+    fn compound_statement(&mut self, stmt: &CompoundStatement) {
+        match *stmt {
+            CompoundStatement::If(ref cond_blocks, ref else_block) => {
+                self.tag(0);
+                self.len(cond_blocks.len());
+                for (_, block) in cond_blocks {
+                    self.len(block.len());
+                }
+                self.optional_block(else_block);
+            }
+            CompoundStatement::For {
+                async,
+                ref item,
+                ref iterator,
+                ref for_block,
+                ref else_block,
+            } => {
+                self.tag(1);
+                self.flag(async);
+                self.len(item.len());
+                self.len(iterator.len());
+                self.len(for_block.len());
+                self.optional_block(else_block);
+            }
+            CompoundStatement::While(_, ref block, ref else_block) => {
+                self.tag(2);
+                self.len(block.len());
+                self.optional_block(else_block);
+            }
+            CompoundStatement::With(ref contexts, ref block) => {
+                self.tag(3);
+                self.len(contexts.len());
+                for (_, target) in contexts {
+                    self.flag(target.is_some());
+                }
+                self.len(block.len());
+            }
+            CompoundStatement::Funcdef(ref funcdef) => {
+                self.tag(4);
+                self.flag(funcdef.async);
+                self.decorators(&funcdef.decorators);
+                self.str(&funcdef.name);
+                self.typed_params(&funcdef.parameters);
+                self.flag(funcdef.return_type.is_some());
+                self.len(funcdef.code.len());
+            }
+            CompoundStatement::Classdef(ref classdef) => {
+                self.tag(5);
+                self.decorators(&classdef.decorators);
+                self.str(&classdef.name);
+                self.args(&classdef.arguments);
+                self.len(classdef.code.len());
+            }
+            CompoundStatement::Try(ref try_) => {
+                self.tag(6);
+                self.len(try_.try_block.len());
+                self.len(try_.except_clauses.len());
+                for (_, name, block) in &try_.except_clauses {
+                    self.optional_name(name);
+                    self.len(block.len());
+                }
+                self.len(try_.last_except.len());
+                self.len(try_.else_block.len());
+                self.len(try_.finally_block.len());
+            }
+        }
+    }
+}
+
+impl Visitor for StableHasher {
+    fn visit_statement(&mut self, stmt: &Statement) {
+        match *stmt {
+            Statement::Pass => self.tag(0),
+            Statement::Del(ref exprs) => {
+                self.tag(1);
+                self.len(exprs.len());
+            }
+            Statement::Break => self.tag(2),
+            Statement::Continue => self.tag(3),
+            Statement::Return(ref exprs) => {
+                self.tag(4);
+                self.len(exprs.len());
+            }
+            Statement::RaiseExcFrom(_, _) => self.tag(5),
+            Statement::RaiseExc(_) => self.tag(6),
+            Statement::Raise => self.tag(7),
+            Statement::Global(ref names) => {
+                self.tag(8);
+                self.names(names);
+            }
+            Statement::Nonlocal(ref names) => {
+                self.tag(9);
+                self.names(names);
+            }
+            Statement::Assert(_, ref msg) => {
+                self.tag(10);
+                self.flag(msg.is_some());
+            }
+            Statement::Import(ref import) => {
+                self.tag(11);
+                self.import(import);
+            }
+            Statement::Expressions(ref exprs) => {
+                self.tag(12);
+                self.len(exprs.len());
+            }
+            Statement::Assignment(ref lhs, ref rhs) => {
+                self.tag(13);
+                self.len(lhs.len());
+                self.len(rhs.len());
+                for exprs in rhs {
+                    self.len(exprs.len());
+                }
+            }
+            Statement::TypeAnnotation(ref lhs, _) => {
+                self.tag(14);
+                self.len(lhs.len());
+            }
+            Statement::TypedAssignment(ref lhs, _, ref rhs) => {
+                self.tag(15);
+                self.len(lhs.len());
+                self.len(rhs.len());
+            }
+            Statement::AugmentedAssignment(ref lhs, op, ref rhs) => {
+                self.tag(16);
+                self.len(lhs.len());
+                self.str(&op.to_string());
+                self.len(rhs.len());
+            }
+            Statement::Compound(ref stmt) => {
+                self.tag(17);
+                self.compound_statement(stmt);
+            }
+            Statement::Invalid(ref code) => {
+                self.tag(18);
+                self.str(code);
+            }
+            Statement::Extension(ref name, ref code) => {
+                self.tag(19);
+                self.str(name);
+                self.str(code);
+            }
+        }
+        walk_statement(self, stmt)
+    }
+
+    fn visit_expression(&mut self, expr: &Expression) {
+        match *expr {
+            Expression::Ellipsis => self.tag(0),
+            Expression::None => self.tag(1),
+            Expression::True => self.tag(2),
+            Expression::False => self.tag(3),
+            Expression::Name(ref name) => {
+                self.tag(4);
+                self.str(name);
+            }
+            Expression::Int(ref n) => {
+                self.tag(5);
+                self.int(n);
+            }
+            Expression::ImaginaryInt(ref n) => {
+                self.tag(6);
+                self.int(n);
+            }
+            Expression::Float(f) => {
+                self.tag(7);
+                self.0.update(&f.to_bits().to_le_bytes());
+            }
+            Expression::ImaginaryFloat(f) => {
+                self.tag(8);
+                self.0.update(&f.to_bits().to_le_bytes());
+            }
+            Expression::String(ref strings) => {
+                self.tag(9);
+                self.len(strings.len());
+                for s in strings {
+                    self.string(s);
+                }
+            }
+            Expression::Bytes(ref bytes) => {
+                self.tag(10);
+                self.bytes(bytes);
+            }
+            Expression::DictLiteral(ref items) => {
+                self.tag(11);
+                self.len(items.len());
+                for item in items {
+                    self.dictitem(item);
+                }
+            }
+            Expression::SetLiteral(ref items)
+            | Expression::ListLiteral(ref items)
+            | Expression::TupleLiteral(ref items) => {
+                self.tag(match *expr {
+                    Expression::SetLiteral(_) => 12,
+                    Expression::ListLiteral(_) => 13,
+                    _ => 14,
+                });
+                self.len(items.len());
+                for item in items {
+                    self.setitem(item);
+                }
+            }
+            Expression::DictComp(ref item, ref comp) => {
+                self.tag(15);
+                self.dictitem(item);
+                self.comp(comp);
+            }
+            Expression::SetComp(ref item, ref comp)
+            | Expression::ListComp(ref item, ref comp)
+            | Expression::Generator(ref item, ref comp) => {
+                self.tag(match *expr {
+                    Expression::SetComp(_, _) => 16,
+                    Expression::ListComp(_, _) => 17,
+                    _ => 18,
+                });
+                self.setitem(item);
+                self.comp(comp);
+            }
+            Expression::Await(_) => self.tag(19),
+            Expression::Call(_, ref args) => {
+                self.tag(20);
+                self.args(args);
+            }
+            Expression::Subscript(_, ref subs) => {
+                self.tag(21);
+                self.subscripts(subs);
+            }
+            Expression::Attribute(_, ref name) => {
+                self.tag(22);
+                self.str(name);
+            }
+            Expression::Uop(op, _) => {
+                self.tag(23);
+                self.str(&op.to_string());
+            }
+            Expression::Bop(op, _, _) => {
+                self.tag(24);
+                self.str(&op.to_string());
+            }
+            Expression::MultiBop(_, ref rest) => {
+                self.tag(25);
+                self.len(rest.len());
+                for (op, _) in rest {
+                    self.str(&op.to_string());
+                }
+            }
+            Expression::Ternary(_, _, _) => self.tag(26),
+            Expression::Yield(ref exprs) => {
+                self.tag(27);
+                self.len(exprs.len());
+            }
+            Expression::YieldFrom(_) => self.tag(28),
+            Expression::Star(_) => self.tag(29),
+            Expression::Lambdef(ref params, _) => {
+                self.tag(30);
+                self.untyped_params(params);
+            }
+            Expression::Named(_, _) => self.tag(31),
+        }
+        walk_expression(self, expr)
+    }
+}
+
+/// AST nodes that can be hashed by `stable_hash`.
+pub trait StableHash {
+    /// Feeds this node and its children to `hasher`.
+    fn hash_into(&self, hasher: &mut StableHasher);
+}
+
+impl StableHash for Statement {
+    fn hash_into(&self, hasher: &mut StableHasher) {
+        hasher.visit_statement(self)
+    }
+}
+
+impl StableHash for Expression {
+    fn hash_into(&self, hasher: &mut StableHasher) {
+        hasher.visit_expression(self)
+    }
+}
+
+impl StableHash for CompoundStatement {
+    fn hash_into(&self, hasher: &mut StableHasher) {
+        hasher.compound_statement(self);
+        walk_compound_statement(hasher, self)
+    }
+}
+
+impl StableHash for [Statement] {
+    fn hash_into(&self, hasher: &mut StableHasher) {
+        hasher.len(self.len());
+        walk_block(hasher, self)
+    }
+}
+
+impl StableHash for Vec<Statement> {
+    fn hash_into(&self, hasher: &mut StableHasher) {
+        self[..].hash_into(hasher)
+    }
+}
+
+/// Only the code of modules is hashed, not their comments.
+impl StableHash for Module {
+    fn hash_into(&self, hasher: &mut StableHasher) {
+        self.body.hash_into(hasher)
+    }
+}
+
+/// Returns a hash of an AST node (`Statement`, `Expression`,
+/// `CompoundStatement`, `Module` or a list of statements), that only
+/// depends on its structure.
+///
+/// Unlike `std::hash::Hash` with the default hasher, the hash is the
+/// same on all platforms, in all processes and with all the features of
+/// this crate, so it can be stored; but it may change with the version
+/// of this crate.
+/// Two ASTs that only differ by formatting have the same hash, as the
+/// AST does not store formatting; normalize them first (see
+/// `visitors::normalize`) to also ignore equivalent spellings.
+///
+/// ```
+/// use python_parser::{file_input, make_strspan};
+/// use python_parser::visitors::hash::stable_hash;
+///
+/// let ast1 = file_input(make_strspan("def f(x):  return x\n")).unwrap().1;
+/// let ast2 = file_input(make_strspan("def f(x):\n    return (x)\n")).unwrap().1;
+/// let ast3 = file_input(make_strspan("def f(y):\n    return y\n")).unwrap().1;
+/// assert_eq!(stable_hash(&ast1), stable_hash(&ast2));
+/// assert_ne!(stable_hash(&ast1), stable_hash(&ast3));
+/// ```
+pub fn stable_hash<T: StableHash + ?Sized>(node: &T) -> u64 {
+    let mut hasher = StableHasher(Fnv::new());
+    node.hash_into(&mut hasher);
+    hasher.0.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use {parse_expression, parse_module};
+
+    #[test]
+    fn test_stable_hash() {
+        // Must not depend on the process nor on the features
+        let e = parse_expression("f(x, y=1, *'\\u00e9', **{2**70: 1.5})").unwrap();
+        assert_eq!(stable_hash(&e), 0x0c89_f0cd_a9ba_2de3);
+        assert_eq!(stable_hash(&e), stable_hash(&e.clone()));
+        let e = parse_expression("f(x, y=1)").unwrap();
+        assert_eq!(
+            stable_hash(&e),
+            stable_hash(&parse_expression("f( x ,y = 1 ,)").unwrap())
+        );
+        for other in &[
+            "f(x, z=1)",
+            "f(x, y=1.)",
+            "f(x)(y=1)",
+            "f.x(y=1)",
+            "f(x, 1)",
+        ] {
+            let other = parse_expression(other).unwrap();
+            assert_ne!(stable_hash(&e), stable_hash(&other));
+        }
+        assert_ne!(
+            stable_hash(&Expression::Name("x".to_string())),
+            stable_hash(&Statement::Global(vec!["x".to_string()]))
+        );
+    }
+
+    #[test]
+    fn test_children_count() {
+        // Same nodes in the same order, but not with the same parents
+        let pairs = [
+            ("[[a], b]\n", "[[a, b]]\n"),
+            ("if a:\n    b\n    c\n", "if a:\n    b\nc\n"),
+            ("def f(x=a): pass\n", "def f(x: a): pass\n"),
+            ("a = b = c\n", "a, b = c\n"),
+        ];
+        for (code1, code2) in &pairs {
+            let module1 = parse_module(code1).unwrap();
+            let module2 = parse_module(code2).unwrap();
+            assert_ne!(stable_hash(&module1), stable_hash(&module2));
+        }
+    }
+}
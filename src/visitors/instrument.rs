@@ -0,0 +1,244 @@
+//! Instrumentation of functions, for tracing or profiling: a decorator
+//! is added to them, or calls are inserted at their start and end.
+//!
+//! Functions are selected by a predicate, given their qualified name
+//! (like Python's `__qualname__`, eg. `C.method` or `f.<locals>.g`).
+//! The names used by the instrumentation must be imported by the
+//! caller.
+
+use std::mem;
+
+use ast::*;
+use doctest::docstring;
+use visitors::walk_mut::{walk_statement_mut, VisitorMut};
+
+/// How to instrument functions.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Instrumentation {
+    /// Adds a decorator to the functions, as the innermost one (so it
+    /// gets the function itself, and not eg. a `staticmethod`).
+    Decorator(Decorator),
+    /// Calls the function `enter` (a dotted name) with the qualified
+    /// name of the function at its start (after its docstring), and
+    /// `exit` with its qualified name when it returns or raises (in a
+    /// `finally` block).
+    Calls { enter: Vec<Name>, exit: Vec<Name> },
+}
+
+#[cfg(feature = "wtf8")]
+fn content_from_str(s: &str) -> PyStringContent {
+    PyStringContent::from_str(s)
+}
+
+#[cfg(not(feature = "wtf8"))]
+fn content_from_str(s: &str) -> PyStringContent {
+    s.to_string()
+}
+
+/// Returns the statement calling the function with the dotted name
+/// `function` with `qualname`.
+fn call(function: &[Name], qualname: &str) -> Statement {
+    let mut names = function.iter();
+    let first = Expression::Name(names.next().cloned().unwrap_or_default());
+    let function = names.fold(first, |expr, name| {
+        Expression::Attribute(Box::new(expr), name.clone())
+    });
+    let name = Expression::String(vec![PyString {
+        prefix: String::new(),
+        content: content_from_str(qualname),
+        raw: None,
+    }]);
+    Statement::Assignment(
+        small_list![Expression::Call(
+            Box::new(function),
+            vec![Argument::Positional(name)],
+        )],
+        Vec::new(),
+    )
+}
+
+fn instrument_function(funcdef: &mut Funcdef, qualname: &str, instrumentation: &Instrumentation) {
+    match *instrumentation {
+        Instrumentation::Decorator(ref decorator) => funcdef.decorators.push(decorator.clone()),
+        Instrumentation::Calls {
+            ref enter,
+            ref exit,
+        } => {
+            let mut body = mem::take(&mut funcdef.code);
+            if docstring(&body).is_some() {
+                funcdef.code.push(body.remove(0));
+            }
+            if body.is_empty() {
+                body.push(Statement::Pass);
+            }
+            funcdef.code.push(call(enter, qualname));
+            let try_ = Try {
+                try_block: body,
+                except_clauses: Vec::new(),
+                last_except: Vec::new(),
+                else_block: Vec::new(),
+                finally_block: vec![call(exit, qualname)],
+            };
+            funcdef
+                .code
+                .push(Statement::Compound(Box::new(CompoundStatement::Try(try_))));
+        }
+    }
+}
+
+struct Instrumenter<'i, F> {
+    instrumentation: &'i Instrumentation,
+    predicate: F,
+    /// Qualified name of the enclosing class or function, followed by
+    /// a dot.
+    prefix: String,
+    instrumented: usize,
+}
+
+impl<'i, F: FnMut(&str, &Funcdef) -> bool> VisitorMut for Instrumenter<'i, F> {
+    fn visit_statement(&mut self, stmt: &mut Statement) {
+        let (name, is_function) = match *stmt {
+            Statement::Compound(ref compound) => match **compound {
+                CompoundStatement::Funcdef(ref funcdef) => (&funcdef.name, true),
+                CompoundStatement::Classdef(ref classdef) => (&classdef.name, false),
+                _ => return walk_statement_mut(self, stmt),
+            },
+            _ => return walk_statement_mut(self, stmt),
+        };
+        let qualname = format!("{}{}", self.prefix, name);
+        let prefix = if is_function {
+            format!("{}.<locals>.", qualname)
+        } else {
+            format!("{}.", qualname)
+        };
+        let prefix = mem::replace(&mut self.prefix, prefix);
+        walk_statement_mut(self, stmt);
+        self.prefix = prefix;
+        if let Statement::Compound(ref mut compound) = *stmt {
+            if let CompoundStatement::Funcdef(ref mut funcdef) = **compound {
+                if (self.predicate)(&qualname, funcdef) {
+                    instrument_function(funcdef, &qualname, self.instrumentation);
+                    self.instrumented += 1;
+                }
+            }
+        }
+    }
+}
+
+/// Instruments the functions of `stmts`, the body of a module (and
+/// their nested functions and methods) for which `predicate` returns
+/// true, given their qualified name, and returns their number.
+///
+/// ```
+/// use python_parser::ast::{Decorator, Funcdef};
+/// use python_parser::parse_module;
+/// use python_parser::visitors::instrument::{instrument, Instrumentation};
+///
+/// let mut module = parse_module("class C:\n    def f(self): pass\n    def _g(self): pass\n").unwrap();
+/// let name = vec!["tracing".to_string(), "trace".to_string()];
+/// let decorator = Decorator { name: name.into_iter().collect(), args: None };
+/// let public = |_: &str, funcdef: &Funcdef| !funcdef.name.starts_with('_');
+/// assert_eq!(instrument(&mut module.body, &Instrumentation::Decorator(decorator), public), 1);
+/// let expected = "class C:\n    @tracing.trace\n    def f(self): pass\n    def _g(self): pass\n";
+/// assert_eq!(module, parse_module(expected).unwrap());
+/// ```
+pub fn instrument<F>(
+    stmts: &mut Vec<Statement>,
+    instrumentation: &Instrumentation,
+    predicate: F,
+) -> usize
+where
+    F: FnMut(&str, &Funcdef) -> bool,
+{
+    let mut instrumenter = Instrumenter {
+        instrumentation,
+        predicate,
+        prefix: String::new(),
+        instrumented: 0,
+    };
+    instrumenter.visit_block(stmts);
+    instrumenter.instrumented
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parse_module;
+
+    #[test]
+    fn test_calls() {
+        let mut module = parse_module(
+            "def f():
+    '''Doc.'''
+    def g():
+        return 1
+    return g()
+class C:
+    def h(self):
+        '''Doc.'''
+",
+        )
+        .unwrap();
+        let instrumentation = Instrumentation::Calls {
+            enter: vec!["enter".to_string()],
+            exit: vec!["trace".to_string(), "exit".to_string()],
+        };
+        let mut names = Vec::new();
+        let count = instrument(&mut module.body, &instrumentation, |name, _| {
+            names.push(name.to_string());
+            name != "f"
+        });
+        assert_eq!(count, 2);
+        assert_eq!(names, ["f.<locals>.g", "f", "C.h"]);
+        let expected = "def f():
+    '''Doc.'''
+    def g():
+        enter('f.<locals>.g')
+        try:
+            return 1
+        finally:
+            trace.exit('f.<locals>.g')
+    return g()
+class C:
+    def h(self):
+        '''Doc.'''
+        enter('C.h')
+        try:
+            pass
+        finally:
+            trace.exit('C.h')
+";
+        assert_eq!(module, parse_module(expected).unwrap());
+    }
+
+    #[test]
+    fn test_decorator() {
+        let mut module = parse_module(
+            "class C:
+    @staticmethod
+    def f():
+        pass
+",
+        )
+        .unwrap();
+        let decorator = Decorator {
+            name: small_list!["profile".to_string()],
+            args: Some(vec![Argument::Keyword(
+                "calls".to_string(),
+                Expression::True,
+            )]),
+        };
+        let instrumentation = Instrumentation::Decorator(decorator);
+        assert_eq!(
+            instrument(&mut module.body, &instrumentation, |_, _| true),
+            1
+        );
+        let expected = "class C:
+    @staticmethod
+    @profile(calls=True)
+    def f():
+        pass
+";
+        assert_eq!(module, parse_module(expected).unwrap());
+    }
+}
@@ -0,0 +1,589 @@
+//! Analysis of the loops of a function body: the loops following a
+//! pattern equivalent to a comprehension (accumulating items into a
+//! list or set, possibly filtering them, or building a dict), and the
+//! loop variables which may be read after the loop.
+//!
+//! A loop variable escapes when it may be read after the loop, which
+//! is conservatively assumed when the name is read by any statement
+//! which may run after the loop (including the next iterations of the
+//! enclosing loops, and the handlers of an enclosing `try`), when it is
+//! declared `global` or `nonlocal` or used in a nested function, class
+//! or lambda, or when the function uses `locals`, `vars`, `dir`, `eval`
+//! or `exec`.
+
+use std::slice;
+
+use ast::*;
+use visitors::dead_stores::{Names, ScopeInfo};
+use visitors::target_names;
+use visitors::walk::{walk_block, walk_expression, walk_statement, Visitor};
+
+/// What a loop does. Loops whose body is a single `if` statement
+/// (without `elif` nor `else`) around the accumulation filter their
+/// items, and have a `condition`.
+///
+/// The accumulator is not used by the iterator, the condition nor the
+/// accumulated expressions, and these have no `yield`. Nothing is known
+/// about its value before the loop.
+#[derive(Clone, Debug, PartialEq)]
+pub enum LoopPattern<'a> {
+    /// `for item in items: list.append(element)`
+    ListAccumulation {
+        list: &'a str,
+        element: &'a Expression,
+        condition: Option<&'a Expression>,
+    },
+    /// `for item in items: set.add(element)`
+    SetAccumulation {
+        set: &'a str,
+        element: &'a Expression,
+        condition: Option<&'a Expression>,
+    },
+    /// `for item in items: dict[key] = value`
+    DictBuilding {
+        dict: &'a str,
+        key: &'a Expression,
+        value: &'a Expression,
+        condition: Option<&'a Expression>,
+    },
+    /// Any other loop, including `while` loops and loops with an `else`
+    /// block.
+    Other,
+}
+
+impl<'a> LoopPattern<'a> {
+    /// Returns the condition of the loops filtering their items.
+    pub fn condition(&self) -> Option<&'a Expression> {
+        match *self {
+            LoopPattern::ListAccumulation { condition, .. }
+            | LoopPattern::SetAccumulation { condition, .. }
+            | LoopPattern::DictBuilding { condition, .. } => condition,
+            LoopPattern::Other => None,
+        }
+    }
+
+    pub fn is_filtering(&self) -> bool {
+        self.condition().is_some()
+    }
+}
+
+/// A loop found by `loops`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LoopInfo<'a> {
+    /// The `for` or `while` statement.
+    pub statement: &'a CompoundStatement,
+    /// Number of loops around this one.
+    pub depth: usize,
+    pub pattern: LoopPattern<'a>,
+    /// Names bound by the target of a `for` loop, in the order of the
+    /// code.
+    pub variables: Vec<&'a str>,
+    /// The `variables` which may be read after the loop.
+    pub escaping: Vec<&'a str>,
+}
+
+/// Collects the names read by statements and expressions, that is all
+/// the names except the targets of assignments and `for` loops.
+struct ReadNames {
+    names: Names,
+}
+
+impl ReadNames {
+    fn visit_target(&mut self, target: &Expression) {
+        match *target {
+            Expression::Name(_) => (),
+            Expression::TupleLiteral(ref items) | Expression::ListLiteral(ref items) => {
+                for item in items {
+                    match *item {
+                        SetItem::Unique(ref e) | SetItem::Star(ref e) => self.visit_target(e),
+                    }
+                }
+            }
+            Expression::Star(ref e) => self.visit_target(e),
+            _ => self.visit_expression(target),
+        }
+    }
+}
+
+impl Visitor for ReadNames {
+    fn visit_statement(&mut self, stmt: &Statement) {
+        match *stmt {
+            Statement::Assignment(ref lhs, ref rhs) if !rhs.is_empty() => {
+                let (value, targets) = rhs.split_last().unwrap();
+                for target in lhs.iter().chain(targets.iter().flatten()) {
+                    self.visit_target(target);
+                }
+                for expr in value {
+                    self.visit_expression(expr);
+                }
+            }
+            Statement::TypedAssignment(ref lhs, ref typed, ref rhs) => {
+                for target in lhs {
+                    self.visit_target(target);
+                }
+                self.visit_expression(typed);
+                for expr in rhs {
+                    self.visit_expression(expr);
+                }
+            }
+            Statement::Compound(ref compound) => match **compound {
+                CompoundStatement::For {
+                    ref item,
+                    ref iterator,
+                    ref for_block,
+                    ref else_block,
+                    ..
+                } => {
+                    for target in item {
+                        self.visit_target(target);
+                    }
+                    for expr in iterator {
+                        self.visit_expression(expr);
+                    }
+                    walk_block(self, for_block);
+                    if let Some(ref block) = *else_block {
+                        walk_block(self, block);
+                    }
+                }
+                _ => walk_statement(self, stmt),
+            },
+            _ => walk_statement(self, stmt),
+        }
+    }
+
+    fn visit_expression(&mut self, expr: &Expression) {
+        if let Expression::Name(ref name) = *expr {
+            self.names.insert(name.clone());
+        }
+        walk_expression(self, expr)
+    }
+}
+
+fn read_names(stmts: &[Statement]) -> Names {
+    let mut collector = ReadNames {
+        names: Names::new(),
+    };
+    walk_block(&mut collector, stmts);
+    collector.names
+}
+
+/// Collects the names used by expressions, and whether they have a
+/// `yield`.
+#[derive(Default)]
+struct ExpressionNames {
+    names: Names,
+    yields: bool,
+}
+
+impl Visitor for ExpressionNames {
+    fn visit_expression(&mut self, expr: &Expression) {
+        match *expr {
+            Expression::Name(ref name) => {
+                self.names.insert(name.clone());
+            }
+            Expression::Yield(_) | Expression::YieldFrom(_) => self.yields = true,
+            _ => (),
+        }
+        walk_expression(self, expr)
+    }
+}
+
+/// Returns the accumulator and the pattern of the statement of a loop
+/// body (without its condition).
+fn accumulation<'a>(stmt: &'a Statement) -> Option<(&'a str, LoopPattern<'a>)> {
+    match *stmt {
+        Statement::Assignment(ref lhs, ref rhs) if rhs.is_empty() => match lhs[..] {
+            [Expression::Call(ref callee, ref args)] => match (&**callee, &args[..]) {
+                (Expression::Attribute(ref object, ref method), [Argument::Positional(ref e)]) => {
+                    match (&**object, &method[..]) {
+                        (Expression::Name(ref list), "append") => Some((
+                            list,
+                            LoopPattern::ListAccumulation {
+                                list,
+                                element: e,
+                                condition: None,
+                            },
+                        )),
+                        (Expression::Name(ref set), "add") => Some((
+                            set,
+                            LoopPattern::SetAccumulation {
+                                set,
+                                element: e,
+                                condition: None,
+                            },
+                        )),
+                        _ => None,
+                    }
+                }
+                _ => None,
+            },
+            _ => None,
+        },
+        Statement::Assignment(ref lhs, ref rhs) if rhs.len() == 1 => {
+            match (&lhs[..], &rhs[0][..]) {
+                ([Expression::Subscript(ref object, ref subscripts)], [ref value]) => {
+                    match (&**object, &subscripts[..]) {
+                        (Expression::Name(ref dict), [Subscript::Simple(ref key)]) => Some((
+                            dict,
+                            LoopPattern::DictBuilding {
+                                dict,
+                                key,
+                                value,
+                                condition: None,
+                            },
+                        )),
+                        _ => None,
+                    }
+                }
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+/// Returns the pattern of a `for` loop.
+fn classify<'a>(
+    item: &'a [Expression],
+    iterator: &'a [Expression],
+    body: &'a [Statement],
+    variables: &[&str],
+) -> LoopPattern<'a> {
+    let (condition, stmt) = match *body {
+        [Statement::Compound(ref compound)] => match **compound {
+            CompoundStatement::If(ref cond_blocks, None) => match cond_blocks[..] {
+                [(ref cond, ref block)] if block.len() == 1 => (Some(cond), &block[0]),
+                _ => return LoopPattern::Other,
+            },
+            _ => return LoopPattern::Other,
+        },
+        [ref stmt] => (None, stmt),
+        _ => return LoopPattern::Other,
+    };
+    let (accumulator, mut pattern) = match accumulation(stmt) {
+        Some(accumulation) => accumulation,
+        None => return LoopPattern::Other,
+    };
+    let mut used = ExpressionNames::default();
+    for expr in iterator.iter().chain(condition) {
+        used.visit_expression(expr);
+    }
+    match pattern {
+        LoopPattern::ListAccumulation { element, .. }
+        | LoopPattern::SetAccumulation { element, .. } => used.visit_expression(element),
+        LoopPattern::DictBuilding { key, value, .. } => {
+            used.visit_expression(key);
+            used.visit_expression(value);
+        }
+        LoopPattern::Other => unreachable!(),
+    }
+    // Targets like `acc[i]` use the accumulator too
+    let mut target_uses = ReadNames {
+        names: Names::new(),
+    };
+    for target in item {
+        target_uses.visit_target(target);
+    }
+    if used.yields
+        || used.names.contains(accumulator)
+        || target_uses.names.contains(accumulator)
+        || variables.contains(&accumulator)
+    {
+        return LoopPattern::Other;
+    }
+    match pattern {
+        LoopPattern::ListAccumulation {
+            condition: ref mut c,
+            ..
+        }
+        | LoopPattern::SetAccumulation {
+            condition: ref mut c,
+            ..
+        }
+        | LoopPattern::DictBuilding {
+            condition: ref mut c,
+            ..
+        } => *c = condition,
+        LoopPattern::Other => unreachable!(),
+    }
+    pattern
+}
+
+fn union(mut a: Names, b: &Names) -> Names {
+    a.extend(b.iter().cloned());
+    a
+}
+
+struct LoopCollector<'a> {
+    info: ScopeInfo,
+    loops: Vec<LoopInfo<'a>>,
+}
+
+impl<'a> LoopCollector<'a> {
+    /// Collects the loops of `stmts`, given the names which may be read
+    /// after them.
+    fn block(&mut self, stmts: &'a [Statement], after: &Names, depth: usize) {
+        let mut afters = Vec::with_capacity(stmts.len());
+        let mut read = after.clone();
+        for stmt in stmts.iter().rev() {
+            afters.push(read.clone());
+            read = union(read, &read_names(slice::from_ref(stmt)));
+        }
+        for (stmt, after) in stmts.iter().zip(afters.iter().rev()) {
+            if let Statement::Compound(ref compound) = *stmt {
+                self.compound(stmt, compound, after, depth);
+            }
+        }
+    }
+
+    fn add_loop(
+        &mut self,
+        statement: &'a CompoundStatement,
+        depth: usize,
+        pattern: LoopPattern<'a>,
+        variables: Vec<&'a str>,
+        after: &Names,
+    ) {
+        let escaping = variables
+            .iter()
+            .cloned()
+            .filter(|name| {
+                self.info.dynamic || after.contains(*name) || self.info.excluded.contains(*name)
+            })
+            .collect();
+        self.loops.push(LoopInfo {
+            statement,
+            depth,
+            pattern,
+            variables,
+            escaping,
+        });
+    }
+
+    fn compound(
+        &mut self,
+        stmt: &'a Statement,
+        compound: &'a CompoundStatement,
+        after: &Names,
+        depth: usize,
+    ) {
+        match *compound {
+            CompoundStatement::If(ref cond_blocks, ref else_block) => {
+                for (_, block) in cond_blocks {
+                    self.block(block, after, depth);
+                }
+                if let Some(ref block) = *else_block {
+                    self.block(block, after, depth);
+                }
+            }
+            CompoundStatement::For {
+                ref item,
+                ref iterator,
+                ref for_block,
+                ref else_block,
+                ..
+            } => {
+                let mut variables = Vec::new();
+                for target in item {
+                    target_names(target, &mut variables);
+                }
+                let (pattern, after_loop) = match *else_block {
+                    Some(ref block) => (LoopPattern::Other, union(read_names(block), after)),
+                    None => (
+                        classify(item, iterator, for_block, &variables),
+                        after.clone(),
+                    ),
+                };
+                self.add_loop(compound, depth, pattern, variables, &after_loop);
+                // The next iterations may read anything of the loop
+                let inner = union(read_names(slice::from_ref(stmt)), after);
+                self.block(for_block, &inner, depth + 1);
+                if let Some(ref block) = *else_block {
+                    self.block(block, after, depth);
+                }
+            }
+            CompoundStatement::While(_, ref block, ref else_block) => {
+                self.add_loop(compound, depth, LoopPattern::Other, Vec::new(), after);
+                let inner = union(read_names(slice::from_ref(stmt)), after);
+                self.block(block, &inner, depth + 1);
+                if let Some(ref block) = *else_block {
+                    self.block(block, after, depth);
+                }
+            }
+            CompoundStatement::With(_, ref block) => self.block(block, after, depth),
+            CompoundStatement::Try(ref try_) => {
+                // Any statement may jump to the handlers
+                let inner = union(read_names(slice::from_ref(stmt)), after);
+                self.block(&try_.try_block, &inner, depth);
+                for (_, _, block) in &try_.except_clauses {
+                    self.block(block, &inner, depth);
+                }
+                self.block(&try_.last_except, &inner, depth);
+                self.block(&try_.else_block, &inner, depth);
+                self.block(&try_.finally_block, &inner, depth);
+            }
+            CompoundStatement::Funcdef(_) | CompoundStatement::Classdef(_) => (),
+        }
+    }
+}
+
+/// Returns the loops of `code`, the body of a function (or a module),
+/// in the order of the code. The loops of nested functions and classes
+/// are not included.
+///
+/// ```
+/// use python_parser::parse_module;
+/// use python_parser::ast::{CompoundStatement, Statement};
+/// use python_parser::visitors::loops::{loops, LoopPattern};
+///
+/// let module = parse_module("def f(items):
+///     result = []
+///     for item in items:
+///         if item:
+///             result.append(item)
+///     return result, item
+/// ").unwrap();
+/// let funcdef = match module.body[0] {
+///     Statement::Compound(ref compound) => match **compound {
+///         CompoundStatement::Funcdef(ref funcdef) => funcdef,
+///         _ => unreachable!(),
+///     },
+///     _ => unreachable!(),
+/// };
+/// let loops = loops(&funcdef.code);
+/// assert_eq!(loops.len(), 1);
+/// match loops[0].pattern {
+///     LoopPattern::ListAccumulation { list, .. } => assert_eq!(list, "result"),
+///     _ => unreachable!(),
+/// }
+/// assert!(loops[0].pattern.is_filtering());
+/// assert_eq!(loops[0].escaping, vec!["item"]);
+/// ```
+pub fn loops<'a>(code: &'a [Statement]) -> Vec<LoopInfo<'a>> {
+    let mut collector = LoopCollector {
+        info: ScopeInfo::of_block(code),
+        loops: Vec::new(),
+    };
+    collector.block(code, &Names::new(), 0);
+    collector.loops
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parse_module;
+
+    /// Returns the `(depth, pattern, variables, escaping)` of the loops
+    /// of `code`, with patterns as `"list"`, `"set"`, `"dict"` or
+    /// `"other"`, with a `"filter "` prefix for filtering loops.
+    fn check(code: &str, expected: &[(usize, &str, &[&str], &[&str])]) {
+        let module = parse_module(code).unwrap();
+        let loops: Vec<_> = loops(&module.body)
+            .into_iter()
+            .map(|info| {
+                let pattern = match info.pattern {
+                    LoopPattern::ListAccumulation { .. } => "list",
+                    LoopPattern::SetAccumulation { .. } => "set",
+                    LoopPattern::DictBuilding { .. } => "dict",
+                    LoopPattern::Other => "other",
+                };
+                let pattern = if info.pattern.is_filtering() {
+                    format!("filter {}", pattern)
+                } else {
+                    pattern.to_string()
+                };
+                (info.depth, pattern, info.variables, info.escaping)
+            })
+            .collect();
+        let expected: Vec<_> = expected
+            .iter()
+            .map(|&(depth, pattern, variables, escaping)| {
+                (
+                    depth,
+                    pattern.to_string(),
+                    variables.to_vec(),
+                    escaping.to_vec(),
+                )
+            })
+            .collect();
+        assert_eq!(loops, expected);
+    }
+
+    #[test]
+    fn test_patterns() {
+        check(
+            "for a in items:
+    l.append(a * 2)
+for b, c in items:
+    if b:
+        s.add(c)
+for k in items:
+    d[k] = f(k)
+for e in items:
+    if e:
+        l.append(e)
+    else:
+        pass
+for g in items:
+    l.append(g)
+else:
+    pass
+while items:
+    l.append(items.pop())
+for h in l:
+    l.append(h)
+for i in items:
+    l.append(l[i])
+for l[0] in items:
+    l.append(1)
+def f():
+    for j in items:
+        l.append(j)
+",
+            &[
+                (0, "list", &["a"], &[]),
+                (0, "filter set", &["b", "c"], &[]),
+                (0, "dict", &["k"], &[]),
+                (0, "other", &["e"], &[]),
+                (0, "other", &["g"], &[]),
+                (0, "other", &[], &[]),
+                (0, "other", &["h"], &[]),
+                (0, "other", &["i"], &[]),
+                (0, "other", &[], &[]),
+            ],
+        );
+    }
+
+    #[test]
+    fn test_escaping() {
+        check(
+            "for x in a:
+    pass
+x = 1
+for y in a:
+    pass
+print(y)
+for z in a:
+    pass
+else:
+    print(z)
+for i in a:
+    for j in b:
+        pass
+    print(j)
+for u in a:
+    pass
+def g():
+    return u
+",
+            &[
+                (0, "other", &["x"], &[]),
+                (0, "other", &["y"], &["y"]),
+                (0, "other", &["z"], &["z"]),
+                (0, "other", &["i"], &[]),
+                (1, "other", &["j"], &["j"]),
+                (0, "other", &["u"], &["u"]),
+            ],
+        );
+    }
+}
@@ -1,3 +1,36 @@
 //! Utilities that work on the AST.
 
+pub mod asserts;
+pub mod comprehensions;
+pub mod dead_stores;
+pub mod dump;
+pub mod hash;
+pub mod instrument;
+pub mod loops;
+pub mod normalize;
 pub mod printer;
+pub mod prune;
+pub mod simplify;
+pub mod size;
+pub mod strip;
+pub mod walk;
+pub mod walk_mut;
+
+use ast::{Expression, SetItem};
+
+/// Pushes the names bound by `target`, the target of an assignment or
+/// of a loop, to `names`.
+pub(crate) fn target_names<'a>(target: &'a Expression, names: &mut Vec<&'a str>) {
+    match *target {
+        Expression::Name(ref name) => names.push(name),
+        Expression::TupleLiteral(ref items) | Expression::ListLiteral(ref items) => {
+            for item in items {
+                match *item {
+                    SetItem::Unique(ref e) | SetItem::Star(ref e) => target_names(e, names),
+                }
+            }
+        }
+        Expression::Star(ref e) => target_names(e, names),
+        _ => (),
+    }
+}
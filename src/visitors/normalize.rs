@@ -0,0 +1,564 @@
+//! Rewrites the AST into a canonical form, so that two pieces of code
+//! that differ only by their formatting (or by equivalent spellings of
+//! the same thing) have equal ASTs.
+//!
+//! Parentheses are not stored in the AST, so there is no need to remove
+//! redundant ones. The normalization:
+//!
+//! * sorts the keyword arguments of calls, class definitions and
+//!   decorators, within each run of keyword arguments not separated by
+//!   `*args` or `**kwargs`, if their values have no side effect (see
+//!   `dead_stores::is_pure`), so their evaluation order does not matter,
+//! * removes the `r` and `u` string prefixes and lowercases the `f`
+//!   prefix (string contents are stored unescaped, so quotes and raw
+//!   strings are only a matter of spelling),
+//! * concatenates consecutive strings with the same prefix (`"a" "b"`
+//!   becomes `"ab"`),
+//! * turns imaginary integers into imaginary floats (`1j` is `1.0j`),
+//!   `x[a:b:]` into `x[a:b]`, and multi-operator expressions with a
+//!   single operator into simple binary operations,
+//! * turns expression statements into `Statement::Assignment`s with no
+//!   right-hand side, like the parser does for most of them.
+
+use std::mem;
+
+use super::super::ast::*;
+use super::dead_stores::is_pure;
+use helpers::from_vec;
+
+/// Normalizes the statements of a module, in place.
+///
+/// ```
+/// use python_parser::{file_input, make_strspan};
+/// use python_parser::visitors::normalize::normalize_module;
+///
+/// let mut ast1 = file_input(make_strspan("f(b=1, a=u'x' r'y')\n")).unwrap().1;
+/// let mut ast2 = file_input(make_strspan("f(a=\"xy\", b=1)\n")).unwrap().1;
+/// assert_ne!(ast1, ast2);
+/// normalize_module(&mut ast1);
+/// normalize_module(&mut ast2);
+/// assert_eq!(ast1, ast2);
+/// ```
+pub fn normalize_module(stmts: &mut [Statement]) {
+    for stmt in stmts {
+        normalize_statement(stmt)
+    }
+}
+
+fn normalize_statement(stmt: &mut Statement) {
+    if let Statement::Expressions(ref mut exprs) = *stmt {
+        let exprs = mem::take(exprs);
+        *stmt = Statement::Assignment(from_vec(exprs), Vec::new());
+    }
+    match *stmt {
+        Statement::Pass
+        | Statement::Break
+        | Statement::Continue
+        | Statement::Raise
+        | Statement::Global(_)
+        | Statement::Nonlocal(_)
+        | Statement::Import(_)
+        | Statement::Invalid(_)
+        | Statement::Extension(_, _) => (),
+        Statement::Del(ref mut exprs)
+        | Statement::Return(ref mut exprs)
+        | Statement::Expressions(ref mut exprs) => normalize_expressions(exprs),
+        Statement::RaiseExcFrom(ref mut exc, ref mut from) => {
+            normalize_expression(exc);
+            normalize_expression(from);
+        }
+        Statement::RaiseExc(ref mut exc) => normalize_expression(exc),
+        Statement::Assert(ref mut cond, ref mut msg) => {
+            normalize_expression(cond);
+            if let Some(ref mut msg) = *msg {
+                normalize_expression(msg);
+            }
+        }
+        Statement::Assignment(ref mut lhs, ref mut rhs) => {
+            normalize_expressions(lhs);
+            for exprs in rhs {
+                normalize_expressions(exprs);
+            }
+        }
+        Statement::TypeAnnotation(ref mut lhs, ref mut typed) => {
+            normalize_expressions(lhs);
+            normalize_expression(typed);
+        }
+        Statement::TypedAssignment(ref mut lhs, ref mut typed, ref mut rhs) => {
+            normalize_expressions(lhs);
+            normalize_expression(typed);
+            normalize_expressions(rhs);
+        }
+        Statement::AugmentedAssignment(ref mut lhs, _, ref mut rhs) => {
+            normalize_expressions(lhs);
+            normalize_expressions(rhs);
+        }
+        Statement::Compound(ref mut stmt) => normalize_compound_statement(stmt),
+    }
+}
+
+fn normalize_compound_statement(stmt: &mut CompoundStatement) {
+    match *stmt {
+        CompoundStatement::If(ref mut cond_blocks, ref mut else_block) => {
+            for &mut (ref mut cond, ref mut block) in cond_blocks {
+                normalize_expression(cond);
+                normalize_module(block);
+            }
+            if let Some(ref mut block) = *else_block {
+                normalize_module(block);
+            }
+        }
+        CompoundStatement::For {
+            ref mut item,
+            ref mut iterator,
+            ref mut for_block,
+            ref mut else_block,
+            ..
+        } => {
+            normalize_expressions(item);
+            normalize_expressions(iterator);
+            normalize_module(for_block);
+            if let Some(ref mut block) = *else_block {
+                normalize_module(block);
+            }
+        }
+        CompoundStatement::While(ref mut cond, ref mut block, ref mut else_block) => {
+            normalize_expression(cond);
+            normalize_module(block);
+            if let Some(ref mut block) = *else_block {
+                normalize_module(block);
+            }
+        }
+        CompoundStatement::With(ref mut contexts, ref mut block) => {
+            for &mut (ref mut ctx, ref mut as_what) in contexts {
+                normalize_expression(ctx);
+                if let Some(ref mut e) = *as_what {
+                    normalize_expression(e);
+                }
+            }
+            normalize_module(block);
+        }
+        CompoundStatement::Funcdef(ref mut funcdef) => {
+            normalize_decorators(&mut funcdef.decorators);
+            normalize_typed_params(&mut funcdef.parameters);
+            if let Some(ref mut e) = funcdef.return_type {
+                normalize_expression(e);
+            }
+            normalize_module(&mut funcdef.code);
+        }
+        CompoundStatement::Classdef(ref mut classdef) => {
+            normalize_decorators(&mut classdef.decorators);
+            normalize_args(&mut classdef.arguments);
+            normalize_module(&mut classdef.code);
+        }
+        CompoundStatement::Try(ref mut try_) => {
+            normalize_module(&mut try_.try_block);
+            for &mut (ref mut guard, _, ref mut block) in &mut try_.except_clauses {
+                normalize_expression(guard);
+                normalize_module(block);
+            }
+            normalize_module(&mut try_.last_except);
+            normalize_module(&mut try_.else_block);
+            normalize_module(&mut try_.finally_block);
+        }
+    }
+}
+
+fn normalize_decorators(decorators: &mut [Decorator]) {
+    for decorator in decorators {
+        if let Some(ref mut args) = decorator.args {
+            normalize_args(args);
+        }
+    }
+}
+
+fn normalize_args(args: &mut [Argument]) {
+    for arg in args.iter_mut() {
+        match *arg {
+            Argument::Positional(ref mut e)
+            | Argument::Starargs(ref mut e)
+            | Argument::Keyword(_, ref mut e)
+            | Argument::Kwargs(ref mut e) => normalize_expression(e),
+        }
+    }
+    for run in args.split_mut(|arg| !matches!(*arg, Argument::Keyword(..))) {
+        // Values with side effects must be evaluated in their order
+        let is_pure_run = run.iter().all(|arg| match *arg {
+            Argument::Keyword(_, ref value) => is_pure(value),
+            _ => unreachable!(),
+        });
+        if !is_pure_run {
+            continue;
+        }
+        run.sort_by(|arg1, arg2| match (arg1, arg2) {
+            (Argument::Keyword(name1, _), Argument::Keyword(name2, _)) => name1.cmp(name2),
+            _ => unreachable!(), // Runs only contain keyword arguments
+        });
+    }
+}
+
+fn normalize_optional_expression(e: &mut Option<Expression>) {
+    if let Some(ref mut e) = *e {
+        normalize_expression(e);
+    }
+}
+
+fn normalize_typed_params(params: &mut TypedArgsList) {
+    for param in params
+        .posonly_args
+        .iter_mut()
+        .chain(params.args.iter_mut())
+        .chain(params.keyword_args.iter_mut())
+    {
+        normalize_optional_expression(&mut param.1);
+        normalize_optional_expression(&mut param.2);
+    }
+    if let StarParams::Named((_, ref mut typed)) = params.star_args {
+        normalize_optional_expression(typed);
+    }
+    if let Some((_, ref mut typed)) = params.star_kwargs {
+        normalize_optional_expression(typed);
+    }
+}
+
+fn normalize_untyped_params(params: &mut UntypedArgsList) {
+    for param in params
+        .posonly_args
+        .iter_mut()
+        .chain(params.args.iter_mut())
+        .chain(params.keyword_args.iter_mut())
+    {
+        normalize_optional_expression(&mut param.1);
+    }
+}
+
+fn normalize_setitem(item: &mut SetItem) {
+    match *item {
+        SetItem::Star(ref mut e) | SetItem::Unique(ref mut e) => normalize_expression(e),
+    }
+}
+
+fn normalize_dictitem(item: &mut DictItem) {
+    match *item {
+        DictItem::Star(ref mut e) => normalize_expression(e),
+        DictItem::Unique(ref mut key, ref mut value) => {
+            normalize_expression(key);
+            normalize_expression(value);
+        }
+    }
+}
+
+fn normalize_comp(chunks: &mut [ComprehensionChunk]) {
+    for chunk in chunks {
+        match *chunk {
+            ComprehensionChunk::If { ref mut cond } => normalize_expression(cond),
+            ComprehensionChunk::For {
+                ref mut item,
+                ref mut iterator,
+                ..
+            } => {
+                normalize_expressions(item);
+                normalize_expression(iterator);
+            }
+        }
+    }
+}
+
+fn normalize_subscript(sub: &mut Subscript) {
+    if let Subscript::Triple(ref mut start, ref mut end, None) = *sub {
+        let (start, end) = (start.take(), end.take());
+        *sub = Subscript::Double(start, end);
+    }
+    match *sub {
+        Subscript::Simple(ref mut e) => normalize_expression(e),
+        Subscript::Double(ref mut start, ref mut end) => {
+            normalize_optional_expression(start);
+            normalize_optional_expression(end);
+        }
+        Subscript::Triple(ref mut start, ref mut end, ref mut step) => {
+            normalize_optional_expression(start);
+            normalize_optional_expression(end);
+            normalize_optional_expression(step);
+        }
+    }
+}
+
+#[cfg(feature = "wtf8")]
+fn append_content(content: &mut PyStringContent, other: &PyStringContent) {
+    content.push_wtf8(other)
+}
+
+#[cfg(not(feature = "wtf8"))]
+fn append_content(content: &mut PyStringContent, other: &PyStringContent) {
+    content.push_str(other)
+}
+
+/// Removes the prefixes that do not change the value of the strings,
+/// and concatenates the consecutive ones with the same prefix.
+fn normalize_strings(strings: &mut Vec<PyString>) {
+    let mut normalized: Vec<PyString> = Vec::with_capacity(strings.len());
+    for mut string in strings.drain(..) {
+        string.raw = None;
+        string.prefix = string
+            .prefix
+            .to_ascii_lowercase()
+            .replace(&['r', 'u'][..], "");
+        match normalized.last_mut() {
+            Some(ref mut last) if last.prefix == string.prefix => {
+                append_content(&mut last.content, &string.content);
+                continue;
+            }
+            _ => (),
+        }
+        normalized.push(string);
+    }
+    *strings = normalized;
+}
+
+#[cfg(feature = "bigint")]
+fn int_to_float(n: &IntegerType) -> f64 {
+    use num_traits::ToPrimitive;
+    n.to_f64().unwrap_or(f64::INFINITY)
+}
+
+#[cfg(not(feature = "bigint"))]
+fn int_to_float(n: &IntegerType) -> f64 {
+    *n as f64
+}
+
+fn normalize_expressions(exprs: &mut [Expression]) {
+    for e in exprs {
+        normalize_expression(e)
+    }
+}
+
+/// Normalizes an expression, in place.
+pub fn normalize_expression(e: &mut Expression) {
+    let replacement = match *e {
+        Expression::ImaginaryInt(ref n) => Some(Expression::ImaginaryFloat(int_to_float(n))),
+        Expression::MultiBop(ref mut first, ref mut rest) if rest.len() == 1 => {
+            let (op, rhs) = rest.pop().unwrap(); // Can't panic, because len == 1
+            let first = mem::replace(first, Box::new(Expression::None));
+            Some(Expression::Bop(op, first, Box::new(rhs)))
+        }
+        _ => None,
+    };
+    if let Some(replacement) = replacement {
+        *e = replacement;
+    }
+
+    match *e {
+        Expression::Ellipsis
+        | Expression::None
+        | Expression::True
+        | Expression::False
+        | Expression::Name(_)
+        | Expression::Int(_)
+        | Expression::ImaginaryInt(_)
+        | Expression::Float(_)
+        | Expression::ImaginaryFloat(_)
+        | Expression::Bytes(_) => (),
+        Expression::String(ref mut strings) => normalize_strings(strings),
+        Expression::DictLiteral(ref mut items) => {
+            for item in items {
+                normalize_dictitem(item);
+            }
+        }
+        Expression::SetLiteral(ref mut items)
+        | Expression::ListLiteral(ref mut items)
+        | Expression::TupleLiteral(ref mut items) => {
+            for item in items {
+                normalize_setitem(item);
+            }
+        }
+        Expression::DictComp(ref mut item, ref mut chunks) => {
+            normalize_dictitem(item);
+            normalize_comp(chunks);
+        }
+        Expression::SetComp(ref mut item, ref mut chunks)
+        | Expression::ListComp(ref mut item, ref mut chunks)
+        | Expression::Generator(ref mut item, ref mut chunks) => {
+            normalize_setitem(item);
+            normalize_comp(chunks);
+        }
+        Expression::Await(ref mut e)
+        | Expression::YieldFrom(ref mut e)
+        | Expression::Star(ref mut e)
+        | Expression::Uop(_, ref mut e)
+        | Expression::Attribute(ref mut e, _) => normalize_expression(e),
+        Expression::Call(ref mut func, ref mut args) => {
+            normalize_expression(func);
+            normalize_args(args);
+        }
+        Expression::Subscript(ref mut e, ref mut subs) => {
+            normalize_expression(e);
+            for sub in subs {
+                normalize_subscript(sub);
+            }
+        }
+        Expression::Bop(_, ref mut lhs, ref mut rhs)
+        | Expression::Named(ref mut lhs, ref mut rhs) => {
+            normalize_expression(lhs);
+            normalize_expression(rhs);
+        }
+        Expression::MultiBop(ref mut first, ref mut rest) => {
+            normalize_expression(first);
+            for &mut (_, ref mut e) in rest {
+                normalize_expression(e);
+            }
+        }
+        Expression::Ternary(ref mut e1, ref mut e2, ref mut e3) => {
+            normalize_expression(e1);
+            normalize_expression(e2);
+            normalize_expression(e3);
+        }
+        Expression::Yield(ref mut exprs) => normalize_expressions(exprs),
+        Expression::Lambdef(ref mut params, ref mut body) => {
+            normalize_untyped_params(params);
+            normalize_expression(body);
+        }
+    }
+}
+
+/// ASTs that can be normalized, and compared by `ast_eq`.
+pub trait Normalize: Clone + PartialEq {
+    /// Normalizes the AST, in place.
+    fn normalize(&mut self);
+}
+
+impl Normalize for Module {
+    fn normalize(&mut self) {
+        normalize_module(&mut self.body)
+    }
+}
+
+impl Normalize for Vec<Statement> {
+    fn normalize(&mut self) {
+        normalize_module(self)
+    }
+}
+
+impl Normalize for Statement {
+    fn normalize(&mut self) {
+        normalize_statement(self)
+    }
+}
+
+impl Normalize for Expression {
+    fn normalize(&mut self) {
+        normalize_expression(self)
+    }
+}
+
+/// Returns whether two ASTs are equal once normalized, ie. whether
+/// they only differ by formatting or by equivalent spellings.
+///
+/// The AST does not store comments, so to compare two `Module`s while
+/// ignoring their comments, compare their `body`.
+///
+/// ```
+/// use python_parser::{file_input, make_strspan};
+/// use python_parser::visitors::normalize::ast_eq;
+///
+/// let ast1 = file_input(make_strspan("x = ( 'a'  # comment\n  'b' )\n")).unwrap().1;
+/// let ast2 = file_input(make_strspan("x = \"ab\"\n")).unwrap().1;
+/// assert!(ast1 != ast2 && ast_eq(&ast1, &ast2));
+/// ```
+pub fn ast_eq<T: Normalize>(a: &T, b: &T) -> bool {
+    if a == b {
+        return true; // Normalization does not make equal ASTs different
+    }
+    let (mut a, mut b) = (a.clone(), b.clone());
+    a.normalize();
+    b.normalize();
+    a == b
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use helpers::make_strspan;
+    use {file_input, parse_expression};
+
+    fn normalized(code: &str) -> Vec<Statement> {
+        let (rest, mut ast) = file_input(make_strspan(code)).unwrap();
+        assert_eq!(rest.fragment.0, "");
+        normalize_module(&mut ast);
+        ast
+    }
+
+    fn assert_same(code1: &str, code2: &str) {
+        assert_eq!(normalized(code1), normalized(code2), "{:?}", (code1, code2));
+    }
+
+    #[test]
+    fn test_equivalent() {
+        assert_same("f(b=1, a=2)\n", "f(a=2, b=1)\n");
+        assert_same("f(x, c=1, *y, b=2, a=3)\n", "f(x, c=1, *y, a=3, b=2)\n");
+        assert_same(
+            "@d(y=1, x=2)\nclass C(m=1, b=2): pass\n",
+            "@d(x=2, y=1)\nclass C(b=2, m=1): pass\n",
+        );
+        assert_same("'foo'\n", "\"foo\"\n");
+        assert_same("R'\\n' U'x'\n", "'\\\\nx'\n");
+        assert_same("'a' \"b\" '''c'''\n", "'abc'\n");
+        assert_same("Rf'{x}' F'{y}'\n", "f'{x}{y}'\n");
+        assert_same("x = 2j\n", "x = 2.0j\n");
+        assert_same("x[a:b:]\n", "x[a:b]\n");
+        assert_same(
+            "def f(x=g(b=1, a=2)): yield [i for i in h(d=1, c=2)]\n",
+            "def f(x=g(a=2, b=1)): yield [i for i in h(c=2, d=1)]\n",
+        );
+    }
+
+    #[test]
+    fn test_different() {
+        for &(code1, code2) in &[
+            ("f(a, b)\n", "f(b, a)\n"),
+            ("f(b=1, **k, a=2)\n", "f(a=2, **k, b=1)\n"),
+            ("f(b=g(), a=h())\n", "f(a=h(), b=g())\n"),
+            ("'a' f'b'\n", "f'ab'\n"),
+            ("x = 2\n", "x = 2.0\n"),
+            ("x[a:b:c]\n", "x[a:b]\n"),
+        ] {
+            assert_ne!(normalized(code1), normalized(code2), "{:?}", (code1, code2));
+        }
+    }
+
+    #[test]
+    fn test_ast_eq() {
+        let parse = |code| file_input(make_strspan(code)).unwrap().1;
+        assert!(ast_eq(
+            &parse("f(b=1, a=2)  # foo\n"),
+            &parse("f(\n    a=2,\n    b=1,\n)\n")
+        ));
+        assert!(!ast_eq(&parse("f(a, b)\n"), &parse("f(b, a)\n")));
+        // The calls would be made in another order
+        assert!(!ast_eq(
+            &parse("f(b=g(), a=h())\n"),
+            &parse("f(a=h(), b=g())\n")
+        ));
+        let e1 = parse_expression("1j + 'a'").unwrap();
+        let e2 = parse_expression("(1.0j + u\"a\")").unwrap();
+        assert!(ast_eq(&e1, &e2));
+        // The arguments are not modified
+        assert_ne!(e1, e2);
+    }
+
+    #[test]
+    fn test_forms() {
+        assert_eq!(
+            normalized("yield x\n"),
+            vec![Statement::Assignment(
+                small_list![Expression::Yield(vec![Expression::Name("x".to_string())])],
+                vec![]
+            )]
+        );
+        let mut e = Expression::MultiBop(
+            Box::new(Expression::Name("a".to_string())),
+            vec![(Bop::Lt, Expression::Name("b".to_string()))],
+        );
+        normalize_expression(&mut e);
+        assert_eq!(e, parse_expression("a < b").unwrap());
+    }
+}
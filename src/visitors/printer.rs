@@ -40,6 +40,25 @@ pub fn format_module(stmts: &[Statement]) -> String {
     s
 }
 
+/// Like `format_module`, but also prints the shebang and leading
+/// comments of the module.
+pub fn format_file(module: &Module) -> String {
+    let mut s = "".to_string();
+    if let Some(ref shebang) = module.shebang {
+        s.push_str(shebang);
+        s.push('\n');
+    }
+    for comment in &module.leading_comments {
+        s.push_str(comment);
+        s.push('\n');
+    }
+    s.push_str(&format_module(&module.body));
+    if !module.trailing_newline && s.ends_with('\n') {
+        s.pop();
+    }
+    s
+}
+
 fn push_indent(indent: usize, s: &mut String) {
     for _ in 0..indent {
         s.push_str(" ")
@@ -137,6 +156,10 @@ fn format_statement(indent: usize, stmt: &Statement) -> String {
             ));
         }
         Statement::Compound(ref stmt) => s.push_str(&format_compound_statement(indent, stmt)),
+        Statement::Invalid(ref code) | Statement::Extension(_, ref code) => {
+            s.push_str(code);
+            s.push('\n');
+        }
     }
     s
 }
@@ -517,7 +540,11 @@ fn format_string(v: &Vec<PyString>) -> String {
         |&PyString {
              ref prefix,
              ref content,
+             ref raw,
          }| {
+            if let Some(ref raw) = *raw {
+                return raw.clone();
+            }
             format!(
                 "{}\"{}\"",
                 prefix.to_ascii_lowercase().replace("r", ""),
@@ -548,7 +575,11 @@ fn format_string(v: &Vec<PyString>) -> String {
         |&PyString {
              ref prefix,
              ref content,
+             ref raw,
          }| {
+            if let Some(ref raw) = *raw {
+                return raw.clone();
+            }
             format!(
                 "{}\"{}\"",
                 prefix.to_ascii_lowercase().replace("r", ""),
@@ -814,7 +845,7 @@ mod tests {
             vec![
                 ComprehensionChunk::For {
                     async: false,
-                    item: vec![Expression::Name("a".to_string())],
+                    item: small_list![Expression::Name("a".to_string())],
                     iterator: Expression::Name("L".to_string()),
                 },
                 ComprehensionChunk::If {
@@ -843,4 +874,16 @@ mod tests {
         );
         assert_eq!(&format_expr(&e), "foo := (bar)");
     }
+
+    #[test]
+    fn test_format_file() {
+        let module = Module {
+            shebang: Some("#!/usr/bin/python3".to_string()),
+            encoding: None,
+            leading_comments: vec!["# foo".to_string()],
+            trailing_newline: false,
+            body: vec![Statement::Pass],
+        };
+        assert_eq!(&format_file(&module), "#!/usr/bin/python3\n# foo\npass");
+    }
 }
@@ -0,0 +1,575 @@
+//! Removal of the branches of `if` statements which are never run on a
+//! target version of Python or platform, like the `else` of `if
+//! sys.version_info >= (3, 7):` when targeting Python 3.8, to vendor or
+//! minify code for a single version or make platform-specific bundles.
+//!
+//! Conditions are evaluated statically when they compare
+//! `sys.version_info` (or `sys.version_info[:2]`, `sys.version_info[0]`,
+//! `sys.version_info.minor`, etc.), `sys.platform` or `os.name` to
+//! literals, possibly combined with `not`, `and` and `or`, or call
+//! `startswith` on `sys.platform` or `os.name`. Other conditions are
+//! kept, and so are the branches which cannot be removed (see
+//! `simplify`).
+//!
+//! Blocks left empty get a `pass` statement.
+
+use std::cmp::Ordering;
+use std::mem;
+
+use ast::*;
+use visitors::simplify::is_removable;
+use visitors::walk_mut::{walk_block_mut, walk_statement_mut, VisitorMut};
+
+/// Where the code runs. Conditions on what is `None` are kept.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Default)]
+pub struct Target {
+    /// The major and minor version of Python.
+    pub version: Option<(u8, u8)>,
+    /// The value of `sys.platform`, like `linux`, `win32` or `darwin`.
+    pub platform: Option<String>,
+    /// The value of `os.name`, like `posix` or `nt`.
+    pub os_name: Option<String>,
+}
+
+/// A branch removed from an `if` statement.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Removed {
+    /// The condition of the branch, or `None` for an `else` branch.
+    pub condition: Option<Expression>,
+    pub block: Vec<Statement>,
+}
+
+/// A value known from the target.
+enum Known {
+    Int(u8),
+    /// The first elements of a tuple, and whether they are all of it
+    /// (`sys.version_info[:2]`) or only its start (`sys.version_info`).
+    Tuple(Vec<u8>, bool),
+    Str(String),
+}
+
+/// A literal integer, tuple of integers or string.
+enum Literal<'a> {
+    Int(&'a IntegerType),
+    Tuple(Vec<&'a IntegerType>),
+    Str(String),
+}
+
+/// Returns whether `expr` is `module.name`.
+fn is_attribute(expr: &Expression, module: &str, name: &str) -> bool {
+    match *expr {
+        Expression::Attribute(ref value, ref attribute) => {
+            attribute == name && **value == Expression::Name(module.to_string())
+        }
+        _ => false,
+    }
+}
+
+fn is_version_info(expr: &Expression) -> bool {
+    is_attribute(expr, "sys", "version_info")
+}
+
+/// Returns the value of `expr` if it is known from the target.
+fn known(expr: &Expression, target: &Target) -> Option<Known> {
+    if is_attribute(expr, "sys", "platform") {
+        return target.platform.clone().map(Known::Str);
+    }
+    if is_attribute(expr, "os", "name") {
+        return target.os_name.clone().map(Known::Str);
+    }
+    let (major, minor) = target.version?;
+    let int = |expr: &Expression| match *expr {
+        Expression::Int(ref n) if *n == IntegerType::from(0u8) => Some(0),
+        Expression::Int(ref n) if *n == IntegerType::from(1u8) => Some(1),
+        Expression::Int(ref n) if *n == IntegerType::from(2u8) => Some(2),
+        _ => None,
+    };
+    match *expr {
+        _ if is_version_info(expr) => Some(Known::Tuple(vec![major, minor], false)),
+        Expression::Attribute(ref value, ref name) if is_version_info(value) => match &name[..] {
+            "major" => Some(Known::Int(major)),
+            "minor" => Some(Known::Int(minor)),
+            _ => None,
+        },
+        Expression::Subscript(ref value, ref subscripts) if is_version_info(value) => {
+            match subscripts[..] {
+                [Subscript::Simple(ref index)] => match int(index)? {
+                    0 => Some(Known::Int(major)),
+                    1 => Some(Known::Int(minor)),
+                    _ => None,
+                },
+                [Subscript::Double(None, Some(ref end))] => match int(end)? {
+                    0 => Some(Known::Tuple(vec![], true)),
+                    1 => Some(Known::Tuple(vec![major], true)),
+                    _ => Some(Known::Tuple(vec![major, minor], true)),
+                },
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+#[cfg(feature = "wtf8")]
+fn content_to_string(content: &PyStringContent) -> String {
+    content.to_string_lossy().into_owned()
+}
+
+#[cfg(not(feature = "wtf8"))]
+fn content_to_string(content: &PyStringContent) -> String {
+    content.clone()
+}
+
+fn string(expr: &Expression) -> Option<String> {
+    match *expr {
+        Expression::String(ref strings) => {
+            if strings.iter().any(|s| s.prefix.contains(&['f', 'F'][..])) {
+                return None;
+            }
+            Some(
+                strings
+                    .iter()
+                    .map(|s| content_to_string(&s.content))
+                    .collect(),
+            )
+        }
+        _ => None,
+    }
+}
+
+/// Returns the strings of a literal tuple, list or set of strings.
+fn strings(expr: &Expression) -> Option<Vec<String>> {
+    match *expr {
+        Expression::TupleLiteral(ref items)
+        | Expression::ListLiteral(ref items)
+        | Expression::SetLiteral(ref items) => items
+            .iter()
+            .map(|item| match *item {
+                SetItem::Unique(ref item) => string(item),
+                SetItem::Star(_) => None,
+            })
+            .collect(),
+        _ => None,
+    }
+}
+
+fn literal(expr: &Expression) -> Option<Literal<'_>> {
+    match *expr {
+        Expression::Int(ref n) => Some(Literal::Int(n)),
+        Expression::TupleLiteral(ref items) => {
+            let integers = items.iter().map(|item| match *item {
+                SetItem::Unique(Expression::Int(ref n)) => Some(n),
+                _ => None,
+            });
+            Some(Literal::Tuple(integers.collect::<Option<_>>()?))
+        }
+        _ => string(expr).map(Literal::Str),
+    }
+}
+
+/// Compares a known value to a literal of the same type, or returns
+/// `None` if the order is not known.
+fn compare(known: &Known, literal: &Literal) -> Option<Ordering> {
+    match (known, literal) {
+        (&Known::Int(known), &Literal::Int(literal)) => Some(IntegerType::from(known).cmp(literal)),
+        (&Known::Tuple(ref known, exact), &Literal::Tuple(ref literal)) => {
+            for (&element, &integer) in known.iter().zip(literal) {
+                match IntegerType::from(element).cmp(integer) {
+                    Ordering::Equal => (),
+                    ordering => return Some(ordering),
+                }
+            }
+            match known.len().cmp(&literal.len()) {
+                Ordering::Less if exact => Some(Ordering::Less),
+                Ordering::Less => None,
+                Ordering::Equal if exact => Some(Ordering::Equal),
+                // `sys.version_info` has more elements
+                _ => Some(Ordering::Greater),
+            }
+        }
+        (&Known::Str(ref known), &Literal::Str(ref literal)) => Some(known.cmp(literal)),
+        _ => None,
+    }
+}
+
+fn same_type(known: &Known, literal: &Literal) -> bool {
+    match (known, literal) {
+        (&Known::Int(_), &Literal::Int(_))
+        | (&Known::Tuple(..), &Literal::Tuple(_))
+        | (&Known::Str(_), &Literal::Str(_)) => true,
+        _ => false,
+    }
+}
+
+/// Evaluates `left <op> right`.
+fn comparison(op: Bop, left: &Expression, right: &Expression, target: &Target) -> Option<bool> {
+    if op == Bop::In || op == Bop::NotIn {
+        // `sys.platform in ("linux", "darwin")`
+        let known = match known(left, target)? {
+            Known::Str(known) => known,
+            _ => return None,
+        };
+        return Some(strings(right)?.contains(&known) == (op == Bop::In));
+    }
+    let (op, known, literal) = match (known(left, target), known(right, target)) {
+        (Some(known), None) => (op, known, literal(right)?),
+        (None, Some(known)) => {
+            let op = match op {
+                Bop::Lt => Bop::Gt,
+                Bop::Gt => Bop::Lt,
+                Bop::Leq => Bop::Geq,
+                Bop::Geq => Bop::Leq,
+                op => op,
+            };
+            (op, known, literal(left)?)
+        }
+        _ => return None,
+    };
+    if !same_type(&known, &literal) {
+        // Values of different types are never equal, and not ordered
+        return match op {
+            Bop::Eq => Some(false),
+            Bop::Neq => Some(true),
+            _ => None,
+        };
+    }
+    let ordering = compare(&known, &literal)?;
+    match op {
+        Bop::Lt => Some(ordering == Ordering::Less),
+        Bop::Gt => Some(ordering == Ordering::Greater),
+        Bop::Leq => Some(ordering != Ordering::Greater),
+        Bop::Geq => Some(ordering != Ordering::Less),
+        Bop::Eq => Some(ordering == Ordering::Equal),
+        Bop::Neq => Some(ordering != Ordering::Equal),
+        _ => None,
+    }
+}
+
+/// Evaluates `value.startswith(prefix)` where `value` is known.
+fn startswith(function: &Expression, args: &[Argument], target: &Target) -> Option<bool> {
+    let known = match *function {
+        Expression::Attribute(ref value, ref name) if name == "startswith" => {
+            match known(value, target)? {
+                Known::Str(known) => known,
+                _ => return None,
+            }
+        }
+        _ => return None,
+    };
+    match *args {
+        [Argument::Positional(ref prefix)] => match string(prefix) {
+            Some(prefix) => Some(known.starts_with(&prefix[..])),
+            // `sys.platform.startswith(("linux", "darwin"))`
+            None => Some(strings(prefix)?.iter().any(|p| known.starts_with(&p[..]))),
+        },
+        _ => None,
+    }
+}
+
+/// Evaluates a condition, or returns `None` if its value is not known
+/// or it has operands which are not known evaluated before the ones
+/// giving its value (which may have side effects).
+fn evaluate(expr: &Expression, target: &Target) -> Option<bool> {
+    match *expr {
+        Expression::Uop(Uop::Not, ref operand) => evaluate(operand, target).map(|value| !value),
+        Expression::Bop(op @ Bop::And, ref left, ref right)
+        | Expression::Bop(op @ Bop::Or, ref left, ref right) => {
+            chain(op, &[&**left, &**right], target)
+        }
+        Expression::Bop(op, ref left, ref right) => comparison(op, left, right, target),
+        Expression::Call(ref function, ref args) => startswith(function, args, target),
+        Expression::MultiBop(ref first, ref rest) => {
+            let op = rest.first()?.0;
+            if op == Bop::And || op == Bop::Or {
+                if rest.iter().any(|&(other, _)| other != op) {
+                    return None;
+                }
+                let operands: Vec<_> = Some(&**first)
+                    .into_iter()
+                    .chain(rest.iter().map(|&(_, ref e)| e))
+                    .collect();
+                return chain(op, &operands, target);
+            }
+            // `a < b < c` is `a < b and b < c`
+            let mut left = &**first;
+            for &(op, ref right) in rest {
+                if !comparison(op, left, right, target)? {
+                    return Some(false);
+                }
+                left = right;
+            }
+            Some(true)
+        }
+        _ => None,
+    }
+}
+
+/// Evaluates a chain of `and` or `or`.
+fn chain(op: Bop, operands: &[&Expression], target: &Target) -> Option<bool> {
+    // `a and b` is false as soon as an operand is false
+    let short_circuit = op == Bop::Or;
+    for operand in operands {
+        if evaluate(operand, target)? == short_circuit {
+            return Some(short_circuit);
+        }
+    }
+    Some(!short_circuit)
+}
+
+/// Returns the statements replacing an `if` statement whose branches
+/// were pruned, or `None` if it is kept.
+fn prune_if(
+    cond_blocks: &mut Vec<(Expression, Vec<Statement>)>,
+    else_block: &mut Option<Vec<Statement>>,
+    target: &Target,
+    in_function: bool,
+    removed: &mut Vec<Removed>,
+) -> Option<Vec<Statement>> {
+    let is_removable = |block: &[Statement]| is_removable(block, in_function);
+    let mut kept = Vec::new();
+    let mut branches = mem::take(cond_blocks).into_iter();
+    while let Some((cond, block)) = branches.next() {
+        match evaluate(&cond, target) {
+            Some(false) if is_removable(&block) => removed.push(Removed {
+                condition: Some(cond),
+                block,
+            }),
+            Some(true) => {
+                // The next branches are never run
+                let rest: Vec<_> = branches.by_ref().collect();
+                let removable = rest.iter().all(|&(_, ref block)| is_removable(block))
+                    && else_block
+                        .as_ref()
+                        .map_or(true, |block| is_removable(block));
+                if removable {
+                    removed.extend(rest.into_iter().map(|(cond, block)| Removed {
+                        condition: Some(cond),
+                        block,
+                    }));
+                    if let Some(block) = else_block.take() {
+                        removed.push(Removed {
+                            condition: None,
+                            block,
+                        });
+                    }
+                    *else_block = Some(block);
+                } else {
+                    kept.push((cond, block));
+                    kept.extend(rest);
+                }
+                break;
+            }
+            _ => kept.push((cond, block)),
+        }
+    }
+    *cond_blocks = kept;
+    if cond_blocks.is_empty() {
+        Some(else_block.take().unwrap_or_default())
+    } else {
+        None
+    }
+}
+
+struct Pruner<'a> {
+    target: &'a Target,
+    /// Whether the statements are in a function.
+    in_function: bool,
+    removed: Vec<Removed>,
+}
+
+impl<'a> VisitorMut for Pruner<'a> {
+    fn visit_block(&mut self, stmts: &mut Vec<Statement>) {
+        walk_block_mut(self, stmts);
+        let was_empty = stmts.is_empty();
+        for mut stmt in mem::take(stmts) {
+            let replacement = match stmt {
+                Statement::Compound(ref mut compound) => match **compound {
+                    CompoundStatement::If(ref mut cond_blocks, ref mut else_block) => prune_if(
+                        cond_blocks,
+                        else_block,
+                        self.target,
+                        self.in_function,
+                        &mut self.removed,
+                    ),
+                    _ => None,
+                },
+                _ => None,
+            };
+            match replacement {
+                Some(replacement) => stmts.extend(replacement),
+                None => stmts.push(stmt),
+            }
+        }
+        if stmts.is_empty() && !was_empty {
+            stmts.push(Statement::Pass);
+        }
+    }
+
+    fn visit_statement(&mut self, stmt: &mut Statement) {
+        let in_function = self.in_function;
+        if let Statement::Compound(ref compound) = *stmt {
+            if let CompoundStatement::Funcdef(_) = **compound {
+                self.in_function = true;
+            }
+        }
+        walk_statement_mut(self, stmt);
+        self.in_function = in_function;
+    }
+}
+
+/// Removes the branches of the `if` statements of a module which are
+/// never run on `target`, in place, and returns them, innermost first.
+///
+/// ```
+/// use python_parser::parse_module;
+/// use python_parser::visitors::prune::{prune_module, Target};
+///
+/// let code = "import os\nif os.name == 'nt':\n    import msvcrt\nelse:\n    import termios\n";
+/// let mut module = parse_module(code).unwrap();
+/// let target = Target { os_name: Some("posix".to_string()), ..Target::default() };
+/// let removed = prune_module(&mut module.body, &target);
+/// assert_eq!(removed.len(), 1);
+/// assert_eq!(removed[0].block, parse_module("import msvcrt\n").unwrap().body);
+/// assert_eq!(module.body, parse_module("import os\nimport termios\n").unwrap().body);
+///
+/// let code = "import sys\nif sys.version_info >= (3, 8):\n    from typing import Literal\n\
+///             else:\n    from typing_extensions import Literal\n";
+/// let mut module = parse_module(code).unwrap();
+/// let target = Target { version: Some((3, 9)), ..Target::default() };
+/// let removed = prune_module(&mut module.body, &target);
+/// assert_eq!(removed[0].condition, None);
+/// let expected = "import sys\nfrom typing import Literal\n";
+/// assert_eq!(module.body, parse_module(expected).unwrap().body);
+/// ```
+pub fn prune_module(stmts: &mut Vec<Statement>, target: &Target) -> Vec<Removed> {
+    let mut pruner = Pruner {
+        target,
+        in_function: false,
+        removed: Vec::new(),
+    };
+    pruner.visit_block(stmts);
+    pruner.removed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parse_expression;
+    use parse_module;
+
+    #[test]
+    fn test_evaluate() {
+        let target = Target {
+            version: Some((3, 8)),
+            platform: Some("linux".to_string()),
+            os_name: Some("posix".to_string()),
+        };
+        let evaluated = |code: &str| evaluate(&parse_expression(code).unwrap(), &target);
+        let cases = [
+            ("sys.version_info >= (3, 8)", Some(true)),
+            ("sys.version_info > (3, 8)", Some(true)),
+            ("sys.version_info == (3, 8)", Some(false)),
+            ("sys.version_info < (3,)", Some(false)),
+            ("sys.version_info >= (3, 8, 1)", None),
+            ("sys.version_info >= (3, 9, 1)", Some(false)),
+            ("sys.version_info[:2] == (3, 8)", Some(true)),
+            ("sys.version_info[:2] < (3, 8, 0)", Some(true)),
+            ("(3, 7) < sys.version_info[:2] <= (3, 8)", Some(true)),
+            ("(3, 9) <= sys.version_info", Some(false)),
+            ("sys.version_info[0] == 2", Some(false)),
+            ("sys.version_info.minor >= 7", Some(true)),
+            ("sys.version_info[0] == (3,)", Some(false)),
+            ("sys.version_info[0] < (3,)", None),
+            ("not sys.version_info.major < 3", Some(true)),
+            ("sys.version_info < (3,) or x", None),
+            ("sys.version_info >= (3,) or x", Some(true)),
+            ("x or sys.version_info >= (3,)", None),
+            (
+                "sys.version_info >= (3,) and sys.version_info < (3, 5) and x",
+                Some(false),
+            ),
+            ("version_info >= (3,)", None),
+            ("sys.version_info >= x", None),
+            ("sys.platform == 'win32'", Some(false)),
+            ("'linux' == sys.platform", Some(true)),
+            ("sys.platform != 'lin' 'ux'", Some(false)),
+            ("sys.platform == 3", Some(false)),
+            ("sys.platform.startswith('linux')", Some(true)),
+            ("sys.platform.startswith(('win', 'cygwin'))", Some(false)),
+            ("sys.platform.startswith(prefix)", None),
+            ("sys.platform in ('darwin', 'linux')", Some(true)),
+            ("sys.platform not in ['darwin', 'linux']", Some(false)),
+            ("os.name == 'nt' or sys.platform == 'cygwin'", Some(false)),
+            ("os.name == f'nt'", None),
+            ("os.path == 'nt'", None),
+        ];
+        for &(code, expected) in &cases {
+            assert_eq!(evaluated(code), expected, "{}", code);
+        }
+        let unknown = Target::default();
+        assert_eq!(
+            evaluate(
+                &parse_expression("sys.version_info >= (3,)").unwrap(),
+                &unknown
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn test_prune_module() {
+        let code = "if sys.version_info < (3,):
+    a
+elif sys.version_info < (3, 8):
+    b
+elif x:
+    c
+else:
+    d
+def f():
+    if sys.version_info[0] == 2:
+        e
+    if sys.version_info >= (3, 7):
+        f
+    elif x:
+        g
+    if sys.version_info < (3, 7):
+        yield
+    if x:
+        pass
+    elif sys.version_info > (3, 9):
+        h
+";
+        let expected = "if x:
+    c
+else:
+    d
+def f():
+    f
+    if sys.version_info < (3, 7):
+        yield
+    if x:
+        pass
+";
+        let mut module = parse_module(code).unwrap();
+        let target = Target {
+            version: Some((3, 8)),
+            ..Target::default()
+        };
+        let removed = prune_module(&mut module.body, &target);
+        let blocks: Vec<_> = removed.iter().map(|removed| &removed.block).collect();
+        let statement = |code| parse_module(code).unwrap().body;
+        let expected_blocks = ["e\n", "g\n", "h\n", "a\n", "b\n"];
+        let expected_blocks: Vec<_> = expected_blocks.iter().map(|code| statement(code)).collect();
+        assert_eq!(blocks, expected_blocks.iter().collect::<Vec<_>>());
+        assert_eq!(
+            removed[1].condition,
+            Some(Expression::Name("x".to_string()))
+        );
+        assert_eq!(
+            removed[2].condition,
+            Some(parse_expression("sys.version_info > (3, 9)").unwrap())
+        );
+        assert_eq!(module.body, parse_module(expected).unwrap().body);
+    }
+}
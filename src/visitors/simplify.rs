@@ -0,0 +1,607 @@
+//! Simplification of boolean expressions and conditional statements,
+//! preserving the semantics of the code:
+//!
+//! * `and` and `or` with constant operands (`True and x` is `x`, and
+//!   `0 or x` is `x`), conditional expressions with a constant
+//!   condition, and `not` of a constant,
+//! * `not not x` when `x` is a `not` expression (whose value is a
+//!   bool), and negated identity and membership tests (`not a is b` is
+//!   `a is not b`, and `not a not in b` is `a in b`); comparisons like
+//!   `not a == b` are kept, since `__ne__` may not be the negation of
+//!   `__eq__`,
+//! * in conditions, where only the truthiness of values matters:
+//!   `not not x` and `x and True` are `x`,
+//! * `if` statements with constant conditions (`if True:` is replaced by
+//!   its block, and `elif False:` branches are removed), and
+//!   `while False:` loops.
+//!
+//! Constants are the literals whose truthiness is known: `True`,
+//! `False`, `None`, `...`, numbers, strings (not f-strings) and bytes.
+//!
+//! Branches with a `yield` (which makes the function a generator) or a
+//! `global` or `nonlocal` declaration are never removed, and neither are
+//! branches binding names in a function: the names would no longer be
+//! local variables, so reading them before they are assigned would read
+//! a global instead of raising an `UnboundLocalError`.
+
+use std::mem;
+
+use ast::*;
+use visitors::target_names;
+use visitors::walk::{walk_expression, walk_statement, Visitor};
+use visitors::walk_mut::{walk_block_mut, walk_expression_mut, walk_statement_mut, VisitorMut};
+
+#[cfg(feature = "wtf8")]
+fn is_empty_content(content: &PyStringContent) -> bool {
+    content.len() == 0 // Wtf8 has no is_empty
+}
+
+#[cfg(not(feature = "wtf8"))]
+fn is_empty_content(content: &PyStringContent) -> bool {
+    content.is_empty()
+}
+
+/// Returns the truthiness of `expr` if it is a constant.
+fn truthiness(expr: &Expression) -> Option<bool> {
+    match *expr {
+        Expression::True | Expression::Ellipsis => Some(true),
+        Expression::False | Expression::None => Some(false),
+        Expression::Int(ref n) | Expression::ImaginaryInt(ref n) => {
+            Some(*n != IntegerType::from(0u8))
+        }
+        Expression::Float(f) | Expression::ImaginaryFloat(f) => Some(f != 0.),
+        Expression::String(ref strings) => {
+            if strings.iter().any(|s| s.prefix.contains(&['f', 'F'][..])) {
+                None
+            } else {
+                Some(strings.iter().any(|s| !is_empty_content(&s.content)))
+            }
+        }
+        Expression::Bytes(ref bytes) => Some(!bytes.is_empty()),
+        _ => None,
+    }
+}
+
+fn bool_expression(value: bool) -> Expression {
+    if value {
+        Expression::True
+    } else {
+        Expression::False
+    }
+}
+
+/// Returns the operands of `expr` if it is a chain of `op` (`And` or
+/// `Or`).
+fn take_chain(expr: &mut Expression, op: Bop) -> Option<Vec<Expression>> {
+    match *expr {
+        Expression::Bop(bop, ref mut left, ref mut right) if bop == op => Some(vec![
+            mem::replace(&mut **left, Expression::None),
+            mem::replace(&mut **right, Expression::None),
+        ]),
+        Expression::MultiBop(ref mut first, ref mut rest)
+            if rest.iter().all(|&(bop, _)| bop == op) =>
+        {
+            let mut operands = vec![mem::replace(&mut **first, Expression::None)];
+            operands.extend(rest.drain(..).map(|(_, e)| e));
+            Some(operands)
+        }
+        _ => None,
+    }
+}
+
+/// Builds a chain of `op` from (at least one) operands.
+fn make_chain(op: Bop, mut operands: Vec<Expression>) -> Expression {
+    match operands.len() {
+        1 => operands.remove(0),
+        2 => {
+            let right = operands.remove(1);
+            let left = operands.remove(0);
+            Expression::Bop(op, Box::new(left), Box::new(right))
+        }
+        _ => {
+            let first = operands.remove(0);
+            let rest = operands.into_iter().map(|e| (op, e)).collect();
+            Expression::MultiBop(Box::new(first), rest)
+        }
+    }
+}
+
+/// Returns the operator of `expr` if it is a chain of `and` or `or`.
+fn chain_operator(expr: &Expression) -> Option<Bop> {
+    let op = match *expr {
+        Expression::Bop(op, _, _) => op,
+        Expression::MultiBop(_, ref rest) => {
+            let op = rest.first()?.0;
+            if rest.iter().any(|&(other, _)| other != op) {
+                return None;
+            }
+            op
+        }
+        _ => return None,
+    };
+    if op == Bop::And || op == Bop::Or {
+        Some(op)
+    } else {
+        None
+    }
+}
+
+/// Returns the operands of a chain of `and` or `or`.
+fn chain_operands(expr: &mut Expression) -> Vec<&mut Expression> {
+    match *expr {
+        Expression::Bop(_, ref mut left, ref mut right) => vec![left, right],
+        Expression::MultiBop(ref mut first, ref mut rest) => Some(&mut **first)
+            .into_iter()
+            .chain(rest.iter_mut().map(|(_, e)| e))
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Simplifies a chain of `and` or `or`. `condition` is whether only
+/// the truthiness of its value matters.
+fn simplify_chain(expr: &mut Expression, condition: bool) {
+    let op = match chain_operator(expr) {
+        Some(op) => op,
+        None => return,
+    };
+    // The value of `a and b` is `a` if it is falsy, and `b` otherwise
+    let short_circuit = op == Bop::Or;
+    let operands = match take_chain(expr, op) {
+        Some(operands) => operands,
+        None => return,
+    };
+    let count = operands.len();
+    let mut kept = Vec::new();
+    for (i, operand) in operands.into_iter().enumerate() {
+        match truthiness(&operand) {
+            // The operand is skipped, unless it is the value of the
+            // whole chain
+            Some(value) if value != short_circuit && (i + 1 < count || condition) => {
+                if i + 1 == count && kept.is_empty() {
+                    kept.push(operand);
+                }
+            }
+            // The operand is the value of the chain
+            Some(value) if value == short_circuit => {
+                kept.push(operand);
+                break;
+            }
+            _ => kept.push(operand),
+        }
+    }
+    *expr = make_chain(op, kept);
+}
+
+/// Simplifies `expr`, whose children are already simplified.
+fn simplify_value(expr: &mut Expression) {
+    match *expr {
+        Expression::Uop(Uop::Not, ref mut operand) => {
+            if let Some(value) = truthiness(operand) {
+                *expr = bool_expression(!value);
+                return;
+            }
+            let inverse = match **operand {
+                Expression::Bop(Bop::Is, _, _) => Some(Bop::IsNot),
+                Expression::Bop(Bop::IsNot, _, _) => Some(Bop::Is),
+                Expression::Bop(Bop::In, _, _) => Some(Bop::NotIn),
+                Expression::Bop(Bop::NotIn, _, _) => Some(Bop::In),
+                // `not not (not x)` is `not x`, which is a bool
+                Expression::Uop(Uop::Not, ref inner) => match **inner {
+                    Expression::Uop(Uop::Not, _) => {
+                        let inner = mem::replace(&mut **operand, Expression::None);
+                        if let Expression::Uop(_, inner) = inner {
+                            *expr = *inner;
+                        }
+                        return;
+                    }
+                    _ => None,
+                },
+                _ => None,
+            };
+            if let Some(inverse) = inverse {
+                if let Expression::Bop(ref mut op, _, _) = **operand {
+                    *op = inverse;
+                }
+                let operand = mem::replace(&mut **operand, Expression::None);
+                *expr = operand;
+            }
+        }
+        _ if chain_operator(expr).is_some() => simplify_chain(expr, false),
+        Expression::Ternary(ref mut then, ref cond, ref mut else_) => {
+            let branch = match truthiness(cond) {
+                Some(true) => then,
+                Some(false) => else_,
+                None => return,
+            };
+            let branch = mem::replace(&mut **branch, Expression::None);
+            *expr = branch;
+        }
+        _ => (),
+    }
+}
+
+/// Simplifies a condition, whose value is already simplified.
+fn simplify_condition(expr: &mut Expression) {
+    match *expr {
+        Expression::Uop(Uop::Not, ref mut operand) => {
+            simplify_condition(operand);
+            if let Expression::Uop(Uop::Not, ref mut inner) = **operand {
+                let inner = mem::replace(&mut **inner, Expression::None);
+                *expr = inner;
+                simplify_condition(expr);
+            }
+        }
+        _ if chain_operator(expr).is_some() => {
+            simplify_chain(expr, true);
+            if chain_operator(expr).is_some() {
+                // The operands are conditions too
+                for operand in chain_operands(expr) {
+                    simplify_condition(operand);
+                }
+            } else {
+                // Reduced to one of its operands
+                simplify_condition(expr);
+            }
+        }
+        _ => (),
+    }
+}
+
+/// Whether assigning to `target` binds a name.
+fn binds_name(target: &Expression) -> bool {
+    let mut names = Vec::new();
+    target_names(target, &mut names);
+    !names.is_empty()
+}
+
+/// Pushes the names bound by `stmt` itself (not by the statements of
+/// its blocks) in its scope to `names`.
+pub(crate) fn bound_names<'a>(stmt: &'a Statement, names: &mut Vec<&'a str>) {
+    match *stmt {
+        Statement::Del(ref targets)
+        | Statement::TypeAnnotation(ref targets, _)
+        | Statement::TypedAssignment(ref targets, _, _)
+        | Statement::AugmentedAssignment(ref targets, _, _) => {
+            for target in targets {
+                target_names(target, names);
+            }
+        }
+        Statement::Assignment(ref lhs, ref rhs) => {
+            if let Some((_, targets)) = rhs.split_last() {
+                for target in lhs.iter().chain(targets.iter().flatten()) {
+                    target_names(target, names);
+                }
+            }
+        }
+        Statement::Import(Import::ImportFrom {
+            names: ref imported,
+            ..
+        }) => {
+            for (name, alias) in imported {
+                names.push(alias.as_ref().unwrap_or(name));
+            }
+        }
+        Statement::Import(Import::Import {
+            names: ref imported,
+        }) => {
+            for (path, alias) in imported {
+                match *alias {
+                    Some(ref alias) => names.push(alias),
+                    None => names.extend(path.first().map(|s| &s[..])),
+                }
+            }
+        }
+        Statement::Compound(ref compound) => match **compound {
+            CompoundStatement::For { ref item, .. } => {
+                for target in item {
+                    target_names(target, names);
+                }
+            }
+            CompoundStatement::With(ref items, _) => {
+                for (_, target) in items {
+                    if let Some(ref target) = *target {
+                        target_names(target, names);
+                    }
+                }
+            }
+            CompoundStatement::Funcdef(ref funcdef) => names.push(&funcdef.name),
+            CompoundStatement::Classdef(ref classdef) => names.push(&classdef.name),
+            CompoundStatement::Try(ref try_) => {
+                for (_, name, _) in &try_.except_clauses {
+                    if let Some(ref name) = *name {
+                        names.push(name);
+                    }
+                }
+            }
+            _ => (),
+        },
+        _ => (),
+    }
+}
+
+/// Whether `stmt` itself (not the statements of its blocks) binds a
+/// name.
+fn binds(stmt: &Statement) -> bool {
+    let mut names = Vec::new();
+    bound_names(stmt, &mut names);
+    !names.is_empty()
+}
+
+/// Finds statements which may not be removed.
+struct Unremovable {
+    in_function: bool,
+    found: bool,
+}
+
+impl Visitor for Unremovable {
+    fn visit_statement(&mut self, stmt: &Statement) {
+        if self.in_function && binds(stmt) {
+            self.found = true;
+        }
+        match *stmt {
+            Statement::Global(_) | Statement::Nonlocal(_) => self.found = true,
+            Statement::Compound(ref compound) => match **compound {
+                // Their own scopes
+                CompoundStatement::Funcdef(_) | CompoundStatement::Classdef(_) => return,
+                _ => (),
+            },
+            _ => (),
+        }
+        walk_statement(self, stmt)
+    }
+
+    fn visit_expression(&mut self, expr: &Expression) {
+        match *expr {
+            Expression::Yield(_) | Expression::YieldFrom(_) => self.found = true,
+            Expression::Named(ref target, _) if self.in_function && binds_name(target) => {
+                self.found = true
+            }
+            Expression::Lambdef(..) => return,
+            _ => (),
+        }
+        walk_expression(self, expr)
+    }
+}
+
+/// Whether removing `block` (if it is never run) preserves the
+/// semantics of the code, in a function if `in_function`.
+pub(crate) fn is_removable(block: &[Statement], in_function: bool) -> bool {
+    let mut visitor = Unremovable {
+        in_function,
+        found: false,
+    };
+    for stmt in block {
+        visitor.visit_statement(stmt);
+    }
+    !visitor.found
+}
+
+/// Returns the statements replacing a compound statement with constant
+/// conditions, or `None` if it is kept.
+fn simplify_compound(stmt: &mut CompoundStatement, in_function: bool) -> Option<Vec<Statement>> {
+    let is_removable = |block: &[Statement]| is_removable(block, in_function);
+    match *stmt {
+        CompoundStatement::If(ref mut cond_blocks, ref mut else_block) => {
+            // The branches after a condition which is always true are
+            // never run, and its block becomes the `else` block
+            let always = cond_blocks
+                .iter()
+                .position(|(cond, _)| truthiness(cond) == Some(true));
+            if let Some(i) = always {
+                let removable = cond_blocks[i + 1..]
+                    .iter()
+                    .all(|(_, block)| is_removable(block))
+                    && else_block.as_ref().is_none_or(|block| is_removable(block));
+                if removable {
+                    cond_blocks.truncate(i + 1);
+                    *else_block = cond_blocks.pop().map(|(_, block)| block);
+                }
+            }
+            cond_blocks
+                .retain(|(cond, block)| truthiness(cond) != Some(false) || !is_removable(block));
+            if cond_blocks.is_empty() {
+                Some(else_block.take().unwrap_or_default())
+            } else {
+                None
+            }
+        }
+        CompoundStatement::While(ref cond, ref block, ref mut else_block)
+            if truthiness(cond) == Some(false) && is_removable(block) =>
+        {
+            Some(else_block.take().unwrap_or_default())
+        }
+        _ => None,
+    }
+}
+
+struct Simplifier {
+    /// Whether the statements are in a function (possibly in a class
+    /// in the function).
+    in_function: bool,
+}
+
+impl VisitorMut for Simplifier {
+    fn visit_block(&mut self, stmts: &mut Vec<Statement>) {
+        walk_block_mut(self, stmts);
+        let was_empty = stmts.is_empty();
+        for mut stmt in mem::take(stmts) {
+            let replacement = match stmt {
+                Statement::Compound(ref mut compound) => {
+                    simplify_compound(compound, self.in_function)
+                }
+                _ => None,
+            };
+            match replacement {
+                Some(replacement) => stmts.extend(replacement),
+                None => stmts.push(stmt),
+            }
+        }
+        if stmts.is_empty() && !was_empty {
+            stmts.push(Statement::Pass);
+        }
+    }
+
+    fn visit_statement(&mut self, stmt: &mut Statement) {
+        let in_function = self.in_function;
+        if let Statement::Compound(ref compound) = *stmt {
+            if let CompoundStatement::Funcdef(_) = **compound {
+                self.in_function = true;
+            }
+        }
+        walk_statement_mut(self, stmt);
+        self.in_function = in_function;
+        match *stmt {
+            Statement::Assert(ref mut cond, _) => simplify_condition(cond),
+            Statement::Compound(ref mut compound) => match **compound {
+                CompoundStatement::If(ref mut cond_blocks, _) => {
+                    for (cond, _) in cond_blocks {
+                        simplify_condition(cond);
+                    }
+                }
+                CompoundStatement::While(ref mut cond, _, _) => simplify_condition(cond),
+                _ => (),
+            },
+            _ => (),
+        }
+    }
+
+    fn visit_expression(&mut self, expr: &mut Expression) {
+        walk_expression_mut(self, expr);
+        simplify_value(expr);
+        match *expr {
+            Expression::Ternary(_, ref mut cond, _) => simplify_condition(cond),
+            Expression::DictComp(_, ref mut chunks)
+            | Expression::SetComp(_, ref mut chunks)
+            | Expression::ListComp(_, ref mut chunks)
+            | Expression::Generator(_, ref mut chunks) => {
+                for chunk in chunks {
+                    if let ComprehensionChunk::If { ref mut cond } = *chunk {
+                        simplify_condition(cond);
+                    }
+                }
+            }
+            _ => (),
+        }
+    }
+}
+
+/// Simplifies an expression, in place.
+///
+/// ```
+/// use python_parser::parse_expression;
+/// use python_parser::visitors::simplify::simplify_expression;
+///
+/// let mut expr = parse_expression("True and not (a is not b) or c").unwrap();
+/// simplify_expression(&mut expr);
+/// assert_eq!(expr, parse_expression("a is b or c").unwrap());
+/// ```
+pub fn simplify_expression(expr: &mut Expression) {
+    Simplifier { in_function: false }.visit_expression(expr)
+}
+
+/// Simplifies the statements of a module, in place.
+pub fn simplify_module(stmts: &mut Vec<Statement>) {
+    Simplifier { in_function: false }.visit_block(stmts)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parse_expression;
+    use parse_module;
+
+    fn check_expression(code: &str, expected: &str) {
+        let mut expr = parse_expression(code).unwrap();
+        simplify_expression(&mut expr);
+        assert_eq!(expr, parse_expression(expected).unwrap(), "{}", code);
+    }
+
+    fn check_module(code: &str, expected: &str) {
+        let mut module = parse_module(code).unwrap();
+        simplify_module(&mut module.body);
+        assert_eq!(module.body, parse_module(expected).unwrap().body);
+    }
+
+    #[test]
+    fn test_values() {
+        check_expression("True and x", "x");
+        check_expression("x and True", "x and True");
+        check_expression("x and 0 and y", "x and 0");
+        check_expression("0 or '' or x or y", "x or y");
+        check_expression("x or None", "x or None");
+        check_expression("1 and 0", "0");
+        check_expression("not not x", "not not x");
+        check_expression("not not not x", "not x");
+        check_expression("not ''", "True");
+        check_expression("not a not in b", "a in b");
+        check_expression("not a == b", "not a == b");
+        check_expression("a if 1 else b", "a");
+        check_expression("f(b'' or x)", "f(x)");
+        check_expression(
+            "[x for x in y if not not x and True]",
+            "[x for x in y if x]",
+        );
+        check_expression("a if not not b else c", "a if b else c");
+    }
+
+    #[test]
+    fn test_statements() {
+        check_module(
+            "if True:
+    a
+    if x and not not y or False:
+        b
+elif c:
+    d
+if False:
+    e
+elif not x:
+    f
+elif 1:
+    g
+else:
+    h
+while 0:
+    i
+else:
+    j
+def k():
+    if False:
+        yield
+    assert not not x and 1
+    while not True:
+        pass
+",
+            "a
+if x and y:
+    b
+if not x:
+    f
+else:
+    g
+j
+def k():
+    if False:
+        yield
+    assert x
+",
+        );
+    }
+
+    #[test]
+    fn test_bindings_in_functions() {
+        // `x` is a local variable of `f`, so `print(x)` raises an
+        // `UnboundLocalError`
+        let code = "def f():\n    print(x)\n    if False:\n        x = 1\n";
+        check_module(code, code);
+        check_module(
+            "def f():\n    if False:\n        print(x)\n    while 0:\n        (y := 1)\n",
+            "def f():\n    while 0:\n        (y := 1)\n",
+        );
+        check_module(
+            "if False:\n    x = 1\nclass A:\n    if False:\n        y = 1\n",
+            "class A:\n    pass\n",
+        );
+    }
+}
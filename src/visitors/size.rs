@@ -0,0 +1,353 @@
+//! Estimation of the memory used by the AST, eg. to keep a cache of
+//! parsed modules under a memory budget.
+
+use std::mem;
+
+#[cfg(feature = "bigint")]
+use num_bigint::BigUint;
+
+#[cfg(feature = "wtf8")]
+use wtf8::Wtf8Buf;
+
+use ast::*;
+
+/// Types that may own memory on the heap.
+pub trait HeapSize {
+    /// Number of bytes allocated on the heap for this value (not
+    /// including its own size).
+    fn heap_size(&self) -> usize;
+}
+
+/// Returns the number of bytes used by an AST node (`Statement`,
+/// `Expression`, `Module`, a list of statements, etc.): its own size
+/// and the size of the memory it allocated on the heap.
+///
+/// Allocators may round allocations up, and the size of big integers
+/// is estimated from their number of bits, so the actual memory use
+/// is a bit higher.
+///
+/// ```
+/// use python_parser::ast::*;
+/// use python_parser::visitors::size::deep_size;
+/// let e = Expression::Name("foo".to_string());
+/// assert_eq!(deep_size(&e), std::mem::size_of::<Expression>() + 3);
+/// ```
+pub fn deep_size<T: HeapSize + ?Sized>(node: &T) -> usize {
+    mem::size_of_val(node) + node.heap_size()
+}
+
+macro_rules! no_heap_size {
+    ( $($name:ty),* ) => {
+        $(
+            impl HeapSize for $name {
+                fn heap_size(&self) -> usize {
+                    0
+                }
+            }
+        )*
+    };
+}
+
+no_heap_size!(bool, u8, u64, usize, f64, Uop, Bop, AugAssignOp);
+
+impl HeapSize for String {
+    fn heap_size(&self) -> usize {
+        self.capacity()
+    }
+}
+
+#[cfg(feature = "wtf8")]
+impl HeapSize for Wtf8Buf {
+    fn heap_size(&self) -> usize {
+        self.capacity()
+    }
+}
+
+#[cfg(feature = "bigint")]
+impl HeapSize for BigUint {
+    fn heap_size(&self) -> usize {
+        // Stored as 32-bit digits
+        self.bits().div_ceil(32) * 4
+    }
+}
+
+impl<T: HeapSize> HeapSize for Box<T> {
+    fn heap_size(&self) -> usize {
+        deep_size(&**self)
+    }
+}
+
+impl<T: HeapSize> HeapSize for Option<T> {
+    fn heap_size(&self) -> usize {
+        self.as_ref().map_or(0, HeapSize::heap_size)
+    }
+}
+
+impl<T: HeapSize> HeapSize for Vec<T> {
+    fn heap_size(&self) -> usize {
+        self.capacity() * mem::size_of::<T>() + self.iter().map(HeapSize::heap_size).sum::<usize>()
+    }
+}
+
+#[cfg(feature = "smallvec")]
+impl<T: HeapSize> HeapSize for SmallList<T> {
+    fn heap_size(&self) -> usize {
+        let items = if self.spilled() { self.capacity() } else { 0 };
+        items * mem::size_of::<T>() + self.iter().map(HeapSize::heap_size).sum::<usize>()
+    }
+}
+
+impl<T: HeapSize> HeapSize for [T] {
+    fn heap_size(&self) -> usize {
+        self.iter().map(HeapSize::heap_size).sum()
+    }
+}
+
+impl<A: HeapSize, B: HeapSize> HeapSize for (A, B) {
+    fn heap_size(&self) -> usize {
+        self.0.heap_size() + self.1.heap_size()
+    }
+}
+
+impl<A: HeapSize, B: HeapSize, C: HeapSize> HeapSize for (A, B, C) {
+    fn heap_size(&self) -> usize {
+        self.0.heap_size() + self.1.heap_size() + self.2.heap_size()
+    }
+}
+
+/// Implements `HeapSize` for a structure, by adding the heap size of
+/// its fields.
+macro_rules! heap_size_struct {
+    ( $name:ident { $($field:ident),* $(,)* } ) => {
+        impl HeapSize for $name {
+            fn heap_size(&self) -> usize {
+                0 $( + self.$field.heap_size() )*
+            }
+        }
+    };
+}
+
+/// Implements `HeapSize` for an enum, by adding the heap size of the
+/// fields of the variant. Fields of tuple variants are named
+/// arbitrarily.
+macro_rules! heap_size_enum {
+    ( $name:ident $(<$param:ident>)* {
+        $( $variant:ident $( ( $($field:ident),* ) )* $( { $($sfield:ident),* } )* ),* $(,)*
+    } ) => {
+        impl$(<$param: HeapSize>)* HeapSize for $name$(<$param>)* {
+            fn heap_size(&self) -> usize {
+                match *self {
+                    $(
+                        $name::$variant $( ( $(ref $field),* ) )* $( { $(ref $sfield),* } )* => {
+                            0 $( $( + $field.heap_size() )* )* $( $( + $sfield.heap_size() )* )*
+                        }
+                    )*
+                }
+            }
+        }
+    };
+}
+
+heap_size_enum!(StarParams<T> {
+    No,
+    Anonymous,
+    Named(a),
+});
+
+heap_size_struct!(TypedArgsList {
+    posonly_args,
+    args,
+    star_args,
+    keyword_args,
+    star_kwargs,
+});
+
+heap_size_struct!(UntypedArgsList {
+    posonly_args,
+    args,
+    star_args,
+    keyword_args,
+    star_kwargs,
+});
+
+heap_size_struct!(Decorator { name, args });
+
+heap_size_enum!(Argument {
+    Positional(a),
+    Starargs(a),
+    Keyword(a, b),
+    Kwargs(a),
+});
+
+heap_size_enum!(Subscript {
+    Simple(a),
+    Double(a, b),
+    Triple(a, b, c),
+});
+
+heap_size_enum!(ComprehensionChunk {
+    If { cond },
+    For { async, item, iterator },
+});
+
+heap_size_enum!(DictItem {
+    Star(a),
+    Unique(a, b),
+});
+
+heap_size_enum!(SetItem {
+    Star(a),
+    Unique(a),
+});
+
+heap_size_struct!(PyString {
+    prefix,
+    content,
+    raw,
+});
+
+heap_size_enum!(Expression {
+    Ellipsis,
+    None,
+    True,
+    False,
+    Name(a),
+    Int(a),
+    ImaginaryInt(a),
+    Float(a),
+    ImaginaryFloat(a),
+    String(a),
+    Bytes(a),
+    DictLiteral(a),
+    SetLiteral(a),
+    ListLiteral(a),
+    TupleLiteral(a),
+    DictComp(a, b),
+    SetComp(a, b),
+    ListComp(a, b),
+    Generator(a, b),
+    Await(a),
+    Call(a, b),
+    Subscript(a, b),
+    Attribute(a, b),
+    Uop(a, b),
+    Bop(a, b, c),
+    MultiBop(a, b),
+    Ternary(a, b, c),
+    Yield(a),
+    YieldFrom(a),
+    Star(a),
+    Lambdef(a, b),
+    Named(a, b),
+});
+
+heap_size_enum!(Import {
+    ImportFrom { leading_dots, path, names },
+    ImportStarFrom { leading_dots, path },
+    Import { names },
+});
+
+heap_size_enum!(Statement {
+    Pass,
+    Del(a),
+    Break,
+    Continue,
+    Return(a),
+    RaiseExcFrom(a, b),
+    RaiseExc(a),
+    Raise,
+    Global(a),
+    Nonlocal(a),
+    Assert(a, b),
+    Import(a),
+    Expressions(a),
+    Assignment(a, b),
+    TypeAnnotation(a, b),
+    TypedAssignment(a, b, c),
+    AugmentedAssignment(a, b, c),
+    Compound(a),
+    Invalid(a),
+    Extension(a, b),
+});
+
+heap_size_struct!(Funcdef {
+    async,
+    decorators,
+    name,
+    parameters,
+    return_type,
+    code,
+});
+
+heap_size_struct!(Classdef {
+    decorators,
+    name,
+    arguments,
+    code,
+});
+
+heap_size_struct!(Try {
+    try_block,
+    except_clauses,
+    last_except,
+    else_block,
+    finally_block,
+});
+
+heap_size_enum!(CompoundStatement {
+    If(a, b),
+    For { async, item, iterator, for_block, else_block },
+    While(a, b, c),
+    With(a, b),
+    Funcdef(a),
+    Classdef(a),
+    Try(a),
+});
+
+heap_size_struct!(Module {
+    shebang,
+    encoding,
+    leading_comments,
+    trailing_newline,
+    body,
+});
+
+heap_size_struct!(FuncType {
+    args,
+    star_args,
+    star_kwargs,
+    return_type,
+});
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parse_module;
+
+    #[test]
+    fn test_deep_size() {
+        let stmt_size = mem::size_of::<Statement>();
+        let expr_size = mem::size_of::<Expression>();
+        assert_eq!(deep_size(&Statement::Pass), stmt_size);
+        assert_eq!(
+            deep_size(&Expression::Await(Box::new(Expression::None))),
+            2 * expr_size
+        );
+        let mut stmts = Vec::with_capacity(2);
+        stmts.push(Statement::Expressions(vec![Expression::Name(
+            "foo".to_string(),
+        )]));
+        assert_eq!(
+            deep_size(&stmts),
+            mem::size_of::<Vec<Statement>>() + 2 * stmt_size + expr_size + 3
+        );
+    }
+
+    #[test]
+    fn test_module() {
+        let small = parse_module("x = 1\n").unwrap();
+        let big = parse_module("x = 1\ndef f(a, b=2):\n    return [a for a in b]\n").unwrap();
+        assert!(deep_size(&small) > mem::size_of::<Module>());
+        assert!(deep_size(&big) > deep_size(&small) + 4 * mem::size_of::<Expression>());
+    }
+}
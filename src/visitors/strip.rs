@@ -0,0 +1,289 @@
+//! Removes what Python itself removes when running with `-O` or `-OO`:
+//! `assert` statements and `if __debug__:` blocks, and docstrings; and
+//! comments, which are lost anyway when printing the AST.
+//!
+//! Blocks left empty get a `pass` statement.
+
+use std::mem;
+
+use ast::*;
+use doctest::docstring;
+
+/// What to do with the docstrings of the module, classes and functions.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Docstrings {
+    Keep,
+    /// Keep only their first non-blank line, stripped, like the summary
+    /// line of `help()`.
+    FirstLine,
+    Remove,
+}
+
+/// What to remove from a module.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct StripOptions {
+    pub docstrings: Docstrings,
+    /// The leading comments of the module (see `Module`), but not the
+    /// shebang and encoding declaration.
+    pub comments: bool,
+    /// `assert` statements and `if __debug__:` blocks (and
+    /// `elif __debug__:` branches).
+    pub asserts: bool,
+}
+
+impl StripOptions {
+    /// Options removing what Python removes with `-O` (`level = 1`) or
+    /// `-OO` (`level >= 2`), and the comments.
+    pub fn optimize(level: u8) -> StripOptions {
+        StripOptions {
+            docstrings: if level >= 2 {
+                Docstrings::Remove
+            } else {
+                Docstrings::Keep
+            },
+            comments: true,
+            asserts: level >= 1,
+        }
+    }
+}
+
+/// Removes everything.
+impl Default for StripOptions {
+    fn default() -> StripOptions {
+        StripOptions::optimize(2)
+    }
+}
+
+#[cfg(feature = "wtf8")]
+fn content_from_str(s: &str) -> PyStringContent {
+    PyStringContent::from_str(s)
+}
+
+#[cfg(not(feature = "wtf8"))]
+fn content_from_str(s: &str) -> PyStringContent {
+    s.to_string()
+}
+
+/// Whether `expr` is `__debug__`, which is false with `-O`.
+fn is_debug(expr: &Expression) -> bool {
+    match *expr {
+        Expression::Name(ref name) => name == "__debug__",
+        _ => false,
+    }
+}
+
+/// Removes or shortens the docstring of a body.
+fn strip_docstring(body: &mut Vec<Statement>, mode: Docstrings) {
+    let text = match docstring(body) {
+        Some(text) => text,
+        None => return,
+    };
+    let first_line = text.lines().map(str::trim).find(|line| !line.is_empty());
+    match (mode, first_line) {
+        (Docstrings::Keep, _) => (),
+        (Docstrings::FirstLine, Some(line)) => {
+            let string = PyString {
+                prefix: String::new(),
+                content: content_from_str(line),
+                raw: None,
+            };
+            body[0] =
+                Statement::Assignment(small_list![Expression::String(vec![string])], Vec::new());
+        }
+        (Docstrings::FirstLine, None) | (Docstrings::Remove, _) => {
+            body.remove(0);
+        }
+    }
+}
+
+/// Strips a block. `body` is whether it is the body of a class or
+/// function, which may have a docstring, and `required` whether it must
+/// not be left empty.
+fn strip_block(stmts: &mut Vec<Statement>, options: &StripOptions, body: bool, required: bool) {
+    if body {
+        strip_docstring(stmts, options.docstrings);
+    }
+    for mut stmt in mem::take(stmts) {
+        if options.asserts {
+            if let Statement::Assert(_, _) = stmt {
+                continue;
+            }
+            if let Statement::Compound(ref mut compound) = stmt {
+                if let CompoundStatement::If(ref mut cond_blocks, ref mut else_block) = **compound {
+                    cond_blocks.retain(|(cond, _)| !is_debug(cond));
+                    if cond_blocks.is_empty() {
+                        // Only the `else` block is left, if any
+                        let mut block = else_block.take().unwrap_or_default();
+                        strip_block(&mut block, options, false, false);
+                        stmts.extend(block);
+                        continue;
+                    }
+                }
+            }
+        }
+        if let Statement::Compound(ref mut compound) = stmt {
+            strip_compound_statement(compound, options);
+        }
+        stmts.push(stmt);
+    }
+    if required && stmts.is_empty() {
+        stmts.push(Statement::Pass);
+    }
+}
+
+/// Strips an optional block, which is kept if it was not empty.
+fn strip_optional_block(block: &mut Vec<Statement>, options: &StripOptions) {
+    let required = !block.is_empty();
+    strip_block(block, options, false, required);
+}
+
+fn strip_compound_statement(stmt: &mut CompoundStatement, options: &StripOptions) {
+    match *stmt {
+        CompoundStatement::If(ref mut cond_blocks, ref mut else_block) => {
+            for (_, block) in cond_blocks {
+                strip_block(block, options, false, true);
+            }
+            if let Some(ref mut block) = *else_block {
+                strip_block(block, options, false, true);
+            }
+        }
+        CompoundStatement::For {
+            ref mut for_block,
+            ref mut else_block,
+            ..
+        } => {
+            strip_block(for_block, options, false, true);
+            if let Some(ref mut block) = *else_block {
+                strip_block(block, options, false, true);
+            }
+        }
+        CompoundStatement::While(_, ref mut block, ref mut else_block) => {
+            strip_block(block, options, false, true);
+            if let Some(ref mut block) = *else_block {
+                strip_block(block, options, false, true);
+            }
+        }
+        CompoundStatement::With(_, ref mut block) => strip_block(block, options, false, true),
+        CompoundStatement::Funcdef(Funcdef { ref mut code, .. })
+        | CompoundStatement::Classdef(Classdef { ref mut code, .. }) => {
+            strip_block(code, options, true, true)
+        }
+        CompoundStatement::Try(ref mut try_) => {
+            strip_block(&mut try_.try_block, options, false, true);
+            for (_, _, block) in &mut try_.except_clauses {
+                strip_block(block, options, false, true);
+            }
+            strip_optional_block(&mut try_.last_except, options);
+            strip_optional_block(&mut try_.else_block, options);
+            strip_optional_block(&mut try_.finally_block, options);
+        }
+    }
+}
+
+/// Removes docstrings, comments and assertions from a module, in place,
+/// as chosen by `options`.
+///
+/// ```
+/// use python_parser::parse_module;
+/// use python_parser::visitors::strip::{strip_module, StripOptions};
+///
+/// let mut module = parse_module("'''Doc.'''\ndef f(x):\n    'Doc.'\n    assert x\n").unwrap();
+/// strip_module(&mut module, &StripOptions::optimize(2));
+/// assert_eq!(module, parse_module("def f(x):\n    pass\n").unwrap());
+/// ```
+pub fn strip_module(module: &mut Module, options: &StripOptions) {
+    if options.comments {
+        module.leading_comments.clear();
+    }
+    strip_block(&mut module.body, options, true, false);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parse_module;
+
+    fn strip(code: &str, options: &StripOptions) -> Module {
+        let mut module = parse_module(code).unwrap();
+        strip_module(&mut module, options);
+        module
+    }
+
+    #[test]
+    fn test_asserts() {
+        let code = "#!/usr/bin/env python
+# comment
+if __debug__:
+    check()
+elif x:
+    assert x, 'x'
+elif __debug__:
+    pass
+else:
+    if __debug__: pass
+    else: y = 1
+try:
+    f()
+finally:
+    assert y
+";
+        let expected = "#!/usr/bin/env python
+if x:
+    pass
+else:
+    y = 1
+try:
+    f()
+finally:
+    pass
+";
+        assert_eq!(
+            strip(code, &StripOptions::optimize(1)),
+            parse_module(expected).unwrap()
+        );
+        let options = StripOptions {
+            asserts: false,
+            ..StripOptions::default()
+        };
+        let mut module = parse_module(code).unwrap();
+        module.leading_comments.clear();
+        assert_eq!(strip(code, &options), module);
+    }
+
+    #[test]
+    fn test_docstrings() {
+        let code = "class C:
+    '''
+    Summary.
+
+    Details.
+    '''
+    def f(self):
+        \"   \"
+    x = '''Not a docstring'''
+def g(): f'{x}'
+";
+        let options = StripOptions {
+            docstrings: Docstrings::FirstLine,
+            ..StripOptions::default()
+        };
+        let expected = "class C:
+    'Summary.'
+    def f(self):
+        pass
+    x = '''Not a docstring'''
+def g(): f'{x}'
+";
+        assert_eq!(strip(code, &options), parse_module(expected).unwrap());
+        let expected = "class C:
+    def f(self):
+        pass
+    x = '''Not a docstring'''
+def g(): f'{x}'
+";
+        assert_eq!(
+            strip(code, &StripOptions::default()),
+            parse_module(expected).unwrap()
+        );
+    }
+}
@@ -0,0 +1,344 @@
+//! Read-only traversal of the AST.
+//!
+//! Implement `Visitor` by overriding the methods of the nodes of
+//! interest, and call the matching `walk_*` function from them to also
+//! visit their children. Nodes are visited in the order of the code.
+
+use ast::*;
+
+pub trait Visitor {
+    fn visit_statement(&mut self, stmt: &Statement) {
+        walk_statement(self, stmt)
+    }
+
+    fn visit_expression(&mut self, expr: &Expression) {
+        walk_expression(self, expr)
+    }
+}
+
+/// Visits each statement of `stmts`.
+pub fn walk_block<V: Visitor + ?Sized>(v: &mut V, stmts: &[Statement]) {
+    for stmt in stmts {
+        v.visit_statement(stmt);
+    }
+}
+
+fn walk_expressions<V: Visitor + ?Sized>(v: &mut V, exprs: &[Expression]) {
+    for expr in exprs {
+        v.visit_expression(expr);
+    }
+}
+
+fn walk_optional_expression<V: Visitor + ?Sized>(v: &mut V, expr: &Option<Expression>) {
+    if let Some(ref expr) = *expr {
+        v.visit_expression(expr);
+    }
+}
+
+/// Visits the children of `stmt`.
+pub fn walk_statement<V: Visitor + ?Sized>(v: &mut V, stmt: &Statement) {
+    match *stmt {
+        Statement::Pass
+        | Statement::Break
+        | Statement::Continue
+        | Statement::Raise
+        | Statement::Global(_)
+        | Statement::Nonlocal(_)
+        | Statement::Import(_)
+        | Statement::Invalid(_)
+        | Statement::Extension(_, _) => (),
+        Statement::Del(ref exprs)
+        | Statement::Return(ref exprs)
+        | Statement::Expressions(ref exprs) => walk_expressions(v, exprs),
+        Statement::RaiseExcFrom(ref exc, ref from) => {
+            v.visit_expression(exc);
+            v.visit_expression(from);
+        }
+        Statement::RaiseExc(ref exc) => v.visit_expression(exc),
+        Statement::Assert(ref cond, ref msg) => {
+            v.visit_expression(cond);
+            walk_optional_expression(v, msg);
+        }
+        Statement::Assignment(ref lhs, ref rhs) => {
+            walk_expressions(v, lhs);
+            for exprs in rhs {
+                walk_expressions(v, exprs);
+            }
+        }
+        Statement::TypeAnnotation(ref lhs, ref typed) => {
+            walk_expressions(v, lhs);
+            v.visit_expression(typed);
+        }
+        Statement::TypedAssignment(ref lhs, ref typed, ref rhs) => {
+            walk_expressions(v, lhs);
+            v.visit_expression(typed);
+            walk_expressions(v, rhs);
+        }
+        Statement::AugmentedAssignment(ref lhs, _, ref rhs) => {
+            walk_expressions(v, lhs);
+            walk_expressions(v, rhs);
+        }
+        Statement::Compound(ref stmt) => walk_compound_statement(v, stmt),
+    }
+}
+
+/// Visits the expressions and blocks of `stmt`.
+pub fn walk_compound_statement<V: Visitor + ?Sized>(v: &mut V, stmt: &CompoundStatement) {
+    match *stmt {
+        CompoundStatement::If(ref cond_blocks, ref else_block) => {
+            for (cond, block) in cond_blocks {
+                v.visit_expression(cond);
+                walk_block(v, block);
+            }
+            if let Some(ref block) = *else_block {
+                walk_block(v, block);
+            }
+        }
+        CompoundStatement::For {
+            ref item,
+            ref iterator,
+            ref for_block,
+            ref else_block,
+            ..
+        } => {
+            walk_expressions(v, item);
+            walk_expressions(v, iterator);
+            walk_block(v, for_block);
+            if let Some(ref block) = *else_block {
+                walk_block(v, block);
+            }
+        }
+        CompoundStatement::While(ref cond, ref block, ref else_block) => {
+            v.visit_expression(cond);
+            walk_block(v, block);
+            if let Some(ref block) = *else_block {
+                walk_block(v, block);
+            }
+        }
+        CompoundStatement::With(ref contexts, ref block) => {
+            for (context, target) in contexts {
+                v.visit_expression(context);
+                walk_optional_expression(v, target);
+            }
+            walk_block(v, block);
+        }
+        CompoundStatement::Funcdef(ref funcdef) => {
+            walk_decorators(v, &funcdef.decorators);
+            walk_typed_params(v, &funcdef.parameters);
+            walk_optional_expression(v, &funcdef.return_type);
+            walk_block(v, &funcdef.code);
+        }
+        CompoundStatement::Classdef(ref classdef) => {
+            walk_decorators(v, &classdef.decorators);
+            walk_args(v, &classdef.arguments);
+            walk_block(v, &classdef.code);
+        }
+        CompoundStatement::Try(ref try_) => {
+            walk_block(v, &try_.try_block);
+            for (exc, _, block) in &try_.except_clauses {
+                v.visit_expression(exc);
+                walk_block(v, block);
+            }
+            walk_block(v, &try_.last_except);
+            walk_block(v, &try_.else_block);
+            walk_block(v, &try_.finally_block);
+        }
+    }
+}
+
+fn walk_decorators<V: Visitor + ?Sized>(v: &mut V, decorators: &[Decorator]) {
+    for decorator in decorators {
+        if let Some(ref args) = decorator.args {
+            walk_args(v, args);
+        }
+    }
+}
+
+fn walk_args<V: Visitor + ?Sized>(v: &mut V, args: &[Argument]) {
+    for arg in args {
+        match *arg {
+            Argument::Positional(ref e)
+            | Argument::Starargs(ref e)
+            | Argument::Keyword(_, ref e)
+            | Argument::Kwargs(ref e) => v.visit_expression(e),
+        }
+    }
+}
+
+fn walk_typed_params<V: Visitor + ?Sized>(v: &mut V, params: &TypedArgsList) {
+    let walk_param = |v: &mut V, param: &(Name, Option<Expression>, Option<Expression>)| {
+        walk_optional_expression(v, &param.1);
+        walk_optional_expression(v, &param.2);
+    };
+    for param in params.posonly_args.iter().chain(&params.args) {
+        walk_param(v, param);
+    }
+    if let StarParams::Named((_, ref typed)) = params.star_args {
+        walk_optional_expression(v, typed);
+    }
+    for param in &params.keyword_args {
+        walk_param(v, param);
+    }
+    if let Some((_, ref typed)) = params.star_kwargs {
+        walk_optional_expression(v, typed);
+    }
+}
+
+fn walk_untyped_params<V: Visitor + ?Sized>(v: &mut V, params: &UntypedArgsList) {
+    let params = params
+        .posonly_args
+        .iter()
+        .chain(&params.args)
+        .chain(&params.keyword_args);
+    for (_, default) in params {
+        walk_optional_expression(v, default);
+    }
+}
+
+fn walk_setitem<V: Visitor + ?Sized>(v: &mut V, item: &SetItem) {
+    match *item {
+        SetItem::Star(ref e) | SetItem::Unique(ref e) => v.visit_expression(e),
+    }
+}
+
+fn walk_dictitem<V: Visitor + ?Sized>(v: &mut V, item: &DictItem) {
+    match *item {
+        DictItem::Star(ref e) => v.visit_expression(e),
+        DictItem::Unique(ref key, ref value) => {
+            v.visit_expression(key);
+            v.visit_expression(value);
+        }
+    }
+}
+
+fn walk_comp<V: Visitor + ?Sized>(v: &mut V, chunks: &[ComprehensionChunk]) {
+    for chunk in chunks {
+        match *chunk {
+            ComprehensionChunk::If { ref cond } => v.visit_expression(cond),
+            ComprehensionChunk::For {
+                ref item,
+                ref iterator,
+                ..
+            } => {
+                walk_expressions(v, item);
+                v.visit_expression(iterator);
+            }
+        }
+    }
+}
+
+fn walk_subscript<V: Visitor + ?Sized>(v: &mut V, sub: &Subscript) {
+    match *sub {
+        Subscript::Simple(ref e) => v.visit_expression(e),
+        Subscript::Double(ref start, ref end) => {
+            walk_optional_expression(v, start);
+            walk_optional_expression(v, end);
+        }
+        Subscript::Triple(ref start, ref end, ref step) => {
+            walk_optional_expression(v, start);
+            walk_optional_expression(v, end);
+            walk_optional_expression(v, step);
+        }
+    }
+}
+
+/// Visits the children of `expr`.
+pub fn walk_expression<V: Visitor + ?Sized>(v: &mut V, expr: &Expression) {
+    match *expr {
+        Expression::Ellipsis
+        | Expression::None
+        | Expression::True
+        | Expression::False
+        | Expression::Name(_)
+        | Expression::Int(_)
+        | Expression::ImaginaryInt(_)
+        | Expression::Float(_)
+        | Expression::ImaginaryFloat(_)
+        | Expression::String(_)
+        | Expression::Bytes(_) => (),
+        Expression::DictLiteral(ref items) => {
+            for item in items {
+                walk_dictitem(v, item);
+            }
+        }
+        Expression::SetLiteral(ref items)
+        | Expression::ListLiteral(ref items)
+        | Expression::TupleLiteral(ref items) => {
+            for item in items {
+                walk_setitem(v, item);
+            }
+        }
+        Expression::DictComp(ref item, ref comp) => {
+            walk_dictitem(v, item);
+            walk_comp(v, comp);
+        }
+        Expression::SetComp(ref item, ref comp)
+        | Expression::ListComp(ref item, ref comp)
+        | Expression::Generator(ref item, ref comp) => {
+            walk_setitem(v, item);
+            walk_comp(v, comp);
+        }
+        Expression::Await(ref e)
+        | Expression::Attribute(ref e, _)
+        | Expression::Uop(_, ref e)
+        | Expression::YieldFrom(ref e)
+        | Expression::Star(ref e) => v.visit_expression(e),
+        Expression::Call(ref func, ref args) => {
+            v.visit_expression(func);
+            walk_args(v, args);
+        }
+        Expression::Subscript(ref e, ref subs) => {
+            v.visit_expression(e);
+            for sub in subs {
+                walk_subscript(v, sub);
+            }
+        }
+        Expression::Bop(_, ref left, ref right) | Expression::Named(ref left, ref right) => {
+            v.visit_expression(left);
+            v.visit_expression(right);
+        }
+        Expression::MultiBop(ref first, ref rest) => {
+            v.visit_expression(first);
+            for (_, e) in rest {
+                v.visit_expression(e);
+            }
+        }
+        Expression::Ternary(ref then, ref cond, ref else_) => {
+            // `then if cond else else_`
+            v.visit_expression(then);
+            v.visit_expression(cond);
+            v.visit_expression(else_);
+        }
+        Expression::Yield(ref exprs) => walk_expressions(v, exprs),
+        Expression::Lambdef(ref params, ref body) => {
+            walk_untyped_params(v, params);
+            v.visit_expression(body);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parse_module;
+
+    struct Names(Vec<String>);
+
+    impl Visitor for Names {
+        fn visit_expression(&mut self, expr: &Expression) {
+            if let Expression::Name(ref name) = *expr {
+                self.0.push(name.clone());
+            }
+            walk_expression(self, expr)
+        }
+    }
+
+    #[test]
+    fn test_order() {
+        let code = "@a(b)\ndef f(x: c = d, *y: e, z=g, **w: h) -> i:\n    j = [k for l in m if n] + o(p, *q, r=s)[t:u]\n    return lambda v=x: v if aa else bb\nclass C(cc):\n    with dd as ee:\n        ff\ntry:\n    gg\nexcept hh:\n    ii\n";
+        let mut names = Names(Vec::new());
+        walk_block(&mut names, &parse_module(code).unwrap().body);
+        let expected = "b c d e g h i j k l m n o p q s t u x v aa bb cc dd ee ff gg hh ii";
+        assert_eq!(names.0.join(" "), expected);
+    }
+}
@@ -0,0 +1,361 @@
+//! Traversal of the AST allowing to modify it, like `walk` for
+//! read-only traversals.
+//!
+//! Implement `VisitorMut` by overriding the methods of the nodes of
+//! interest, and call the matching `walk_*_mut` function from them to
+//! also visit their children. `visit_block` gets the blocks themselves,
+//! to add or remove statements. Nodes are visited in the order of the
+//! code.
+
+use ast::*;
+
+pub trait VisitorMut {
+    fn visit_block(&mut self, stmts: &mut Vec<Statement>) {
+        walk_block_mut(self, stmts)
+    }
+
+    fn visit_statement(&mut self, stmt: &mut Statement) {
+        walk_statement_mut(self, stmt)
+    }
+
+    fn visit_expression(&mut self, expr: &mut Expression) {
+        walk_expression_mut(self, expr)
+    }
+}
+
+/// Visits each statement of `stmts`.
+pub fn walk_block_mut<V: VisitorMut + ?Sized>(v: &mut V, stmts: &mut [Statement]) {
+    for stmt in stmts {
+        v.visit_statement(stmt);
+    }
+}
+
+fn walk_expressions_mut<V: VisitorMut + ?Sized>(v: &mut V, exprs: &mut [Expression]) {
+    for expr in exprs {
+        v.visit_expression(expr);
+    }
+}
+
+fn walk_optional_expression_mut<V: VisitorMut + ?Sized>(v: &mut V, expr: &mut Option<Expression>) {
+    if let Some(ref mut expr) = *expr {
+        v.visit_expression(expr);
+    }
+}
+
+/// Visits the children of `stmt`.
+pub fn walk_statement_mut<V: VisitorMut + ?Sized>(v: &mut V, stmt: &mut Statement) {
+    match *stmt {
+        Statement::Pass
+        | Statement::Break
+        | Statement::Continue
+        | Statement::Raise
+        | Statement::Global(_)
+        | Statement::Nonlocal(_)
+        | Statement::Import(_)
+        | Statement::Invalid(_)
+        | Statement::Extension(_, _) => (),
+        Statement::Del(ref mut exprs)
+        | Statement::Return(ref mut exprs)
+        | Statement::Expressions(ref mut exprs) => walk_expressions_mut(v, exprs),
+        Statement::RaiseExcFrom(ref mut exc, ref mut from) => {
+            v.visit_expression(exc);
+            v.visit_expression(from);
+        }
+        Statement::RaiseExc(ref mut exc) => v.visit_expression(exc),
+        Statement::Assert(ref mut cond, ref mut msg) => {
+            v.visit_expression(cond);
+            walk_optional_expression_mut(v, msg);
+        }
+        Statement::Assignment(ref mut lhs, ref mut rhs) => {
+            walk_expressions_mut(v, lhs);
+            for exprs in rhs {
+                walk_expressions_mut(v, exprs);
+            }
+        }
+        Statement::TypeAnnotation(ref mut lhs, ref mut typed) => {
+            walk_expressions_mut(v, lhs);
+            v.visit_expression(typed);
+        }
+        Statement::TypedAssignment(ref mut lhs, ref mut typed, ref mut rhs) => {
+            walk_expressions_mut(v, lhs);
+            v.visit_expression(typed);
+            walk_expressions_mut(v, rhs);
+        }
+        Statement::AugmentedAssignment(ref mut lhs, _, ref mut rhs) => {
+            walk_expressions_mut(v, lhs);
+            walk_expressions_mut(v, rhs);
+        }
+        Statement::Compound(ref mut stmt) => walk_compound_statement_mut(v, stmt),
+    }
+}
+
+/// Visits the expressions and blocks of `stmt`.
+pub fn walk_compound_statement_mut<V: VisitorMut + ?Sized>(
+    v: &mut V,
+    stmt: &mut CompoundStatement,
+) {
+    match *stmt {
+        CompoundStatement::If(ref mut cond_blocks, ref mut else_block) => {
+            for (cond, block) in cond_blocks {
+                v.visit_expression(cond);
+                v.visit_block(block);
+            }
+            if let Some(ref mut block) = *else_block {
+                v.visit_block(block);
+            }
+        }
+        CompoundStatement::For {
+            ref mut item,
+            ref mut iterator,
+            ref mut for_block,
+            ref mut else_block,
+            ..
+        } => {
+            walk_expressions_mut(v, item);
+            walk_expressions_mut(v, iterator);
+            v.visit_block(for_block);
+            if let Some(ref mut block) = *else_block {
+                v.visit_block(block);
+            }
+        }
+        CompoundStatement::While(ref mut cond, ref mut block, ref mut else_block) => {
+            v.visit_expression(cond);
+            v.visit_block(block);
+            if let Some(ref mut block) = *else_block {
+                v.visit_block(block);
+            }
+        }
+        CompoundStatement::With(ref mut contexts, ref mut block) => {
+            for (context, target) in contexts {
+                v.visit_expression(context);
+                walk_optional_expression_mut(v, target);
+            }
+            v.visit_block(block);
+        }
+        CompoundStatement::Funcdef(ref mut funcdef) => {
+            walk_decorators_mut(v, &mut funcdef.decorators);
+            walk_typed_params_mut(v, &mut funcdef.parameters);
+            walk_optional_expression_mut(v, &mut funcdef.return_type);
+            v.visit_block(&mut funcdef.code);
+        }
+        CompoundStatement::Classdef(ref mut classdef) => {
+            walk_decorators_mut(v, &mut classdef.decorators);
+            walk_args_mut(v, &mut classdef.arguments);
+            v.visit_block(&mut classdef.code);
+        }
+        CompoundStatement::Try(ref mut try_) => {
+            v.visit_block(&mut try_.try_block);
+            for (exc, _, block) in &mut try_.except_clauses {
+                v.visit_expression(exc);
+                v.visit_block(block);
+            }
+            v.visit_block(&mut try_.last_except);
+            v.visit_block(&mut try_.else_block);
+            v.visit_block(&mut try_.finally_block);
+        }
+    }
+}
+
+fn walk_decorators_mut<V: VisitorMut + ?Sized>(v: &mut V, decorators: &mut [Decorator]) {
+    for decorator in decorators {
+        if let Some(ref mut args) = decorator.args {
+            walk_args_mut(v, args);
+        }
+    }
+}
+
+fn walk_args_mut<V: VisitorMut + ?Sized>(v: &mut V, args: &mut [Argument]) {
+    for arg in args {
+        match *arg {
+            Argument::Positional(ref mut e)
+            | Argument::Starargs(ref mut e)
+            | Argument::Keyword(_, ref mut e)
+            | Argument::Kwargs(ref mut e) => v.visit_expression(e),
+        }
+    }
+}
+
+fn walk_typed_params_mut<V: VisitorMut + ?Sized>(v: &mut V, params: &mut TypedArgsList) {
+    let walk_param = |v: &mut V, param: &mut (Name, Option<Expression>, Option<Expression>)| {
+        walk_optional_expression_mut(v, &mut param.1);
+        walk_optional_expression_mut(v, &mut param.2);
+    };
+    for param in params.posonly_args.iter_mut().chain(&mut params.args) {
+        walk_param(v, param);
+    }
+    if let StarParams::Named((_, ref mut typed)) = params.star_args {
+        walk_optional_expression_mut(v, typed);
+    }
+    for param in &mut params.keyword_args {
+        walk_param(v, param);
+    }
+    if let Some((_, ref mut typed)) = params.star_kwargs {
+        walk_optional_expression_mut(v, typed);
+    }
+}
+
+fn walk_untyped_params_mut<V: VisitorMut + ?Sized>(v: &mut V, params: &mut UntypedArgsList) {
+    let params = params
+        .posonly_args
+        .iter_mut()
+        .chain(&mut params.args)
+        .chain(&mut params.keyword_args);
+    for (_, default) in params {
+        walk_optional_expression_mut(v, default);
+    }
+}
+
+fn walk_setitem_mut<V: VisitorMut + ?Sized>(v: &mut V, item: &mut SetItem) {
+    match *item {
+        SetItem::Star(ref mut e) | SetItem::Unique(ref mut e) => v.visit_expression(e),
+    }
+}
+
+fn walk_dictitem_mut<V: VisitorMut + ?Sized>(v: &mut V, item: &mut DictItem) {
+    match *item {
+        DictItem::Star(ref mut e) => v.visit_expression(e),
+        DictItem::Unique(ref mut key, ref mut value) => {
+            v.visit_expression(key);
+            v.visit_expression(value);
+        }
+    }
+}
+
+fn walk_comp_mut<V: VisitorMut + ?Sized>(v: &mut V, chunks: &mut [ComprehensionChunk]) {
+    for chunk in chunks {
+        match *chunk {
+            ComprehensionChunk::If { ref mut cond } => v.visit_expression(cond),
+            ComprehensionChunk::For {
+                ref mut item,
+                ref mut iterator,
+                ..
+            } => {
+                walk_expressions_mut(v, item);
+                v.visit_expression(iterator);
+            }
+        }
+    }
+}
+
+fn walk_subscript_mut<V: VisitorMut + ?Sized>(v: &mut V, sub: &mut Subscript) {
+    match *sub {
+        Subscript::Simple(ref mut e) => v.visit_expression(e),
+        Subscript::Double(ref mut start, ref mut end) => {
+            walk_optional_expression_mut(v, start);
+            walk_optional_expression_mut(v, end);
+        }
+        Subscript::Triple(ref mut start, ref mut end, ref mut step) => {
+            walk_optional_expression_mut(v, start);
+            walk_optional_expression_mut(v, end);
+            walk_optional_expression_mut(v, step);
+        }
+    }
+}
+
+/// Visits the children of `expr`.
+pub fn walk_expression_mut<V: VisitorMut + ?Sized>(v: &mut V, expr: &mut Expression) {
+    match *expr {
+        Expression::Ellipsis
+        | Expression::None
+        | Expression::True
+        | Expression::False
+        | Expression::Name(_)
+        | Expression::Int(_)
+        | Expression::ImaginaryInt(_)
+        | Expression::Float(_)
+        | Expression::ImaginaryFloat(_)
+        | Expression::String(_)
+        | Expression::Bytes(_) => (),
+        Expression::DictLiteral(ref mut items) => {
+            for item in items {
+                walk_dictitem_mut(v, item);
+            }
+        }
+        Expression::SetLiteral(ref mut items)
+        | Expression::ListLiteral(ref mut items)
+        | Expression::TupleLiteral(ref mut items) => {
+            for item in items {
+                walk_setitem_mut(v, item);
+            }
+        }
+        Expression::DictComp(ref mut item, ref mut comp) => {
+            walk_dictitem_mut(v, item);
+            walk_comp_mut(v, comp);
+        }
+        Expression::SetComp(ref mut item, ref mut comp)
+        | Expression::ListComp(ref mut item, ref mut comp)
+        | Expression::Generator(ref mut item, ref mut comp) => {
+            walk_setitem_mut(v, item);
+            walk_comp_mut(v, comp);
+        }
+        Expression::Await(ref mut e)
+        | Expression::Attribute(ref mut e, _)
+        | Expression::Uop(_, ref mut e)
+        | Expression::YieldFrom(ref mut e)
+        | Expression::Star(ref mut e) => v.visit_expression(e),
+        Expression::Call(ref mut func, ref mut args) => {
+            v.visit_expression(func);
+            walk_args_mut(v, args);
+        }
+        Expression::Subscript(ref mut e, ref mut subs) => {
+            v.visit_expression(e);
+            for sub in subs {
+                walk_subscript_mut(v, sub);
+            }
+        }
+        Expression::Bop(_, ref mut left, ref mut right)
+        | Expression::Named(ref mut left, ref mut right) => {
+            v.visit_expression(left);
+            v.visit_expression(right);
+        }
+        Expression::MultiBop(ref mut first, ref mut rest) => {
+            v.visit_expression(first);
+            for (_, e) in rest {
+                v.visit_expression(e);
+            }
+        }
+        Expression::Ternary(ref mut then, ref mut cond, ref mut else_) => {
+            // `then if cond else else_`
+            v.visit_expression(then);
+            v.visit_expression(cond);
+            v.visit_expression(else_);
+        }
+        Expression::Yield(ref mut exprs) => walk_expressions_mut(v, exprs),
+        Expression::Lambdef(ref mut params, ref mut body) => {
+            walk_untyped_params_mut(v, params);
+            v.visit_expression(body);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parse_module;
+
+    /// Renames `x` to `y`, and removes the `pass` statements.
+    struct Rewriter;
+
+    impl VisitorMut for Rewriter {
+        fn visit_block(&mut self, stmts: &mut Vec<Statement>) {
+            stmts.retain(|stmt| *stmt != Statement::Pass);
+            walk_block_mut(self, stmts)
+        }
+
+        fn visit_expression(&mut self, expr: &mut Expression) {
+            if *expr == Expression::Name("x".to_string()) {
+                *expr = Expression::Name("y".to_string());
+            }
+            walk_expression_mut(self, expr)
+        }
+    }
+
+    #[test]
+    fn test_rewrite() {
+        let code = "@d(x)\ndef f(a=x):\n    pass\n    return [x for z in x if x]\nwhile x:\n    pass\n    x.x = lambda b=x: x\n";
+        let expected = "@d(y)\ndef f(a=y):\n    return [y for z in y if y]\nwhile y:\n    y.x = lambda b=y: y\n";
+        let mut module = parse_module(code).unwrap();
+        Rewriter.visit_block(&mut module.body);
+        assert_eq!(module, parse_module(expected).unwrap());
+    }
+}
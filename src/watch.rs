@@ -0,0 +1,131 @@
+//! Polling of files for changes, for tools parsing the files being
+//! edited again each time they are saved (eg. the `--watch` option of
+//! the command line tool).
+//!
+//! Files are compared by modification time and size, so a change which
+//! keeps both (within the precision of the file system) is missed.
+
+use std::fs;
+use std::path::PathBuf;
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+/// A change found by `FileWatcher::poll`.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum FileChange {
+    /// The file was created or modified.
+    Modified(PathBuf),
+    Removed(PathBuf),
+}
+
+/// What a file is compared by.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+struct Stamp {
+    modified: SystemTime,
+    len: u64,
+}
+
+fn stamp(path: &PathBuf) -> Option<Stamp> {
+    let metadata = fs::metadata(path).ok()?;
+    Some(Stamp {
+        modified: metadata.modified().ok()?,
+        len: metadata.len(),
+    })
+}
+
+/// Files being watched, with what they were when last polled.
+#[derive(Clone, Debug, Default)]
+pub struct FileWatcher {
+    files: Vec<(PathBuf, Option<Stamp>)>,
+}
+
+impl FileWatcher {
+    /// Returns a watcher of `paths`. The first poll returns the ones
+    /// which exist, as modified.
+    pub fn new<I, P>(paths: I) -> FileWatcher
+    where
+        I: IntoIterator<Item = P>,
+        P: Into<PathBuf>,
+    {
+        FileWatcher {
+            files: paths.into_iter().map(|path| (path.into(), None)).collect(),
+        }
+    }
+
+    /// Returns the files which changed since the last poll, in the order
+    /// they were given.
+    ///
+    /// ```
+    /// use python_parser::watch::{FileChange, FileWatcher};
+    ///
+    /// let path = std::env::temp_dir().join("python-parser-watch-doctest.py");
+    /// std::fs::write(&path, "x = 1\n").unwrap();
+    /// let mut watcher = FileWatcher::new(vec![&path]);
+    /// assert_eq!(watcher.poll(), vec![FileChange::Modified(path.clone())]);
+    /// assert_eq!(watcher.poll(), vec![]);
+    /// std::fs::remove_file(&path).unwrap();
+    /// assert_eq!(watcher.poll(), vec![FileChange::Removed(path.clone())]);
+    /// ```
+    pub fn poll(&mut self) -> Vec<FileChange> {
+        let mut changes = Vec::new();
+        for &mut (ref path, ref mut last) in &mut self.files {
+            let current = stamp(path);
+            if current == *last {
+                continue;
+            }
+            changes.push(match current {
+                Some(_) => FileChange::Modified(path.clone()),
+                None => FileChange::Removed(path.clone()),
+            });
+            *last = current;
+        }
+        changes
+    }
+
+    /// Polls the files every `interval` until some of them change, and
+    /// returns the changes.
+    pub fn wait(&mut self, interval: Duration) -> Vec<FileChange> {
+        loop {
+            let changes = self.poll();
+            if !changes.is_empty() {
+                return changes;
+            }
+            thread::sleep(interval);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::process;
+
+    #[test]
+    fn test_poll() {
+        let dir = env::temp_dir().join(format!("python-parser-watch-{}", process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let a = dir.join("a.py");
+        let b = dir.join("b.py");
+        fs::write(&a, "x = 1\n").unwrap();
+        let mut watcher = FileWatcher::new(vec![&a, &b]);
+        assert_eq!(watcher.poll(), vec![FileChange::Modified(a.clone())]);
+        assert_eq!(watcher.poll(), vec![]);
+
+        fs::write(&b, "y = 2\n").unwrap();
+        fs::write(&a, "x = 10\n").unwrap();
+        assert_eq!(
+            watcher.wait(Duration::from_millis(1)),
+            vec![
+                FileChange::Modified(a.clone()),
+                FileChange::Modified(b.clone())
+            ]
+        );
+
+        fs::remove_file(&b).unwrap();
+        assert_eq!(watcher.poll(), vec![FileChange::Removed(b.clone())]);
+        assert_eq!(watcher.poll(), vec![]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
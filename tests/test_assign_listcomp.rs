@@ -10,12 +10,12 @@ fn assign_listcomp() {
     assert_eq!(
         ast,
         vec![Statement::Assignment(
-            vec![Expression::Name("foo".to_string()),],
-            vec![vec![Expression::ListComp(
+            small_list![Expression::Name("foo".to_string()),],
+            vec![small_list![Expression::ListComp(
                 Box::new(SetItem::Unique(Expression::Name("bar".to_string()))),
                 vec![ComprehensionChunk::For {
                     async: false,
-                    item: vec![Expression::Name("baz".to_string())],
+                    item: small_list![Expression::Name("baz".to_string())],
                     iterator: Expression::Name("qux".to_string()),
                 }]
             )]]
@@ -10,8 +10,8 @@ fn test_call_and_attribute() {
     assert_eq!(
         ast,
         vec![Statement::Assignment(
-            vec![Expression::Name("egg".to_string()),],
-            vec![vec![Expression::Call(
+            small_list![Expression::Name("egg".to_string()),],
+            vec![small_list![Expression::Call(
                 Box::new(Expression::Attribute(
                     Box::new(Expression::Call(
                         Box::new(Expression::Attribute(
@@ -35,8 +35,8 @@ fn test_call_and_attribute2() {
     assert_eq!(
         ast,
         vec![Statement::Assignment(
-            vec![Expression::Name("egg".to_string()),],
-            vec![vec![Expression::Call(
+            small_list![Expression::Name("egg".to_string()),],
+            vec![small_list![Expression::Call(
                 Box::new(Expression::Attribute(
                     Box::new(Expression::Name("foo".to_string())),
                     "bar".to_string(),